@@ -1194,7 +1194,7 @@ pub fn expr_to_path(expr: &SpannedExpr<Symbol>, path: &mut String) -> Result<(),
 }
 
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 pub struct ArenaRef<'a, 'ast, Id>(
     &'ast Arena<'ast, Id>,
@@ -1267,6 +1267,25 @@ macro_rules! impl_ast_arena {
             {
                 T::alloc_extend(iter, self)
             }
+
+            /// Returns how many values and bytes have been allocated in this arena
+            pub fn stats(&self) -> ArenaStats {
+                let mut stats = ArenaStats::default();
+                $(
+                    stats.add::<$ty>(self.$field.len());
+                )+
+                stats
+            }
+
+            /// Drops every value allocated in this arena. Each type starts out with room for as
+            /// many values as were allocated before the reset so an arena which is reused for
+            /// expressions of similar size allocates a single chunk per type instead of growing
+            /// one chunk at a time.
+            pub fn reset(&mut self) {
+                $(
+                    self.$field = typed_arena::Arena::with_capacity(self.$field.len());
+                )+
+            }
         }
 
         $(
@@ -1305,6 +1324,117 @@ impl_ast_arena! {
     Metadata => metadata,
 }
 
+/// The amount of memory used by an `Arena`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ArenaStats {
+    /// The number of values allocated
+    pub values: usize,
+    /// The number of bytes used by the allocated values
+    pub bytes: usize,
+}
+
+impl ArenaStats {
+    fn add<T>(&mut self, values: usize) {
+        self.values += values;
+        self.bytes += values * mem::size_of::<T>();
+    }
+
+    /// Returns the stats which used the most bytes
+    pub fn max(self, other: Self) -> Self {
+        if other.bytes > self.bytes {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Keeps the arenas of dropped expressions around so that later expressions can be allocated in
+/// them instead of in a new arena. Useful for embedders which parse many expressions over the
+/// lifetime of a program.
+///
+/// ```
+/// use gluon_base::{ast::{ArenaPool, Expr, RootExpr}, mk_ast_arena, pos};
+///
+/// let pool = ArenaPool::<String>::new();
+/// for _ in 0..3 {
+///     let expr = {
+///         mk_ast_arena!(arena, pool);
+///         let expr = arena.alloc(pos::spanned(Default::default(), Expr::Error(None)));
+///         RootExpr::new(arena.clone(), expr)
+///     };
+///     pool.recycle(expr.try_into_send().unwrap());
+/// }
+/// assert_eq!(pool.len(), 1);
+/// assert_eq!(pool.peak().values, 1);
+/// ```
+pub struct ArenaPool<Id: 'static> {
+    arenas: Mutex<Vec<Arena<'static, Id>>>,
+    peak: Mutex<ArenaStats>,
+}
+
+impl<Id> Default for ArenaPool<Id> {
+    fn default() -> Self {
+        ArenaPool {
+            arenas: Default::default(),
+            peak: Default::default(),
+        }
+    }
+}
+
+impl<Id> ArenaPool<Id> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes an arena out of the pool or creates a new one if the pool is empty.
+    /// Use `mk_ast_arena!(arena, pool)` instead of calling this directly.
+    #[doc(hidden)]
+    pub unsafe fn take<'ast>(&self, tag: &'ast InvariantLifetime<'ast>) -> Arc<Arena<'ast, Id>> {
+        let arena = self.arenas.lock().unwrap().pop();
+        match arena {
+            // SAFETY The arena is empty so nothing depends on its lifetime
+            Some(arena) => {
+                mem::transmute::<Arc<Arena<'static, Id>>, Arc<Arena<'ast, Id>>>(Arc::new(arena))
+            }
+            None => Arc::new(Arena::new(tag)),
+        }
+    }
+
+    /// Drops `expr` and returns its arena to the pool. Returns how much memory the arena used.
+    pub fn recycle(&self, expr: OwnedExpr<Id>) -> ArenaStats {
+        let OwnedExpr { mut arena, expr: _ } = expr;
+        let stats = arena.stats();
+        arena.reset();
+
+        {
+            let mut peak = self.peak.lock().unwrap();
+            *peak = peak.max(stats);
+        }
+        self.arenas.lock().unwrap().push(arena);
+        stats
+    }
+
+    /// Returns the stats of the largest arena that has been returned with `recycle`
+    pub fn peak(&self) -> ArenaStats {
+        *self.peak.lock().unwrap()
+    }
+
+    /// The number of arenas which are waiting to be reused
+    pub fn len(&self) -> usize {
+        self.arenas.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every arena in the pool
+    pub fn clear(&self) {
+        self.arenas.lock().unwrap().clear();
+    }
+}
+
 #[doc(hidden)]
 #[derive(Default)]
 pub struct InvariantLifetime<'a>(std::marker::PhantomData<fn(&'a ()) -> &'a ()>);
@@ -1313,8 +1443,14 @@ pub struct InvariantLifetime<'a>(std::marker::PhantomData<fn(&'a ()) -> &'a ()>)
 #[macro_export]
 macro_rules! mk_ast_arena {
     ($name: ident) => {
-        let tag = $crate::ast::InvariantLifetime::default();
-        let $name = unsafe { std::sync::Arc::new($crate::ast::Arena::new(&tag)) };
+        $crate::mk_ast_arena!(@new $name, tag, std::sync::Arc::new($crate::ast::Arena::new(&tag)));
+    };
+    ($name: ident, $pool: expr) => {
+        $crate::mk_ast_arena!(@new $name, tag, $pool.take(&tag));
+    };
+    (@new $name: ident, $tag: ident, $arena: expr) => {
+        let $tag = $crate::ast::InvariantLifetime::default();
+        let $name = unsafe { $arena };
         let _guard;
         // this doesn't make it to MIR, but ensures that borrowck will not
         // unify the lifetimes of two macro calls by binding the lifetime to
@@ -1324,7 +1460,7 @@ macro_rules! mk_ast_arena {
             impl<'tag> ::core::ops::Drop for Guard<'tag> {
                 fn drop(&mut self) {}
             }
-            _guard = Guard(&tag);
+            _guard = Guard(&$tag);
         }
     };
 }
@@ -1378,6 +1514,11 @@ impl<Id> RootExpr<Id> {
         f(arena, expr)
     }
 
+    /// Returns how much memory the arena that `self` is allocated in uses
+    pub fn arena_stats(&self) -> ArenaStats {
+        self.arena.stats()
+    }
+
     pub fn expr(&self) -> &SpannedExpr<'_, Id> {
         unsafe { mem::transmute::<&SpannedExpr<Id>, &SpannedExpr<Id>>(&*self.expr) }
     }
@@ -1487,6 +1628,11 @@ impl<Id> OwnedExpr<Id> {
         f(arena, expr)
     }
 
+    /// Returns how much memory the arena that `self` is allocated in uses
+    pub fn arena_stats(&self) -> ArenaStats {
+        self.arena.stats()
+    }
+
     pub fn expr(&self) -> &SpannedExpr<'_, Id> {
         unsafe { mem::transmute::<&SpannedExpr<Id>, &SpannedExpr<Id>>(&*self.expr) }
     }
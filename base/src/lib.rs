@@ -93,6 +93,7 @@ pub mod fnv;
 pub mod kind;
 pub mod merge;
 pub mod metadata;
+pub mod origin;
 pub mod pos;
 pub mod resolve;
 pub mod scoped_map;
@@ -0,0 +1,165 @@
+//! Records where the code produced by desugarings (macros, derives, `do` expressions and the
+//! reparsing of infix operators) came from, so that positions in the desugared code can be mapped
+//! back to what the user wrote.
+
+use std::collections::BTreeMap;
+
+use crate::pos::{BytePos, Span};
+
+/// The desugaring which produced some code
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Desugaring {
+    /// The expansion of the macro with the given name (without the `!`)
+    Macro(String),
+    /// The bindings generated by a `#[derive(..)]` attribute, holding its arguments
+    Derive(String),
+    /// The `flat_map` call of a `do` expression
+    Do,
+    /// An infix expression reassociated by the fixities of its operators
+    Infix,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Origin {
+    pub desugaring: Desugaring,
+    /// The code written by the user which the desugaring was applied to
+    pub span: Span<BytePos>,
+}
+
+/// Maps the spans of desugared code to their `Origin`. Code which was written by the user keeps
+/// its span through desugaring and is usually not present in the map.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OriginMap {
+    origins: BTreeMap<Span<BytePos>, Origin>,
+}
+
+impl OriginMap {
+    pub fn new() -> OriginMap {
+        OriginMap::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    /// Records that the code at `generated` was produced by `desugaring` from the code at
+    /// `origin`. If `generated` already has an origin it is only replaced if the earlier origin was
+    /// `generated` itself and the new one is not, which lets nested expansions chain to the
+    /// outermost one.
+    pub fn record(
+        &mut self,
+        generated: Span<BytePos>,
+        desugaring: Desugaring,
+        origin: Span<BytePos>,
+    ) {
+        let origin = Origin {
+            desugaring,
+            span: origin,
+        };
+        let entry = self
+            .origins
+            .entry(generated)
+            .or_insert_with(|| origin.clone());
+        if entry.span == generated && origin.span != generated {
+            *entry = origin;
+        }
+    }
+
+    pub fn extend(&mut self, other: OriginMap) {
+        for (generated, origin) in other.origins {
+            self.record(generated, origin.desugaring, origin.span);
+        }
+    }
+
+    /// Returns the origin of the code at exactly `span`
+    pub fn get(&self, span: Span<BytePos>) -> Option<&Origin> {
+        self.origins.get(&span)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Span<BytePos>, &Origin)> {
+        self.origins.iter().map(|(span, origin)| (*span, origin))
+    }
+
+    /// Returns the span of the code written by the user which the code at `span` came from
+    pub fn user_span(&self, mut span: Span<BytePos>) -> Span<BytePos> {
+        // Bounded so that a (buggy) cycle of origins can't loop forever
+        for _ in 0..self.origins.len() {
+            match self.origins.get(&span) {
+                Some(origin) if origin.span != span => span = origin.span,
+                _ => break,
+            }
+        }
+        span
+    }
+
+    /// Like `user_span` but for a position, which may be the start of any span in the map. Used
+    /// for expressions whose spans are computed from their desugared sub-expressions.
+    pub fn user_position(&self, mut pos: BytePos) -> BytePos {
+        for _ in 0..self.origins.len() {
+            let next = self
+                .origins
+                .range(Span::new(pos, pos)..)
+                .take_while(|(generated, _)| generated.start() == pos)
+                .map(|(_, origin)| origin.span.start())
+                .find(|&start| start != pos);
+            match next {
+                Some(start) => pos = start,
+                None => break,
+            }
+        }
+        pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(pos: u32) -> BytePos {
+        BytePos::from(pos)
+    }
+
+    fn span(start: u32, end: u32) -> Span<BytePos> {
+        Span::new(pos(start), pos(end))
+    }
+
+    #[test]
+    fn nested_expansions_map_to_the_outermost_origin() {
+        let mut origins = OriginMap::new();
+        // An inner macro, expanded first, which is itself produced by an outer macro
+        origins.record(
+            span(100, 110),
+            Desugaring::Macro("inner".into()),
+            span(90, 95),
+        );
+        origins.record(
+            span(90, 95),
+            Desugaring::Macro("inner".into()),
+            span(90, 95),
+        );
+        origins.record(span(90, 95), Desugaring::Macro("outer".into()), span(1, 10));
+
+        assert_eq!(origins.user_span(span(100, 110)), span(1, 10));
+        assert_eq!(origins.user_position(pos(100)), pos(1));
+        assert_eq!(origins.user_span(span(3, 4)), span(3, 4));
+        assert_eq!(
+            origins.get(span(90, 95)).map(|origin| &origin.desugaring),
+            Some(&Desugaring::Macro("outer".into()))
+        );
+    }
+
+    #[test]
+    fn identity_origins_keep_positions() {
+        let mut origins = OriginMap::new();
+        origins.record(span(5, 20), Desugaring::Infix, span(5, 20));
+        origins.record(span(5, 8), Desugaring::Do, span(1, 8));
+
+        assert_eq!(origins.user_span(span(5, 20)), span(5, 20));
+        assert_eq!(origins.user_position(pos(5)), pos(1));
+        assert_eq!(origins.user_position(pos(6)), pos(6));
+    }
+}
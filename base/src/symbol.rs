@@ -592,23 +592,38 @@ impl SymbolInner {
     }
 }
 
+type SymbolIndexes =
+    hashbrown::HashMap<SymbolData<&'static Name>, Symbol, BuildHasherDefault<fnv::FnvHasher>>;
+
+/// Must be a power of two
+const SHARDS: usize = 16;
+
 /// `Symbols` is a bidirectional mapping between `Symbol`s and their name as represented in a
 /// source file.
 /// Used to make identifiers within a single module point to the same symbol
-#[derive(Debug, Default)]
+///
+/// The symbols are split into shards by the hash of their name, each behind its own lock, so
+/// that a `Symbols` can be shared between threads which create symbols in parallel (`&Symbols`
+/// implements `IdentEnv`). Looking up an existing symbol only takes a read lock on its shard.
+#[derive(Debug)]
 pub struct Symbols {
-    indexes:
-        hashbrown::HashMap<SymbolData<&'static Name>, Symbol, BuildHasherDefault<fnv::FnvHasher>>,
+    shards: Box<[parking_lot::RwLock<SymbolIndexes>]>,
 }
 
-impl Symbols {
-    pub fn new() -> Symbols {
+impl Default for Symbols {
+    fn default() -> Self {
         Symbols {
-            indexes: Default::default(),
+            shards: (0..SHARDS).map(|_| Default::default()).collect(),
         }
     }
+}
 
-    pub fn simple_symbol<N>(&mut self, name: N) -> Symbol
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn simple_symbol<N>(&self, name: N) -> Symbol
     where
         N: Into<NameBuf> + AsRef<Name>,
     {
@@ -619,8 +634,20 @@ impl Symbols {
         })
     }
 
+    fn hash(name: &SymbolData<&Name>) -> u64 {
+        let mut hasher = BuildHasherDefault::<fnv::FnvHasher>::default().build_hasher();
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn shard(&self, hash: u64) -> &parking_lot::RwLock<SymbolIndexes> {
+        // The low bits select the bucket and the high bits are stored in the control bytes of the
+        // shard's table so use bits from the middle
+        &self.shards[(hash >> 32) as usize & (SHARDS - 1)]
+    }
+
     /// Looks up the symbol for `name` or creates a new symbol if it does not exist
-    pub fn symbol<N>(&mut self, name: SymbolData<N>) -> Symbol
+    pub fn symbol<N>(&self, name: SymbolData<N>) -> Symbol
     where
         N: Into<NameBuf> + AsRef<Name>,
     {
@@ -630,12 +657,20 @@ impl Symbols {
             name: name.name.as_ref(),
         };
 
-        let mut hasher = self.indexes.hasher().build_hasher();
-        name_ref.hash(&mut hasher);
-        let hash = hasher.finish();
+        let hash = Self::hash(&name_ref);
+        let shard = self.shard(hash);
 
-        match self
-            .indexes
+        if let Some((_, symbol)) = shard
+            .read()
+            .raw_entry()
+            .from_hash(hash, |key| *key == name_ref)
+        {
+            return symbol.clone();
+        }
+
+        let mut indexes = shard.write();
+        // Another thread may have created the symbol since the read lock was released
+        match indexes
             .raw_entry_mut()
             .from_hash(hash, |key| *key == name_ref)
         {
@@ -679,20 +714,25 @@ impl Symbols {
         }
     }
 
-    pub fn contains_name<N>(&mut self, name: N) -> bool
+    pub fn contains_name<N>(&self, name: N) -> bool
     where
         N: AsRef<Name>,
     {
         let s = SymbolData::<&Name>::from(name.as_ref().as_str());
-        self.indexes.contains_key(&s)
+        let hash = Self::hash(&s);
+        self.shard(hash)
+            .read()
+            .raw_entry()
+            .from_hash(hash, |key| *key == s)
+            .is_some()
     }
 
     pub fn len(&self) -> usize {
-        self.indexes.len()
+        self.shards.iter().map(|shard| shard.read().len()).sum()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.indexes.is_empty()
+        self.shards.iter().all(|shard| shard.read().is_empty())
     }
 }
 
@@ -789,6 +829,12 @@ impl IdentEnv for Symbols {
     }
 }
 
+impl IdentEnv for &'_ Symbols {
+    fn from_str(&mut self, s: &str) -> Symbol {
+        self.symbol(SymbolData::<&Name>::from(s))
+    }
+}
+
 impl<'s> DisplayEnv for SymbolModule<'s> {
     type Ident = Symbol;
 
@@ -808,3 +854,40 @@ impl<P> From<crate::pos::Spanned<Symbol, P>> for Symbol {
         s.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_created_in_parallel_are_shared() {
+        let symbols = Arc::new(Symbols::new());
+        let names = (0..100).map(|i| format!("x{}", i)).collect::<Vec<_>>();
+
+        let threads = (0..4)
+            .map(|_| {
+                let symbols = symbols.clone();
+                let names = names.clone();
+                std::thread::spawn(move || {
+                    let mut env = &*symbols;
+                    names
+                        .iter()
+                        .map(|name| env.from_str(name))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let created = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(symbols.len(), names.len());
+        for other in &created[1..] {
+            assert_eq!(&created[0], other);
+        }
+        assert_ne!(created[0][0], created[0][1]);
+        assert!(symbols.contains_name("x42"));
+        assert!(!symbols.contains_name("y"));
+    }
+}
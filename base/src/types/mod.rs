@@ -2649,7 +2649,7 @@ where
                         printer.space_before(typ.span().start()),
                         top(typ).pretty_(printer)
                     ]
-                    .nest(INDENT)
+                    .nest(printer.indent)
                 ];
                 p.enclose(Prec::Function, arena, doc).group()
             }
@@ -2658,9 +2658,9 @@ where
                 chain![arena, skolem.name.as_ref(), "@", skolem.id.to_string()]
             }
             Type::Generic(ref gen) => arena.text(gen.id.as_ref()),
-            Type::Function(..) => self.pretty_function(printer).nest(INDENT),
+            Type::Function(..) => self.pretty_function(printer).nest(printer.indent),
             Type::App(ref t, ref args) => match self.typ.as_function() {
-                Some(_) => self.pretty_function(printer).nest(INDENT),
+                Some(_) => self.pretty_function(printer).nest(printer.indent),
                 None => {
                     let doc = dt(Prec::Top, t).pretty_(printer);
                     let arg_doc = arena.concat(args.iter().map(|arg| {
@@ -2668,7 +2668,7 @@ where
                             .space_before(arg.span().start())
                             .append(dt(Prec::Constructor, arg).pretty_(printer))
                     }));
-                    let doc = doc.append(arg_doc.nest(INDENT));
+                    let doc = doc.append(arg_doc.nest(printer.indent));
                     p.enclose(Prec::Constructor, arena, doc).group()
                 }
             },
@@ -2710,10 +2710,10 @@ where
                                             chain![
                                                 arena,
                                                 " :",
-                                                arena.line(),
-                                                top(&field.typ).pretty(printer),
+                                                printer.space_before(field.typ.span().start()),
+                                                top(&field.typ).pretty_(printer),
                                             ]
-                                            .nest(INDENT)
+                                            .nest(printer.indent)
                                         }
                                     ]
                                     .group()
@@ -2821,12 +2821,12 @@ where
             Type::EmptyRow => doc,
             Type::ExtendRow { .. } | Type::ExtendTypeRow { .. } => doc
                 .append(top(row).pretty_row(open, printer, pretty_field))
-                .nest(INDENT),
+                .nest(printer.indent),
             _ => doc
                 .append(arena.line())
                 .append("| ")
                 .append(top(row).pretty(printer))
-                .nest(INDENT),
+                .nest(printer.indent),
         };
         if open != "(" {
             doc = doc.append(hardline);
@@ -2927,7 +2927,7 @@ where
             match *field.typ {
                 // Records handle nesting on their own
                 Type::Record(_) => (),
-                _ => rhs = rhs.nest(INDENT),
+                _ => rhs = rhs.nest(printer.indent),
             }
             let f = chain![
                 arena,
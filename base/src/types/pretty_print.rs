@@ -135,6 +135,7 @@ impl<'a, I, T, A> TypeFormatter<'a, I, T, A> {
         top(self.typ).pretty(&Printer {
             arena,
             source: &(),
+            indent: super::INDENT,
             filter: self.filter,
             symbol_text: self.symbol_text,
             annotate_symbol: self.annotate_symbol,
@@ -145,6 +146,7 @@ impl<'a, I, T, A> TypeFormatter<'a, I, T, A> {
         Printer {
             arena,
             source,
+            indent: super::INDENT,
             filter: self.filter,
             symbol_text: self.symbol_text,
             annotate_symbol: self.annotate_symbol,
@@ -176,6 +178,8 @@ where
 pub struct Printer<'a, I: 'a, A: 'a> {
     pub arena: &'a Arena<'a, A>,
     pub source: &'a dyn Source,
+    /// The number of spaces that nested lines are indented with
+    pub indent: isize,
     filter: &'a dyn Fn(&I) -> Filter,
     symbol_text: &'a dyn Fn(&I) -> &str,
     annotate_symbol: &'a dyn Fn(&I) -> Option<A>,
@@ -189,6 +193,7 @@ impl<'a, I, A> Printer<'a, I, A> {
         Printer {
             arena,
             source,
+            indent: super::INDENT,
             filter: &|_| Filter::Retain,
             symbol_text: &|s: &I| s.as_ref(),
             annotate_symbol: &|_| None,
@@ -445,6 +445,31 @@ fn take_implicits_into_account_on_infix_type() {
     assert_eq!(expr.env_type_of(&MockEnv), Type::int());
 }
 
+#[test]
+fn arena_pool_reuses_arenas() {
+    let pool = ast::ArenaPool::new();
+
+    let mut peaks = Vec::new();
+    for i in 0..3 {
+        let expr = {
+            base::mk_ast_arena!(arena, pool);
+            let mut expr = int(0);
+            for _ in 0..i {
+                expr = binop(arena.borrow(), expr, "+", int(1));
+            }
+            ast::RootExpr::new(arena.clone(), arena.alloc(expr))
+        };
+        let stats = expr.arena_stats();
+        assert_eq!(pool.recycle(expr.try_into_send().unwrap()), stats);
+        peaks.push(pool.peak());
+    }
+
+    assert_eq!(pool.len(), 1);
+    assert!(peaks[0].bytes < peaks[1].bytes);
+    assert!(peaks[1].bytes < peaks[2].bytes);
+    assert_eq!(peaks[2].values, 5);
+}
+
 #[test]
 fn resolve_partially_applied_alias() {
     let gen = |x: &str| Type::<_, ArcType>::generic(Generic::new(intern(x), Kind::typ()));
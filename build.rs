@@ -3,6 +3,7 @@ use std::{
     fs::File,
     io::{Read, Write},
     path::Path,
+    process::Command,
 };
 
 use {itertools::Itertools, walkdir::WalkDir};
@@ -170,9 +171,36 @@ static STD_LIBS: &[(&str, &str)] = "#
     writeln!(file, "&[{}];", tuples).unwrap();
 }
 
+/// Records the version of `rustc` and the features which gluon is compiled with. A native module
+/// must be compiled the same way as the application which loads it, see `native.rs`
+fn native_module_abi() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!(
+        "cargo:rustc-env=GLUON_RUSTC_VERSION={}",
+        rustc_version.trim()
+    );
+
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase())
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=GLUON_FEATURES={}", features.join(","));
+}
+
 fn main() {
     gen_skeptic::generate();
 
+    native_module_abi();
+
     example_24_up_to_date();
     println!("cargo:rerun-if-changed=examples/24.glu");
 
@@ -0,0 +1,353 @@
+#ifndef GLUON_H
+#define GLUON_H
+
+/* Generated by scripts/generate_c_header.sh, do not edit */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* Types defined by gluon_vm */
+typedef struct GluVm GluVm;
+typedef uint32_t GluIndex;
+typedef uint32_t GluTag;
+typedef int64_t GluInt;
+
+typedef enum GluStatus {
+  GLU_STATUS_OK,
+  GLU_STATUS_YIELD,
+  GLU_STATUS_ERROR,
+} GluStatus;
+
+typedef enum GluError {
+  GLU_ERROR_OK,
+  GLU_ERROR_UNKNOWN,
+  /**
+   * A string was not valid UTF-8
+   */
+  GLU_ERROR_UTF8,
+  /**
+   * Gluon code failed to parse, typecheck or compile
+   */
+  GLU_ERROR_COMPILE,
+  /**
+   * Gluon code failed while running, for instance by panicking or by exceeding a limit
+   */
+  GLU_ERROR_RUNTIME,
+  /**
+   * A stack slot did not exist or did not contain a value of the expected kind
+   */
+  GLU_ERROR_VALUE,
+  /**
+   * A global, field or element did not exist
+   */
+  GLU_ERROR_NOT_FOUND,
+} GluError;
+
+/**
+ * The effects which a vm may be allowed to perform, see `gluon::vm::vm::Capabilities`
+ */
+typedef enum GluCapability {
+  GLU_CAPABILITY_IO,
+  GLU_CAPABILITY_FS,
+  GLU_CAPABILITY_NET,
+  GLU_CAPABILITY_PROCESS,
+  GLU_CAPABILITY_FFI,
+  GLU_CAPABILITY_CLOCK,
+  GLU_CAPABILITY_ENV,
+} GluCapability;
+
+/**
+ * The settings of a vm which has not been built yet
+ */
+typedef struct GluBuilder GluBuilder;
+
+/**
+ * A value which is kept alive until it is freed with `glu_free_value`, regardless of whether
+ * it is on the stack
+ */
+typedef struct GluValue GluValue;
+
+/**
+ * A function implemented in C. The arguments are at the start of the stack and the function
+ * returns by pushing its result. If it returns `Status::Error` the error message is expected to
+ * be on the top of the stack.
+ */
+typedef GluStatus (*GluFunction)(const GluVm*);
+
+/**
+ * A function in a module added with `glu_add_module`
+ */
+typedef struct GluFunctionDef {
+  /**
+   * The name of the function in the module
+   */
+  const uint8_t *name;
+  uintptr_t name_len;
+  /**
+   * The gluon type of the function, such as `Float -> Float -> Float`
+   */
+  const uint8_t *signature;
+  uintptr_t signature_len;
+  GluFunction function;
+  /**
+   * The number of arguments which `function` takes
+   */
+  GluIndex args;
+} GluFunctionDef;
+
+/**
+ * A string passed to the API, which must be valid UTF-8
+ */
+typedef struct GluStr {
+  const uint8_t *data;
+  uintptr_t len;
+} GluStr;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error returned to the calling thread. The string is valid
+ * until the next call which fails on the same thread.
+ */
+void glu_last_error(const uint8_t **out, uintptr_t *out_len);
+
+/**
+ * Creates a vm with the standard library and the default settings, see `glu_builder_new` to
+ * configure the vm
+ */
+const GluVm *glu_new_vm(void);
+
+void glu_free_vm(const GluVm *vm);
+
+/**
+ * Compiles and runs `expr` as the module `module`, pushing the resulting value to the stack
+ */
+enum GluError glu_run_expr(const GluVm *vm,
+                           const uint8_t *module,
+                           uintptr_t module_len,
+                           const uint8_t *expr,
+                           uintptr_t expr_len);
+
+/**
+ * Compiles and runs `expr` as the module `module` which can then be imported with `import!`
+ */
+enum GluError glu_load_script(const GluVm *vm,
+                              const uint8_t *module,
+                              uintptr_t module_len,
+                              const uint8_t *expr,
+                              uintptr_t expr_len);
+
+/**
+ * Calls the function below the `args` topmost values of the stack with those values, replacing
+ * them all with the result
+ */
+enum GluError glu_call_function(const GluVm *thread, GluIndex args);
+
+uintptr_t glu_len(const GluVm *vm);
+
+void glu_pop(const GluVm *vm, uintptr_t n);
+
+void glu_push_int(const GluVm *vm, GluInt int_);
+
+void glu_push_byte(const GluVm *vm, uint8_t b);
+
+void glu_push_float(const GluVm *vm, double float_);
+
+void glu_push_bool(const GluVm *vm, int8_t b);
+
+enum GluError glu_push_function(const GluVm *vm,
+                                const uint8_t *name,
+                                uintptr_t len,
+                                GluFunction function,
+                                GluIndex args);
+
+/**
+ * Push a string to the stack. The string must be valid utf-8 or an error will be returned
+ */
+enum GluError glu_push_string(const GluVm *vm, const uint8_t *s, uintptr_t len);
+
+/**
+ * Push a string to the stack. If the string is not utf-8 this function will trigger undefined
+ * behaviour.
+ */
+enum GluError glu_push_string_unchecked(const GluVm *vm, const uint8_t *s, uintptr_t len);
+
+#if !defined(GLU_WASM32)
+void glu_push_light_userdata(const GluVm *vm, void *data);
+#endif
+
+enum GluError glu_get_byte(const GluVm *vm, GluIndex index, uint8_t *out);
+
+enum GluError glu_get_int(const GluVm *vm, GluIndex index, GluInt *out);
+
+enum GluError glu_get_float(const GluVm *vm, GluIndex index, double *out);
+
+enum GluError glu_get_bool(const GluVm *vm, GluIndex index, int8_t *out);
+
+/**
+ * The returned string is garbage collected and may not be valid after the string is removed from
+ * its slot in the stack
+ */
+enum GluError glu_get_string(const GluVm *vm,
+                             GluIndex index,
+                             const uint8_t **out,
+                             uintptr_t *out_len);
+
+#if !defined(GLU_WASM32)
+enum GluError glu_get_light_userdata(const GluVm *vm, GluIndex index, void **out);
+#endif
+
+/**
+ * Creates a builder with the same defaults as `glu_new_vm`. The builder is freed by
+ * `glu_builder_build` or `glu_builder_free`.
+ */
+struct GluBuilder *glu_builder_new(void);
+
+void glu_builder_free(struct GluBuilder *builder);
+
+/**
+ * Adds a directory which `import!` looks for modules in. Adding a path replaces the default
+ * import path, the working directory.
+ */
+enum GluError glu_builder_add_import_path(struct GluBuilder *builder,
+                                          const uint8_t *path,
+                                          uintptr_t len);
+
+/**
+ * Allows or denies the vm `capability` (default: all capabilities are allowed)
+ */
+void glu_builder_set_capability(struct GluBuilder *builder,
+                                enum GluCapability capability,
+                                int8_t allow);
+
+/**
+ * See `VmBuilder::deterministic` (default: false)
+ */
+void glu_builder_set_deterministic(struct GluBuilder *builder, int8_t deterministic);
+
+/**
+ * Limits the number of bytes the main thread may allocate, 0 means unlimited (default: 0)
+ */
+void glu_builder_set_memory_limit(struct GluBuilder *builder, uintptr_t limit);
+
+/**
+ * Makes the standard library available to `import!` (default: true)
+ */
+void glu_builder_set_std_lib(struct GluBuilder *builder, int8_t use_std_lib);
+
+/**
+ * Runs the `IO` actions which `glu_run_expr` and `glu_load_script` evaluate to (default: false)
+ */
+void glu_builder_set_run_io(struct GluBuilder *builder, int8_t run_io);
+
+/**
+ * Builds the vm and frees `builder`. The vm is freed with `glu_free_vm`.
+ */
+const GluVm *glu_builder_build(struct GluBuilder *builder);
+
+#if defined(GLU_SERIALIZATION)
+/**
+ * Compiles and runs `expr` as the module `module` and returns its value as JSON. The string is
+ * valid until the next call which returns JSON on the same thread.
+ */
+enum GluError glu_run_expr_json(const GluVm *vm,
+                                const uint8_t *module,
+                                uintptr_t module_len,
+                                const uint8_t *expr,
+                                uintptr_t expr_len,
+                                const uint8_t **out,
+                                uintptr_t *out_len);
+#endif
+
+#if defined(GLU_SERIALIZATION)
+/**
+ * Calls the global function `name` with the elements of the JSON array `args`, each converted to
+ * the type of the argument in the function's signature. The result is returned as JSON, like
+ * `glu_run_expr_json`.
+ */
+enum GluError glu_call_json(const GluVm *vm,
+                            const uint8_t *name,
+                            uintptr_t name_len,
+                            const uint8_t *args,
+                            uintptr_t args_len,
+                            const uint8_t **out,
+                            uintptr_t *out_len);
+#endif
+
+/**
+ * Adds the module `module` containing `functions`, which gluon code can then import with
+ * `import! module`. The signatures are typechecked when the module is added and the function is
+ * trusted to follow its signature.
+ */
+enum GluError glu_add_module(const GluVm *vm,
+                             const uint8_t *module,
+                             uintptr_t module_len,
+                             const struct GluFunctionDef *functions,
+                             uintptr_t count);
+
+/**
+ * Replaces the `count` topmost values of the stack with a record containing them, named by
+ * `fields`. The fields must be pushed in the order they appear in the record's type.
+ */
+enum GluError glu_push_record(const GluVm *vm, const struct GluStr *fields, uintptr_t count);
+
+/**
+ * Replaces the `count` topmost values of the stack with the variant `tag` (the index of the
+ * constructor in its type) which contains them as its arguments
+ */
+enum GluError glu_push_variant(const GluVm *vm, GluTag tag, uintptr_t count);
+
+/**
+ * Replaces the `count` topmost values of the stack with an array containing them. The values
+ * must all have the same type.
+ */
+enum GluError glu_push_array(const GluVm *vm, uintptr_t count);
+
+/**
+ * Returns the tag of the variant at `index`, the index of its constructor in its type
+ */
+enum GluError glu_get_tag(const GluVm *vm, GluIndex index, GluTag *out);
+
+/**
+ * Returns the number of elements of the array, or the number of fields of the record or variant,
+ * at `index`
+ */
+enum GluError glu_get_len(const GluVm *vm, GluIndex index, uintptr_t *out);
+
+/**
+ * Pushes the element `element` of the array, or the field at position `element` of the record
+ * or variant, at `index`
+ */
+enum GluError glu_push_element(const GluVm *vm, GluIndex index, uintptr_t element);
+
+/**
+ * Pushes the field `name` of the record at `index`
+ */
+enum GluError glu_push_field(const GluVm *vm, GluIndex index, const uint8_t *name, uintptr_t len);
+
+/**
+ * Pushes the global `name`, such as `std.int.num.(+)` or a module loaded with
+ * `glu_load_script`
+ */
+enum GluError glu_push_global(const GluVm *vm, const uint8_t *name, uintptr_t len);
+
+/**
+ * Roots the value at `index` so that it stays alive after it is removed from the stack. Returns
+ * null if there is no value at `index`.
+ */
+struct GluValue *glu_root_value(const GluVm *vm, GluIndex index);
+
+/**
+ * Pushes a value rooted by `glu_root_value`
+ */
+enum GluError glu_push_value(const GluVm *vm, const struct GluValue *value);
+
+void glu_free_value(struct GluValue *value);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GLUON_H */
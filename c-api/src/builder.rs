@@ -0,0 +1,128 @@
+//! Configuring and building a vm, the equivalent of `gluon::VmBuilder`
+
+use gluon::{vm::thread::Thread, ThreadExt, VmBuilder};
+
+use crate::{str_arg, Error};
+
+/// The effects which a vm may be allowed to perform, see `gluon::vm::vm::Capabilities`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    Io,
+    Fs,
+    Net,
+    Process,
+    Ffi,
+    Clock,
+    Env,
+}
+
+/// The settings of a vm which has not been built yet
+pub struct Builder {
+    builder: VmBuilder,
+    import_paths: Option<Vec<std::path::PathBuf>>,
+    use_std_lib: bool,
+    run_io: bool,
+}
+
+/// Creates a builder with the same defaults as `glu_new_vm`. The builder is freed by
+/// `glu_builder_build` or `glu_builder_free`.
+#[no_mangle]
+pub extern "C" fn glu_builder_new() -> *mut Builder {
+    Box::into_raw(Box::new(Builder {
+        builder: VmBuilder::new(),
+        import_paths: None,
+        use_std_lib: true,
+        run_io: false,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glu_builder_free(builder: *mut Builder) {
+    drop(Box::from_raw(builder));
+}
+
+/// Adds a directory which `import!` looks for modules in. Adding a path replaces the default
+/// import path, the working directory.
+#[no_mangle]
+pub unsafe extern "C" fn glu_builder_add_import_path(
+    builder: &mut Builder,
+    path: &u8,
+    len: usize,
+) -> Error {
+    match str_arg(path, len) {
+        Ok(path) => {
+            builder
+                .import_paths
+                .get_or_insert_with(Vec::new)
+                .push(path.into());
+            Error::Ok
+        }
+        Err(err) => err,
+    }
+}
+
+/// Allows or denies the vm `capability` (default: all capabilities are allowed)
+#[no_mangle]
+pub extern "C" fn glu_builder_set_capability(
+    builder: &mut Builder,
+    capability: Capability,
+    allow: i8,
+) {
+    let allow = allow != 0;
+    let builder = &mut builder.builder;
+    match capability {
+        Capability::Io => builder.set_allow_io(allow),
+        Capability::Fs => builder.set_allow_fs(allow),
+        Capability::Net => builder.set_allow_net(allow),
+        Capability::Process => builder.set_allow_process(allow),
+        Capability::Ffi => builder.set_allow_ffi(allow),
+        Capability::Clock => builder.set_allow_clock(allow),
+        Capability::Env => builder.set_allow_env(allow),
+    }
+}
+
+/// See `VmBuilder::deterministic` (default: false)
+#[no_mangle]
+pub extern "C" fn glu_builder_set_deterministic(builder: &mut Builder, deterministic: i8) {
+    builder.builder.set_deterministic(deterministic != 0);
+}
+
+/// Limits the number of bytes the main thread may allocate, 0 means unlimited (default: 0)
+#[no_mangle]
+pub extern "C" fn glu_builder_set_memory_limit(builder: &mut Builder, limit: usize) {
+    builder
+        .builder
+        .set_memory_limit(if limit == 0 { None } else { Some(limit) });
+}
+
+/// Makes the standard library available to `import!` (default: true)
+#[no_mangle]
+pub extern "C" fn glu_builder_set_std_lib(builder: &mut Builder, use_std_lib: i8) {
+    builder.use_std_lib = use_std_lib != 0;
+}
+
+/// Runs the `IO` actions which `glu_run_expr` and `glu_load_script` evaluate to (default: false)
+#[no_mangle]
+pub extern "C" fn glu_builder_set_run_io(builder: &mut Builder, run_io: i8) {
+    builder.run_io = run_io != 0;
+}
+
+/// Builds the vm and frees `builder`. The vm is freed with `glu_free_vm`.
+#[no_mangle]
+pub unsafe extern "C" fn glu_builder_build(builder: *mut Builder) -> *const Thread {
+    let Builder {
+        mut builder,
+        import_paths,
+        use_std_lib,
+        run_io,
+    } = *Box::from_raw(builder);
+    if import_paths.is_some() {
+        builder.set_import_paths(import_paths);
+    }
+    let vm = builder.build();
+    vm.get_database_mut()
+        .use_standard_lib(use_std_lib)
+        .run_io(run_io);
+    vm.into_raw()
+}
@@ -0,0 +1,90 @@
+//! Running expressions and calling functions with values encoded as JSON, which are converted
+//! according to their gluon types as described by `gluon::vm::api::json`. Only available with the
+//! `serialization` feature.
+
+use std::cell::RefCell;
+
+use gluon::{
+    vm::{
+        api::{
+            json::{call_json, to_json},
+            Hole, OpaqueValue,
+        },
+        thread::{RootedThread, Thread},
+    },
+    ThreadExt,
+};
+
+use crate::{fail, gluon_error, str_arg, Error};
+
+thread_local! {
+    static LAST_JSON: RefCell<String> = RefCell::new(String::new());
+}
+
+/// Stores `value` as the last JSON returned to the calling thread and points `out` to it
+fn return_json(value: &serde_json::Value, out: &mut *const u8, out_len: &mut usize) -> Error {
+    LAST_JSON.with(|last| {
+        let mut last = last.borrow_mut();
+        *last = value.to_string();
+        *out = last.as_ptr();
+        *out_len = last.len();
+    });
+    Error::Ok
+}
+
+/// Compiles and runs `expr` as the module `module` and returns its value as JSON. The string is
+/// valid until the next call which returns JSON on the same thread.
+#[no_mangle]
+pub unsafe extern "C" fn glu_run_expr_json(
+    vm: &Thread,
+    module: &u8,
+    module_len: usize,
+    expr: &u8,
+    expr_len: usize,
+    out: &mut *const u8,
+    out_len: &mut usize,
+) -> Error {
+    let (module, expr) = match (str_arg(module, module_len), str_arg(expr, expr_len)) {
+        (Ok(module), Ok(expr)) => (module, expr),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let (value, typ) = match vm.run_expr::<OpaqueValue<RootedThread, Hole>>(module, expr) {
+        Ok(value) => value,
+        Err(err) => return gluon_error(err),
+    };
+    match to_json(vm, value.get_variant(), &typ) {
+        Ok(value) => return_json(&value, out, out_len),
+        Err(err) => fail(Error::Value, err),
+    }
+}
+
+/// Calls the global function `name` with the elements of the JSON array `args`, each converted to
+/// the type of the argument in the function's signature. The result is returned as JSON, like
+/// `glu_run_expr_json`.
+#[no_mangle]
+pub unsafe extern "C" fn glu_call_json(
+    vm: &Thread,
+    name: &u8,
+    name_len: usize,
+    args: &u8,
+    args_len: usize,
+    out: &mut *const u8,
+    out_len: &mut usize,
+) -> Error {
+    let (name, args) = match (str_arg(name, name_len), str_arg(args, args_len)) {
+        (Ok(name), Ok(args)) => (name, args),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let args: Vec<serde_json::Value> = match serde_json::from_str(args) {
+        Ok(args) => args,
+        Err(err) => return fail(Error::Value, err),
+    };
+    let (function, typ) = match vm.get_env().get_binding(name) {
+        Ok(binding) => binding,
+        Err(err) => return fail(Error::NotFound, err),
+    };
+    match call_json(&function, &typ, &args) {
+        Ok(value) => return_json(&value, out, out_len),
+        Err(err) => fail(Error::Runtime, err),
+    }
+}
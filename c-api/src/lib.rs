@@ -1,34 +1,108 @@
-//! A (WIP) C API allowing use of gluon in other langauges than Rust.
+//! A C API allowing use of gluon in other langauges than Rust.
+//!
+//! Functions which can fail return an `Error`, the message of the last error on the calling
+//! thread is returned by `glu_last_error`. Strings are passed as a pointer and a length and must
+//! be valid UTF-8. Values are passed between the host and gluon through the stack of a thread,
+//! values which need to outlive their slot in the stack are rooted with `glu_root_value`. With the
+//! `serialization` feature values can also be passed as JSON, see `glu_run_expr_json`.
+//!
+//! `include/gluon.h` is generated from this crate with `scripts/generate_c_header.sh`.
 #![doc(html_root_url = "https://docs.rs/gluon_c-api/0.17.1")] // # GLUON
 
-use std::{slice, str};
+mod builder;
+#[cfg(feature = "serialization")]
+mod json;
+mod module;
+mod value;
+
+#[cfg(feature = "serialization")]
+pub use crate::json::*;
+pub use crate::{builder::*, module::*, value::*};
+
+/// The vm which the functions operate on, which is opaque to C
+pub use gluon::vm::thread::Thread;
+
+use std::{cell::RefCell, fmt, slice, str};
 
 use futures::{executor::block_on, future};
 
 use gluon::{
+    new_vm,
     vm::{
-        api::{CPrimitive, Getable, Hole, OpaqueValue, Pushable},
+        api::{CPrimitive, Hole, OpaqueValue, Pushable, ValueRef},
         stack,
-        thread::{RootedThread, Status, Thread, ThreadInternal},
+        thread::{RootedThread, Status, ThreadInternal},
         types::{VmIndex, VmInt},
+        Variants,
     },
     ThreadExt,
 };
 
+/// A function implemented in C. The arguments are at the start of the stack and the function
+/// returns by pushing its result. If it returns `Status::Error` the error message is expected to
+/// be on the top of the stack.
 pub type Function = extern "C" fn(&Thread) -> Status;
 
-// TODO What should the c api return as errors
-// TODO How should error messages be returned
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum Error {
     Ok,
     Unknown,
+    /// A string was not valid UTF-8
+    Utf8,
+    /// Gluon code failed to parse, typecheck or compile
+    Compile,
+    /// Gluon code failed while running, for instance by panicking or by exceeding a limit
+    Runtime,
+    /// A stack slot did not exist or did not contain a value of the expected kind
+    Value,
+    /// A global, field or element did not exist
+    NotFound,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = RefCell::new(String::new());
+}
+
+/// Records `message` as the last error and returns `kind`
+fn fail(kind: Error, message: impl fmt::Display) -> Error {
+    LAST_ERROR.with(|last| *last.borrow_mut() = message.to_string());
+    kind
+}
+
+fn gluon_error(err: gluon::Error) -> Error {
+    let kind = match err {
+        gluon::Error::VM(_) => Error::Runtime,
+        gluon::Error::IO(_) => Error::Unknown,
+        _ => Error::Compile,
+    };
+    fail(kind, err)
+}
+
+fn vm_error(err: gluon::vm::Error) -> Error {
+    fail(Error::Runtime, err)
+}
+
+unsafe fn str_arg<'a>(s: &'a u8, len: usize) -> Result<&'a str, Error> {
+    str::from_utf8(slice::from_raw_parts(s, len)).map_err(|err| fail(Error::Utf8, err))
+}
+
+/// Returns the message of the last error returned to the calling thread. The string is valid
+/// until the next call which fails on the same thread.
+#[no_mangle]
+pub unsafe extern "C" fn glu_last_error(out: &mut *const u8, out_len: &mut usize) {
+    LAST_ERROR.with(|last| {
+        let last = last.borrow();
+        *out = last.as_ptr();
+        *out_len = last.len();
+    })
 }
 
+/// Creates a vm with the standard library and the default settings, see `glu_builder_new` to
+/// configure the vm
 #[no_mangle]
 pub extern "C" fn glu_new_vm() -> *const Thread {
-    let vm = RootedThread::new();
+    let vm = new_vm();
     vm.into_raw()
 }
 
@@ -37,6 +111,7 @@ pub unsafe extern "C" fn glu_free_vm(vm: &Thread) {
     RootedThread::from_raw(vm);
 }
 
+/// Compiles and runs `expr` as the module `module`, pushing the resulting value to the stack
 #[no_mangle]
 pub unsafe extern "C" fn glu_run_expr(
     vm: &Thread,
@@ -45,21 +120,20 @@ pub unsafe extern "C" fn glu_run_expr(
     expr: &u8,
     expr_len: usize,
 ) -> Error {
-    let module = match str::from_utf8(slice::from_raw_parts(module, module_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let expr = match str::from_utf8(slice::from_raw_parts(expr, expr_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
+    let (module, expr) = match (str_arg(module, module_len), str_arg(expr, expr_len)) {
+        (Ok(module), Ok(expr)) => (module, expr),
+        (Err(err), _) | (_, Err(err)) => return err,
     };
-    let result = vm.run_expr::<OpaqueValue<&Thread, Hole>>(module, expr);
-    match result {
-        Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
+    match vm.run_expr::<OpaqueValue<RootedThread, Hole>>(module, expr) {
+        Ok((value, _)) => match Thread::push(vm, value) {
+            Ok(()) => Error::Ok,
+            Err(err) => vm_error(err),
+        },
+        Err(err) => gluon_error(err),
     }
 }
 
+/// Compiles and runs `expr` as the module `module` which can then be imported with `import!`
 #[no_mangle]
 pub unsafe extern "C" fn glu_load_script(
     vm: &Thread,
@@ -68,21 +142,18 @@ pub unsafe extern "C" fn glu_load_script(
     expr: &u8,
     expr_len: usize,
 ) -> Error {
-    let module = match str::from_utf8(slice::from_raw_parts(module, module_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
+    let (module, expr) = match (str_arg(module, module_len), str_arg(expr, expr_len)) {
+        (Ok(module), Ok(expr)) => (module, expr),
+        (Err(err), _) | (_, Err(err)) => return err,
     };
-    let expr = match str::from_utf8(slice::from_raw_parts(expr, expr_len)) {
-        Ok(s) => s,
-        Err(_) => return Error::Unknown,
-    };
-    let result = vm.load_script(module, expr);
-    match result {
-        Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
+    match vm.load_script(module, expr) {
+        Ok(()) => Error::Ok,
+        Err(err) => gluon_error(err),
     }
 }
 
+/// Calls the function below the `args` topmost values of the stack with those values, replacing
+/// them all with the result
 #[no_mangle]
 pub extern "C" fn glu_call_function(thread: &Thread, args: VmIndex) -> Error {
     match block_on(future::poll_fn(|cx| {
@@ -90,7 +161,7 @@ pub extern "C" fn glu_call_function(thread: &Thread, args: VmIndex) -> Error {
         thread.call_function(cx, context, args)
     })) {
         Ok(_) => Error::Ok,
-        Err(_) => Error::Unknown,
+        Err(err) => vm_error(err),
     }
 }
 
@@ -138,26 +209,26 @@ pub unsafe extern "C" fn glu_push_function(
     function: Function,
     args: VmIndex,
 ) -> Error {
-    let s = match str::from_utf8(slice::from_raw_parts(name, len)) {
+    let s = match str_arg(name, len) {
         Ok(s) => s,
-        Err(_) => return Error::Unknown,
+        Err(err) => return err,
     };
     match Thread::push(vm, CPrimitive::new(function, args, s)) {
         Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
+        Err(err) => vm_error(err),
     }
 }
 
 /// Push a string to the stack. The string must be valid utf-8 or an error will be returned
 #[no_mangle]
 pub unsafe extern "C" fn glu_push_string(vm: &Thread, s: &u8, len: usize) -> Error {
-    let s = match str::from_utf8(slice::from_raw_parts(s, len)) {
+    let s = match str_arg(s, len) {
         Ok(s) => s,
-        Err(_) => return Error::Unknown,
+        Err(err) => return err,
     };
     match s.vm_push(&mut vm.current_context()) {
         Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
+        Err(err) => vm_error(err),
     }
 }
 
@@ -168,7 +239,7 @@ pub unsafe extern "C" fn glu_push_string_unchecked(vm: &Thread, s: &u8, len: usi
     let s = str::from_utf8_unchecked(slice::from_raw_parts(s, len));
     match s.vm_push(&mut vm.current_context()) {
         Ok(()) => Error::Ok,
-        Err(_) => Error::Unknown,
+        Err(err) => vm_error(err),
     }
 }
 
@@ -178,29 +249,77 @@ pub extern "C" fn glu_push_light_userdata(vm: &Thread, data: *mut libc::c_void)
     Thread::push(vm, data as usize).unwrap()
 }
 
+/// Calls `f` with the value at `index` in the current stack frame
+pub(crate) fn with_value<R>(
+    vm: &Thread,
+    index: VmIndex,
+    f: impl FnOnce(Variants) -> Result<R, Error>,
+) -> Result<R, Error> {
+    let mut context = vm.context();
+    let stack = context.stack_frame::<stack::State>();
+    match stack.get_variant(index) {
+        Some(value) => f(value),
+        None => Err(fail(
+            Error::Value,
+            format!("There is no value at index {} of the stack", index),
+        )),
+    }
+}
+
+fn get_value<T>(
+    vm: &Thread,
+    index: VmIndex,
+    out: &mut T,
+    expected: &str,
+    get: impl FnOnce(ValueRef) -> Option<T>,
+) -> Error {
+    let result = with_value(vm, index, |value| {
+        get(value.as_ref()).ok_or_else(|| {
+            fail(
+                Error::Value,
+                format!("The value at index {} is not {}", index, expected),
+            )
+        })
+    });
+    match result {
+        Ok(value) => {
+            *out = value;
+            Error::Ok
+        }
+        Err(err) => err,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn glu_get_byte(vm: &Thread, index: VmIndex, out: &mut u8) -> Error {
-    get_value(vm, index, out)
+    get_value(vm, index, out, "a byte", |value| match value {
+        ValueRef::Byte(b) => Some(b),
+        _ => None,
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn glu_get_int(vm: &Thread, index: VmIndex, out: &mut VmInt) -> Error {
-    get_value(vm, index, out)
+    get_value(vm, index, out, "an integer", |value| match value {
+        ValueRef::Int(i) => Some(i),
+        _ => None,
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn glu_get_float(vm: &Thread, index: VmIndex, out: &mut f64) -> Error {
-    get_value(vm, index, out)
+    get_value(vm, index, out, "a float", |value| match value {
+        ValueRef::Float(f) => Some(f),
+        _ => None,
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn glu_get_bool(vm: &Thread, index: VmIndex, out: &mut i8) -> Error {
-    let mut b = false;
-    let err = get_value(vm, index, &mut b);
-    if err == Error::Ok {
-        *out = b as i8;
-    }
-    err
+    get_value(vm, index, out, "a bool", |value| match value {
+        ValueRef::Data(data) if data.len() == 0 && data.tag() <= 1 => Some(data.tag() as i8),
+        _ => None,
+    })
 }
 
 /// The returned string is garbage collected and may not be valid after the string is removed from
@@ -212,19 +331,16 @@ pub unsafe extern "C" fn glu_get_string(
     out: &mut *const u8,
     out_len: &mut usize,
 ) -> Error {
-    let mut context = vm.context();
-    let stack = context.stack_frame::<stack::State>();
-    match stack
-        .get_variant(index)
-        .map(|value| <&str>::from_value(vm, value))
-    {
-        Some(value) => {
-            *out = &*value.as_ptr();
-            *out_len = value.len();
-            Error::Ok
-        }
-        None => Error::Unknown,
+    let mut string = (std::ptr::null(), 0);
+    let err = get_value(vm, index, &mut string, "a string", |value| match value {
+        ValueRef::String(s) => Some((s.as_ptr(), s.len())),
+        _ => None,
+    });
+    if err == Error::Ok {
+        *out = string.0;
+        *out_len = string.1;
     }
+    err
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -234,30 +350,10 @@ pub extern "C" fn glu_get_light_userdata(
     index: VmIndex,
     out: &mut *mut libc::c_void,
 ) -> Error {
-    let mut userdata = 0usize;
-    let err = get_value(vm, index, &mut userdata);
-    if err == Error::Ok {
-        *out = userdata as *mut libc::c_void;
-    }
-    err
-}
-
-fn get_value<T>(vm: &Thread, index: VmIndex, out: &mut T) -> Error
-where
-    T: for<'vm, 'value> Getable<'vm, 'value>,
-{
-    let mut context = vm.context();
-    let stack = context.stack_frame::<stack::State>();
-    match stack
-        .get_variant(index)
-        .map(|value| T::from_value(vm, value))
-    {
-        Some(value) => {
-            *out = value;
-            Error::Ok
-        }
-        None => Error::Unknown,
-    }
+    get_value(vm, index, out, "light userdata", |value| match value {
+        ValueRef::Int(i) => Some(i as usize as *mut libc::c_void),
+        _ => None,
+    })
 }
 
 #[cfg(test)]
@@ -355,4 +451,356 @@ mod tests {
             glu_free_vm(vm);
         }
     }
+
+    fn last_error() -> String {
+        let mut message = ptr::null();
+        let mut len = 0;
+        unsafe {
+            glu_last_error(&mut message, &mut len);
+            str::from_utf8(slice::from_raw_parts(message, len))
+                .unwrap()
+                .to_string()
+        }
+    }
+
+    fn s(s: &str) -> Str {
+        Str {
+            data: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    #[test]
+    fn configured_vm() {
+        unsafe {
+            let builder = &mut *glu_builder_new();
+            glu_builder_set_capability(builder, Capability::Fs, 0);
+            glu_builder_set_run_io(builder, 1);
+            let vm = &*glu_builder_build(builder);
+
+            let expr = r#"(import! std.io).read_file_to_string "Cargo.toml""#;
+            let module = "test";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Runtime
+            );
+            assert!(
+                last_error().contains("The `fs` capability has not been granted to this vm"),
+                "{}",
+                last_error()
+            );
+
+            let expr = "1 +";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Compile
+            );
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[test]
+    fn c_function_module() {
+        extern "C" fn mult(vm: &Thread) -> Status {
+            let mut l = 0.0;
+            assert_eq!(glu_get_float(vm, 0, &mut l), Error::Ok);
+            let mut r = 0.0;
+            assert_eq!(glu_get_float(vm, 1, &mut r), Error::Ok);
+            glu_push_float(vm, l * r);
+            Status::Ok
+        }
+
+        unsafe {
+            let vm = &*glu_new_vm();
+            let (name, signature) = ("mult", "Float -> Float -> Float");
+            let functions = [FunctionDef {
+                name: name.as_ptr(),
+                name_len: name.len(),
+                signature: signature.as_ptr(),
+                signature_len: signature.len(),
+                function: mult,
+                args: 2,
+            }];
+            let module = "math";
+            assert_eq!(
+                glu_add_module(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    functions.as_ptr(),
+                    1
+                ),
+                Error::Ok
+            );
+
+            let expr = "let { mult } = import! math in mult 3.0 4.0";
+            let module = "test";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Ok
+            );
+            let mut result = 0.0;
+            assert_eq!(glu_get_float(vm, 0, &mut result), Error::Ok);
+            assert_eq!(result, 12.0);
+
+            // The signatures are typechecked when the module is added
+            let signature = "Float ->";
+            let functions = [FunctionDef {
+                signature: signature.as_ptr(),
+                signature_len: signature.len(),
+                ..functions[0]
+            }];
+            let module = "broken";
+            assert_eq!(
+                glu_add_module(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    functions.as_ptr(),
+                    1
+                ),
+                Error::Compile
+            );
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[test]
+    fn marshal_records_arrays_and_variants() {
+        unsafe {
+            let vm = &*glu_new_vm();
+
+            let expr = r#"
+                type Shape = | Circle Float | Rect Float Float
+                let area shape =
+                    match shape with
+                    | Circle r -> 3.0 * r * r
+                    | Rect w h -> w * h
+                let total shapes : Array Shape -> Float =
+                    (import! std.array).foldable.foldl (\acc s -> acc + area s) 0.0 shapes
+                { total, point = { x = 1, y = 2 }, shapes = [Circle 1.0, Rect 2.0 3.0] }
+            "#;
+            let module = "shapes";
+            assert_eq!(
+                glu_load_script(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Ok
+            );
+
+            assert_eq!(
+                glu_push_global(vm, &module.as_bytes()[0], module.len()),
+                Error::Ok
+            );
+            let field = "point";
+            assert_eq!(
+                glu_push_field(vm, 0, &field.as_bytes()[0], field.len()),
+                Error::Ok
+            );
+            let mut len = 0;
+            assert_eq!(glu_get_len(vm, 1, &mut len), Error::Ok);
+            assert_eq!(len, 2);
+            let field = "y";
+            assert_eq!(
+                glu_push_field(vm, 1, &field.as_bytes()[0], field.len()),
+                Error::Ok
+            );
+            let mut y = 0;
+            assert_eq!(glu_get_int(vm, 2, &mut y), Error::Ok);
+            assert_eq!(y, 2);
+
+            let field = "missing";
+            assert_eq!(
+                glu_push_field(vm, 1, &field.as_bytes()[0], field.len()),
+                Error::NotFound
+            );
+            assert_eq!(glu_get_int(vm, 1, &mut y), Error::Value);
+            assert_eq!(last_error(), "The value at index 1 is not an integer");
+
+            let field = "shapes";
+            assert_eq!(
+                glu_push_field(vm, 0, &field.as_bytes()[0], field.len()),
+                Error::Ok
+            );
+            assert_eq!(glu_push_element(vm, 3, 1), Error::Ok);
+            let mut tag = 0;
+            assert_eq!(glu_get_tag(vm, 4, &mut tag), Error::Ok);
+            assert_eq!(tag, 1);
+            assert_eq!(glu_push_element(vm, 4, 1), Error::Ok);
+            let mut h = 0.0;
+            assert_eq!(glu_get_float(vm, 5, &mut h), Error::Ok);
+            assert_eq!(h, 3.0);
+            glu_pop(vm, 6);
+
+            // total [Rect 2.0 3.0, Circle 2.0]
+            assert_eq!(
+                glu_push_global(vm, &module.as_bytes()[0], module.len()),
+                Error::Ok
+            );
+            let field = "total";
+            assert_eq!(
+                glu_push_field(vm, 0, &field.as_bytes()[0], field.len()),
+                Error::Ok
+            );
+            glu_push_float(vm, 2.0);
+            glu_push_float(vm, 3.0);
+            assert_eq!(glu_push_variant(vm, 1, 2), Error::Ok);
+            glu_push_float(vm, 2.0);
+            assert_eq!(glu_push_variant(vm, 0, 1), Error::Ok);
+            assert_eq!(glu_push_array(vm, 2), Error::Ok);
+            assert_eq!(glu_call_function(vm, 1), Error::Ok);
+            let mut total = 0.0;
+            assert_eq!(glu_get_float(vm, 1, &mut total), Error::Ok);
+            assert_eq!(total, 18.0);
+            glu_pop(vm, 2);
+
+            let fields = [s("x"), s("y")];
+            glu_push_int(vm, 10);
+            glu_push_int(vm, 20);
+            assert_eq!(glu_push_record(vm, fields.as_ptr(), 2), Error::Ok);
+            let field = "x";
+            assert_eq!(
+                glu_push_field(vm, 0, &field.as_bytes()[0], field.len()),
+                Error::Ok
+            );
+            let mut x = 0;
+            assert_eq!(glu_get_int(vm, 1, &mut x), Error::Ok);
+            assert_eq!(x, 10);
+
+            assert_eq!(glu_push_array(vm, 10), Error::Value);
+            assert_eq!(
+                last_error(),
+                "Expected 10 values on the stack but there are only 2"
+            );
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[test]
+    fn rooted_values() {
+        unsafe {
+            let vm = &*glu_new_vm();
+
+            let expr = r#""rooted" ++ " string""#;
+            let module = "test";
+            assert_eq!(
+                glu_run_expr(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len()
+                ),
+                Error::Ok
+            );
+            let value = glu_root_value(vm, 0);
+            assert!(!value.is_null());
+            assert!(glu_root_value(vm, 1).is_null());
+            glu_pop(vm, 1);
+
+            assert_eq!(glu_push_value(vm, &*value), Error::Ok);
+            glu_free_value(value);
+
+            let mut string_ptr = ptr::null();
+            let mut string_len = 0;
+            assert_eq!(
+                glu_get_string(vm, 0, &mut string_ptr, &mut string_len),
+                Error::Ok
+            );
+            assert_eq!(
+                str::from_utf8(slice::from_raw_parts(string_ptr, string_len)),
+                Ok("rooted string")
+            );
+
+            glu_free_vm(vm);
+        }
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn json_values() {
+        unsafe {
+            let vm = &*glu_new_vm();
+
+            let module = "shapes";
+            let script = "let area w h : Float -> Float -> Float = w * h in { area }";
+            assert_eq!(
+                glu_load_script(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &script.as_bytes()[0],
+                    script.len()
+                ),
+                Error::Ok
+            );
+
+            let mut json_ptr = ptr::null();
+            let mut json_len = 0;
+            let name = "shapes.area";
+            let args = "[2.0, 3.5]";
+            assert_eq!(
+                glu_call_json(
+                    vm,
+                    &name.as_bytes()[0],
+                    name.len(),
+                    &args.as_bytes()[0],
+                    args.len(),
+                    &mut json_ptr,
+                    &mut json_len
+                ),
+                Error::Ok
+            );
+            assert_eq!(
+                str::from_utf8(slice::from_raw_parts(json_ptr, json_len)),
+                Ok("7.0")
+            );
+
+            let module = "json";
+            let expr = r#"{ x = [1, 2] ++ [3], y = "a" }"#;
+            assert_eq!(
+                glu_run_expr_json(
+                    vm,
+                    &module.as_bytes()[0],
+                    module.len(),
+                    &expr.as_bytes()[0],
+                    expr.len(),
+                    &mut json_ptr,
+                    &mut json_len
+                ),
+                Error::Ok
+            );
+            assert_eq!(
+                str::from_utf8(slice::from_raw_parts(json_ptr, json_len)),
+                Ok(r#"{"x":[1,2,3],"y":"a"}"#)
+            );
+
+            glu_free_vm(vm);
+        }
+    }
 }
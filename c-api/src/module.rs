@@ -0,0 +1,121 @@
+//! Modules of functions implemented in C
+
+use std::slice;
+
+use gluon::{
+    base::{
+        symbol::Symbol,
+        types::{Field, Type},
+    },
+    import::add_extern_module,
+    vm::{
+        api::{generic::A, CPrimitive, Pushable, VmType},
+        thread::{Thread, ThreadInternal},
+        types::VmIndex,
+        ExternModule,
+    },
+    ThreadExt,
+};
+
+use crate::{gluon_error, str_arg, Error, Function};
+
+/// A function in a module added with `glu_add_module`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FunctionDef {
+    /// The name of the function in the module
+    pub name: *const u8,
+    pub name_len: usize,
+    /// The gluon type of the function, such as `Float -> Float -> Float`
+    pub signature: *const u8,
+    pub signature_len: usize,
+    pub function: Function,
+    /// The number of arguments which `function` takes
+    pub args: VmIndex,
+}
+
+struct Prim {
+    name: String,
+    function: Function,
+    args: VmIndex,
+}
+
+/// Loads the module `<module>.prim` which contains the functions without their types, which are
+/// given by the gluon module wrapping it
+fn load_prims(vm: &Thread, module: &str, prims: &[Prim]) -> gluon::vm::Result<ExternModule> {
+    let any = A::make_forall_type(vm);
+    let mut context = vm.current_context();
+    let mut field_names = Vec::with_capacity(prims.len());
+    let mut fields = Vec::with_capacity(prims.len());
+    for prim in prims {
+        let id = format!("{}.{}", module, prim.name);
+        unsafe { CPrimitive::new(prim.function, prim.args, &id) }.vm_push(&mut context)?;
+        field_names.push(vm.global_env().intern(&prim.name)?);
+        fields.push(Field::new(Symbol::from(&prim.name[..]), any.clone()));
+    }
+    context
+        .context()
+        .push_new_record(prims.len(), &field_names)?;
+    let value = vm.root_value(context.pop().clone());
+
+    Ok(ExternModule {
+        metadata: Default::default(),
+        value,
+        typ: Type::record(vec![], fields),
+    })
+}
+
+/// Adds the module `module` containing `functions`, which gluon code can then import with
+/// `import! module`. The signatures are typechecked when the module is added and the function is
+/// trusted to follow its signature.
+#[no_mangle]
+pub unsafe extern "C" fn glu_add_module(
+    vm: &Thread,
+    module: &u8,
+    module_len: usize,
+    functions: *const FunctionDef,
+    count: usize,
+) -> Error {
+    let module = match str_arg(module, module_len) {
+        Ok(module) => module.to_string(),
+        Err(err) => return err,
+    };
+    let functions = if count == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(functions, count)
+    };
+
+    let mut prims = Vec::with_capacity(count);
+    let mut source = format!("let prim = import! {}.prim\n", module);
+    for def in functions {
+        let (name, signature) = match (
+            str_arg(&*def.name, def.name_len),
+            str_arg(&*def.signature, def.signature_len),
+        ) {
+            (Ok(name), Ok(signature)) => (name, signature),
+            (Err(err), _) | (_, Err(err)) => return err,
+        };
+        source.push_str(&format!("let {} : {} = prim.{}\n", name, signature, name));
+        prims.push(Prim {
+            name: name.to_string(),
+            function: def.function,
+            args: def.args,
+        });
+    }
+    source.push_str("{\n");
+    for prim in &prims {
+        source.push_str(&format!("    {},\n", prim.name));
+    }
+    source.push_str("}\n");
+
+    let prim_module = format!("{}.prim", module);
+    {
+        let module = module.clone();
+        add_extern_module(vm, &prim_module, move |vm| load_prims(vm, &module, &prims));
+    }
+    match vm.load_script(&module, &source) {
+        Ok(()) => crate::Error::Ok,
+        Err(err) => gluon_error(err),
+    }
+}
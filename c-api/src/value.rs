@@ -0,0 +1,248 @@
+//! Marshalling of records, arrays and variants as well as values which are kept alive outside of
+//! the stack
+
+use std::slice;
+
+use gluon::vm::{
+    api::{Hole, OpaqueValue, Pushable, ValueRef},
+    thread::{RootedThread, RootedValue, Thread, ThreadInternal},
+    types::{VmIndex, VmTag},
+    Variants,
+};
+
+use crate::{fail, str_arg, vm_error, with_value, Error};
+
+/// A string passed to the API, which must be valid UTF-8
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Str {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// A value which is kept alive until it is freed with `glu_free_value`, regardless of whether
+/// it is on the stack
+pub struct Value(RootedValue<RootedThread>);
+
+fn push_result(result: gluon::vm::Result<Variants>) -> Error {
+    match result {
+        Ok(_) => Error::Ok,
+        Err(err) => vm_error(err),
+    }
+}
+
+fn check_stack(vm: &Thread, count: usize) -> Result<(), Error> {
+    if crate::glu_len(vm) < count {
+        return Err(fail(
+            Error::Value,
+            format!(
+                "Expected {} values on the stack but there are only {}",
+                count,
+                crate::glu_len(vm)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces the `count` topmost values of the stack with a record containing them, named by
+/// `fields`. The fields must be pushed in the order they appear in the record's type.
+#[no_mangle]
+pub unsafe extern "C" fn glu_push_record(vm: &Thread, fields: *const Str, count: usize) -> Error {
+    if let Err(err) = check_stack(vm, count) {
+        return err;
+    }
+    let fields = if count == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(fields, count)
+    };
+    let mut names = Vec::with_capacity(count);
+    for field in fields {
+        let name = match str_arg(&*field.data, field.len) {
+            Ok(name) => name,
+            Err(err) => return err,
+        };
+        match vm.global_env().intern(name) {
+            Ok(name) => names.push(name),
+            Err(err) => return vm_error(err),
+        }
+    }
+    let mut context = vm.current_context();
+    push_result(context.context().push_new_record(count, &names))
+}
+
+/// Replaces the `count` topmost values of the stack with the variant `tag` (the index of the
+/// constructor in its type) which contains them as its arguments
+#[no_mangle]
+pub extern "C" fn glu_push_variant(vm: &Thread, tag: VmTag, count: usize) -> Error {
+    if let Err(err) = check_stack(vm, count) {
+        return err;
+    }
+    let mut context = vm.current_context();
+    push_result(context.context().push_new_data(tag, count))
+}
+
+/// Replaces the `count` topmost values of the stack with an array containing them. The values
+/// must all have the same type.
+#[no_mangle]
+pub extern "C" fn glu_push_array(vm: &Thread, count: usize) -> Error {
+    if let Err(err) = check_stack(vm, count) {
+        return err;
+    }
+    let mut context = vm.current_context();
+    push_result(context.context().push_new_array(count))
+}
+
+/// Returns the tag of the variant at `index`, the index of its constructor in its type
+#[no_mangle]
+pub extern "C" fn glu_get_tag(vm: &Thread, index: VmIndex, out: &mut VmTag) -> Error {
+    let result = with_value(vm, index, |value| match value.as_ref() {
+        ValueRef::Data(data) => Ok(data.tag()),
+        _ => Err(fail(
+            Error::Value,
+            format!("The value at index {} is not a variant", index),
+        )),
+    });
+    match result {
+        Ok(tag) => {
+            *out = tag;
+            Error::Ok
+        }
+        Err(err) => err,
+    }
+}
+
+/// Returns the number of elements of the array, or the number of fields of the record or variant,
+/// at `index`
+#[no_mangle]
+pub extern "C" fn glu_get_len(vm: &Thread, index: VmIndex, out: &mut usize) -> Error {
+    let result = with_value(vm, index, |value| match value.as_ref() {
+        ValueRef::Data(data) => Ok(data.len()),
+        ValueRef::Array(array) => Ok(array.len()),
+        _ => Err(fail(
+            Error::Value,
+            format!(
+                "The value at index {} is not an array, record or variant",
+                index
+            ),
+        )),
+    });
+    match result {
+        Ok(len) => {
+            *out = len;
+            Error::Ok
+        }
+        Err(err) => err,
+    }
+}
+
+fn push_value<'vm>(vm: &'vm Thread, value: impl Pushable<'vm>) -> Error {
+    match Thread::push(vm, value) {
+        Ok(()) => Error::Ok,
+        Err(err) => vm_error(err),
+    }
+}
+
+/// Pushes the element `element` of the array, or the field at position `element` of the record
+/// or variant, at `index`
+#[no_mangle]
+pub extern "C" fn glu_push_element(vm: &Thread, index: VmIndex, element: usize) -> Error {
+    let result = with_value(vm, index, |value| {
+        let element_value = match value.as_ref() {
+            ValueRef::Data(data) => data
+                .get_variant(element)
+                .map(|value| vm.root_value::<RootedThread>(value)),
+            ValueRef::Array(array) => array
+                .get(element)
+                .map(|value| vm.root_value::<RootedThread>(value)),
+            _ => {
+                return Err(fail(
+                    Error::Value,
+                    format!(
+                        "The value at index {} is not an array, record or variant",
+                        index
+                    ),
+                ))
+            }
+        };
+        element_value.ok_or_else(|| {
+            fail(
+                Error::NotFound,
+                format!("The value at index {} has no element {}", index, element),
+            )
+        })
+    });
+    match result {
+        Ok(value) => push_value(vm, value),
+        Err(err) => err,
+    }
+}
+
+/// Pushes the field `name` of the record at `index`
+#[no_mangle]
+pub unsafe extern "C" fn glu_push_field(
+    vm: &Thread,
+    index: VmIndex,
+    name: &u8,
+    len: usize,
+) -> Error {
+    let name = match str_arg(name, len) {
+        Ok(name) => name,
+        Err(err) => return err,
+    };
+    let result = with_value(vm, index, |value| match value.as_ref() {
+        ValueRef::Data(data) => data
+            .lookup_field(vm, name)
+            .map(|value| vm.root_value::<RootedThread>(value))
+            .ok_or_else(|| {
+                fail(
+                    Error::NotFound,
+                    format!("The record at index {} has no field `{}`", index, name),
+                )
+            }),
+        _ => Err(fail(
+            Error::Value,
+            format!("The value at index {} is not a record", index),
+        )),
+    });
+    match result {
+        Ok(value) => push_value(vm, value),
+        Err(err) => err,
+    }
+}
+
+/// Pushes the global `name`, such as `std.int.num.(+)` or a module loaded with
+/// `glu_load_script`
+#[no_mangle]
+pub unsafe extern "C" fn glu_push_global(vm: &Thread, name: &u8, len: usize) -> Error {
+    let name = match str_arg(name, len) {
+        Ok(name) => name,
+        Err(err) => return err,
+    };
+    match vm.get_global::<OpaqueValue<RootedThread, Hole>>(name) {
+        Ok(value) => push_value(vm, value),
+        Err(err) => fail(Error::NotFound, err),
+    }
+}
+
+/// Roots the value at `index` so that it stays alive after it is removed from the stack. Returns
+/// null if there is no value at `index`.
+#[no_mangle]
+pub extern "C" fn glu_root_value(vm: &Thread, index: VmIndex) -> *mut Value {
+    match with_value(vm, index, |value| Ok(vm.root_value(value))) {
+        Ok(value) => Box::into_raw(Box::new(Value(value))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Pushes a value rooted by `glu_root_value`
+#[no_mangle]
+pub extern "C" fn glu_push_value(vm: &Thread, value: &Value) -> Error {
+    push_value(vm, value.0.clone())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn glu_free_value(value: *mut Value) {
+    drop(Box::from_raw(value));
+}
@@ -0,0 +1,166 @@
+//! Rejects typechecked expressions which use values that perform effects the host does not allow.
+//!
+//! An effect is named either by a type, such as `IO`, or by the label of an effect row, such as
+//! `io` in `Eff [| io : Lift IO | r |] a`. Effect types are resolved to the type they refer to so
+//! a module can't be rejected, or get through, by defining an unrelated type of the same name.
+//! Every identifier and field access in the expression is checked, so `io.println "x"` is
+//! rejected if `IO` is forbidden since `println` has the type `String -> IO ()`. Only the result
+//! of a function is searched for effects, so a function which just takes an effectful argument,
+//! such as `(String -> IO ()) -> Int`, is allowed. Records are only checked when their fields are
+//! accessed so importing a module is allowed as long as its effectful values are not used.
+//! Aliases are expanded while searching so that an alias of an effectful type can't be used to
+//! hide the effect.
+
+use crate::base::{
+    ast::{self, Expr, SpannedExpr, Visitor},
+    error::{Errors, Help},
+    fnv::FnvSet,
+    pos::{self, BytePos, Span},
+    symbol::Symbol,
+    types::{self, ArcType, Type, Walker},
+};
+
+use crate::typecheck::{SpannedTypeError, TypeError};
+
+/// The effects which an expression is not allowed to perform
+pub struct Forbidden<'f> {
+    /// The types of effects, such as `std.io.IO`, along with the name they were forbidden by
+    types: Vec<(Symbol, &'f str)>,
+    /// The names, which are matched against the labels of effect rows
+    labels: &'f [String],
+}
+
+impl<'f> Forbidden<'f> {
+    /// Creates the forbidden effects from their names. `find_type` resolves the names which
+    /// refer to types to the symbol of the type.
+    pub fn new(forbidden: &'f [String], mut find_type: impl FnMut(&str) -> Option<Symbol>) -> Self {
+        Forbidden {
+            types: forbidden
+                .iter()
+                .filter_map(|effect| Some((find_type(effect)?, &effect[..])))
+                .collect(),
+            labels: forbidden,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    fn typ(&self, name: &Symbol) -> Option<&'f str> {
+        self.types
+            .iter()
+            .find(|(typ, _)| typ == name)
+            .map(|(_, effect)| *effect)
+    }
+
+    fn label(&self, name: &Symbol) -> Option<&'f str> {
+        self.labels
+            .iter()
+            .find(|effect| **effect == name.declared_name())
+            .map(|effect| &effect[..])
+    }
+}
+
+struct FindEffect<'a, 'f> {
+    forbidden: &'a Forbidden<'f>,
+    found: Option<&'f str>,
+    visited: FnvSet<Symbol>,
+}
+
+impl<'a, 'f> Walker<'a, ArcType> for FindEffect<'_, 'f> {
+    fn walk(&mut self, typ: &'a ArcType) {
+        if self.found.is_some() {
+            return;
+        }
+        match &**typ {
+            Type::Ident(id) => self.found = self.forbidden.typ(&id.name),
+            Type::Alias(alias) => {
+                self.found = self.forbidden.typ(&alias.name);
+                if self.visited.insert(alias.name.clone()) {
+                    self.walk(alias.unresolved_type());
+                }
+            }
+            Type::Effect(row) => {
+                self.found =
+                    types::row_iter(row).find_map(|field| self.forbidden.label(&field.name));
+            }
+            // Only calling a function performs effects, and then only those in its result
+            Type::Function(_, _, ret) => return self.walk(ret),
+            // Types exported from a record are not effects that the record performs
+            Type::ExtendTypeRow { rest, .. } => return self.walk(rest),
+            _ => (),
+        }
+        types::walk_type_(typ, self)
+    }
+}
+
+/// Returns the first of the `forbidden` effects that a value of type `typ` may perform
+pub fn forbidden_effect<'f>(forbidden: &Forbidden<'f>, typ: &ArcType) -> Option<&'f str> {
+    let mut finder = FindEffect {
+        forbidden,
+        found: None,
+        visited: FnvSet::default(),
+    };
+    finder.walk(typ);
+    finder.found
+}
+
+struct CheckEffects<'a, 'f> {
+    forbidden: &'a Forbidden<'f>,
+    errors: Errors<SpannedTypeError<Symbol>>,
+}
+
+impl CheckEffects<'_, '_> {
+    fn check(&mut self, span: Span<BytePos>, name: &Symbol, typ: &ArcType) {
+        // Records, such as imported modules, are checked when their fields are accessed
+        if let Type::Record(_) = **typ {
+            return;
+        }
+        if let Some(effect) = forbidden_effect(self.forbidden, typ) {
+            self.errors.push(pos::spanned(
+                span,
+                Help {
+                    error: TypeError::ForbiddenEffect {
+                        name: name.clone(),
+                        effect: effect.to_string(),
+                    },
+                    help: None,
+                },
+            ));
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'a, 'ast> for CheckEffects<'_, '_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+        match &e.value {
+            Expr::Ident(id) => self.check(e.span, &id.name, &id.typ),
+            Expr::Projection(_, field, typ) => self.check(e.span, field, typ),
+            _ => (),
+        }
+        ast::walk_expr(self, e)
+    }
+}
+
+/// Checks that `expr` does not use any value which may perform one of the `forbidden` effects
+pub fn check_effects(
+    forbidden: &Forbidden<'_>,
+    expr: &SpannedExpr<'_, Symbol>,
+) -> Result<(), Errors<SpannedTypeError<Symbol>>> {
+    if forbidden.is_empty() {
+        return Ok(());
+    }
+    let mut visitor = CheckEffects {
+        forbidden,
+        errors: Errors::new(),
+    };
+    visitor.visit_expr(expr);
+    if visitor.errors.has_errors() {
+        Err(visitor.errors)
+    } else {
+        Ok(())
+    }
+}
@@ -17,8 +17,10 @@ extern crate gluon_base as base;
 #[macro_use]
 extern crate gluon_codegen;
 
+pub mod effects;
 pub mod kindcheck;
 pub mod metadata;
+pub mod privacy;
 mod recursion_check;
 pub mod rename;
 pub mod substitution;
@@ -162,8 +162,16 @@ pub fn metadata(
                         // We want the first definition of this value and not the definition of the
                         // type
                         let metadata_definition = metadata.definition.take();
+                        let attributes = metadata.attributes.len();
                         metadata.merge_with_ref(&type_metadata);
                         metadata.definition = metadata_definition;
+
+                        // Values of a private type are not private themselves
+                        let mut i = 0;
+                        metadata.attributes.retain(|attribute| {
+                            i += 1;
+                            i <= attributes || attribute.name != "private"
+                        });
                     }
 
                     self.stack_var(id.name.clone(), metadata);
@@ -279,6 +287,10 @@ pub fn metadata(
                     // We want the first definition of this value and not the definition of the
                     // type
                     type_metadata.definition = None;
+                    // Nor do we want the argument to be private because its type is
+                    type_metadata
+                        .attributes
+                        .retain(|attribute| attribute.name != "private");
                     self.stack_var(arg.name.value.name.clone(), type_metadata);
                 }
             }
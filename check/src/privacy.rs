@@ -0,0 +1,358 @@
+//! Rejects uses of values, fields and constructors which another module marked as `#[private]`.
+//!
+//! The attribute may be placed on
+//!
+//! * `let` bindings, which are removed from the record that the module exports so other modules
+//!   can't access them however the module is passed around.
+//! * fields of records, which can then not be accessed from the modules which import the record
+//!   that they are exported in.
+//! * fields of record types, which can then not be accessed, constructed or matched on outside of
+//!   the module defining the type.
+//! * type bindings, which hides every field of a record type and every constructor of a variant
+//!   type from other modules. The type itself can still be named so the module can export it as
+//!   an abstract type together with functions which uphold its invariants.
+//!
+//! ```gluon
+//! #[private]
+//! type Counter = { count : Int }
+//!
+//! let new : Counter = { count = 0 }
+//! let increment counter : Counter -> Counter = { count = counter.count + 1 }
+//!
+//! { Counter, new, increment }
+//! ```
+//!
+//! As with other metadata, a binding defined as another private binding, such as
+//! `let x = private_value`, is private as well.
+
+use std::{mem, sync::Arc};
+
+use crate::base::{
+    ast::{
+        self, Expr, ExprField, Pattern, PatternField, SpannedExpr, SpannedPattern, Typed, Visitor,
+    },
+    error::{Errors, Help},
+    fnv::{FnvMap, FnvSet},
+    metadata::Metadata,
+    pos::{self, BytePos, Span},
+    symbol::Symbol,
+    types::{ArcType, Type, TypeExt},
+};
+
+use crate::{
+    typecheck::{SpannedTypeError, TypeError},
+    TypecheckEnv,
+};
+
+fn is_private(metadata: &Metadata) -> bool {
+    metadata.get_attribute("private").is_some()
+}
+
+struct CheckPrivacy<'e> {
+    env: &'e dyn TypecheckEnv<Type = ArcType>,
+    /// Metadata of the local variables bound to values from other modules
+    values: FnvMap<Symbol, Arc<Metadata>>,
+    /// Metadata of the types defined in other modules
+    types: FnvMap<Symbol, Arc<Metadata>>,
+    modules: FnvSet<Symbol>,
+    errors: Errors<SpannedTypeError<Symbol>>,
+}
+
+impl CheckPrivacy<'_> {
+    fn error(&mut self, span: Span<BytePos>, name: &Symbol) {
+        self.errors.push(pos::spanned(
+            span,
+            Help {
+                error: TypeError::Private { name: name.clone() },
+                help: None,
+            },
+        ));
+    }
+
+    /// Records the metadata of the types exported by `typ`, the type of a module
+    fn add_module_types(&mut self, typ: &ArcType, metadata: &Metadata) {
+        for field in typ.type_field_iter() {
+            if let Some(field_metadata) = metadata.module.get(field.name.declared_name()) {
+                self.types
+                    .insert(field.typ.name.clone(), field_metadata.clone());
+            }
+        }
+        for field in typ.row_iter() {
+            if let Type::Record(_) = *field.typ {
+                if let Some(field_metadata) = metadata.module.get(field.name.declared_name()) {
+                    self.add_module_types(&field.typ, field_metadata);
+                }
+            }
+        }
+    }
+
+    /// Returns the metadata of `expr` if it is a value from another module
+    fn foreign_metadata(&mut self, expr: &SpannedExpr<'_, Symbol>) -> Option<Arc<Metadata>> {
+        match &expr.value {
+            Expr::Ident(id) if id.name.is_global() => {
+                let metadata = self.env.get_metadata(&id.name)?;
+                if self.modules.insert(id.name.clone()) {
+                    self.add_module_types(&id.typ, &metadata);
+                }
+                Some(metadata)
+            }
+            Expr::Ident(id) => self.values.get(&id.name).cloned(),
+            Expr::Projection(expr, field, _) => self
+                .foreign_metadata(expr)?
+                .module
+                .get(field.declared_name())
+                .cloned(),
+            Expr::MacroExpansion { replacement, .. } => self.foreign_metadata(replacement),
+            Expr::Tuple { elems, .. } if elems.len() == 1 => self.foreign_metadata(&elems[0]),
+            _ => None,
+        }
+    }
+
+    /// Returns the metadata of `typ` if it is defined in another module
+    fn foreign_type(&self, typ: &ArcType) -> Option<&Arc<Metadata>> {
+        typ.remove_forall()
+            .alias_ident()
+            .and_then(|id| self.types.get(id))
+    }
+
+    fn is_private_field(&self, typ: &ArcType, field: &Symbol) -> bool {
+        self.foreign_type(typ).map_or(false, |metadata| {
+            is_private(metadata)
+                || metadata
+                    .module
+                    .get(field.declared_name())
+                    .map_or(false, |field| is_private(field))
+        })
+    }
+
+    fn check_constructor(&mut self, span: Span<BytePos>, id: &Symbol, typ: &ArcType) {
+        let mut typ = typ.remove_forall_and_implicit_args();
+        while let Type::Function(_, _, ret) = &**typ {
+            typ = ret;
+        }
+        if self
+            .foreign_type(typ)
+            .map_or(false, |metadata| is_private(metadata))
+        {
+            self.error(span, id);
+        }
+    }
+
+    /// Checks a pattern which matches on a value from another module
+    fn foreign_pattern(&mut self, pattern: &SpannedPattern<'_, Symbol>, metadata: Arc<Metadata>) {
+        match &pattern.value {
+            Pattern::Ident(id) => {
+                self.values.insert(id.name.clone(), metadata);
+            }
+            Pattern::As(id, pattern) => {
+                self.values.insert(id.value.clone(), metadata.clone());
+                self.foreign_pattern(pattern, metadata);
+            }
+            Pattern::Record { fields, .. } => {
+                for field in &**fields {
+                    if let PatternField::Value { name, value } = field {
+                        let field_metadata = match metadata.module.get(name.value.declared_name()) {
+                            Some(field_metadata) => field_metadata.clone(),
+                            None => continue,
+                        };
+                        if is_private(&field_metadata) {
+                            self.error(name.span, &name.value);
+                        }
+                        match value {
+                            Some(pattern) => self.foreign_pattern(pattern, field_metadata),
+                            None => {
+                                self.values.insert(name.value.clone(), field_metadata);
+                            }
+                        }
+                    }
+                }
+            }
+            Pattern::Constructor(..)
+            | Pattern::Tuple { .. }
+            | Pattern::Literal(_)
+            | Pattern::Error => (),
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'a, 'ast> for CheckPrivacy<'_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+        match &e.value {
+            Expr::Ident(id) if id.name.declared_name().starts_with(char::is_uppercase) => {
+                self.check_constructor(e.span, &id.name, &id.typ)
+            }
+            Expr::Projection(expr, field, _) => {
+                let env = self.env;
+                let private = self.foreign_metadata(expr).map_or(false, |metadata| {
+                    metadata
+                        .module
+                        .get(field.declared_name())
+                        .map_or(false, |field| is_private(field))
+                }) || expr
+                    .try_type_of(&env)
+                    .map_or(false, |typ| self.is_private_field(&typ, field));
+                if private {
+                    self.error(e.span, field);
+                }
+            }
+            Expr::Record { typ, exprs, .. } => {
+                for field in &**exprs {
+                    if self.is_private_field(typ, &field.name.value) {
+                        self.error(field.name.span, &field.name.value);
+                    }
+                }
+            }
+            Expr::LetBindings(bindings, _) => {
+                for bind in bindings {
+                    if let Some(metadata) = self.foreign_metadata(&bind.expr) {
+                        self.foreign_pattern(&bind.name, metadata);
+                    }
+                }
+            }
+            Expr::Match(expr, alts) => {
+                if let Some(metadata) = self.foreign_metadata(expr) {
+                    for alt in &**alts {
+                        self.foreign_pattern(&alt.pattern, metadata.clone());
+                    }
+                }
+            }
+            _ => (),
+        }
+        ast::walk_expr(self, e)
+    }
+
+    fn visit_pattern(&mut self, p: &'a SpannedPattern<'ast, Symbol>) {
+        match &p.value {
+            Pattern::Constructor(id, _) => self.check_constructor(p.span, &id.name, &id.typ),
+            Pattern::Record { typ, fields, .. } => {
+                for field in &**fields {
+                    if let PatternField::Value { name, .. } = field {
+                        if self.is_private_field(typ, &name.value) {
+                            self.error(name.span, &name.value);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+        ast::walk_pattern(self, &p.value)
+    }
+}
+
+/// Records the types exported by every module which is imported, before any of them are used
+struct ImportedModules<'c, 'e>(&'c mut CheckPrivacy<'e>);
+
+impl<'a, 'ast> Visitor<'a, 'ast> for ImportedModules<'_, '_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+        if let Expr::Ident(id) = &e.value {
+            if id.name.is_global() {
+                self.0.foreign_metadata(e);
+            }
+        }
+        ast::walk_expr(self, e)
+    }
+}
+
+/// Checks that `expr` does not use anything which another module marked as `#[private]`
+pub fn check_privacy(
+    env: &dyn TypecheckEnv<Type = ArcType>,
+    expr: &SpannedExpr<'_, Symbol>,
+) -> Result<(), Errors<SpannedTypeError<Symbol>>> {
+    let mut visitor = CheckPrivacy {
+        env,
+        values: FnvMap::default(),
+        types: FnvMap::default(),
+        modules: FnvSet::default(),
+        errors: Errors::new(),
+    };
+    ImportedModules(&mut visitor).visit_expr(expr);
+    visitor.visit_expr(expr);
+    if visitor.errors.has_errors() {
+        Err(visitor.errors)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the record expression which the module `expr` exports
+fn exported_record<'a, 'ast>(
+    expr: &'a mut SpannedExpr<'ast, Symbol>,
+) -> Option<&'a mut SpannedExpr<'ast, Symbol>> {
+    if let Expr::Record { .. } = expr.value {
+        return Some(expr);
+    }
+    match &mut expr.value {
+        Expr::LetBindings(_, body) | Expr::TypeBindings(_, body) => exported_record(body),
+        _ => None,
+    }
+}
+
+fn remove_fields(typ: &ArcType, hidden: &FnvSet<String>) -> ArcType {
+    match &**typ {
+        Type::Forall(params, body) => Type::forall(params.clone(), remove_fields(body, hidden)),
+        Type::Record(_) => {
+            let mut fields = typ.row_iter();
+            let kept = fields
+                .by_ref()
+                .filter(|field| !hidden.contains(field.name.declared_name()))
+                .cloned()
+                .collect();
+            let rest = fields.current_type().clone();
+            Type::poly_record(typ.type_field_iter().cloned().collect(), kept, rest)
+        }
+        _ => typ.clone(),
+    }
+}
+
+/// Removes the `let` bindings marked as `#[private]` from the record which the module `expr`
+/// exports, along with their fields in `typ`, the type of the module, and in `metadata`. Other
+/// modules are then rejected by the typechecker when they try to access them.
+pub fn hide_private_bindings(
+    expr: &mut SpannedExpr<'_, Symbol>,
+    typ: &mut ArcType,
+    metadata: &mut Arc<Metadata>,
+) {
+    let record = match exported_record(expr) {
+        Some(record) => record,
+        None => return,
+    };
+    let (record_type, exprs) = match &mut record.value {
+        Expr::Record { typ, exprs, .. } => (typ, exprs),
+        _ => return,
+    };
+
+    let is_hidden = |field: &ExprField<'_, Symbol, SpannedExpr<'_, Symbol>>| {
+        metadata
+            .module
+            .get(field.name.value.declared_name())
+            .map_or(false, |metadata| is_private(metadata))
+    };
+    // Move the hidden fields to the end while keeping the order of the others
+    let mut len = 0;
+    for i in 0..exprs.len() {
+        if !is_hidden(&exprs[i]) {
+            exprs.swap(len, i);
+            len += 1;
+        }
+    }
+    if len == exprs.len() {
+        return;
+    }
+    let (kept, hidden) = mem::take(exprs).split_at_mut(len);
+    *exprs = kept;
+
+    let hidden = hidden
+        .iter()
+        .map(|field| field.name.value.declared_name().to_string())
+        .collect::<FnvSet<_>>();
+    *record_type = remove_fields(record_type, &hidden);
+    *typ = remove_fields(typ, &hidden);
+    let metadata = Arc::make_mut(metadata);
+    for name in &hidden {
+        metadata.module.remove(name);
+    }
+}
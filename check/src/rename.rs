@@ -6,6 +6,7 @@ use crate::base::{
         TypedIdent,
     },
     fnv::FnvMap,
+    origin::{Desugaring, OriginMap},
     pos::{self, ByteOffset, BytePos, Span},
     scoped_map::ScopedMap,
     source::Source,
@@ -20,6 +21,7 @@ struct Environment {
 pub fn rename<'s, 'ast>(
     source: &'s (dyn Source + 's),
     symbols: &mut SymbolModule,
+    origins: &mut OriginMap,
     ast_arena: ast::ArenaRef<'s, 'ast, Symbol>,
     expr: &mut SpannedExpr<'ast, Symbol>,
 ) {
@@ -31,6 +33,7 @@ pub fn rename<'s, 'ast>(
     struct RenameVisitor<'a: 'b, 'b, 's, 'ast> {
         source: &'s (dyn Source + 's),
         symbols: &'b mut SymbolModule<'a>,
+        origins: &'b mut OriginMap,
         seen_symbols: FnvMap<Symbol, u32>,
         scope: Vec<Symbol>,
         env: Environment,
@@ -284,8 +287,16 @@ pub fn rename<'s, 'ast>(
                     ..
                 }) => {
                     let flat_map = self.symbols.simple_symbol("flat_map");
+                    let flat_map_span =
+                        Span::new(expr.span.end(), expr.span.start() + ByteOffset::from(2));
+                    // The call to `flat_map` is the code which runs `do x = bound`
+                    self.origins.record(
+                        flat_map_span,
+                        Desugaring::Do,
+                        Span::new(expr.span.start(), bound.span.end()),
+                    );
                     *flat_map_id = Some(self.ast_arena.alloc(pos::spanned(
-                        Span::new(expr.span.end(), expr.span.start() + ByteOffset::from(2)),
+                        flat_map_span,
                         Expr::Ident(TypedIdent {
                             name: flat_map,
                             typ: self.hole.clone(),
@@ -377,6 +388,7 @@ pub fn rename<'s, 'ast>(
     let mut visitor = RenameVisitor {
         source,
         symbols: symbols,
+        origins,
         seen_symbols: Default::default(),
         scope: Vec::new(),
         env: Environment {
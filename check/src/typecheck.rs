@@ -399,6 +399,8 @@ impl<'a, 'ast> Typecheck<'a, 'ast> {
                 | EmptyCase
                 | KindError(_)
                 | RecursionCheck(_)
+                | ForbiddenEffect { .. }
+                | Private { .. }
                 | Message(_) => (),
                 NotAFunction(ref mut typ)
                 | UndefinedField(ref mut typ, _)
@@ -59,6 +59,15 @@ pub enum TypeError<I, T> {
         expected: I,
         actual: T,
     },
+    /// A value whose type performs an effect which is not allowed was used, see `effects`
+    ForbiddenEffect {
+        name: I,
+        effect: String,
+    },
+    /// Something which another module marked as `#[private]` was used, see `privacy`
+    Private {
+        name: I,
+    },
 }
 
 impl<I, T> From<KindCheckError<I, T>> for TypeError<I, T> {
@@ -219,6 +228,12 @@ where
                 "The constructor returns the type `{}` instead of the expected type `{}`",
                 actual, expected
             ),
+            ForbiddenEffect { name, effect } => write!(
+                f,
+                "`{}` performs the `{}` effect which is not allowed",
+                name, effect
+            ),
+            Private { name } => write!(f, "`{}` is private to the module which defines it", name),
         }
     }
 }
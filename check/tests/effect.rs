@@ -270,3 +270,46 @@ let run_state eff : (forall s . Eff [| st : State s | r |] a) -> Eff [| | r |] a
     "#,
     "()"
 }
+
+fn forbidden_effects(text: &str, forbidden: &[&str]) -> Vec<String> {
+    let (expr, result) = support::typecheck_expr(text);
+    result.unwrap_or_else(|err| panic!("{}", err));
+
+    let forbidden = forbidden.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    match check::effects::check_effects(&forbidden, expr.expr()) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .map(|err| err.value.error.to_string())
+            .collect(),
+    }
+}
+
+#[test]
+fn forbidden_effects_are_rejected() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Eff r a =
+    | Pure : a -> Eff r a
+type State s r a =
+    | Get : State s r s
+    .. r
+
+let any x = any x
+let get : Eff [| state : State Int | r |] Int = any ()
+let pure : Eff [| | r |] Int = any ()
+let x = pure
+get
+"#;
+    assert_eq!(forbidden_effects(text, &[]), Vec::<String>::new());
+    assert_eq!(forbidden_effects(text, &["io"]), Vec::<String>::new());
+    assert_eq!(
+        forbidden_effects(text, &["state"]),
+        vec!["`get` performs the `state` effect which is not allowed"]
+    );
+    assert_eq!(
+        forbidden_effects(text, &["State"]),
+        vec!["`get` performs the `State` effect which is not allowed"]
+    );
+}
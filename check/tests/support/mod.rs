@@ -63,7 +63,7 @@ pub fn get_local_interner() -> Rc<RefCell<Symbols>> {
 #[allow(dead_code)]
 pub fn intern_unscoped(s: &str) -> Symbol {
     let i = get_local_interner();
-    let mut i = i.borrow_mut();
+    let i = i.borrow();
 
     i.simple_symbol(s)
 }
@@ -99,7 +99,7 @@ pub struct MockEnv {
 impl MockEnv {
     pub fn new() -> MockEnv {
         let interner = get_local_interner();
-        let mut interner = interner.borrow_mut();
+        let interner = interner.borrow();
 
         MockEnv {
             bool: {
@@ -215,11 +215,13 @@ pub fn typecheck_expr_expected(
         rename::rename(
             &source,
             &mut SymbolModule::new("test".into(), &mut interner),
+            &mut Default::default(),
             arena,
             expr,
         );
         let (_, mut metadata) = metadata::metadata(&env, &expr);
-        reparse_infix(arena, &metadata, &*interner, expr).unwrap_or_else(|err| panic!("{}", err));
+        reparse_infix(arena, &metadata, &*interner, &mut Default::default(), expr)
+            .unwrap_or_else(|err| panic!("{}", err));
 
         let mut tc = Typecheck::new(
             "test".into(),
@@ -266,13 +268,15 @@ pub fn typecheck_partial_expr(
         rename::rename(
             &source,
             &mut SymbolModule::new("test".into(), &mut interner),
+            &mut Default::default(),
             arena,
             expr,
         );
 
         let (_, mut metadata) = metadata::metadata(&env, &expr);
 
-        reparse_infix(arena, &metadata, &*interner, expr).unwrap_or_else(|err| panic!("{}", err));
+        reparse_infix(arena, &metadata, &*interner, &mut Default::default(), expr)
+            .unwrap_or_else(|err| panic!("{}", err));
 
         let mut tc = Typecheck::new(
             "test".into(),
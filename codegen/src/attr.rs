@@ -114,6 +114,55 @@ impl Container {
     }
 }
 
+pub struct Field {
+    pub rename: Option<String>,
+}
+
+impl Field {
+    pub fn from_ast(field: &syn::Field) -> Field {
+        use syn::NestedMeta::*;
+
+        let mut rename = None;
+
+        for meta_items in field.attrs.iter().filter_map(get_gluon_meta_items) {
+            for meta_item in meta_items {
+                match meta_item {
+                    // Parse `#[gluon(rename = "foo")]`
+                    Meta(NameValue(ref m)) if m.path.is_ident("rename") => {
+                        rename = Some(get_lit_str(&m.path, &m.path, &m.lit).unwrap().value())
+                    }
+
+                    Meta(meta_item) => {
+                        let path = meta_item
+                            .path()
+                            .into_token_stream()
+                            .to_string()
+                            .replace(' ', "");
+                        panic!("unexpected gluon field attribute: `{}`", path)
+                    }
+
+                    Lit(_) => {
+                        panic!("Unexpected literal in gluon field attribute",);
+                    }
+                }
+            }
+        }
+
+        Field { rename }
+    }
+
+    /// The name of a named field as seen from gluon
+    pub fn name(field: &syn::Field) -> String {
+        Field::from_ast(field).rename.unwrap_or_else(|| {
+            field
+                .ident
+                .as_ref()
+                .expect("Struct fields always have names")
+                .to_string()
+        })
+    }
+}
+
 fn get_lit_str<'a>(
     attr_name: &Path,
     _meta_item_name: &Path,
@@ -0,0 +1,104 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use proc_macro2::{Span, TokenStream};
+use syn::{self, LitStr};
+
+pub fn expand(input: TokenStream) -> TokenStream {
+    let pattern: LitStr = match syn::parse2(input) {
+        Ok(pattern) => pattern,
+        Err(err) => return err.to_compile_error(),
+    };
+    match embed(&pattern.value()) {
+        Ok(tokens) => tokens,
+        Err(msg) => syn::Error::new(pattern.span(), msg).to_compile_error(),
+    }
+}
+
+fn embed(pattern: &str) -> Result<TokenStream, String> {
+    let root = PathBuf::from(
+        env::var("CARGO_MANIFEST_DIR").map_err(|_| "CARGO_MANIFEST_DIR is not set".to_string())?,
+    );
+
+    // The directory which the module names are relative to is everything before the first
+    // component containing a wildcard
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let wildcard = components
+        .iter()
+        .position(|c| c.contains('*') || c.contains('?'))
+        .unwrap_or(components.len().saturating_sub(1));
+    let base = components[..wildcard]
+        .iter()
+        .fold(root, |path, component| path.join(component));
+    let pattern = &components[wildcard..];
+
+    let mut files = Vec::new();
+    collect_files(&base, &mut files)
+        .map_err(|err| format!("Unable to read `{}`: {}", base.display(), err))?;
+    files.sort();
+
+    let modules = files.iter().filter_map(|file| {
+        let relative = file.strip_prefix(&base).ok()?;
+        let relative: Vec<_> = relative
+            .iter()
+            .map(|component| component.to_string_lossy().into_owned())
+            .collect();
+        if !matches(pattern, &relative) {
+            return None;
+        }
+
+        let mut name = relative.join(".");
+        if name.ends_with(".glu") {
+            name.truncate(name.len() - ".glu".len());
+        }
+        let name = LitStr::new(&name, Span::call_site());
+        let path = LitStr::new(&file.to_string_lossy(), Span::call_site());
+        Some(quote! { (#name, include_str!(#path)) })
+    });
+
+    Ok(quote! {
+        ::gluon::import::EmbeddedModules::new(&[#(#modules),*])
+    })
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Matches the components of a path against a glob where `**` matches any number of directories
+fn matches(pattern: &[&str], path: &[String]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            matches(&pattern[1..], path) || (!path.is_empty() && matches(pattern, &path[1..]))
+        }
+        (Some(p), Some(component)) => {
+            matches_component(p.as_bytes(), component.as_bytes())
+                && matches(&pattern[1..], &path[1..])
+        }
+        _ => false,
+    }
+}
+
+fn matches_component(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_component(&pattern[1..], name)
+                || (!name.is_empty() && matches_component(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_component(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && matches_component(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
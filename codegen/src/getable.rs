@@ -57,7 +57,7 @@ where
             .ident
             .as_ref()
             .expect("Struct fields always have names");
-        let quoted_ident = format!("{}", quote! { #ident });
+        let quoted_ident = attr::Field::name(&field);
 
         quote! {
             #ident: if let Some(val) = data.lookup_field(vm, #quoted_ident) {
@@ -285,7 +285,7 @@ where
             .ident
             .as_ref()
             .expect("Struct fields always have names");
-        let quoted_field_ident = format!("{}", quote! { #field_ident });
+        let quoted_field_ident = attr::Field::name(field);
         quote! {
             #field_ident: if let Some(val) = inner_data.lookup_field(vm, #quoted_field_ident) {
                 <#field_ty as _gluon_api::Getable<'__vm, '__value>>::from_value(vm, val)
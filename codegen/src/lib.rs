@@ -78,6 +78,7 @@
 //! The gluon type must be registered before a binding using the mapped rust type is first loaded.
 //!
 //! If the rust type has type parameters, they have to implement `VmType` as well.
+//! Any bounds on the type parameters must also hold for their `VmType::Type`.
 //! All lifetimes have to be `'static`.
 //!
 //! __Note:__ Newtype structs are mapped to their inner type.
@@ -123,6 +124,26 @@
 //! # fn main() {}
 //! ```
 //!
+//! ### Field names
+//!
+//! Named fields are exposed to gluon under their rust name. The derives for `Getable`, `Pushable`
+//! and `VmType` all accept `#[gluon(rename = "<name>")]` on a field to use a different name.
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//!
+//! // will map to: `{ type_ : String, id : Int }`
+//! #[derive(Getable, Pushable, VmType)]
+//! struct Item {
+//!     #[gluon(rename = "type_")]
+//!     kind: String,
+//!     id: u32,
+//! }
+//! # fn main() {}
+//! ```
+//!
 //! ### Userdata
 //!
 //! Derives `Userdata` and the required `Trace` and `VmType` for a rust type.
@@ -149,6 +170,34 @@
 //! # fn main() {}
 //! ```
 //!
+//! ### Trace
+//!
+//! Derives `Trace` which lets the garbage collector find the gluon values stored in a rust type.
+//! Gluon values held by userdata (`OpaqueValue`, `OwnedFunction`, ...) are unrooted once the
+//! userdata is moved into the vm and are instead traced through the userdata, so a cycle between
+//! gluon values and userdata can be collected. Mutable fields must use `gluon::vm::gc::mutex::Mutex`
+//! or `gluon::vm::gc::rw_lock::RwLock`, which root their contents while they are locked for writing.
+//!
+//! Types which do not contain any gluon values can skip the traversal with `#[gluon_trace(skip)]`.
+//!
+//! #### Examples
+//!
+//! A registry of gluon callbacks which may refer back to the registry itself:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate gluon_codegen;
+//! extern crate gluon;
+//!
+//! use gluon::vm::{api::OwnedFunction, gc::mutex::Mutex};
+//!
+//! #[derive(Userdata, Trace, Debug)]
+//! struct Registry {
+//!     callbacks: Mutex<Vec<OwnedFunction<fn(()) -> ()>>>,
+//! }
+//! # fn main() {}
+//! ```
+//!
 
 #![recursion_limit = "128"]
 
@@ -157,6 +206,7 @@ extern crate quote;
 
 mod ast_clone;
 mod attr;
+mod embed;
 mod functor;
 mod getable;
 mod pushable;
@@ -206,3 +256,25 @@ pub fn functor(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 pub fn ast_clone(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     ast_clone::derive(input.into()).into()
 }
+
+/// Embeds every `.glu` file matching a glob, relative to the crate's `Cargo.toml`, into the
+/// binary. Expands to a `gluon::import::EmbeddedModules` which can be added to the import macro
+/// with `Import::add_resolver`.
+///
+/// Modules are named by their path relative to the part of the glob before the first wildcard,
+/// so `embed_modules!("scripts/**/*.glu")` embeds `scripts/ui/button.glu` as `ui.button`.
+/// Changes to the embedded files cause a rebuild but files which are added later are only picked
+/// up once the crate is rebuilt for some other reason.
+///
+/// ```rust,ignore
+/// let import = vm.get_macros().get("import");
+/// import
+///     .as_ref()
+///     .and_then(|import| import.downcast_ref::<Import>())
+///     .expect("Import macro")
+///     .add_resolver(embed_modules!("scripts/**/*.glu"));
+/// ```
+#[proc_macro]
+pub fn embed_modules(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    embed::expand(input.into()).into()
+}
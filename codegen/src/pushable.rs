@@ -8,7 +8,7 @@ use syn::{
 };
 
 use crate::{
-    attr::{Container, CrateName},
+    attr::{Container, CrateName, Field},
     shared::{map_type_params, split_for_impl},
 };
 
@@ -61,7 +61,8 @@ fn derive_struct(
         Fields::Unit => quote! {},
     };
 
-    let push_impl = gen_push_impl(None, &field_idents, &field_types);
+    let field_names = get_field_names(&ast.fields);
+    let push_impl = gen_push_impl(None, &field_idents, &field_names, &field_types);
 
     gen_impl(
         &container,
@@ -92,7 +93,8 @@ fn derive_enum(
 
         match &variant.fields {
             Fields::Named(_) => {
-                let push_impl = gen_push_impl(None, &field_idents, &field_types);
+                let field_names = get_field_names(&variant.fields);
+                let push_impl = gen_push_impl(None, &field_idents, &field_names, &field_types);
                 quote! {
                     #pattern => {
                         #push_impl
@@ -101,7 +103,7 @@ fn derive_enum(
                 }
             }
             _ => {
-                let push_impl = gen_push_impl(Some(tag), &field_idents, &field_types);
+                let push_impl = gen_push_impl(Some(tag), &field_idents, &[], &field_types);
                 quote! {
                     #pattern => {
                         #push_impl
@@ -175,6 +177,7 @@ fn gen_impl(
 fn gen_push_impl(
     tag: Option<usize>,
     field_idents: &[Cow<Ident>],
+    field_names: &[String],
     field_types: &[&Type],
 ) -> TokenStream {
     debug_assert!(field_idents.len() == field_types.len());
@@ -192,9 +195,8 @@ fn gen_push_impl(
             ctx.context().push_new_data(#tag as _gluon_types::VmTag, #fields_len)?
         },
         None => {
-            let field_ident_strs = field_idents.iter().map(|i| i.to_string());
             quote! { {
-                let field_names = [#(vm.global_env().intern(#field_ident_strs)?),*];
+                let field_names = [#(vm.global_env().intern(#field_names)?),*];
                 ctx.context().push_new_record(#fields_len, &field_names)?;
             } }
         }
@@ -215,6 +217,11 @@ fn create_pushable_bounds(generics: &Generics) -> Vec<TokenStream> {
     })
 }
 
+/// The names of the fields of a record as seen from gluon
+fn get_field_names(fields: &Fields) -> Vec<String> {
+    fields.iter().map(Field::name).collect()
+}
+
 fn get_info_from_fields(fields: &Fields) -> (Vec<Cow<Ident>>, Vec<&Type>) {
     // get all the fields if there are any
     let fields = match fields {
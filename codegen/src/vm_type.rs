@@ -1,8 +1,8 @@
 use proc_macro2::{Ident, Span, TokenStream};
-use syn::{self, Data, DeriveInput, Fields, GenericParam, Generics};
+use syn::{self, Data, DeriveInput, Fields, GenericParam, Generics, PredicateType, WherePredicate};
 
 use crate::{
-    attr::{Container, CrateName},
+    attr::{Container, CrateName, Field},
     shared::{map_type_params, split_for_impl},
 };
 
@@ -30,6 +30,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
     let trait_bounds = &map_type_params(&generics, |ty| {
         quote! { #ty: _gluon_api::VmType, #ty::Type: Sized }
     });
+    let associated_type_bounds = create_associated_type_bounds(&generics);
 
     let (impl_generics, ty_generics, where_clause) = split_for_impl(&generics, &[], &[]);
 
@@ -67,7 +68,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
             Data::Struct(ref struct_) => match struct_.fields {
                 Fields::Named(ref fields) => {
                     let fields = fields.named.iter().map(|field| {
-                        let ident = field.ident.as_ref().unwrap().to_string();
+                        let ident = Field::name(field);
                         let typ = &field.ty;
                         quote! {
                             _gluon_base::types::Field {
@@ -106,7 +107,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
                     match variant.fields {
                         Fields::Named(ref fields) => {
                             let fields = fields.named.iter().map(|field| {
-                                let ident = field.ident.as_ref().unwrap().to_string();
+                                let ident = Field::name(field);
                                 let typ = &field.ty;
                                 quote! {
                                     _gluon_base::types::Field {
@@ -207,7 +208,7 @@ fn gen_impl(container: &Container, ident: Ident, generics: Generics, data: &Data
             #[automatically_derived]
             #[allow(unused_attributes, unused_variables)]
             impl #impl_generics _gluon_api::VmType for #ident #ty_generics
-            #where_clause #(#trait_bounds,)*
+            #where_clause #(#trait_bounds,)* #(#associated_type_bounds,)*
             {
                 type Type = #ident<
                         #(#associated_type_generics),*
@@ -242,3 +243,38 @@ fn gen_type_application(generics: &Generics) -> TokenStream {
         }
     }
 }
+
+/// `Self::Type` uses the `Type` of each type parameter so any bounds on the parameters must also
+/// hold for their `Type`
+fn create_associated_type_bounds(generics: &Generics) -> Vec<TokenStream> {
+    let type_params = map_type_params(generics, |ty| ty.clone());
+
+    let inline_bounds = generics.type_params().filter_map(|param| {
+        let ident = &param.ident;
+        let bounds = &param.bounds;
+        if bounds.is_empty() {
+            None
+        } else {
+            Some(quote! { #ident::Type: #bounds })
+        }
+    });
+
+    let where_bounds = generics
+        .where_clause
+        .iter()
+        .flat_map(|clause| &clause.predicates)
+        .filter_map(|predicate| match predicate {
+            WherePredicate::Type(PredicateType {
+                bounded_ty: syn::Type::Path(path),
+                bounds,
+                ..
+            }) if path.qself.is_none()
+                && type_params.iter().any(|param| path.path.is_ident(param)) =>
+            {
+                Some(quote! { #path::Type: #bounds })
+            }
+            _ => None,
+        });
+
+    inline_bounds.chain(where_bounds).collect()
+}
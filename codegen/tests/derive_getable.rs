@@ -291,3 +291,71 @@ fn tuple_struct_derive() {
         panic!("{}", why);
     }
 }
+
+#[derive(Getable, Pushable, VmType, Debug, PartialEq)]
+enum Payload<T>
+where
+    T: Clone,
+{
+    Pair(T, String),
+    Named {
+        #[gluon(rename = "renamed")]
+        value: T,
+        count: i32,
+    },
+}
+
+fn swap_payload(payload: Payload<i32>) -> Payload<i32> {
+    match payload {
+        Payload::Pair(value, s) => Payload::Named {
+            value,
+            count: s.len() as i32,
+        },
+        Payload::Named { value, count } => Payload::Pair(value, count.to_string()),
+    }
+}
+
+#[test]
+fn generic_enum_with_payloads_and_renamed_fields() {
+    let _ = env_logger::try_init();
+
+    let vm = new_vm();
+
+    let src = r#"
+        type Payload a = | Pair a String | Named { renamed : a, count : Int }
+        { Payload }
+    "#;
+    vm.load_script("types", &src).unwrap();
+    import::add_extern_module(&vm, "functions", |vm| {
+        ExternModule::new(
+            vm,
+            record! {
+                swap_payload => primitive!(1, swap_payload),
+            },
+        )
+    });
+
+    let (value, _) = vm
+        .run_expr::<Payload<i32>>(
+            "test",
+            r#"
+            let { Payload } = import! types
+            let { swap_payload } = import! functions
+            swap_payload (Named { renamed = 1, count = 23 })
+        "#,
+        )
+        .unwrap_or_else(|why| panic!("{}", why));
+    assert_eq!(value, Payload::Pair(1, "23".to_string()));
+
+    let (value, _) = vm
+        .run_expr::<Payload<i32>>(
+            "test",
+            r#"
+            let { Payload } = import! types
+            let { swap_payload } = import! functions
+            swap_payload (Pair 2 "abc")
+        "#,
+        )
+        .unwrap_or_else(|why| panic!("{}", why));
+    assert_eq!(value, Payload::Named { value: 2, count: 3 });
+}
@@ -28,6 +28,24 @@ fn struct_() {
     );
 }
 
+#[derive(VmType)]
+#[allow(unused)]
+struct RenamedFields {
+    #[gluon(rename = "name")]
+    string: String,
+    number: u32,
+}
+
+#[test]
+fn renamed_fields() {
+    let vm = new_vm();
+
+    assert_eq!(
+        RenamedFields::make_type(&vm).to_string(),
+        "{ name : String, number : Int }"
+    );
+}
+
 #[derive(VmType, Serialize, Deserialize)]
 #[allow(unused)]
 enum Enum {
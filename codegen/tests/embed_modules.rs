@@ -0,0 +1,33 @@
+#[macro_use]
+extern crate gluon_codegen;
+extern crate gluon;
+
+mod init;
+
+use gluon::{
+    import::{Import, ImportResolver},
+    ThreadExt,
+};
+
+use init::new_vm;
+
+#[test]
+fn embed_modules_matching_a_glob() {
+    let modules = embed_modules!("tests/embed/**/*.glu");
+    let mut names = modules.modules();
+    names.sort();
+    assert_eq!(names, ["greeting", "nested.name"]);
+
+    let vm = new_vm();
+    let import = vm.get_macros().get("import");
+    import
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .expect("Import macro")
+        .add_resolver(modules);
+
+    let (result, _) = vm
+        .run_expr::<String>("test", "import! greeting")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "Hello world");
+}
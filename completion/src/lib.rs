@@ -2,17 +2,27 @@
 #![doc(html_root_url = "https://docs.rs/gluon_completion/0.17.1")] // # GLUON
 
 extern crate gluon_base as base;
-
-use std::{borrow::Cow, cmp::Ordering, iter::once, path::PathBuf, sync::Arc};
+extern crate gluon_parser as parser;
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt,
+    iter::once,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use codespan::ByteOffset;
 
 use either::Either;
 
+use crate::parser::infix::OpMeta;
+
 use crate::base::{
     ast::{
         self, walk_expr, walk_pattern, AstType, Expr, Pattern, PatternField, SpannedExpr,
-        SpannedIdent, SpannedPattern, Typed, TypedIdent, Visitor,
+        SpannedIdent, SpannedPattern, Typed, TypedIdent, ValueBinding, Visitor,
     },
     filename_to_module,
     fnv::{FnvMap, FnvSet},
@@ -21,10 +31,11 @@ use crate::base::{
     pos::{self, BytePos, HasSpan, Span, Spanned},
     resolve,
     scoped_map::ScopedMap,
+    source::Source,
     symbol::{Name, Symbol, SymbolRef},
     types::{
-        walk_type_, AliasData, ArcType, ControlVisitation, Generic, NullInterner, Type, TypeEnv,
-        TypeExt,
+        self, walk_type_, AliasData, ArcType, ArgType, ControlVisitation, Generic, NullInterner,
+        Type, TypeEnv, TypeExt,
     },
 };
 
@@ -104,6 +115,39 @@ where
 pub struct Suggestion {
     pub name: String,
     pub typ: Either<ArcKind, ArcType>,
+    pub score: Score,
+}
+
+/// How well a suggestion fits the position it was suggested at. Scores compare field by field, so
+/// a suggestion of the expected type is preferred over one with a more similar name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    pub type_match: TypeMatch,
+    /// How well the name matches the text before the cursor, as returned by `fuzzy_match`
+    pub name: u32,
+    /// Higher for values that are bound closer to the cursor. Fields and imported modules, which
+    /// are not bound in the expression, have `0`.
+    pub locality: u32,
+}
+
+/// How the type of a suggestion relates to the type expected at the position it is suggested at,
+/// such as the type of the parameter that a function argument is passed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TypeMatch {
+    /// The suggestion has some other type than the expected type
+    Mismatch,
+    /// No type is expected at the position, or the suggestion is a type
+    Unknown,
+    /// The suggestion is a function which returns the expected type
+    Returns,
+    /// The suggestion has the expected type
+    Exact,
+}
+
+impl Default for TypeMatch {
+    fn default() -> Self {
+        TypeMatch::Unknown
+    }
 }
 
 struct Suggest<E> {
@@ -111,6 +155,9 @@ struct Suggest<E> {
     stack: ScopedMap<Symbol, ArcType>,
     type_stack: ScopedMap<Symbol, ArcKind>,
     patterns: ScopedMap<Symbol, ArcType>,
+    /// The order that the values in `stack` were bound in, starting from `1`
+    order: FnvMap<Symbol, u32>,
+    bindings: u32,
 }
 
 impl<E> Suggest<E>
@@ -123,8 +170,16 @@ where
             stack: ScopedMap::new(),
             type_stack: ScopedMap::new(),
             patterns: ScopedMap::new(),
+            order: FnvMap::default(),
+            bindings: 0,
         }
     }
+
+    fn insert_value(&mut self, name: Symbol, typ: ArcType) {
+        self.bindings += 1;
+        self.order.insert(name.clone(), self.bindings);
+        self.stack.insert(name, typ);
+    }
 }
 
 impl<E> OnFound for Suggest<E>
@@ -132,7 +187,7 @@ where
     E: TypeEnv<Type = ArcType>,
 {
     fn on_ident(&mut self, ident: &TypedIdent) {
-        self.stack.insert(ident.name.clone(), ident.typ.clone());
+        self.insert_value(ident.name.clone(), ident.typ.clone());
     }
 
     fn on_type_ident(&mut self, gen: &Generic<Symbol>) {
@@ -142,14 +197,14 @@ where
     fn on_pattern(&mut self, pattern: &SpannedPattern<Symbol>) {
         match &pattern.value {
             Pattern::As(id, pat) => {
-                self.stack.insert(
+                self.insert_value(
                     id.value.clone(),
                     pat.try_type_of(&self.env).unwrap_or_else(|_| Type::hole()),
                 );
                 self.on_pattern(pat);
             }
             Pattern::Ident(id) => {
-                self.stack.insert(id.name.clone(), id.typ.clone());
+                self.insert_value(id.name.clone(), id.typ.clone());
             }
             Pattern::Record { typ, fields, .. } => {
                 let unaliased = resolve::remove_aliases(&self.env, &mut NullInterner, typ.clone());
@@ -174,7 +229,7 @@ where
                                     // If we did not find a matching field in the type, default to a
                                     // type hole so that the user at least gets completion on the name
                                     .unwrap_or_else(Type::hole);
-                                self.stack.insert(name, typ);
+                                self.insert_value(name, typ);
                             }
                         },
                     }
@@ -194,7 +249,7 @@ where
         let aliased_type = alias.unresolved_type().remove_forall();
         if let Type::Variant(ref row) = **aliased_type {
             for field in row.row_iter().cloned() {
-                self.stack.insert(field.name.clone(), field.typ.clone());
+                self.insert_value(field.name.clone(), field.typ.clone());
                 self.patterns.insert(field.name, field.typ);
             }
         }
@@ -692,9 +747,16 @@ where
             }
             Expr::MacroExpansion {
                 ref replacement, ..
-            } => self.visit_expr(replacement),
+            } => match replacement.value {
+                // A macro which failed to expand, such as when importing a module whose path is
+                // still being written, has nothing to complete from
+                Expr::Error(..) => (),
+                _ => self.visit_expr(replacement),
+            },
             Expr::Annotated(..) => unimplemented!(), // FIXME
-            Expr::Error(..) => (),
+            // The parser could not recover the expression here, but anything in scope may still be
+            // valid inside of it
+            Expr::Error(..) => self.found = MatchState::Empty,
         }
     }
 
@@ -972,6 +1034,418 @@ pub fn symbol<'a, 'ast>(
     completion(extract, source_span, expr, pos)
 }
 
+/// Where the name at a position was defined
+#[derive(Debug, PartialEq)]
+pub enum Definition<'a> {
+    /// The name is bound in the searched expression itself
+    Local(Span<BytePos>),
+    /// The name was imported from another module. `find_definition` locates it once the module
+    /// which defines it has been found.
+    Imported(&'a Symbol),
+}
+
+/// Finds the definition of the name at `pos`, following the names which were brought into scope
+/// by `import!` back to the binding in the imported module
+pub fn definition<'a, 'ast>(
+    env: &'a FnvMap<Symbol, Arc<Metadata>>,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<'ast, Symbol>,
+    pos: BytePos,
+) -> Option<Definition<'a>> {
+    let symbol = self::symbol(source_span, expr, pos).ok()?;
+    let definition = get_metadata(env, source_span, expr, pos)
+        .or_else(|| env.get(symbol).map(|metadata| &**metadata))
+        .and_then(|metadata| metadata.definition.as_ref());
+    match definition {
+        Some(definition) => Some(match find_definition(source_span, expr, definition) {
+            Some(span) => Definition::Local(span),
+            None => Definition::Imported(definition),
+        }),
+        None => find_definition(source_span, expr, symbol).map(Definition::Local),
+    }
+}
+
+/// Returns the span of the binding or type named `definition` in `expr`
+pub fn find_definition<'ast>(
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<'ast, Symbol>,
+    definition: &SymbolRef,
+) -> Option<Span<BytePos>> {
+    fn find_symbol(
+        symbols: &[SpCompletionSymbol],
+        definition: &SymbolRef,
+    ) -> Option<Span<BytePos>> {
+        symbols.iter().find_map(|symbol| {
+            if **symbol.value.name == *definition {
+                Some(symbol.span)
+            } else {
+                find_symbol(&symbol.value.children, definition)
+            }
+        })
+    }
+    find_symbol(&all_symbols(source_span, expr), definition)
+}
+
+#[derive(Clone, Debug)]
+struct Occurrence {
+    symbol: Symbol,
+    span: Span<BytePos>,
+    binding: bool,
+}
+
+/// The occurrences of the values in a module, used to find the references to a binding and to
+/// rename it.
+///
+/// Names which are bound by a record pattern without a new name, as in
+/// `let { map } = import! std.list`, and the fields of projections such as `list.map` refer to
+/// the binding they were imported from, so the references to a binding can be collected from the
+/// indexes of every module that imports it.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolIndex {
+    occurrences: Vec<Occurrence>,
+    /// Maps the names bound by record patterns to the binding they were imported from
+    imported: FnvMap<Symbol, Symbol>,
+    /// The bindings which are passed as implicit arguments. The typechecker inserts these so they
+    /// do not have a span in the source.
+    implicit_uses: FnvSet<Symbol>,
+}
+
+impl SymbolIndex {
+    pub fn new<'ast>(
+        env: &FnvMap<Symbol, Arc<Metadata>>,
+        source_span: Span<BytePos>,
+        expr: &SpannedExpr<'ast, Symbol>,
+    ) -> SymbolIndex {
+        struct Indexer<'e> {
+            env: &'e FnvMap<Symbol, Arc<Metadata>>,
+            source_span: Span<BytePos>,
+            index: SymbolIndex,
+        }
+
+        impl Indexer<'_> {
+            fn push(&mut self, symbol: &Symbol, span: Span<BytePos>, binding: bool) {
+                // Expressions generated by macros may not have a span in the source
+                if self.source_span.contains(span) && span.start() != span.end() {
+                    self.index.occurrences.push(Occurrence {
+                        symbol: symbol.clone(),
+                        span,
+                        binding,
+                    });
+                }
+            }
+
+            /// Returns the binding that `field` of the module bound to `module` was imported from
+            fn imported_field(&self, module: &Symbol, field: &str) -> Option<Symbol> {
+                let definition = self
+                    .env
+                    .get(module)?
+                    .module
+                    .get(field)?
+                    .definition
+                    .clone()?;
+                // The field may be bound under another name, in which case only the binding
+                // itself is renamed
+                if definition.declared_name() == field {
+                    Some(definition)
+                } else {
+                    None
+                }
+            }
+
+            fn visit_implicit_args(&mut self, implicit_args: &[SpannedExpr<Symbol>]) {
+                struct ImplicitUses<'s>(&'s mut FnvSet<Symbol>);
+
+                impl<'a, 'ast> Visitor<'a, 'ast> for ImplicitUses<'_> {
+                    type Ident = Symbol;
+
+                    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+                        if let Expr::Ident(ref id) = e.value {
+                            self.0.insert(id.name.clone());
+                        }
+                        walk_expr(self, e);
+                    }
+                }
+
+                for arg in implicit_args {
+                    ImplicitUses(&mut self.index.implicit_uses).visit_expr(arg);
+                }
+            }
+
+            fn visit_pattern(&mut self, pattern: &SpannedPattern<Symbol>) {
+                match pattern.value {
+                    Pattern::Ident(ref id) => self.push(&id.name, pattern.span, true),
+                    Pattern::As(ref id, ref pattern) => {
+                        self.push(&id.value, id.span, true);
+                        self.visit_pattern(pattern);
+                    }
+                    Pattern::Constructor(_, ref args) => {
+                        for arg in &**args {
+                            self.visit_pattern(arg);
+                        }
+                    }
+                    Pattern::Tuple { ref elems, .. } => {
+                        for elem in &**elems {
+                            self.visit_pattern(elem);
+                        }
+                    }
+                    Pattern::Record { ref fields, .. } => {
+                        for field in &**fields {
+                            match field {
+                                PatternField::Value { name, value: None } => {
+                                    self.push(&name.value, name.span, true);
+                                    let definition = self
+                                        .env
+                                        .get(&name.value)
+                                        .and_then(|metadata| metadata.definition.as_ref())
+                                        .filter(|definition| {
+                                            **definition != name.value
+                                                && definition.declared_name()
+                                                    == name.value.declared_name()
+                                        });
+                                    if let Some(definition) = definition {
+                                        self.index
+                                            .imported
+                                            .insert(name.value.clone(), definition.clone());
+                                    }
+                                }
+                                PatternField::Value {
+                                    value: Some(value), ..
+                                } => self.visit_pattern(value),
+                                PatternField::Type { .. } => (),
+                            }
+                        }
+                    }
+                    Pattern::Literal(_) | Pattern::Error => (),
+                }
+            }
+        }
+
+        impl<'a, 'ast> Visitor<'a, 'ast> for Indexer<'_> {
+            type Ident = Symbol;
+
+            fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+                match e.value {
+                    Expr::Ident(ref id) => self.push(&id.name, e.span, false),
+                    Expr::App {
+                        ref func,
+                        ref implicit_args,
+                        ref args,
+                    } => {
+                        self.visit_expr(func);
+                        self.visit_implicit_args(implicit_args);
+                        for arg in &**args {
+                            self.visit_expr(arg);
+                        }
+                    }
+                    Expr::Infix {
+                        ref lhs,
+                        ref op,
+                        ref rhs,
+                        ref implicit_args,
+                    } => {
+                        self.visit_expr(lhs);
+                        self.push(&op.value.name, op.span, false);
+                        self.visit_implicit_args(implicit_args);
+                        self.visit_expr(rhs);
+                    }
+                    Expr::Projection(ref expr, ref field, _) => {
+                        if let Expr::Ident(ref module) = expr.value {
+                            let field_name = field.declared_name();
+                            if let Some(definition) = self.imported_field(&module.name, field_name)
+                            {
+                                let end = e.span.end();
+                                let start = end - ByteOffset::from(field_name.len() as i64);
+                                self.push(&definition, Span::new(start, end), false);
+                            }
+                        }
+                        self.visit_expr(expr);
+                    }
+                    Expr::Record {
+                        ref exprs,
+                        ref base,
+                        ..
+                    } => {
+                        for field in &**exprs {
+                            match field.value {
+                                None => self.push(&field.name.value, field.name.span, false),
+                                Some(ref value) => {
+                                    // `{ map = map }` exports the binding under its own name so
+                                    // the field is renamed along with it
+                                    if let Expr::Ident(ref id) = value.value {
+                                        if id.name.declared_name()
+                                            == field.name.value.declared_name()
+                                        {
+                                            self.push(&id.name, field.name.span, false);
+                                        }
+                                    }
+                                    self.visit_expr(value);
+                                }
+                            }
+                        }
+                        if let Some(ref base) = *base {
+                            self.visit_expr(base);
+                        }
+                    }
+                    Expr::LetBindings(ref binds, ref body) => {
+                        for bind in binds {
+                            self.visit_pattern(&bind.name);
+                            for arg in &*bind.args {
+                                self.push(&arg.name.value.name, arg.name.span, true);
+                            }
+                            self.visit_expr(&bind.expr);
+                        }
+                        self.visit_expr(body);
+                    }
+                    Expr::Lambda(ref lambda) => {
+                        for arg in &*lambda.args {
+                            self.push(&arg.name.value.name, arg.name.span, true);
+                        }
+                        self.visit_expr(&lambda.body);
+                    }
+                    Expr::Match(ref expr, ref alts) => {
+                        self.visit_expr(expr);
+                        for alt in &**alts {
+                            self.visit_pattern(&alt.pattern);
+                            self.visit_expr(&alt.expr);
+                        }
+                    }
+                    Expr::Do(ref do_expr) => {
+                        if let Some(ref id) = do_expr.id {
+                            self.visit_pattern(id);
+                        }
+                        self.visit_expr(&do_expr.bound);
+                        self.visit_expr(&do_expr.body);
+                    }
+                    _ => walk_expr(self, e),
+                }
+            }
+        }
+
+        let mut indexer = Indexer {
+            env,
+            source_span,
+            index: SymbolIndex::default(),
+        };
+        indexer.visit_expr(expr);
+        indexer.index
+    }
+
+    /// Returns the binding that `symbol` refers to, which is `symbol` itself unless it was
+    /// imported by a record pattern
+    fn resolve<'s>(&'s self, symbol: &'s Symbol) -> &'s Symbol {
+        self.imported.get(symbol).unwrap_or(symbol)
+    }
+
+    /// Returns the binding that the name at `pos` refers to
+    pub fn definition_at(&self, pos: BytePos) -> Option<&Symbol> {
+        self.occurrences
+            .iter()
+            .find(|occurrence| occurrence.span.contains_pos(pos))
+            .map(|occurrence| self.resolve(&occurrence.symbol))
+    }
+
+    /// Returns the spans of every name in the module which refers to `definition`, the binding
+    /// itself included
+    pub fn references(&self, definition: &SymbolRef) -> Vec<Span<BytePos>> {
+        self.occurrences
+            .iter()
+            .filter(|occurrence| **self.resolve(&occurrence.symbol) == *definition)
+            .map(|occurrence| occurrence.span)
+            .collect()
+    }
+
+    /// Returns `true` if `definition` is referred to anywhere in the module, other than by the
+    /// binding itself
+    pub fn is_used(&self, definition: &SymbolRef) -> bool {
+        self.implicit_uses.iter().any(|used| **used == *definition)
+            || self.occurrences.iter().any(|occurrence| {
+                !occurrence.binding && **self.resolve(&occurrence.symbol) == *definition
+            })
+    }
+
+    /// Returns the span of a binding named `name`, other than `definition`
+    fn binding_named(&self, name: &str, definition: &SymbolRef) -> Option<Span<BytePos>> {
+        self.occurrences
+            .iter()
+            .find(|occurrence| {
+                occurrence.binding
+                    && occurrence.symbol.declared_name() == name
+                    && **self.resolve(&occurrence.symbol) != *definition
+            })
+            .map(|occurrence| occurrence.span)
+    }
+}
+
+/// The reasons that a binding can't be renamed
+#[derive(Debug, PartialEq)]
+pub enum RenameError<K> {
+    /// The new name is not a valid name for the binding, or the binding is an operator
+    InvalidName(String),
+    /// Another binding with the new name is already in `module`, at `span`
+    Conflict { module: K, span: Span<BytePos> },
+}
+
+impl<K> fmt::Display for RenameError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenameError::InvalidName(name) => write!(f, "`{}` is not a valid name", name),
+            RenameError::Conflict { .. } => write!(f, "The new name is already bound"),
+        }
+    }
+}
+
+fn is_keyword(name: &str) -> bool {
+    match name {
+        "do" | "else" | "forall" | "if" | "in" | "let" | "match" | "rec" | "seq" | "then"
+        | "type" | "with" => true,
+        _ => false,
+    }
+}
+
+/// Returns the spans in each module which must be replaced by `new_name` to rename `definition`.
+/// `indexes` should contain every module that may refer to `definition`.
+///
+/// Renaming fails instead of changing the meaning of the program if a module already binds a
+/// value named `new_name`.
+pub fn rename<K>(
+    indexes: &[(K, &SymbolIndex)],
+    definition: &SymbolRef,
+    new_name: &str,
+) -> Result<Vec<(K, Vec<Span<BytePos>>)>, RenameError<K>>
+where
+    K: Clone,
+{
+    let is_identifier = |name: &str| {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && !is_keyword(name)
+    };
+    let old_name = definition.declared_name();
+    let uppercase = |name: &str| name.starts_with(char::is_uppercase);
+    if !is_identifier(old_name)
+        || !is_identifier(new_name)
+        || uppercase(old_name) != uppercase(new_name)
+    {
+        return Err(RenameError::InvalidName(new_name.to_string()));
+    }
+
+    let mut edits = Vec::new();
+    for (module, index) in indexes {
+        let references = index.references(definition);
+        if references.is_empty() {
+            continue;
+        }
+        if let Some(span) = index.binding_named(new_name, definition) {
+            return Err(RenameError::Conflict {
+                module: module.clone(),
+                span,
+            });
+        }
+        edits.push((module.clone(), references));
+    }
+    Ok(edits)
+}
+
 pub type SpCompletionSymbol<'a, 'ast> = Spanned<CompletionSymbol<'a, 'ast>, BytePos>;
 
 #[derive(Debug, PartialEq)]
@@ -1170,6 +1644,390 @@ pub fn all_symbols<'a, 'ast>(
     result
 }
 
+/// The kinds of the symbols returned by `document_symbols`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Type,
+    Field,
+    Constructor,
+}
+
+/// A binding in a module as shown in an outline of the module, with the bindings declared inside
+/// it as its children
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The span of the whole binding, from its name to the end of its expression or type
+    pub span: Span<BytePos>,
+    /// The span of the name of the binding
+    pub name_span: Span<BytePos>,
+    pub children: Vec<DocumentSymbol>,
+}
+
+/// Returns the `let` and `type` bindings in `expr`, nested in the bindings that they are declared
+/// in. The fields and constructors of types are the children of the type. Function parameters are
+/// left out.
+pub fn document_symbols<'ast>(
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<'ast, Symbol>,
+) -> Vec<DocumentSymbol> {
+    fn convert(symbols: Vec<SpCompletionSymbol>, in_type: bool) -> Vec<DocumentSymbol> {
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let name = symbol.value.name.declared_name().to_string();
+                let (kind, end) =
+                    match symbol.value.content {
+                        CompletionSymbolContent::Value {
+                            kind: CompletionValueKind::Parameter,
+                            ..
+                        } => return None,
+                        CompletionSymbolContent::Value {
+                            kind: CompletionValueKind::Binding,
+                            expr,
+                            ..
+                        } => {
+                            let is_function = symbol.value.children.iter().any(|child| match child
+                                .value
+                                .content
+                            {
+                                CompletionSymbolContent::Value {
+                                    kind: CompletionValueKind::Parameter,
+                                    ..
+                                } => true,
+                                _ => false,
+                            }) || expr.map_or(false, |expr| match expr.value {
+                                Expr::Lambda(_) => true,
+                                _ => false,
+                            });
+                            let kind = if is_function {
+                                SymbolKind::Function
+                            } else {
+                                SymbolKind::Variable
+                            };
+                            (kind, expr.map(|expr| expr.span.end()))
+                        }
+                        CompletionSymbolContent::Type { typ } => {
+                            let kind = if !in_type {
+                                SymbolKind::Type
+                            } else if name.starts_with(char::is_uppercase) {
+                                SymbolKind::Constructor
+                            } else {
+                                SymbolKind::Field
+                            };
+                            (kind, Some(typ.span().end()))
+                        }
+                    };
+                let name_span = symbol.span;
+                let span = match end {
+                    Some(end) if end > name_span.end() => name_span.with_end(end),
+                    _ => name_span,
+                };
+                Some(DocumentSymbol {
+                    name,
+                    kind,
+                    span,
+                    name_span,
+                    children: convert(symbol.value.children, kind == SymbolKind::Type),
+                })
+            })
+            .collect()
+    }
+
+    convert(all_symbols(source_span, expr), false)
+}
+
+/// A symbol found by `workspace_symbols`
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceSymbol {
+    pub module: String,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The span of the name of the symbol in `module`
+    pub span: Span<BytePos>,
+    /// The name of the type that the field or constructor belongs to
+    pub container: Option<String>,
+}
+
+/// Returns how well `query` matches `name`, or `None` if the characters of `query` do not appear
+/// in `name` in the same order. Characters are compared case insensitively and characters which
+/// start a word in `name`, or follow the previous match, give a higher score.
+pub fn fuzzy_match(query: &str, name: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+    for c in name.chars() {
+        let next = match query.peek() {
+            Some(&next) => next,
+            None => break,
+        };
+        let matched = c.to_lowercase().eq(once(next));
+        if matched {
+            query.next();
+            score += 1;
+            let word_start = match previous {
+                None => true,
+                Some(previous) => {
+                    !previous.is_alphanumeric() || (previous.is_lowercase() && c.is_uppercase())
+                }
+            };
+            if word_start {
+                score += 3;
+            } else if previous_matched {
+                score += 2;
+            }
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+    if query.peek().is_none() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Searches the module level bindings of each module, along with the fields and constructors of
+/// their types, for symbols matching `query`. The best matches are returned first.
+pub fn workspace_symbols<'s, I>(modules: I, query: &str) -> Vec<WorkspaceSymbol>
+where
+    I: IntoIterator<Item = (&'s str, &'s [DocumentSymbol])>,
+{
+    fn search(
+        module: &str,
+        symbols: &[DocumentSymbol],
+        container: Option<&str>,
+        query: &str,
+        result: &mut Vec<(u32, WorkspaceSymbol)>,
+    ) {
+        for symbol in symbols {
+            if let Some(score) = fuzzy_match(query, &symbol.name) {
+                result.push((
+                    score,
+                    WorkspaceSymbol {
+                        module: module.to_string(),
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        span: symbol.name_span,
+                        container: container.map(|container| container.to_string()),
+                    },
+                ));
+            }
+            // The children of values are local to the value
+            if symbol.kind == SymbolKind::Type {
+                search(module, &symbol.children, Some(&symbol.name), query, result);
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for (module, symbols) in modules {
+        search(module, symbols, None, query, &mut result);
+    }
+    result.sort_by(|(l_score, l), (r_score, r)| {
+        r_score
+            .cmp(l_score)
+            .then_with(|| l.name.len().cmp(&r.name.len()))
+            .then_with(|| l.name.cmp(&r.name))
+            .then_with(|| l.module.cmp(&r.module))
+    });
+    result.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+/// Returns the part of the name at the cursor which suggestions are completing
+fn completion_prefix<'a>(found: &Found<'a, '_>) -> &'a str {
+    let name = match found.match_ {
+        Some(Match::Expr(&Spanned {
+            value: Expr::Ident(ref id),
+            ..
+        })) => {
+            if id.name.is_global() {
+                // Modules are suggested by the next part of their path
+                let path = id.name.definition_name();
+                return &path[path.rfind('.').map_or(0, |i| i + 1)..];
+            }
+            &id.name
+        }
+        Some(Match::Pattern(&Spanned {
+            value: Pattern::Constructor(ref id, _),
+            ..
+        }))
+        | Some(Match::Pattern(&Spanned {
+            value: Pattern::Ident(ref id),
+            ..
+        })) => &id.name,
+        Some(Match::Ident(_, id, _)) => id,
+        Some(Match::Type(_, id, _)) => return id.declared_name(),
+        _ => return "",
+    };
+    name.declared_name()
+}
+
+/// Returns the type of the value expected at the cursor, if it is an argument or an operand whose
+/// parameter type is known, or if the typechecker could infer the type of an unfinished name from
+/// how it is used
+fn expected_type<T>(env: &T, found: &Found) -> Option<ArcType>
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    let is_known = |typ: &ArcType| match **typ {
+        Type::Hole | Type::Error | Type::Variable(_) | Type::Generic(_) => false,
+        _ => true,
+    };
+    let nth_parameter = |typ: &ArcType, n: usize| {
+        let mut typ = typ.remove_forall_and_implicit_args();
+        for _ in 0..n {
+            typ = typ
+                .as_explicit_function()?
+                .1
+                .remove_forall_and_implicit_args();
+        }
+        typ.as_explicit_function().map(|(arg, _)| arg.clone())
+    };
+
+    let expr = match found.match_ {
+        Some(Match::Expr(expr)) => expr,
+        Some(Match::Ident(_, _, ref typ)) => return Some(typ.clone()).filter(is_known),
+        _ => return None,
+    };
+    // The enclosing matches can end with the match itself
+    let parent = found
+        .enclosing_matches
+        .iter()
+        .rev()
+        .find(|parent| parent.span() != expr.span);
+    let parameter = match parent {
+        Some(&Match::Expr(&Spanned {
+            value: Expr::App {
+                ref func, ref args, ..
+            },
+            ..
+        })) => args
+            .iter()
+            .position(|arg| arg.span == expr.span)
+            .and_then(|i| nth_parameter(&func.try_type_of(env).ok()?, i)),
+        Some(&Match::Expr(&Spanned {
+            value:
+                Expr::Infix {
+                    ref lhs,
+                    ref op,
+                    ref rhs,
+                    ..
+                },
+            ..
+        })) => {
+            if lhs.span == expr.span {
+                nth_parameter(&op.value.typ, 0)
+            } else if rhs.span == expr.span {
+                nth_parameter(&op.value.typ, 1)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    parameter
+        .or_else(|| expr.try_type_of(env).ok())
+        .filter(is_known)
+}
+
+/// Returns `true` if a value of type `actual` can be used where `expected` is expected. Type
+/// variables and generic types match any type as they could be instantiated to it.
+fn types_match<T>(env: &T, expected: &ArcType, actual: &ArcType) -> bool
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    let expected = expected.remove_forall_and_implicit_args();
+    let actual = actual.remove_forall_and_implicit_args();
+    match (&**expected, &**actual) {
+        (Type::Hole, _)
+        | (_, Type::Hole)
+        | (Type::Variable(_), _)
+        | (_, Type::Variable(_))
+        | (Type::Generic(_), _)
+        | (_, Type::Generic(_)) => true,
+        (Type::Function(l_type, l_arg, l_ret), Type::Function(r_type, r_arg, r_ret)) => {
+            l_type == r_type && types_match(env, l_arg, r_arg) && types_match(env, l_ret, r_ret)
+        }
+        (Type::App(l, l_args), Type::App(r, r_args)) if l_args.len() == r_args.len() => {
+            types_match(env, l, r)
+                && l_args
+                    .iter()
+                    .zip(r_args.iter())
+                    .all(|(l, r)| types_match(env, l, r))
+        }
+        _ if expected == actual => true,
+        _ => {
+            let unaliased_expected = resolve::remove_aliases_cow(env, &mut NullInterner, expected);
+            let unaliased_actual = resolve::remove_aliases_cow(env, &mut NullInterner, actual);
+            match (&unaliased_expected, &unaliased_actual) {
+                (Cow::Borrowed(_), Cow::Borrowed(_)) => false,
+                _ => types_match(env, &unaliased_expected, &unaliased_actual),
+            }
+        }
+    }
+}
+
+fn type_match<T>(env: &T, expected: &ArcType, actual: &ArcType) -> TypeMatch
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    if types_match(env, expected, actual) {
+        return TypeMatch::Exact;
+    }
+    let mut typ = actual.remove_forall_and_implicit_args();
+    while let Some((_, ret)) = typ.as_explicit_function() {
+        if types_match(env, expected, ret) {
+            return TypeMatch::Returns;
+        }
+        typ = ret.remove_forall_and_implicit_args();
+    }
+    TypeMatch::Mismatch
+}
+
+/// Returns the text of `source` up to `pos`, with every bracket which is still open at `pos`
+/// closed.
+///
+/// While an expression is being written the source often fails to parse at all, as in
+/// `f (record.`, which leaves nothing to complete from. Parsing and typechecking the repaired
+/// source instead gives an expression where the names before `pos` are recovered, and where
+/// positions up to `pos` are the same as in `source`. Returns `None` if the source could not be
+/// tokenized up to `pos`, which happens inside unterminated string literals.
+pub fn repair_source(source: &dyn Source, pos: BytePos) -> Option<String> {
+    use crate::parser::{Token, Tokenizer};
+
+    let offset = (pos - source.span().start()).to_usize();
+    let prefix = source.src().get(..offset)?;
+    let mut closers = Vec::new();
+    let mut tokenizer = Tokenizer::new(prefix);
+    for token in &mut tokenizer {
+        match token.ok()?.value {
+            Token::LParen => closers.push(')'),
+            Token::LBracket | Token::AttributeOpen => closers.push(']'),
+            Token::LBrace => closers.push('}'),
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                closers.pop();
+            }
+            // The tokenizer keeps returning `EOF` at the end of the input
+            Token::EOF => break,
+            _ => (),
+        }
+    }
+    if tokenizer.errors.has_errors() {
+        return None;
+    }
+
+    let mut repaired = prefix.to_string();
+    repaired.extend(closers.into_iter().rev());
+    Some(repaired)
+}
+
+/// Returns the names which can be written at `pos`, best matches first. See `Score` for how they
+/// are ranked.
 pub fn suggest<'ast, T>(
     env: &T,
     source_span: Span<BytePos>,
@@ -1185,6 +2043,8 @@ where
 pub struct SuggestionQuery {
     pub paths: Vec<PathBuf>,
     pub modules: Vec<Cow<'static, str>>,
+    /// Only suggest names which contain the characters of the name being completed, in order, as
+    /// checked by `fuzzy_match`
     pub prefix_filter: bool,
     pub span: Option<Span<BytePos>>,
 }
@@ -1206,7 +2066,7 @@ impl SuggestionQuery {
     }
 
     fn filter(&self, name: &str, prefix: &str) -> bool {
-        !self.prefix_filter || name.starts_with(prefix)
+        !self.prefix_filter || fuzzy_match(prefix, name).is_some()
     }
 
     fn suggest_fields_of_type(
@@ -1234,6 +2094,7 @@ impl SuggestionQuery {
             .map(|field| Suggestion {
                 name: field.name.declared_name().into(),
                 typ: Either::Right(field.typ.clone()),
+                score: Score::default(),
             });
         let types = typ
             .type_field_iter()
@@ -1241,6 +2102,7 @@ impl SuggestionQuery {
             .map(|field| Suggestion {
                 name: field.name.declared_name().into(),
                 typ: Either::Right(field.typ.clone().into_type()),
+                score: Score::default(),
             });
         result.extend(fields.chain(types));
     }
@@ -1281,7 +2143,7 @@ impl SuggestionQuery {
 
         let enclosing_match = found.enclosing_matches.last().unwrap();
         match found.match_ {
-            Some(match_) => match match_ {
+            Some(ref match_) => match *match_ {
                 Match::Expr(expr) => match expr.value {
                     Expr::Ident(ref id) if id.name.is_global() => {
                         let name = id.name.definition_name();
@@ -1318,6 +2180,7 @@ impl SuggestionQuery {
                             .map(|(name, typ)| Suggestion {
                                 name: name.declared_name().into(),
                                 typ: Either::Right(typ.clone()),
+                                score: Score::default(),
                             }),
                     );
                 }
@@ -1335,6 +2198,7 @@ impl SuggestionQuery {
                                 result.extend(iter.map(|(name, typ)| Suggestion {
                                     name: name.declared_name().into(),
                                     typ: Either::Right(typ),
+                                    score: Score::default(),
                                 }));
                             }
                         }
@@ -1416,10 +2280,30 @@ impl SuggestionQuery {
                     _ => result.extend(suggest.patterns.iter().map(|(name, typ)| Suggestion {
                         name: name.declared_name().into(),
                         typ: Either::Right(typ.clone()),
+                        score: Score::default(),
                     })),
                 },
             },
         }
+
+        let prefix = completion_prefix(&found);
+        let expected = expected_type(env, &found);
+        let mut locality = FnvMap::default();
+        for (name, &order) in &suggest.order {
+            let entry = locality.entry(name.declared_name()).or_insert(0);
+            *entry = order.max(*entry);
+        }
+        for suggestion in &mut result {
+            suggestion.score = Score {
+                type_match: match (&expected, &suggestion.typ) {
+                    (Some(expected), Either::Right(typ)) => type_match(env, expected, typ),
+                    _ => TypeMatch::Unknown,
+                },
+                name: fuzzy_match(prefix, &suggestion.name).unwrap_or(0),
+                locality: locality.get(&suggestion.name[..]).cloned().unwrap_or(0),
+            };
+        }
+        result.sort_by(|l, r| r.score.cmp(&l.score).then_with(|| l.name.cmp(&r.name)));
         result
     }
 
@@ -1457,6 +2341,7 @@ impl SuggestionQuery {
                 .map(|(k, typ)| Suggestion {
                     name: k.declared_name().into(),
                     typ: Either::Right(typ.clone()),
+                    score: Score::default(),
                 }),
         )
     }
@@ -1495,24 +2380,19 @@ impl SuggestionQuery {
                 .map(|(name, kind)| Suggestion {
                     name: name.declared_name().into(),
                     typ: Either::Left(kind.clone()),
+                    score: Score::default(),
                 }),
         );
     }
 
-    fn suggest_module_import<T>(&self, env: &T, path: &str, suggestions: &mut Vec<Suggestion>)
-    where
-        T: TypeEnv<Type = ArcType>,
-    {
+    /// Returns the modules in the files below `base` in each of the import paths
+    fn path_modules(&self, base: &Path) -> Vec<String> {
         use std::ffi::OsStr;
-        let path = Name::new(path);
-
-        let base = PathBuf::from(path.module().as_str().replace(".", "/"));
 
-        let modules = self
-            .paths
+        self.paths
             .iter()
             .flat_map(|root| {
-                let walk_root = root.join(&*base);
+                let walk_root = root.join(base);
                 walkdir::WalkDir::new(walk_root)
                     .into_iter()
                     .filter_map(|entry| entry.ok())
@@ -1530,14 +2410,39 @@ impl SuggestionQuery {
                         }
                     })
             })
-            .collect::<Vec<String>>();
+            .collect()
+    }
+
+    /// Returns every module which can be imported, such as the modules to search with
+    /// `workspace_symbols`
+    pub fn all_modules(&self) -> Vec<String> {
+        let mut modules = self.path_modules(Path::new(""));
+        modules.extend(self.modules.iter().map(|module| module.to_string()));
+        modules.sort();
+        modules.dedup();
+        modules
+    }
+
+    /// Suggests the modules which can be imported with a path starting with `path`, such as `list`
+    /// for `std.li`
+    pub fn suggest_module_import<T>(&self, env: &T, path: &str, suggestions: &mut Vec<Suggestion>)
+    where
+        T: TypeEnv<Type = ArcType>,
+    {
+        let path = Name::new(path);
+
+        let base = PathBuf::from(path.module().as_str().replace(".", "/"));
+
+        let modules = self.path_modules(&base);
 
         suggestions.extend(
             modules
                 .iter()
                 .map(|s| &s[..])
                 .chain(self.modules.iter().map(|s| &s[..]))
-                .filter(|module| self.filter(module, path.as_str()))
+                // Module paths are matched by prefix, as fuzzy matching the whole path would let
+                // the characters match in any of its parts
+                .filter(|module| !self.prefix_filter || module.starts_with(path.as_str()))
                 .map(|module| {
                     let name = module[path.module().as_str().len()..]
                         .trim_start_matches('.')
@@ -1551,6 +2456,7 @@ impl SuggestionQuery {
                             env.find_type(SymbolRef::new(module))
                                 .unwrap_or_else(Type::hole),
                         ),
+                        score: Score::default(),
                     }
                 }),
         );
@@ -1625,11 +2531,97 @@ impl SuggestionQuery {
 pub struct SignatureHelp {
     pub name: String,
     pub typ: ArcType,
+    /// The index of the argument at the position, or `None` if the position is on the function
     pub index: Option<u32>,
+    /// The documentation of the function
+    pub comment: Option<String>,
+    pub parameters: Vec<ParameterHelp>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParameterHelp {
+    /// The name of the parameter, if the function was defined with named arguments
+    pub name: Option<String>,
+    pub typ: ArcType,
+    /// The list item of the function documentation which starts with the name of the parameter,
+    /// such as `` * `xs` the list to reverse ``
+    pub comment: Option<String>,
 }
 
+/// Returns the list item in `comment` which starts with `` `name` ``, without its bullet
+fn parameter_comment(comment: &str, name: &str) -> Option<String> {
+    let is_item = |line: &str| line.starts_with("* ") || line.starts_with("- ");
+    let prefix = format!("`{}`", name);
+    let mut lines = comment.lines().map(|line| line.trim());
+    let first = lines.find(|line| is_item(line) && line[2..].trim_start().starts_with(&prefix))?;
+    let mut item = first[2..].trim_start().to_string();
+    for line in lines.take_while(|line| !line.is_empty() && !is_item(line)) {
+        item.push(' ');
+        item.push_str(line);
+    }
+    Some(item)
+}
+
+fn signature<'ast>(
+    metadata: &FnvMap<Symbol, Arc<Metadata>>,
+    func: &SpannedExpr<'ast, Symbol>,
+    typ: ArcType,
+    index: Option<u32>,
+) -> SignatureHelp {
+    let name = match func.value {
+        Expr::Ident(ref id) => id.name.declared_name().to_string(),
+        Expr::Projection(_, ref name, _) => name.declared_name().to_string(),
+        _ => "".to_string(),
+    };
+    let metadata = match func.value {
+        Expr::Ident(ref id) => metadata.get(&id.name),
+        Expr::Projection(ref expr, ref field, _) => match expr.value {
+            Expr::Ident(ref expr_id) => metadata
+                .get(&expr_id.name)
+                .and_then(|metadata| metadata.module.get(field.declared_name())),
+            _ => None,
+        },
+        _ => None,
+    };
+    let comment = metadata
+        .and_then(|metadata| metadata.comment.as_ref())
+        .map(|comment| comment.content.clone());
+
+    let mut names = metadata
+        .into_iter()
+        .flat_map(|metadata| &metadata.args)
+        .filter(|arg| arg.arg_type == ArgType::Explicit)
+        .map(|arg| arg.name.declared_name().to_string());
+    let mut parameters = Vec::new();
+    let mut function = typ.remove_forall_and_implicit_args();
+    while let Some((arg, ret)) = function.as_explicit_function() {
+        let name = names.next();
+        parameters.push(ParameterHelp {
+            comment: comment
+                .as_ref()
+                .and_then(|comment| parameter_comment(comment, name.as_ref()?)),
+            name,
+            typ: arg.clone(),
+        });
+        function = ret.remove_forall_and_implicit_args();
+    }
+
+    SignatureHelp {
+        name,
+        typ,
+        index,
+        comment,
+        parameters,
+    }
+}
+
+/// Returns the signature of the function that is applied at `pos`, along with the documentation
+/// of the function and its parameters from `metadata`. `pos` may also be just after a function
+/// which has not been applied yet, as in `f `, so that the signature can be shown as soon as the
+/// first argument is started.
 pub fn signature_help<'ast>(
     env: &dyn TypeEnv<Type = ArcType>,
+    metadata: &FnvMap<Symbol, Arc<Metadata>>,
     source_span: Span<BytePos>,
     expr: &SpannedExpr<'ast, Symbol>,
     pos: BytePos,
@@ -1667,13 +2659,6 @@ pub fn signature_help<'ast>(
                         Expr::App {
                             ref func, ref args, ..
                         } => func.try_type_of(env).ok().map(|typ| {
-                            let name = match func.value {
-                                Expr::Ident(ref id) => id.name.declared_name().to_string(),
-                                Expr::Projection(_, ref name, _) => {
-                                    name.declared_name().to_string()
-                                }
-                                _ => "".to_string(),
-                            };
                             let index = if args.first().map_or(false, |arg| pos >= arg.span.start())
                             {
                                 Some(
@@ -1685,7 +2670,7 @@ pub fn signature_help<'ast>(
                             } else {
                                 None
                             };
-                            SignatureHelp { name, typ, index }
+                            signature(metadata, func, typ, index)
                         }),
                         _ => None,
                     },
@@ -1697,25 +2682,1188 @@ pub fn signature_help<'ast>(
                 .chain(&found.near_matches)
                 .rev()
                 .filter_map(|enclosing_match| match *enclosing_match {
-                    Match::Expr(expr) => {
-                        let name = match expr.value {
-                            Expr::Ident(ref id) => id.name.declared_name().to_string(),
-                            Expr::Projection(_, ref name, _) => name.declared_name().to_string(),
-                            _ => "".to_string(),
-                        };
-
-                        expr.value.try_type_of(env).ok().map(|typ| SignatureHelp {
-                            name,
-                            typ,
-                            index: if pos > expr.span.end() { Some(0) } else { None },
-                        })
-                    }
+                    Match::Expr(expr) => expr.value.try_type_of(env).ok().map(|typ| {
+                        let index = if pos > expr.span.end() { Some(0) } else { None };
+                        signature(metadata, expr, typ, index)
+                    }),
                     _ => None,
                 });
             applications.chain(any_expr).next()
         })
 }
 
+/// The kinds of the names highlighted by `semantic_tokens`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Variable,
+    Function,
+    Parameter,
+    Type,
+    TypeParameter,
+    Constructor,
+    Field,
+    Operator,
+}
+
+/// A name in the source, classified by what it refers to
+#[derive(Clone, Debug, PartialEq)]
+pub struct SemanticToken {
+    pub span: Span<BytePos>,
+    pub kind: TokenKind,
+    /// `true` if the token binds the name, `false` if it refers to an existing binding
+    pub definition: bool,
+    /// `true` if the name is an implicit argument or a binding with the `#[implicit]` attribute
+    pub implicit: bool,
+    /// The fixity of an operator, from its `#[infix]` attribute
+    pub fixity: Option<OpMeta>,
+}
+
+/// Returns the names in `expr` classified by what they refer to, ordered by their position and
+/// without overlapping each other. Names which are inserted by the typechecker, such as
+/// implicit arguments, are not part of the source and are left out.
+pub fn semantic_tokens<'ast>(
+    env: &FnvMap<Symbol, Arc<Metadata>>,
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<'ast, Symbol>,
+) -> Vec<SemanticToken> {
+    struct Tokens<'e> {
+        env: &'e FnvMap<Symbol, Arc<Metadata>>,
+        source_span: Span<BytePos>,
+        parameters: FnvSet<Symbol>,
+        implicits: FnvSet<Symbol>,
+        tokens: Vec<SemanticToken>,
+    }
+
+    fn is_function(typ: &ArcType) -> bool {
+        typ.remove_forall_and_implicit_args()
+            .as_function()
+            .is_some()
+    }
+
+    impl Tokens<'_> {
+        fn push(&mut self, span: Span<BytePos>, kind: TokenKind, definition: bool) {
+            self.tokens.push(SemanticToken {
+                span,
+                kind,
+                definition,
+                implicit: false,
+                fixity: None,
+            });
+        }
+
+        fn push_value(&mut self, span: Span<BytePos>, id: &TypedIdent<Symbol>, definition: bool) {
+            let name = id.name.declared_name();
+            let metadata = self.env.get(&id.name);
+            let kind = if name.starts_with(ast::is_operator_char) {
+                TokenKind::Operator
+            } else if ast::is_constructor(name) {
+                TokenKind::Constructor
+            } else if self.parameters.contains(&id.name) {
+                TokenKind::Parameter
+            } else if is_function(&id.typ) {
+                TokenKind::Function
+            } else {
+                TokenKind::Variable
+            };
+            let fixity = if kind == TokenKind::Operator {
+                metadata
+                    .and_then(|metadata| metadata.get_attribute("infix"))
+                    .and_then(|infix| infix.parse().ok())
+                    .or_else(|| parser::infix::builtin_fixity(name).cloned())
+            } else {
+                None
+            };
+            let implicit = self.implicits.contains(&id.name)
+                || metadata.map_or(false, |metadata| {
+                    metadata.get_attribute("implicit").is_some()
+                });
+            self.tokens.push(SemanticToken {
+                span,
+                kind,
+                definition,
+                implicit,
+                fixity,
+            });
+        }
+
+        fn visit_argument(&mut self, arg: &ast::Argument<SpannedIdent<Symbol>>) {
+            self.parameters.insert(arg.name.value.name.clone());
+            if arg.arg_type == ArgType::Implicit {
+                self.implicits.insert(arg.name.value.name.clone());
+            }
+            self.push_value(arg.name.span, &arg.name.value, true);
+        }
+
+        fn visit_pattern(&mut self, pattern: &SpannedPattern<Symbol>) {
+            match pattern.value {
+                Pattern::Ident(ref id) => self.push_value(pattern.span, id, true),
+                Pattern::As(ref id, ref pattern) => {
+                    self.push(id.span, TokenKind::Variable, true);
+                    self.visit_pattern(pattern);
+                }
+                Pattern::Constructor(ref id, ref args) => {
+                    let start = pattern.span.start();
+                    let end = start + ByteOffset::from(id.name.declared_name().len() as i64);
+                    self.push(Span::new(start, end), TokenKind::Constructor, false);
+                    for arg in &**args {
+                        self.visit_pattern(arg);
+                    }
+                }
+                Pattern::Tuple { ref elems, .. } => {
+                    for elem in &**elems {
+                        self.visit_pattern(elem);
+                    }
+                }
+                Pattern::Record {
+                    ref fields,
+                    ref typ,
+                    ..
+                } => {
+                    for field in &**fields {
+                        match field {
+                            PatternField::Type { name } => {
+                                self.push(name.span, TokenKind::Type, true)
+                            }
+                            PatternField::Value { name, value: None } => {
+                                let field_type = typ
+                                    .row_iter()
+                                    .find(|field| field.name.name_eq(&name.value))
+                                    .map(|field| field.typ.clone())
+                                    .unwrap_or_else(Type::hole);
+                                self.push_value(
+                                    name.span,
+                                    &TypedIdent {
+                                        name: name.value.clone(),
+                                        typ: field_type,
+                                    },
+                                    true,
+                                );
+                            }
+                            PatternField::Value {
+                                name,
+                                value: Some(value),
+                            } => {
+                                self.push(name.span, TokenKind::Field, false);
+                                self.visit_pattern(value);
+                            }
+                        }
+                    }
+                }
+                Pattern::Literal(_) | Pattern::Error => (),
+            }
+        }
+    }
+
+    impl<'a, 'ast> Visitor<'a, 'ast> for Tokens<'_> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+            match e.value {
+                Expr::Ident(ref id) => self.push_value(e.span, id, false),
+                Expr::App {
+                    ref func, ref args, ..
+                } => {
+                    self.visit_expr(func);
+                    for arg in &**args {
+                        self.visit_expr(arg);
+                    }
+                }
+                Expr::Infix {
+                    ref lhs,
+                    ref op,
+                    ref rhs,
+                    ..
+                } => {
+                    self.visit_expr(lhs);
+                    self.push_value(op.span, &op.value, false);
+                    self.visit_expr(rhs);
+                }
+                Expr::Projection(ref expr, ref field, ref typ) => {
+                    self.visit_expr(expr);
+                    let end = e.span.end();
+                    let start = end - ByteOffset::from(field.declared_name().len() as i64);
+                    let kind = if ast::is_constructor(field.declared_name()) {
+                        TokenKind::Constructor
+                    } else if is_function(typ) {
+                        TokenKind::Function
+                    } else {
+                        TokenKind::Field
+                    };
+                    self.push(Span::new(start, end), kind, false);
+                }
+                Expr::Record {
+                    ref typ,
+                    ref types,
+                    ref exprs,
+                    ref base,
+                } => {
+                    for field in &**types {
+                        self.push(field.name.span, TokenKind::Type, false);
+                    }
+                    for field in &**exprs {
+                        match field.value {
+                            Some(ref value) => {
+                                self.push(field.name.span, TokenKind::Field, true);
+                                self.visit_expr(value);
+                            }
+                            None => {
+                                let field_type = typ
+                                    .row_iter()
+                                    .find(|f| f.name.name_eq(&field.name.value))
+                                    .map(|f| f.typ.clone())
+                                    .unwrap_or_else(Type::hole);
+                                self.push_value(
+                                    field.name.span,
+                                    &TypedIdent {
+                                        name: field.name.value.clone(),
+                                        typ: field_type,
+                                    },
+                                    false,
+                                );
+                            }
+                        }
+                    }
+                    if let Some(ref base) = *base {
+                        self.visit_expr(base);
+                    }
+                }
+                Expr::LetBindings(ref binds, ref body) => {
+                    for bind in binds {
+                        let implicit = bind.metadata.get_attribute("implicit").is_some();
+                        if implicit {
+                            if let Pattern::Ident(ref id) = bind.name.value {
+                                self.implicits.insert(id.name.clone());
+                            }
+                        }
+                        // Recursive bindings are in scope in every binding so they need to be
+                        // seen before any of the expressions
+                        self.visit_pattern(&bind.name);
+                    }
+                    for bind in binds {
+                        for arg in &*bind.args {
+                            self.visit_argument(arg);
+                        }
+                        if let Some(ref typ) = bind.typ {
+                            self.visit_ast_type(typ);
+                        }
+                        self.visit_expr(&bind.expr);
+                    }
+                    self.visit_expr(body);
+                }
+                Expr::TypeBindings(ref binds, ref body) => {
+                    for bind in &**binds {
+                        self.push(bind.name.span, TokenKind::Type, true);
+                        self.visit_ast_type(bind.alias.value.unresolved_type());
+                    }
+                    self.visit_expr(body);
+                }
+                Expr::Lambda(ref lambda) => {
+                    for arg in &*lambda.args {
+                        self.visit_argument(arg);
+                    }
+                    self.visit_expr(&lambda.body);
+                }
+                Expr::Match(ref expr, ref alts) => {
+                    self.visit_expr(expr);
+                    for alt in &**alts {
+                        self.visit_pattern(&alt.pattern);
+                        self.visit_expr(&alt.expr);
+                    }
+                }
+                Expr::Do(ref do_expr) => {
+                    if let Some(ref id) = do_expr.id {
+                        self.visit_pattern(id);
+                    }
+                    self.visit_expr(&do_expr.bound);
+                    self.visit_expr(&do_expr.body);
+                }
+                Expr::MacroExpansion {
+                    ref replacement, ..
+                } => {
+                    // Names generated by the macro are given the span of the whole macro call
+                    let start = self.tokens.len();
+                    self.visit_expr(replacement);
+                    let span = e.span;
+                    let mut i = start;
+                    self.tokens.retain(|token| {
+                        i += 1;
+                        i <= start || token.span != span
+                    });
+                }
+                _ => walk_expr(self, e),
+            }
+        }
+
+        fn visit_ast_type(&mut self, typ: &'a AstType<'ast, Symbol>) {
+            match **typ {
+                Type::Ident(_) | Type::Alias(_) | Type::Builtin(_) | Type::Projection(_) => {
+                    self.push(typ.span(), TokenKind::Type, false)
+                }
+                Type::Generic(_) => self.push(typ.span(), TokenKind::TypeParameter, false),
+                Type::Record(_) | Type::Variant(_) | Type::Effect(_) => {
+                    let kind = match **typ {
+                        Type::Variant(_) => TokenKind::Constructor,
+                        _ => TokenKind::Field,
+                    };
+                    for field in base::types::row_iter(typ) {
+                        self.push(field.name.span, kind, true);
+                        self.visit_ast_type(&field.typ);
+                    }
+                }
+                _ => ast::walk_ast_type(self, typ),
+            }
+        }
+    }
+
+    let mut tokens = Tokens {
+        env,
+        source_span,
+        parameters: FnvSet::default(),
+        implicits: FnvSet::default(),
+        tokens: Vec::new(),
+    };
+    tokens.visit_expr(expr);
+
+    let source_span = tokens.source_span;
+    let mut tokens: Vec<_> = tokens
+        .tokens
+        .into_iter()
+        .filter(|token| source_span.contains(token.span) && token.span.start() != token.span.end())
+        .collect();
+    // Prefer the innermost name when names overlap
+    tokens.sort_by_key(|token| (token.span.start(), token.span.end()));
+    let mut result: Vec<SemanticToken> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match result.last() {
+            Some(last) if token.span.start() < last.span.end() => {
+                if token.span.end() < last.span.end() {
+                    *result.last_mut().unwrap() = token;
+                }
+            }
+            _ => result.push(token),
+        }
+    }
+    result
+}
+
+/// Selects the inlay hints returned by `inlay_hints`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InlayHintOptions {
+    /// Show the inferred types of `let` bindings which do not have a type annotation
+    pub types: bool,
+    /// Show the implicit arguments which the typechecker passes to functions
+    pub implicit_arguments: bool,
+}
+
+impl Default for InlayHintOptions {
+    fn default() -> Self {
+        InlayHintOptions {
+            types: true,
+            implicit_arguments: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InlayHintKind {
+    Type,
+    ImplicitArgument,
+}
+
+/// A label to show at `position` which is not part of the source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InlayHint {
+    pub position: BytePos,
+    pub label: String,
+    pub kind: InlayHintKind,
+}
+
+fn is_import(expr: &SpannedExpr<Symbol>) -> bool {
+    match expr.value {
+        Expr::MacroExpansion { ref original, .. } => match original.value {
+            Expr::App { ref func, .. } => match func.value {
+                Expr::Ident(ref id) => id.name.declared_name() == "import!",
+                _ => false,
+            },
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns the return type of the function bound by `bind` and the position after its last
+/// argument, where a return type annotation is written
+fn return_type<'a>(bind: &'a ValueBinding<Symbol>) -> Option<(BytePos, &'a ArcType)> {
+    let last = bind.args.last()?;
+    let id = match bind.name.value {
+        Pattern::Ident(ref id) => id,
+        _ => return None,
+    };
+    let mut typ = id.typ.remove_forall_and_implicit_args();
+    for _ in bind
+        .args
+        .iter()
+        .filter(|arg| arg.arg_type == ArgType::Explicit)
+    {
+        typ = typ.as_function()?.1;
+    }
+    Some((last.name.span.end(), typ))
+}
+
+/// Returns the source that would pass the implicit argument `expr` explicitly
+fn implicit_argument_label(expr: &SpannedExpr<Symbol>) -> Option<String> {
+    match expr.value {
+        Expr::Ident(ref id) => {
+            let name = id.name.declared_name();
+            if name.starts_with(ast::is_operator_char) {
+                Some(format!("({})", name))
+            } else {
+                Some(name.to_string())
+            }
+        }
+        Expr::Projection(ref expr, ref field, _) => {
+            let module = implicit_argument_label(expr)?;
+            // Modules imported with `let { ? } = ...` are bound to a generated name which can't be
+            // written in the source
+            if module.contains('?') {
+                Some(field.declared_name().to_string())
+            } else {
+                Some(format!("{}.{}", module, field.declared_name()))
+            }
+        }
+        Expr::App {
+            ref func,
+            ref implicit_args,
+            ref args,
+        } => {
+            let mut label = implicit_argument_label(func)?;
+            for arg in implicit_args.iter().chain(&**args) {
+                let arg = implicit_argument_label(arg)?;
+                label.push(' ');
+                if arg.contains(' ') {
+                    label.push('(');
+                    label.push_str(&arg);
+                    label.push(')');
+                } else {
+                    label.push_str(&arg);
+                }
+            }
+            Some(label)
+        }
+        _ => None,
+    }
+}
+
+/// Returns hints for the inferred types of `let` bindings without a type annotation and for the
+/// implicit arguments passed at each call, as selected by `options`.
+///
+/// The type of a function binding is shown as its return type after the last argument, where a
+/// return type annotation would be written. Bindings of `import!` are left out as the type of a
+/// module is too large to be useful as a hint.
+pub fn inlay_hints<'ast>(
+    options: &InlayHintOptions,
+    source_span: Span<BytePos>,
+    expr: &SpannedExpr<'ast, Symbol>,
+) -> Vec<InlayHint> {
+    struct Hints<'o> {
+        options: &'o InlayHintOptions,
+        hints: Vec<InlayHint>,
+    }
+
+    impl Hints<'_> {
+        fn type_hint(&mut self, position: BytePos, typ: &ArcType) {
+            match **typ {
+                Type::Hole | Type::Error => return,
+                _ => (),
+            }
+            self.hints.push(InlayHint {
+                position,
+                label: format!(": {}", typ),
+                kind: InlayHintKind::Type,
+            });
+        }
+
+        fn pattern_hints(&mut self, pattern: &SpannedPattern<Symbol>) {
+            match pattern.value {
+                Pattern::Ident(ref id) => self.type_hint(pattern.span.end(), &id.typ),
+                Pattern::As(_, ref pattern) => self.pattern_hints(pattern),
+                Pattern::Constructor(_, ref args) => {
+                    for arg in &**args {
+                        self.pattern_hints(arg);
+                    }
+                }
+                Pattern::Tuple { ref elems, .. } => {
+                    for elem in &**elems {
+                        self.pattern_hints(elem);
+                    }
+                }
+                Pattern::Record { ref fields, .. } => {
+                    for field in &**fields {
+                        if let PatternField::Value {
+                            value: Some(value), ..
+                        } = field
+                        {
+                            self.pattern_hints(value);
+                        }
+                    }
+                }
+                Pattern::Literal(_) | Pattern::Error => (),
+            }
+        }
+
+        fn implicit_hint(&mut self, position: BytePos, implicit_args: &[SpannedExpr<Symbol>]) {
+            let labels = implicit_args
+                .iter()
+                .map(implicit_argument_label)
+                .collect::<Option<Vec<_>>>();
+            if let Some(labels) = labels.filter(|labels| !labels.is_empty()) {
+                self.hints.push(InlayHint {
+                    position,
+                    label: labels
+                        .iter()
+                        .map(|label| {
+                            if label.contains(' ') {
+                                format!("?({})", label)
+                            } else {
+                                format!("?{}", label)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    kind: InlayHintKind::ImplicitArgument,
+                });
+            }
+        }
+    }
+
+    impl<'a, 'ast> Visitor<'a, 'ast> for Hints<'_> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+            match e.value {
+                Expr::LetBindings(ref binds, _) if self.options.types => {
+                    for bind in &**binds {
+                        if bind.typ.is_some() || is_import(&bind.expr) {
+                            continue;
+                        }
+                        if bind.args.is_empty() {
+                            self.pattern_hints(&bind.name);
+                        } else if let Some((position, typ)) = return_type(bind) {
+                            self.type_hint(position, typ);
+                        }
+                    }
+                }
+                Expr::App {
+                    ref func,
+                    ref implicit_args,
+                    ..
+                } if self.options.implicit_arguments => {
+                    self.implicit_hint(func.span.end(), implicit_args)
+                }
+                Expr::Infix {
+                    ref op,
+                    ref implicit_args,
+                    ..
+                } if self.options.implicit_arguments => {
+                    self.implicit_hint(op.span.end(), implicit_args)
+                }
+                _ => (),
+            }
+            match e.value {
+                // The implicit arguments are generated by the typechecker so hints inside them
+                // would duplicate the hint for the argument itself
+                Expr::App {
+                    ref func, ref args, ..
+                } => {
+                    self.visit_expr(func);
+                    for arg in &**args {
+                        self.visit_expr(arg);
+                    }
+                }
+                Expr::Infix {
+                    ref lhs, ref rhs, ..
+                } => {
+                    self.visit_expr(lhs);
+                    self.visit_expr(rhs);
+                }
+                Expr::MacroExpansion { .. } if is_import(e) => (),
+                _ => walk_expr(self, e),
+            }
+        }
+    }
+
+    let mut hints = Hints {
+        options,
+        hints: Vec::new(),
+    };
+    hints.visit_expr(expr);
+    let mut hints: Vec<_> = hints
+        .hints
+        .into_iter()
+        .filter(|hint| source_span.contains_pos(hint.position))
+        .collect();
+    hints.sort_by_key(|hint| hint.position);
+    hints
+}
+
+/// A replacement of the text at `span`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span<BytePos>,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    fn insert(position: BytePos, new_text: String) -> TextEdit {
+        TextEdit {
+            span: Span::new(position, position),
+            new_text,
+        }
+    }
+}
+
+/// A fix or refactoring which is applied by replacing the text at each of `edits`. The edits do
+/// not overlap and are all in the module that the action was requested for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeAction {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+fn line_start(source: &dyn Source, pos: BytePos) -> BytePos {
+    let src = source.src();
+    let offset = (pos - source.span().start()).to_usize();
+    let start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+    source.span().start() + ByteOffset::from(start as i64)
+}
+
+/// Returns the position after the newline of the line that `pos` is on
+fn next_line_start(source: &dyn Source, pos: BytePos) -> BytePos {
+    let src = source.src();
+    let offset = (pos - source.span().start()).to_usize();
+    let end = src[offset..]
+        .find('\n')
+        .map_or(src.len(), |i| offset + i + 1);
+    source.span().start() + ByteOffset::from(end as i64)
+}
+
+/// Returns the `let` bindings, in `expr` or any expression inside it, for which `f` returns
+/// `true`
+fn find_bindings<'a, 'ast>(
+    expr: &'a SpannedExpr<'ast, Symbol>,
+    mut f: impl FnMut(&'a ValueBinding<'ast, Symbol>) -> bool,
+) -> Vec<&'a [ValueBinding<'ast, Symbol>]> {
+    struct FindBindings<'a, 'ast, F> {
+        f: F,
+        result: Vec<&'a [ValueBinding<'ast, Symbol>]>,
+    }
+
+    impl<'a, 'ast, F> Visitor<'a, 'ast> for FindBindings<'a, 'ast, F>
+    where
+        F: FnMut(&'a ValueBinding<'ast, Symbol>) -> bool,
+    {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+            if let Expr::LetBindings(ref binds, _) = e.value {
+                let f = &mut self.f;
+                if binds.iter().any(|bind| f(bind)) {
+                    self.result.push(binds);
+                }
+            }
+            walk_expr(self, e);
+        }
+    }
+
+    let mut visitor = FindBindings {
+        f: &mut f,
+        result: Vec::new(),
+    };
+    visitor.visit_expr(expr);
+    visitor.result
+}
+
+/// Adds the inferred type of the `let` binding whose name is at `pos` as a type annotation. For
+/// a function the annotation is the return type, written after the last argument.
+pub fn add_type_annotation<'ast>(
+    expr: &SpannedExpr<'ast, Symbol>,
+    pos: BytePos,
+) -> Option<CodeAction> {
+    let is_target = |bind: &ValueBinding<Symbol>| {
+        bind.typ.is_none() && !is_import(&bind.expr) && bind.name.span.contains_pos(pos)
+    };
+    let binds = find_bindings(expr, |bind| is_target(bind));
+    let bind = binds.first()?.iter().find(|bind| is_target(bind))?;
+    let (position, typ) = if bind.args.is_empty() {
+        (bind.name.span.end(), &bind.resolved_type)
+    } else {
+        return_type(bind)?
+    };
+    match **typ {
+        Type::Hole | Type::Error => return None,
+        _ => (),
+    }
+    Some(CodeAction {
+        title: "Add type annotation".to_string(),
+        edits: vec![TextEdit::insert(position, format!(" : {}", typ))],
+    })
+}
+
+/// Fixes a record expression at `span` which lacks `fields` by adding each field with a value
+/// which raises an error, for the user to replace.
+///
+/// Driven by the diagnostics which report missing fields when a record is unified with the type
+/// it is expected to have.
+pub fn insert_missing_fields<'ast>(
+    expr: &SpannedExpr<'ast, Symbol>,
+    span: Span<BytePos>,
+    fields: &[&str],
+) -> Option<CodeAction> {
+    struct FindRecord<'a, 'ast> {
+        span: Span<BytePos>,
+        found: Option<&'a SpannedExpr<'ast, Symbol>>,
+    }
+
+    impl<'a, 'ast> Visitor<'a, 'ast> for FindRecord<'a, 'ast> {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+            if let Expr::Record { .. } = e.value {
+                // The innermost record containing the span
+                if e.span.contains(self.span) {
+                    self.found = Some(e);
+                }
+            }
+            walk_expr(self, e);
+        }
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+    let mut finder = FindRecord { span, found: None };
+    finder.visit_expr(expr);
+    let record = finder.found?;
+    let (types, exprs, base) = match record.value {
+        Expr::Record {
+            ref types,
+            ref exprs,
+            ref base,
+            ..
+        } => (types, exprs, base),
+        _ => return None,
+    };
+    if base.is_some() {
+        return None;
+    }
+
+    let new_fields = fields
+        .iter()
+        .map(|field| format!("{} = error \"TODO: {}\"", field, field))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let edit = match exprs.last() {
+        Some(last) => {
+            let end = last
+                .value
+                .as_ref()
+                .map_or(last.name.span.end(), |value| value.span.end());
+            TextEdit::insert(end, format!(", {}", new_fields))
+        }
+        None => {
+            // Only `{}` can be replaced, a record of only types may not be for a record value
+            if !types.is_empty() {
+                return None;
+            }
+            TextEdit {
+                span: record.span,
+                new_text: format!("{{ {} }}", new_fields),
+            }
+        }
+    };
+    let title = if fields.len() == 1 {
+        format!("Add the missing field `{}`", fields[0])
+    } else {
+        "Add the missing fields".to_string()
+    };
+    Some(CodeAction {
+        title,
+        edits: vec![edit],
+    })
+}
+
+/// Returns the module path and the name bound for each `let x = import! path` at the top of
+/// `expr`
+fn module_imports<'a>(expr: &'a SpannedExpr<Symbol>) -> Vec<(String, &'a Symbol)> {
+    let mut imports = Vec::new();
+    let mut expr = expr;
+    while let Expr::LetBindings(ref binds, ref body) = expr.value {
+        for bind in &**binds {
+            let original = match bind.expr.value {
+                Expr::MacroExpansion { ref original, .. } if is_import(&bind.expr) => original,
+                _ => continue,
+            };
+            let path = match original.value {
+                Expr::App { ref args, .. } if args.len() == 1 => {
+                    let mut path = String::new();
+                    match ast::expr_to_path(&args[0], &mut path) {
+                        Ok(()) => path,
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue,
+            };
+            if let Pattern::Ident(ref id) = bind.name.value {
+                imports.push((path, &id.name));
+            }
+        }
+        expr = body;
+    }
+    imports
+}
+
+/// Fixes the undefined variable `name` at `span` by referring to a field of one of `modules`.
+/// If the module is already imported as `let module = import! path` the variable is qualified
+/// with it, otherwise the field is imported at the start of the module.
+///
+/// Driven by the diagnostics for undefined variables. `modules` are the paths of the modules
+/// which may be imported, such as those returned by `SuggestionQuery::all_modules`, and `env`
+/// must contain the types of the modules which should be searched for `name`.
+pub fn qualify_name<'ast, T>(
+    env: &T,
+    source: &dyn Source,
+    expr: &SpannedExpr<'ast, Symbol>,
+    span: Span<BytePos>,
+    name: &str,
+    modules: &[String],
+) -> Vec<CodeAction>
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    let imports = module_imports(expr);
+    let mut actions = Vec::new();
+    for module in modules {
+        let module_has_field = env
+            // Modules are looked up by their global name
+            .find_type(SymbolRef::new(&format!("@{}", module)))
+            .map_or(false, |typ| {
+                let typ = resolve::remove_aliases(env, &mut NullInterner, typ);
+                typ.row_iter()
+                    .any(|field| field.name.declared_name() == name)
+            });
+        if !module_has_field {
+            continue;
+        }
+
+        match imports.iter().find(|(path, _)| path == module) {
+            Some((_, binding)) => {
+                let qualified = format!("{}.{}", binding.declared_name(), name);
+                actions.push(CodeAction {
+                    title: format!("Change to `{}`", qualified),
+                    edits: vec![TextEdit {
+                        span,
+                        new_text: qualified,
+                    }],
+                });
+            }
+            None => {
+                let name = if name.starts_with(ast::is_operator_char) {
+                    format!("({})", name)
+                } else {
+                    name.to_string()
+                };
+                // Imports go below any comments at the start of the module, such as `//!`
+                // comments documenting the module
+                let src = source.src();
+                let mut offset = 0;
+                for line in src.split_inclusive('\n') {
+                    let trimmed = line.trim();
+                    if trimmed.starts_with("//") || trimmed.is_empty() {
+                        offset += line.len();
+                    } else {
+                        break;
+                    }
+                }
+                let position = source.span().start() + ByteOffset::from(offset as i64);
+                actions.push(CodeAction {
+                    title: format!("Import `{}` from `{}`", name, module),
+                    edits: vec![TextEdit::insert(
+                        position,
+                        format!("let {{ {} }} = import! {}\n", name, module),
+                    )],
+                });
+            }
+        }
+    }
+    actions
+}
+
+/// Fixes the operator `name`, which has no fixity, by adding an `#[infix(left, 9)]` attribute to
+/// its binding.
+///
+/// Driven by the diagnostic for operators without a fixity. Only needs `expr` to be parsed and
+/// not typechecked, which is not possible until the fixity is fixed.
+pub fn add_infix_attribute<'ast>(
+    source: &dyn Source,
+    expr: &SpannedExpr<'ast, Symbol>,
+    name: &str,
+) -> Option<CodeAction> {
+    let is_operator = |bind: &ValueBinding<Symbol>| match bind.name.value {
+        Pattern::Ident(ref id) => id.name.declared_name() == name,
+        _ => false,
+    };
+    let binds = find_bindings(expr, |bind| is_operator(bind));
+    let bind = binds.first()?.iter().find(|bind| is_operator(bind))?;
+    let start = line_start(source, bind.name.span.start());
+    let line = source.src_slice(Span::new(start, bind.name.span.start()));
+    let indent = &line[..line.len() - line.trim_start().len()];
+    if !line.trim().ends_with("let") {
+        return None;
+    }
+    Some(CodeAction {
+        title: format!("Add `#[infix(left, 9)]` to `{}`", name),
+        edits: vec![TextEdit::insert(
+            start,
+            format!("{}#[infix(left, 9)]\n", indent),
+        )],
+    })
+}
+
+/// Returns an action to remove each `let` binding in `expr` which is never used. Bindings whose
+/// name starts with `_` and bindings which may be used as implicit arguments are kept.
+///
+/// Only bindings which are alone on their lines are removed, along with any comments above them.
+pub fn remove_unused_bindings<'ast>(
+    env: &FnvMap<Symbol, Arc<Metadata>>,
+    source: &dyn Source,
+    expr: &SpannedExpr<'ast, Symbol>,
+) -> Vec<CodeAction> {
+    let index = SymbolIndex::new(env, source.span(), expr);
+    let is_unused = |bind: &ValueBinding<Symbol>| match bind.name.value {
+        Pattern::Ident(ref id) => {
+            !id.name.declared_name().starts_with('_')
+                && source.span().contains(bind.name.span)
+                && !bind
+                    .metadata
+                    .attributes()
+                    .any(|attribute| attribute.name == "implicit")
+                && !index.is_used(&id.name)
+        }
+        _ => false,
+    };
+
+    let mut actions = Vec::new();
+    for binds in find_bindings(expr, |bind| is_unused(bind)) {
+        // Removing one binding of a `rec` group would leave the rest of the group behind
+        if binds.len() != 1 {
+            continue;
+        }
+        let bind = &binds[0];
+        let let_start = line_start(source, bind.name.span.start());
+        let end = next_line_start(source, bind.expr.span.end());
+        let head = source.src_slice(Span::new(let_start, bind.name.span.start()));
+        let tail = source.src_slice(Span::new(bind.expr.span.end(), end));
+        if head.trim() != "let" || !(tail.trim().is_empty() || tail.trim().starts_with("//")) {
+            continue;
+        }
+
+        let mut start = let_start;
+        while start > source.span().start() {
+            let previous = line_start(source, start - ByteOffset::from(1));
+            let line = source.src_slice(Span::new(previous, start)).trim();
+            if !(line.starts_with("//") || line.starts_with("#[")) || line.starts_with("//!") {
+                break;
+            }
+            start = previous;
+        }
+
+        actions.push(CodeAction {
+            title: format!(
+                "Remove the unused binding `{}`",
+                match bind.name.value {
+                    Pattern::Ident(ref id) => id.name.declared_name(),
+                    _ => "",
+                }
+            ),
+            edits: vec![TextEdit {
+                span: Span::new(start, end),
+                new_text: String::new(),
+            }],
+        });
+    }
+    actions
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HoverOptions {
+    /// Show types with their aliases replaced by the types they are defined as
+    pub expand_aliases: bool,
+}
+
+/// The information shown when hovering over a name
+#[derive(Debug)]
+pub struct Hover<'a> {
+    /// The span of the expression, pattern or name that the information is about
+    pub span: Span<BytePos>,
+    pub name: Option<String>,
+    pub typ: Either<ArcKind, ArcType>,
+    /// The documentation of the name
+    pub comment: Option<&'a str>,
+    pub definition: Option<Definition<'a>>,
+    /// The signature in a `gluon` code block, followed by the documentation
+    pub markdown: String,
+}
+
+/// Replaces every alias in `typ` with the type it is defined as
+fn expand_aliases<T>(env: &T, typ: ArcType) -> ArcType
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    types::walk_move_type(typ, &mut |typ: &ArcType| match resolve::remove_aliases_cow(
+        env,
+        &mut NullInterner,
+        typ,
+    ) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(typ) => Some(typ),
+    })
+}
+
+/// Returns the documentation of the record field or variant constructor `name` which is a
+/// member of the type `typ`
+fn member_metadata<'a>(
+    metadata: &'a FnvMap<Symbol, Arc<Metadata>>,
+    typ: &ArcType,
+    name: &str,
+) -> Option<&'a Metadata> {
+    let id = typ.remove_forall().alias_ident()?;
+    metadata
+        .get(id)?
+        .module
+        .get(name)
+        .map(|metadata| &**metadata)
+}
+
+/// Returns the type, documentation and definition of the name or expression at `pos`.
+///
+/// Record fields are documented by the comments on their declaration in the record type, so
+/// their documentation is found through the type of the record. Variant constructors can't be
+/// documented by themselves and show the documentation of the type they construct.
+pub fn hover<'a, 'ast, T>(
+    env: &T,
+    metadata: &'a FnvMap<Symbol, Arc<Metadata>>,
+    options: &HoverOptions,
+    source_span: Span<BytePos>,
+    expr: &'a SpannedExpr<'ast, Symbol>,
+    pos: BytePos,
+) -> Option<Hover<'a>>
+where
+    T: TypeEnv<Type = ArcType>,
+{
+    let found = complete_at((), source_span, expr, pos).ok()?;
+    let match_ = found
+        .match_
+        .as_ref()
+        .unwrap_or_else(|| found.enclosing_match());
+    let typ = TypeAt { env }.match_extract(match_).ok()?;
+    let name = IdentAt.match_extract(match_).ok();
+
+    let member = || {
+        let name = name?.declared_name();
+        let owner = match *found.enclosing_match() {
+            Match::Expr(&Spanned {
+                value: Expr::Projection(ref expr, ..),
+                ..
+            }) => expr.try_type_of(env).ok()?,
+            Match::Expr(
+                record @ &Spanned {
+                    value: Expr::Record { .. },
+                    ..
+                },
+            ) => {
+                let owner = record.try_type_of(env).ok()?;
+                if owner.alias_ident().is_some() {
+                    owner
+                } else {
+                    // The record is not known to be of an alias by itself, but the binding it is
+                    // the value of may have its fields documented through its type annotation
+                    let bind = found
+                        .enclosing_matches
+                        .iter()
+                        .rev()
+                        .find_map(|m| match *m {
+                            Match::Expr(&Spanned {
+                                value: Expr::LetBindings(ref binds, _),
+                                ..
+                            }) => binds.iter().find(|bind| bind.expr.span == record.span),
+                            _ => None,
+                        })?;
+                    return match bind.name.value {
+                        Pattern::Ident(ref id) => metadata
+                            .get(&id.name)?
+                            .module
+                            .get(name)
+                            .map(|metadata| &**metadata),
+                        _ => None,
+                    };
+                }
+            }
+            Match::Pattern(
+                pattern @ &Spanned {
+                    value: Pattern::Record { .. },
+                    ..
+                },
+            ) => pattern.try_type_of(env).ok()?,
+            // Constructors are functions returning the type they construct
+            _ if name.starts_with(char::is_uppercase) => {
+                let mut typ = typ.as_ref().right()?.remove_forall_and_implicit_args();
+                while let Some((_, ret)) = typ.as_function() {
+                    typ = ret.remove_forall_and_implicit_args();
+                }
+                return metadata.get(typ.alias_ident()?).map(|metadata| &**metadata);
+            }
+            _ => return None,
+        };
+        member_metadata(metadata, &owner, name)
+    };
+    let comment = get_metadata(metadata, source_span, expr, pos)
+        .filter(|metadata| metadata.comment.is_some())
+        .or_else(member)
+        .and_then(|metadata| metadata.comment.as_ref())
+        .map(|comment| &comment.content[..]);
+
+    let typ = match typ {
+        Either::Right(typ) if options.expand_aliases => Either::Right(expand_aliases(env, typ)),
+        typ => typ,
+    };
+
+    let mut markdown = "```gluon\n".to_string();
+    if let Some(name) = name {
+        let name = name.declared_name();
+        if name.starts_with(ast::is_operator_char) {
+            markdown.push_str(&format!("({})", name));
+        } else {
+            markdown.push_str(name);
+        }
+        markdown.push_str(" : ");
+    }
+    markdown.push_str(&typ.to_string());
+    markdown.push_str("\n```");
+    if let Some(comment) = comment {
+        markdown.push_str("\n\n");
+        markdown.push_str(comment);
+    }
+
+    Some(Hover {
+        span: match_.span(),
+        name: name.map(|name| name.declared_name().to_string()),
+        typ,
+        comment,
+        definition: definition(metadata, source_span, expr, pos),
+        markdown,
+    })
+}
+
 pub fn get_metadata<'a, 'ast>(
     env: &'a FnvMap<Symbol, Arc<Metadata>>,
     source_span: Span<BytePos>,
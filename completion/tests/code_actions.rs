@@ -0,0 +1,205 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::base::{
+    pos::{BytePos, Span},
+    source::FileMap,
+};
+
+use crate::completion::CodeAction;
+
+#[allow(unused)]
+mod support;
+use crate::support::MockEnv;
+
+/// Returns `text` with the edits of `action` applied
+fn apply(text: &str, action: &CodeAction) -> String {
+    let mut edits = action.edits.clone();
+    edits.sort_by_key(|edit| edit.span.start());
+    let mut result = text.to_string();
+    for edit in edits.iter().rev() {
+        let (start, end) = (
+            edit.span.start().to_usize() - 1,
+            edit.span.end().to_usize() - 1,
+        );
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+fn pos(text: &str, needle: &str) -> BytePos {
+    BytePos::from(text.find(needle).expect("Needle") as u32 + 1)
+}
+
+#[test]
+fn add_type_annotation() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let x = 1
+let f a b = a #Int+ b
+let g : Int = 2
+f x g
+"#;
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let expr = expr.expr();
+
+    let action = completion::add_type_annotation(&expr, pos(text, "x =")).unwrap();
+    assert_eq!(
+        apply(text, &action),
+        r#"
+let x : Int = 1
+let f a b = a #Int+ b
+let g : Int = 2
+f x g
+"#
+    );
+
+    let action = completion::add_type_annotation(&expr, pos(text, "f a")).unwrap();
+    assert_eq!(
+        apply(text, &action),
+        r#"
+let x = 1
+let f a b : Int = a #Int+ b
+let g : Int = 2
+f x g
+"#
+    );
+
+    assert_eq!(
+        completion::add_type_annotation(&expr, pos(text, "g :")),
+        None
+    );
+}
+
+#[test]
+fn insert_missing_fields() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let x = { a = 1 }
+let y = {}
+x
+"#;
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let expr = expr.expr();
+
+    let span = |needle: &str| {
+        let start = pos(text, needle);
+        Span::new(
+            start,
+            start + base::pos::ByteOffset::from(needle.len() as i64),
+        )
+    };
+
+    let action = completion::insert_missing_fields(&expr, span("{ a = 1 }"), &["b", "c"]).unwrap();
+    assert_eq!(
+        apply(text, &action),
+        r#"
+let x = { a = 1, b = error "TODO: b", c = error "TODO: c" }
+let y = {}
+x
+"#
+    );
+
+    let action = completion::insert_missing_fields(&expr, span("{}"), &["b"]).unwrap();
+    assert_eq!(
+        apply(text, &action),
+        r#"
+let x = { a = 1 }
+let y = { b = error "TODO: b" }
+x
+"#
+    );
+}
+
+#[test]
+fn add_infix_attribute() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let f x =
+    let (+++) l r = l
+    1 +++ 2
+f
+"#;
+    let expr = match support::parse_new(text) {
+        Ok(expr) => expr,
+        Err((Some(expr), _)) => expr,
+        Err((None, err)) => panic!("{}", err),
+    };
+    let source = FileMap::new("test".into(), text.into());
+
+    let action = completion::add_infix_attribute(&source, expr.expr(), "+++").unwrap();
+    assert_eq!(
+        apply(text, &action),
+        r#"
+let f x =
+    #[infix(left, 9)]
+    let (+++) l r = l
+    1 +++ 2
+f
+"#
+    );
+}
+
+#[test]
+fn remove_unused_bindings() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let used = 1
+/// Documentation
+let unused = 2
+let _ignored = 3
+let f x =
+    let inner = x
+    used
+f
+"#;
+    let (expr, result) = support::typecheck_expr(text);
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let expr = expr.expr();
+    let (_, metadata_map) = check::metadata::metadata(&MockEnv::new(), &expr);
+    let source = FileMap::new("test".into(), text.into());
+
+    let actions = completion::remove_unused_bindings(&metadata_map, &source, &expr);
+    let titles: Vec<_> = actions.iter().map(|action| &action.title[..]).collect();
+    assert_eq!(
+        titles,
+        [
+            "Remove the unused binding `unused`",
+            "Remove the unused binding `inner`"
+        ]
+    );
+    assert_eq!(
+        apply(text, &actions[0]),
+        r#"
+let used = 1
+let _ignored = 3
+let f x =
+    let inner = x
+    used
+f
+"#
+    );
+    assert_eq!(
+        apply(text, &actions[1]),
+        r#"
+let used = 1
+/// Documentation
+let unused = 2
+let _ignored = 3
+let f x =
+    used
+f
+"#
+    );
+}
@@ -0,0 +1,99 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::base::pos::BytePos;
+
+use crate::completion::{Definition, HoverOptions};
+
+#[allow(unused)]
+mod support;
+use crate::support::MockEnv;
+
+const TEXT: &str = r#"
+/// A point
+type Point = {
+    /// The x coordinate
+    x : Int,
+    y : Int
+}
+/// An optional value
+type Opt a =
+    | None
+    | Some a
+let p : Point = { x = 1, y = 2 }
+let o = Some p
+p.x
+"#;
+
+/// Returns the markdown shown when hovering the `n`th occurrence of `needle` and where it is
+/// defined
+fn hover(needle: &str, n: usize, options: &HoverOptions) -> (String, Option<usize>) {
+    let env = MockEnv::new();
+
+    let (expr, result) = support::typecheck_expr(TEXT);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+    let offset = TEXT.match_indices(needle).nth(n).expect("Needle").0;
+    let hover = completion::hover(
+        &env,
+        &metadata_map,
+        options,
+        expr.span,
+        &expr,
+        BytePos::from(offset as u32 + 1),
+    )
+    .expect("Hover");
+    let definition = match hover.definition {
+        Some(Definition::Local(span)) => Some(span.start().to_usize() - 1),
+        _ => None,
+    };
+    (hover.markdown, definition)
+}
+
+#[test]
+fn hover_binding() {
+    let _ = env_logger::try_init();
+
+    let (markdown, definition) = hover("p.x", 0, &HoverOptions::default());
+    assert_eq!(markdown, "```gluon\np : test.Point\n```\n\nA point");
+    assert_eq!(definition, TEXT.find("p :"));
+
+    let (markdown, _) = hover(
+        "p.x",
+        0,
+        &HoverOptions {
+            expand_aliases: true,
+        },
+    );
+    assert_eq!(
+        markdown,
+        "```gluon\np : { x : Int, y : Int }\n```\n\nA point"
+    );
+}
+
+#[test]
+fn hover_record_field() {
+    let _ = env_logger::try_init();
+
+    let expected = "```gluon\nx : Int\n```\n\nThe x coordinate";
+    assert_eq!(hover("x\n", 0, &HoverOptions::default()).0, expected);
+    assert_eq!(hover("x =", 0, &HoverOptions::default()).0, expected);
+}
+
+#[test]
+fn hover_variant_constructor() {
+    let _ = env_logger::try_init();
+
+    let (markdown, _) = hover("Some p", 0, &HoverOptions::default());
+    assert_eq!(
+        markdown,
+        "```gluon\nSome : test.Point -> test.Opt test.Point\n```\n\nAn optional value"
+    );
+}
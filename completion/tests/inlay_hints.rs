@@ -0,0 +1,65 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::completion::{InlayHintKind, InlayHintOptions};
+
+#[allow(unused)]
+mod support;
+
+/// Returns the text before each hint, up to the start of its line, with the hint appended
+fn hints(s: &str, options: &InlayHintOptions) -> Vec<String> {
+    let (expr, result) = support::typecheck_expr(s);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    completion::inlay_hints(options, expr.span, &expr)
+        .into_iter()
+        .map(|hint| {
+            assert_eq!(hint.kind, InlayHintKind::Type);
+            let end = hint.position.to_usize() - 1;
+            let start = s[..end].rfind('\n').map_or(0, |i| i + 1);
+            format!("{}{}", &s[start..end], hint.label)
+        })
+        .collect()
+}
+
+#[test]
+fn types_of_let_bindings() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let x = 1
+let annotated : Int = 2
+let add l r : Int -> Int -> Int = l #Int+ r
+let increment y = add y 1
+let (a, b) = (x, "")
+let id = \z -> z
+increment a
+"#;
+    assert_eq!(
+        hints(text, &InlayHintOptions::default()),
+        vec![
+            "let x: Int",
+            "let increment y: Int",
+            "let (a: Int",
+            "let (a, b: String",
+            "let id: forall a . a -> a",
+        ]
+    );
+}
+
+#[test]
+fn disabled_type_hints() {
+    let _ = env_logger::try_init();
+
+    let options = InlayHintOptions {
+        types: false,
+        ..InlayHintOptions::default()
+    };
+    assert_eq!(hints("let x = 1\nx", &options), Vec::<String>::new());
+}
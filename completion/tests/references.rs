@@ -0,0 +1,165 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::base::pos::{BytePos, Span};
+
+use crate::completion::{RenameError, SymbolIndex};
+
+#[allow(unused)]
+mod support;
+use crate::support::MockEnv;
+
+fn index(s: &str) -> SymbolIndex {
+    let env = MockEnv::new();
+
+    let (expr, result) = support::typecheck_expr(s);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+    SymbolIndex::new(&metadata_map, expr.span, &expr)
+}
+
+/// Returns the text of each reference to the binding at `pos`, with the offset it starts at
+fn references(s: &str, pos: usize) -> Vec<(usize, String)> {
+    let index = index(s);
+    let definition = index
+        .definition_at(BytePos::from(pos as u32))
+        .expect("Definition")
+        .clone();
+    let mut references: Vec<_> = index
+        .references(&definition)
+        .into_iter()
+        .map(|span| {
+            let (start, end) = (span.start().to_usize() - 1, span.end().to_usize() - 1);
+            (start, s[start..end].to_string())
+        })
+        .collect();
+    references.sort();
+    references
+}
+
+fn offsets(s: &str, needle: &str) -> Vec<usize> {
+    s.match_indices(needle).map(|(i, _)| i).collect()
+}
+
+#[test]
+fn references_to_let_binding() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+#[infix(left, 6)]
+let (+) l r = l
+let abc = 1
+let f x = x + abc
+let abc2 = abc
+f abc
+"#;
+    let expected: Vec<_> = offsets(text, "abc")
+        .into_iter()
+        .filter(|&i| &text[i..i + 4] != "abc2")
+        .map(|i| (i, "abc".to_string()))
+        .collect();
+    assert_eq!(expected.len(), 4);
+
+    let last_use = text.rfind("abc").unwrap();
+    assert_eq!(references(text, last_use + 1), expected);
+
+    let plus = offsets(text, "+");
+    assert_eq!(
+        references(text, plus[1] + 1),
+        vec![(plus[0] - 1, "(+)".to_string()), (plus[1], "+".to_string())]
+    );
+}
+
+#[test]
+fn references_respect_shadowing() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let x = 1
+let f x = x
+let y = x
+{ f, y }
+"#;
+    let xs = offsets(text, "x");
+    assert_eq!(
+        references(text, xs[2] + 1),
+        vec![(xs[1], "x".to_string()), (xs[2], "x".to_string())]
+    );
+    assert_eq!(
+        references(text, xs[3] + 1),
+        vec![(xs[0], "x".to_string()), (xs[3], "x".to_string())]
+    );
+}
+
+#[test]
+fn references_in_patterns_and_records() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let value = 1
+let record = { value, other = value }
+match record with
+| { value } -> value
+"#;
+    let values = offsets(text, "value");
+    assert_eq!(
+        references(text, values[0] + 1),
+        vec![
+            (values[0], "value".to_string()),
+            (values[1], "value".to_string()),
+            (values[2], "value".to_string()),
+        ]
+    );
+    assert_eq!(
+        references(text, values[4] + 1),
+        vec![
+            (values[3], "value".to_string()),
+            (values[4], "value".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn rename_binding() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+#[infix(left, 6)]
+let (+) l r = l
+let abc = 1
+let other = 2
+let f x = x + abc
+f abc
+"#;
+    let index = index(text);
+    let abc = index
+        .definition_at(BytePos::from(text.find("abc").unwrap() as u32 + 1))
+        .unwrap()
+        .clone();
+
+    let edits = completion::rename(&[("test", &index)], &abc, "renamed").unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].1.len(), 3);
+
+    let other = text.find("other").unwrap() as u32 + 1;
+    assert_eq!(
+        completion::rename(&[("test", &index)], &abc, "other"),
+        Err(RenameError::Conflict {
+            module: "test",
+            span: Span::new(BytePos::from(other), BytePos::from(other + 5)),
+        })
+    );
+    for invalid in &["let", "Abc", "not valid", "1abc", "+"] {
+        assert_eq!(
+            completion::rename(&[("test", &index)], &abc, invalid),
+            Err(RenameError::InvalidName(invalid.to_string()))
+        );
+    }
+}
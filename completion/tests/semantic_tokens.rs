@@ -0,0 +1,141 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::completion::TokenKind;
+
+#[allow(unused)]
+mod support;
+use crate::support::MockEnv;
+
+/// Returns the text, kind and whether the token is a definition for each token in `s`
+fn tokens(s: &str) -> Vec<(&str, TokenKind, bool)> {
+    let env = MockEnv::new();
+
+    let (expr, result) = support::typecheck_expr(s);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+    completion::semantic_tokens(&metadata_map, expr.span, &expr)
+        .into_iter()
+        .map(|token| {
+            let span = token.span;
+            (
+                &s[span.start().to_usize() - 1..span.end().to_usize() - 1],
+                token.kind,
+                token.definition,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn values_and_types() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Shape = | Circle Int | Square Int
+let area shape : Shape -> Int =
+    match shape with
+    | Circle r -> r
+    | Square w -> w
+let record = { shape = Circle 1 }
+area record.shape
+"#;
+    use crate::TokenKind::*;
+    assert_eq!(
+        tokens(text),
+        vec![
+            ("Shape", Type, true),
+            ("Circle", Constructor, true),
+            ("Int", Type, false),
+            ("Square", Constructor, true),
+            ("Int", Type, false),
+            ("area", Function, true),
+            ("shape", Parameter, true),
+            ("Shape", Type, false),
+            ("Int", Type, false),
+            ("shape", Parameter, false),
+            ("Circle", Constructor, false),
+            ("r", Variable, true),
+            ("r", Variable, false),
+            ("Square", Constructor, false),
+            ("w", Variable, true),
+            ("w", Variable, false),
+            ("record", Variable, true),
+            ("shape", Field, true),
+            ("Circle", Constructor, false),
+            ("area", Function, false),
+            ("record", Variable, false),
+            ("shape", Field, false),
+        ]
+    );
+}
+
+#[test]
+fn operators_have_fixities() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+#[infix(right, 5)]
+let (++) l r : Int -> Int -> Int = l
+1 ++ 2 #Int+ 3
+"#;
+    let env = MockEnv::new();
+    let (expr, result) = support::typecheck_expr(text);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+
+    let operators: Vec<_> = completion::semantic_tokens(&metadata_map, expr.span, &expr)
+        .into_iter()
+        .filter(|token| token.kind == TokenKind::Operator)
+        .map(|token| {
+            (
+                token.definition,
+                token.fixity.map(|fixity| fixity.to_string()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        operators,
+        vec![
+            (true, Some("infixr 5".to_string())),
+            (false, Some("infixr 5".to_string())),
+            (false, Some("infixl 6".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn implicit_arguments() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+let f ?x y : [Int] -> Int -> Int = x
+1
+"#;
+    let env = MockEnv::new();
+    let (expr, result) = support::typecheck_expr(text);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+
+    let implicits: Vec<_> = completion::semantic_tokens(&metadata_map, expr.span, &expr)
+        .into_iter()
+        .filter(|token| token.implicit)
+        .map(|token| {
+            let span = token.span;
+            (
+                &text[span.start().to_usize() - 1..span.end().to_usize() - 1],
+                token.definition,
+            )
+        })
+        .collect();
+    assert_eq!(implicits, vec![("x", true), ("x", false)]);
+}
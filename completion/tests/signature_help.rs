@@ -13,13 +13,30 @@ use crate::support::*;
 
 use crate::base::types::Type;
 
-use crate::completion::SignatureHelp;
+use crate::completion::{ParameterHelp, SignatureHelp};
 
 fn signature_help(expr_str: &str, row: usize, column: usize) -> Option<SignatureHelp> {
     let offset = loc(expr_str, row, column);
     let (expr, _result) = support::typecheck_partial_expr(expr_str);
     let expr = expr.expr();
-    completion::signature_help(&support::MockEnv::new(), expr.span, &expr, offset)
+    let env = support::MockEnv::new();
+    let (_, metadata_map) = check::metadata::metadata(&env, &expr);
+    completion::signature_help(&env, &metadata_map, expr.span, &expr, offset)
+}
+
+fn test_parameters() -> Vec<ParameterHelp> {
+    vec![
+        ParameterHelp {
+            name: Some("x".to_string()),
+            typ: typ("Int"),
+            comment: None,
+        },
+        ParameterHelp {
+            name: Some("y".to_string()),
+            typ: typ("String"),
+            comment: None,
+        },
+    ]
 }
 
 #[test]
@@ -38,6 +55,8 @@ test //
         name: "test".to_string(),
         typ: Type::function(vec![typ("Int"), typ("String")], typ("Int")),
         index: Some(0),
+        comment: None,
+        parameters: test_parameters(),
     });
 
     assert_eq!(result, expected);
@@ -59,6 +78,8 @@ test 123//
         name: "test".to_string(),
         typ: Type::function(vec![typ("Int"), typ("String")], typ("Int")),
         index: None,
+        comment: None,
+        parameters: test_parameters(),
     });
 
     assert_eq!(result, expected);
@@ -80,6 +101,8 @@ test 123 //
         name: "test".to_string(),
         typ: Type::function(vec![typ("Int"), typ("String")], typ("Int")),
         index: Some(1),
+        comment: None,
+        parameters: test_parameters(),
     });
 
     assert_eq!(result, expected);
@@ -101,6 +124,52 @@ test { x = "" }
         name: "".to_string(),
         typ: typ("String"),
         index: None,
+        comment: None,
+        parameters: Vec::new(),
+    });
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn parameter_documentation() {
+    let _ = env_logger::try_init();
+
+    let result = signature_help(
+        r#"
+let module =
+    /// Repeats `s`
+    ///
+    /// * `count` is the number of times to repeat it,
+    ///   which may be zero
+    /// * `s` is the string to repeat
+    let repeat count s : Int -> String -> String = s
+    { repeat }
+module.repeat 2 ""
+"#,
+        9,
+        16,
+    );
+    let comment = "Repeats `s`\n\n* `count` is the number of times to repeat it,\n  which may be zero\n* `s` is the string to repeat";
+    let expected = Some(SignatureHelp {
+        name: "repeat".to_string(),
+        typ: Type::function(vec![typ("Int"), typ("String")], typ("String")),
+        index: Some(1),
+        comment: Some(comment.to_string()),
+        parameters: vec![
+            ParameterHelp {
+                name: Some("count".to_string()),
+                typ: typ("Int"),
+                comment: Some(
+                    "`count` is the number of times to repeat it, which may be zero".to_string(),
+                ),
+            },
+            ParameterHelp {
+                name: Some("s".to_string()),
+                typ: typ("String"),
+                comment: Some("`s` is the string to repeat".to_string()),
+            },
+        ],
     });
 
     assert_eq!(result, expected);
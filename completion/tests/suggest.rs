@@ -15,10 +15,12 @@ use std::{
 use either::Either;
 
 use crate::base::ast::{expr_to_path, walk_mut_expr, Expr, MutVisitor, SpannedExpr, TypedIdent};
+use crate::base::kind::ArcKind;
 use crate::base::pos::{BytePos, Span};
+use crate::base::source::{FileMap, Source};
 use crate::base::symbol::Symbol;
-use crate::base::types::Type;
-use crate::completion::{Suggestion, SuggestionQuery};
+use crate::base::types::{ArcType, Type};
+use crate::completion::{Suggestion, SuggestionQuery, TypeMatch};
 
 #[allow(unused)]
 mod support;
@@ -70,6 +72,13 @@ fn suggest_query(query: &SuggestionQuery, s: &str, pos: BytePos) -> Result<Vec<S
     Ok(vec)
 }
 
+fn names_and_types(suggestions: Vec<Suggestion>) -> Vec<(String, Either<ArcKind, ArcType>)> {
+    suggestions
+        .into_iter()
+        .map(|suggestion| (suggestion.name, suggestion.typ))
+        .collect()
+}
+
 fn suggest_loc(s: &str, row: usize, column: usize) -> Result<Vec<String>, ()> {
     suggest(s, loc(s, row, column))
 }
@@ -210,12 +219,9 @@ a
 "#,
         BytePos::from(47),
     );
-    let expected = Ok(vec![Suggestion {
-        name: "aa".into(),
-        typ: Either::Right(Type::int()),
-    }]);
+    let expected = Ok(vec![("aa".to_string(), Either::Right(Type::int()))]);
 
-    assert_eq!(result, expected);
+    assert_eq!(result.map(names_and_types), expected);
 }
 
 #[test]
@@ -492,12 +498,9 @@ import! std.prelud
     let result = suggest_query(&query, text, loc(text, 1, 12));
     assert!(result.is_ok());
 
-    let expected = Ok(vec![Suggestion {
-        name: "prelude".into(),
-        typ: Either::Right(Type::int()),
-    }]);
+    let expected = Ok(vec![("prelude".to_string(), Either::Right(Type::int()))]);
 
-    assert_eq!(result, expected);
+    assert_eq!(result.map(names_and_types), expected);
 }
 
 #[test]
@@ -834,3 +837,130 @@ let abc = 1
 
     assert_eq!(result, expected);
 }
+
+/// Returns the suggestions at the end of `s` in the order they are ranked
+fn ranked_suggestions(s: &str) -> Vec<(String, TypeMatch)> {
+    ranked_suggestions_at(s, BytePos::from(s.trim_end().len() as u32 + 1))
+}
+
+fn ranked_suggestions_at(s: &str, pos: BytePos) -> Vec<(String, TypeMatch)> {
+    let env = MockEnv::new();
+
+    let (expr, _result) = support::typecheck_partial_expr(s);
+    let expr = expr.expr();
+
+    completion::suggest(&env, expr.span, &expr, pos)
+        .into_iter()
+        .map(|suggestion| (suggestion.name, suggestion.score.type_match))
+        .collect()
+}
+
+#[test]
+fn suggest_fuzzy_match() {
+    let _ = env_logger::try_init();
+
+    let result = ranked_suggestions(
+        r#"
+let list_length = 1
+let lost = 2
+lsl
+"#,
+    );
+    assert_eq!(
+        result,
+        vec![("list_length".to_string(), TypeMatch::Unknown)]
+    );
+}
+
+#[test]
+fn suggest_ranks_expected_type_first() {
+    let _ = env_logger::try_init();
+
+    let result = ranked_suggestions(
+        r#"
+let f : Int -> Int = \x -> x
+let a_string = ""
+let a_int = 1
+let a_fun : Int -> Int = \y -> y
+f a_
+"#,
+    );
+    assert_eq!(
+        result,
+        vec![
+            ("a_int".to_string(), TypeMatch::Exact),
+            ("a_fun".to_string(), TypeMatch::Returns),
+            ("a_string".to_string(), TypeMatch::Mismatch),
+        ]
+    );
+}
+
+#[test]
+fn suggest_ranks_closer_bindings_first() {
+    let _ = env_logger::try_init();
+
+    let result = ranked_suggestions(
+        r#"
+let value1 = 1
+let value2 = 2
+valu
+"#,
+    );
+    let names: Vec<_> = result.into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["value2", "value1"]);
+}
+
+/// Returns the suggestions at the end of `s` after repairing it with `repair_source`
+fn repaired_suggestions(s: &str) -> Vec<String> {
+    let source = FileMap::new("test".into(), s.into());
+    let end = source.span().end();
+    let repaired = completion::repair_source(&source, end).expect("Repaired source");
+    ranked_suggestions_at(&repaired, end)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+#[test]
+fn suggest_in_unclosed_brackets() {
+    let _ = env_logger::try_init();
+
+    let result = repaired_suggestions(
+        r#"
+let record = { aa = 1, ab = 2 }
+let f x = x
+f (record.a"#,
+    );
+    assert_eq!(result, ["aa", "ab"]);
+
+    let result = repaired_suggestions(
+        r#"
+let test = 1
+[1, { x = te"#,
+    );
+    assert_eq!(result, ["test"]);
+}
+
+#[test]
+fn suggest_in_unrecovered_expression() {
+    let _ = env_logger::try_init();
+
+    let result = repaired_suggestions(
+        r#"
+let test = 1
+match te with
+"#,
+    );
+    assert_eq!(result, ["test"]);
+}
+
+#[test]
+fn dont_repair_inside_string() {
+    let _ = env_logger::try_init();
+
+    let source = FileMap::new("test".into(), "let x = \"abc".into());
+    assert_eq!(
+        completion::repair_source(&source, source.span().end()),
+        None
+    );
+}
@@ -0,0 +1,132 @@
+#[macro_use]
+extern crate collect_mac;
+
+extern crate gluon_base as base;
+extern crate gluon_check as check;
+extern crate gluon_completion as completion;
+extern crate gluon_parser as parser;
+
+use crate::completion::{DocumentSymbol, SymbolKind};
+
+#[allow(unused)]
+mod support;
+
+fn document_symbols(s: &str) -> Vec<DocumentSymbol> {
+    let (expr, result) = support::typecheck_expr(s);
+    let expr = expr.expr();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    completion::document_symbols(expr.span, &expr)
+}
+
+/// Flattens `symbols` into the name, kind and depth of each symbol
+fn outline(symbols: &[DocumentSymbol]) -> Vec<(String, SymbolKind, usize)> {
+    fn go(symbols: &[DocumentSymbol], depth: usize, result: &mut Vec<(String, SymbolKind, usize)>) {
+        for symbol in symbols {
+            result.push((symbol.name.clone(), symbol.kind, depth));
+            go(&symbol.children, depth + 1, result);
+        }
+    }
+    let mut result = Vec::new();
+    go(symbols, 0, &mut result);
+    result
+}
+
+#[test]
+fn nested_bindings() {
+    let _ = env_logger::try_init();
+
+    let text = r#"
+type Shape = | Circle Int | Square Int
+type Point = { x : Int, y : Int }
+let origin = { x = 0, y = 0 }
+let area shape =
+    let square x = x
+    match shape with
+    | Circle r -> square r
+    | Square w -> square w
+let id = \x -> x
+{ area, origin, id }
+"#;
+    let symbols = document_symbols(text);
+    assert_eq!(
+        outline(&symbols),
+        vec![
+            ("Shape".to_string(), SymbolKind::Type, 0),
+            ("Circle".to_string(), SymbolKind::Constructor, 1),
+            ("Square".to_string(), SymbolKind::Constructor, 1),
+            ("Point".to_string(), SymbolKind::Type, 0),
+            ("x".to_string(), SymbolKind::Field, 1),
+            ("y".to_string(), SymbolKind::Field, 1),
+            ("origin".to_string(), SymbolKind::Variable, 0),
+            ("area".to_string(), SymbolKind::Function, 0),
+            ("square".to_string(), SymbolKind::Function, 1),
+            ("id".to_string(), SymbolKind::Function, 0),
+        ]
+    );
+
+    let area = &symbols[3];
+    let slice = |span: base::pos::Span<base::pos::BytePos>| {
+        &text[span.start().to_usize() - 1..span.end().to_usize() - 1]
+    };
+    assert_eq!(slice(area.name_span), "area");
+    assert!(slice(area.span).starts_with("area shape =\n"));
+    assert!(slice(area.span).ends_with("| Square w -> square w"));
+}
+
+#[test]
+fn fuzzy_match() {
+    assert!(completion::fuzzy_match("", "map").is_some());
+    assert!(completion::fuzzy_match("mp", "map").is_some());
+    assert!(completion::fuzzy_match("MAP", "map").is_some());
+    assert_eq!(completion::fuzzy_match("pm", "map"), None);
+    assert_eq!(completion::fuzzy_match("maps", "map"), None);
+
+    // Prefixes and word starts are preferred
+    assert!(completion::fuzzy_match("fm", "flat_map") > completion::fuzzy_match("fm", "from"));
+    assert!(completion::fuzzy_match("fla", "flat_map") > completion::fuzzy_match("fla", "fold_la"));
+    assert!(
+        completion::fuzzy_match("ot", "OrderedTree") > completion::fuzzy_match("ot", "Ordinate")
+    );
+}
+
+#[test]
+fn workspace_symbols() {
+    let _ = env_logger::try_init();
+
+    let list = document_symbols(
+        r#"
+type List a = | Cons a (List a) | Nil
+let flat_map f xs = Nil
+let from_array xs : Array Int -> List Int = Nil
+{ List, flat_map, from_array }
+"#,
+    );
+    let map = document_symbols(
+        r#"
+let fmap f =
+    let local_map x = x
+    local_map
+{ fmap }
+"#,
+    );
+    let modules = vec![("list", &list[..]), ("map", &map[..])];
+
+    let found: Vec<_> = completion::workspace_symbols(modules.iter().cloned(), "fm")
+        .into_iter()
+        .map(|symbol| (symbol.module, symbol.name))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("list".to_string(), "flat_map".to_string()),
+            ("map".to_string(), "fmap".to_string()),
+            ("list".to_string(), "from_array".to_string()),
+        ]
+    );
+
+    let cons = completion::workspace_symbols(modules.iter().cloned(), "cons");
+    assert_eq!(cons.len(), 1);
+    assert_eq!(cons[0].kind, SymbolKind::Constructor);
+    assert_eq!(cons[0].container, Some("List".to_string()));
+}
@@ -0,0 +1,80 @@
+(function () {
+    var input = document.getElementById("search");
+    var results = document.getElementById("search-results");
+    if (!input || !results || typeof searchIndex === "undefined") {
+        return;
+    }
+    var root = input.getAttribute("data-root");
+    var maxResults = 50;
+
+    // Exact matches of the unqualified name first, then prefix matches, then any match
+    function rank(entry, query) {
+        var name = entry.name.toLowerCase();
+        var last = name.substring(name.lastIndexOf(".") + 1);
+        var qualified = name.substring(name.indexOf(".") + 1);
+        if (last === query || name === query) {
+            return 0;
+        }
+        if (last.indexOf(query) === 0 || qualified.indexOf(query) === 0) {
+            return 1;
+        }
+        if (name.indexOf(query) !== -1 || entry.signature.toLowerCase().indexOf(query) !== -1) {
+            return 2;
+        }
+        return -1;
+    }
+
+    function search(query) {
+        var matches = [];
+        for (var i = 0; i < searchIndex.length; i++) {
+            var r = rank(searchIndex[i], query);
+            if (r !== -1) {
+                matches.push({ rank: r, entry: searchIndex[i] });
+            }
+        }
+        matches.sort(function (l, r) {
+            return l.rank - r.rank || l.entry.name.length - r.entry.name.length ||
+                (l.entry.name < r.entry.name ? -1 : l.entry.name > r.entry.name ? 1 : 0);
+        });
+        return matches.slice(0, maxResults).map(function (m) { return m.entry; });
+    }
+
+    function render(entries) {
+        results.innerHTML = "";
+        entries.forEach(function (entry) {
+            var link = document.createElement("a");
+            link.className = "list-group-item list-group-item-action";
+            link.href = root + entry.path;
+
+            var name = document.createElement("strong");
+            name.textContent = entry.name;
+            link.appendChild(name);
+
+            var kind = document.createElement("small");
+            kind.className = "text-muted";
+            kind.textContent = " " + entry.kind;
+            link.appendChild(kind);
+
+            if (entry.signature) {
+                var signature = document.createElement("span");
+                signature.className = "signature";
+                signature.textContent = entry.signature;
+                link.appendChild(signature);
+            }
+            results.appendChild(link);
+        });
+    }
+
+    input.addEventListener("input", function () {
+        var query = input.value.trim().toLowerCase();
+        render(query === "" ? [] : search(query));
+    });
+    input.addEventListener("keydown", function (event) {
+        if (event.key === "Escape") {
+            input.value = "";
+            render([]);
+        } else if (event.key === "Enter" && results.firstChild) {
+            window.location.href = results.firstChild.href;
+        }
+    });
+})();
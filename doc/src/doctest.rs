@@ -0,0 +1,306 @@
+//! Extraction and execution of the examples in documentation comments.
+//!
+//! Every code block in a doc comment (`///`, `/** */` or `//!`) which is not marked as being
+//! written in another language than `gluon` is a doctest. A doctest must evaluate to a test effect
+//! from `std.test`, such as `assert_eq (1 + 1) 2`, which is then run with `std.test.run_io`.
+//! Marking the block with `ignore` (```` ```gluon,ignore ````) skips it while `no_run` only
+//! typechecks it.
+
+use std::panic::AssertUnwindSafe;
+
+use {
+    anyhow::anyhow,
+    futures::prelude::*,
+    pulldown_cmark::{CodeBlockKind, Event, Parser, Tag},
+};
+
+use gluon::{
+    base::{
+        pos::{ByteOffset, BytePos, Span},
+        source::{FileMap, Source},
+        types::{ArcType, Type},
+    },
+    parser::{Token, Tokenizer},
+    vm::{
+        api::{generic::A, OpaqueValue, OwnedFunction, VmType, IO},
+        Error as VMError,
+    },
+    RootedThread, Thread, ThreadExt, VmBuilder,
+};
+
+use crate::Result;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// Typecheck and run the example
+    Run,
+    /// Only typecheck the example
+    NoRun,
+    Ignore,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Doctest {
+    /// The name of the documented item or, for module documentation, the module
+    pub item: String,
+    pub source: String,
+    pub mode: Mode,
+    /// The span of the code block in the doc comment, including its fences
+    pub span: Span<BytePos>,
+    /// The line the code block starts at, starting from 1
+    pub line: usize,
+}
+
+impl Doctest {
+    /// The name of the test in reports
+    pub fn name(&self) -> String {
+        format!("{} (line {})", self.item, self.line)
+    }
+}
+
+/// The text of a doc comment along with where each of its lines start in the source
+struct DocComment {
+    item: String,
+    content: String,
+    line_starts: Vec<BytePos>,
+}
+
+impl DocComment {
+    fn new(item: String) -> Self {
+        DocComment {
+            item,
+            content: String::new(),
+            line_starts: Vec::new(),
+        }
+    }
+
+    fn push_line(&mut self, start: BytePos, line: &str) {
+        if !self.line_starts.is_empty() {
+            self.content.push('\n');
+        }
+        self.content.push_str(line);
+        self.line_starts.push(start);
+    }
+
+    /// Returns the position in the source of `offset` in `content`
+    fn pos(&self, offset: usize) -> BytePos {
+        let before = &self.content[..offset];
+        let line = before.matches('\n').count();
+        let column = before.rfind('\n').map_or(offset, |i| offset - i - 1);
+        self.line_starts[line] + ByteOffset::from(column as i64)
+    }
+}
+
+/// Returns the mode of a code block, or `None` if it is not a gluon code block
+fn mode(kind: &CodeBlockKind) -> Option<Mode> {
+    let info = match kind {
+        CodeBlockKind::Indented => return Some(Mode::Run),
+        CodeBlockKind::Fenced(info) => info,
+    };
+    let mut mode = Mode::Run;
+    for word in info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+    {
+        match word {
+            "gluon" => (),
+            "ignore" => mode = Mode::Ignore,
+            "no_run" if mode == Mode::Run => mode = Mode::NoRun,
+            "no_run" => (),
+            _ => return None,
+        }
+    }
+    Some(mode)
+}
+
+/// Returns the module documentation (`//!` comments) of `source`
+fn module_comments(module: &str, source: &FileMap) -> Vec<DocComment> {
+    let mut comments = Vec::new();
+    let mut current: Option<DocComment> = None;
+    let mut offset = 0;
+    for line in source.src().split('\n') {
+        let start = source.span().start() + ByteOffset::from(offset as i64);
+        offset += line.len() + 1;
+
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("//!") {
+            let content_offset = line.len() - trimmed.len() + "//!".len();
+            let content = &line[content_offset..];
+            let (content, content_offset) = match content.strip_prefix(' ') {
+                Some(content) => (content, content_offset + 1),
+                None => (content, content_offset),
+            };
+            current
+                .get_or_insert_with(|| DocComment::new(module.to_string()))
+                .push_line(
+                    start + ByteOffset::from(content_offset as i64),
+                    content.trim_end_matches('\r'),
+                );
+        } else {
+            comments.extend(current.take());
+        }
+    }
+    comments.extend(current);
+    comments
+}
+
+/// Returns the documentation of the items in `source`, each named after the binding, type or
+/// field which follows the comment
+fn item_comments(module: &str, source: &FileMap) -> Vec<DocComment> {
+    let mut comments = Vec::new();
+    let mut current: Option<DocComment> = None;
+    // How many brackets deep the tokenizer is into attributes between a comment and its item
+    let mut attribute_depth = 0;
+
+    for token in Tokenizer::new(source) {
+        let token = match token {
+            Ok(token) => token,
+            Err(_) => break,
+        };
+        match token.value {
+            // The tokenizer keeps returning `EOF` at the end of the input
+            Token::EOF => break,
+            Token::DocComment(comment) => {
+                let start = token.span.start().absolute;
+                let token_src = source.src_slice(Span::new(start, token.span.end().absolute));
+                let offset = token_src.find(comment.content).unwrap_or(0);
+                let doc = current.get_or_insert_with(|| DocComment::new(module.to_string()));
+                let mut line_offset = 0;
+                for line in comment.content.split('\n') {
+                    doc.push_line(
+                        start + ByteOffset::from((offset + line_offset) as i64),
+                        line.trim_end_matches('\r'),
+                    );
+                    line_offset += line.len() + 1;
+                }
+            }
+            _ if current.is_none() => (),
+            Token::AttributeOpen => attribute_depth += 1,
+            Token::LBracket if attribute_depth != 0 => attribute_depth += 1,
+            Token::RBracket if attribute_depth != 0 => attribute_depth -= 1,
+            _ if attribute_depth != 0 => (),
+            Token::Let | Token::Rec | Token::Type | Token::LParen => (),
+            Token::Identifier(name) | Token::Operator(name) => {
+                let mut doc = current.take().unwrap();
+                doc.item = name.to_string();
+                comments.push(doc);
+            }
+            _ => comments.extend(current.take()),
+        }
+    }
+    comments.extend(current);
+    comments
+}
+
+/// Extracts the doctests in the doc comments of `source`, the source of the module `module`
+pub fn extract(module: &str, source: &FileMap) -> Vec<Doctest> {
+    let mut comments = module_comments(module, source);
+    comments.extend(item_comments(module, source));
+
+    let mut doctests = Vec::new();
+    for comment in &comments {
+        let mut current = None;
+        for (event, range) in Parser::new(&comment.content).into_offset_iter() {
+            match event {
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    current = mode(&kind).map(|mode| (mode, range.start, String::new()));
+                }
+                Event::Text(text) => {
+                    if let Some((_, _, code)) = &mut current {
+                        code.push_str(&text);
+                    }
+                }
+                Event::End(Tag::CodeBlock(_)) => {
+                    if let Some((mode, start, code)) = current.take() {
+                        let start = comment.pos(start);
+                        let end = comment.pos(range.end.min(comment.content.len()));
+                        doctests.push(Doctest {
+                            item: comment.item.clone(),
+                            source: code,
+                            mode,
+                            span: Span::new(start, end),
+                            line: source
+                                .location(start)
+                                .map_or(0, |location| location.line.to_usize() + 1),
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    doctests.sort_by_key(|doctest| doctest.span.start());
+    doctests
+}
+
+macro_rules! define_test_type {
+    ($name:ident $($args: ident)*) => {
+        impl VmType for $name {
+            type Type = $name;
+            fn make_type(vm: &Thread) -> ArcType {
+                let typ = concat!("std.test.", stringify!($name));
+                Type::app(
+                    vm.get_env().find_type_info(typ).unwrap().into_type(),
+                    vec![$($args::make_type(vm),)* Type::unit()].into_iter().collect(),
+                )
+            }
+        }
+    };
+}
+
+struct TestEffIO;
+
+define_test_type! { TestEffIO A }
+
+type TestEff = OpaqueValue<RootedThread, TestEffIO>;
+
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    err.downcast::<String>()
+        .map(|s| *s)
+        .or_else(|e| e.downcast::<&str>().map(|s| String::from(&s[..])))
+        .unwrap_or_else(|_| "Unknown panic".to_string())
+}
+
+async fn run_in(vm: &Thread, doctest: &Doctest) -> gluon::Result<IO<()>> {
+    // Naming the module after the item could shadow the module the example imports
+    let name = "doctest";
+    if doctest.mode == Mode::NoRun {
+        vm.typecheck_str_async(name, &doctest.source, None).await?;
+        return Ok(IO::Value(()));
+    }
+    let (test, _) = vm.run_expr_async::<TestEff>(name, &doctest.source).await?;
+    let mut run_io: OwnedFunction<fn(TestEff) -> IO<()>> = vm.get_global("std.test.run_io")?;
+    Ok(run_io.call_async(test).await?)
+}
+
+/// Runs `doctest` in a new vm, returning an error if it could not be compiled or if it failed
+pub fn run(doctest: &Doctest, use_std_lib: bool) -> impl Future<Output = Result<()>> + '_ {
+    // Not using `build_async` lets the doctest run without a tokio runtime. `build` blocks on its
+    // own executor so the vm must be built before the returned future is polled
+    let vm = match doctest.mode {
+        Mode::Ignore => None,
+        Mode::Run | Mode::NoRun => Some(VmBuilder::new().build()),
+    };
+
+    async move {
+        let vm = match vm {
+            Some(vm) => vm,
+            None => return Ok(()),
+        };
+        vm.get_database_mut()
+            .use_standard_lib(use_std_lib)
+            .run_io(true);
+        vm.load_file_async("std/test.glu").await?;
+
+        let result = AssertUnwindSafe(run_in(&vm, doctest)).catch_unwind().await;
+
+        match result {
+            Ok(Ok(IO::Value(()))) => Ok(()),
+            Ok(Ok(IO::Exception(err))) => Err(anyhow!("{}", err)),
+            // The stacktrace of a failed assertion only points into `std.test`
+            Ok(Err(gluon::Error::VM(VMError::Panic(err, _)))) => Err(anyhow!("{}", err.trim())),
+            Ok(Err(err)) => Err(err.into()),
+            Err(err) => Err(anyhow!("{}", panic_message(err))),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! The graph of which modules the documented modules import.
+//!
+//! The imports are found by scanning the source for `import!` so the graph can be generated even
+//! if the modules fail to typecheck, which they do if they import each other in a cycle.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use gluon::{
+    base::{filename_to_module, source::FileMap},
+    parser::{Token, Tokenizer},
+};
+
+/// Returns the modules which `source` imports with `import!`, in the order they are first
+/// imported
+pub fn imports(source: &FileMap) -> Vec<String> {
+    let mut tokens = Tokenizer::new(source)
+        .map_while(|token| token.ok())
+        .map(|token| token.value)
+        // The tokenizer keeps returning `EOF` at the end of the input
+        .take_while(|token| *token != Token::EOF)
+        .peekable();
+
+    let mut imports = Vec::new();
+    while let Some(token) = tokens.next() {
+        if token != Token::Identifier("import!") {
+            continue;
+        }
+        let module = match tokens.next() {
+            Some(Token::StringLiteral(filename)) => filename_to_module(&filename.unescape()),
+            Some(Token::Identifier(name)) => {
+                let mut module = name.to_string();
+                while tokens.peek() == Some(&Token::Dot) {
+                    tokens.next();
+                    match tokens.next() {
+                        Some(Token::Identifier(name)) => {
+                            module.push('.');
+                            module.push_str(name);
+                        }
+                        _ => break,
+                    }
+                }
+                module
+            }
+            _ => continue,
+        };
+        if !imports.contains(&module) {
+            imports.push(module);
+        }
+    }
+    imports
+}
+
+#[derive(Default, Debug)]
+pub struct ImportGraph {
+    /// The imports of each documented module
+    modules: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct GraphModule<'a> {
+    pub name: &'a str,
+    /// `true` if the module is imported but not documented
+    pub external: bool,
+    pub imports: &'a [String],
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Graph<'a> {
+    pub modules: Vec<GraphModule<'a>>,
+    pub cycles: Vec<Vec<&'a str>>,
+}
+
+impl ImportGraph {
+    pub fn insert(&mut self, module: String, imports: Vec<String>) {
+        self.modules.insert(module, imports);
+    }
+
+    /// Returns every module in the graph, including the imported modules which are not
+    /// documented
+    fn nodes(&self) -> BTreeSet<&str> {
+        self.modules
+            .iter()
+            .flat_map(|(module, imports)| Some(module).into_iter().chain(imports))
+            .map(|module| &module[..])
+            .collect()
+    }
+
+    fn imports_of(&self, module: &str) -> &[String] {
+        self.modules.get(module).map_or(&[], |imports| &imports[..])
+    }
+
+    /// Returns the groups of modules which (indirectly) import each other
+    pub fn cycles(&self) -> Vec<Vec<&str>> {
+        // Tarjan's strongly connected components algorithm
+        struct State<'a> {
+            graph: &'a ImportGraph,
+            next_index: usize,
+            indices: BTreeMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            on_stack: BTreeSet<&'a str>,
+            cycles: Vec<Vec<&'a str>>,
+        }
+
+        impl<'a> State<'a> {
+            fn visit(&mut self, module: &'a str) -> usize {
+                let index = self.next_index;
+                self.next_index += 1;
+                self.indices.insert(module, index);
+                self.stack.push(module);
+                self.on_stack.insert(module);
+
+                let mut low_link = index;
+                for import in self.graph.imports_of(module) {
+                    let import = &import[..];
+                    let import_low_link = match self.indices.get(import) {
+                        None => self.visit(import),
+                        Some(&import_index) if self.on_stack.contains(import) => import_index,
+                        Some(_) => continue,
+                    };
+                    low_link = low_link.min(import_low_link);
+                }
+
+                if low_link == index {
+                    let start = self.stack.iter().rposition(|m| *m == module).unwrap();
+                    let mut component = self.stack.split_off(start);
+                    for m in &component {
+                        self.on_stack.remove(m);
+                    }
+                    let imports_itself = self
+                        .graph
+                        .imports_of(module)
+                        .iter()
+                        .any(|import| import == module);
+                    if component.len() > 1 || imports_itself {
+                        component.sort();
+                        self.cycles.push(component);
+                    }
+                }
+                low_link
+            }
+        }
+
+        let mut state = State {
+            graph: self,
+            next_index: 0,
+            indices: BTreeMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            cycles: Vec::new(),
+        };
+        for module in self.nodes() {
+            if !state.indices.contains_key(module) {
+                state.visit(module);
+            }
+        }
+        let mut cycles = state.cycles;
+        cycles.sort();
+        cycles
+    }
+
+    /// Returns the graph in a form which can be serialized to JSON
+    pub fn graph(&self) -> Graph<'_> {
+        Graph {
+            modules: self
+                .nodes()
+                .into_iter()
+                .map(|name| GraphModule {
+                    name,
+                    external: !self.modules.contains_key(name),
+                    imports: self.imports_of(name),
+                })
+                .collect(),
+            cycles: self.cycles(),
+        }
+    }
+
+    /// Returns the graph in graphviz's DOT format. Modules and imports which are part of a cycle
+    /// are red and modules which are not documented are dashed.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let cycle_of = |module: &str| cycles.iter().position(|cycle| cycle.contains(&module));
+
+        let mut out = String::new();
+        out.push_str("digraph imports {\n");
+        for module in self.nodes() {
+            let mut attributes = Vec::new();
+            if !self.modules.contains_key(module) {
+                attributes.push("style = dashed");
+            }
+            if cycle_of(module).is_some() {
+                attributes.push("color = red");
+            }
+            write_attributes(&mut out, &format!("{:?}", module), &attributes);
+        }
+        for (module, imports) in &self.modules {
+            for import in imports {
+                let in_cycle = cycle_of(module).is_some() && cycle_of(module) == cycle_of(import);
+                write_attributes(
+                    &mut out,
+                    &format!("{:?} -> {:?}", module, import),
+                    if in_cycle { &["color = red"][..] } else { &[] },
+                );
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn write_attributes(out: &mut String, statement: &str, attributes: &[&str]) {
+    write!(out, "    {}", statement).unwrap();
+    if !attributes.is_empty() {
+        write!(out, " [{}]", attributes.join(", ")).unwrap();
+    }
+    out.push_str(";\n");
+}
@@ -12,8 +12,10 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     fs::{self, create_dir_all, File},
     io::{self, Read},
+    mem,
     path::{Path, PathBuf},
     result::Result as StdResult,
+    sync::Arc,
 };
 
 use {
@@ -28,10 +30,11 @@ use {
 
 use gluon::{
     base::{
+        ast::OwnedExpr,
         filename_to_module,
         fnv::FnvMap,
         metadata::Metadata,
-        source::Source,
+        source::{FileMap, Source},
         symbol::{Name, Symbol},
         types::{ArcType, ArgType, Type, TypeExt, TypePtr},
     },
@@ -39,6 +42,9 @@ use gluon::{
     Thread, ThreadExt,
 };
 
+pub mod doctest;
+pub mod graph;
+
 pub type Error = anyhow::Error;
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -65,14 +71,74 @@ pub struct Argument {
 #[derive(Serialize, PartialEq, Debug)]
 pub struct Field {
     pub name: String,
+    /// The id of the element documenting this field
+    pub anchor: String,
     pub args: Vec<Argument>,
     #[serde(rename = "type")]
     pub typ: String,
+    /// The type of the field as plain text, used by the search index
+    pub signature: String,
     pub attributes: String,
     pub comment: String,
     pub definition_line: Option<u32>,
 }
 
+/// Maps the fully qualified name of each documented type to the module and name it is documented
+/// under, so that types can be linked to from any module
+#[derive(PartialEq, Debug, Default)]
+pub struct TypeIndex {
+    types: BTreeMap<String, (String, String)>,
+}
+
+impl TypeIndex {
+    /// Adds the types exported by `module`
+    pub fn insert_module(&mut self, module: &str, typ: &ArcType, meta: &Metadata) {
+        for field in typ.type_field_iter() {
+            if hidden(meta, field.name.as_ref()) {
+                continue;
+            }
+            let name = field.name.definition_name().to_string();
+            let qualified_name = field.typ.name.as_ref();
+
+            // Prefer the module which defines the type over the modules that re-export it
+            let defined_here = Name::new(qualified_name).module().as_str() == module;
+            match self.types.get(qualified_name) {
+                Some(_) if !defined_here => (),
+                _ => {
+                    self.types.insert(
+                        qualified_name.to_string(),
+                        (module.to_string(), name.clone()),
+                    );
+                }
+            }
+            self.types
+                .entry(format!("{}.{}", module, name))
+                .or_insert_with(|| (module.to_string(), name));
+        }
+    }
+
+    /// Returns a link, relative to `current_module`, to the documentation of the type `name`
+    pub fn link(&self, current_module: &str, name: &str) -> Option<String> {
+        self.types
+            .get(name)
+            .map(|(module, typ)| symbol_link(current_module, module, &anchor("type", typ)))
+    }
+}
+
+/// Returns the id used for the documentation of `name`. Operators are encoded so that the id
+/// stays the same between runs and can be used in a url without escaping.
+pub fn anchor(kind: &str, name: &str) -> String {
+    let mut anchor = format!("{}.", kind);
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            anchor.push(c);
+        } else {
+            anchor.push_str(&format!("-{:x}", c as u32));
+        }
+    }
+    anchor
+}
+
 struct SymbolLinkRenderer {
     escaped: String,
     un_escaped: String,
@@ -115,9 +181,8 @@ impl pretty::RenderAnnotated<'_, String> for SymbolLinkRenderer {
     }
 }
 
-fn print_type(current_module: &str, typ: &ArcType) -> String {
-    let annotate_symbol =
-        |symbol: &Symbol| Some(symbol_link(false, current_module, symbol.as_ref()));
+fn print_type(current_module: &str, index: &TypeIndex, typ: &ArcType) -> String {
+    let annotate_symbol = |symbol: &Symbol| index.link(current_module, symbol.as_ref());
     let arena = Arena::new();
     let mut doc = typ
         .display(80)
@@ -140,6 +205,17 @@ fn print_type(current_module: &str, typ: &ArcType) -> String {
     renderer.finish()
 }
 
+/// Prints `typ` on a single line without any markup
+fn print_signature(typ: &ArcType) -> String {
+    // Variants are always printed on multiple lines so the whitespace is collapsed afterwards
+    typ.display(usize::max_value() / 2)
+        .symbol_text(&|s: &Symbol| s.declared_name())
+        .to_string()
+        .split_whitespace()
+        .format(" ")
+        .to_string()
+}
+
 fn hidden(meta: &Metadata, field: &str) -> bool {
     meta.module.get(field).map_or(false, |meta| {
         meta.attributes().any(|attr| {
@@ -150,6 +226,7 @@ fn hidden(meta: &Metadata, field: &str) -> bool {
 
 pub fn record(
     current_module: &str,
+    index: &TypeIndex,
     typ: &ArcType,
     symbols: &FnvMap<&Symbol, completion::SpCompletionSymbol>,
     source: &dyn Source,
@@ -195,8 +272,11 @@ pub fn record(
                     }
                 }
 
+                let name = field.name.definition_name().to_string();
+                let typ = field.typ.unresolved_type().remove_forall();
                 Field {
-                    name: field.name.definition_name().to_string(),
+                    anchor: anchor("type", &name),
+                    name,
                     args: field
                         .typ
                         .params()
@@ -206,7 +286,8 @@ pub fn record(
                             name: gen.id.to_string(),
                         })
                         .collect(),
-                    typ: print_type(current_module, &field.typ.unresolved_type().remove_forall()),
+                    typ: print_type(current_module, index, &typ),
+                    signature: print_signature(&typ),
                     attributes,
                     comment,
                     definition_line,
@@ -253,10 +334,13 @@ pub fn record(
                     }
                 }
 
+                let name = field.name.definition_name().to_string();
                 Field {
-                    name: field.name.definition_name().to_string(),
+                    anchor: anchor("value", &name),
+                    name,
                     args,
-                    typ: print_type(current_module, &field.typ),
+                    typ: print_type(current_module, index, &field.typ),
+                    signature: print_signature(&field.typ),
                     attributes,
                     comment,
                     definition_line,
@@ -278,29 +362,34 @@ pub struct TemplateModule<'a> {
 
 const MODULE_TEMPLATE: &str = "module";
 
-fn symbol_link(index: bool, current_module: &str, param: &str) -> String {
-    let skipped = if index { 0 } else { 1 };
-
-    let parts: Vec<_> = param.split('.').collect();
-    let (typ, module_parts) = parts.split_last().unwrap();
+fn root_path(current_module: &str) -> String {
+    current_module
+        .split('.')
+        .skip(1)
+        .map(|_| "../")
+        .format("")
+        .to_string()
+}
 
+fn symbol_link(current_module: &str, module: &str, anchor: &str) -> String {
     format!(
-        "{}{}.html#type.{}",
-        current_module
-            .split('.')
-            .skip(skipped)
-            .map(|_| "../")
-            .format(""),
-        module_parts.iter().format("/"),
-        typ
+        "{}{}.html#{}",
+        root_path(current_module),
+        module.replace(".", "/"),
+        anchor
     )
 }
 
 fn module_link(current_module: &str, param: &str) -> String {
+    module_link_with_extension(current_module, param, "html")
+}
+
+fn module_link_with_extension(current_module: &str, param: &str, extension: &str) -> String {
     format!(
-        "{}{}.html",
-        current_module.split('.').skip(1).map(|_| "../").format(""),
+        "{}{}.{}",
+        root_path(current_module),
         param.replace(".", "/"),
+        extension
     )
 }
 
@@ -403,7 +492,7 @@ fn handlebars() -> Result<Handlebars> {
         out: &mut dyn Output,
     ) -> ::std::result::Result<(), RenderError> {
         let current_module = &context.data()["name"].as_str().expect("name").to_string();
-        let relative_path = current_module.split('.').skip(1).map(|_| "../").format("");
+        let relative_path = root_path(current_module);
 
         out.write(&format!(r#"
 <link rel="stylesheet" href="https://maxcdn.bootstrapcdn.com/bootstrap/4.0.0/css/bootstrap.min.css" integrity="sha384-Gn5384xqQ1aoWXA+058RXPxPg6fy4IWvTNh0E263XmFcJlSAwiGgFAW/dAiS6JXm" crossorigin="anonymous">
@@ -413,6 +502,19 @@ fn handlebars() -> Result<Handlebars> {
     }
     reg.register_helper("style", Box::new(style));
 
+    fn root_path_helper(
+        _: &Helper,
+        _: &Handlebars,
+        context: &Context,
+        _: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> ::std::result::Result<(), RenderError> {
+        let current_module = context.data()["name"].as_str().expect("name");
+        out.write(&root_path(current_module))?;
+        Ok(())
+    }
+    reg.register_helper("root_path", Box::new(root_path_helper));
+
     fn markdown(
         h: &Helper,
         _: &Handlebars,
@@ -486,47 +588,149 @@ where
     Ok(())
 }
 
+/// Writes `module` as markdown, suitable for embedding in mdBook or a static site
+pub fn generate_markdown<W>(out: &mut W, module: &TemplateModule) -> Result<()>
+where
+    W: io::Write,
+{
+    trace!("DOC: {:?}", module);
+
+    writeln!(out, "# {}", module.name)?;
+    if !module.comment.is_empty() {
+        writeln!(out, "\n{}", module.comment.trim_end())?;
+    }
+
+    if !module.sub_modules.is_empty() {
+        writeln!(out, "\n## Modules\n")?;
+        for sub_module in &module.sub_modules {
+            write!(
+                out,
+                "- [{}]({})",
+                sub_module.name,
+                module_link_with_extension(module.name, &sub_module.name, "md")
+            )?;
+            let first_paragraph = sub_module
+                .comment
+                .lines()
+                .map(|s| s.trim())
+                .take_while(|s| !s.is_empty())
+                .format(" ")
+                .to_string();
+            if !first_paragraph.is_empty() {
+                write!(out, ": {}", first_paragraph)?;
+            }
+            writeln!(out)?;
+        }
+    }
+
+    let write_comment = |out: &mut W, field: &Field| -> io::Result<()> {
+        if !field.comment.is_empty() {
+            writeln!(out, "\n{}", field.comment.trim_end())?;
+        }
+        Ok(())
+    };
+
+    if !module.record.types.is_empty() {
+        writeln!(out, "\n## Types")?;
+        for field in &module.record.types {
+            writeln!(
+                out,
+                "\n### `{name}`\n\n```gluon\n{attributes}type {name}{args} = {typ}\n```",
+                name = field.name,
+                attributes = field.attributes,
+                args = field
+                    .args
+                    .iter()
+                    .format_with("", |arg, f| f(&format_args!(" {}", arg.name))),
+                typ = field.signature,
+            )?;
+            write_comment(out, field)?;
+        }
+    }
+
+    if !module.record.values.is_empty() {
+        writeln!(out, "\n## Values")?;
+        for field in &module.record.values {
+            writeln!(
+                out,
+                "\n### `{name}`\n\n```gluon\n{attributes}let {name}{args} : {typ}\n```",
+                name = field.name,
+                attributes = field.attributes,
+                args = field.args.iter().format_with("", |arg, f| {
+                    f(&format_args!(
+                        " {}{}",
+                        if arg.implicit { "?" } else { "" },
+                        arg.name
+                    ))
+                }),
+                typ = field.signature,
+            )?;
+            write_comment(out, field)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_gluon_file(entry: &walkdir::DirEntry) -> bool {
+    entry.file_type().is_file()
+        && entry.path().extension().and_then(|ext| ext.to_str()) == Some("glu")
+}
+
+/// Returns the name of the module at `path`, relative to the `parent` directory
+fn module_name(parent: Option<&Path>, path: &Path) -> Result<String> {
+    let module_path = parent
+        .and_then(|parent| path.strip_prefix(parent).ok())
+        .unwrap_or(path);
+    Ok(filename_to_module(
+        module_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Non-UTF-8 filename"))?,
+    ))
+}
+
+/// A module which has been typechecked but not yet documented
+struct CheckedModule {
+    name: String,
+    comment: String,
+    expr: Arc<OwnedExpr<Symbol>>,
+    typ: ArcType,
+    meta: Arc<Metadata>,
+}
+
 struct DocCollector<'a> {
     directories: BTreeMap<String, BTreeMap<String, Module>>,
     modules: BTreeSet<String>,
+    checked: Vec<CheckedModule>,
+    index: TypeIndex,
+    /// The doctests of the typechecked modules, if they should be run
+    doctests: Option<Vec<(PathBuf, doctest::Doctest)>>,
     content: String,
     parent: Option<&'a Path>,
-    out_path: &'a Path,
     thread: &'a Thread,
 }
 
 impl DocCollector<'_> {
     fn try_add_path(&mut self, entry: walkdir::DirEntry) -> Result<()> {
-        if !entry.file_type().is_file()
-            || entry.path().extension().and_then(|ext| ext.to_str()) != Some("glu")
-        {
+        if !is_gluon_file(&entry) {
             return Ok(());
         }
 
-        let module = self.module_for(entry.path())?;
+        let module = self.typecheck(entry.path())?;
 
-        let DocCollector {
-            directories,
-            modules,
-            ..
-        } = self;
-
-        modules.insert(module.name.clone());
-        let name = Name::new(&module.name);
-        directories
-            .entry(name.module().as_str().to_owned())
-            .or_default()
-            .insert(name.name().as_str().to_owned(), module);
+        self.index
+            .insert_module(&module.name, &module.typ, &module.meta);
+        self.checked.push(module);
 
         Ok(())
     }
 
-    fn module_for(&mut self, path: &Path) -> Result<Module> {
+    fn typecheck(&mut self, path: &Path) -> Result<CheckedModule> {
         let DocCollector {
             content,
             parent,
-            out_path,
             thread,
+            doctests,
             ..
         } = self;
 
@@ -538,19 +742,22 @@ impl DocCollector<'_> {
         content.clear();
         input.read_to_string(content)?;
 
-        let module_path = parent
-            .and_then(|parent| path.strip_prefix(parent).ok())
-            .unwrap_or(path);
-        let name = filename_to_module(
-            module_path
-                .to_str()
-                .ok_or_else(|| anyhow!("Non-UTF-8 filename"))?,
-        );
+        let name = module_name(*parent, path)?;
 
         let (expr, typ) = thread.typecheck_str(&name, &content, None)?;
         let (meta, _) = metadata(&thread.get_database().as_env(), &expr.expr());
 
-        create_dir_all(out_path.join(module_path.parent().unwrap_or(Path::new(""))))?;
+        if let Some(doctests) = doctests {
+            let source = thread
+                .get_database()
+                .get_filemap(&name)
+                .expect("SourceMap not inserted by compilation");
+            doctests.extend(
+                doctest::extract(&name, &source)
+                    .into_iter()
+                    .map(|doctest| (path.to_owned(), doctest)),
+            );
+        }
 
         let comment = content
             .lines()
@@ -561,7 +768,47 @@ impl DocCollector<'_> {
             .format("\n")
             .to_string();
 
-        let source = thread
+        Ok(CheckedModule {
+            name,
+            comment,
+            expr,
+            typ,
+            meta,
+        })
+    }
+
+    /// Documents the typechecked modules. Called once every module has been typechecked so that
+    /// types from any module can be linked to.
+    fn document_modules(&mut self) {
+        for module in mem::replace(&mut self.checked, Vec::new()) {
+            let module = self.module_for(module);
+
+            let DocCollector {
+                directories,
+                modules,
+                ..
+            } = self;
+
+            modules.insert(module.name.clone());
+            let name = Name::new(&module.name);
+            directories
+                .entry(name.module().as_str().to_owned())
+                .or_default()
+                .insert(name.name().as_str().to_owned(), module);
+        }
+    }
+
+    fn module_for(&self, module: CheckedModule) -> Module {
+        let CheckedModule {
+            name,
+            comment,
+            expr,
+            typ,
+            meta,
+        } = module;
+
+        let source = self
+            .thread
             .get_database()
             .get_filemap(&name)
             .expect("SourceMap not inserted by compilation");
@@ -571,15 +818,50 @@ impl DocCollector<'_> {
             .map(|s| (s.value.name, s))
             .collect::<FnvMap<_, _>>();
 
-        Ok(Module {
-            record: record(&name, &typ, &symbols, &*source, &meta),
+        Module {
+            record: record(&name, &self.index, &typ, &symbols, &*source, &meta),
             name,
             github_source: meta
                 .get_attribute("github")
                 .map(|s| s.trim_matches('"').to_string()),
             comment,
-        })
+        }
+    }
+}
+
+/// An item which can be searched for in the generated documentation
+#[derive(Serialize, PartialEq, Debug)]
+pub struct SearchEntry {
+    pub name: String,
+    pub kind: &'static str,
+    pub signature: String,
+    /// The location of the item, relative to the root of the documentation
+    pub path: String,
+}
+
+/// Returns the entries of the search index for `modules`
+pub fn search_index<'a>(modules: impl IntoIterator<Item = &'a Module>) -> Vec<SearchEntry> {
+    let mut entries = Vec::new();
+    for module in modules {
+        let path = module_link("", &module.name);
+        entries.push(SearchEntry {
+            name: module.name.clone(),
+            kind: "module",
+            signature: String::new(),
+            path: path.clone(),
+        });
+        let fields = (module.record.types.iter().map(|field| ("type", field)))
+            .chain(module.record.values.iter().map(|field| ("value", field)));
+        for (kind, field) in fields {
+            entries.push(SearchEntry {
+                name: format!("{}.{}", module.name, field.name),
+                kind,
+                signature: field.signature.clone(),
+                path: format!("{}#{}", path, field.anchor),
+            });
+        }
     }
+    entries
 }
 
 pub fn generate_for_path<P, Q>(thread: &Thread, path: &P, out_path: &Q) -> Result<()>
@@ -592,6 +874,9 @@ where
             input: path.as_ref().to_owned(),
             output: out_path.as_ref().to_owned(),
             src_url: None,
+            format: Format::Html,
+            test: false,
+            import_graph: false,
         },
         thread,
     )
@@ -601,26 +886,39 @@ pub fn generate(options: &Options, thread: &Thread) -> Result<()> {
     let Options {
         input: path,
         output: out_path,
-        src_url,
+        format,
+        test,
+        import_graph,
+        ..
     } = options;
 
+    // Written before typechecking as modules which import each other fail to typecheck
+    if *import_graph {
+        generate_import_graph(path, out_path)?;
+    }
+
     thread.get_database_mut().full_metadata(true);
 
     let mut collector = DocCollector {
         directories: BTreeMap::new(),
         modules: BTreeSet::new(),
+        checked: Vec::new(),
+        index: TypeIndex::default(),
+        doctests: if *test { Some(Vec::new()) } else { None },
         parent: path.parent(),
         content: String::new(),
         thread,
-        out_path,
     };
 
     for entry in walkdir::WalkDir::new(path) {
         collector.try_add_path(entry?)?;
     }
+    collector.document_modules();
 
     let DocCollector {
-        mut directories, ..
+        mut directories,
+        doctests,
+        ..
     } = collector;
 
     let directory_modules = directories.keys().cloned().collect::<BTreeSet<_>>();
@@ -642,6 +940,73 @@ pub fn generate(options: &Options, thread: &Thread) -> Result<()> {
             });
     }
 
+    match format {
+        Format::Json => generate_json(out_path, &directories)?,
+        Format::Html | Format::Markdown => generate_modules(options, &directories)?,
+    }
+
+    if let Some(doctests) = doctests {
+        run_doctests(&doctests)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the graph of the imports of the modules in `path` to `imports.dot` and `imports.json`
+fn generate_import_graph(path: &Path, out_path: &Path) -> Result<()> {
+    let mut graph = graph::ImportGraph::default();
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry?;
+        if !is_gluon_file(&entry) {
+            continue;
+        }
+        let content = fs::read_to_string(entry.path())
+            .with_context(|| format!("Unable to open gluon file `{}`", entry.path().display()))?;
+        let name = module_name(path.parent(), entry.path())?;
+        let imports = graph::imports(&FileMap::new(name.clone(), content));
+        graph.insert(name, imports);
+    }
+
+    for cycle in graph.cycles() {
+        warn!("Cyclic imports between {}", cycle.iter().format(", "));
+    }
+
+    create_dir_all(out_path)?;
+    fs::write(out_path.join("imports.dot"), graph.to_dot())?;
+    let out_path = out_path.join("imports.json");
+    let graph_file = File::create(&*out_path)
+        .with_context(|| format!("Unable to open output file `{}`", out_path.display()))?;
+    serde_json::to_writer_pretty(graph_file, &graph.graph())?;
+    Ok(())
+}
+
+fn generate_json(
+    out_path: &Path,
+    directories: &BTreeMap<String, BTreeMap<String, Module>>,
+) -> Result<()> {
+    create_dir_all(out_path)?;
+    let modules = directories
+        .values()
+        .flat_map(|modules| modules.values())
+        .collect::<Vec<_>>();
+    let out_path = out_path.join("doc.json");
+    let doc_file = File::create(&*out_path)
+        .with_context(|| format!("Unable to open output file `{}`", out_path.display()))?;
+    serde_json::to_writer_pretty(doc_file, &modules)?;
+    Ok(())
+}
+
+fn generate_modules(
+    options: &Options,
+    directories: &BTreeMap<String, BTreeMap<String, Module>>,
+) -> Result<()> {
+    let Options {
+        output: out_path,
+        src_url,
+        format,
+        ..
+    } = options;
+
     let reg = handlebars()?;
 
     directories
@@ -650,44 +1015,143 @@ pub fn generate(options: &Options, thread: &Thread) -> Result<()> {
         .par_bridge()
         .try_for_each(|(modules, module)| -> Result<()> {
             let module_path = PathBuf::from(module.name.replace(".", "/"));
-            let out_path = out_path.join(&module_path).with_extension("html");
+            let out_path = out_path
+                .join(&module_path)
+                .with_extension(format.extension());
+            create_dir_all(out_path.parent().unwrap_or(Path::new("")))?;
             let mut doc_file = File::create(&*out_path)
                 .with_context(|| format!("Unable to open output file `{}`", out_path.display()))?;
 
-            generate_module(
-                &reg,
-                &mut doc_file,
-                &TemplateModule {
-                    name: &module.name,
-                    src_url: src_url.as_ref().map(|s| &s[..]),
-                    comment: &module.comment,
-                    record: &module.record,
-                    sub_modules: directories
-                        .get(&module.name)
-                        .iter()
-                        .flat_map(|sub_modules| sub_modules.values())
-                        .collect(),
-                    sibling_modules: modules.keys().map(|s| s as &str).collect(),
-                },
-            )?;
+            let template_module = TemplateModule {
+                name: &module.name,
+                src_url: src_url.as_ref().map(|s| &s[..]),
+                comment: &module.comment,
+                record: &module.record,
+                sub_modules: directories
+                    .get(&module.name)
+                    .iter()
+                    .flat_map(|sub_modules| sub_modules.values())
+                    .collect(),
+                sibling_modules: modules.keys().map(|s| s as &str).collect(),
+            };
+            match format {
+                Format::Markdown => generate_markdown(&mut doc_file, &template_module)?,
+                Format::Html | Format::Json => {
+                    generate_module(&reg, &mut doc_file, &template_module)?
+                }
+            }
 
             debug!("Documented {}", module.name);
 
             Ok(())
         })?;
 
+    if *format == Format::Markdown {
+        return Ok(());
+    }
+
     fs::write(
         out_path.join("style.css"),
         &include_bytes!("doc/style.css")[..],
     )?;
+    fs::write(
+        out_path.join("search.js"),
+        &include_bytes!("doc/search.js")[..],
+    )?;
+
+    let search_index = search_index(directories.values().flat_map(|modules| modules.values()));
+    fs::write(
+        out_path.join("search-index.js"),
+        format!(
+            "var searchIndex = {};\n",
+            serde_json::to_string(&search_index)?
+        ),
+    )?;
 
     Ok(())
 }
 
+/// Runs `doctests`, returning an error which lists every failed doctest
+fn run_doctests(doctests: &[(PathBuf, doctest::Doctest)]) -> Result<()> {
+    // gluon's compiler is recursive so the threads need more than rayon's default stack
+    let pool = rayon::ThreadPoolBuilder::new()
+        .stack_size(8 * 1024 * 1024)
+        .build()?;
+    let failures = pool.install(|| {
+        doctests
+            .par_iter()
+            .filter_map(|(path, doctest)| {
+                let result = futures::executor::block_on(doctest::run(doctest, true));
+                debug!("Ran doctest {}", doctest.name());
+                result.err().map(|err| {
+                    format!(
+                        "{}:{}: {}\n{}",
+                        path.display(),
+                        doctest.line,
+                        doctest.name(),
+                        err
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} doctests failed\n\n{}",
+            failures.len(),
+            doctests.len(),
+            failures.iter().format("\n\n")
+        ))
+    }
+}
+
+/// The kind of documentation to generate
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// A website with one page per module
+    Html,
+    /// One markdown file per module
+    Markdown,
+    /// A single `doc.json` file describing all documented items
+    Json,
+}
+
+impl Format {
+    /// The extension of the files generated for each module
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Markdown => "md",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "html" => Format::Html,
+            "markdown" | "md" => Format::Markdown,
+            "json" => Format::Json,
+            _ => return Err(anyhow!("Unknown documentation format `{}`", s)),
+        })
+    }
+}
+
 pub struct Options {
     pub src_url: Option<String>,
     pub input: PathBuf,
     pub output: PathBuf,
+    pub format: Format,
+    /// Runs the doctests of the documented modules, see `doctest`
+    pub test: bool,
+    /// Writes the import graph of the documented modules, see `graph`
+    pub import_graph: bool,
 }
 
 impl From<&'_ Opt> for Options {
@@ -696,6 +1160,9 @@ impl From<&'_ Opt> for Options {
             src_url: opt.src_url.clone(),
             input: opt.input.clone().into(),
             output: opt.output.clone().into(),
+            format: opt.format,
+            test: opt.test,
+            import_graph: opt.import_graph,
         }
     }
 }
@@ -713,6 +1180,17 @@ pub struct Opt {
     #[structopt(long = "src-url")]
     #[structopt(help = "Where the source can be found")]
     pub src_url: Option<String>,
+    #[structopt(long = "format", default_value = "html")]
+    #[structopt(help = "The output format: `html`, `markdown` or `json`")]
+    pub format: Format,
+    #[structopt(long = "test")]
+    #[structopt(help = "Runs the examples in the documentation comments")]
+    pub test: bool,
+    #[structopt(long = "import-graph")]
+    #[structopt(
+        help = "Writes the import graph of the modules to `imports.dot` and `imports.json`"
+    )]
+    pub import_graph: bool,
     #[structopt(help = "Documents the file or directory")]
     pub input: String,
     #[structopt(help = "Outputs the documentation to this directory")]
@@ -30,9 +30,12 @@ fn main_() -> Result<(), anyhow::Error> {
     gluon_doc::generate(&gluon_doc::Options::from(&opt), &gluon::new_vm())?;
 
     if opt.open {
-        let path = Path::new(&opt.output)
-            .join(&opt.input)
-            .with_extension("html");
+        let path = match opt.format {
+            gluon_doc::Format::Json => Path::new(&opt.output).join("doc.json"),
+            format => Path::new(&opt.output)
+                .join(&opt.input)
+                .with_extension(format.extension()),
+        };
         eprintln!("Opening {}", path.display());
         opener::open(path)?;
     }
@@ -2,9 +2,13 @@ use std::{fs, path::Path};
 
 use {itertools::Itertools, rayon::prelude::*};
 
-use gluon_doc as doc;
+use gluon_doc::{self as doc, doctest};
 
-use gluon::{check::metadata::metadata, RootedThread, ThreadExt};
+use gluon::{
+    base::source::{FileMap, Source},
+    check::metadata::metadata,
+    RootedThread, ThreadExt,
+};
 
 fn new_vm() -> RootedThread {
     ::gluon::VmBuilder::new()
@@ -12,19 +16,26 @@ fn new_vm() -> RootedThread {
         .build()
 }
 
-fn doc_check(module: &str, expected: doc::Record) {
+fn doc_record(module: &str) -> doc::Record {
     let vm = new_vm();
     let (expr, typ) = vm.typecheck_str("basic", module, None).unwrap();
     let (meta, _) = metadata(&vm.get_env(), &expr.expr());
 
-    let out = doc::record(
+    let mut index = doc::TypeIndex::default();
+    index.insert_module("basic", &typ, &meta);
+
+    doc::record(
         "basic",
+        &index,
         &typ,
         &Default::default(),
         &<() as gluon::base::source::Source>::new(""),
         &meta,
-    );
-    assert_eq!(out, expected,);
+    )
+}
+
+fn doc_check(module: &str, expected: doc::Record) {
+    assert_eq!(doc_record(module), expected);
 }
 
 #[test]
@@ -40,11 +51,13 @@ let test x = x
             types: Vec::new(),
             values: vec![doc::Field {
                 name: "test".to_string(),
+                anchor: "value.test".to_string(),
                 args: vec![doc::Argument {
                     implicit: false,
                     name: "x".to_string(),
                 }],
                 typ: handlebars::html_escape("forall a . a -> a"),
+                signature: "forall a . a -> a".to_string(),
                 attributes: "".to_string(),
                 comment: "This is the test function".to_string(),
                 definition_line: None,
@@ -71,6 +84,76 @@ let test x = x
     );
 }
 
+#[test]
+fn link_types() {
+    let module = r#"
+type Test = Int
+let test : Test = 1
+let option : Option Int = None
+{ Test, test, option }
+"#;
+    let record = doc_record(module);
+    let value_type = |name: &str| {
+        &record
+            .values
+            .iter()
+            .find(|field| field.name == name)
+            .expect("Value")
+            .typ
+    };
+    assert_eq!(record.types[0].anchor, "type.Test");
+    assert_eq!(
+        value_type("test"),
+        r#"<a href="basic.html#type.Test">Test</a>"#
+    );
+    // `Option` is not part of the documented modules so it can't be linked to
+    assert_eq!(value_type("option"), "Option Int");
+}
+
+#[test]
+fn operator_anchor() {
+    assert_eq!(doc::anchor("value", "<|>"), "value.-3c-7c-3e");
+    assert_eq!(doc::anchor("value", "map_err"), "value.map_err");
+}
+
+#[test]
+fn search_index() {
+    let module = doc::Module {
+        name: "std.test".to_string(),
+        record: doc_record(
+            r#"
+type Test = | A | B Int
+let test x : Int -> Test = B x
+{ Test, test }
+"#,
+        ),
+        ..doc::Module::default()
+    };
+    assert_eq!(
+        doc::search_index(Some(&module)),
+        vec![
+            doc::SearchEntry {
+                name: "std.test".to_string(),
+                kind: "module",
+                signature: "".to_string(),
+                path: "std/test.html".to_string(),
+            },
+            doc::SearchEntry {
+                name: "std.test.Test".to_string(),
+                kind: "type",
+                signature: "| A | B Int".to_string(),
+                path: "std/test.html#type.Test".to_string(),
+            },
+            doc::SearchEntry {
+                name: "std.test.test".to_string(),
+                kind: "value",
+                signature: "Int -> Test".to_string(),
+                path: "std/test.html#value.test".to_string(),
+            },
+        ]
+    );
+}
+
 #[test]
 fn check_links() {
     let _ = env_logger::try_init();
@@ -90,3 +173,254 @@ fn check_links() {
 
     assert!(errors.is_empty(), "{}", errors.iter().format("\n"));
 }
+
+#[test]
+fn markdown() {
+    let record = doc_record(
+        r#"
+/// A test type
+type Test = | A | B Int
+/// Wraps `x`
+let test x : Int -> Test = B x
+{ Test, test }
+"#,
+    );
+    let sub_module = doc::Module {
+        name: "basic.sub".to_string(),
+        comment: "The sub module\n\nMore details".to_string(),
+        ..doc::Module::default()
+    };
+
+    let mut out = Vec::new();
+    doc::generate_markdown(
+        &mut out,
+        &doc::TemplateModule {
+            name: "basic",
+            src_url: None,
+            comment: "The basic module",
+            record: &record,
+            sub_modules: vec![&sub_module],
+            sibling_modules: Vec::new(),
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        r#"# basic
+
+The basic module
+
+## Modules
+
+- [basic.sub](basic/sub.md): The sub module
+
+## Types
+
+### `Test`
+
+```gluon
+type Test = | A | B Int
+```
+
+A test type
+
+## Values
+
+### `test`
+
+```gluon
+let test x : Int -> Test
+```
+
+Wraps `x`
+"#
+    );
+}
+
+#[test]
+fn json_output() {
+    let dir = Path::new("../target/doc_json_test");
+    if dir.exists() {
+        fs::remove_dir_all(dir).unwrap_or_else(|err| panic!("{}", err));
+    }
+    let input = dir.join("input");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(
+        input.join("test.glu"),
+        r#"
+//! A test module
+
+/// Returns `x`
+let id x = x
+{ id }
+"#,
+    )
+    .unwrap();
+
+    let out = dir.join("out");
+    doc::generate(
+        &doc::Options {
+            input: input.clone(),
+            output: out.clone(),
+            src_url: None,
+            format: doc::Format::Json,
+            test: false,
+            import_graph: false,
+        },
+        &new_vm(),
+    )
+    .unwrap_or_else(|err| panic!("{}", err));
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("doc.json")).unwrap()).unwrap();
+    let module = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|module| module["name"] == "input.test")
+        .expect("Module");
+    assert_eq!(module["comment"], " A test module");
+    let value = &module["record"]["values"][0];
+    assert_eq!(value["name"], "id");
+    assert_eq!(value["anchor"], "value.id");
+    assert_eq!(value["signature"], "forall a . a -> a");
+    assert_eq!(value["comment"], "Returns `x`");
+    assert!(!out.join("input").exists());
+}
+
+#[test]
+fn import_graph() {
+    let dir = Path::new("../target/doc_import_graph_test");
+    if dir.exists() {
+        fs::remove_dir_all(dir).unwrap_or_else(|err| panic!("{}", err));
+    }
+    let input = dir.join("input");
+    fs::create_dir_all(&input).unwrap();
+    let modules = [
+        ("a.glu", "let b = import! input.b\nlet { List } = import! std.list\nb"),
+        ("b.glu", "let a = import! \"input/a.glu\"\na"),
+        ("c.glu", "let b = import! input.b\n{ b }"),
+    ];
+    for (file, source) in &modules {
+        fs::write(input.join(file), source).unwrap();
+    }
+
+    let out = dir.join("out");
+    // The graph is written even though the modules which import each other fail to typecheck
+    let result = doc::generate(
+        &doc::Options {
+            input: input.clone(),
+            output: out.clone(),
+            src_url: None,
+            format: doc::Format::Html,
+            test: false,
+            import_graph: true,
+        },
+        &new_vm(),
+    );
+    assert!(result.is_err());
+
+    assert_eq!(
+        fs::read_to_string(out.join("imports.dot")).unwrap(),
+        r#"digraph imports {
+    "input.a" [color = red];
+    "input.b" [color = red];
+    "input.c";
+    "std.list" [style = dashed];
+    "input.a" -> "input.b" [color = red];
+    "input.a" -> "std.list";
+    "input.b" -> "input.a" [color = red];
+    "input.c" -> "input.b";
+}
+"#
+    );
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(out.join("imports.json")).unwrap()).unwrap();
+    assert_eq!(json["cycles"], serde_json::json!([["input.a", "input.b"]]));
+    assert_eq!(
+        json["modules"][3],
+        serde_json::json!({ "name": "std.list", "external": true, "imports": [] })
+    );
+}
+
+const DOCTESTS: &str = r#"//! ```
+//! let { assert_eq, ? } = import! std.test
+//! assert_eq 1 1
+//! ```
+
+/// Adds one
+///
+/// ```gluon
+/// let { assert_eq, ? } = import! std.test
+/// assert_eq 2 3
+/// ```
+///
+/// ```js
+/// not_gluon()
+/// ```
+#[inline]
+let add_one x = x #Int+ 1
+
+/**
+```no_run
+error "not run"
+```
+*/
+let (++) x y = x
+
+{
+    /// ```ignore
+    /// ignored
+    /// ```
+    add_one,
+    (++),
+}
+"#;
+
+#[test]
+fn extract_doctests() {
+    let source = FileMap::new("test".into(), DOCTESTS.into());
+    let doctests = doctest::extract("test", &source);
+
+    let summary = doctests
+        .iter()
+        .map(|doctest| (&doctest.item[..], doctest.line, doctest.mode))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("test", 1, doctest::Mode::Run),
+            ("add_one", 8, doctest::Mode::Run),
+            ("++", 20, doctest::Mode::NoRun),
+            ("add_one", 27, doctest::Mode::Ignore),
+        ]
+    );
+    assert_eq!(
+        doctests[1].source,
+        "let { assert_eq, ? } = import! std.test\nassert_eq 2 3\n"
+    );
+    assert_eq!(
+        source.src_slice(doctests[1].span),
+        "```gluon\n/// let { assert_eq, ? } = import! std.test\n/// assert_eq 2 3\n/// ```"
+    );
+    assert_eq!(doctests[1].name(), "add_one (line 8)");
+}
+
+#[test]
+fn run_doctests() {
+    if std::env::var("GLUON_PATH").is_err() {
+        std::env::set_var("GLUON_PATH", "..");
+    }
+    let source = FileMap::new("test".into(), DOCTESTS.into());
+    let doctests = doctest::extract("test", &source);
+
+    let run = |doctest| futures::executor::block_on(doctest::run(doctest, true));
+    run(&doctests[0]).unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        run(&doctests[1]).unwrap_err().to_string(),
+        "Assertion failed: 2 != 3"
+    );
+    run(&doctests[2]).unwrap_or_else(|err| panic!("{}", err));
+    run(&doctests[3]).unwrap_or_else(|err| panic!("{}", err));
+}
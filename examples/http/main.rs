@@ -135,4 +135,36 @@ mod tests {
             })
             .unwrap_or_else(|err: anyhow::Error| panic!("{}", err));
     }
+
+    #[test]
+    fn route_with_params() {
+        let _ = env_logger::try_init();
+
+        let mut runtime = Runtime::new().unwrap();
+
+        let port = 12236;
+        let thread = new_vm();
+        let start_server = async move {
+            tokio::spawn(async move { start(&thread, port).await.unwrap() });
+            wait_for_server(port).await
+        };
+
+        let (user_id, body) = runtime
+            .block_on(async {
+                start_server.await?;
+                let response = Client::new()
+                    .get(
+                        format!("http://localhost:{}/users/a%20b?greeting=Hi", port)
+                            .parse()
+                            .unwrap(),
+                    )
+                    .await?;
+                let user_id = response.headers()["x-user-id"].to_str()?.to_owned();
+                let body = hyper::body::to_bytes(response.into_body()).await?;
+                Ok::<_, anyhow::Error>((user_id, body))
+            })
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(user_id, "a b");
+        assert_eq!(str::from_utf8(&body).unwrap(), "Hi a b");
+    }
 }
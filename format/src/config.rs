@@ -0,0 +1,167 @@
+//! Loading of the formatter options from `.gluonfmt` and `gluon.toml` files.
+//!
+//! Both files are TOML. Options in a `.gluonfmt` file are written at the top level while
+//! `gluon.toml` keeps them in its `[format]` table and may contain other tables which are ignored
+//! here.
+//!
+//! ```toml
+//! [format]
+//! max_width = 80
+//! indent_size = 2
+//! trailing_commas = false
+//! operator_line_break = "after"
+//! sort_imports = true
+//! ```
+
+use std::{
+    error::Error as StdError,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use toml::{value::Table, Value};
+
+use crate::{Formatter, OperatorLineBreak};
+
+/// The names of the configuration files, in the order they are looked for in each directory
+pub const CONFIG_FILES: &[&str] = &[".gluonfmt", "gluon.toml"];
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse { path, message } => {
+                if let Some(path) = path {
+                    write!(f, "{}: ", path.display())?;
+                }
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl StdError for Error {}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a datetime",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+impl Formatter {
+    /// Parses the options in `config`, starting from the default options.
+    ///
+    /// If `table` is `Some`, only the keys of that table are read, otherwise only the keys which
+    /// appear before any table header.
+    pub fn from_config(config: &str, table: Option<&str>) -> Result<Formatter, Error> {
+        let parse_error = |message: String| Error::Parse {
+            path: None,
+            message,
+        };
+
+        let mut root =
+            toml::from_str::<Table>(config).map_err(|err| parse_error(err.to_string()))?;
+        let options = match table {
+            Some(table) => match root.remove(table) {
+                Some(Value::Table(options)) => options,
+                Some(value) => {
+                    return Err(parse_error(format!(
+                        "Expected a table for `{}`, found {}",
+                        table,
+                        describe(&value)
+                    )))
+                }
+                None => Table::new(),
+            },
+            None => root
+                .into_iter()
+                .filter(|(_, value)| !value.is_table())
+                .collect(),
+        };
+
+        let mut formatter = Formatter::default();
+        for (key, value) in &options {
+            let unexpected = |expected: &str| {
+                parse_error(format!(
+                    "Expected {} for `{}`, found {}",
+                    expected,
+                    key,
+                    describe(value)
+                ))
+            };
+
+            match &key[..] {
+                "max_width" => match *value {
+                    Value::Integer(width) if width > 0 => formatter.width = width as usize,
+                    _ => return Err(unexpected("a positive integer")),
+                },
+                "indent_size" => match *value {
+                    Value::Integer(indent) if indent >= 0 => formatter.indent = indent as usize,
+                    _ => return Err(unexpected("a non-negative integer")),
+                },
+                "trailing_commas" => match *value {
+                    Value::Boolean(b) => formatter.trailing_commas = b,
+                    _ => return Err(unexpected("a boolean")),
+                },
+                "operator_line_break" => match value.as_str() {
+                    Some("before") => formatter.operator_line_break = OperatorLineBreak::Before,
+                    Some("after") => formatter.operator_line_break = OperatorLineBreak::After,
+                    _ => return Err(unexpected("\"before\" or \"after\"")),
+                },
+                "sort_imports" => match *value {
+                    Value::Boolean(b) => formatter.sort_imports = b,
+                    _ => return Err(unexpected("a boolean")),
+                },
+                _ => return Err(parse_error(format!("Unknown option `{}`", key))),
+            }
+        }
+        Ok(formatter)
+    }
+
+    /// Reads the options from the configuration file at `path`. `gluon.toml` files are read from
+    /// their `[format]` table.
+    pub fn from_config_file(path: &Path) -> Result<Formatter, Error> {
+        let config = fs::read_to_string(path).map_err(|err| Error::Io(path.to_owned(), err))?;
+        let table = if path.file_name().map_or(false, |name| name == "gluon.toml") {
+            Some("format")
+        } else {
+            None
+        };
+        Formatter::from_config(&config, table).map_err(|err| match err {
+            Error::Parse { message, .. } => Error::Parse {
+                path: Some(path.to_owned()),
+                message,
+            },
+            err => err,
+        })
+    }
+
+    /// Looks for a configuration file in `dir` and its ancestors and reads the options in the
+    /// first one that is found. Returns the default options if there is no configuration file.
+    pub fn find_config(dir: &Path) -> Result<Formatter, Error> {
+        for dir in dir.ancestors() {
+            for file in CONFIG_FILES {
+                let path = dir.join(file);
+                if path.is_file() {
+                    return Formatter::from_config_file(&path);
+                }
+            }
+        }
+        Ok(Formatter::default())
+    }
+}
@@ -0,0 +1,155 @@
+//! Line based differences between a source file and its formatted version.
+
+use std::{fmt, ops::Range};
+
+use base::pos::{BytePos, Span};
+
+use crate::TextEdit;
+
+/// A run of consecutive lines which differ between the original and the formatted source
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk<'a> {
+    /// The (0-indexed) lines of the original source which are replaced
+    pub original: Range<usize>,
+    /// The (0-indexed) lines of the formatted source which replace them
+    pub formatted: Range<usize>,
+    pub removed: Vec<&'a str>,
+    pub added: Vec<&'a str>,
+}
+
+/// Writes the hunk in the unified diff format, without any context lines
+impl fmt::Display for Hunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Empty ranges refer to the line before them
+        let header = |range: &Range<usize>| {
+            let len = range.end - range.start;
+            let start = if len == 0 {
+                range.start
+            } else {
+                range.start + 1
+            };
+            format!("{},{}", start, len)
+        };
+        writeln!(
+            f,
+            "@@ -{} +{} @@",
+            header(&self.original),
+            header(&self.formatted)
+        )?;
+        for line in &self.removed {
+            writeln!(f, "-{}", line.trim_end_matches(&['\r', '\n'][..]))?;
+        }
+        for line in &self.added {
+            writeln!(f, "+{}", line.trim_end_matches(&['\r', '\n'][..]))?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `s` into lines which keep their line endings
+fn lines(s: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if c == '\n' {
+            lines.push(&s[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < s.len() {
+        lines.push(&s[start..]);
+    }
+    lines
+}
+
+/// Returns the lines which must change to turn `original` into `formatted`. An empty result means
+/// that `original` is already formatted.
+pub fn diff<'a>(original: &'a str, formatted: &'a str) -> Vec<Hunk<'a>> {
+    let old = lines(original);
+    let new = lines(formatted);
+
+    // Most formatting changes are local so the lines at either end are skipped before computing
+    // the longest common subsequence of the rest
+    let prefix = old.iter().zip(&new).take_while(|(l, r)| l == r).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of old_middle[i..] and
+    // new_middle[j..]
+    let width = new_middle.len() + 1;
+    let mut lcs = vec![0u32; (old_middle.len() + 1) * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lcs[i * width + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (old_line, new_line) = (prefix + i, prefix + j);
+        let hunk = match hunks.last_mut() {
+            Some(hunk) if hunk.original.end == old_line && hunk.formatted.end == new_line => hunk,
+            _ => {
+                hunks.push(Hunk {
+                    original: old_line..old_line,
+                    formatted: new_line..new_line,
+                    removed: Vec::new(),
+                    added: Vec::new(),
+                });
+                hunks.last_mut().unwrap()
+            }
+        };
+        if j == new_middle.len()
+            || (i < old_middle.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            hunk.removed.push(old_middle[i]);
+            hunk.original.end += 1;
+            i += 1;
+        } else {
+            hunk.added.push(new_middle[j]);
+            hunk.formatted.end += 1;
+            j += 1;
+        }
+    }
+    hunks
+}
+
+/// Returns the edits which turn `original` into `formatted`, one for each changed run of lines.
+/// The spans are byte offsets into `original`.
+pub fn text_edits(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let line_starts: Vec<_> = lines(original)
+        .iter()
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(start)
+        })
+        .chain(Some(original.len()))
+        .collect();
+    diff(original, formatted)
+        .into_iter()
+        .map(|hunk| TextEdit {
+            span: Span::new(
+                BytePos::from(line_starts[hunk.original.start] as u32),
+                BytePos::from(line_starts[hunk.original.end] as u32),
+            ),
+            new_text: hunk.added.concat(),
+        })
+        .collect()
+}
@@ -0,0 +1,250 @@
+//! Sorting of the `import!` bindings at the top of a module.
+//!
+//! The bindings are grouped by the first component of their module path, with the `std` group
+//! first, and sorted by the module path inside each group. Comments above a binding move with it.
+//! The bindings are left untouched if two of them bind the same name since reordering them would
+//! change which of the bindings is visible.
+
+use std::collections::HashSet;
+
+use base::{
+    ast::{Expr, Pattern, PatternField, SpannedExpr, SpannedPattern, ValueBinding},
+    pos::BytePos,
+    source::Source,
+    symbol::Symbol,
+};
+
+struct Import<'a> {
+    /// The comments (and attributes) above the binding, one line each
+    comments: Vec<&'a str>,
+    /// The lines of the binding itself, starting with `let`
+    binding: &'a str,
+    /// The comment after the binding, on its last line
+    trailing: &'a str,
+    path: &'a str,
+    names: Vec<String>,
+}
+
+impl Import<'_> {
+    fn group(&self) -> (bool, &str) {
+        let root = self.path.split('.').next().unwrap_or("").trim();
+        (root != "std", root)
+    }
+
+    /// The binding with its whitespace normalized, used to find duplicated imports
+    fn key(&self) -> String {
+        self.binding
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn import_path<'a>(source: &'a dyn Source, bind: &ValueBinding<Symbol>) -> Option<&'a str> {
+    let expr = match bind.expr.value {
+        Expr::MacroExpansion { ref original, .. } => original,
+        _ => &bind.expr,
+    };
+    match expr.value {
+        Expr::App {
+            ref func, ref args, ..
+        } if args.len() == 1 => match func.value {
+            Expr::Ident(ref id) if id.name.declared_name() == "import!" => {
+                Some(source.src_slice(args[0].span))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn pattern_names(pattern: &SpannedPattern<Symbol>, names: &mut Vec<String>) {
+    match pattern.value {
+        Pattern::As(ref id, ref pattern) => {
+            names.push(id.value.declared_name().to_string());
+            pattern_names(pattern, names);
+        }
+        Pattern::Ident(ref id) => names.push(id.name.declared_name().to_string()),
+        Pattern::Constructor(_, ref args) => {
+            for arg in args.iter() {
+                pattern_names(arg, names);
+            }
+        }
+        Pattern::Tuple { ref elems, .. } => {
+            for elem in elems.iter() {
+                pattern_names(elem, names);
+            }
+        }
+        Pattern::Record { ref fields, .. } => {
+            for field in fields.iter() {
+                match field {
+                    PatternField::Type { name } => {
+                        names.push(format!("type {}", name.value.declared_name()))
+                    }
+                    PatternField::Value {
+                        value: Some(value), ..
+                    } => pattern_names(value, names),
+                    PatternField::Value { name, value: None } => {
+                        names.push(name.value.declared_name().to_string())
+                    }
+                }
+            }
+        }
+        Pattern::Literal(_) | Pattern::Error => (),
+    }
+}
+
+fn line_start(src: &str, offset: usize) -> usize {
+    src[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(src: &str, offset: usize) -> usize {
+    let end = src[offset..].find('\n').map_or(src.len(), |i| offset + i);
+    if src[..end].ends_with('\r') {
+        end - 1
+    } else {
+        end
+    }
+}
+
+fn is_comment(line: &str) -> bool {
+    let line = line.trim();
+    (line.starts_with("//") && !line.starts_with("//!") && !line.starts_with("//@"))
+        || (line.starts_with("/*") && line.ends_with("*/"))
+        || line.starts_with("#[")
+}
+
+/// Returns `source` with the `import!` bindings at the start of `expr` sorted, grouped and with
+/// duplicates removed. Returns `None` if nothing would change.
+pub fn sort_imports(source: &dyn Source, mut expr: &SpannedExpr<Symbol>) -> Option<String> {
+    let src = source.src();
+    let offset = |pos: BytePos| (pos - source.span().start()).to_usize();
+    let newline = crate::newline(src);
+
+    let mut imports = Vec::new();
+    let mut block_start = None;
+    let mut previous_end = None;
+    while let Expr::LetBindings(ref binds, ref body) = expr.value {
+        if binds.len() != 1 || binds[0].args.len() != 0 {
+            break;
+        }
+        let bind = &binds[0];
+        let path = match import_path(source, bind) {
+            Some(path) => path,
+            None => break,
+        };
+
+        let pattern_start = offset(bind.name.span.start());
+        let let_start = line_start(src, pattern_start);
+        if src[let_start..pattern_start].trim() != "let" {
+            return None;
+        }
+        let expr_end = offset(bind.expr.span.end());
+        let end = line_end(src, expr_end);
+        let trailing = src[expr_end..end].trim();
+        if !trailing.is_empty() && !trailing.starts_with("//") {
+            return None;
+        }
+
+        // Everything between two imports must be comments, which are attached to the import below
+        // them. Above the first import only the comments directly above it are attached.
+        let comments_start = match previous_end {
+            Some(previous_end) => {
+                let between = &src[previous_end..let_start];
+                if !between
+                    .lines()
+                    .all(|line| line.trim().is_empty() || is_comment(line))
+                {
+                    return None;
+                }
+                previous_end
+            }
+            None => {
+                let mut start = let_start;
+                while start > 0 {
+                    let previous = line_start(src, start - 1);
+                    if !is_comment(&src[previous..start]) {
+                        break;
+                    }
+                    start = previous;
+                }
+                block_start = Some(start);
+                start
+            }
+        };
+        let comments = src[comments_start..let_start]
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+
+        let mut names = Vec::new();
+        pattern_names(&bind.name, &mut names);
+        imports.push(Import {
+            comments,
+            binding: &src[let_start..expr_end],
+            trailing,
+            path,
+            names,
+        });
+
+        previous_end = Some(end);
+        expr = body;
+    }
+    let (block_start, block_end) = (block_start?, previous_end?);
+
+    let mut unique: Vec<Import> = Vec::new();
+    for import in imports {
+        match unique
+            .iter_mut()
+            .find(|existing| existing.key() == import.key())
+        {
+            Some(existing) => {
+                existing.comments.extend(import.comments);
+                if existing.trailing.is_empty() {
+                    existing.trailing = import.trailing;
+                } else if !import.trailing.is_empty() {
+                    existing.comments.push(import.trailing);
+                }
+            }
+            None => unique.push(import),
+        }
+    }
+
+    let mut names = HashSet::new();
+    for name in unique.iter().flat_map(|import| &import.names) {
+        if !names.insert(name) {
+            return None;
+        }
+    }
+
+    unique.sort_by(|l, r| {
+        (l.group(), l.path.trim(), l.binding).cmp(&(r.group(), r.path.trim(), r.binding))
+    });
+
+    let mut block = String::new();
+    for (i, import) in unique.iter().enumerate() {
+        if i != 0 {
+            block.push_str(newline);
+            if unique[i - 1].group() != import.group() {
+                block.push_str(newline);
+            }
+        }
+        for comment in &import.comments {
+            block.push_str(comment);
+            block.push_str(newline);
+        }
+        block.push_str(import.binding);
+        if !import.trailing.is_empty() {
+            block.push(' ');
+            block.push_str(import.trailing);
+        }
+    }
+
+    let sorted = format!("{}{}{}", &src[..block_start], block, &src[block_end..]);
+    if sorted == src {
+        None
+    } else {
+        Some(sorted)
+    }
+}
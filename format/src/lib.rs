@@ -4,43 +4,185 @@
 extern crate codespan;
 #[macro_use]
 extern crate gluon_base as base;
+extern crate gluon_parser as parser;
 extern crate itertools;
 extern crate pretty;
 
-use base::{ast::SpannedExpr, source::Source, symbol::Symbol};
+use std::fmt;
 
+use base::{
+    ast::{walk_expr, SpannedExpr, Visitor},
+    pos::{BytePos, Span},
+    source::Source,
+    symbol::Symbol,
+    types::AsId,
+};
+use parser::infix::OpTable;
+
+pub mod config;
+pub mod diff;
+pub mod imports;
 mod pretty_print;
 
 pub fn pretty_expr(input: &dyn Source, expr: &SpannedExpr<Symbol>) -> String {
     Formatter::default().pretty_expr(input, expr)
 }
 
-#[derive(Default, Debug, Clone)]
+/// Formats an expression which was constructed programmatically rather than parsed, such as the
+/// output of a code generator. See `Formatter::print_expr`.
+pub fn print_expr<Id>(expr: &SpannedExpr<Id>) -> String
+where
+    Id: AsRef<str> + AsId<Id> + fmt::Debug,
+{
+    Formatter::default().print_expr(expr)
+}
+
+pub fn format_range(
+    input: &dyn Source,
+    expr: &SpannedExpr<Symbol>,
+    range: Span<BytePos>,
+) -> TextEdit {
+    Formatter::default().format_range(input, expr, range)
+}
+
+/// An edit which replaces the text in `span` with `new_text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub span: Span<BytePos>,
+    pub new_text: String,
+}
+
+/// Where a binary operator is placed when an expression is split over multiple lines
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OperatorLineBreak {
+    /// Starts the continuation line with the operator
+    Before,
+    /// Ends the line before the break with the operator
+    After,
+}
+
+#[derive(Debug, Clone)]
 pub struct Formatter {
     /// Prints the source code after macro expansion
     ///
     /// NOTE: This is only provided for debug purposes and is likely to have have bugs
     pub expanded: bool,
+    /// The width that lines are kept within, where possible
+    pub width: usize,
+    /// The number of spaces used for each level of indentation
+    pub indent: usize,
+    /// Adds a comma after the last element of records and tuples which span multiple lines
+    pub trailing_commas: bool,
+    pub operator_line_break: OperatorLineBreak,
+    /// The fixities of the operators in the formatted code, keyed by their names. Chains of
+    /// operators with the same fixity are laid out together. Operators which are missing from the
+    /// table use the fixities of the builtin operators, or are only chained with themselves.
+    pub operators: OpTable<String>,
+    /// Sorts and groups the `import!` bindings at the top of each module, see `imports`
+    pub sort_imports: bool,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            expanded: false,
+            width: 100,
+            indent: 4,
+            trailing_commas: true,
+            operator_line_break: OperatorLineBreak::Before,
+            operators: OpTable::new(None),
+            sort_imports: false,
+        }
+    }
 }
 
 impl Formatter {
     pub fn pretty_expr(&self, source: &dyn Source, expr: &SpannedExpr<Symbol>) -> String {
-        let input = source.src();
-        let newline = match input.find(|c: char| c == '\n' || c == '\r') {
-            Some(i) => {
-                if input[i..].starts_with("\r\n") {
-                    "\r\n"
-                } else if input[i..].starts_with("\r") {
-                    "\r"
-                } else {
-                    "\n"
+        let arena = pretty::Arena::<()>::new();
+        let printer = pretty_print::Printer::new(&arena, source, self.clone());
+        printer.format(self.width, newline(source.src()), &expr)
+    }
+
+    /// Formats an expression which was constructed programmatically rather than parsed. The spans
+    /// in `expr` are ignored and parentheses are added wherever the structure of `expr` requires
+    /// them, so the result parses back to the same expression. Nested operators use the fixities
+    /// in `operators` and are always parenthesized if one of them has no fixity.
+    pub fn print_expr<Id>(&self, expr: &SpannedExpr<Id>) -> String
+    where
+        Id: AsRef<str> + AsId<Id> + fmt::Debug,
+    {
+        let arena = pretty::Arena::<()>::new();
+        let printer = pretty_print::Printer::generated(&arena, self.clone());
+        printer.format(self.width, "\n", expr)
+    }
+
+    /// Formats the smallest expression in `expr` which encloses `range`, leaving the rest of the
+    /// source untouched. The whole source is formatted if no expression encloses `range`.
+    pub fn format_range(
+        &self,
+        source: &dyn Source,
+        expr: &SpannedExpr<Symbol>,
+        range: Span<BytePos>,
+    ) -> TextEdit {
+        let mut finder = FindEnclosing { range, found: None };
+        finder.visit_expr(expr);
+        match finder.found {
+            Some(enclosing) => {
+                let input = source.src();
+                let start = (enclosing.span.start() - source.span().start()).to_usize();
+                let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+                let line = &input[line_start..start];
+                let column = line.chars().count();
+                let indent = line.len() - line.trim_start().len();
+
+                let arena = pretty::Arena::<()>::new();
+                let printer = pretty_print::Printer::new(&arena, source, self.clone());
+                TextEdit {
+                    span: enclosing.span,
+                    new_text: printer.format_at(
+                        self.width,
+                        newline(input),
+                        column,
+                        indent,
+                        enclosing,
+                    ),
                 }
             }
-            None => "\n",
-        };
+            None => TextEdit {
+                span: source.span(),
+                new_text: self.pretty_expr(source, expr),
+            },
+        }
+    }
+}
 
-        let arena = pretty::Arena::<()>::new();
-        let printer = pretty_print::Printer::new(&arena, source, self.clone());
-        printer.format(100, newline, &expr)
+fn newline(input: &str) -> &'static str {
+    match input.find(|c: char| c == '\n' || c == '\r') {
+        Some(i) => {
+            if input[i..].starts_with("\r\n") {
+                "\r\n"
+            } else if input[i..].starts_with("\r") {
+                "\r"
+            } else {
+                "\n"
+            }
+        }
+        None => "\n",
+    }
+}
+
+struct FindEnclosing<'a, 'ast> {
+    range: Span<BytePos>,
+    found: Option<&'a SpannedExpr<'ast, Symbol>>,
+}
+
+impl<'a, 'ast> Visitor<'a, 'ast> for FindEnclosing<'a, 'ast> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+        if e.span.contains(self.range) {
+            self.found = Some(e);
+            walk_expr(self, e);
+        }
     }
 }
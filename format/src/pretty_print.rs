@@ -7,6 +7,7 @@ use {
 };
 
 use self::types::pretty_print as pretty_types;
+use crate::OperatorLineBreak;
 use base::{
     ast::{
         Do, Expr, Literal, Pattern, PatternField, SpannedExpr, SpannedPattern, ValueBinding,
@@ -16,10 +17,9 @@ use base::{
     metadata::Attribute,
     pos::{self, BytePos, HasSpan, Span, Spanned},
     source,
-    types::{self, ArgType, AsId,Prec, Type},
+    types::{self, ArgType, AsId, Prec, Type},
 };
-
-const INDENT: isize = 4;
+use parser::infix::{Fixity, OpMeta};
 
 macro_rules! newlines_iter {
     ($self_:ident, $iterable:expr) => {
@@ -47,13 +47,107 @@ fn is_nil<'a, A>(doc: &DocBuilder<'a, Arena<'a, A>, A>) -> bool {
     }
 }
 
-fn trailing_comma<'a, A>(arena: &'a Arena<'a, A>) -> DocBuilder<'a, Arena<'a, A>, A> {
-    arena.text(",").flat_alt(arena.nil())
+/// Writes `literal` as it would appear in the source, for literals which have no source text
+fn literal_text(literal: &Literal) -> String {
+    fn escape(s: &str, quote: char) -> String {
+        let mut escaped = String::with_capacity(s.len() + 2);
+        escaped.push(quote);
+        for c in s.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                _ if c == quote => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                _ => escaped.push(c),
+            }
+        }
+        escaped.push(quote);
+        escaped
+    }
+    match *literal {
+        Literal::Byte(b) => format!("{}b", b),
+        Literal::Int(i) => i.to_string(),
+        // `Debug` keeps the decimal point of integral floats so they are not read back as integers
+        Literal::Float(f) => format!("{:?}", f.into_inner()),
+        Literal::String(ref s) => escape(s, '"'),
+        Literal::Char(c) => escape(c.encode_utf8(&mut [0; 4]), '\''),
+    }
+}
+
+/// Where an expression is printed inside its parent
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Position<'a> {
+    Function,
+    Argument,
+    Projected,
+    Operand { op: &'a str, lhs: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Trivia<'a> {
+    Newline,
+    Comment(&'a str),
+}
+
+/// Scans the whitespace and comments at the start of `src` into `items`, returning the rest of
+/// `src`. Doc comments are left in place as they are printed from the metadata of the node they
+/// document.
+fn scan_trivia<'a>(mut src: &'a str, items: &mut Vec<Trivia<'a>>) -> &'a str {
+    loop {
+        src = src.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        if src.starts_with('\n') {
+            items.push(Trivia::Newline);
+            src = &src[1..];
+        } else if src.starts_with("//") && !src.starts_with("///") {
+            let end = src.find('\n').unwrap_or(src.len());
+            items.push(Trivia::Comment(src[..end].trim_end()));
+            src = &src[end..];
+        } else if src.starts_with("/*") && (!src.starts_with("/**") || src.starts_with("/**/")) {
+            match src.find("*/") {
+                Some(i) => {
+                    items.push(Trivia::Comment(&src[..i + 2]));
+                    src = &src[i + 2..];
+                }
+                None => return src,
+            }
+        } else {
+            return src;
+        }
+    }
+}
+
+/// The comments between two nodes. `trailing` holds the comments on the same line as the first
+/// node, which stay attached to it, and `leading` the lines after it, which belong to the second
+/// node.
+struct Gap<'a> {
+    trailing: Vec<&'a str>,
+    leading: Vec<Trivia<'a>>,
+}
+
+impl Gap<'_> {
+    fn has_comments(&self) -> bool {
+        !self.trailing.is_empty() || self.leading.iter().any(|t| *t != Trivia::Newline)
+    }
+
+    fn ends_with_line_comment(&self) -> bool {
+        self.trailing.last().map_or(false, |c| c.starts_with("//"))
+    }
+
+    fn has_leading_comments(&self) -> bool {
+        self.leading.iter().any(|t| *t != Trivia::Newline)
+    }
 }
 
 pub(super) struct Printer<'a, I: 'a, A: 'a> {
     printer: pretty_types::Printer<'a, I, A>,
     formatter: crate::Formatter,
+    /// `false` if the expression was constructed instead of parsed from the source. Such
+    /// expressions have no spans so parentheses are added based on their structure instead.
+    parsed: bool,
 }
 
 impl<'a, I, A> Printer<'a, I, A>
@@ -67,9 +161,94 @@ where
         source: &'a dyn source::Source,
         formatter: crate::Formatter,
     ) -> Self {
+        let mut printer = pretty_types::Printer::new(arena, source);
+        printer.indent = formatter.indent as isize;
         Printer {
-            printer: pretty_types::Printer::new(arena, source),
+            printer,
             formatter,
+            parsed: true,
+        }
+    }
+
+    pub(super) fn generated(arena: &'a Arena<'a, A>, formatter: crate::Formatter) -> Self {
+        Printer {
+            parsed: false,
+            ..Printer::new(arena, &(), formatter)
+        }
+    }
+
+    /// The line break between a binding and the expression after it. Parsed expressions get it
+    /// from the whitespace in the source.
+    fn statement_break(&self) -> DocBuilder<'a, Arena<'a, A>, A> {
+        if self.formatter.expanded || !self.parsed {
+            self.arena.hardline()
+        } else {
+            self.arena.nil()
+        }
+    }
+
+    /// Prints `expr` at `position`, in parentheses if it was constructed as a child of an
+    /// expression which binds tighter than itself. Parsed expressions keep the parentheses from
+    /// the source as `Expr::Block`s instead.
+    fn pretty_at(
+        &self,
+        position: Position,
+        expr: &'a SpannedExpr<I>,
+    ) -> DocBuilder<'a, Arena<'a, A>, A>
+    where
+        A: Clone,
+    {
+        let doc = self.pretty_expr_(expr.span.start(), expr);
+        if !self.parsed && self.needs_parens(position, expr) {
+            chain![self.arena, "(", doc, ")"]
+        } else {
+            doc
+        }
+    }
+
+    fn needs_parens(&self, position: Position, expr: &SpannedExpr<I>) -> bool {
+        let atomic = match expr.value {
+            Expr::Ident(_)
+            | Expr::Record { .. }
+            | Expr::Tuple { .. }
+            | Expr::Array(_)
+            | Expr::Projection(..) => true,
+            Expr::Literal(Literal::Int(i)) => i >= 0,
+            Expr::Literal(Literal::Float(f)) => f.into_inner() >= 0.0,
+            Expr::Literal(_) => true,
+            Expr::Block(ref elems) => elems.len() == 1,
+            Expr::MacroExpansion { ref original, .. } => {
+                return self.needs_parens(position, original)
+            }
+            _ => false,
+        };
+        if atomic {
+            return false;
+        }
+        match (position, &expr.value) {
+            (Position::Function, Expr::App { .. })
+            | (Position::Operand { .. }, Expr::App { .. }) => false,
+            (Position::Operand { op, lhs }, Expr::Infix { op: inner, .. }) => {
+                match (self.fixity(op), self.fixity(inner.value.name.as_ref())) {
+                    (Some(outer), Some(inner)) => {
+                        let associates =
+                            inner.fixity == outer.fixity && (inner.fixity == Fixity::Left) == lhs;
+                        !(inner.precedence > outer.precedence
+                            || (inner.precedence == outer.precedence && associates))
+                    }
+                    _ => true,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    fn trailing_comma(&self) -> DocBuilder<'a, Arena<'a, A>, A> {
+        let arena = self.arena;
+        if self.formatter.trailing_commas {
+            arena.text(",").flat_alt(arena.nil())
+        } else {
+            arena.nil()
         }
     }
 
@@ -85,6 +264,35 @@ where
             .collect()
     }
 
+    /// Formats `expr` on its own, as if it started at `column` of a line indented by `indent`. The
+    /// first line is returned without the spaces before `column`.
+    pub(super) fn format_at(
+        &self,
+        width: usize,
+        hardline: &'a str,
+        column: usize,
+        indent: usize,
+        expr: &'a SpannedExpr<I>,
+    ) -> String
+    where
+        A: Clone,
+    {
+        let arena = self.arena;
+        let doc = arena
+            .text(" ".repeat(column))
+            .append(
+                self.pretty_expr_(expr.span.start(), expr)
+                    .nest(indent as isize),
+            )
+            .1;
+        let text = doc.pretty(width).to_string();
+        text[column..]
+            .lines()
+            .map(|s| s.trim_end())
+            .format(hardline)
+            .to_string()
+    }
+
     fn pretty_expr(&self, expr: &'a SpannedExpr<I>) -> DocBuilder<'a, Arena<'a, A>, A>
     where
         A: Clone,
@@ -130,7 +338,7 @@ where
     where
         A: Clone,
     {
-        if !self.formatter.expanded {
+        if !self.formatter.expanded && self.parsed {
             while expr.span.start() == 0.into() {
                 expr = match expr.value {
                     Expr::TypeBindings(_, ref expr) | Expr::LetBindings(_, ref expr) => expr,
@@ -156,16 +364,25 @@ where
                     .chain(args.iter().map(|arg| (arg, false)))
                     .tuple_windows()
                     .map(|((prev, _), (arg, implicit))| {
-                        self.space(Span::new(prev.span.end(), arg.span.start()))
+                        let span = Span::new(prev.span.end(), arg.span.start());
+                        let gap = self.gap(span, &[]);
+                        let space = if gap.has_comments() {
+                            let (trailing, before_next) =
+                                self.separate_comments(&gap, arena.line());
+                            trailing.append(before_next)
+                        } else {
+                            self.space(span)
+                        };
+                        space
                             .append(if implicit {
                                 arena.text("?")
                             } else {
                                 arena.nil()
                             })
-                            .append(pretty(arg))
+                            .append(self.pretty_at(Position::Argument, arg))
                     });
-                pretty(func)
-                    .append(arena.concat(arg_iter).nest(INDENT))
+                self.pretty_at(Position::Function, func)
+                    .append(arena.concat(arg_iter).nest(self.indent))
                     .group()
             }
 
@@ -194,6 +411,7 @@ where
                             pretty(elem)
                                 .group()
                                 .append(self.comments_after(elem.span.end()))
+                                .append(self.statement_break())
                         }
                     }))
                 }
@@ -203,24 +421,7 @@ where
 
             Expr::IfElse(..) => self.pretty_if_expr(expr),
 
-            Expr::Infix {
-                ref lhs,
-                ref op,
-                ref rhs,
-                ..
-            } => chain![
-                arena,
-                pretty(lhs).group(),
-                chain![
-                    arena,
-                    hardline(arena, rhs),
-                    op.value.name.as_ref() as &str,
-                    " ",
-                    pretty(rhs).group()
-                ]
-                .nest(INDENT)
-            ]
-            .group(),
+            Expr::Infix { .. } => self.pretty_infix(expr),
 
             Expr::LetBindings(ref binds, ref body) => {
                 let binding = |bind: &'a ValueBinding<I>| {
@@ -251,7 +452,7 @@ where
                                     .text(": ")
                                     .append(types::pretty_print(self, typ))
                                     .append(self.space_after(typ.span().end()))
-                                    .nest(INDENT)
+                                    .nest(self.indent)
                             }
                         },
                         "="
@@ -267,11 +468,7 @@ where
                             &bind.expr
                         )
                         .group(),
-                        if self.formatter.expanded {
-                            arena.hardline()
-                        } else {
-                            arena.nil()
-                        }
+                        self.statement_break()
                     ]
                 };
                 let is_recursive = match binds {
@@ -319,43 +516,43 @@ where
                 if literally {
                     arena.text(text)
                 } else {
-                    match *literal {
-                        Literal::Byte(b) => arena.text(b.to_string()),
-                        Literal::Int(i) => arena.text(i.to_string()),
-                        Literal::Float(f) => arena.text(f.to_string()),
-                        Literal::String(ref s) => chain![arena, "\"", arena.text(&s[..]), "\""],
-                        Literal::Char(c) => chain![arena, "'", arena.text(c.to_string()), "'"],
-                    }
+                    arena.text(literal_text(literal))
                 }
             }
 
-            Expr::Match(ref expr, ref alts) => chain![
-                arena,
-                chain![arena, "match ", pretty(expr), " with"].group(),
-                arena.hardline(),
-                arena.concat(
-                    alts.iter()
-                        .map(|alt| {
-                            chain![
-                                arena,
-                                "| ",
-                                self.pretty_pattern(&alt.pattern),
-                                " ->",
-                                self.hang(
-                                    arena.nil(),
-                                    (self.space_before(alt.expr.span.start()), true),
-                                    &alt.expr
-                                )
-                                .group()
-                            ]
-                        })
-                        .intersperse(arena.hardline())
-                )
-            ],
+            Expr::Match(ref expr, ref alts) => {
+                let mut prev_end = expr.span.end();
+                let mut separators = &["with", "|"][..];
+                chain![
+                    arena,
+                    chain![arena, "match ", pretty(expr), " with"].group(),
+                    arena.concat(alts.iter().map(|alt| {
+                        let gap =
+                            self.gap(Span::new(prev_end, alt.pattern.span.start()), separators);
+                        prev_end = alt.expr.span.end();
+                        separators = &["|"];
+                        chain![
+                            arena,
+                            self.trailing_comments(&gap),
+                            self.own_line_comments(&gap),
+                            arena.hardline(),
+                            "| ",
+                            self.pretty_pattern(&alt.pattern),
+                            " ->",
+                            self.hang(
+                                arena.nil(),
+                                (self.space_before(alt.expr.span.start()), true),
+                                &alt.expr
+                            )
+                            .group()
+                        ]
+                    }))
+                ]
+            }
 
             Expr::Projection(ref expr, ref field, _) => chain![
                 arena,
-                pretty(expr),
+                self.pretty_at(Position::Projected, expr),
                 ".",
                 pretty_types::ident(arena, field.as_ref() as &str)
             ],
@@ -395,7 +592,7 @@ where
                                 let mut type_doc = types::pretty_print(self, typ);
                                 match **typ {
                                     Type::Record(_) | Type::Variant(_) => (),
-                                    _ => type_doc = type_doc.nest(INDENT),
+                                    _ => type_doc = type_doc.nest(self.indent),
                                 }
                                 let variant = match &**typ {
                                     Type::Variant(row) => match &**row {
@@ -446,10 +643,11 @@ where
                                                 arena.line()
                                             ]
                                         }))
-                                        .group(),
+                                        .group()
+                                        .nest(self.indent),
                                     "=",
                                     if variant {
-                                        chain![arena, arena.hardline(), type_doc].nest(INDENT)
+                                        chain![arena, arena.hardline(), type_doc].nest(self.indent)
                                     } else {
                                         chain![arena, arena.space(), type_doc].group()
                                     }
@@ -463,11 +661,7 @@ where
                     } else {
                         arena.nil()
                     },
-                    if self.formatter.expanded {
-                        arena.hardline()
-                    } else {
-                        arena.nil()
-                    },
+                    self.statement_break(),
                     self.pretty_expr_(binds.last().unwrap().alias.span.end(), body)
                 ]
                 .group()
@@ -493,6 +687,7 @@ where
                 chain![
                     arena,
                     self.hang(from, (self.space_before(bound.span.start()), true), bound),
+                    self.statement_break(),
                     self.pretty_expr_(bound.span.end(), body)
                 ]
             }
@@ -529,6 +724,102 @@ where
         }
     }
 
+    /// Prints a chain of operators with the same precedence and associativity, such as
+    /// `x |> f |> g` or `a && b && c`, so that either the whole chain fits on one line or each
+    /// operator gets a line of its own
+    fn pretty_infix<'ast>(&self, expr: &'a SpannedExpr<'ast, I>) -> DocBuilder<'a, Arena<'a, A>, A>
+    where
+        A: Clone,
+    {
+        let arena = self.arena;
+
+        let (first, rest) = self.infix_chain(expr);
+        // Each operand is the left operand of the operator after it, except for the operands of a
+        // left associative chain (and the last operand) which are the right operand of the
+        // operator before them
+        let right_associative =
+            rest.len() > 1 && self.fixity(rest[0].0).map(|meta| meta.fixity) == Some(Fixity::Right);
+        let first = self.pretty_at(
+            Position::Operand {
+                op: rest[0].0,
+                lhs: true,
+            },
+            first,
+        );
+        let operands = arena.concat(rest.iter().enumerate().map(|(i, &(op, operand))| {
+            let position = match rest.get(i + 1) {
+                Some(&(next, _)) if right_associative => Position::Operand {
+                    op: next,
+                    lhs: true,
+                },
+                _ => Position::Operand { op, lhs: false },
+            };
+            let operand_doc = self.pretty_at(position, operand).group();
+            match self.formatter.operator_line_break {
+                OperatorLineBreak::Before => {
+                    chain![arena, hardline(arena, operand), op, " ", operand_doc]
+                }
+                OperatorLineBreak::After => {
+                    chain![arena, " ", op, hardline(arena, operand), operand_doc]
+                }
+            }
+        }));
+        chain![arena, first.group(), operands.nest(self.indent)].group()
+    }
+
+    fn fixity(&self, op: &str) -> Option<OpMeta> {
+        self.formatter.operators.get(&op.to_string()).cloned()
+    }
+
+    /// Splits `expr` into its first operand and the operators and operands following it. Nested
+    /// operators are part of the chain if the fixity table gives them the same precedence and
+    /// associativity as the outermost operator (or if they are the same operator).
+    fn infix_chain<'ast>(
+        &self,
+        expr: &'a SpannedExpr<'ast, I>,
+    ) -> (
+        &'a SpannedExpr<'ast, I>,
+        Vec<(&'a str, &'a SpannedExpr<'ast, I>)>,
+    ) {
+        let fixity = |op: &str| self.fixity(op);
+
+        let (lhs, op, rhs) = match infix(expr) {
+            Some(infix) => infix,
+            None => return (expr, Vec::new()),
+        };
+        let meta = fixity(op);
+        let in_chain = |other: &str| other == op || (meta.is_some() && fixity(other) == meta);
+
+        match meta.map(|meta| meta.fixity) {
+            // `a && (b && c)`
+            Some(Fixity::Right) => {
+                let mut rest = vec![(op, rhs)];
+                while let Some((lhs, next_op, rhs)) = rest.last().and_then(|last| infix(last.1)) {
+                    if !in_chain(next_op) {
+                        break;
+                    }
+                    rest.last_mut().unwrap().1 = lhs;
+                    rest.push((next_op, rhs));
+                }
+                (lhs, rest)
+            }
+            // `(x |> f) |> g`
+            _ => {
+                let mut first = lhs;
+                let mut rest = vec![(op, rhs)];
+                while let Some((lhs, next_op, rhs)) = infix(first) {
+                    if !in_chain(next_op) {
+                        break;
+                    }
+                    first = lhs;
+                    rest.push((next_op, rhs));
+                }
+                rest.reverse();
+                (first, rest)
+            }
+        }
+    }
+
     fn pretty_if_expr(&self, mut expr: &'a SpannedExpr<I>) -> DocBuilder<'a, Arena<'a, A>, A>
     where
         A: Clone,
@@ -552,7 +843,11 @@ where
                     "then"
                 ]
                 .group(),
-                arena.line().append(pretty(if_true)).nest(INDENT).group(),
+                arena
+                    .line()
+                    .append(pretty(if_true))
+                    .nest(self.indent)
+                    .group(),
             ]
             .group();
             doc = doc.append(next).append(arena.line());
@@ -563,7 +858,7 @@ where
             arena,
             doc,
             chain![arena, prefix.unwrap(), arena.line(), pretty(expr),]
-                .nest(INDENT)
+                .nest(self.indent)
                 .group(),
         ]
     }
@@ -656,6 +951,7 @@ where
                     .last()
                     .map_or(expr.span.start() + 1.into(), |s| s.end());
                 let last_element_end = base.as_ref().map_or(last_field_end, |base| base.span.end());
+                let closing_gap = self.gap(Span::new(last_element_end, expr.span.end()), &[","]);
 
                 let record = arena
                     .concat(self.comma_sep(
@@ -687,13 +983,17 @@ where
                                         None => id,
                                     }
                                 ];
-                                pos::spanned(r.name.span, doc)
+                                let end = r
+                                    .value
+                                    .as_ref()
+                                    .map_or(r.name.span.end(), |expr| expr.span.end());
+                                pos::spanned(Span::new(r.name.span.start(), end), doc)
                             }
                         }),
                         |spanned| spanned.value,
                     ))
                     .append(if !types.is_empty() || !exprs.is_empty() {
-                        trailing_comma(arena)
+                        self.trailing_comma()
                     } else {
                         arena.nil()
                     })
@@ -714,10 +1014,24 @@ where
                         }
                         None => arena.nil(),
                     })
-                    .nest(INDENT)
-                    .append(
-                        self.whitespace(Span::new(last_element_end, expr.span.end()), line.clone()),
-                    )
+                    .append(if closing_gap.has_comments() {
+                        self.trailing_comments(&closing_gap)
+                            .append(self.own_line_comments(&closing_gap))
+                    } else {
+                        arena.nil()
+                    })
+                    .nest(self.indent)
+                    .append(if closing_gap.has_comments() {
+                        if closing_gap.has_leading_comments()
+                            || closing_gap.ends_with_line_comment()
+                        {
+                            arena.hardline()
+                        } else {
+                            line.clone()
+                        }
+                    } else {
+                        self.whitespace(Span::new(last_element_end, expr.span.end()), line.clone())
+                    })
                     .group();
 
                 (record, arena.text("}"))
@@ -745,19 +1059,20 @@ where
                 decls.push((body_spacing, arena.text("(")));
                 decls.push(((arena.nil(), true), arena.nil()));
 
-                let inner = arena.concat(
-                    self.comma_sep_paren(
-                        elems
-                            .iter()
-                            .map(|elem| pos::spanned(elem.span, pretty(elem))),
-                        |spanned| spanned.value,
-                    ),
+                let mut elements = self.comma_sep_paren(
+                    elems
+                        .iter()
+                        .map(|elem| pos::spanned(elem.span, pretty(elem))),
+                    |spanned| spanned.value,
                 );
+                // The whitespace after the opening parenthesis is printed by `nilline_after`
+                elements.before_next = Some(arena.nil());
+                let inner = arena.concat(elements);
                 let tuple = chain![
                     arena,
                     self.nilline_after(expr.span.start() + ByteOffset::from(1)),
                     inner,
-                    trailing_comma(arena),
+                    self.trailing_comma(),
                 ]
                 .group();
 
@@ -800,6 +1115,7 @@ where
             f,
             i: 0,
             parens: false,
+            before_next: None,
             _marker: ::std::marker::PhantomData,
         }
     }
@@ -895,7 +1211,7 @@ where
                         ),
                     |spanned| spanned.value,
                 );
-                let doc = arena.concat(iter).nest(INDENT);
+                let doc = arena.concat(iter).nest(self.indent);
                 chain![
                     arena,
                     "{",
@@ -924,7 +1240,14 @@ where
             ]
             .group(),
             Pattern::Error => arena.text("<error>"),
-            Pattern::Literal(_) => arena.text(self.source.src_slice(pattern.span)),
+            Pattern::Literal(ref literal) => {
+                let text = self.source.src_slice(pattern.span);
+                if text.is_empty() {
+                    arena.text(literal_text(literal))
+                } else {
+                    arena.text(text)
+                }
+            }
         }
     }
 
@@ -976,7 +1299,7 @@ where
                 |next, ((body_spacing, nest), from)| {
                     let doc = body_spacing.append(from).append(next);
                     if nest {
-                        doc.nest(INDENT)
+                        doc.nest(self.indent)
                     } else {
                         doc
                     }
@@ -1024,10 +1347,106 @@ where
             f,
             i: 0,
             parens: true,
+            before_next: None,
             _marker: ::std::marker::PhantomData,
         }
     }
 
+    /// Collects the comments in `span`, which lies between two nodes, skipping over any of the
+    /// `separators` tokens
+    fn gap(&self, span: Span<BytePos>, separators: &[&str]) -> Gap<'a> {
+        let source_span = self.source.span();
+        let mut items = Vec::new();
+        if span.start() != BytePos::default()
+            && span.start() <= span.end()
+            && source_span.contains(span)
+        {
+            let mut rest = self.source.src_slice(span);
+            loop {
+                rest = scan_trivia(rest, &mut items);
+                match separators.iter().find(|sep| rest.starts_with(**sep)) {
+                    Some(sep) => rest = &rest[sep.len()..],
+                    None => break,
+                }
+            }
+        }
+
+        let line_end = items
+            .iter()
+            .position(|t| *t == Trivia::Newline)
+            .unwrap_or(items.len());
+        let mut leading = items.split_off(line_end);
+        if !leading.is_empty() {
+            leading.remove(0);
+        }
+        Gap {
+            trailing: items
+                .into_iter()
+                .filter_map(|t| match t {
+                    Trivia::Comment(comment) => Some(comment),
+                    Trivia::Newline => None,
+                })
+                .collect(),
+            leading,
+        }
+    }
+
+    /// The comments which stay on the line of the node before `gap`
+    fn trailing_comments(&self, gap: &Gap<'a>) -> DocBuilder<'a, Arena<'a, A>, A> {
+        let arena = self.arena;
+        arena.concat(
+            gap.trailing
+                .iter()
+                .map(|comment| chain![arena, " ", *comment]),
+        )
+    }
+
+    /// The comments on the lines after the node before `gap`, each preceded by a line break and
+    /// keeping any empty line before it
+    fn own_line_comments(&self, gap: &Gap<'a>) -> DocBuilder<'a, Arena<'a, A>, A> {
+        let arena = self.arena;
+        let mut doc = arena.nil();
+        let mut newlines = 1;
+        for item in &gap.leading {
+            match *item {
+                Trivia::Newline => newlines += 1,
+                Trivia::Comment(comment) => {
+                    doc = chain![
+                        arena,
+                        doc,
+                        match newlines {
+                            0 => arena.space(),
+                            1 => arena.hardline(),
+                            _ => arena.hardline().append(arena.hardline()),
+                        },
+                        comment
+                    ];
+                    newlines = 0;
+                }
+            }
+        }
+        doc
+    }
+
+    /// Splits the comments in `gap` into the comments that follow the node before it and the
+    /// whitespace, with comments, before the node after it. `default` is used as the whitespace if
+    /// no line break is needed.
+    fn separate_comments(
+        &self,
+        gap: &Gap<'a>,
+        default: DocBuilder<'a, Arena<'a, A>, A>,
+    ) -> (
+        DocBuilder<'a, Arena<'a, A>, A>,
+        DocBuilder<'a, Arena<'a, A>, A>,
+    ) {
+        let before_next = if gap.has_leading_comments() || gap.ends_with_line_comment() {
+            self.own_line_comments(gap).append(self.arena.hardline())
+        } else {
+            default
+        };
+        (self.trailing_comments(gap), before_next)
+    }
+
     fn comments(&self, span: Span<BytePos>) -> DocBuilder<'a, Arena<'a, A>, A> {
         self.comments_count(span).0
     }
@@ -1074,6 +1493,9 @@ where
     f: F,
     parens: bool,
     i: usize,
+    /// The whitespace and comments before the next element, if the comments after the previous
+    /// element required it
+    before_next: Option<DocBuilder<'a, Arena<'a, A>, A>>,
     _marker: ::std::marker::PhantomData<U>,
 }
 
@@ -1093,22 +1515,29 @@ where
             let arena = self.printer.arena;
             let i = self.i;
             self.i += 1;
-            chain![
-                arena,
-                if i == 0 && self.parens {
-                    self.printer.comments_before(span.start())
-                } else {
-                    self.printer.space_before(span.start())
-                },
-                (self.f)(item),
-                if self.iter.peek().is_some() {
-                    self.printer
-                        .comments(Span::new(span.end(), self.printer.source.span().end()))
-                        .append(",")
-                } else {
-                    arena.nil()
+            let before = match self.before_next.take() {
+                Some(before) => before,
+                None if i == 0 && self.parens => self.printer.comments_before(span.start()),
+                None => self.printer.space_before(span.start()),
+            };
+            let printer = self.printer;
+            let gap = self
+                .iter
+                .peek()
+                .map(|next| printer.gap(Span::new(span.end(), next.borrow().span.start()), &[","]));
+            let after = match gap {
+                Some(ref gap) if gap.has_comments() => {
+                    let (trailing, before_next) = self.printer.separate_comments(gap, arena.line());
+                    self.before_next = Some(before_next);
+                    chain![arena, ",", trailing]
                 }
-            ]
+                Some(_) => self
+                    .printer
+                    .comments(Span::new(span.end(), self.printer.source.span().end()))
+                    .append(","),
+                None => arena.nil(),
+            };
+            chain![arena, before, (self.f)(item), after]
         })
     }
 }
@@ -1137,6 +1566,27 @@ fn pretty_kind<'a, A>(
     }
 }
 
+fn infix<'a, 'ast, Id>(
+    expr: &'a SpannedExpr<'ast, Id>,
+) -> Option<(
+    &'a SpannedExpr<'ast, Id>,
+    &'a str,
+    &'a SpannedExpr<'ast, Id>,
+)>
+where
+    Id: AsRef<str>,
+{
+    match expr.value {
+        Expr::Infix {
+            ref lhs,
+            ref op,
+            ref rhs,
+            ..
+        } => Some((&**lhs, op.value.name.as_ref(), &**rhs)),
+        _ => None,
+    }
+}
+
 fn hardline<'a, Id, A>(
     arena: &'a Arena<'a, A>,
     expr: &'a SpannedExpr<Id>,
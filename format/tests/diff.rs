@@ -0,0 +1,64 @@
+use gluon_base::pos::{BytePos, Span};
+use gluon_format::{
+    diff::{diff, text_edits},
+    TextEdit,
+};
+
+#[test]
+fn formatted_source_has_no_hunks() {
+    let source = "let x = 1\nx\n";
+    assert_eq!(diff(source, source), Vec::new());
+    assert_eq!(text_edits(source, source), Vec::new());
+}
+
+#[test]
+fn only_changed_lines_are_in_hunks() {
+    let original = "let x =  1\nlet y = 2\nlet z =   3\nx\n";
+    let formatted = "let x = 1\nlet y = 2\nlet z = 3\nx\n";
+    let hunks = diff(original, formatted);
+    assert_eq!(
+        hunks
+            .iter()
+            .map(|hunk| hunk.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "@@ -1,1 +1,1 @@\n-let x =  1\n+let x = 1\n",
+            "@@ -3,1 +3,1 @@\n-let z =   3\n+let z = 3\n",
+        ]
+    );
+
+    let edits = text_edits(original, formatted);
+    assert_eq!(
+        edits,
+        [
+            TextEdit {
+                span: Span::new(BytePos::from(0), BytePos::from(11)),
+                new_text: "let x = 1\n".into(),
+            },
+            TextEdit {
+                span: Span::new(BytePos::from(21), BytePos::from(33)),
+                new_text: "let z = 3\n".into(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn added_and_removed_lines() {
+    let original = "{ a = 1, b = 2 }\n\n\nx\n";
+    let formatted = "{\n    a = 1,\n    b = 2,\n}\n\nx\n";
+    let hunks = diff(original, formatted);
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].original, 0..2);
+    assert_eq!(hunks[0].formatted, 0..4);
+
+    let edits = text_edits(original, formatted);
+    let mut result = original.to_string();
+    for edit in edits.iter().rev() {
+        result.replace_range(
+            edit.span.start().to_usize()..edit.span.end().to_usize(),
+            &edit.new_text,
+        );
+    }
+    assert_eq!(result, formatted);
+}
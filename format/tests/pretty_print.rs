@@ -6,7 +6,10 @@ extern crate gluon_format as format;
 
 use difference::assert_diff;
 
-use gluon::{RootedThread, ThreadExt, VmBuilder};
+use gluon::{
+    base::pos::{BytePos, Span},
+    RootedThread, ThreadExt, VmBuilder,
+};
 
 macro_rules! test_format {
     ($name: ident, $initial: expr) => {
@@ -40,7 +43,18 @@ fn format_expr(expr: &str) -> gluon::Result<String> {
 fn format_expr_expanded(expr: &str) -> gluon::Result<String> {
     let thread = new_vm();
     thread.get_database_mut().set_implicit_prelude(false);
-    thread.format_expr(&mut format::Formatter { expanded: true }, "test", expr)
+    let mut formatter = format::Formatter {
+        expanded: true,
+        ..format::Formatter::default()
+    };
+    thread.format_expr(&mut formatter, "test", expr)
+}
+
+fn format_expr_with(config: &str, expr: &str) -> gluon::Result<String> {
+    let thread = new_vm();
+    thread.get_database_mut().set_implicit_prelude(false);
+    let mut formatter = format::Formatter::from_config(config, None).unwrap();
+    thread.format_expr(&mut formatter, "test", expr)
 }
 
 #[test]
@@ -249,19 +263,23 @@ fn doc_comment_in_record_expr() {
     assert_diff!(&format_expr(expr).unwrap(), expr, "\n", 0);
 }
 
-#[test]
-fn preserve_comments_in_empty_record() {
-    let expr = r#"
+test_format! {
+    preserve_comments_in_empty_record,
+r#"
 {
 // 123
 }
-"#;
-    assert_diff!(&format_expr(expr).unwrap(), expr, "\n", 0);
+"#,
+r#"
+{
+    // 123
+}
+"#
 }
 
-#[test]
-fn preserve_comments_in_record_base() {
-    let expr = r#"
+test_format! {
+    preserve_comments_in_record_base,
+r#"
 {
     // 123
     ..
@@ -269,8 +287,16 @@ fn preserve_comments_in_record_base() {
     test
 /* x */
 }
-"#;
-    assert_diff!(&format_expr(expr).unwrap(), expr, "\n", 0);
+"#,
+r#"
+{
+    // 123
+    ..
+    // abc
+    test
+    /* x */
+}
+"#
 }
 
 #[test]
@@ -829,3 +855,361 @@ let assert_success : [Show e]
 ()
 "#
 }
+
+#[test]
+fn configured_width_and_indent() {
+    let expr = r#"
+let f x =
+  let y = x
+  y
+{ field = f 1, other = f 2 }
+"#;
+    assert_diff!(
+        &format_expr_with("max_width = 20\nindent_size = 2", expr).unwrap(),
+        r#"
+let f x =
+  let y = x
+  y
+{
+  field = f 1,
+  other = f 2,
+}
+"#,
+        "\n",
+        0
+    );
+}
+
+#[test]
+fn configured_trailing_commas() {
+    let expr = r#"
+{ field = 1, other = 2 }
+"#;
+    assert_diff!(
+        &format_expr_with("max_width = 20\ntrailing_commas = false", expr).unwrap(),
+        r#"
+{
+    field = 1,
+    other = 2
+}
+"#,
+        "\n",
+        0
+    );
+}
+
+#[test]
+fn configured_operator_line_break() {
+    let expr = r#"
+aaaaaaaaaaaaaaaa + bbbbbbbbbbbbbbbb
+"#;
+    assert_diff!(
+        &format_expr_with("max_width = 30", expr).unwrap(),
+        r#"
+aaaaaaaaaaaaaaaa
+    + bbbbbbbbbbbbbbbb
+"#,
+        "\n",
+        0
+    );
+    assert_diff!(
+        &format_expr_with("max_width = 30\noperator_line_break = \"after\"", expr).unwrap(),
+        r#"
+aaaaaaaaaaaaaaaa +
+    bbbbbbbbbbbbbbbb
+"#,
+        "\n",
+        0
+    );
+}
+
+test_format! {
+    pipeline_chain_puts_each_operator_on_its_own_line,
+    r#"
+let { (|>) } = import! std.function
+let { (>>=) } = import! std.monad
+input |> parse_all_the_things |> validate_every_field |> transform_into_output |> write_it_somewhere_else
+load_configuration path >>= (\config -> connect config) >>= run_the_main_loop >>= shutdown_it_cleanly
+x |> f |> g
+"#,
+    r#"
+let { (|>) } = import! std.function
+let { (>>=) } = import! std.monad
+input
+    |> parse_all_the_things
+    |> validate_every_field
+    |> transform_into_output
+    |> write_it_somewhere_else
+load_configuration path
+    >>= (\config -> connect config)
+    >>= run_the_main_loop
+    >>= shutdown_it_cleanly
+x |> f |> g
+"#
+}
+
+test_format! {
+    boolean_chain_puts_each_operator_on_its_own_line,
+    r#"
+is_valid_identifier name && is_not_a_keyword name && is_short_enough name && is_not_taken_by_anyone name
+    || is_allowed_anyway name
+"#,
+    r#"
+is_valid_identifier name
+    && is_not_a_keyword name
+    && is_short_enough name
+    && is_not_taken_by_anyone name
+    || is_allowed_anyway name
+"#
+}
+
+test_format! {
+    chain_operators_with_the_same_fixity,
+    r#"
+let { (<*>), (<*), (*>) } = import! std.applicative
+map make_record parse_the_name <* parse_whitespace <*> parse_the_value *> parse_the_terminating_token
+"#,
+    r#"
+let { (<*>), (<*), (*>) } = import! std.applicative
+map make_record parse_the_name
+    <* parse_whitespace
+    <*> parse_the_value
+    *> parse_the_terminating_token
+"#
+}
+
+test_format! {
+    chain_operators_with_different_precedences_separately,
+    r#"
+let { (==), (<), (>) } = import! std.cmp
+first_argument_of_the_check > 0 && second_argument_of_the_check < 100 || the_fallback_value_of_it == 1
+"#,
+    r#"
+let { (==), (<), (>) } = import! std.cmp
+first_argument_of_the_check > 0 && second_argument_of_the_check < 100
+    || the_fallback_value_of_it == 1
+"#
+}
+
+#[test]
+fn split_type_parameters_stay_indented() {
+    let expr = r#"
+type Category (cat : Type -> Type -> Type) = { id : forall a . cat a a }
+()
+"#;
+    let formatted = format_expr_with("max_width = 30", expr).unwrap();
+    assert_diff!(
+        &formatted,
+        r#"
+type Category (cat :
+    Type -> Type -> Type)
+    = {
+    id : forall a . cat a a
+}
+()
+"#,
+        "\n",
+        0
+    );
+    assert_diff!(
+        &format_expr_with("max_width = 30", &formatted).unwrap(),
+        &formatted,
+        "\n",
+        0
+    );
+}
+
+#[test]
+fn split_variant_type_is_idempotent() {
+    let expr = r#"
+type Eff r a =
+    | Pure a
+    | Impure : forall x . r x -> Arr r x a -> Eff r a
+()
+"#;
+    let formatted = format_expr_with("max_width = 30", expr).unwrap();
+    assert_diff!(
+        &format_expr_with("max_width = 30", &formatted).unwrap(),
+        &formatted,
+        "\n",
+        0
+    );
+}
+
+#[test]
+fn config_table_and_errors() {
+    let config = r#"
+[package]
+max_width = "ignored"
+
+[format] # only this table is read
+max_width = 80
+operator_line_break = "after"
+"#;
+    let formatter = format::Formatter::from_config(config, Some("format")).unwrap();
+    assert_eq!(formatter.width, 80);
+    assert_eq!(formatter.indent, 4);
+    assert_eq!(
+        formatter.operator_line_break,
+        format::OperatorLineBreak::After
+    );
+
+    let err = format::Formatter::from_config("indent_size = true", None).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Expected a non-negative integer for `indent_size`, found a boolean"
+    );
+    let err = format::Formatter::from_config("\nmax_widht = 80", None).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown option `max_widht`");
+}
+
+fn format_range(config: &str, expr: &str, range: &str) -> (String, String) {
+    let thread = new_vm();
+    thread.get_database_mut().set_implicit_prelude(false);
+    let mut formatter = format::Formatter::from_config(config, None).unwrap();
+    let start = expr.find(range).unwrap();
+    let span = Span::new(
+        BytePos::from(start as u32),
+        BytePos::from((start + range.len()) as u32),
+    );
+    let edit = thread
+        .format_range(&mut formatter, "test", expr, span)
+        .unwrap();
+    let replaced = expr[edit.span.start().to_usize()..edit.span.end().to_usize()].to_string();
+    (replaced, edit.new_text)
+}
+
+#[test]
+fn format_range_of_nested_expression() {
+    let expr = r#"
+let f   x =
+    let y = x
+    {  a  =  y,b=   2 }
+f    1
+"#;
+    assert_eq!(
+        format_range("", expr, "=  y,b"),
+        (
+            "{  a  =  y,b=   2 }".to_string(),
+            "{ a = y, b = 2 }".to_string()
+        )
+    );
+}
+
+#[test]
+fn format_range_keeps_the_indentation_of_the_expression() {
+    let expr = r#"
+let f x =
+    let y = x
+    g   { first_field = y, second_field = y }
+f 1
+"#;
+    assert_eq!(
+        format_range("max_width = 30", expr, "first_field"),
+        (
+            "{ first_field = y, second_field = y }".to_string(),
+            "{\n        first_field = y,\n        second_field = y,\n    }".to_string()
+        )
+    );
+}
+
+test_format! {
+    comments_attached_to_record_fields,
+r#"
+{
+    // leading a
+    a = 1, // trailing a
+
+    // leading b
+    b = 2, // trailing b
+    // dangling
+}
+"#
+}
+
+test_format! {
+    comments_attached_to_match_arms,
+r#"
+match x with // trailing with
+// before first arm
+| Some y -> y // after first arm
+
+// before second arm
+| None -> 0
+"#
+}
+
+test_format! {
+    comments_attached_to_arguments,
+r#"
+f
+    // about 1
+    1 // after 1
+    // about 2
+    2
+"#,
+r#"
+f
+    // about 1
+    1 // after 1
+    // about 2
+    2
+"#
+}
+
+test_format! {
+    comments_attached_to_tuple_elements,
+r#"
+(
+    1, // one
+    2)
+"#,
+r#"
+(
+    1, // one
+    2,
+)
+"#
+}
+
+#[test]
+fn sort_imports() {
+    let expr = r#"
+// The first line
+let string = import! std.string
+let { List } = import! std.list
+let { x } = import! other.module
+
+// Option is needed for `Some`
+let { Option } = import! std.option
+let { Map } = import! std.map
+// The second line
+let string = import! std.string
+string
+"#;
+    assert_eq!(
+        &format_expr_with("sort_imports = true", expr).unwrap(),
+        r#"
+let { List } = import! std.list
+let { Map } = import! std.map
+// Option is needed for `Some`
+let { Option } = import! std.option
+// The first line
+// The second line
+let string = import! std.string
+
+let { x } = import! other.module
+string
+"#
+    );
+}
+
+#[test]
+fn sort_imports_keeps_imports_which_shadow_each_other() {
+    let expr = r#"
+let { map } = import! std.map
+let { map } = import! std.functor
+map
+"#;
+    assert_eq!(&format_expr_with("sort_imports = true", expr).unwrap(), expr);
+}
@@ -0,0 +1,279 @@
+//! Formatting of expressions which are constructed programmatically, as a code generator would.
+
+extern crate gluon_base as base;
+extern crate gluon_format as format;
+
+use base::{
+    ast::{
+        self, Alternative, Argument, Array, Expr, ExprField, Lambda, Literal, Pattern, SpannedExpr,
+        TypedIdent, ValueBinding,
+    },
+    metadata::BaseMetadata,
+    mk_ast_arena,
+    pos::{self, BytePos, Span},
+    types::Type,
+};
+
+use gluon::{
+    parser::infix::{Fixity, OpMeta, OpTable},
+    RootedThread, ThreadExt, VmBuilder,
+};
+
+type Arena<'a, 'ast> = ast::ArenaRef<'a, 'ast, String>;
+type SpExpr<'ast> = SpannedExpr<'ast, String>;
+
+fn no_loc<T>(value: T) -> pos::Spanned<T, BytePos> {
+    pos::spanned(Span::default(), value)
+}
+
+fn id<'ast>(s: &str) -> SpExpr<'ast> {
+    no_loc(Expr::Ident(TypedIdent::new(s.to_string())))
+}
+
+fn int<'ast>(i: i64) -> SpExpr<'ast> {
+    no_loc(Expr::Literal(Literal::Int(i)))
+}
+
+fn literal<'ast>(literal: Literal) -> SpExpr<'ast> {
+    no_loc(Expr::Literal(literal))
+}
+
+fn binop<'ast>(arena: Arena<'_, 'ast>, l: SpExpr<'ast>, op: &str, r: SpExpr<'ast>) -> SpExpr<'ast> {
+    no_loc(Expr::Infix {
+        lhs: arena.alloc(l),
+        op: no_loc(TypedIdent::new(op.to_string())),
+        rhs: arena.alloc(r),
+        implicit_args: Default::default(),
+    })
+}
+
+fn app<'ast>(arena: Arena<'_, 'ast>, func: SpExpr<'ast>, args: Vec<SpExpr<'ast>>) -> SpExpr<'ast> {
+    no_loc(Expr::App {
+        func: arena.alloc(func),
+        implicit_args: Default::default(),
+        args: arena.alloc_extend(args),
+    })
+}
+
+fn let_<'ast>(
+    arena: Arena<'_, 'ast>,
+    name: &str,
+    args: &[&str],
+    expr: SpExpr<'ast>,
+    body: SpExpr<'ast>,
+) -> SpExpr<'ast> {
+    no_loc(Expr::let_binding(
+        arena,
+        ValueBinding {
+            metadata: BaseMetadata::default(),
+            name: no_loc(Pattern::Ident(TypedIdent::new(name.to_string()))),
+            typ: None,
+            resolved_type: Type::hole(),
+            args: arena.alloc_extend(
+                args.iter()
+                    .map(|arg| Argument::explicit(no_loc(TypedIdent::new(arg.to_string())))),
+            ),
+            expr,
+        },
+        body,
+    ))
+}
+
+fn lambda<'ast>(arena: Arena<'_, 'ast>, args: &[&str], body: SpExpr<'ast>) -> SpExpr<'ast> {
+    no_loc(Expr::Lambda(Lambda {
+        id: TypedIdent::new("lambda".to_string()),
+        args: arena.alloc_extend(
+            args.iter()
+                .map(|arg| Argument::explicit(no_loc(TypedIdent::new(arg.to_string())))),
+        ),
+        body: arena.alloc(body),
+    }))
+}
+
+fn if_else<'ast>(
+    arena: Arena<'_, 'ast>,
+    cond: SpExpr<'ast>,
+    if_true: SpExpr<'ast>,
+    if_false: SpExpr<'ast>,
+) -> SpExpr<'ast> {
+    no_loc(Expr::IfElse(
+        arena.alloc(cond),
+        arena.alloc(if_true),
+        arena.alloc(if_false),
+    ))
+}
+
+fn record<'ast>(arena: Arena<'_, 'ast>, fields: Vec<(&str, SpExpr<'ast>)>) -> SpExpr<'ast> {
+    no_loc(Expr::Record {
+        typ: Type::hole(),
+        types: arena.alloc_extend(vec![]),
+        exprs: arena.alloc_extend(fields.into_iter().map(|(name, value)| ExprField {
+            metadata: BaseMetadata::default(),
+            name: no_loc(name.to_string()),
+            value: Some(value),
+        })),
+        base: None,
+    })
+}
+
+fn array<'ast>(arena: Arena<'_, 'ast>, exprs: Vec<SpExpr<'ast>>) -> SpExpr<'ast> {
+    no_loc(Expr::Array(Array {
+        typ: Type::hole(),
+        exprs: arena.alloc_extend(exprs),
+    }))
+}
+
+/// Checks that `source` is already formatted, which also checks that it parses
+fn assert_reparses(source: &str) {
+    let thread: RootedThread = VmBuilder::new().build();
+    thread.get_database_mut().set_implicit_prelude(false);
+    let formatted = thread
+        .format_expr(&mut format::Formatter::default(), "test", source)
+        .unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    assert_eq!(formatted, source);
+}
+
+#[test]
+fn let_bindings_and_applications() {
+    mk_ast_arena!(arena);
+    let arena = arena.borrow();
+
+    let expr = let_(
+        arena,
+        "x",
+        &[],
+        int(1),
+        let_(
+            arena,
+            "add",
+            &["l", "r"],
+            binop(arena, id("l"), "+", id("r")),
+            app(arena, id("add"), vec![id("x"), int(2)]),
+        ),
+    );
+    let printed = format::print_expr(&expr);
+    assert_eq!(printed, "let x = 1\nlet add l r = l + r\nadd x 2\n");
+    assert_reparses(&printed);
+}
+
+#[test]
+fn parenthesize_nested_expressions() {
+    mk_ast_arena!(arena);
+    let arena = arena.borrow();
+
+    let sum = binop(arena, id("a"), "+", id("b"));
+    let product = binop(arena, sum, "*", id("c"));
+    let nested_app = app(arena, id("f"), vec![app(arena, id("g"), vec![id("x")])]);
+    let lambda_arg = app(
+        arena,
+        id("map"),
+        vec![
+            lambda(arena, &["x"], binop(arena, id("x"), "+", int(1))),
+            id("xs"),
+        ],
+    );
+    let if_operand = binop(arena, if_else(arena, id("b"), int(1), int(2)), "+", int(3));
+    let projection = no_loc(Expr::Projection(
+        arena.alloc(app(arena, id("f"), vec![id("x")])),
+        "field".to_string(),
+        Type::hole(),
+    ));
+    let expr = array(
+        arena,
+        vec![
+            product,
+            nested_app,
+            lambda_arg,
+            if_operand,
+            projection,
+            binop(arena, id("a"), "-", binop(arena, id("b"), "-", id("c"))),
+        ],
+    );
+    let printed = format::print_expr(&expr);
+    assert_eq!(
+        printed,
+        "[(a + b) * c, f (g x), map (\\x -> x + 1) xs, (if b then 1 else 2) + 3, (f x).field, a - (b - c)]\n"
+    );
+    assert_reparses(&printed);
+}
+
+#[test]
+fn parenthesize_by_fixity() {
+    mk_ast_arena!(arena);
+    let arena = arena.borrow();
+
+    let mut formatter = format::Formatter::default();
+    formatter.operators = OpTable::new(vec![
+        ("+".to_string(), OpMeta::new(6, Fixity::Left)),
+        ("*".to_string(), OpMeta::new(7, Fixity::Left)),
+        ("++".to_string(), OpMeta::new(5, Fixity::Right)),
+    ]);
+
+    let product = binop(arena, id("b"), "*", id("c"));
+    let sum = binop(arena, id("a"), "+", product);
+    let sum = binop(arena, sum, "+", binop(arena, id("d"), "+", id("e")));
+    let append = binop(
+        arena,
+        id("xs"),
+        "++",
+        binop(arena, id("ys"), "++", id("zs")),
+    );
+    let append = binop(arena, append, "++", id("ws"));
+    let expr = no_loc(Expr::Tuple {
+        typ: Type::hole(),
+        elems: arena.alloc_extend(vec![sum, append]),
+    });
+    assert_eq!(
+        formatter.print_expr(&expr),
+        "(a + b * c + (d + e), (xs ++ ys ++ zs) ++ ws)\n"
+    );
+}
+
+#[test]
+fn escape_literals() {
+    mk_ast_arena!(arena);
+    let arena = arena.borrow();
+
+    let expr = record(
+        arena,
+        vec![
+            (
+                "string",
+                literal(Literal::String("say \"hi\"\n\\".to_string())),
+            ),
+            ("char", literal(Literal::Char('\''))),
+            ("byte", literal(Literal::Byte(7))),
+            ("negative", int(-3)),
+        ],
+    );
+    let printed = format::print_expr(&expr);
+    assert_eq!(
+        printed,
+        r#"{ string = "say \"hi\"\n\\", char = '\'', byte = 7b, negative = -3 }
+"#
+    );
+    assert_reparses(&printed);
+}
+
+#[test]
+fn match_on_literals() {
+    mk_ast_arena!(arena);
+    let arena = arena.borrow();
+
+    let expr = no_loc(Expr::Match(
+        arena.alloc(id("x")),
+        arena.alloc_extend(vec![
+            Alternative {
+                pattern: no_loc(Pattern::Literal(Literal::Int(1))),
+                expr: literal(Literal::String("one".to_string())),
+            },
+            Alternative {
+                pattern: no_loc(Pattern::Ident(TypedIdent::new("_".to_string()))),
+                expr: literal(Literal::String("other".to_string())),
+            },
+        ]),
+    ));
+    let printed = format::print_expr(&expr);
+    assert_eq!(printed, "match x with\n| 1 -> \"one\"\n| _ -> \"other\"\n");
+    assert_reparses(&printed);
+}
@@ -0,0 +1,351 @@
+//! Checks that formatting is idempotent and keeps lines within the configured width, both for the
+//! standard library and for randomly generated programs.
+
+extern crate gluon_format as format;
+
+use std::fs;
+
+use gluon::{RootedThread, ThreadExt, VmBuilder};
+
+fn new_vm() -> RootedThread {
+    VmBuilder::new()
+        .import_paths(Some(vec![".".into(), "..".into()]))
+        .build()
+}
+
+/// The configurations that every property is checked with
+fn configs() -> Vec<format::Formatter> {
+    let config = |config: &str| format::Formatter::from_config(config, None).unwrap();
+    vec![
+        format::Formatter::default(),
+        config("max_width = 60"),
+        config("max_width = 40\nindent_size = 2\ntrailing_commas = false"),
+        config("max_width = 80\noperator_line_break = \"after\""),
+    ]
+}
+
+/// Configurations with a smaller width are only checked for idempotence. The formatter never
+/// splits the head of a `let` binding or a lambda, so deeply nested code can't always fit in them.
+const MIN_CHECKED_WIDTH: usize = 80;
+
+/// Lines are allowed to exceed the width if they contain something which can't be split
+fn is_unbreakable(line: &str) -> bool {
+    let line = line.trim();
+    line.contains('"') || line.contains("//") || line.contains("/*") || !line.contains(' ')
+}
+
+struct Checker {
+    thread: RootedThread,
+    files: usize,
+}
+
+impl Checker {
+    fn new() -> Checker {
+        Checker {
+            thread: new_vm(),
+            files: 0,
+        }
+    }
+
+    fn format(&mut self, formatter: &format::Formatter, source: &str) -> gluon::Result<String> {
+        // Each call gets its own module name so that no source is cached between the calls
+        self.files += 1;
+        let name = format!("format_property_{}", self.files);
+        self.thread
+            .format_expr(&mut formatter.clone(), &name, source)
+    }
+
+    /// Returns the violations of the properties for `source`, formatted with `formatter`
+    fn check(&mut self, formatter: &format::Formatter, source: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        let once = self
+            .format(formatter, source)
+            .unwrap_or_else(|err| panic!("{}\nWhen formatting:\n{}", err, source));
+        let twice = match self.format(formatter, &once) {
+            Ok(twice) => twice,
+            Err(err) => {
+                violations.push(format!(
+                    "The formatted code does not parse with {:?}\n{}\n{}",
+                    formatter, err, once
+                ));
+                return violations;
+            }
+        };
+        if once != twice {
+            violations.push(format!(
+                "Formatting is not idempotent with {:?}\n{}",
+                formatter,
+                format::diff::diff(&once, &twice)
+                    .iter()
+                    .map(|hunk| hunk.to_string())
+                    .collect::<String>()
+            ));
+        }
+
+        if formatter.width < MIN_CHECKED_WIDTH {
+            return violations;
+        }
+        for (i, line) in once.lines().enumerate() {
+            if line.chars().count() > formatter.width && !is_unbreakable(line) {
+                violations.push(format!(
+                    "Line {} is longer than {} characters:\n{}",
+                    i + 1,
+                    formatter.width,
+                    line
+                ));
+            }
+        }
+        violations
+    }
+}
+
+#[test]
+fn std_library() {
+    let _ = env_logger::try_init();
+
+    let mut checker = Checker::new();
+    let mut violations = Vec::new();
+    for entry in walkdir::WalkDir::new("../std") {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("glu") {
+            continue;
+        }
+        let source = fs::read_to_string(path).unwrap();
+        for formatter in configs() {
+            violations.extend(
+                checker
+                    .check(&formatter, &source)
+                    .into_iter()
+                    .map(|violation| format!("{}: {}", path.display(), violation)),
+            );
+        }
+    }
+    assert!(
+        violations.is_empty(),
+        "{} violations:\n{}",
+        violations.len(),
+        violations.join("\n\n")
+    );
+}
+
+/// A xorshift generator, so that the generated programs are the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+}
+
+const IDENTS: &[&str] = &[
+    "x",
+    "y",
+    "value",
+    "accumulator",
+    "some_longer_name",
+    "transform",
+    "f",
+];
+const FIELDS: &[&str] = &["a", "field", "another_field", "name"];
+const OPERATORS: &[&str] = &["+", "-", "*", "==", "<", "&&", "||", "<>", "|>", ">>="];
+
+const PRELUDE: &str = r#"let { (<>) } = import! std.semigroup
+let { (|>) } = import! std.function
+let { (>>=) } = import! std.monad
+"#;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Atom,
+    Infix,
+    Other,
+}
+
+/// Generates the source of random (but not necessarily well typed) programs
+struct Generator {
+    rng: Rng,
+}
+
+impl Generator {
+    fn atom(&mut self) -> String {
+        match self.rng.below(5) {
+            0 => self.rng.below(1000).to_string(),
+            1 => format!("\"{}\"", self.rng.pick(IDENTS)),
+            2 => format!("{}.{}", self.rng.pick(IDENTS), self.rng.pick(FIELDS)),
+            _ => self.rng.pick(IDENTS).to_string(),
+        }
+    }
+
+    fn parenthesized(&mut self, depth: u32) -> String {
+        match self.expr(depth) {
+            (expr, Kind::Atom) => expr,
+            (expr, _) => format!("({})", expr),
+        }
+    }
+
+    fn operand(&mut self, depth: u32) -> String {
+        match self.expr(depth) {
+            (expr, Kind::Other) => format!("({})", expr),
+            (expr, _) => expr,
+        }
+    }
+
+    fn list(&mut self, depth: u32, max: usize) -> Vec<String> {
+        (0..self.rng.below(max + 1))
+            .map(|_| self.expr(depth).0)
+            .collect()
+    }
+
+    /// Generates an expression which is written on a single line
+    fn expr(&mut self, depth: u32) -> (String, Kind) {
+        if depth == 0 {
+            return (self.atom(), Kind::Atom);
+        }
+        let depth = depth - 1;
+        match self.rng.below(10) {
+            0 => (self.atom(), Kind::Atom),
+            1 | 2 => {
+                let mut app = self.rng.pick(IDENTS).to_string();
+                for _ in 0..self.rng.below(4) + 1 {
+                    app.push(' ');
+                    app.push_str(&self.parenthesized(depth));
+                }
+                (app, Kind::Other)
+            }
+            3 | 4 => {
+                let mut infix = self.operand(depth);
+                for _ in 0..self.rng.below(3) + 1 {
+                    let op = self.rng.pick(OPERATORS);
+                    infix = format!("{} {} {}", infix, op, self.operand(depth));
+                }
+                (infix, Kind::Infix)
+            }
+            5 => {
+                let fields: Vec<_> = (0..self.rng.below(4))
+                    .map(|i| format!("{} = {}", FIELDS[i], self.expr(depth).0))
+                    .collect();
+                (format!("{{ {} }}", fields.join(", ")), Kind::Atom)
+            }
+            6 => (format!("[{}]", self.list(depth, 4).join(", ")), Kind::Atom),
+            7 => {
+                let first = self.expr(depth).0;
+                let second = self.expr(depth).0;
+                (format!("({}, {})", first, second), Kind::Atom)
+            }
+            8 => {
+                let cond = self.operand(depth);
+                let if_true = self.operand(depth);
+                let if_false = self.operand(depth);
+                (
+                    format!("if {} then {} else {}", cond, if_true, if_false),
+                    Kind::Other,
+                )
+            }
+            _ => {
+                let args: Vec<_> = (0..self.rng.below(2) + 1)
+                    .map(|_| self.rng.pick(IDENTS))
+                    .collect();
+                let body = self.expr(depth).0;
+                (format!("\\{} -> {}", args.join(" "), body), Kind::Other)
+            }
+        }
+    }
+
+    fn pattern(&mut self) -> String {
+        match self.rng.below(5) {
+            0 => "_".to_string(),
+            1 => format!("Some {}", self.rng.pick(IDENTS)),
+            2 => "None".to_string(),
+            3 => format!("{{ {}, {} }}", FIELDS[0], FIELDS[1]),
+            _ => format!("({}, {})", self.rng.pick(IDENTS), self.rng.pick(IDENTS)),
+        }
+    }
+
+    /// Generates an expression which may span multiple lines, each line after the first one is
+    /// indented by `indent`
+    fn block(&mut self, depth: u32, indent: usize) -> String {
+        let newline = format!("\n{}", " ".repeat(indent));
+        match self.rng.below(if depth == 0 { 1 } else { 4 }) {
+            0 => self.expr(depth + 2).0,
+            1 => {
+                let comment = if self.rng.below(3) == 0 {
+                    format!("// {}{}", self.rng.pick(IDENTS), newline)
+                } else {
+                    String::new()
+                };
+                let name = self.rng.pick(IDENTS);
+                let args: Vec<_> = (0..self.rng.below(3))
+                    .map(|_| format!(" {}", self.rng.pick(IDENTS)))
+                    .collect();
+                let value = self.expr(depth + 1).0;
+                let body = self.block(depth - 1, indent);
+                format!(
+                    "{}let {}{} = {}{}{}",
+                    comment,
+                    name,
+                    args.concat(),
+                    value,
+                    newline,
+                    body
+                )
+            }
+            2 => {
+                let scrutinee = self.operand(depth);
+                let mut result = format!("match {} with", scrutinee);
+                for _ in 0..self.rng.below(3) + 1 {
+                    let pattern = self.pattern();
+                    let body = if self.rng.below(3) == 0 {
+                        format!("{}    {}", newline, self.block(depth - 1, indent + 4))
+                    } else {
+                        format!(" {}", self.expr(depth).0)
+                    };
+                    result.push_str(&format!("{}| {} ->{}", newline, pattern, body));
+                }
+                result
+            }
+            _ => {
+                let fields: Vec<_> = (0..self.rng.below(4) + 1)
+                    .map(|i| format!("{}    {} = {},", newline, FIELDS[i], self.expr(depth).0))
+                    .collect();
+                format!("{{{}{}}}", fields.concat(), newline)
+            }
+        }
+    }
+}
+
+#[test]
+fn generated_programs() {
+    let _ = env_logger::try_init();
+
+    let mut checker = Checker::new();
+    let mut generator = Generator {
+        rng: Rng(0x2545_f491_4f6c_dd1d),
+    };
+    let mut violations = Vec::new();
+    for _ in 0..200 {
+        let source = format!("{}{}\n", PRELUDE, generator.block(3, 0));
+        for formatter in configs() {
+            violations.extend(
+                checker
+                    .check(&formatter, &source)
+                    .into_iter()
+                    .map(|violation| format!("{}\nIn program:\n{}", violation, source)),
+            );
+        }
+    }
+    assert!(
+        violations.is_empty(),
+        "{} violations:\n{}",
+        violations.len(),
+        violations.join("\n\n")
+    );
+}
@@ -0,0 +1,164 @@
+//! Node.js bindings for gluon, built with `napi build` into the `gluon-lang` package. The bindings
+//! are built on the C API of `gluon_c-api`, run `npm run build` in the `node` directory to build
+//! them.
+//!
+//! ```js
+//! const { Vm } = require("gluon-lang");
+//!
+//! const vm = new Vm();
+//! vm.loadScript("shapes", "let area w h : Float -> Float -> Float = w * h in { area }");
+//! assert.strictEqual(vm.call("shapes.area", [2, 3.5]), 7);
+//! assert.deepStrictEqual(vm.runExpr("[1, 2] ++ [3]"), [1, 2, 3]);
+//! ```
+//!
+//! Values are converted according to their gluon type, as described by
+//! `gluon::vm::api::json::to_json`. Numbers, strings and booleans become their JavaScript
+//! equivalents, arrays become arrays, records become objects and `Option` becomes the value or
+//! `null`. Errors from gluon are thrown as `Error`s.
+#![doc(html_root_url = "https://docs.rs/gluon_node/0.17.1")] // # GLUON
+
+use std::{ptr, slice};
+
+use napi::{Error, Result};
+use napi_derive::napi;
+
+use serde_json::Value;
+
+use gluon_c_api::{
+    glu_builder_build, glu_builder_new, glu_builder_set_run_io, glu_call_json, glu_free_vm,
+    glu_last_error, glu_load_script, glu_run_expr_json, Error as GluError, Thread,
+};
+
+fn gluon_error(err: impl ToString) -> Error {
+    Error::from_reason(err.to_string())
+}
+
+/// A vm created through the C API which is freed when dropped
+struct VmHandle(*const Thread);
+
+impl Drop for VmHandle {
+    fn drop(&mut self) {
+        unsafe { glu_free_vm(&*self.0) }
+    }
+}
+
+impl VmHandle {
+    fn new(run_io: bool) -> Self {
+        unsafe {
+            let builder = glu_builder_new();
+            glu_builder_set_run_io(&mut *builder, run_io as i8);
+            VmHandle(glu_builder_build(builder))
+        }
+    }
+
+    fn run_expr(&self, name: &str, expr: &str) -> Result<Value> {
+        let (mut json, mut json_len) = (ptr::null(), 0);
+        unsafe {
+            check(glu_run_expr_json(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(expr),
+                expr.len(),
+                &mut json,
+                &mut json_len,
+            ))?;
+            parse_json(json, json_len)
+        }
+    }
+
+    fn load_script(&self, name: &str, source: &str) -> Result<()> {
+        unsafe {
+            check(glu_load_script(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(source),
+                source.len(),
+            ))
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value> {
+        let args = serde_json::to_string(args).map_err(gluon_error)?;
+        let (mut json, mut json_len) = (ptr::null(), 0);
+        unsafe {
+            check(glu_call_json(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(&args),
+                args.len(),
+                &mut json,
+                &mut json_len,
+            ))?;
+            parse_json(json, json_len)
+        }
+    }
+}
+
+/// The C API takes strings as a reference to their first byte, which empty strings do not have
+fn str_ptr(s: &str) -> &u8 {
+    s.as_bytes().first().unwrap_or(&0)
+}
+
+fn check(err: GluError) -> Result<()> {
+    if err == GluError::Ok {
+        return Ok(());
+    }
+    let (mut message, mut len) = (ptr::null(), 0);
+    unsafe {
+        glu_last_error(&mut message, &mut len);
+        Err(gluon_error(String::from_utf8_lossy(slice::from_raw_parts(
+            message, len,
+        ))))
+    }
+}
+
+/// Parses the JSON returned by the C API, which is only valid until the next call on this thread
+unsafe fn parse_json(json: *const u8, len: usize) -> Result<Value> {
+    serde_json::from_slice(slice::from_raw_parts(json, len)).map_err(gluon_error)
+}
+
+#[napi(object)]
+pub struct VmOptions {
+    /// Run the `IO` actions which scripts and expressions evaluate to (default: false)
+    pub run_io: Option<bool>,
+}
+
+/// A gluon virtual machine with the standard library
+#[napi]
+pub struct Vm {
+    vm: VmHandle,
+}
+
+#[napi]
+impl Vm {
+    #[napi(constructor)]
+    pub fn new(options: Option<VmOptions>) -> Self {
+        let run_io = options.and_then(|options| options.run_io).unwrap_or(false);
+        Vm {
+            vm: VmHandle::new(run_io),
+        }
+    }
+
+    /// Compiles and runs `expr`, returning its value
+    #[napi]
+    pub fn run_expr(&self, expr: String, name: Option<String>) -> Result<Value> {
+        self.vm.run_expr(name.as_deref().unwrap_or("node"), &expr)
+    }
+
+    /// Compiles and runs `source` as the module `name`, which can then be imported with
+    /// `import! name` and its fields called with `call`
+    #[napi]
+    pub fn load_script(&self, name: String, source: String) -> Result<()> {
+        self.vm.load_script(&name, &source)
+    }
+
+    /// Calls the global function `name`, such as `std.string.len` or a field of a module loaded
+    /// with `loadScript`. The arguments are converted to the types in the function's signature.
+    #[napi]
+    pub fn call(&self, name: String, args: Vec<Value>) -> Result<Value> {
+        self.vm.call(&name, &args)
+    }
+}
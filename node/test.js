@@ -0,0 +1,31 @@
+// Run with `npm run build && npm test`
+const assert = require("assert");
+
+const { Vm } = require(".");
+
+const vm = new Vm();
+
+assert.strictEqual(vm.runExpr("1 + 2"), 3);
+assert.deepStrictEqual(vm.runExpr("[1.5, 2.0]"), [1.5, 2.0]);
+assert.deepStrictEqual(
+  vm.runExpr(`{ name = "gluon", tags = ["a"], version = Some 17, ok = True }`),
+  { name: "gluon", tags: ["a"], version: 17, ok: true }
+);
+assert.throws(() => vm.runExpr(`1 + ""`));
+
+vm.loadScript(
+  "shapes",
+  `
+  type Shape = | Circle Float | Rect Float Float
+  let area shape : Shape -> Float =
+      match shape with
+      | Circle r -> 3.0 * r * r
+      | Rect w h -> w * h
+  let sum xs : Array Int -> Int = (import! std.array).foldable.foldl (+) 0 xs
+  { Shape, area, sum }
+  `
+);
+assert.strictEqual(vm.call("shapes.area", [{ Rect: [2, 3.5] }]), 7);
+assert.strictEqual(vm.call("shapes.sum", [[1, 2, 3]]), 6);
+assert.strictEqual(vm.call("std.string.len", ["abc"]), 3);
+assert.throws(() => vm.call("shapes.area", ["Square"]), /not a constructor/);
@@ -7,6 +7,7 @@ use crate::base::ast::{
 };
 use crate::base::error::Errors;
 use crate::base::fnv::FnvMap;
+use crate::base::origin::{Desugaring, OriginMap};
 use crate::base::pos::{self, BytePos, Spanned};
 use std::cmp::Ordering;
 use std::error::Error as StdError;
@@ -14,6 +15,7 @@ use std::fmt;
 use std::hash::Hash;
 use std::marker::PhantomData;
 use std::mem;
+use std::str::FromStr;
 
 /// The fixity (associativity) of an infix operator
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -63,6 +65,34 @@ impl OpMeta {
     }
 }
 
+/// Parses the arguments of an `#[infix(left, 6)]` attribute
+impl FromStr for OpMeta {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<OpMeta, Error> {
+        let mut iter = s.splitn(2, ",");
+        let fixity = match iter.next().ok_or(Error::InvalidFixity)?.trim() {
+            "left" => Fixity::Left,
+            "right" => Fixity::Right,
+            _ => {
+                return Err(Error::InvalidFixity);
+            }
+        };
+        let precedence = iter
+            .next()
+            .and_then(|s| s.trim().parse().ok())
+            .and_then(|precedence| {
+                if precedence >= 0 {
+                    Some(precedence)
+                } else {
+                    None
+                }
+            })
+            .ok_or(Error::InvalidPrecedence)?;
+        Ok(OpMeta { fixity, precedence })
+    }
+}
+
 impl fmt::Display for OpMeta {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} {}", self.fixity, self.precedence)
@@ -70,6 +100,7 @@ impl fmt::Display for OpMeta {
 }
 
 /// A table of operator metadata
+#[derive(Clone, Debug)]
 pub struct OpTable<Id> {
     pub operators: FnvMap<Id, OpMeta>,
 }
@@ -99,106 +130,112 @@ where
         })
     }
 
-    fn get(&self, name: &Id) -> Option<&OpMeta> {
-        self.operators.get(name).or_else(|| {
-            let name = name.as_ref();
-            if name.starts_with('#') || name == "&&" || name == "||" {
-                const OPS: &[(&str, OpMeta)] = &[
-                    (
-                        "*",
-                        OpMeta {
-                            precedence: 7,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "/",
-                        OpMeta {
-                            precedence: 7,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "+",
-                        OpMeta {
-                            precedence: 6,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "-",
-                        OpMeta {
-                            precedence: 6,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "==",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "/=",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "<",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        ">",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "<=",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        ">=",
-                        OpMeta {
-                            precedence: 4,
-                            fixity: Fixity::Left,
-                        },
-                    ),
-                    (
-                        "&&",
-                        OpMeta {
-                            precedence: 3,
-                            fixity: Fixity::Right,
-                        },
-                    ),
-                    (
-                        "||",
-                        OpMeta {
-                            precedence: 2,
-                            fixity: Fixity::Right,
-                        },
-                    ),
-                ];
-
-                let op = name
-                    .trim_start_matches('#')
-                    .trim_start_matches(char::is_alphanumeric);
-
-                OPS.iter().find(|t| t.0 == op).map(|t| &t.1)
-            } else {
-                None
-            }
-        })
+    /// Returns the fixity of `name`, falling back to the fixities of the builtin operators
+    pub fn get(&self, name: &Id) -> Option<&OpMeta> {
+        self.operators
+            .get(name)
+            .or_else(|| builtin_fixity(name.as_ref()))
+    }
+}
+
+/// Returns the fixity of the builtin operators, such as `#Int+` and `&&`, which do not have an
+/// `#[infix]` attribute
+pub fn builtin_fixity(name: &str) -> Option<&'static OpMeta> {
+    if name.starts_with('#') || name == "&&" || name == "||" {
+        const OPS: &[(&str, OpMeta)] = &[
+            (
+                "*",
+                OpMeta {
+                    precedence: 7,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "/",
+                OpMeta {
+                    precedence: 7,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "+",
+                OpMeta {
+                    precedence: 6,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "-",
+                OpMeta {
+                    precedence: 6,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "==",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "/=",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "<",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                ">",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "<=",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                ">=",
+                OpMeta {
+                    precedence: 4,
+                    fixity: Fixity::Left,
+                },
+            ),
+            (
+                "&&",
+                OpMeta {
+                    precedence: 3,
+                    fixity: Fixity::Right,
+                },
+            ),
+            (
+                "||",
+                OpMeta {
+                    precedence: 2,
+                    fixity: Fixity::Right,
+                },
+            ),
+        ];
+
+        let op = name
+            .trim_start_matches('#')
+            .trim_start_matches(char::is_alphanumeric);
+
+        OPS.iter().find(|t| t.0 == op).map(|t| &t.1)
+    } else {
+        None
     }
 }
 
@@ -207,6 +244,7 @@ pub struct Reparser<'s, 'ast, Id: 's> {
     operators: OpTable<Id>,
     symbols: &'s dyn IdentEnv<Ident = Id>,
     errors: Errors<Spanned<Error, BytePos>>,
+    origins: OriginMap,
     _marker: PhantomData<Id>,
 }
 
@@ -221,10 +259,16 @@ impl<'s, 'ast, Id> Reparser<'s, 'ast, Id> {
             operators,
             symbols,
             errors: Errors::new(),
+            origins: OriginMap::new(),
             _marker: PhantomData,
         }
     }
 
+    /// The infix expressions which were reassociated by `reparse`
+    pub fn into_origins(self) -> OriginMap {
+        self.origins
+    }
+
     pub fn reparse(
         &mut self,
         expr: &mut SpannedExpr<'ast, Id>,
@@ -254,6 +298,7 @@ where
             match reparse(self.arena, dummy, self.symbols, &self.operators) {
                 Ok(expr) => {
                     mem::swap(e, expr);
+                    record_infix_origins(&mut self.origins, e);
                 }
                 Err((err, reconstructed_expr)) => {
                     info!("Infix error: {}", err);
@@ -272,6 +317,14 @@ where
     }
 }
 
+fn record_infix_origins<Id>(origins: &mut OriginMap, expr: &SpannedExpr<Id>) {
+    if let Expr::Infix { lhs, rhs, .. } = &expr.value {
+        origins.record(expr.span, Desugaring::Infix, expr.span);
+        record_infix_origins(origins, lhs);
+        record_infix_origins(origins, rhs);
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Error {
     ConflictingFixities((String, OpMeta), (String, OpMeta)),
@@ -365,8 +418,8 @@ where
                                     Some(expr) => arg_stack.push(expr),
                                     None => (),
                                 }
-                                op_stack.push(next_op);
                                 op_stack.push(stack_op);
+                                op_stack.push(next_op);
                                 while arg_stack.len() > 1 {
                                     let rhs = arg_stack.pop().unwrap();
                                     let lhs = arg_stack.pop().unwrap();
@@ -660,6 +713,33 @@ mod tests {
         assert_eq!(reparse(arena, expr(), &env, &ops), expected);
     }
 
+    #[test]
+    fn reparse_undefined_fixity_keeps_the_order_of_the_operators() {
+        mk_ast_arena!(arena);
+        let arena = arena.borrow();
+
+        let env = MockEnv::new();
+        let ops = OpTable::new(vec![("-".to_string(), OpMeta::new(6, Fixity::Left))]);
+
+        // 1 - (2 <> 8)
+        let expr = || {
+            op(
+                arena,
+                int(arena, 1),
+                "-",
+                op(arena, int(arena, 2), "<>", int(arena, 8)),
+            )
+        };
+        let result = super::reparse(arena, expr(), &env, &ops);
+        match result {
+            Err((err, Some(reconstructed))) => {
+                assert_eq!(err.value, UndefinedFixity("<>".to_string()));
+                assert_eq!(reconstructed, expr().value);
+            }
+            _ => panic!("Expected an undefined fixity error, got {:?}", result),
+        }
+    }
+
     #[test]
     fn reparse_less_precedence() {
         mk_ast_arena!(arena);
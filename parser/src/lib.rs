@@ -28,6 +28,7 @@ use crate::base::{
     fnv::FnvMap,
     metadata::{BaseMetadata, Metadata},
     mk_ast_arena,
+    origin::OriginMap,
     pos::{self, ByteOffset, BytePos, Span, Spanned},
     source,
     symbol::Symbol,
@@ -35,14 +36,16 @@ use crate::base::{
 };
 
 use crate::{
-    infix::{Fixity, OpMeta, OpTable, Reparser},
+    infix::{OpMeta, OpTable, Reparser},
     layout::Layout,
-    token::{BorrowedToken, Tokenizer},
+    token::BorrowedToken,
 };
 
 pub use crate::{
-    infix::Error as InfixError, layout::Error as LayoutError, token::Error as TokenizeError,
-    token::Token,
+    infix::Error as InfixError,
+    layout::Error as LayoutError,
+    token::Error as TokenizeError,
+    token::{SpannedToken, Token, Tokenizer},
 };
 
 lalrpop_mod!(
@@ -53,8 +56,10 @@ lalrpop_mod!(
 
 pub mod infix;
 mod layout;
+mod pattern_synonym;
 mod str_suffix;
 mod token;
+mod view_pattern;
 
 fn new_ident<Id>(type_cache: &TypeCache<Id, ArcType<Id>>, name: Id) -> TypedIdent<Id> {
     TypedIdent {
@@ -194,6 +199,16 @@ fn remove_extra_quotes(tokens: &mut [String]) {
 }
 
 impl Error {
+    /// Returns true if the error was caused by the input ending before the expression did, such as
+    /// an unterminated layout block, an unclosed delimiter or a trailing operator. Appending more
+    /// input may fix these errors.
+    pub fn is_unexpected_end(&self) -> bool {
+        match self {
+            Error::UnexpectedToken(Token::CloseBlock, _) | Error::UnexpectedEof(_) => true,
+            _ => false,
+        }
+    }
+
     fn from_lalrpop(source_span: Span<BytePos>, err: LalrpopError) -> Spanned<Error, BytePos> {
         use lalrpop_util::ParseError::*;
 
@@ -335,6 +350,7 @@ impl_temp_vec! {
     ast::Alternative<'ast, Id> => alts,
     ast::Argument<ast::SpannedIdent<Id>> => args,
     FieldExpr<'ast, Id> => field_expr,
+    view_pattern::ViewPattern<'ast, Id> => view_patterns,
     ast::InnerAstType<'ast, Id> => types,
     AstType<'ast, Id> => type_ptrs,
     Generic<Id> => generics,
@@ -416,17 +432,57 @@ where
     Id: Clone + AsRef<str> + std::fmt::Debug,
     S: ?Sized + ParserSource,
 {
-    parse_with(input, &mut |parse_errors, layout| {
+    let mut temp_vecs = TempVecs::new();
+    let result = parse_with(input, &mut |parse_errors, layout| {
         grammar::TopExprParser::new().parse(
             &input,
             type_cache,
             arena,
             symbols,
             parse_errors,
-            &mut TempVecs::new(),
+            &mut temp_vecs,
             layout,
         )
-    })
+    });
+    let views = std::mem::take(&mut temp_vecs.view_patterns);
+    match result {
+        Ok(mut expr) => match desugar(arena, symbols, type_cache, views, &mut expr) {
+            Ok(()) => Ok(expr),
+            Err(errors) => Err((Some(expr), errors)),
+        },
+        Err((Some(mut expr), mut errors)) => {
+            if let Err(err) = desugar(arena, symbols, type_cache, views, &mut expr) {
+                errors.extend(err);
+            }
+            Err((Some(expr), errors))
+        }
+        Err((None, errors)) => Err((None, errors)),
+    }
+}
+
+/// Expands pattern synonyms and desugars view patterns
+fn desugar<'ast, Id>(
+    arena: ast::ArenaRef<'_, 'ast, Id>,
+    symbols: &mut dyn IdentEnv<Ident = Id>,
+    type_cache: &TypeCache<Id, ArcType<Id>>,
+    views: Vec<view_pattern::ViewPattern<'ast, Id>>,
+    expr: &mut SpannedExpr<'ast, Id>,
+) -> Result<(), ParseErrors>
+where
+    Id: Clone + AsRef<str>,
+{
+    let mut errors = Errors::new();
+    if let Err(err) = pattern_synonym::expand(arena, type_cache, expr) {
+        errors.extend(err);
+    }
+    if let Err(err) = view_pattern::desugar(arena, symbols, type_cache, views, expr) {
+        errors.extend(err);
+    }
+    if errors.has_errors() {
+        Err(errors)
+    } else {
+        Ok(())
+    }
 }
 
 pub fn parse_expr<'ast>(
@@ -520,15 +576,51 @@ pub fn reparse_infix<'ast, Id>(
     arena: ast::ArenaRef<'_, 'ast, Id>,
     metadata: &FnvMap<Id, Arc<Metadata>>,
     symbols: &dyn IdentEnv<Ident = Id>,
+    origins: &mut OriginMap,
     expr: &mut SpannedExpr<'ast, Id>,
 ) -> Result<(), ParseErrors>
 where
     Id: Clone + Eq + Hash + AsRef<str> + ::std::fmt::Debug,
 {
-    use crate::base::ast::{is_operator_char, walk_pattern, Pattern, Visitor};
-
     let mut errors = Errors::new();
 
+    let op_table = check_infix(metadata, expr, &mut errors);
+
+    let mut reparser = Reparser::new(arena, op_table, symbols);
+    match reparser.reparse(expr) {
+        Err(reparse_errors) => {
+            errors.extend(reparse_errors.into_iter().map(|err| err.map(Error::from)));
+        }
+        Ok(_) => {}
+    }
+    origins.extend(reparser.into_origins());
+
+    if errors.has_errors() {
+        Err(errors)
+    } else {
+        Ok(())
+    }
+}
+
+/// Collects the fixities of the operators bound in `expr` from their `#[infix]` attributes.
+/// Operators with missing or invalid attributes are left out of the table.
+pub fn op_table<Id>(metadata: &FnvMap<Id, Arc<Metadata>>, expr: &SpannedExpr<Id>) -> OpTable<Id>
+where
+    Id: Clone + Eq + Hash + AsRef<str>,
+{
+    check_infix(metadata, expr, &mut Errors::new())
+}
+
+fn check_infix<Id>(
+    metadata: &FnvMap<Id, Arc<Metadata>>,
+    expr: &SpannedExpr<Id>,
+    errors: &mut Errors<Spanned<Error, BytePos>>,
+) -> OpTable<Id>
+where
+    Id: Clone + Eq + Hash + AsRef<str>,
+{
+    use crate::base::ast::{is_operator_char, walk_pattern, Pattern, Visitor};
+
     struct CheckInfix<'b, Id>
     where
         Id: 'b,
@@ -548,39 +640,14 @@ where
                 .get(id)
                 .and_then(|meta| meta.get_attribute("infix"))
             {
-                Some(infix_attribute) => {
-                    fn parse_infix(s: &str) -> Result<OpMeta, InfixError> {
-                        let mut iter = s.splitn(2, ",");
-                        let fixity = match iter.next().ok_or(InfixError::InvalidFixity)?.trim() {
-                            "left" => Fixity::Left,
-                            "right" => Fixity::Right,
-                            _ => {
-                                return Err(InfixError::InvalidFixity);
-                            }
-                        };
-                        let precedence = iter
-                            .next()
-                            .and_then(|s| s.trim().parse().ok())
-                            .and_then(|precedence| {
-                                if precedence >= 0 {
-                                    Some(precedence)
-                                } else {
-                                    None
-                                }
-                            })
-                            .ok_or(InfixError::InvalidPrecedence)?;
-                        Ok(OpMeta { fixity, precedence })
+                Some(infix_attribute) => match infix_attribute.parse::<OpMeta>() {
+                    Ok(op_meta) => {
+                        self.op_table.operators.insert(id.clone(), op_meta);
                     }
-
-                    match parse_infix(infix_attribute) {
-                        Ok(op_meta) => {
-                            self.op_table.operators.insert(id.clone(), op_meta);
-                        }
-                        Err(err) => {
-                            self.errors.push(pos::spanned(span, err.into()));
-                        }
+                    Err(err) => {
+                        self.errors.push(pos::spanned(span, err.into()));
                     }
-                }
+                },
 
                 None => {
                     if id.as_ref().starts_with(is_operator_char) {
@@ -627,22 +694,9 @@ where
     let mut op_table = OpTable::new(None);
     CheckInfix {
         metadata,
-        errors: &mut errors,
+        errors,
         op_table: &mut op_table,
     }
     .visit_expr(expr);
-
-    let mut reparser = Reparser::new(arena, op_table, symbols);
-    match reparser.reparse(expr) {
-        Err(reparse_errors) => {
-            errors.extend(reparse_errors.into_iter().map(|err| err.map(Error::from)));
-        }
-        Ok(_) => {}
-    }
-
-    if errors.has_errors() {
-        Err(errors)
-    } else {
-        Ok(())
-    }
+    op_table
 }
@@ -0,0 +1,369 @@
+//! Expands pattern synonyms, `let` bindings marked with the `#[pattern]` attribute.
+//!
+//! ```gluon
+//! #[pattern]
+//! let Singleton x = Cons x Nil
+//!
+//! match list with
+//! | Singleton x -> x
+//! | _ -> 0
+//! ```
+//!
+//! Inside the body of the `let`, patterns which use the synonym are replaced by the pattern it
+//! stands for, with the arguments of the synonym substituted for its parameters, so the match
+//! above becomes `| Cons x Nil -> x`. The binding itself is kept as a function so the synonym can
+//! construct values as well.
+use std::mem;
+
+use crate::base::{
+    ast::{
+        self, ArenaRef, AstClone, Expr, MutVisitor, Pattern, PatternField, SpannedExpr,
+        SpannedPattern, ValueBinding,
+    },
+    error::Errors,
+    pos::{self, BytePos, Spanned},
+    types::{ArcType, TypeCache},
+};
+
+use crate::{new_ident, Error, ParseErrors};
+
+struct Synonym<'ast, Id> {
+    name: String,
+    params: Vec<String>,
+    pattern: SpannedPattern<'ast, Id>,
+}
+
+struct Expander<'a, 'b, 'ast, Id> {
+    arena: ArenaRef<'b, 'ast, Id>,
+    type_cache: &'a TypeCache<Id, ArcType<Id>>,
+    synonyms: Vec<Synonym<'ast, Id>>,
+    errors: ParseErrors,
+}
+
+fn is_synonym<Id>(bind: &ValueBinding<'_, Id>) -> bool {
+    bind.metadata.get_attribute("pattern").is_some()
+}
+
+impl<'a, 'b, 'ast, Id> Expander<'a, 'b, 'ast, Id>
+where
+    Id: Clone + AsRef<str> + 'ast,
+{
+    fn error(&mut self, span: pos::Span<BytePos>, message: String) {
+        self.errors
+            .push(pos::spanned(span, Error::Message(message)));
+    }
+
+    /// Turns `bind` into a synonym. The name of a synonym without parameters is parsed as a
+    /// constructor pattern so it is changed into an identifier to bind the function.
+    fn define(&mut self, bind: &mut ValueBinding<'ast, Id>) -> Option<Synonym<'ast, Id>> {
+        let name = match &mut bind.name.value {
+            Pattern::Ident(id) => id.name.clone(),
+            Pattern::Constructor(id, args) if args.is_empty() => {
+                let id = id.clone();
+                let name = id.name.clone();
+                bind.name.value = Pattern::Ident(id);
+                name
+            }
+            _ => {
+                self.error(
+                    bind.name.span,
+                    "Pattern synonyms must be named by an identifier".into(),
+                );
+                return None;
+            }
+        };
+        if !name.as_ref().starts_with(char::is_uppercase) {
+            self.error(
+                bind.name.span,
+                format!(
+                    "The pattern synonym `{}` must start with an uppercase letter",
+                    name.as_ref()
+                ),
+            );
+            return None;
+        }
+
+        let params = bind
+            .args
+            .iter()
+            .map(|arg| arg.name.value.name.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let mut used = vec![false; params.len()];
+        let mut pattern = match self.to_pattern(&bind.expr, &params, &mut used) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                self.errors.push(err);
+                return None;
+            }
+        };
+        if let Some(unused) = used.iter().position(|used| !used) {
+            self.error(
+                bind.args[unused].name.span,
+                format!(
+                    "The parameter `{}` is not used in the pattern synonym `{}`",
+                    params[unused],
+                    name.as_ref()
+                ),
+            );
+            return None;
+        }
+        // Synonyms which are already in scope may be used in the definition
+        self.visit_pattern(&mut pattern);
+        Some(Synonym {
+            name: name.as_ref().to_string(),
+            params,
+            pattern,
+        })
+    }
+
+    /// Converts the expression which defines a synonym into the pattern that it stands for
+    fn to_pattern(
+        &self,
+        expr: &SpannedExpr<'ast, Id>,
+        params: &[String],
+        used: &mut [bool],
+    ) -> Result<SpannedPattern<'ast, Id>, Spanned<Error, BytePos>> {
+        let arena = self.arena;
+        let mut param = |span, name: &Id| -> Result<bool, Spanned<Error, BytePos>> {
+            match params.iter().position(|param| param == name.as_ref()) {
+                Some(i) if used[i] => Err(pos::spanned(
+                    span,
+                    Error::Message(format!(
+                        "The parameter `{}` is used more than once in the pattern synonym",
+                        name.as_ref()
+                    )),
+                )),
+                Some(i) => {
+                    used[i] = true;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        };
+        let pattern = match &expr.value {
+            Expr::Ident(id) => {
+                if param(expr.span, &id.name)? {
+                    Pattern::Ident(id.clone())
+                } else if id.name.as_ref().starts_with(char::is_uppercase) {
+                    Pattern::Constructor(id.clone(), arena.alloc_extend(None))
+                } else {
+                    return Err(pos::spanned(
+                        expr.span,
+                        Error::Message(format!(
+                            "`{}` is not a parameter of the pattern synonym",
+                            id.name.as_ref()
+                        )),
+                    ));
+                }
+            }
+            Expr::App {
+                func,
+                implicit_args,
+                args,
+            } if implicit_args.is_empty() => match &func.value {
+                Expr::Ident(id) if id.name.as_ref().starts_with(char::is_uppercase) => {
+                    let args = args
+                        .iter()
+                        .map(|arg| self.to_pattern(arg, params, used))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Pattern::Constructor(id.clone(), arena.alloc_extend(args))
+                }
+                _ => {
+                    return Err(pos::spanned(
+                        func.span,
+                        Error::Message(
+                            "Only constructors may be applied in a pattern synonym".into(),
+                        ),
+                    ))
+                }
+            },
+            Expr::Literal(literal) => Pattern::Literal(literal.clone()),
+            Expr::Tuple { elems, .. } if elems.len() == 1 => {
+                return self.to_pattern(&elems[0], params, used)
+            }
+            Expr::Tuple { elems, .. } => {
+                let elems = elems
+                    .iter()
+                    .map(|elem| self.to_pattern(elem, params, used))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Pattern::Tuple {
+                    typ: self.type_cache.hole(),
+                    elems: arena.alloc_extend(elems),
+                }
+            }
+            Expr::Record {
+                types,
+                exprs,
+                base: None,
+                ..
+            } => {
+                let mut fields = types
+                    .iter()
+                    .map(|field| PatternField::Type {
+                        name: field.name.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                for field in exprs.iter() {
+                    let value = match &field.value {
+                        Some(value) => self.to_pattern(value, params, used)?,
+                        None => {
+                            let ident = pos::spanned(
+                                field.name.span,
+                                Expr::Ident(new_ident(self.type_cache, field.name.value.clone())),
+                            );
+                            self.to_pattern(&ident, params, used)?
+                        }
+                    };
+                    fields.push(PatternField::Value {
+                        name: field.name.clone(),
+                        value: Some(value),
+                    });
+                }
+                Pattern::Record {
+                    typ: self.type_cache.hole(),
+                    fields: arena.alloc_extend(fields),
+                    implicit_import: None,
+                }
+            }
+            _ => {
+                return Err(pos::spanned(
+                    expr.span,
+                    Error::Message(
+                        "Pattern synonyms must be defined by a constructor, literal, tuple, \
+                         record or parameter"
+                            .into(),
+                    ),
+                ))
+            }
+        };
+        Ok(pos::spanned(expr.span, pattern))
+    }
+
+    fn expand(&mut self, pattern: &mut SpannedPattern<'ast, Id>) {
+        let (id, args) = match &mut pattern.value {
+            Pattern::Constructor(id, args) => (id, args),
+            _ => return,
+        };
+        let synonym = match self
+            .synonyms
+            .iter()
+            .rev()
+            .find(|synonym| synonym.name == id.name.as_ref())
+        {
+            Some(synonym) => synonym,
+            None => return,
+        };
+        if synonym.params.len() != args.len() {
+            let message = format!(
+                "The pattern synonym `{}` expects {} arguments but {} were given",
+                synonym.name,
+                synonym.params.len(),
+                args.len()
+            );
+            return self.error(pattern.span, message);
+        }
+
+        let mut args = args
+            .iter_mut()
+            .map(|arg| Some(mem::take(arg)))
+            .collect::<Vec<_>>();
+        let mut expanded = synonym.pattern.ast_clone(self.arena);
+        substitute(&mut expanded, &synonym.params, &mut args);
+        expanded.span = pattern.span;
+        *pattern = expanded;
+    }
+}
+
+fn substitute<'ast, Id>(
+    pattern: &mut SpannedPattern<'ast, Id>,
+    params: &[String],
+    args: &mut [Option<SpannedPattern<'ast, Id>>],
+) where
+    Id: AsRef<str>,
+{
+    match &mut pattern.value {
+        Pattern::Ident(id) => {
+            if let Some(i) = params.iter().position(|param| param == id.name.as_ref()) {
+                if let Some(arg) = args[i].take() {
+                    *pattern = arg;
+                }
+            }
+        }
+        Pattern::As(_, pattern) => substitute(pattern, params, args),
+        Pattern::Constructor(_, patterns)
+        | Pattern::Tuple {
+            elems: patterns, ..
+        } => {
+            for pattern in patterns.iter_mut() {
+                substitute(pattern, params, args);
+            }
+        }
+        Pattern::Record { fields, .. } => {
+            for field in fields.iter_mut() {
+                if let PatternField::Value {
+                    value: Some(pattern),
+                    ..
+                } = field
+                {
+                    substitute(pattern, params, args);
+                }
+            }
+        }
+        Pattern::Literal(_) | Pattern::Error => (),
+    }
+}
+
+impl<'a, 'b, 'c, 'ast, Id> MutVisitor<'c, 'ast> for Expander<'a, 'b, 'ast, Id>
+where
+    Id: Clone + AsRef<str> + 'c + 'ast,
+{
+    type Ident = Id;
+
+    fn visit_expr(&mut self, expr: &mut SpannedExpr<'ast, Id>) {
+        match expr.value {
+            Expr::LetBindings(ref mut bindings, ref mut body) => {
+                let scope = self.synonyms.len();
+                for bind in bindings.iter_mut().filter(|bind| is_synonym(bind)) {
+                    if let Some(synonym) = self.define(bind) {
+                        self.synonyms.push(synonym);
+                    }
+                }
+                for bind in bindings.iter_mut().filter(|bind| !is_synonym(bind)) {
+                    self.visit_pattern(&mut bind.name);
+                    self.visit_expr(&mut bind.expr);
+                }
+                self.visit_expr(body);
+                self.synonyms.truncate(scope);
+            }
+            _ => ast::walk_mut_expr(self, expr),
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &mut SpannedPattern<'ast, Id>) {
+        ast::walk_mut_pattern(self, &mut pattern.value);
+        self.expand(pattern);
+    }
+}
+
+/// Expands the pattern synonyms defined in `expr`
+pub(crate) fn expand<'ast, Id>(
+    arena: ArenaRef<'_, 'ast, Id>,
+    type_cache: &TypeCache<Id, ArcType<Id>>,
+    expr: &mut SpannedExpr<'ast, Id>,
+) -> Result<(), ParseErrors>
+where
+    Id: Clone + AsRef<str> + 'ast,
+{
+    let mut expander = Expander {
+        arena,
+        type_cache,
+        synonyms: Vec::new(),
+        errors: Errors::new(),
+    };
+    expander.visit_expr(expr);
+    if expander.errors.has_errors() {
+        Err(expander.errors)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+//! Desugars view patterns, `(view -> pattern)`, which match `pattern` against the result of
+//! applying the function `view` to the value being matched.
+//!
+//! The grammar replaces each view pattern with a fresh variable and records the view on the side.
+//! An alternative containing view patterns is then matched in two steps, first against the
+//! pattern with the fresh variables and then against the views applied to those variables. The
+//! alternatives after it are bound to a function which is called if the second match fails, so
+//! each alternative appears only once in the desugared expression.
+//!
+//! ```gluon
+//! match x with
+//! | Cons (int.parse -> Some n) _ -> n
+//! | _ -> 0
+//! ```
+//!
+//! becomes
+//!
+//! ```gluon
+//! let match_value = x
+//! let fallthrough _ =
+//!     match match_value with
+//!     | _ -> 0
+//! match match_value with
+//! | Cons view _ ->
+//!     match int.parse view with
+//!     | Some n -> n
+//!     | _ -> fallthrough ()
+//! | _ -> fallthrough ()
+//! ```
+use std::mem;
+
+use crate::base::{
+    ast::{
+        self, Alternative, ArenaRef, Argument, Expr, IdentEnv, MutVisitor, Pattern, PatternField,
+        SpannedExpr, SpannedIdent, SpannedPattern, ValueBinding, ValueBindings,
+    },
+    error::Errors,
+    fnv::FnvMap,
+    metadata::BaseMetadata,
+    pos::{self, Span},
+    types::{ArcType, TypeCache},
+};
+
+use crate::{new_ident, Error, ParseErrors};
+
+#[derive(Debug)]
+pub struct ViewPattern<'ast, Id> {
+    /// The name of the variable which replaced the view pattern
+    pub name: Id,
+    pub view: SpannedExpr<'ast, Id>,
+    pub pattern: SpannedPattern<'ast, Id>,
+}
+
+struct Desugar<'a, 'b, 'ast, Id> {
+    arena: ArenaRef<'b, 'ast, Id>,
+    symbols: &'a mut dyn IdentEnv<Ident = Id>,
+    type_cache: &'a TypeCache<Id, ArcType<Id>>,
+    views: FnvMap<String, ViewPattern<'ast, Id>>,
+}
+
+impl<'a, 'b, 'ast, Id> Desugar<'a, 'b, 'ast, Id>
+where
+    Id: Clone + AsRef<str> + 'ast,
+{
+    fn find_views(&self, pattern: &SpannedPattern<'ast, Id>, found: &mut Vec<Id>) {
+        match &pattern.value {
+            Pattern::Ident(id) => {
+                if self.views.contains_key(id.name.as_ref()) {
+                    found.push(id.name.clone());
+                }
+            }
+            Pattern::As(_, pattern) => self.find_views(pattern, found),
+            Pattern::Constructor(_, patterns)
+            | Pattern::Tuple {
+                elems: patterns, ..
+            } => {
+                for pattern in patterns.iter() {
+                    self.find_views(pattern, found);
+                }
+            }
+            Pattern::Record { fields, .. } => {
+                for field in fields.iter() {
+                    if let PatternField::Value {
+                        value: Some(pattern),
+                        ..
+                    } = field
+                    {
+                        self.find_views(pattern, found);
+                    }
+                }
+            }
+            Pattern::Literal(_) | Pattern::Error => (),
+        }
+    }
+
+    fn ident(&self, span: Span<pos::BytePos>, name: &Id) -> SpannedExpr<'ast, Id> {
+        pos::spanned(span, Expr::Ident(new_ident(self.type_cache, name.clone())))
+    }
+
+    fn wildcard(&mut self, span: Span<pos::BytePos>) -> SpannedPattern<'ast, Id> {
+        let wildcard = self.symbols.from_str("_");
+        pos::spanned(span, Pattern::Ident(new_ident(self.type_cache, wildcard)))
+    }
+
+    fn match_expr(
+        &self,
+        span: Span<pos::BytePos>,
+        scrutinee: SpannedExpr<'ast, Id>,
+        alts: Vec<Alternative<'ast, Id>>,
+    ) -> SpannedExpr<'ast, Id> {
+        pos::spanned(
+            span,
+            Expr::Match(self.arena.alloc(scrutinee), self.arena.alloc_extend(alts)),
+        )
+    }
+
+    fn let_expr(
+        &self,
+        span: Span<pos::BytePos>,
+        name: Id,
+        args: Vec<Argument<SpannedIdent<Id>>>,
+        expr: SpannedExpr<'ast, Id>,
+        body: SpannedExpr<'ast, Id>,
+    ) -> SpannedExpr<'ast, Id> {
+        let bind = ValueBinding {
+            metadata: BaseMetadata::default(),
+            name: pos::spanned(expr.span, Pattern::Ident(new_ident(self.type_cache, name))),
+            typ: None,
+            resolved_type: self.type_cache.hole(),
+            args: self.arena.alloc_extend(args),
+            expr,
+        };
+        pos::spanned(
+            span,
+            Expr::LetBindings(
+                ValueBindings::Plain(self.arena.alloc(bind)),
+                self.arena.alloc(body),
+            ),
+        )
+    }
+
+    /// Returns `fallthrough ()`
+    fn call_fallthrough(
+        &self,
+        span: Span<pos::BytePos>,
+        fallthrough: &Id,
+    ) -> SpannedExpr<'ast, Id> {
+        let unit = pos::spanned(
+            span,
+            Expr::Tuple {
+                typ: self.type_cache.hole(),
+                elems: self.arena.alloc_extend(None),
+            },
+        );
+        pos::spanned(
+            span,
+            Expr::App {
+                func: self.arena.alloc(self.ident(span, fallthrough)),
+                implicit_args: self.arena.alloc_extend(None),
+                args: self.arena.alloc_extend(Some(unit)),
+            },
+        )
+    }
+
+    /// Matches the body of `alt` against the results of `views`, calling `fallthrough` if they do
+    /// not match
+    fn apply_views(
+        &mut self,
+        alt: &mut Alternative<'ast, Id>,
+        views: &[Id],
+        fallthrough: Option<&Id>,
+    ) {
+        let arena = self.arena;
+        let span = alt.expr.span;
+        let mut view_exprs = Vec::with_capacity(views.len());
+        let mut view_patterns = Vec::with_capacity(views.len());
+        for name in views {
+            // Each view is only desugared once, the views left afterwards were not used in a
+            // `match`
+            let view = self
+                .views
+                .remove(name.as_ref())
+                .expect("View pattern is used once");
+            let arg = self.ident(view.view.span, name);
+            view_exprs.push(pos::spanned(
+                view.pattern.span,
+                Expr::App {
+                    func: arena.alloc(view.view),
+                    implicit_args: arena.alloc_extend(None),
+                    args: arena.alloc_extend(Some(arg)),
+                },
+            ));
+            view_patterns.push(view.pattern);
+        }
+        let (view_expr, view_pattern) = if views.len() == 1 {
+            (view_exprs.pop().unwrap(), view_patterns.pop().unwrap())
+        } else {
+            let span = alt.pattern.span;
+            (
+                pos::spanned(
+                    span,
+                    Expr::Tuple {
+                        typ: self.type_cache.hole(),
+                        elems: arena.alloc_extend(view_exprs),
+                    },
+                ),
+                pos::spanned(
+                    span,
+                    Pattern::Tuple {
+                        typ: self.type_cache.hole(),
+                        elems: arena.alloc_extend(view_patterns),
+                    },
+                ),
+            )
+        };
+
+        let mut view_alts = vec![Alternative {
+            pattern: view_pattern,
+            expr: mem::take(&mut alt.expr),
+        }];
+        if let Some(fallthrough) = fallthrough {
+            view_alts.push(Alternative {
+                pattern: self.wildcard(span),
+                expr: self.call_fallthrough(span, fallthrough),
+            });
+        }
+        alt.expr = self.match_expr(span, view_expr, view_alts);
+    }
+
+    /// Rewrites `expr` if it is a `match` with an alternative that contains view patterns
+    fn desugar_match(&mut self, expr: &mut SpannedExpr<'ast, Id>) {
+        let span = expr.span;
+        let (scrutinee, alts) = match &mut expr.value {
+            Expr::Match(scrutinee, alts) => (scrutinee, alts),
+            _ => return,
+        };
+
+        let alt_views = alts
+            .iter()
+            .map(|alt| {
+                let mut views = Vec::new();
+                self.find_views(&alt.pattern, &mut views);
+                views
+            })
+            .collect::<Vec<_>>();
+        if alt_views.iter().all(|views| views.is_empty()) {
+            return;
+        }
+
+        // Bind the matched value so that it can be matched again if a view does not match
+        let scrutinee_span = scrutinee.span;
+        let value = self
+            .symbols
+            .from_str(&format!("match?{}", scrutinee_span.start()));
+        let scrutinee = mem::take(&mut **scrutinee);
+        let mut alts = mem::take(alts)
+            .iter_mut()
+            .map(|alt| Alternative {
+                pattern: mem::take(&mut alt.pattern),
+                expr: mem::take(&mut alt.expr),
+            })
+            .collect::<Vec<_>>();
+
+        // Split the alternatives after each alternative with views, starting from the last ones.
+        // Every group except the first is bound to a function which the alternative before it
+        // calls if its views do not match.
+        let mut fallthrough = None;
+        let mut bindings = Vec::new();
+        let body = loop {
+            let end = alts.len();
+            let start = alt_views[..end - 1]
+                .iter()
+                .rposition(|views| !views.is_empty())
+                .map_or(0, |i| i + 1);
+            let mut group = alts.drain(start..).collect::<Vec<_>>();
+            let group_span = group[0].pattern.span;
+
+            let views = &alt_views[end - 1];
+            if !views.is_empty() {
+                self.apply_views(group.last_mut().unwrap(), views, fallthrough.as_ref());
+                if let Some(fallthrough) = &fallthrough {
+                    group.push(Alternative {
+                        pattern: self.wildcard(group_span),
+                        expr: self.call_fallthrough(group_span, fallthrough),
+                    });
+                }
+            }
+
+            let group_match = self.match_expr(span, self.ident(scrutinee_span, &value), group);
+            if start == 0 {
+                break group_match;
+            }
+            let name = self
+                .symbols
+                .from_str(&format!("fallthrough?{}", group_span.start()));
+            bindings.push((name.clone(), group_match));
+            fallthrough = Some(name);
+        };
+
+        let body = bindings
+            .into_iter()
+            .rev()
+            .fold(body, |body, (name, group_match)| {
+                let span = group_match.span;
+                let arg = Argument::explicit(pos::spanned(
+                    span,
+                    new_ident(self.type_cache, self.symbols.from_str("_")),
+                ));
+                self.let_expr(span, name, vec![arg], group_match, body)
+            });
+        *expr = self.let_expr(span, value, Vec::new(), scrutinee, body);
+    }
+}
+
+impl<'a, 'b, 'c, 'ast, Id> MutVisitor<'c, 'ast> for Desugar<'a, 'b, 'ast, Id>
+where
+    Id: Clone + AsRef<str> + 'c + 'ast,
+{
+    type Ident = Id;
+
+    fn visit_expr(&mut self, expr: &mut SpannedExpr<'ast, Id>) {
+        self.desugar_match(expr);
+        ast::walk_mut_expr(self, expr);
+    }
+}
+
+/// Desugars the view patterns in `expr`. Each of `views` must be used in the alternatives of a
+/// `match`.
+pub(crate) fn desugar<'ast, Id>(
+    arena: ArenaRef<'_, 'ast, Id>,
+    symbols: &mut dyn IdentEnv<Ident = Id>,
+    type_cache: &TypeCache<Id, ArcType<Id>>,
+    views: Vec<ViewPattern<'ast, Id>>,
+    expr: &mut SpannedExpr<'ast, Id>,
+) -> Result<(), ParseErrors>
+where
+    Id: Clone + AsRef<str> + 'ast,
+{
+    if views.is_empty() {
+        return Ok(());
+    }
+    let mut desugar = Desugar {
+        arena,
+        symbols,
+        type_cache,
+        views: views
+            .into_iter()
+            .map(|view| (view.name.as_ref().to_string(), view))
+            .collect(),
+    };
+    desugar.visit_expr(expr);
+
+    let mut unused = desugar
+        .views
+        .values()
+        .map(|view| view.view.span)
+        .collect::<Vec<_>>();
+    unused.sort_by_key(|span| span.start());
+
+    let mut errors = Errors::new();
+    for span in unused {
+        errors.push(pos::spanned(
+            span,
+            Error::Message("View patterns may only be used in `match` alternatives".into()),
+        ));
+    }
+    if errors.has_errors() {
+        Err(errors)
+    } else {
+        Ok(())
+    }
+}
@@ -329,3 +329,11 @@ do
         )
     );
 }
+
+#[test]
+fn view_patterns_are_only_allowed_in_match() {
+    let _ = env_logger::try_init();
+
+    assert!(parse(r#"match x with | (f -> Some y) -> y"#).is_ok());
+    assert!(parse(r#"let (f -> Some y) = x in y"#).is_err());
+}
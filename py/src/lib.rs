@@ -0,0 +1,244 @@
+//! Python bindings for gluon, built with `maturin` into the `gluon` Python module. The bindings
+//! are built on the C API of `gluon_c-api`, run `maturin build` in the `py` directory to build
+//! them.
+//!
+//! ```python
+//! import gluon
+//!
+//! vm = gluon.Vm()
+//! vm.load_script("shapes", "let area w h : Float -> Float -> Float = w * h in { area }")
+//! assert vm.call("shapes.area", 2.0, 3.5) == 7.0
+//! assert vm.run_expr("[1, 2] ++ [3]") == [1, 2, 3]
+//! ```
+//!
+//! Values are converted according to their gluon type, as described by
+//! `gluon::vm::api::json::to_json`. `Int`, `Float`, `String` and `Bool` become the Python
+//! builtins of the same name, arrays become lists, records become dictionaries and `Option`
+//! becomes the value or `None`. Errors from gluon are raised as `gluon.GluonError`.
+#![doc(html_root_url = "https://docs.rs/gluon_py/0.17.1")] // # GLUON
+
+use std::{ptr, slice};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTypeError},
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple},
+};
+
+use serde_json::Value;
+
+use gluon_c_api::{
+    glu_builder_build, glu_builder_new, glu_builder_set_run_io, glu_call_json, glu_free_vm,
+    glu_last_error, glu_load_script, glu_run_expr_json, Error, Thread,
+};
+
+create_exception!(gluon, GluonError, PyException);
+
+fn gluon_error(err: impl ToString) -> PyErr {
+    GluonError::new_err(err.to_string())
+}
+
+/// A vm created through the C API which is freed when dropped
+struct VmHandle(*const Thread);
+
+// The functions of the C API may be called from any thread
+unsafe impl Send for VmHandle {}
+unsafe impl Sync for VmHandle {}
+
+impl Drop for VmHandle {
+    fn drop(&mut self) {
+        unsafe { glu_free_vm(&*self.0) }
+    }
+}
+
+impl VmHandle {
+    fn new(run_io: bool) -> Self {
+        unsafe {
+            let builder = glu_builder_new();
+            glu_builder_set_run_io(&mut *builder, run_io as i8);
+            VmHandle(glu_builder_build(builder))
+        }
+    }
+
+    fn run_expr(&self, name: &str, expr: &str) -> Result<Value, String> {
+        let (mut json, mut json_len) = (ptr::null(), 0);
+        unsafe {
+            check(glu_run_expr_json(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(expr),
+                expr.len(),
+                &mut json,
+                &mut json_len,
+            ))?;
+            parse_json(json, json_len)
+        }
+    }
+
+    fn load_script(&self, name: &str, source: &str) -> Result<(), String> {
+        unsafe {
+            check(glu_load_script(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(source),
+                source.len(),
+            ))
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let args = serde_json::to_string(args).map_err(|err| err.to_string())?;
+        let (mut json, mut json_len) = (ptr::null(), 0);
+        unsafe {
+            check(glu_call_json(
+                &*self.0,
+                str_ptr(name),
+                name.len(),
+                str_ptr(&args),
+                args.len(),
+                &mut json,
+                &mut json_len,
+            ))?;
+            parse_json(json, json_len)
+        }
+    }
+}
+
+/// The C API takes strings as a reference to their first byte, which empty strings do not have
+fn str_ptr(s: &str) -> &u8 {
+    s.as_bytes().first().unwrap_or(&0)
+}
+
+fn check(err: Error) -> Result<(), String> {
+    if err == Error::Ok {
+        return Ok(());
+    }
+    let (mut message, mut len) = (ptr::null(), 0);
+    unsafe {
+        glu_last_error(&mut message, &mut len);
+        Err(String::from_utf8_lossy(slice::from_raw_parts(message, len)).into_owned())
+    }
+}
+
+/// Parses the JSON returned by the C API, which is only valid until the next call on this thread
+unsafe fn parse_json(json: *const u8, len: usize) -> Result<Value, String> {
+    serde_json::from_slice(slice::from_raw_parts(json, len)).map_err(|err| err.to_string())
+}
+
+/// A gluon virtual machine with the standard library
+#[pyclass(module = "gluon")]
+pub struct Vm {
+    vm: VmHandle,
+}
+
+#[pymethods]
+impl Vm {
+    /// Creates a vm. If `run_io` is true, `IO` actions which scripts and expressions evaluate to
+    /// are run.
+    #[new]
+    #[pyo3(signature = (run_io = false))]
+    fn new(run_io: bool) -> Self {
+        Vm {
+            vm: VmHandle::new(run_io),
+        }
+    }
+
+    /// Compiles and runs `expr`, returning its value
+    #[pyo3(signature = (expr, name = "python"))]
+    fn run_expr(&self, py: Python<'_>, expr: &str, name: &str) -> PyResult<PyObject> {
+        let value = py.allow_threads(|| self.vm.run_expr(name, expr));
+        to_py(py, &value.map_err(gluon_error)?)
+    }
+
+    /// Compiles and runs `source` as the module `name`, which can then be imported with
+    /// `import! name` and its fields called with `call`
+    fn load_script(&self, py: Python<'_>, name: &str, source: &str) -> PyResult<()> {
+        py.allow_threads(|| self.vm.load_script(name, source))
+            .map_err(gluon_error)
+    }
+
+    /// Calls the global function `name`, such as `std.string.len` or a field of a module loaded
+    /// with `load_script`. The arguments are converted to the types in the function's signature.
+    #[pyo3(signature = (name, *args))]
+    fn call(&self, py: Python<'_>, name: &str, args: &Bound<'_, PyTuple>) -> PyResult<PyObject> {
+        let args = args
+            .iter()
+            .map(|arg| from_py(&arg))
+            .collect::<PyResult<Vec<_>>>()?;
+        let value = py.allow_threads(|| self.vm.call(name, &args));
+        to_py(py, &value.map_err(gluon_error)?)
+    }
+}
+
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_py(py),
+            None => n.as_f64().unwrap_or(f64::NAN).into_py(py),
+        },
+        Value::String(s) => s.into_py(py),
+        Value::Array(elems) => PyList::new_bound(
+            py,
+            elems
+                .iter()
+                .map(|elem| to_py(py, elem))
+                .collect::<PyResult<Vec<_>>>()?,
+        )
+        .into_py(py),
+        Value::Object(fields) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in fields {
+                dict.set_item(key, to_py(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+fn from_py(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    // `bool` is a subclass of `int` so it must be checked first
+    Ok(if value.is_none() {
+        Value::Null
+    } else if value.is_instance_of::<PyBool>() {
+        Value::Bool(value.extract()?)
+    } else if value.is_instance_of::<PyLong>() {
+        Value::Number(value.extract::<i64>()?.into())
+    } else if value.is_instance_of::<PyFloat>() {
+        serde_json::Number::from_f64(value.extract()?)
+            .map(Value::Number)
+            .ok_or_else(|| PyTypeError::new_err("NaN and infinity can't be passed to gluon"))?
+    } else if value.is_instance_of::<PyString>() {
+        Value::String(value.extract()?)
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        Value::Object(
+            dict.iter()
+                .map(|(key, value)| Ok((key.extract()?, from_py(&value)?)))
+                .collect::<PyResult<_>>()?,
+        )
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        Value::Array(
+            value
+                .iter()?
+                .map(|elem| from_py(&elem?))
+                .collect::<PyResult<_>>()?,
+        )
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Values of type `{}` can't be passed to gluon",
+            value.get_type().name()?
+        )));
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "gluon")]
+fn gluon_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Vm>()?;
+    m.add("GluonError", m.py().get_type_bound::<GluonError>())?;
+    Ok(())
+}
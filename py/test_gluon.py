@@ -0,0 +1,44 @@
+# Run with `maturin develop && python test_gluon.py`
+import unittest
+
+import gluon
+
+
+class TestGluon(unittest.TestCase):
+    def setUp(self):
+        self.vm = gluon.Vm()
+
+    def test_run_expr(self):
+        self.assertEqual(self.vm.run_expr("1 + 2"), 3)
+        self.assertEqual(self.vm.run_expr("[1.5, 2.0]"), [1.5, 2.0])
+        self.assertEqual(
+            self.vm.run_expr('{ name = "gluon", tags = ["a"], version = Some 17, ok = True }'),
+            {"name": "gluon", "tags": ["a"], "version": 17, "ok": True},
+        )
+        with self.assertRaises(gluon.GluonError):
+            self.vm.run_expr("1 + \"\"")
+
+    def test_call(self):
+        self.vm.load_script(
+            "shapes",
+            """
+            type Shape = | Circle Float | Rect Float Float
+            let area shape : Shape -> Float =
+                match shape with
+                | Circle r -> 3.0 * r * r
+                | Rect w h -> w * h
+            let sum xs : Array Int -> Int = (import! std.array).foldable.foldl (+) 0 xs
+            { Shape, area, sum }
+            """,
+        )
+        self.assertEqual(self.vm.call("shapes.area", {"Rect": [2, 3.5]}), 7.0)
+        self.assertEqual(self.vm.call("shapes.sum", (1, 2, 3)), 6)
+        self.assertEqual(self.vm.call("std.string.len", "abc"), 3)
+        with self.assertRaises(gluon.GluonError):
+            self.vm.call("shapes.area", "Square")
+        with self.assertRaises(TypeError):
+            self.vm.call("shapes.sum", object())
+
+
+if __name__ == "__main__":
+    unittest.main()
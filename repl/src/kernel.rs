@@ -0,0 +1,889 @@
+//! Implementation of `gluon kernel`, a Jupyter kernel which evaluates the cells of a notebook the
+//! same way as the REPL evaluates its input.
+//!
+//! Jupyter starts the kernel with a connection file which lists the ports of the five channels of
+//! the [messaging protocol](https://jupyter-client.readthedocs.io/en/stable/messaging.html). Each
+//! channel is served by its own threads, requests on the shell channel are evaluated one at a time
+//! by `Kernel` while the control channel is served directly so that evaluation can be interrupted.
+
+use std::{
+    fs,
+    net::TcpListener,
+    path::PathBuf,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc, Mutex,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use futures::{channel::mpsc, prelude::*};
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+use structopt::StructOpt;
+
+use codespan_reporting::term::termcolor;
+
+use gluon::{
+    base::{
+        resolve::remove_aliases_cow,
+        types::{ArcType, NullInterner, Type, TypeEnv, TypeExt},
+        DebugLevel,
+    },
+    vm::{api::ValueRef, internal::ValuePrinter, Variants},
+    Error as GluonError, RootedThread, ThreadExt,
+};
+
+use crate::{
+    repl::{eval_repl_line, split_bindings, EvaluatedLine},
+    zmtp::{Connection, SocketType},
+};
+
+const PROTOCOL_VERSION: &str = "5.3";
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+#[derive(StructOpt)]
+#[structopt(about = "Runs gluon as a Jupyter kernel")]
+pub struct KernelOpt {
+    #[structopt(
+        long = "install",
+        help = "Installs the kernel specification so that Jupyter can start the kernel"
+    )]
+    install: bool,
+
+    #[structopt(
+        name = "CONNECTION_FILE",
+        parse(from_os_str),
+        required_unless = "install",
+        help = "The connection file which Jupyter starts the kernel with"
+    )]
+    connection_file: Option<PathBuf>,
+}
+
+/// The contents of the connection file
+#[derive(Deserialize)]
+struct ConnectionInfo {
+    ip: String,
+    transport: String,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+    key: String,
+    signature_scheme: String,
+}
+
+/// A message of the Jupyter messaging protocol
+#[derive(Debug)]
+struct Message {
+    /// The ZeroMQ routing identities of the message, replies are sent back with the same ones
+    identities: Vec<Vec<u8>>,
+    header: Json,
+    parent_header: Json,
+    metadata: Json,
+    content: Json,
+}
+
+impl Message {
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or("")
+    }
+}
+
+/// The parts of the kernel which are shared by the threads serving each channel
+struct Session {
+    id: String,
+    /// The key messages are signed with, messages are not signed if it is empty
+    key: Vec<u8>,
+    message_count: AtomicUsize,
+    /// Every subscriber of the iopub channel, subscriptions are ignored and every message is sent
+    /// to every subscriber
+    iopub: Mutex<Vec<Connection>>,
+}
+
+impl Session {
+    fn new(key: &str) -> Session {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Session {
+            id: format!("{:x}-{:x}", nanos, std::process::id()),
+            key: key.as_bytes().to_owned(),
+            message_count: AtomicUsize::new(0),
+            iopub: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the HMAC of `parts` or `None` if messages are not signed
+    fn mac(&self, parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+        if self.key.is_empty() {
+            return None;
+        }
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).expect("HMAC accepts any key");
+        for part in parts {
+            mac.update(part);
+        }
+        Some(mac)
+    }
+
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        match self.mac(parts) {
+            Some(mac) => mac
+                .finalize()
+                .into_bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            None => String::new(),
+        }
+    }
+
+    fn decode(&self, mut frames: Vec<Vec<u8>>) -> Result<Message, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or_else(|| "Message is missing the `<IDS|MSG>` delimiter".to_string())?;
+        if frames.len() < delimiter + 6 {
+            return Err("Message does not contain every part".to_string());
+        }
+        let parts = frames.split_off(delimiter);
+        let identities = frames;
+
+        if let Some(mac) = self.mac(&[&parts[2], &parts[3], &parts[4], &parts[5]]) {
+            // `verify` compares the signatures in constant time
+            let valid =
+                decode_hex(&parts[1]).map_or(false, |signature| mac.verify(&signature).is_ok());
+            if !valid {
+                return Err("Message has an invalid signature".to_string());
+            }
+        }
+
+        let parse = |part: &[u8]| {
+            serde_json::from_slice(part).map_err(|err| format!("Invalid message: {}", err))
+        };
+        Ok(Message {
+            identities,
+            header: parse(&parts[2])?,
+            parent_header: parse(&parts[3])?,
+            metadata: parse(&parts[4])?,
+            content: parse(&parts[5])?,
+        })
+    }
+
+    fn encode(&self, message: &Message) -> Vec<Vec<u8>> {
+        let parts: Vec<Vec<u8>> = [
+            &message.header,
+            &message.parent_header,
+            &message.metadata,
+            &message.content,
+        ]
+        .iter()
+        .map(|part| part.to_string().into_bytes())
+        .collect();
+        let signature = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+
+        let mut frames = message.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        frames
+    }
+
+    /// Creates a message of `msg_type` caused by `parent`
+    fn message(
+        &self,
+        identities: Vec<Vec<u8>>,
+        parent: &Message,
+        msg_type: &str,
+        content: Json,
+    ) -> Message {
+        let count = self.message_count.fetch_add(1, atomic::Ordering::Relaxed);
+        Message {
+            identities,
+            header: json!({
+                "msg_id": format!("{}-{}", self.id, count),
+                "session": self.id,
+                "username": "kernel",
+                "date": timestamp(SystemTime::now()),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: parent.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    fn reply(
+        &self,
+        connection: &Mutex<Connection>,
+        parent: &Message,
+        msg_type: &str,
+        content: Json,
+    ) {
+        let message = self.message(parent.identities.clone(), parent, msg_type, content);
+        if let Err(err) = connection.lock().unwrap().send(self.encode(&message)) {
+            warn!("Unable to send `{}`: {}", msg_type, err);
+        }
+    }
+
+    fn publish(&self, parent: &Message, msg_type: &str, content: Json) {
+        let message = self.message(
+            vec![msg_type.as_bytes().to_vec()],
+            parent,
+            msg_type,
+            content,
+        );
+        let frames = self.encode(&message);
+        let mut iopub = self.iopub.lock().unwrap();
+        // Subscribers which have disconnected are dropped
+        *iopub = iopub
+            .drain(..)
+            .filter_map(|mut subscriber| subscriber.send(&frames).ok().map(|()| subscriber))
+            .collect();
+    }
+
+    fn publish_status(&self, parent: &Message, status: &str) {
+        self.publish(parent, "status", json!({ "execution_state": status }))
+    }
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Formats `time` as an ISO 8601 timestamp in UTC
+fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        elapsed.subsec_micros()
+    )
+}
+
+/// Converts the number of days since 1970-01-01 into a year, month and day
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+/// An error which was raised while evaluating a cell
+#[derive(Debug, PartialEq)]
+struct CellError {
+    ename: String,
+    evalue: String,
+    traceback: Vec<String>,
+}
+
+impl CellError {
+    fn new(err: GluonError) -> CellError {
+        let ename = match err {
+            GluonError::Parse(_) => "ParseError",
+            GluonError::Typecheck(_) => "TypeError",
+            GluonError::Macro(_) => "MacroError",
+            GluonError::VM(_) => "RuntimeError",
+            GluonError::IO(_) => "IOError",
+            _ => "Error",
+        };
+        // Jupyter renders ANSI colors in tracebacks
+        let mut output = termcolor::Ansi::new(Vec::new());
+        let traceback = match err.emit(&mut output) {
+            Ok(()) => String::from_utf8_lossy(&output.into_inner()).into_owned(),
+            Err(_) => err.to_string(),
+        };
+        CellError {
+            ename: ename.to_string(),
+            evalue: err.to_string().lines().next().unwrap_or("").to_string(),
+            traceback: traceback
+                .trim_end()
+                .lines()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    fn message(msg: String) -> CellError {
+        CellError {
+            ename: "Error".to_string(),
+            evalue: msg.clone(),
+            traceback: vec![msg],
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        json!({
+            "ename": self.ename,
+            "evalue": self.evalue,
+            "traceback": self.traceback,
+        })
+    }
+}
+
+/// Evaluates the cells sent on the shell channel
+struct Kernel {
+    vm: RootedThread,
+    session: Arc<Session>,
+    execution_count: usize,
+    /// The thread which is evaluating a cell, so that an `interrupt_request` can interrupt it
+    running: Arc<Mutex<Option<RootedThread>>>,
+}
+
+impl Kernel {
+    async fn handle(&mut self, request: Message, connection: &Mutex<Connection>) {
+        let session = self.session.clone();
+        session.publish_status(&request, "busy");
+        match request.msg_type() {
+            "kernel_info_request" => {
+                session.reply(connection, &request, "kernel_info_reply", kernel_info())
+            }
+            "execute_request" => self.execute(&request, connection).await,
+            "is_complete_request" => session.reply(
+                connection,
+                &request,
+                "is_complete_reply",
+                json!({ "status": "unknown" }),
+            ),
+            "shutdown_request" => shutdown(&session, &request, connection),
+            msg_type => warn!("Ignoring unsupported message `{}`", msg_type),
+        }
+        session.publish_status(&request, "idle");
+    }
+
+    async fn execute(&mut self, request: &Message, connection: &Mutex<Connection>) {
+        let code = request.content["code"].as_str().unwrap_or("");
+        let silent = request.content["silent"].as_bool().unwrap_or(false);
+        if !silent && request.content["store_history"].as_bool().unwrap_or(true) {
+            self.execution_count += 1;
+        }
+        let execution_count = self.execution_count;
+        let session = self.session.clone();
+
+        if !silent {
+            session.publish(
+                request,
+                "execute_input",
+                json!({ "code": code, "execution_count": execution_count }),
+            );
+        }
+        let content = match self.evaluate(code).await {
+            Ok(data) => {
+                if let (Some(data), false) = (data, silent) {
+                    session.publish(
+                        request,
+                        "execute_result",
+                        json!({
+                            "execution_count": execution_count,
+                            "data": data,
+                            "metadata": {},
+                        }),
+                    );
+                }
+                json!({
+                    "status": "ok",
+                    "execution_count": execution_count,
+                    "user_expressions": {},
+                    "payload": [],
+                })
+            }
+            Err(err) => {
+                session.publish(request, "error", err.to_json());
+                let mut content = err.to_json();
+                content["status"] = json!("error");
+                content["execution_count"] = json!(execution_count);
+                content
+            }
+        };
+        session.reply(connection, request, "execute_reply", content);
+    }
+
+    /// Evaluates each binding of `code`, returning the display data of the last line if it is an
+    /// expression
+    async fn evaluate(&mut self, code: &str) -> Result<Option<Json>, CellError> {
+        let mut last = None;
+        for item in split_bindings(code, false) {
+            let (line, binding) = item.map_err(|(_, msg)| CellError::message(msg))?;
+            // Pad with the preceding lines so that errors point into the cell
+            let padded = format!("{}{}", "\n".repeat(line), binding);
+
+            let thread = self
+                .vm
+                .new_thread()
+                .map_err(|err| CellError::new(err.into()))?;
+            *self.running.lock().unwrap() = Some(thread.clone());
+            let result = eval_repl_line(thread, "cell", &padded).await;
+            *self.running.lock().unwrap() = None;
+
+            last = result.map_err(CellError::new)?;
+        }
+        Ok(last
+            .filter(|evaluated| !evaluated.is_binding)
+            .map(|evaluated| display_data(&evaluated)))
+    }
+}
+
+fn kernel_info() -> Json {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "gluon",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "gluon",
+            "version": env!("CARGO_PKG_VERSION"),
+            "mimetype": "text/x-gluon",
+            "file_extension": ".glu",
+        },
+        "banner": "gluon",
+        "help_links": [{ "text": "The gluon book", "url": "https://gluon-lang.org/doc/nightly/book/" }],
+    })
+}
+
+fn shutdown(session: &Session, request: &Message, connection: &Mutex<Connection>) {
+    let restart = request.content["restart"].as_bool().unwrap_or(false);
+    session.reply(
+        connection,
+        request,
+        "shutdown_reply",
+        json!({ "status": "ok", "restart": restart }),
+    );
+    std::process::exit(0);
+}
+
+/// The display data of the value of a cell. Records and arrays are rendered as HTML tables in
+/// addition to the plain text which the REPL prints.
+fn display_data(evaluated: &EvaluatedLine) -> Json {
+    let EvaluatedLine { value, typ, .. } = evaluated;
+    let vm = value.vm();
+    let env = vm.get_env();
+    let debug_level = vm.global_env().get_debug_level();
+
+    let mut data = json!({
+        "text/plain": ValuePrinter::new(&env, typ, value.get_variant(), &debug_level)
+            .width(80)
+            .max_level(5)
+            .to_string(),
+    });
+    if let Some(html) = html_table(&env, &debug_level, typ, value.get_variant()) {
+        data["text/html"] = json!(html);
+    }
+    data
+}
+
+fn html_table(
+    env: &dyn TypeEnv<Type = ArcType>,
+    debug_level: &DebugLevel,
+    typ: &ArcType,
+    value: Variants,
+) -> Option<String> {
+    let cell = |typ: &ArcType, value: Variants| {
+        let text = ValuePrinter::new(env, typ, value, debug_level)
+            .width(80)
+            .max_level(3)
+            .to_string();
+        format!("<td>{}</td>", escape_html(&text))
+    };
+
+    let typ = remove_aliases_cow(env, &mut NullInterner, typ);
+    match (value.as_ref(), &**typ) {
+        (ValueRef::Data(data), Type::Record(row)) => {
+            let rows: String = data
+                .iter()
+                .zip(row.row_iter())
+                .map(|(value, field)| {
+                    format!(
+                        "<tr><th>{}</th>{}</tr>",
+                        escape_html(field.name.declared_name()),
+                        cell(&field.typ, value)
+                    )
+                })
+                .collect();
+            if rows.is_empty() {
+                None
+            } else {
+                Some(format!("<table>{}</table>", rows))
+            }
+        }
+        (ValueRef::Array(array), Type::App(_, args)) if args.len() == 1 => {
+            let element_type = remove_aliases_cow(env, &mut NullInterner, &args[0]);
+            match &**element_type {
+                // Arrays of records get a column for each field
+                Type::Record(row) if row.row_iter().next().is_some() => {
+                    let header: String = row
+                        .row_iter()
+                        .map(|field| {
+                            format!("<th>{}</th>", escape_html(field.name.declared_name()))
+                        })
+                        .collect();
+                    let rows: String = array
+                        .iter()
+                        .map(|element| match element.as_ref() {
+                            ValueRef::Data(data) => format!(
+                                "<tr>{}</tr>",
+                                data.iter()
+                                    .zip(row.row_iter())
+                                    .map(|(value, field)| cell(&field.typ, value))
+                                    .collect::<String>()
+                            ),
+                            _ => String::new(),
+                        })
+                        .collect();
+                    Some(format!("<table><tr>{}</tr>{}</table>", header, rows))
+                }
+                _ => {
+                    let rows: String = array
+                        .iter()
+                        .enumerate()
+                        .map(|(i, element)| {
+                            format!("<tr><th>{}</th>{}</tr>", i, cell(&args[0], element))
+                        })
+                        .collect();
+                    Some(format!("<table>{}</table>", rows))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Accepts connections on `port` in a background thread, calling `f` with each connection once
+/// its handshake has completed
+fn serve<F>(ip: &str, port: u16, socket_type: SocketType, f: F) -> anyhow::Result<()>
+where
+    F: Fn(Connection) + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind((ip, port))
+        .with_context(|| format!("Unable to listen on {}:{}", ip, port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let f = f.clone();
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Unable to accept connection: {}", err);
+                    continue;
+                }
+            };
+            thread::spawn(
+                move || match Connection::handshake(stream, socket_type, true) {
+                    Ok(connection) => f(connection),
+                    Err(err) => warn!("ZMTP handshake failed: {}", err),
+                },
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Calls `f` with each message received on `connection` and the connection to reply on
+fn receive_messages(
+    session: &Session,
+    connection: Connection,
+    mut f: impl FnMut(Message, Arc<Mutex<Connection>>),
+) {
+    let replies = match connection.try_clone() {
+        Ok(replies) => Arc::new(Mutex::new(replies)),
+        Err(err) => {
+            warn!("Unable to reply on the connection: {}", err);
+            return;
+        }
+    };
+    let mut connection = connection;
+    while let Ok(frames) = connection.recv() {
+        match session.decode(frames) {
+            Ok(message) => f(message, replies.clone()),
+            Err(err) => warn!("{}", err),
+        }
+    }
+}
+
+/// Returns the directory where Jupyter looks for kernel specifications
+fn jupyter_data_dir() -> anyhow::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("JUPYTER_DATA_DIR") {
+        return Ok(dir.into());
+    }
+    let var = |name| {
+        std::env::var_os(name)
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("`{}` is not set", name))
+    };
+    Ok(if cfg!(windows) {
+        var("APPDATA")?.join("jupyter")
+    } else if cfg!(target_os = "macos") {
+        var("HOME")?.join("Library").join("Jupyter")
+    } else {
+        match var("XDG_DATA_HOME") {
+            Ok(dir) => dir.join("jupyter"),
+            Err(_) => var("HOME")?.join(".local").join("share").join("jupyter"),
+        }
+    })
+}
+
+fn install() -> anyhow::Result<()> {
+    let dir = jupyter_data_dir()?.join("kernels").join("gluon");
+    fs::create_dir_all(&dir)?;
+    let spec = json!({
+        "argv": [std::env::current_exe()?, "kernel", "{connection_file}"],
+        "display_name": "Gluon",
+        "language": "gluon",
+        "interrupt_mode": "message",
+    });
+    fs::write(
+        dir.join("kernel.json"),
+        serde_json::to_string_pretty(&spec)?,
+    )?;
+    println!("Installed the gluon kernel in {}", dir.display());
+    Ok(())
+}
+
+pub async fn run(opt: &KernelOpt, use_std_lib: bool) -> anyhow::Result<()> {
+    if opt.install {
+        return install();
+    }
+    let connection_file = opt
+        .connection_file
+        .as_ref()
+        .expect("structopt requires a connection file");
+    let info: ConnectionInfo = serde_json::from_str(
+        &fs::read_to_string(connection_file)
+            .with_context(|| format!("Unable to read `{}`", connection_file.display()))?,
+    )?;
+    if info.transport != "tcp" {
+        return Err(anyhow!("Unsupported transport `{}`", info.transport));
+    }
+    if info.signature_scheme != "hmac-sha256" && !info.key.is_empty() {
+        return Err(anyhow!(
+            "Unsupported signature scheme `{}`",
+            info.signature_scheme
+        ));
+    }
+
+    let vm = gluon::VmBuilder::new().build_async().await;
+    vm.get_database_mut()
+        .use_standard_lib(use_std_lib)
+        .run_io(true);
+
+    let session = Arc::new(Session::new(&info.key));
+    let running = Arc::new(Mutex::new(None::<RootedThread>));
+    let (sender, mut receiver) = mpsc::unbounded();
+
+    serve(&info.ip, info.hb_port, SocketType::Rep, |mut connection| {
+        while let Ok(frames) = connection.recv() {
+            if connection.send(frames).is_err() {
+                break;
+            }
+        }
+    })?;
+    {
+        let session = session.clone();
+        serve(
+            &info.ip,
+            info.iopub_port,
+            SocketType::Pub,
+            move |connection| session.iopub.lock().unwrap().push(connection),
+        )?;
+    }
+    // Reading from stdin is not supported so the connections are only accepted
+    serve(&info.ip, info.stdin_port, SocketType::Router, |_| ())?;
+    {
+        let session = session.clone();
+        serve(
+            &info.ip,
+            info.shell_port,
+            SocketType::Router,
+            move |connection| {
+                let sender = sender.clone();
+                receive_messages(&session, connection, |message, replies| {
+                    let _ = sender.unbounded_send((message, replies));
+                })
+            },
+        )?;
+    }
+    {
+        let session = session.clone();
+        let running = running.clone();
+        serve(
+            &info.ip,
+            info.control_port,
+            SocketType::Router,
+            move |connection| {
+                receive_messages(&session, connection, |request, replies| {
+                    match request.msg_type() {
+                        "interrupt_request" => {
+                            if let Some(thread) = &*running.lock().unwrap() {
+                                thread.interrupt();
+                            }
+                            session.reply(&replies, &request, "interrupt_reply", json!({}));
+                        }
+                        "shutdown_request" => shutdown(&session, &request, &replies),
+                        "kernel_info_request" => {
+                            session.reply(&replies, &request, "kernel_info_reply", kernel_info())
+                        }
+                        msg_type => warn!("Ignoring unsupported control message `{}`", msg_type),
+                    }
+                })
+            },
+        )?;
+    }
+
+    let mut kernel = Kernel {
+        vm,
+        session,
+        execution_count: 0,
+        running,
+    };
+    while let Some((request, replies)) = receiver.next().await {
+        kernel.handle(request, &replies).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use gluon::import::Import;
+
+    async fn new_kernel() -> Kernel {
+        if std::env::var("GLUON_PATH").is_err() {
+            std::env::set_var("GLUON_PATH", "..");
+        }
+        let vm = gluon::new_vm_async().await;
+        let import = vm.get_macros().get("import");
+        import
+            .as_ref()
+            .and_then(|import| import.downcast_ref::<Import>())
+            .expect("Import macro")
+            .add_path("..");
+        Kernel {
+            vm,
+            session: Arc::new(Session::new("key")),
+            execution_count: 0,
+            running: Default::default(),
+        }
+    }
+
+    #[test]
+    fn hmac_sha256_test_vector() {
+        // Test case 2 of RFC 4231
+        let session = Session::new("Jefe");
+        assert_eq!(
+            session.sign(&[b"what do ya want ", b"for nothing?"]),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn format_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_micros(1_704_164_645_000_123);
+        assert_eq!(timestamp(time), "2024-01-02T03:04:05.000123Z");
+    }
+
+    #[test]
+    fn encode_and_decode_signed_messages() {
+        let session = Session::new("secret");
+        let request = Message {
+            identities: vec![b"client".to_vec()],
+            header: json!({ "msg_type": "kernel_info_request" }),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content: json!({}),
+        };
+        let reply = session.message(
+            request.identities.clone(),
+            &request,
+            "kernel_info_reply",
+            json!({ "status": "ok" }),
+        );
+        let mut frames = session.encode(&reply);
+        let decoded = session.decode(frames.clone()).unwrap();
+        assert_eq!(decoded.identities, vec![b"client".to_vec()]);
+        assert_eq!(decoded.msg_type(), "kernel_info_reply");
+        assert_eq!(decoded.parent_header, request.header);
+        assert_eq!(decoded.content, json!({ "status": "ok" }));
+
+        frames[2] = b"forged".to_vec();
+        assert_eq!(
+            session.decode(frames).unwrap_err(),
+            "Message has an invalid signature"
+        );
+    }
+
+    #[tokio::test]
+    async fn evaluate_cells() {
+        let mut kernel = new_kernel().await;
+        assert_eq!(kernel.evaluate("let x = 1").await, Ok(None));
+        assert_eq!(
+            kernel
+                .evaluate("let y = x + 1\n{ name = \"<y>\", value = y }")
+                .await,
+            Ok(Some(json!({
+                "text/plain": "{ name: \"<y>\", value: 2, }",
+                "text/html": "<table><tr><th>name</th><td>&quot;&lt;y&gt;&quot;</td></tr>\
+                    <tr><th>value</th><td>2</td></tr></table>",
+            })))
+        );
+        assert_eq!(
+            kernel
+                .evaluate("[{ a = 1, b = 'b' }, { a = 2, b = 'c' }]")
+                .await,
+            Ok(Some(json!({
+                "text/plain": "[{ a: 1, b: 'b', }, { a: 2, b: 'c', }]",
+                "text/html": "<table><tr><th>a</th><th>b</th></tr>\
+                    <tr><td>1</td><td>'b'</td></tr><tr><td>2</td><td>'c'</td></tr></table>",
+            })))
+        );
+
+        let err = kernel.evaluate("x + \"\"").await.unwrap_err();
+        assert_eq!(err.ename, "TypeError");
+        assert!(
+            err.traceback.iter().any(|line| line.contains("cell:1:5")),
+            "{:?}",
+            err.traceback
+        );
+    }
+}
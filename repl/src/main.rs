@@ -12,6 +12,7 @@ extern crate gluon_vm;
 extern crate gluon_codegen;
 
 use std::{
+    env,
     ffi::OsStr,
     fs,
     io::{self, Write},
@@ -19,6 +20,7 @@ use std::{
     sync::Arc,
 };
 
+use anyhow::anyhow;
 use codespan_reporting::term::termcolor;
 use quick_error::quick_error;
 use structopt::StructOpt;
@@ -32,10 +34,15 @@ use crate::base::{
 };
 
 use gluon::{
-    new_vm_async, vm::thread::ThreadInternal, vm::Error as VMError, Result, Thread, ThreadExt,
+    new_vm_async, project::Project, vm::thread::ThreadInternal, vm::Error as VMError, Result,
+    Thread, ThreadExt,
 };
 
+mod kernel;
+mod project;
 mod repl;
+mod test_runner;
+mod zmtp;
 
 quick_error! {
 /// Error type wrapping all possible errors that can be generated from gluon
@@ -103,6 +110,12 @@ impl ::std::str::FromStr for Color {
 pub struct FmtOpt {
     #[structopt(name = "FILE", parse(from_os_str), help = "Formats each file")]
     input: Vec<PathBuf>,
+    #[structopt(
+        long = "check",
+        help = "Prints the changes that formatting would make instead of writing them and fails if \
+                any file is not formatted"
+    )]
+    check: bool,
 }
 
 #[derive(StructOpt)]
@@ -111,12 +124,25 @@ pub enum SubOpt {
     Fmt(FmtOpt),
     #[structopt(name = "doc", about = "Documents gluon source code")]
     Doc(::gluon_doc::Opt),
+    #[structopt(name = "test", about = "Runs gluon tests")]
+    Test(test_runner::TestOpt),
+    #[structopt(name = "kernel", about = "Runs gluon as a Jupyter kernel")]
+    Kernel(kernel::KernelOpt),
+    #[structopt(name = "new", about = "Creates a gluon project")]
+    New(project::NewOpt),
+    #[structopt(name = "build", about = "Typechecks every module of the project")]
+    Build(project::BuildOpt),
+    #[structopt(name = "run", about = "Runs the entry module of the project")]
+    Run(project::RunOpt),
 }
 
 const LONG_VERSION: &str = concat!(clap::crate_version!(), "\n", "commit: ", env!("GIT_HASH"));
 
 #[derive(StructOpt)]
 #[structopt(about = "executes gluon programs", long_version = LONG_VERSION)]
+// Without this clap rejects files such as `tests/print.glu` since they look like a misspelling of
+// the `test` subcommand
+#[structopt(setting = clap::AppSettings::InferSubcommands)]
 pub struct Opt {
     #[structopt(short = "i", long = "interactive", help = "Starts the repl")]
     interactive: bool,
@@ -128,6 +154,12 @@ pub struct Opt {
     )]
     color: Color,
 
+    #[structopt(
+        long = "no-color",
+        help = "Disables coloring, the same as `--color never`"
+    )]
+    no_color: bool,
+
     #[structopt(
         long = "prompt",
         short = "p",
@@ -149,7 +181,11 @@ pub struct Opt {
     )]
     no_std: bool,
 
-    #[structopt(name = "FILE", help = "Executes each file as a gluon program")]
+    #[structopt(
+        name = "FILE",
+        help = "Executes each file as a gluon program. Without any files the entry module of the \
+                project in `gluon.toml` is run"
+    )]
     input: Vec<String>,
 
     #[structopt(
@@ -157,7 +193,6 @@ pub struct Opt {
         name = "ARGS",
         help = "Extra arguments passed to the gluon program"
     )]
-    #[allow(dead_code)]
     args: Vec<String>,
 
     #[structopt(subcommand)]
@@ -183,20 +218,51 @@ fn init_env_logger() {
 #[cfg(not(feature = "env_logger"))]
 fn init_env_logger() {}
 
-async fn format(file: &str, file_map: Arc<source::FileMap>, opt: &Opt) -> Result<String> {
+/// Loads the formatter options from the configuration file which applies to files in `dir`
+fn find_format_config(dir: &Path) -> anyhow::Result<gluon_format::Formatter> {
+    let dir = if dir == Path::new("") {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let dir = fs::canonicalize(dir)?;
+    Ok(gluon_format::Formatter::find_config(&dir)?)
+}
+
+async fn format(
+    file: &str,
+    file_map: Arc<source::FileMap>,
+    formatter: &mut gluon_format::Formatter,
+    opt: &Opt,
+) -> Result<String> {
     let thread = new_vm_async().await;
     thread.get_database_mut().use_standard_lib(!opt.no_std);
 
     Ok(thread
-        .format_expr_async(
-            &mut gluon_format::Formatter::default(),
-            file,
-            file_map.src(),
-        )
+        .format_expr_async(formatter, file, file_map.src())
         .await?)
 }
 
-async fn fmt_file(name: &Path, opt: &Opt) -> Result<()> {
+/// Prints the lines of `original` that differ from `formatted` as a unified diff
+fn print_diff(name: &str, original: &str, formatted: &str) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "--- {}", name)?;
+    writeln!(stdout, "+++ {}", name)?;
+    for hunk in gluon_format::diff::diff(original, formatted) {
+        write!(stdout, "{}", hunk)?;
+    }
+    Ok(())
+}
+
+/// Formats the file at `name`, returning whether it was already formatted. In `check` mode the
+/// file is left untouched and the changes are printed instead.
+async fn fmt_file(
+    name: &Path,
+    formatter: &mut gluon_format::Formatter,
+    check: bool,
+    opt: &Opt,
+) -> Result<bool> {
     use std::fs::File;
     use std::io::Read;
 
@@ -209,10 +275,15 @@ async fn fmt_file(name: &Path, opt: &Opt) -> Result<()> {
     let module_name = filename_to_module(&name.display().to_string());
     let mut code_map = source::CodeMap::new();
     let file_map = code_map.add_filemap(module_name.clone().into(), buffer);
-    let formatted = format(&module_name, file_map.clone(), opt).await?;
+    let formatted = format(&module_name, file_map.clone(), formatter, opt).await?;
 
-    // Avoid touching the .glu file if it did not change
-    if file_map.src() != formatted {
+    if file_map.src() == formatted {
+        // Avoid touching the .glu file if it did not change
+        return Ok(true);
+    }
+    if check {
+        print_diff(&name.display().to_string(), file_map.src(), &formatted)?;
+    } else {
         let bk_name = name.with_extension("glu.bk");
         let tmp_name = name.with_extension("tmp");
         {
@@ -222,10 +293,14 @@ async fn fmt_file(name: &Path, opt: &Opt) -> Result<()> {
         fs::rename(name, tmp_name)?;
         fs::rename(bk_name, name)?;
     }
-    Ok(())
+    Ok(false)
 }
 
-async fn fmt_stdio(opt: &Opt) -> Result<()> {
+async fn fmt_stdio(
+    formatter: &mut gluon_format::Formatter,
+    check: bool,
+    opt: &Opt,
+) -> Result<bool> {
     use std::io::{stdin, stdout, Read};
 
     let mut buffer = String::new();
@@ -234,13 +309,25 @@ async fn fmt_stdio(opt: &Opt) -> Result<()> {
     let mut code_map = source::CodeMap::new();
     let file_map = code_map.add_filemap("STDIN".into(), buffer);
 
-    let formatted = format("STDIN", file_map, opt).await?;
-    stdout().write_all(formatted.as_bytes())?;
-    Ok(())
+    let formatted = format("STDIN", file_map.clone(), formatter, opt).await?;
+    if check {
+        if file_map.src() != formatted {
+            print_diff("STDIN", file_map.src(), &formatted)?;
+        }
+    } else {
+        stdout().write_all(formatted.as_bytes())?;
+    }
+    Ok(file_map.src() == formatted)
 }
 
-async fn run(opt: &Opt, color: Color, vm: &Thread) -> std::result::Result<(), Error> {
+async fn run(
+    opt: &Opt,
+    color: Color,
+    vm: &Thread,
+    project: Option<&Project>,
+) -> std::result::Result<(), Error> {
     vm.global_env().set_debug_level(opt.debug_level.clone());
+    let use_std_lib = use_std_lib(opt, project);
     match opt.subcommand_opt {
         Some(SubOpt::Fmt(ref fmt_opt)) => {
             if !fmt_opt.input.is_empty() {
@@ -264,27 +351,48 @@ async fn run(opt: &Opt, color: Color, vm: &Thread) -> std::result::Result<(), Er
                 gluon_files.sort();
                 gluon_files.dedup();
 
+                let mut unformatted = 0;
                 for file in gluon_files {
-                    fmt_file(&file, opt).await?;
+                    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+                    let mut formatter = find_format_config(dir)?;
+                    if !fmt_file(&file, &mut formatter, fmt_opt.check, opt).await? {
+                        unformatted += 1;
+                    }
+                }
+                if fmt_opt.check && unformatted != 0 {
+                    return Err(anyhow!("{} files are not formatted", unformatted).into());
                 }
             } else {
-                fmt_stdio(opt).await?;
+                let mut formatter = find_format_config(Path::new("."))?;
+                if !fmt_stdio(&mut formatter, fmt_opt.check, opt).await? && fmt_opt.check {
+                    return Err(anyhow!("STDIN is not formatted").into());
+                }
             }
         }
         Some(SubOpt::Doc(ref doc_opt)) => {
-            let input = &doc_opt.input;
-            let output = &doc_opt.output;
             let thread = new_vm_async().await;
-            gluon_doc::generate_for_path(&thread, input, output)?;
+            gluon_doc::generate(&gluon_doc::Options::from(doc_opt), &thread)?;
+        }
+        Some(SubOpt::Test(ref test_opt)) => {
+            test_runner::run(test_opt, use_std_lib)?;
         }
+        Some(SubOpt::Kernel(ref kernel_opt)) => {
+            kernel::run(kernel_opt, use_std_lib).await?;
+        }
+        Some(SubOpt::New(ref new_opt)) => project::new(new_opt)?,
+        Some(SubOpt::Build(ref build_opt)) => {
+            project::build(build_opt, project, color, use_std_lib).await?
+        }
+        Some(SubOpt::Run(ref run_opt)) => project::run(run_opt, project, use_std_lib).await?,
         None => {
             if opt.interactive {
                 let prompt = opt.prompt.clone();
                 let debug_level = opt.debug_level.clone();
-                let use_std_lib = !opt.no_std;
                 repl::run(color, &prompt, debug_level, use_std_lib).await?;
             } else if !opt.input.is_empty() {
                 run_files(&vm, &opt.input).await?;
+            } else if let Some(project) = project {
+                project::load_entry(vm, project).await?;
             } else {
                 writeln!(io::stderr(), "{}", Opt::clap().get_matches().usage())
                     .expect("Error writing help to stderr");
@@ -294,19 +402,41 @@ async fn run(opt: &Opt, color: Color, vm: &Thread) -> std::result::Result<(), Er
     Ok(())
 }
 
+fn use_std_lib(opt: &Opt, project: Option<&Project>) -> bool {
+    !opt.no_std && project.map_or(true, |project| project.manifest.settings.std_lib)
+}
+
 #[tokio::main(basic_scheduler)]
 async fn main() {
     init_env_logger();
 
     let opt = Opt::from_args();
 
-    let vm = new_vm_async().await;
+    // The `gluon.toml` of the project in the working directory, if any
+    let project = match env::current_dir().map(|dir| Project::find(&dir)) {
+        Ok(Ok(project)) => project,
+        Ok(Err(err)) => {
+            eprintln!("{}", err);
+            ::std::process::exit(1);
+        }
+        Err(_) => None,
+    };
+
+    let mut builder = gluon::VmBuilder::new().args(Some(opt.args.clone()));
+    if let Some(project) = &project {
+        builder = project.configure(builder);
+    }
+    let vm = builder.build_async().await;
     vm.get_database_mut()
-        .use_standard_lib(!opt.no_std)
+        .use_standard_lib(use_std_lib(&opt, project.as_ref()))
         .run_io(true);
 
-    let color = opt.color;
-    let result = run(&opt, opt.color, &vm).await;
+    let color = if opt.no_color {
+        Color::Never
+    } else {
+        opt.color
+    };
+    let result = run(&opt, color, &vm, project.as_ref()).await;
     if let Err(err) = result {
         match err {
             Error::Gluon(gluon::Error::VM(VMError::Message(_))) => {
@@ -0,0 +1,297 @@
+//! The `new`, `build` and `run` commands which work on the project described by a `gluon.toml`
+//! manifest, see `gluon::project`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use bincode::Options;
+use codespan_reporting::term::termcolor;
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+use gluon::{
+    base::filename_to_module,
+    project::{Project, MANIFEST_FILE},
+    RootedThread, Thread, ThreadExt, VmBuilder,
+};
+
+use crate::{Color, Error};
+
+#[derive(StructOpt)]
+pub struct NewOpt {
+    #[structopt(
+        long = "lib",
+        help = "Creates a library with the entry module `src/lib.glu` instead of a program"
+    )]
+    lib: bool,
+    #[structopt(
+        name = "PATH",
+        parse(from_os_str),
+        help = "The directory to create the project in, which is also the name of the project"
+    )]
+    path: PathBuf,
+}
+
+#[derive(StructOpt)]
+pub struct BuildOpt {
+    #[structopt(
+        long = "bundle",
+        help = "Writes the compiled modules to `target/<name>.bundle` which `gluon run --bundle` \
+                runs without the project"
+    )]
+    bundle: bool,
+}
+
+#[derive(StructOpt)]
+pub struct RunOpt {
+    #[structopt(
+        long = "bundle",
+        parse(from_os_str),
+        help = "Runs a bundle written by `gluon build --bundle` instead of the project"
+    )]
+    bundle: Option<PathBuf>,
+    #[structopt(
+        last = true,
+        name = "ARGS",
+        help = "Extra arguments passed to the gluon program"
+    )]
+    args: Vec<String>,
+}
+
+fn require(project: Option<&Project>) -> Result<&Project, Error> {
+    project.ok_or_else(|| {
+        anyhow!(
+            "Could not find a `{}` with a `[package]` table in the working directory or any of \
+             its parents",
+            MANIFEST_FILE
+        )
+        .into()
+    })
+}
+
+/// Creates a vm which loads modules from `project` and uses the settings in its manifest
+pub async fn new_vm(
+    project: Option<&Project>,
+    args: Vec<String>,
+    use_std_lib: bool,
+    run_io: bool,
+) -> RootedThread {
+    let mut builder = VmBuilder::new().args(Some(args));
+    if let Some(project) = project {
+        builder = project.configure(builder);
+    }
+    let vm = builder.build_async().await;
+    vm.get_database_mut()
+        .use_standard_lib(use_std_lib)
+        .run_io(run_io);
+    vm
+}
+
+/// The name of the entry module of `project`, derived from its path in the project
+fn entry_module(project: &Project) -> Result<String, Error> {
+    let entry = &project.manifest.package.entry;
+    Ok(filename_to_module(entry.to_str().ok_or_else(|| {
+        anyhow!("Non-UTF-8 filename `{}`", entry.display())
+    })?))
+}
+
+/// Loads the entry module of `project` into `vm`, running it if `vm` runs IO actions
+pub async fn load_entry(vm: &Thread, project: &Project) -> Result<String, Error> {
+    let module = entry_module(project)?;
+    let entry = project.entry();
+    let source = fs::read_to_string(&entry)
+        .with_context(|| format!("Unable to read the entry module `{}`", entry.display()))?;
+    vm.load_script_async(&module, &source).await?;
+    Ok(module)
+}
+
+/// Returns the names of the modules in the source directories of `project`, except the entry
+/// module
+fn modules(project: &Project) -> Result<Vec<String>, Error> {
+    let entry = project.entry();
+    let mut modules = Vec::new();
+    for dir in project.source_dirs() {
+        for entry_result in WalkDir::new(&dir) {
+            let file = entry_result.map_err(anyhow::Error::from)?;
+            let path = file.path();
+            if !file.file_type().is_file()
+                || path.extension().and_then(|ext| ext.to_str()) != Some("glu")
+                || path == entry
+            {
+                continue;
+            }
+            let relative = path.strip_prefix(&dir).unwrap_or(path);
+            modules.push(filename_to_module(
+                relative
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Non-UTF-8 filename `{}`", path.display()))?,
+            ));
+        }
+    }
+    modules.sort();
+    modules.dedup();
+    Ok(modules)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Creates a project in `opt.path` with a manifest and a hello world program or library
+pub fn new(opt: &NewOpt) -> Result<(), Error> {
+    let path = &opt.path;
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Unable to name a project after `{}`", path.display()))?;
+    if !is_identifier(name) {
+        return Err(anyhow!(
+            "`{}` is not a valid package name, it must be an identifier such as `my_project`",
+            name
+        )
+        .into());
+    }
+    if path.join(MANIFEST_FILE).exists() {
+        return Err(anyhow!("`{}` already contains a project", path.display()).into());
+    }
+
+    let (entry, source) = if opt.lib {
+        (
+            "src/lib.glu",
+            "//! The library\n\nlet greeting = \"Hello, world!\"\n\n{ greeting }\n",
+        )
+    } else {
+        (
+            "src/main.glu",
+            "let io = import! std.io\n\nio.println \"Hello, world!\"\n",
+        )
+    };
+    let mut manifest = format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name);
+    if opt.lib {
+        manifest.push_str(&format!("entry = \"{}\"\n", entry));
+    }
+    manifest.push_str("\n[dependencies]\n");
+
+    let write = |file: &str, contents: &str| -> Result<(), Error> {
+        let file = path.join(file);
+        fs::create_dir_all(file.parent().unwrap_or_else(|| Path::new(".")))
+            .and_then(|()| fs::write(&file, contents))
+            .with_context(|| format!("Unable to write `{}`", file.display()))?;
+        Ok(())
+    };
+    write(MANIFEST_FILE, &manifest)?;
+    if !path.join(entry).exists() {
+        write(entry, source)?;
+    }
+    if !path.join(".gitignore").exists() {
+        write(".gitignore", "/target\n/.gluon\n")?;
+    }
+
+    eprintln!("Created the project `{}` in {}", name, path.display());
+    Ok(())
+}
+
+/// Typechecks every module of the project, reporting all the errors which were found. With
+/// `--bundle` the modules which the entry module imports are then compiled and written to
+/// `target/<name>.bundle`.
+pub async fn build(
+    opt: &BuildOpt,
+    project: Option<&Project>,
+    color: Color,
+    use_std_lib: bool,
+) -> Result<(), Error> {
+    let project = require(project)?;
+    let name = &project.manifest.package.name;
+
+    let vm = new_vm(Some(project), Vec::new(), use_std_lib, false).await;
+    let mut errors = Vec::new();
+    {
+        let module = entry_module(project)?;
+        let entry = project.entry();
+        let source = fs::read_to_string(&entry)
+            .with_context(|| format!("Unable to read the entry module `{}`", entry.display()))?;
+        if let Err(err) = vm.typecheck_str_async(&module, &source, None).await {
+            errors.push(err);
+        }
+    }
+    for module in modules(project)? {
+        debug!("Typechecking {}", module);
+        let import = format!("import! {}", module);
+        if let Err(err) = vm.typecheck_str_async("build", &import, None).await {
+            // A module which fails to typecheck is reported by every module which imports it
+            if !errors.iter().any(|e| e.to_string() == err.to_string()) {
+                errors.push(err);
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        let mut stderr = termcolor::StandardStream::stderr(color.into());
+        for err in &errors {
+            if err.emit(&mut stderr).is_err() {
+                eprintln!("{}", err);
+            }
+        }
+        return Err(anyhow!(
+            "Could not build `{}` due to {} previous error{}",
+            name,
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" }
+        )
+        .into());
+    }
+
+    if opt.bundle {
+        // A new vm is used as the entry module has already been typechecked as an inline module
+        // in `vm`. IO actions are not run so the entry module is only evaluated.
+        let vm = new_vm(Some(project), Vec::new(), use_std_lib, false).await;
+        let module = load_entry(&vm, project).await?;
+
+        let target = project.root.join("target");
+        fs::create_dir_all(&target)
+            .with_context(|| format!("Unable to create `{}`", target.display()))?;
+        let path = target.join(format!("{}.bundle", name));
+
+        let options = bincode::DefaultOptions::new();
+        let mut buffer = options.serialize(&module).map_err(anyhow::Error::from)?;
+        vm.save_snapshot(&mut bincode::Serializer::new(&mut buffer, options))
+            .map_err(anyhow::Error::from)?;
+        fs::write(&path, buffer)
+            .with_context(|| format!("Unable to write `{}`", path.display()))?;
+        eprintln!("Bundled `{}` into {}", name, path.display());
+    }
+
+    Ok(())
+}
+
+/// Runs the entry module of the project, or the bundle in `opt.bundle`
+pub async fn run(opt: &RunOpt, project: Option<&Project>, use_std_lib: bool) -> Result<(), Error> {
+    match &opt.bundle {
+        Some(path) => {
+            let vm = new_vm(project, opt.args.clone(), use_std_lib, true).await;
+            let buffer =
+                fs::read(path).with_context(|| format!("Unable to read `{}`", path.display()))?;
+            // A bundle is the name of the entry module followed by a snapshot of the modules
+            let options = bincode::DefaultOptions::new();
+            let mut reader = &buffer[..];
+            let module: String = options
+                .deserialize_from(&mut reader)
+                .with_context(|| format!("`{}` is not a bundle", path.display()))?;
+            vm.load_snapshot(&mut bincode::Deserializer::with_reader(reader, options))?;
+            vm.load_script_async("run", &format!("import! {}", module))
+                .await?;
+        }
+        None => {
+            let project = require(project)?;
+            let vm = new_vm(Some(project), opt.args.clone(), use_std_lib, true).await;
+            load_entry(&vm, project).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,37 +1,48 @@
 extern crate gluon_completion as completion;
 
-use std::{borrow::Cow, error::Error as StdError, path::PathBuf, str::FromStr, sync::Mutex};
+use std::{
+    borrow::Cow,
+    error::Error as StdError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 
+use either::Either;
 use futures::{channel::oneshot, future, prelude::*};
 
 use crate::base::{
     ast::{self, AstClone, Expr, Pattern, RootExpr, SpannedPattern, Typed, TypedIdent},
     error::InFile,
+    fnv::FnvMap,
     kind::Kind,
     mk_ast_arena, pos, resolve,
-    symbol::{Symbol, SymbolModule},
-    types::{ArcType, TypeExt},
+    source::{self, Source},
+    symbol::{Symbol, SymbolModule, Symbols},
+    types::{ArcType, ArgType, NullInterner, Type, TypeEnv, TypeExt},
     DebugLevel,
 };
-use crate::parser::{parse_partial_repl_line, ReplLine};
+use crate::parser::{parse_partial_repl_line, ReplLine, Token};
 use crate::vm::{
-    api::{
-        de::De, generic::A, Generic, Getable, OpaqueValue, OwnedFunction, Pushable, VmType, WithVM,
-        IO,
-    },
+    api::{generic::A, Generic, Getable, OpaqueValue, OwnedFunction, Pushable, VmType, WithVM, IO},
     internal::ValuePrinter,
-    thread::{ActiveThread, RootedValue, Thread},
+    thread::{ActiveThread, DebugInfo, HookFlags, RootedValue, Thread, ThreadInternal},
     {self, Error as VMError, Result as VMResult},
 };
 
 use gluon::{
     compiler_pipeline::{Executable, ExecuteValue},
-    import::add_extern_module,
-    query::CompilerDatabase,
+    import::{add_extern_module, Import},
+    query::{CompilationBase, CompilerDatabase},
     Error as GluonError, Result as GluonResult, RootedThread, ThreadExt,
 };
 
 use codespan_reporting::term::termcolor;
+use rustyline::history::History;
 
 use crate::Color;
 
@@ -51,6 +62,259 @@ fn type_of_expr(args: WithVM<&str>) -> impl Future<Output = IO<Result<String, St
     }
 }
 
+/// Typechecks `args` and shows the implicit arguments which were resolved for it, both as a list
+/// and inserted into the expression
+fn elaborate_expr(args: WithVM<&str>) -> impl Future<Output = IO<Result<String, String>>> {
+    let WithVM { vm, value: args } = args;
+    let args = args.to_string();
+    let vm = vm.new_thread().unwrap(); // TODO Run on the same thread once that works
+
+    async move {
+        IO::Value(match vm.typecheck_str_async("<repl>", &args, None).await {
+            Ok((expr, typ)) => {
+                let start = vm
+                    .get_database()
+                    .get_filemap("<repl>")
+                    .map_or(pos::BytePos::from(0), |file_map| file_map.span().start());
+                let env = vm.get_env();
+                let mut elaborate = Elaborate::new(&env, &args, start);
+                ast::Visitor::visit_expr(&mut elaborate, expr.expr());
+
+                let mut output = format!("{} : {}", elaborate.apply_edits().trim(), typ);
+                if elaborate.implicits.is_empty() {
+                    output.push_str("\n\nNo implicit arguments were inserted");
+                } else {
+                    output.push_str("\n\nImplicit arguments:");
+                    for implicit in &elaborate.implicits {
+                        output.push_str("\n    ");
+                        output.push_str(implicit);
+                    }
+                }
+                Ok(output)
+            }
+            Err(msg) => Err(format!("{}", msg)),
+        })
+    }
+}
+
+/// Collects the implicit arguments of an expression along with the edits which insert them into
+/// its source
+struct Elaborate<'a> {
+    env: &'a dyn TypeEnv<Type = ArcType>,
+    source: &'a str,
+    start: pos::BytePos,
+    /// Replaces the source in the byte range with the string
+    edits: Vec<(usize, usize, String)>,
+    implicits: Vec<String>,
+    /// The modules bound by `let { ? } = import! module`, which would otherwise be shown with the
+    /// generated name of the binding
+    implicit_imports: FnvMap<Symbol, String>,
+}
+
+impl<'a> Elaborate<'a> {
+    fn new(env: &'a dyn TypeEnv<Type = ArcType>, source: &'a str, start: pos::BytePos) -> Self {
+        Elaborate {
+            env,
+            source,
+            start,
+            edits: Vec::new(),
+            implicits: Vec::new(),
+            implicit_imports: Default::default(),
+        }
+    }
+
+    fn offset(&self, pos: pos::BytePos) -> usize {
+        (pos.to_usize() - self.start.to_usize()).min(self.source.len())
+    }
+
+    /// Checks that `span` is part of the source, unlike the expressions inserted by the implicit
+    /// prelude
+    fn in_source(&self, span: pos::Span<pos::BytePos>) -> bool {
+        self.start <= span.start()
+            && span.end().to_usize() <= self.start.to_usize() + self.source.len()
+    }
+
+    fn insert(&mut self, pos: pos::BytePos, text: impl Into<String>) {
+        let offset = self.offset(pos);
+        self.edits.push((offset, offset, text.into()));
+    }
+
+    /// Records the `implicit_args` passed to the function `name` of type `func_type` and returns
+    /// them as source code
+    fn add_implicits(
+        &mut self,
+        name: &str,
+        func_type: Option<ArcType>,
+        implicit_args: &[ast::SpannedExpr<Symbol>],
+    ) -> String {
+        // The type of the implicit parameters of the function are more precise than the type of
+        // the instance itself which may be a function as well
+        let mut param_types = Vec::new();
+        if let Some(func_type) = &func_type {
+            let mut typ = func_type.remove_forall();
+            while let Some((ArgType::Implicit, arg, ret)) = typ.as_function_with_type() {
+                param_types.push(arg.clone());
+                typ = ret.remove_forall();
+            }
+        }
+
+        let mut inserted = String::new();
+        for (i, arg) in implicit_args.iter().enumerate() {
+            let arg_string = self.implicit_arg_to_string(arg);
+            let typ = param_types
+                .get(i)
+                .cloned()
+                .or_else(|| arg.try_type_of(self.env).ok());
+            self.implicits.push(match typ {
+                Some(typ) => format!("{} : {} = {}", name, typ, arg_string),
+                None => format!("{} = {}", name, arg_string),
+            });
+            inserted.push_str(" ?");
+            inserted.push_str(&arg_string);
+        }
+        inserted
+    }
+
+    fn implicit_arg_to_string(&self, expr: &ast::SpannedExpr<Symbol>) -> String {
+        match &expr.value {
+            Expr::Ident(id) => match self.implicit_imports.get(&id.name) {
+                Some(module) => module.clone(),
+                None => id.name.declared_name().to_string(),
+            },
+            Expr::Projection(expr, field, _) => format!(
+                "{}.{}",
+                self.implicit_arg_to_string(expr),
+                field.declared_name()
+            ),
+            Expr::App {
+                func,
+                implicit_args,
+                args,
+            } => {
+                // Every argument of an instance is an implicit argument as well
+                let mut output = format!("({}", self.implicit_arg_to_string(func));
+                for arg in implicit_args.iter().chain(args.iter()) {
+                    output.push_str(" ?");
+                    output.push_str(&self.implicit_arg_to_string(arg));
+                }
+                output.push(')');
+                output
+            }
+            Expr::MacroExpansion {
+                original,
+                replacement,
+            } => match &original.value {
+                // Show `import! std.int` as `std.int`
+                Expr::App { args, .. } if args.len() == 1 => self.implicit_arg_to_string(&args[0]),
+                _ => self.implicit_arg_to_string(replacement),
+            },
+            Expr::Annotated(expr, _) => self.implicit_arg_to_string(expr),
+            _ => "<implicit>".to_string(),
+        }
+    }
+
+    fn apply_edits(&self) -> String {
+        let mut edits = self.edits.clone();
+        // Insertions at the same position are kept in the order they were added
+        edits.sort_by_key(|&(start, end, _)| (start, end));
+        let mut output = String::new();
+        let mut last_end = 0;
+        for (start, end, text) in edits {
+            if start < last_end {
+                continue;
+            }
+            output.push_str(&self.source[last_end..start]);
+            output.push_str(&text);
+            last_end = end;
+        }
+        output.push_str(&self.source[last_end..]);
+        output
+    }
+}
+
+fn is_atomic(expr: &ast::SpannedExpr<Symbol>) -> bool {
+    match expr.value {
+        Expr::Ident(_)
+        | Expr::Literal(_)
+        | Expr::Projection(..)
+        | Expr::Array(_)
+        | Expr::Record { .. }
+        | Expr::Tuple { .. } => true,
+        _ => false,
+    }
+}
+
+impl<'a, 'ast> ast::Visitor<'a, 'ast> for Elaborate<'_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &'a ast::SpannedExpr<'ast, Symbol>) {
+        match &expr.value {
+            Expr::App {
+                func,
+                implicit_args,
+                args,
+            } if !implicit_args.is_empty() && self.in_source(expr.span) => {
+                let name = self.source
+                    [self.offset(func.span.start())..self.offset(func.span.end())]
+                    .to_string();
+                let func_type = func.try_type_of(self.env).ok();
+                let inserted = self.add_implicits(&name, func_type, implicit_args);
+                if args.is_empty() {
+                    // `f` becomes `(f ?implicit)` so that it stays a single argument
+                    self.insert(func.span.start(), "(");
+                    self.insert(func.span.end(), format!("{})", inserted));
+                } else {
+                    self.insert(func.span.end(), inserted);
+                }
+                self.visit_expr(func);
+                for arg in args.iter() {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Infix {
+                lhs,
+                op,
+                rhs,
+                implicit_args,
+            } if !implicit_args.is_empty() && self.in_source(expr.span) => {
+                // `l + r` becomes `(+) ?implicit l r`
+                let name = op.value.name.declared_name().to_string();
+                let inserted = self.add_implicits(&name, Some(op.value.typ.clone()), implicit_args);
+                self.insert(lhs.span.start(), format!("({}){} ", name, inserted));
+                for operand in [&**lhs, &**rhs].iter() {
+                    if !is_atomic(operand) {
+                        self.insert(operand.span.start(), "(");
+                    }
+                }
+                let (between_start, between_end) =
+                    (self.offset(lhs.span.end()), self.offset(rhs.span.start()));
+                self.edits
+                    .push((between_start, between_end, " ".to_string()));
+                for operand in [&**lhs, &**rhs].iter() {
+                    self.visit_expr(operand);
+                    if !is_atomic(operand) {
+                        self.insert(operand.span.end(), ")");
+                    }
+                }
+            }
+            Expr::LetBindings(binds, _) => {
+                for bind in binds.iter() {
+                    if let Pattern::Record {
+                        implicit_import: Some(id),
+                        ..
+                    } = &bind.name.value
+                    {
+                        let module = self.implicit_arg_to_string(&bind.expr);
+                        self.implicit_imports.insert(id.value.clone(), module);
+                    }
+                }
+                ast::walk_expr(self, expr)
+            }
+            _ => ast::walk_expr(self, expr),
+        }
+    }
+}
+
 fn find_kind(args: WithVM<&str>) -> IO<Result<String, String>> {
     let vm = args.vm;
     let args = args.value.trim();
@@ -71,7 +335,7 @@ fn find_info(args: WithVM<&str>) -> IO<Result<String, String>> {
     let args = args.value.trim();
     let env = vm.get_env();
     let mut buffer = String::new();
-    match env.find_type_info(args) {
+    let alias = match env.find_type_info(args) {
         Ok(alias) => {
             // Found a type alias
             let mut fmt = || -> Result<(), std::fmt::Error> {
@@ -82,6 +346,7 @@ fn find_info(args: WithVM<&str>) -> IO<Result<String, String>> {
                 write!(&mut buffer, " = {}", alias.unresolved_type())
             };
             fmt().unwrap();
+            Some(alias)
         }
         Err(err) => {
             // Try to find a value at `args` to print its type and documentation comment (if any)
@@ -91,8 +356,9 @@ fn find_info(args: WithVM<&str>) -> IO<Result<String, String>> {
                 }
                 Err(_) => return IO::Value(Err(format!("{}", err))),
             }
+            None
         }
-    }
+    };
     let maybe_metadata = env.get_metadata(args).ok();
     if let Some(comment) = maybe_metadata
         .as_ref()
@@ -102,9 +368,84 @@ fn find_info(args: WithVM<&str>) -> IO<Result<String, String>> {
             write!(&mut buffer, "\n/// {}", line).unwrap();
         }
     }
+    if let Some(alias) = &alias {
+        let typ = alias.unresolved_type();
+        match **typ {
+            Type::Variant(ref row) => {
+                // The constructors return an opaque placeholder instead of the type itself
+                let return_type = alias
+                    .params()
+                    .iter()
+                    .fold(alias.name.declared_name().to_string(), |acc, param| {
+                        format!("{} {}", acc, param.id)
+                    });
+                write!(&mut buffer, "\n\nConstructors:").unwrap();
+                for field in row.row_iter() {
+                    write!(&mut buffer, "\n    {} : ", field.name).unwrap();
+                    for arg in field.typ.arg_iter() {
+                        write!(&mut buffer, "{} -> ", arg).unwrap();
+                    }
+                    write!(&mut buffer, "{}", return_type).unwrap();
+                }
+            }
+            Type::Record(ref row) => {
+                write!(&mut buffer, "\n\nFields:").unwrap();
+                for field in row.row_iter() {
+                    write!(&mut buffer, "\n    {} : {}", field.name, field.typ).unwrap();
+                }
+            }
+            _ => (),
+        }
+    }
+    // Types are found by the name of the alias as the metadata only has the unqualified name
+    let definition = alias.map(|alias| alias.name.clone()).or_else(|| {
+        maybe_metadata
+            .as_ref()
+            .and_then(|metadata| metadata.definition.clone())
+    });
+    if let Some(location) = definition.and_then(|definition| find_definition(vm, &definition)) {
+        write!(&mut buffer, "\n\nDefined at {}", location).unwrap();
+    }
     IO::Value(Ok(buffer))
 }
 
+fn find_doc(args: WithVM<&str>) -> IO<Result<String, String>> {
+    let vm = args.vm;
+    let args = args.value.trim();
+    let env = vm.get_env();
+    IO::Value(
+        match env
+            .get_metadata(args)
+            .ok()
+            .and_then(|metadata| metadata.comment.clone())
+        {
+            Some(comment) => Ok(comment.content),
+            None if env.find_type_info(args).is_ok() || env.get_binding(args).is_ok() => {
+                Err(format!("`{}` is not documented", args))
+            }
+            None => Err(format!("Undefined name `{}`", args)),
+        },
+    )
+}
+
+/// Searches the modules that have been loaded for the binding or type `definition`, returning the
+/// module, line and column that it was defined at
+fn find_definition(thread: &Thread, definition: &Symbol) -> Option<String> {
+    let db = thread.get_database();
+    db.typechecked_modules().into_iter().find_map(|module| {
+        let value = db.peek_typechecked_source_module(&module)?;
+        let file_map = db.get_filemap(&module)?;
+        let span = completion::find_definition(file_map.span(), &value.expr.expr(), definition)?;
+        let location = file_map.location(span.start())?;
+        Some(format!(
+            "{}:{}:{}",
+            module,
+            location.line.number(),
+            location.column.number()
+        ))
+    })
+}
+
 fn switch_debug_level(args: WithVM<&str>) -> IO<Result<String, String>> {
     let vm = args.vm;
     let args = args.value.trim();
@@ -118,9 +459,35 @@ fn switch_debug_level(args: WithVM<&str>) -> IO<Result<String, String>> {
     IO::Value(Ok(vm.global_env().get_debug_level().to_string()))
 }
 
-fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonResult<Vec<String>> {
+async fn complete(
+    thread: &Thread,
+    name: &str,
+    fileinput: &str,
+    pos: usize,
+) -> GluonResult<Vec<completion::Suggestion>> {
+    // Commands take names such as `std.list.of` instead of expressions
+    if fileinput.starts_with(':') {
+        return complete_module_path(thread, name, &fileinput[..pos]).await;
+    }
+
+    let suggestions = complete_expr(thread, name, fileinput, pos).await?;
+    if suggestions.is_empty() {
+        complete_module_path(thread, name, &fileinput[..pos]).await
+    } else {
+        Ok(suggestions)
+    }
+}
+
+async fn complete_expr(
+    thread: &Thread,
+    name: &str,
+    mut fileinput: &str,
+    pos: usize,
+) -> GluonResult<Vec<completion::Suggestion>> {
     use gluon::compiler_pipeline::*;
 
+    let fileinput_storage;
+
     let mut db = thread.get_database();
     let mut module_compiler = thread.module_compiler(&mut db);
 
@@ -133,33 +500,136 @@ fn complete(thread: &Thread, name: &str, fileinput: &str, pos: usize) -> GluonRe
         fileinput,
     ) {
         Ok(expr) => expr,
-        Err(err) => err.get_value()?,
+        Err(err) => {
+            // Unclosed brackets before the cursor can leave nothing to complete from, so try
+            // again with them closed
+            let source = source::FileMap::new(name.into(), fileinput.into());
+            let cursor = source.span().start() + pos::ByteOffset::from(pos as i64);
+            match completion::repair_source(&source, cursor) {
+                Some(repaired) if repaired != fileinput => {
+                    fileinput_storage = repaired;
+                    fileinput = &fileinput_storage;
+                    match parse_expr(
+                        &mut module_compiler,
+                        thread.global_env().type_cache(),
+                        &name,
+                        fileinput,
+                    ) {
+                        Ok(expr) => expr,
+                        Err(err) => err.get_value()?,
+                    }
+                }
+                _ => err.get_value()?,
+            }
+        }
     };
 
     // Only need the typechecker to fill infer the types as best it can regardless of errors
-    let _ = (&mut expr).typecheck(&mut module_compiler, thread, &name, fileinput);
+    let _ = (&mut expr)
+        .typecheck(&mut module_compiler, thread, &name, fileinput)
+        .await;
     let file_map = module_compiler
         .get_filemap(&name)
         .ok_or_else(|| VMError::from("FileMap is missing for completion".to_string()))?;
-    let suggestions = completion::suggest(
+
+    Ok(import_query(thread, &mut module_compiler).suggest(
         &thread.get_env(),
         file_map.span(),
         &expr.expr(),
         file_map.span().start() + pos::ByteOffset::from(pos as i64),
+    ))
+}
+
+/// Creates a query which lets `import! std.` complete the modules that the import macro is able to
+/// find
+fn import_query(
+    thread: &Thread,
+    module_compiler: &mut gluon::ModuleCompiler<'_, '_>,
+) -> completion::SuggestionQuery {
+    let mut query = completion::SuggestionQuery::new();
+    if let Some(import) = thread
+        .get_macros()
+        .get("import")
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+    {
+        query.paths = import.paths.read().unwrap().clone();
+        query.modules = import.modules(module_compiler);
+    }
+    query
+}
+
+/// Completes a path such as `std.list.o` which refers to a module instead of a local variable.
+/// These can't be used as expressions but they can be given to commands such as `:info`.
+async fn complete_module_path(
+    thread: &Thread,
+    name: &str,
+    line: &str,
+) -> GluonResult<Vec<completion::Suggestion>> {
+    let path_start = line
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let path = &line[path_start..];
+    let (module_path, prefix) = match path.rfind('.') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut suggestions = Vec::new();
+
+    // The members of the longest prefix of `module_path` which is a module
+    let segments = module_path.split('.').collect::<Vec<_>>();
+    for module_len in (1..=segments.len()).rev() {
+        let module = segments[..module_len].join(".");
+        let typ = match thread
+            .typecheck_str_async(name, &format!("import! {}", module), None)
+            .await
+        {
+            Ok((_, typ)) => typ,
+            Err(_) => continue,
+        };
+
+        let env = thread.get_env();
+        let typ = segments[module_len..].iter().try_fold(typ, |typ, field| {
+            resolve::remove_aliases(&env, &mut NullInterner, typ)
+                .row_iter()
+                .find(|f| f.name.declared_name() == *field)
+                .map(|f| f.typ.clone())
+        });
+        if let Some(typ) = typ {
+            let typ = resolve::remove_aliases(&env, &mut NullInterner, typ);
+            suggestions.extend(
+                typ.row_iter()
+                    .filter(|field| field.name.declared_name().starts_with(prefix))
+                    .map(|field| completion::Suggestion {
+                        name: field.name.declared_name().into(),
+                        typ: Either::Right(field.typ.clone()),
+                        score: Default::default(),
+                    }),
+            );
+        }
+        break;
+    }
+
+    // Modules nested inside of `module_path`
+    let mut db = thread.get_database();
+    let mut module_compiler = thread.module_compiler(&mut db);
+    import_query(thread, &mut module_compiler).suggest_module_import(
+        &thread.get_env(),
+        path,
+        &mut suggestions,
     );
-    Ok(suggestions
-        .into_iter()
-        .map(|ident| {
-            let s: &str = ident.name.as_ref();
-            s.to_string()
-        })
-        .collect())
+
+    Ok(suggestions)
 }
 
 struct Completer {
     thread: RootedThread,
     hinter: rustyline::hint::HistoryHinter,
-    highlighter: rustyline::highlight::MatchingBracketHighlighter,
+    /// Whether the input should be colored as it is typed
+    highlight_input: bool,
 }
 
 impl rustyline::Helper for Completer {}
@@ -169,28 +639,32 @@ impl rustyline::validate::Validator for Completer {
         &self,
         ctx: &mut rustyline::validate::ValidationContext,
     ) -> rustyline::Result<rustyline::validate::ValidationResult> {
+        // An empty continuation line submits the input as is so that the parse error is reported
+        // instead of asking for more lines forever
         let line = ctx.input();
+        if line.ends_with('\n') || !is_incomplete_input(&self.thread, line) {
+            Ok(rustyline::validate::ValidationResult::Valid(None))
+        } else {
+            Ok(rustyline::validate::ValidationResult::Incomplete)
+        }
+    }
+}
 
-        let is_incomplete = |err: &gluon::parser::ParseErrors| {
-            use gluon::parser::Token;
-
-            err.iter().any(|err| match &err.value {
-                gluon::parser::Error::UnexpectedToken(Token::CloseBlock, _) => true,
-                _ => false,
-            })
-        };
+/// Checks if `line` only fails to parse because it ends too early, in which case the user is
+/// prompted for another line which continues `line`
+fn is_incomplete_input(thread: &Thread, line: &str) -> bool {
+    if line.trim_start().starts_with(':') {
+        return false;
+    }
 
-        let mut db = self.thread.get_database();
-        let mut module_compiler = self.thread.module_compiler(&mut db);
-        mk_ast_arena!(arena);
-        let filemap = self.thread.get_database().add_filemap("line", line);
-        let mut module = SymbolModule::new("line".into(), module_compiler.mut_symbols());
-        match parse_partial_repl_line((*arena).borrow(), &mut module, &*filemap) {
-            Err((_, err)) if is_incomplete(&err) => {
-                Ok(rustyline::validate::ValidationResult::Incomplete)
-            }
-            Ok(_) | Err(_) => Ok(rustyline::validate::ValidationResult::Valid(None)),
-        }
+    let mut db = thread.get_database();
+    let mut module_compiler = thread.module_compiler(&mut db);
+    mk_ast_arena!(arena);
+    let filemap = thread.get_database().add_filemap("line", line);
+    let mut module = SymbolModule::new("line".into(), module_compiler.mut_symbols());
+    match parse_partial_repl_line((*arena).borrow(), &mut module, &*filemap) {
+        Err((_, err)) => err.iter().any(|err| err.value.is_unexpected_end()),
+        Ok(_) => false,
     }
 }
 
@@ -202,15 +676,11 @@ impl rustyline::hint::Hinter for Completer {
 
 impl rustyline::highlight::Highlighter for Completer {
     fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
-        self.highlighter.highlight(line, pos)
-    }
-
-    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
-        &'s self,
-        prompt: &'p str,
-        default: bool,
-    ) -> Cow<'b, str> {
-        self.highlighter.highlight_prompt(prompt, default)
+        if self.highlight_input {
+            highlight_line(line, pos)
+        } else {
+            Cow::Borrowed(line)
+        }
     }
 
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
@@ -226,35 +696,171 @@ impl rustyline::highlight::Highlighter for Completer {
         }
     }
 
-    fn highlight_candidate<'c>(
-        &self,
-        candidate: &'c str,
-        completion: rustyline::CompletionType,
-    ) -> Cow<'c, str> {
-        self.highlighter.highlight_candidate(candidate, completion)
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // Any edit may change how the tokens of the line are colored so always redraw it
+        self.highlight_input
     }
+}
+
+/// Splits `line` into its tokens along with the byte range of each of them. Input which can't be
+/// tokenized, such as an unterminated string, ends the tokens early.
+fn tokenize_line(line: &str) -> Vec<(std::ops::Range<usize>, Token<&str>)> {
+    let mut tokens = Vec::new();
+    for token in crate::parser::Tokenizer::new(line) {
+        match token {
+            Ok(token) if token.value == Token::EOF => break,
+            Ok(token) => {
+                // The tokenizer starts counting at 1
+                let start = token.span.start().absolute.to_usize() - 1;
+                let end = token.span.end().absolute.to_usize() - 1;
+                tokens.push((start..end, token.value));
+            }
+            Err(_) => break,
+        }
+    }
+    tokens
+}
+
+fn bracket_depth(token: &Token<&str>) -> isize {
+    match token {
+        Token::LBrace | Token::LBracket | Token::LParen => 1,
+        Token::RBrace | Token::RBracket | Token::RParen => -1,
+        _ => 0,
+    }
+}
+
+/// Finds the token which matches the bracket under or just before the cursor at `pos`
+fn find_matching_bracket(
+    tokens: &[(std::ops::Range<usize>, Token<&str>)],
+    pos: usize,
+) -> Option<usize> {
+    let index = tokens
+        .iter()
+        .position(|(range, token)| range.start == pos && bracket_depth(token) != 0)
+        .or_else(|| {
+            tokens
+                .iter()
+                .position(|(range, token)| range.end == pos && bracket_depth(token) != 0)
+        })?;
+
+    let direction = bracket_depth(&tokens[index].1);
+    let mut depth = 0;
+    let mut i = index as isize;
+    while i >= 0 && (i as usize) < tokens.len() {
+        depth += bracket_depth(&tokens[i as usize].1);
+        if depth == 0 {
+            let expected = match (&tokens[index].1, &tokens[i as usize].1) {
+                (Token::LBrace, Token::RBrace)
+                | (Token::RBrace, Token::LBrace)
+                | (Token::LBracket, Token::RBracket)
+                | (Token::RBracket, Token::LBracket)
+                | (Token::LParen, Token::RParen)
+                | (Token::RParen, Token::LParen) => true,
+                _ => false,
+            };
+            return if expected { Some(i as usize) } else { None };
+        }
+        i += direction;
+    }
+    None
+}
 
-    fn highlight_char(&self, line: &str, pos: usize) -> bool {
-        self.highlighter.highlight_char(line, pos)
+/// Colors the keywords, literals and operators of `line` and highlights the bracket matching the
+/// one at the cursor
+fn highlight_line(line: &str, pos: usize) -> Cow<str> {
+    // TODO Detect when windows supports ANSI escapes
+    #[cfg(windows)]
+    {
+        let _ = pos;
+        Cow::Borrowed(line)
+    }
+    #[cfg(not(windows))]
+    {
+        use ansi_term::{Colour, Style};
+
+        let tokens = tokenize_line(line);
+        let matching_bracket = find_matching_bracket(&tokens, pos);
+
+        let mut output = String::with_capacity(line.len());
+        let mut last_end = 0;
+        for (i, (range, token)) in tokens.iter().enumerate() {
+            let style = if Some(i) == matching_bracket {
+                Colour::Blue.bold()
+            } else {
+                match token {
+                    Token::Rec
+                    | Token::Else
+                    | Token::Forall
+                    | Token::If
+                    | Token::In
+                    | Token::Let
+                    | Token::Do
+                    | Token::Seq
+                    | Token::Match
+                    | Token::Then
+                    | Token::Type
+                    | Token::With => Colour::Purple.normal(),
+                    Token::StringLiteral(_) | Token::CharLiteral(_) => Colour::Green.normal(),
+                    Token::IntLiteral(_) | Token::ByteLiteral(_) | Token::FloatLiteral(_) => {
+                        Colour::Yellow.normal()
+                    }
+                    Token::Operator(_)
+                    | Token::Equals
+                    | Token::Lambda
+                    | Token::Pipe
+                    | Token::RArrow
+                    | Token::DotDot => Colour::Cyan.normal(),
+                    Token::DocComment(_) => Style::new().dimmed(),
+                    _ => Style::new(),
+                }
+            };
+            output.push_str(&line[last_end..range.start]);
+            output.push_str(&style.paint(&line[range.clone()]).to_string());
+            last_end = range.end;
+        }
+        output.push_str(&line[last_end..]);
+        Cow::Owned(output)
     }
 }
 
 impl rustyline::completion::Completer for Completer {
-    type Candidate = String;
+    type Candidate = rustyline::completion::Pair;
 
     fn complete(
         &self,
         line: &str,
         pos: usize,
         _: &rustyline::Context,
-    ) -> rustyline::Result<(usize, Vec<String>)> {
-        let result = complete(&self.thread, "<repl>", line, pos);
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        let suggestions = futures::executor::block_on(complete(&self.thread, "<repl>", line, pos))
+            .unwrap_or_default();
 
         // Get the start of the completed identifier
         let ident_start = line[..pos]
             .rfind(|c: char| c.is_whitespace() || c == '.')
             .map_or(0, |i| i + 1);
-        Ok((ident_start, result.unwrap_or(Vec::new())))
+        Ok((
+            ident_start,
+            suggestions
+                .into_iter()
+                .map(|suggestion| {
+                    let typ = suggestion
+                        .typ
+                        .either(|kind| kind.to_string(), |typ| typ.to_string());
+                    // Types may be split over several lines which does not fit in the list of
+                    // candidates
+                    let typ = typ.split_whitespace().collect::<Vec<_>>().join(" ");
+                    rustyline::completion::Pair {
+                        display: if typ == "_" {
+                            suggestion.name.clone()
+                        } else {
+                            format!("{}: {}", suggestion.name, typ)
+                        },
+                        replacement: suggestion.name,
+                    }
+                })
+                .collect(),
+        ))
     }
 }
 
@@ -314,19 +920,122 @@ fn app_dir_root() -> Result<PathBuf, Box<dyn StdError>> {
     )?)
 }
 
-fn new_editor(vm: WithVM<()>) -> IO<Editor> {
+/// Files and directories which mark the root of a project
+const PROJECT_MARKERS: &[&str] = &[".git", "Cargo.toml"];
+
+/// Returns the root of the project which contains `dir`, if `dir` is inside a project
+fn project_root(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|dir| {
+        PROJECT_MARKERS
+            .iter()
+            .any(|marker| dir.join(marker).exists())
+    })
+}
+
+/// Returns the file which stores the history of the REPL. Each project gets its own history, the
+/// REPL falls back to a history shared by everything else when it is started outside of a project.
+fn history_path() -> Result<PathBuf, Box<dyn StdError>> {
+    let root = app_dir_root()?;
+    let current_dir = std::env::current_dir()?;
+    Ok(match project_root(&current_dir) {
+        Some(project) => {
+            let dir = root.join("projects");
+            std::fs::create_dir_all(&dir)?;
+            let name: String = project
+                .to_string_lossy()
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                .collect();
+            dir.join(name)
+        }
+        None => root.join("history"),
+    })
+}
+
+/// Escapes an entry so that entries spanning multiple lines are stored as a single line
+fn escape_history_entry(entry: &str) -> String {
+    let mut escaped = String::with_capacity(entry.len());
+    for c in entry.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_history_entry(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            entry.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => entry.push('\n'),
+            Some('r') => entry.push('\r'),
+            Some(c) => entry.push(c),
+            None => entry.push('\\'),
+        }
+    }
+    entry
+}
+
+/// Adds `entry` as the most recent entry of `history`, removing any earlier copy of it
+fn add_history_entry(history: &mut History, entry: &str) {
+    if history.iter().any(|existing| existing == entry) {
+        let entries: Vec<String> = history
+            .iter()
+            .filter(|existing| *existing != entry)
+            .cloned()
+            .collect();
+        history.clear();
+        for existing in entries {
+            history.add(existing);
+        }
+    }
+    history.add(entry);
+}
+
+fn load_history(history: &mut History, path: &Path) -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader};
+
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        add_history_entry(history, &unescape_history_entry(&line?));
+    }
+    Ok(())
+}
+
+fn write_history(history: &History, path: &Path) -> std::io::Result<()> {
+    use std::io::{BufWriter, Write};
+
+    let mut writer = BufWriter::new(std::fs::File::create(path)?);
+    for entry in history.iter() {
+        writeln!(writer, "{}", escape_history_entry(entry))?;
+    }
+    writer.flush()
+}
+
+fn new_editor(WithVM { vm, value: color }: WithVM<Color>) -> IO<Editor> {
     let mut editor = rustyline::Editor::new();
 
-    let history_result =
-        app_dir_root().and_then(|path| Ok(editor.load_history(&*path.join("history"))?));
+    let history_result = history_path().and_then(|path| {
+        if path.exists() {
+            load_history(editor.history_mut(), &path)?;
+        }
+        Ok(())
+    });
 
     if let Err(err) = history_result {
         warn!("Unable to load history: {}", err);
     }
     editor.set_helper(Some(Completer {
-        thread: vm.vm.root_thread(),
+        thread: vm.root_thread(),
         hinter: rustyline::hint::HistoryHinter {},
-        highlighter: rustyline::highlight::MatchingBracketHighlighter::default(),
+        highlight_input: color != Color::Never,
     }));
     IO::Value(Editor {
         editor: Mutex::new(editor),
@@ -344,36 +1053,591 @@ fn readline(editor: &Editor, prompt: &str) -> IO<Result<String, ReadlineError>>
         Err(err) => return IO::Exception(format!("{}", err)),
     };
     if !input.trim().is_empty() {
-        editor.add_history_entry(&input);
+        add_history_entry(editor.history_mut(), &input);
     }
 
     IO::Value(Ok(input))
 }
 
+fn emit_error(color: Color, err: GluonError) {
+    let mut stderr = termcolor::StandardStream::stderr(color.into());
+    if let Err(err) = err.emit(&mut stderr) {
+        eprintln!("{}", err);
+    }
+    // Unlike the other errors, messages from the VM do not end with a newline
+    if let GluonError::VM(VMError::Message(_)) = err {
+        eprintln!();
+    }
+}
+
+/// Breakpoints and stepping state shared between the REPL and the hooks of the threads it
+/// evaluates code on
+#[derive(Default)]
+struct DebugState {
+    /// Names of the functions to stop at, either `function` or `module.function`
+    breakpoints: Vec<String>,
+    /// Stop at the next line which is executed
+    stepping: bool,
+    /// The stacktrace of the last evaluation which failed
+    last_failure: Option<String>,
+}
+
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "Debugger")]
+#[gluon_trace(skip)]
+struct Debugger {
+    state: Arc<Mutex<DebugState>>,
+}
+
+impl_userdata! { Debugger }
+
+fn new_debugger(_: ()) -> IO<Debugger> {
+    IO::Value(Debugger {
+        state: Default::default(),
+    })
+}
+
+/// Adds a breakpoint at `name` or lists the breakpoints if `name` is empty
+fn set_breakpoint(debugger: &Debugger, name: &str) -> IO<String> {
+    let mut state = debugger.state.lock().unwrap();
+    let name = name.trim();
+    IO::Value(if name.is_empty() {
+        if state.breakpoints.is_empty() {
+            "No breakpoints are set".to_string()
+        } else {
+            state.breakpoints.join("\n")
+        }
+    } else {
+        state.breakpoints.push(name.to_string());
+        format!("Breakpoint set at `{}`", name)
+    })
+}
+
+/// Stops at the first line of the next evaluation
+fn step(debugger: &Debugger) -> IO<String> {
+    debugger.state.lock().unwrap().stepping = true;
+    IO::Value("Stopping at the first line of the next evaluation".to_string())
+}
+
+fn last_failure(debugger: &Debugger) -> IO<String> {
+    let state = debugger.state.lock().unwrap();
+    IO::Value(
+        state
+            .last_failure
+            .clone()
+            .unwrap_or_else(|| "No evaluation has failed".to_string()),
+    )
+}
+
+/// Counts the instructions executed by the code of a line, excluding the modules which are
+/// imported while compiling it
+#[derive(Default)]
+struct InstructionCounter {
+    executed: AtomicUsize,
+    /// Set once the first instruction of the line itself has been executed
+    started: atomic::AtomicBool,
+}
+
+impl InstructionCounter {
+    fn executed(&self) -> usize {
+        self.executed.load(atomic::Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.executed.store(0, atomic::Ordering::Relaxed);
+        self.started.store(false, atomic::Ordering::Relaxed);
+    }
+}
+
+/// Installs a hook on `thread` which stops at the breakpoints of `debugger`. If
+/// `count_instructions` is set or `fuel` is given the instructions which are executed by the code
+/// from `source` are counted into the returned counter, evaluation is aborted once more than
+/// `fuel` instructions have been executed.
+fn install_hook(
+    thread: &Thread,
+    debugger: &Debugger,
+    source: &str,
+    count_instructions: bool,
+    fuel: Option<usize>,
+) -> Arc<InstructionCounter> {
+    let instructions = Arc::new(InstructionCounter::default());
+    let mut flags = HookFlags::empty();
+    if count_instructions || fuel.is_some() {
+        flags |= HookFlags::INSTRUCTION_FLAG;
+    }
+    {
+        let state = debugger.state.lock().unwrap();
+        if !state.breakpoints.is_empty() || state.stepping {
+            flags |= HookFlags::CALL_FLAG | HookFlags::LINE_FLAG;
+        }
+    }
+    if flags.is_empty() {
+        return instructions;
+    }
+
+    let state = debugger.state.clone();
+    let counter = instructions.clone();
+    let source = source.to_string();
+    let mut context = thread.context();
+    context.set_hook(Some(Box::new(move |thread, debug_info| {
+        if debug_info.state() == HookFlags::INSTRUCTION_FLAG {
+            if !counter.started.load(atomic::Ordering::Relaxed) {
+                // Imports are executed before the line itself, they should not use its fuel
+                let in_source = debug_info
+                    .stack_info(0)
+                    .map_or(false, |frame| frame.source_name() == source);
+                if !in_source {
+                    return Ok(()).into();
+                }
+                counter.started.store(true, atomic::Ordering::Relaxed);
+            }
+            let executed = counter.executed.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+            match fuel {
+                Some(fuel) if executed > fuel => {
+                    return Err(VMError::Message(format!(
+                        "Evaluation ran out of fuel after executing {} instructions",
+                        fuel
+                    )))
+                    .into();
+                }
+                _ => (),
+            }
+        } else if should_stop(&state, &debug_info) {
+            debug_prompt(thread, &state, &debug_info);
+        }
+        Ok(()).into()
+    })));
+    context.set_hook_mask(flags);
+    instructions
+}
+
+fn should_stop(state: &Mutex<DebugState>, debug_info: &DebugInfo) -> bool {
+    let state = state.lock().unwrap();
+    if debug_info.state() == HookFlags::LINE_FLAG {
+        return state.stepping;
+    }
+    let frame = match debug_info.stack_info(0) {
+        Some(frame) => frame,
+        None => return false,
+    };
+    let name = match frame.function_name() {
+        Some(name) => name,
+        None => return false,
+    };
+    let qualified_name = format!("{}.{}", frame.source_name(), name);
+    state
+        .breakpoints
+        .iter()
+        .any(|breakpoint| *breakpoint == name || *breakpoint == qualified_name)
+}
+
+fn frame_location(frame: &vm::thread::StackInfo) -> String {
+    let name = frame.function_name().unwrap_or("<unknown>");
+    match frame.line() {
+        Some(line) => format!("{} ({}:{})", name, frame.source_name(), line.number()),
+        None => name.to_string(),
+    }
+}
+
+/// Reads debugger commands from stdin until evaluation should continue
+fn debug_prompt(thread: &Thread, state: &Mutex<DebugState>, debug_info: &DebugInfo) {
+    use std::io::{BufRead, Write};
+
+    let frame = match debug_info.stack_info(0) {
+        Some(frame) => frame,
+        None => return,
+    };
+    println!("Stopped at {}", frame_location(&frame));
+
+    let stdin = std::io::stdin();
+    let mut input = String::new();
+    loop {
+        print!("debug> ");
+        let _ = std::io::stdout().flush();
+
+        input.clear();
+        let stepping = match stdin.lock().read_line(&mut input) {
+            Ok(0) | Err(_) => false,
+            Ok(_) => match input.trim() {
+                ":step" | ":s" => true,
+                ":continue" | ":c" => false,
+                ":locals" | ":l" => {
+                    print_locals(thread, &frame);
+                    continue;
+                }
+                ":backtrace" | ":bt" => {
+                    for level in 0..debug_info.stack_info_len() {
+                        let frame = debug_info.stack_info(level).unwrap();
+                        println!("{}: {}", level, frame_location(&frame));
+                    }
+                    continue;
+                }
+                _ => {
+                    println!(
+                        "Commands: :step (:s), :continue (:c), :locals (:l), :backtrace (:bt)"
+                    );
+                    continue;
+                }
+            },
+        };
+        state.lock().unwrap().stepping = stepping;
+        return;
+    }
+}
+
+fn print_locals(thread: &Thread, frame: &vm::thread::StackInfo) {
+    let env = thread.get_env();
+    let debug_level = thread.global_env().get_debug_level();
+    for local in frame.locals() {
+        match frame.local_value(local) {
+            Some(value) => println!(
+                "{} : {} = {}",
+                local.name.declared_name(),
+                local.typ,
+                ValuePrinter::new(&env, &local.typ, value, &debug_level)
+                    .width(80)
+                    .max_level(3)
+            ),
+            None => println!("{} : {}", local.name.declared_name(), local.typ),
+        }
+    }
+}
+
+/// Remembers the stacktrace of `err`, if it has one, so that `:backtrace` can show it
+fn record_failure(debugger: &Debugger, err: &GluonError) {
+    if let GluonError::VM(VMError::Panic(_, Some(stacktrace))) = err {
+        debugger.state.lock().unwrap().last_failure = Some(stacktrace.to_string());
+    }
+}
+
+/// Evaluates `line`, returning `true` if it is a binding which was evaluated successfully. If
+/// `settings.time` is set the time and the number of instructions it took is printed afterwards,
+/// if `settings.memory` is set the number of bytes that were allocated is printed.
 fn eval_line(
-    De(color): De<crate::Color>,
+    settings: Settings,
+    debugger: &Debugger,
     WithVM { vm, value: line }: WithVM<&str>,
-) -> impl Future<Output = IO<()>> {
+) -> impl Future<Output = IO<bool>> {
     let vm = vm.new_thread().unwrap(); // TODO Reuse the current thread
     let line = line.to_string();
+    let debugger = Debugger {
+        state: debugger.state.clone(),
+    };
     async move {
-        eval_line_(vm.root_thread(), &line)
-            .map(move |result| match result {
-                Ok(x) => IO::Value(x),
+        let instructions = install_hook(&vm, &debugger, "line", settings.time, settings.fuel);
+        let allocated_before = vm.total_allocated_memory();
+        let start = Instant::now();
+
+        let result = eval_line_(vm.root_thread(), &settings, "line", &line).await;
+        debugger.state.lock().unwrap().stepping = false;
+        match result {
+            Ok(is_binding) => {
+                if settings.time {
+                    println!(
+                        "Time: {:?}, instructions: {}",
+                        start.elapsed(),
+                        instructions.executed()
+                    );
+                }
+                if settings.memory {
+                    println!(
+                        "Allocated: {} bytes",
+                        vm.total_allocated_memory() - allocated_before
+                    );
+                }
+                IO::Value(is_binding)
+            }
+            Err(err) => {
+                record_failure(&debugger, &err);
+                emit_error(settings.color, err);
+                IO::Value(false)
+            }
+        }
+    }
+}
+
+/// Evaluates the top level bindings of the file at `path` one at a time, as if they were entered
+/// in the REPL. Bindings which fail are reported and skipped. Returns the source of each binding
+/// which could be evaluated.
+fn load_file(
+    settings: Settings,
+    debugger: &Debugger,
+    WithVM { vm, value: path }: WithVM<&str>,
+) -> impl Future<Output = IO<Vec<String>>> {
+    let vm = vm.new_thread().unwrap(); // TODO Reuse the current thread
+    let path = path.to_string();
+    let debugger = Debugger {
+        state: debugger.state.clone(),
+    };
+    async move {
+        let instructions = install_hook(&vm, &debugger, &path, false, settings.fuel);
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => return IO::Exception(format!("Unable to read `{}`: {}", path, err)),
+        };
+
+        let mut loaded = Vec::new();
+        for item in split_bindings(&source, true) {
+            let (line, binding) = match item {
+                Ok(binding) => binding,
+                Err((line, msg)) => {
+                    eprintln!("{}:{}: {}", path, line + 1, msg);
+                    continue;
+                }
+            };
+            // Pad with the preceding lines so that errors point into the file
+            let padded = format!("{}{}", "\n".repeat(line), binding);
+            // Each binding gets its own fuel
+            instructions.reset();
+            match eval_line_(vm.root_thread(), &settings, &path, &padded).await {
+                Ok(true) => loaded.push(binding.to_string()),
+                Ok(false) => (),
                 Err(err) => {
-                    let mut stderr = termcolor::StandardStream::stderr(color.into());
-                    if let Err(err) = err.emit(&mut stderr) {
-                        eprintln!("{}", err);
+                    record_failure(&debugger, &err);
+                    emit_error(settings.color, err)
+                }
+            }
+        }
+        IO::Value(loaded)
+    }
+}
+
+/// Splits a module into the source of each of its top level bindings and the line they start
+/// at. The body of the module is included as well unless `skip_exports` is set and it is a
+/// record, which is then assumed to export the bindings.
+pub(crate) fn split_bindings(
+    source: &str,
+    skip_exports: bool,
+) -> Vec<Result<(usize, &str), (usize, String)>> {
+    mk_ast_arena!(arena);
+    let mut symbols = Symbols::new();
+    let mut module = SymbolModule::new("load".into(), &mut symbols);
+    let expr = match crate::parser::parse_partial_expr(
+        (*arena).borrow(),
+        &mut module,
+        &Default::default(),
+        source,
+    ) {
+        Ok(expr) | Err((Some(expr), _)) => expr,
+        Err((None, err)) => return vec![Err((0, err.to_string()))],
+    };
+
+    let to_index = |pos: pos::BytePos| (pos.to_usize() - 1).min(source.len());
+    let to_line = |pos: pos::BytePos| source[..to_index(pos)].matches('\n').count();
+
+    let mut bindings = Vec::new();
+    let mut expr = &expr;
+    loop {
+        match &expr.value {
+            Expr::LetBindings(binds, body) => {
+                let line = to_line(expr.span.start());
+                bindings.push(match binds {
+                    ast::ValueBindings::Plain(bind) => Ok((
+                        line,
+                        &source[to_index(expr.span.start())..to_index(bind.expr.span.end())],
+                    )),
+                    ast::ValueBindings::Recursive(_) => Err((
+                        line,
+                        "Recursive bindings can't be evaluated one at a time".to_string(),
+                    )),
+                });
+                expr = body;
+            }
+            Expr::TypeBindings(binds, body) => {
+                bindings.push(Err((
+                    to_line(expr.span.start()),
+                    format!(
+                        "Types can't be defined in the REPL, skipping `{}`",
+                        binds[0].name.value.declared_name()
+                    ),
+                )));
+                expr = body;
+            }
+            Expr::Record { .. } if skip_exports => break,
+            _ => {
+                bindings.push(Ok((
+                    to_line(expr.span.start()),
+                    &source[to_index(expr.span.start())..to_index(expr.span.end())],
+                )));
+                break;
+            }
+        }
+    }
+    bindings
+}
+
+/// Returns the variables bound by `binding`, or nothing if it is not a `let` binding
+fn bound_names(binding: &str) -> Vec<String> {
+    fn pattern_variables<'a>(pattern: &'a SpannedPattern<Symbol>, names: &mut Vec<&'a str>) {
+        match &pattern.value {
+            Pattern::Ident(id) => names.push(id.name.declared_name()),
+            Pattern::As(id, pattern) => {
+                names.push(id.value.declared_name());
+                pattern_variables(pattern, names);
+            }
+            Pattern::Constructor(_, args) => {
+                for arg in args.iter() {
+                    pattern_variables(arg, names);
+                }
+            }
+            Pattern::Tuple { elems, .. } => {
+                for elem in elems.iter() {
+                    pattern_variables(elem, names);
+                }
+            }
+            Pattern::Record { fields, .. } => {
+                for (name, value) in ast::pattern_values(fields) {
+                    match value {
+                        Some(pattern) => pattern_variables(pattern, names),
+                        None => names.push(name.value.declared_name()),
                     }
-                    IO::Value(())
                 }
-            })
-            .await
+            }
+            Pattern::Literal(_) | Pattern::Error => (),
+        }
+    }
+
+    mk_ast_arena!(arena);
+    let mut symbols = Symbols::new();
+    let mut module = SymbolModule::new("binding".into(), &mut symbols);
+    match parse_partial_repl_line((*arena).borrow(), &mut module, binding) {
+        Ok(Some(ReplLine::Let(bind))) => {
+            let mut names = Vec::new();
+            pattern_variables(&bind.name, &mut names);
+            names.into_iter().map(|name| name.to_string()).collect()
+        }
+        _ => Vec::new(),
     }
 }
 
-async fn eval_line_(vm: RootedThread, line: &str) -> gluon::Result<()> {
+/// Writes `bindings` to `path` as a module which exports every variable they bind
+fn save_session(path: &str, bindings: Vec<String>) -> IO<Result<(), String>> {
+    let mut names = Vec::new();
+    for binding in &bindings {
+        for name in bound_names(binding) {
+            // A name which is bound again shadows the earlier binding
+            names.retain(|n: &String| *n != name);
+            names.push(name);
+        }
+    }
+
+    let mut module = String::new();
+    for binding in &bindings {
+        module.push_str(binding.trim_end());
+        module.push('\n');
+    }
+    module.push_str(&format!("{{ {} }}\n", names.join(", ")));
+
+    IO::Value(std::fs::write(path, module).map_err(|err| err.to_string()))
+}
+
+/// Returns the last of `bindings` which binds `name`
+fn find_binding<'a>(name: &str, bindings: &'a [String]) -> Option<&'a str> {
+    bindings
+        .iter()
+        .rev()
+        .find(|binding| bound_names(binding).iter().any(|bound| bound == name))
+        .map(|binding| &binding[..])
+}
+
+/// Opens the source of the binding of `name` in the editor given by `$VISUAL` or `$EDITOR`. If
+/// `name` has not been bound in this session a new binding is opened instead. Returns the edited
+/// source.
+fn edit_binding(name: &str, bindings: Vec<String>) -> IO<Result<String, String>> {
+    let name = name.trim();
+    let source = match find_binding(name, &bindings) {
+        Some(binding) => binding.to_string(),
+        None if name.is_empty() => String::new(),
+        None => format!("let {} = \n", name),
+    };
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    IO::Value(run_editor(&editor, &source).and_then(|edited| {
+        if edited.trim() == source.trim() {
+            Err(format!("`{}` was not changed", name))
+        } else {
+            Ok(edited.trim_end().to_string())
+        }
+    }))
+}
+
+/// Runs `editor` on a temporary file containing `source` and returns the contents of the file
+/// once the editor exits
+fn run_editor(editor: &str, source: &str) -> Result<String, String> {
+    let path = std::env::temp_dir().join(format!("gluon_repl_edit_{}.glu", std::process::id()));
+    std::fs::write(&path, source)
+        .map_err(|err| format!("Unable to write `{}`: {}", path.display(), err))?;
+
+    // Let the editor be given with arguments such as `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().ok_or_else(|| "No editor is set".to_string())?;
+    let status = std::process::Command::new(program)
+        .args(args)
+        .arg(&path)
+        .status()
+        .map_err(|err| format!("Unable to start `{}`: {}", editor, err));
+
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let status = status?;
+    if !status.success() {
+        return Err(format!("`{}` exited with {}", editor, status));
+    }
+    edited.map_err(|err| format!("Unable to read `{}`: {}", path.display(), err))
+}
+
+async fn eval_line_(
+    vm: RootedThread,
+    settings: &Settings,
+    name: &str,
+    line: &str,
+) -> gluon::Result<bool> {
+    let evaluated = match eval_repl_line(vm, name, line).await? {
+        Some(evaluated) => evaluated,
+        None => return Ok(false),
+    };
+    if settings.trace_implicits {
+        for implicit in &evaluated.implicits {
+            println!("Implicit argument: {}", implicit);
+        }
+    }
+    let EvaluatedLine { value, typ, .. } = &evaluated;
+    let vm = value.vm();
+    let env = vm.get_env();
+    let debug_level = vm.global_env().get_debug_level();
+    let mut printer = ValuePrinter::new(&env, typ, value.get_variant(), &debug_level);
+    printer.width(settings.width).max_level(5);
+    if settings.show_types {
+        println!("{} : {}", printer, typ);
+    } else {
+        println!("{}", printer);
+    }
+    Ok(evaluated.is_binding)
+}
+
+/// A line which has been evaluated by `eval_repl_line`
+pub(crate) struct EvaluatedLine {
+    pub(crate) value: RootedValue<RootedThread>,
+    pub(crate) typ: ArcType,
+    /// Set if the line is a `let` binding, the variables it binds are globals afterwards
+    pub(crate) is_binding: bool,
+    /// The implicit arguments which were inserted into the line
+    pub(crate) implicits: Vec<String>,
+}
+
+/// Evaluates `line` as it would be in the REPL. Returns `None` if the line does not contain any
+/// code.
+pub(crate) async fn eval_repl_line(
+    vm: RootedThread,
+    name: &str,
+    line: &str,
+) -> gluon::Result<Option<EvaluatedLine>> {
     let mut is_let_binding = false;
+    let start;
     let mut eval_expr;
     let value = {
         let mut db = vm.get_database();
@@ -383,9 +1647,10 @@ async fn eval_line_(vm: RootedThread, line: &str) -> gluon::Result<()> {
                 mk_ast_arena!(arena);
                 let repl_line = {
                     let result = {
-                        let filemap = vm.get_database().add_filemap("line", line);
+                        let filemap = vm.get_database().add_filemap(name, line);
+                        start = filemap.span().start();
                         let mut module =
-                            SymbolModule::new("line".into(), module_compiler.mut_symbols());
+                            SymbolModule::new(name.into(), module_compiler.mut_symbols());
                         parse_partial_repl_line((*arena).borrow(), &mut module, &*filemap)
                     };
                     match result {
@@ -397,7 +1662,7 @@ async fn eval_line_(vm: RootedThread, line: &str) -> gluon::Result<()> {
                     }
                 };
                 match repl_line {
-                    None => return Ok(()),
+                    None => return Ok(None),
                     Some(ReplLine::Expr(expr)) => RootExpr::new(arena.clone(), arena.alloc(expr)),
                     Some(ReplLine::Let(let_binding)) => {
                         is_let_binding = true;
@@ -438,7 +1703,7 @@ async fn eval_line_(vm: RootedThread, line: &str) -> gluon::Result<()> {
         };
 
         (&mut eval_expr)
-            .run_expr(&mut module_compiler, vm.clone(), "line", line, None)
+            .run_expr(&mut module_compiler, vm.clone(), name, line, None)
             .await?
     };
     let ExecuteValue { value, typ, .. } = value;
@@ -463,16 +1728,18 @@ async fn eval_line_(vm: RootedThread, line: &str) -> gluon::Result<()> {
             &value.as_ref(),
         )?;
     }
-    let vm = value.vm();
-    let env = vm.get_env();
-    let debug_level = vm.global_env().get_debug_level();
-    println!(
-        "{}",
-        ValuePrinter::new(&env, &typ, value.get_variant(), &debug_level)
-            .width(80)
-            .max_level(5)
-    );
-    Ok(())
+    let implicits = {
+        let env = vm.get_env();
+        let mut elaborate = Elaborate::new(&env, line, start);
+        ast::Visitor::visit_expr(&mut elaborate, eval_expr.expr());
+        elaborate.implicits
+    };
+    Ok(Some(EvaluatedLine {
+        value,
+        typ,
+        is_binding: is_let_binding,
+        implicits,
+    }))
 }
 
 fn set_globals(
@@ -594,20 +1861,13 @@ fn finish_or_interrupt(
 }
 
 fn save_history(editor: &Editor) -> IO<()> {
-    let history_result = app_dir_root().and_then(|path| {
-        editor
-            .editor
-            .lock()
-            .unwrap()
-            .save_history(&*path.join("history"))
-            .map_err(|err| {
-                let err: Box<dyn StdError> = Box::new(err);
-                err
-            })
+    let history_result = history_path().and_then(|path| {
+        let editor = editor.editor.lock().unwrap();
+        Ok(write_history(editor.history(), &path)?)
     });
 
     if let Err(err) = history_result {
-        warn!("Unable to load history: {}", err);
+        warn!("Unable to save history: {}", err);
     }
     IO::Value(())
 }
@@ -626,24 +1886,137 @@ fn load_rustyline(vm: &Thread) -> vm::Result<vm::ExternModule> {
     )
 }
 
-#[derive(VmType, Pushable, Getable)]
-struct Settings<'a> {
+#[derive(Clone, VmType, Pushable, Getable)]
+struct Settings {
     color: Color,
-    prompt: &'a str,
+    prompt: String,
+    time: bool,
+    memory: bool,
+    /// The width at which printed values are wrapped
+    width: usize,
+    /// Prints the type of each value after it
+    show_types: bool,
+    /// Prints the implicit arguments which were inserted into each line before evaluating it
+    trace_implicits: bool,
+    /// The maximum number of instructions each line may execute
+    fuel: Option<usize>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            color: Color::default(),
+            prompt: "> ".to_string(),
+            time: false,
+            memory: false,
+            width: 80,
+            show_types: false,
+            trace_implicits: false,
+            fuel: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Sets the option `name` to `value`
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        fn parse_toggle(value: &str) -> Result<bool, String> {
+            match value {
+                "on" | "true" => Ok(true),
+                "off" | "false" => Ok(false),
+                _ => Err(format!("Expected `on` or `off`, found `{}`", value)),
+            }
+        }
+        match name {
+            "width" => {
+                self.width = value
+                    .parse()
+                    .map_err(|_| format!("Expected a width, found `{}`", value))?
+            }
+            "types" => self.show_types = parse_toggle(value)?,
+            "color" => self.color = value.parse()?,
+            "implicits" => self.trace_implicits = parse_toggle(value)?,
+            "fuel" => {
+                self.fuel = match value {
+                    "off" => None,
+                    _ => Some(value.parse().map_err(|_| {
+                        format!(
+                            "Expected a number of instructions or `off`, found `{}`",
+                            value
+                        )
+                    })?),
+                }
+            }
+            "time" => self.time = parse_toggle(value)?,
+            "memory" => self.memory = parse_toggle(value)?,
+            _ => return Err(format!("Unknown option `{}`", name)),
+        }
+        Ok(())
+    }
+
+    fn show(&self) -> String {
+        let toggle = |enabled| if enabled { "on" } else { "off" };
+        let color = match self.color {
+            Color::Auto => "auto",
+            Color::Always => "always",
+            Color::AlwaysAnsi => "always-ansi",
+            Color::Never => "never",
+        };
+        let fuel = self
+            .fuel
+            .map_or_else(|| "off".to_string(), |fuel| fuel.to_string());
+        format!(
+            "width = {}\ntypes = {}\ncolor = {}\nimplicits = {}\nfuel = {}\ntime = {}\nmemory = {}",
+            self.width,
+            toggle(self.show_types),
+            color,
+            toggle(self.trace_implicits),
+            fuel,
+            toggle(self.time),
+            toggle(self.memory),
+        )
+    }
+}
+
+/// Applies a `:set` command. `OPTION VALUE` changes an option while an empty argument prints the
+/// value of every option.
+fn set_option(mut settings: Settings, arg: &str) -> IO<Result<Settings, String>> {
+    let mut words = arg.split_whitespace();
+    IO::Value(match (words.next(), words.next(), words.next()) {
+        (None, _, _) => {
+            println!("{}", settings.show());
+            Ok(settings)
+        }
+        (Some(name), Some(value), None) => settings.set(name, value).map(|()| settings),
+        _ => Err("Expected `:set OPTION VALUE`".to_string()),
+    })
 }
 
 fn load_repl(vm: &Thread) -> vm::Result<vm::ExternModule> {
+    vm.register_type::<Debugger>("Debugger", &[])?;
+
     vm::ExternModule::new(
         vm,
         record!(
             type Color => Color,
-            type Settings => Settings<'static>,
+            type Settings => Settings,
             type_of_expr => primitive!(1, async fn type_of_expr),
+            elaborate_expr => primitive!(1, async fn elaborate_expr),
             find_info => primitive!(1, find_info),
+            find_doc => primitive!(1, find_doc),
             find_kind => primitive!(1, find_kind),
             parse_color => primitive!(1, "parse_color", |s: &str| s.parse::<Color>()),
             switch_debug_level => primitive!(1, switch_debug_level),
-            eval_line => primitive!(2, async fn eval_line),
+            set_option => primitive!(2, set_option),
+            eval_line => primitive!(3, async fn eval_line),
+            load_file => primitive!(3, async fn load_file),
+            type Debugger => Debugger,
+            new_debugger => primitive!(1, new_debugger),
+            set_breakpoint => primitive!(2, set_breakpoint),
+            step => primitive!(1, step),
+            last_failure => primitive!(1, last_failure),
+            save_session => primitive!(2, save_session),
+            edit_binding => primitive!(2, edit_binding),
             finish_or_interrupt => primitive!(2, async fn finish_or_interrupt),
         ),
     )
@@ -681,10 +2054,14 @@ pub async fn run(
 
     let mut repl: OwnedFunction<fn(_) -> _> = vm.get_global("repl")?;
     debug!("Starting repl");
-    repl.call_async(Settings { color, prompt })
-        .await
-        .map(|_: IO<()>| ())
-        .map_err(|err| err.into())
+    repl.call_async(Settings {
+        color,
+        prompt: prompt.to_string(),
+        ..Settings::default()
+    })
+    .await
+    .map(|_: IO<()>| ())
+    .map_err(|err| err.into())
 }
 
 #[cfg(test)]
@@ -692,7 +2069,6 @@ mod tests {
     use super::*;
 
     use crate::vm::api::{FunctionRef, IO};
-    use gluon::import::Import;
     use gluon::{self, RootedThread};
 
     async fn new_vm() -> RootedThread {
@@ -716,7 +2092,7 @@ mod tests {
         compile_repl(&vm)
             .await
             .unwrap_or_else(|err| panic!("{}", err));
-        let repl: Result<FunctionRef<fn(Settings<'static>) -> IO<()>>, _> = vm.get_global("repl");
+        let repl: Result<FunctionRef<fn(Settings) -> IO<()>>, _> = vm.get_global("repl");
         assert!(repl.is_ok(), "{}", repl.err().unwrap());
     }
 
@@ -729,18 +2105,28 @@ mod tests {
             .unwrap_or_else(|err| panic!("{}", err));
 
         // pattern with field names out of order
-        eval_line_(vm.clone(), r#"let {y, x} = {x = "x", y = "y"}"#)
-            .await
-            .expect("Error evaluating let binding");
+        eval_line_(
+            vm.clone(),
+            &Settings::default(),
+            "line",
+            r#"let {y, x} = {x = "x", y = "y"}"#,
+        )
+        .await
+        .expect("Error evaluating let binding");
         let x: String = vm.get_global("x").expect("Error getting x");
         assert_eq!(x, "x");
         let y: String = vm.get_global("y").expect("Error getting y");
         assert_eq!(y, "y");
 
         // pattern with field names out of order and different field types
-        eval_line_(vm.clone(), r#"let {y} = {x = "x", y = ()}"#)
-            .await
-            .expect("Error evaluating let binding 2");
+        eval_line_(
+            vm.clone(),
+            &Settings::default(),
+            "line",
+            r#"let {y} = {x = "x", y = ()}"#,
+        )
+        .await
+        .expect("Error evaluating let binding 2");
         let () = vm.get_global("y").expect("Error getting y");
     }
 
@@ -774,6 +2160,34 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn elaborate_expr() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        compile_repl(&vm)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        let mut elaborate_expr: FunctionRef<QueryFn> =
+            vm.get_global("repl.prim.elaborate_expr").unwrap();
+        assert_eq!(
+            elaborate_expr.call_async("1 + 2").await,
+            Ok(IO::Value(Ok(r#"(+) ?std.int.num 1 2 : Int
+
+Implicit arguments:
+    + : std.num.Num Int = std.int.num"#
+                .into())))
+        );
+        assert_eq!(
+            elaborate_expr.call_async("show (Some 1) == \"\"").await,
+            Ok(IO::Value(Ok(r#"(==) ?std.string.eq (show ?(std.option.show ?std.int.show) (Some 1)) "" : std.types.Bool
+
+Implicit arguments:
+    == : std.cmp.Eq String = std.string.eq
+    show : std.show.Show (std.types.Option Int) = (std.option.show ?std.int.show)"#
+                .into())))
+        );
+    }
+
     #[tokio::test]
     async fn find_info() {
         let _ = env_logger::try_init();
@@ -796,6 +2210,62 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn find_info_of_type() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        compile_repl(&vm)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        vm.typecheck_str_async("<test>", "import! std.list", None)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let mut find_info: FunctionRef<QueryFn> = vm.get_global("repl.prim.find_info").unwrap();
+        let info = match find_info.call_async("std.list.List").await {
+            Ok(IO::Value(Ok(info))) => info,
+            x => panic!("{:?}", x),
+        };
+        assert!(
+            info.contains("\n\nConstructors:\n    Nil : List a\n    Cons : a -> List a -> List a"),
+            "{}",
+            info
+        );
+        assert!(info.contains("\n\nDefined at std.list:"), "{}", info);
+
+        let info = match find_info.call_async("std.list.of").await {
+            Ok(IO::Value(Ok(info))) => info,
+            x => panic!("{:?}", x),
+        };
+        assert!(info.starts_with("std.list.of: "), "{}", info);
+        assert!(info.contains("\n/// "), "{}", info);
+        assert!(info.contains("\n\nDefined at std.list:"), "{}", info);
+    }
+
+    #[tokio::test]
+    async fn find_doc() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        compile_repl(&vm)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        vm.typecheck_str_async("<test>", "import! std.list", None)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        let mut find_doc: FunctionRef<QueryFn> = vm.get_global("repl.prim.find_doc").unwrap();
+        match find_doc.call_async("std.list.of").await {
+            Ok(IO::Value(Ok(doc))) => assert!(!doc.is_empty() && !doc.starts_with("///")),
+            x => panic!("{:?}", x),
+        }
+        assert_eq!(
+            find_doc.call_async("std.list.does_not_exist").await,
+            Ok(IO::Value(Err(
+                "Undefined name `std.list.does_not_exist`".into()
+            )))
+        );
+    }
+
     #[tokio::test]
     async fn complete_repl_empty() {
         let _ = env_logger::try_init();
@@ -803,6 +2273,262 @@ mod tests {
         compile_repl(&vm)
             .await
             .unwrap_or_else(|err| panic!("{}", err));
-        complete(&vm, "<repl>", "", 0).unwrap_or_else(|err| panic!("{}", err));
+        complete(&vm, "<repl>", "", 0)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    #[tokio::test]
+    async fn incomplete_input() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+
+        for line in &[
+            "let x =",
+            "let f x =\n    let y = x",
+            "do x = io.read_line\n",
+            "1 +",
+            "(1, 2",
+            "{ x = 1",
+            "[1, 2",
+            "if True then",
+            "match x with",
+            "\\x ->",
+        ] {
+            assert!(is_incomplete_input(&vm, line), "{:?}", line);
+        }
+
+        for line in &[
+            "1 + 2",
+            "let x = 1",
+            "let f x =\n    x",
+            "1 )",
+            "1 + + 2",
+            ":t 1 +",
+            "",
+        ] {
+            assert!(!is_incomplete_input(&vm, line), "{:?}", line);
+        }
+    }
+
+    fn suggestion_names(suggestions: Vec<completion::Suggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|s| s.name).collect()
+    }
+
+    #[tokio::test]
+    async fn complete_module_members() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        let line = ":i std.list.";
+        let names = suggestion_names(
+            complete(&vm, "<repl>", line, line.len())
+                .await
+                .unwrap_or_else(|err| panic!("{}", err)),
+        );
+        assert!(names.iter().any(|name| name == "of"), "{:?}", names);
+
+        let line = ":i std.li";
+        let names = suggestion_names(
+            complete(&vm, "<repl>", line, line.len())
+                .await
+                .unwrap_or_else(|err| panic!("{}", err)),
+        );
+        assert!(names.iter().any(|name| name == "list"), "{:?}", names);
+    }
+
+    #[tokio::test]
+    async fn complete_record_fields() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        eval_line_(
+            vm.clone(),
+            &Settings::default(),
+            "line",
+            "let record = { pi = 3.14, count = 1 }",
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+
+        let line = "record.p";
+        let suggestions = complete(&vm, "<repl>", line, line.len())
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(suggestions.len(), 1, "{:?}", suggestions);
+        assert_eq!(suggestions[0].name, "pi");
+        assert_eq!(
+            suggestions[0]
+                .typ
+                .as_ref()
+                .either(|kind| kind.to_string(), |typ| typ.to_string()),
+            "Float"
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_in_unclosed_brackets() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        eval_line_(
+            vm.clone(),
+            &Settings::default(),
+            "line",
+            "let record = { pi = 3.14, count = 1 }",
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+
+        let line = "[1.0, (record.p";
+        let names = suggestion_names(
+            complete(&vm, "<repl>", line, line.len())
+                .await
+                .unwrap_or_else(|err| panic!("{}", err)),
+        );
+        assert_eq!(names, ["pi"]);
+    }
+
+    #[tokio::test]
+    async fn complete_import_path() {
+        let _ = env_logger::try_init();
+        let vm = new_vm().await;
+        let line = "import! std.li";
+        let names = suggestion_names(
+            complete(&vm, "<repl>", line, line.len())
+                .await
+                .unwrap_or_else(|err| panic!("{}", err)),
+        );
+        assert!(names.iter().any(|name| name == "list"), "{:?}", names);
+    }
+
+    #[test]
+    fn split_module_into_bindings() {
+        let source = r#"
+let x = 1
+type T = Int
+let f y =
+    x + y
+{ x, f }
+"#;
+        assert_eq!(
+            split_bindings(source, true),
+            vec![
+                Ok((1, "let x = 1")),
+                Err((
+                    2,
+                    "Types can't be defined in the REPL, skipping `T`".to_string()
+                )),
+                Ok((3, "let f y =\n    x + y")),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_options() {
+        let mut settings = Settings::default();
+        settings.set("width", "120").unwrap();
+        settings.set("types", "on").unwrap();
+        settings.set("color", "never").unwrap();
+        settings.set("fuel", "1000").unwrap();
+        assert_eq!(settings.width, 120);
+        assert!(settings.show_types);
+        assert_eq!(settings.color, Color::Never);
+        assert_eq!(settings.fuel, Some(1000));
+
+        settings.set("fuel", "off").unwrap();
+        assert_eq!(settings.fuel, None);
+        assert_eq!(
+            settings.set("types", "maybe"),
+            Err("Expected `on` or `off`, found `maybe`".to_string())
+        );
+        assert_eq!(
+            settings.set("colour", "never"),
+            Err("Unknown option `colour`".to_string())
+        );
+    }
+
+    #[test]
+    fn history_keeps_multi_line_entries_and_removes_duplicates() {
+        let path = std::env::temp_dir().join("gluon_repl_history");
+        let mut history = History::new();
+        add_history_entry(&mut history, "let f x =\n    x \\ 2");
+        add_history_entry(&mut history, "f 1");
+        add_history_entry(&mut history, "let f x =\n    x \\ 2");
+        write_history(&history, &path).unwrap();
+
+        let mut loaded = History::new();
+        load_history(&mut loaded, &path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            ["f 1", "let f x =\n    x \\ 2"]
+        );
+    }
+
+    #[test]
+    fn save_session_exports_bindings() {
+        let path = std::env::temp_dir().join("gluon_repl_save_session.glu");
+        let path = path.to_str().unwrap();
+        let bindings = vec![
+            "let x = 1".to_string(),
+            "let { y, z = w } = { y = 2, z = 3 }".to_string(),
+            "let x = 4".to_string(),
+        ];
+        match save_session(path, bindings) {
+            IO::Value(Ok(())) => (),
+            _ => panic!("Unable to save the session"),
+        }
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "let x = 1\nlet { y, z = w } = { y = 2, z = 3 }\nlet x = 4\n{ y, w, x }\n"
+        );
+    }
+
+    #[test]
+    fn match_brackets() {
+        let line = "f (a [1, 2]) \")\"";
+        let tokens = tokenize_line(line);
+        let bracket_at = |pos| find_matching_bracket(&tokens, pos).map(|i| tokens[i].0.start);
+        assert_eq!(bracket_at(2), Some(11));
+        assert_eq!(bracket_at(12), Some(2));
+        assert_eq!(bracket_at(5), Some(10));
+        assert_eq!(bracket_at(0), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn highlight_keywords_and_literals() {
+        use ansi_term::Colour;
+
+        let highlighted = highlight_line("let x = 1", 0);
+        assert_eq!(
+            highlighted,
+            format!(
+                "{} x {} {}",
+                Colour::Purple.paint("let"),
+                Colour::Cyan.paint("="),
+                Colour::Yellow.paint("1")
+            )
+        );
+    }
+
+    #[test]
+    fn find_last_binding_of_name() {
+        let bindings = vec![
+            "let x = 1".to_string(),
+            "let f y = x + y".to_string(),
+            "let { x } = { x = 2 }".to_string(),
+        ];
+        assert_eq!(find_binding("x", &bindings), Some("let { x } = { x = 2 }"));
+        assert_eq!(find_binding("f", &bindings), Some("let f y = x + y"));
+        assert_eq!(find_binding("y", &bindings), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_editor_returns_edited_source() {
+        assert_eq!(
+            run_editor("sed -i s/1/2/", "let x = 1\n"),
+            Ok("let x = 2\n".to_string())
+        );
+        assert!(run_editor("false", "let x = 1").is_err());
     }
 }
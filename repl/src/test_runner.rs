@@ -0,0 +1,703 @@
+//! Implementation of `gluon test`, which runs the `std.test.TestCase` values that gluon files
+//! evaluate to and reports the results.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use futures::prelude::*;
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+use gluon_doc::doctest;
+
+use gluon::{
+    base::{
+        filename_to_module,
+        source::FileMap,
+        types::{ArcType, Type},
+    },
+    new_vm_async,
+    vm::coverage::CoverageReport,
+    vm::{
+        api::{de::De, generic::A, Getable, Hole, OpaqueValue, OwnedFunction, VmType, IO},
+        Error as VMError,
+    },
+    RootedThread, Thread, ThreadExt,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReportFormat {
+    Pretty,
+    Junit,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "pretty" => ReportFormat::Pretty,
+            "junit" => ReportFormat::Junit,
+            "json" => ReportFormat::Json,
+            _ => return Err("Expected one of 'pretty', 'junit', 'json'"),
+        })
+    }
+}
+
+#[derive(StructOpt)]
+#[structopt(about = "Runs gluon tests")]
+pub struct TestOpt {
+    #[structopt(
+        name = "PATH",
+        parse(from_os_str),
+        help = "Test files or directories to search for test files. Defaults to `tests`"
+    )]
+    input: Vec<PathBuf>,
+
+    #[structopt(
+        long = "filter",
+        short = "f",
+        help = "Only runs tests whose name contains FILTER. May be given multiple times"
+    )]
+    filter: Vec<String>,
+
+    #[structopt(
+        long = "tag",
+        short = "t",
+        help = "Only runs tests which have been tagged with TAG. May be given multiple times"
+    )]
+    tag: Vec<String>,
+
+    #[structopt(
+        long = "jobs",
+        short = "j",
+        help = "How many test files to run in parallel. Defaults to the number of CPUs"
+    )]
+    jobs: Option<usize>,
+
+    #[structopt(
+        long = "format",
+        default_value = "pretty",
+        help = "Report format: pretty, junit, json"
+    )]
+    format: ReportFormat,
+
+    #[structopt(
+        long = "output",
+        short = "o",
+        parse(from_os_str),
+        help = "Writes the junit or json report to FILE instead of stdout"
+    )]
+    output: Option<PathBuf>,
+
+    #[structopt(
+        long = "doc",
+        help = "Runs the examples in the documentation comments of the files instead of the tests \
+                the files evaluate to"
+    )]
+    doc: bool,
+
+    #[structopt(
+        long = "coverage",
+        help = "Counts how often each line of the tested code is executed and writes the counts to \
+                an lcov report"
+    )]
+    coverage: bool,
+
+    #[structopt(
+        long = "coverage-output",
+        parse(from_os_str),
+        default_value = "lcov.info",
+        help = "The file which the lcov report of `--coverage` is written to"
+    )]
+    coverage_output: PathBuf,
+}
+
+macro_rules! define_test_type {
+    ($name:ident $($args: ident)*) => {
+        impl VmType for $name {
+            type Type = $name;
+            fn make_type(vm: &Thread) -> ArcType {
+                let typ = concat!("std.test.", stringify!($name));
+                Type::app(
+                    vm.get_env().find_type_info(typ).unwrap().into_type(),
+                    vec![$($args::make_type(vm),)* Type::unit()].into_iter().collect(),
+                )
+            }
+        }
+    };
+}
+
+type TestEff = OpaqueValue<RootedThread, TestEffIO>;
+type TestFn = OwnedFunction<fn(()) -> TestEff>;
+
+#[derive(Deserialize)]
+enum TestCase {
+    Test(String, TestFn),
+    Group(String, Vec<TestCase>),
+    Tagged(Vec<String>, Box<TestCase>),
+}
+
+define_test_type! { TestCase Hole }
+
+struct TestEffIO;
+
+define_test_type! { TestEffIO A }
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct TestReport {
+    name: String,
+    tags: Vec<String>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    time: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    name: String,
+    path: PathBuf,
+    /// Set if the file could not be compiled or did not evaluate to a `TestCase`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    tests: Vec<TestReport>,
+    time: f64,
+    #[serde(skip)]
+    coverage: CoverageReport,
+}
+
+impl FileReport {
+    fn failures(&self) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.status == Status::Failed)
+            .count()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    passed: usize,
+    failed: usize,
+    errors: usize,
+    time: f64,
+    files: Vec<FileReport>,
+}
+
+impl Report {
+    fn new(files: Vec<FileReport>, time: Duration) -> Report {
+        let failed = files.iter().map(FileReport::failures).sum::<usize>();
+        let total = files.iter().map(|file| file.tests.len()).sum::<usize>();
+        Report {
+            passed: total - failed,
+            failed,
+            errors: files.iter().filter(|file| file.error.is_some()).count(),
+            time: time.as_secs_f64(),
+            files,
+        }
+    }
+}
+
+struct Filter<'a> {
+    names: &'a [String],
+    tags: &'a [String],
+}
+
+impl Filter<'_> {
+    fn matches(&self, name: &str, tags: &[String]) -> bool {
+        (self.names.is_empty() || self.names.iter().any(|filter| name.contains(&filter[..])))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag)))
+    }
+}
+
+/// Flattens `test` into the tests matching `filter`, each named by the path of groups leading to
+/// it and tagged with every tag of the enclosing `Tagged` cases
+fn collect_tests(
+    prefix: &str,
+    inherited_tags: &[String],
+    test: TestCase,
+    filter: &Filter,
+    tests: &mut Vec<(String, Vec<String>, TestFn)>,
+) {
+    let join = |name: &str| {
+        if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        }
+    };
+    match test {
+        TestCase::Test(name, test) => {
+            let name = join(&name);
+            if filter.matches(&name, inherited_tags) {
+                tests.push((name, inherited_tags.to_owned(), test));
+            }
+        }
+        TestCase::Group(name, group) => {
+            let name = join(&name);
+            for test in group {
+                collect_tests(&name, inherited_tags, test, filter, tests);
+            }
+        }
+        TestCase::Tagged(tags, test) => {
+            let mut inherited_tags = inherited_tags.to_owned();
+            for tag in tags {
+                if !inherited_tags.contains(&tag) {
+                    inherited_tags.push(tag);
+                }
+            }
+            collect_tests(prefix, &inherited_tags, *test, filter, tests);
+        }
+    }
+}
+
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    err.downcast::<String>()
+        .map(|s| *s)
+        .or_else(|e| e.downcast::<&str>().map(|s| String::from(&s[..])))
+        .unwrap_or_else(|_| "Unknown panic".to_string())
+}
+
+async fn run_test(test: TestFn) -> Result<(), String> {
+    let result = AssertUnwindSafe(async move {
+        let child_thread = test.vm().new_thread()?;
+        let mut test = TestFn::from_value(&child_thread, test.get_variant());
+        let test = test.call_async(()).await?;
+        let mut run_io: OwnedFunction<fn(TestEff) -> IO<()>> =
+            test.vm().get_global("std.test.run_io")?;
+        run_io.call_async(test).await
+    })
+    .catch_unwind()
+    .await;
+    match result {
+        Ok(Ok(IO::Value(()))) => Ok(()),
+        Ok(Ok(IO::Exception(err))) => Err(err),
+        // The stacktrace of a failed assertion only points into `std.test`
+        Ok(Err(VMError::Panic(err, _))) => Err(err.trim().to_string()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(err) => Err(panic_message(err)),
+    }
+}
+
+async fn load_tests(vm: &Thread, name: &str, path: &Path) -> anyhow::Result<TestCase> {
+    let source = fs::read_to_string(path)?;
+    vm.load_file_async("std/test.glu").await?;
+    let (De(test), _) = AssertUnwindSafe(vm.run_expr_async::<De<TestCase>>(name, &source))
+        .catch_unwind()
+        .await
+        .map_err(|err| anyhow!("{}", panic_message(err)))??;
+    Ok(test)
+}
+
+fn test_report(
+    name: String,
+    tags: Vec<String>,
+    result: Result<(), String>,
+    start: Instant,
+) -> TestReport {
+    TestReport {
+        name,
+        tags,
+        status: if result.is_ok() {
+            Status::Passed
+        } else {
+            Status::Failed
+        },
+        message: result.err(),
+        time: start.elapsed().as_secs_f64(),
+    }
+}
+
+/// Runs all tests in the file of `report` in a fresh vm
+async fn run_tests(
+    report: &mut FileReport,
+    use_std_lib: bool,
+    coverage: bool,
+    filter: &Filter<'_>,
+) {
+    let vm = new_vm_async().await;
+    vm.get_database_mut()
+        .use_standard_lib(use_std_lib)
+        .run_io(true)
+        .coverage(coverage);
+
+    match load_tests(&vm, &report.name, &report.path).await {
+        Ok(test) => {
+            let mut tests = Vec::new();
+            collect_tests("", &[], test, filter, &mut tests);
+            for (name, tags, test) in tests {
+                let test_start = Instant::now();
+                let result = run_test(test).await;
+                report
+                    .tests
+                    .push(test_report(name, tags, result, test_start));
+            }
+        }
+        Err(err) => report.error = Some(err.to_string()),
+    }
+    report.coverage = vm.global_env().coverage().report();
+}
+
+/// Runs the doctests in the file of `report`, each in a fresh vm
+async fn run_doctests(report: &mut FileReport, use_std_lib: bool, filter: &Filter<'_>) {
+    let source = match fs::read_to_string(&report.path) {
+        Ok(source) => FileMap::new(report.name.clone(), source),
+        Err(err) => {
+            report.error = Some(err.to_string());
+            return;
+        }
+    };
+    for doctest in doctest::extract(&report.name, &source) {
+        let name = doctest.name();
+        if doctest.mode == doctest::Mode::Ignore || !filter.matches(&name, &[]) {
+            continue;
+        }
+        let test_start = Instant::now();
+        let result = doctest::run(&doctest, use_std_lib)
+            .await
+            .map_err(|err| format!("{}:{}: {}", report.path.display(), doctest.line, err));
+        report
+            .tests
+            .push(test_report(name, Vec::new(), result, test_start));
+    }
+}
+
+/// Runs all tests, or doctests if `doc` is set, in `path`
+async fn run_file(
+    path: PathBuf,
+    use_std_lib: bool,
+    doc: bool,
+    coverage: bool,
+    filter: &Filter<'_>,
+) -> FileReport {
+    let start = Instant::now();
+    let name = filename_to_module(&path.display().to_string());
+
+    let mut report = FileReport {
+        name,
+        path,
+        error: None,
+        tests: Vec::new(),
+        time: 0.0,
+        coverage: CoverageReport::default(),
+    };
+
+    if doc {
+        run_doctests(&mut report, use_std_lib, filter).await;
+    } else {
+        run_tests(&mut report, use_std_lib, coverage, filter).await;
+    }
+
+    report.time = start.elapsed().as_secs_f64();
+    report
+}
+
+fn test_files(input: &[PathBuf]) -> Vec<PathBuf> {
+    let default_input = [PathBuf::from("tests")];
+    let input = if input.is_empty() {
+        &default_input[..]
+    } else {
+        input
+    };
+    let mut files = input
+        .iter()
+        .flat_map(|arg| {
+            WalkDir::new(arg).into_iter().filter_map(|entry| {
+                entry.ok().and_then(|entry| {
+                    if entry.file_type().is_file()
+                        && entry.path().extension() == Some(OsStr::new("glu"))
+                    {
+                        Some(entry.path().to_owned())
+                    } else {
+                        None
+                    }
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn write_pretty_file(out: &mut dyn Write, file: &FileReport) -> io::Result<()> {
+    if file.error.is_some() {
+        writeln!(out, "file {} ... ERROR", file.name)?;
+    }
+    for test in &file.tests {
+        let status = match test.status {
+            Status::Passed => "ok",
+            Status::Failed => "FAILED",
+        };
+        writeln!(out, "test {}/{} ... {}", file.name, test.name, status)?;
+    }
+    Ok(())
+}
+
+fn write_pretty_summary(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    let mut failures = report
+        .files
+        .iter()
+        .flat_map(|file| {
+            file.error
+                .iter()
+                .map(move |err| (file.name.clone(), err))
+                .chain(file.tests.iter().filter_map(move |test| {
+                    test.message
+                        .as_ref()
+                        .map(move |message| (format!("{}/{}", file.name, test.name), message))
+                }))
+        })
+        .peekable();
+    if failures.peek().is_some() {
+        writeln!(out, "\nfailures:")?;
+        for (name, message) in failures {
+            writeln!(out, "\n---- {} ----\n{}", name, message)?;
+        }
+    }
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed; {} errors; finished in {:.2}s",
+        if report.failed == 0 && report.errors == 0 {
+            "ok"
+        } else {
+            "FAILED"
+        },
+        report.passed,
+        report.failed,
+        report.errors,
+        report.time,
+    )
+}
+
+/// Writes the coverage of every module which is either a test file or a file that the tests
+/// imported from the working directory. The standard library is left out.
+fn write_lcov(path: &Path, report: &Report) -> anyhow::Result<()> {
+    let mut coverage = CoverageReport::default();
+    for file in &report.files {
+        coverage.merge(file.coverage.clone());
+    }
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    coverage.write_lcov(&mut out, |module| {
+        if let Some(file) = report.files.iter().find(|file| file.name == module) {
+            return Some(file.path.display().to_string());
+        }
+        if module == "std" || module.starts_with("std.") {
+            return None;
+        }
+        let path = PathBuf::from(format!("{}.glu", module.replace('.', "/")));
+        if path.is_file() {
+            Some(path.display().to_string())
+        } else {
+            None
+        }
+    })?;
+    out.flush()?;
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_junit(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites name="gluon" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+        report.passed + report.failed,
+        report.failed,
+        report.errors,
+        report.time,
+    )?;
+    for file in &report.files {
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+            escape_xml(&file.name),
+            file.tests.len(),
+            file.failures(),
+            file.error.is_some() as usize,
+            file.time,
+        )?;
+        if let Some(err) = &file.error {
+            writeln!(
+                out,
+                r#"    <error message="Could not load the tests">{}</error>"#,
+                escape_xml(err)
+            )?;
+        }
+        for test in &file.tests {
+            write!(
+                out,
+                r#"    <testcase name="{}" classname="{}" time="{:.3}""#,
+                escape_xml(&test.name),
+                escape_xml(&file.name),
+                test.time,
+            )?;
+            match &test.message {
+                Some(message) => {
+                    writeln!(out, ">")?;
+                    writeln!(
+                        out,
+                        r#"      <failure message="{}">{}</failure>"#,
+                        escape_xml(message.lines().next().unwrap_or("")),
+                        escape_xml(message)
+                    )?;
+                    writeln!(out, "    </testcase>")?;
+                }
+                None => writeln!(out, " />")?,
+            }
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+/// Runs the tests found in `opt.input`, returning an error if any test failed
+pub fn run(opt: &TestOpt, use_std_lib: bool) -> anyhow::Result<()> {
+    let start = Instant::now();
+
+    if opt.coverage && opt.doc {
+        return Err(anyhow!("`--coverage` can not be used with `--doc`"));
+    }
+
+    let files = test_files(&opt.input);
+    if files.is_empty() {
+        return Err(anyhow!("No test files found"));
+    }
+    let file_count = files.len();
+
+    let jobs = opt
+        .jobs
+        .unwrap_or_else(num_cpus::get)
+        .max(1)
+        .min(file_count);
+    let queue = Arc::new(Mutex::new(files.into_iter().enumerate()));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers = (0..jobs)
+        .map(|i| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let names = opt.filter.clone();
+            let tags = opt.tag.clone();
+            let doc = opt.doc;
+            let coverage = opt.coverage;
+            thread::Builder::new()
+                .name(format!("gluon-test-{}", i))
+                // gluon's compiler is recursive so give it the same stack as the main thread
+                .stack_size(8 * 1024 * 1024)
+                .spawn(move || -> anyhow::Result<()> {
+                    let filter = Filter {
+                        names: &names,
+                        tags: &tags,
+                    };
+                    let mut runtime = tokio::runtime::Builder::new()
+                        .basic_scheduler()
+                        .enable_all()
+                        .build()?;
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        let (index, path) = match next {
+                            Some(next) => next,
+                            None => return Ok(()),
+                        };
+                        let report =
+                            runtime.block_on(run_file(path, use_std_lib, doc, coverage, &filter));
+                        if sender.send((index, report)).is_err() {
+                            return Ok(());
+                        }
+                    }
+                })
+                .map_err(anyhow::Error::from)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    drop(sender);
+
+    // Progress is written to stderr when stdout may be used for a machine readable report
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let mut progress: Box<dyn Write> = if opt.format == ReportFormat::Pretty {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(stderr.lock())
+    };
+
+    let mut files = Vec::with_capacity(file_count);
+    for (index, report) in receiver {
+        write_pretty_file(&mut *progress, &report)?;
+        files.push((index, report));
+    }
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|err| anyhow!("{}", panic_message(err)))??;
+    }
+    files.sort_by_key(|(index, _)| *index);
+
+    let report = Report::new(
+        files.into_iter().map(|(_, report)| report).collect(),
+        start.elapsed(),
+    );
+
+    match opt.format {
+        ReportFormat::Pretty => write_pretty_summary(&mut *progress, &report)?,
+        format => {
+            drop(progress);
+            let mut out: Box<dyn Write> = match &opt.output {
+                Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+                None => Box::new(io::stdout()),
+            };
+            if format == ReportFormat::Junit {
+                write_junit(&mut *out, &report)?;
+            } else {
+                serde_json::to_writer_pretty(&mut *out, &report)?;
+                writeln!(out)?;
+            }
+            out.flush()?;
+        }
+    }
+
+    if opt.coverage {
+        write_lcov(&opt.coverage_output, &report)?;
+    }
+
+    if report.failed != 0 || report.errors != 0 {
+        return Err(anyhow!(
+            "{} tests failed and {} files could not be loaded",
+            report.failed,
+            report.errors
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! A minimal implementation of ZMTP 3.0, the wire protocol of ZeroMQ, which is enough to talk to
+//! the clients of a Jupyter kernel. Only the `NULL` security mechanism is supported and each
+//! connection is used directly, so the routing and subscription filtering of the ZeroMQ socket
+//! types is left to the caller.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+
+const MORE_FLAG: u8 = 0b001;
+const LONG_FLAG: u8 = 0b010;
+const COMMAND_FLAG: u8 = 0b100;
+
+/// The largest frame which is accepted from a peer. Jupyter messages are small so this only
+/// needs to leave room for large cells and their outputs.
+const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The ZeroMQ socket type which this side of a connection emulates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SocketType {
+    Router,
+    Pub,
+    Rep,
+    #[cfg(test)]
+    Dealer,
+}
+
+impl SocketType {
+    fn name(self) -> &'static str {
+        match self {
+            SocketType::Router => "ROUTER",
+            SocketType::Pub => "PUB",
+            SocketType::Rep => "REP",
+            #[cfg(test)]
+            SocketType::Dealer => "DEALER",
+        }
+    }
+}
+
+fn greeting(as_server: bool) -> [u8; 64] {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    // Version 3.0
+    greeting[10] = 3;
+    greeting[11] = 0;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting[32] = as_server as u8;
+    greeting
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// A ZMTP connection over TCP which has completed the handshake
+pub struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    /// Performs the handshake on `stream`, announcing this side as a `socket_type` socket
+    pub fn handshake(
+        mut stream: TcpStream,
+        socket_type: SocketType,
+        as_server: bool,
+    ) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        stream.write_all(&greeting(as_server))?;
+
+        let mut peer_greeting = [0; 64];
+        stream.read_exact(&mut peer_greeting)?;
+        if peer_greeting[0] != 0xff || peer_greeting[9] & 1 != 1 {
+            return Err(invalid_data("Expected a ZMTP greeting"));
+        }
+        if peer_greeting[10] < 3 {
+            return Err(invalid_data(format!(
+                "ZMTP version {} is not supported",
+                peer_greeting[10]
+            )));
+        }
+        if &peer_greeting[12..16] != b"NULL" || peer_greeting[16] != 0 {
+            return Err(invalid_data(
+                "Only the NULL security mechanism is supported",
+            ));
+        }
+
+        let mut connection = Connection { stream };
+        connection.write_frame(
+            &ready_command(&[("Socket-Type", socket_type.name().as_bytes())]),
+            COMMAND_FLAG,
+        )?;
+        let (flags, body) = connection.read_frame()?;
+        if flags & COMMAND_FLAG == 0 || !body.starts_with(b"\x05READY") {
+            return Err(invalid_data("Expected the READY command"));
+        }
+        Ok(connection)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Ok(Connection {
+            stream: self.stream.try_clone()?,
+        })
+    }
+
+    /// Receives the frames of the next message, skipping any commands sent by the peer
+    pub fn recv(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut frames = Vec::new();
+        loop {
+            let (flags, body) = self.read_frame()?;
+            if flags & COMMAND_FLAG != 0 {
+                continue;
+            }
+            frames.push(body);
+            if flags & MORE_FLAG == 0 {
+                return Ok(frames);
+            }
+        }
+    }
+
+    /// Sends `frames` as a single message
+    pub fn send<I>(&mut self, frames: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let frames: Vec<_> = frames.into_iter().collect();
+        let mut buffer = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let more = if i + 1 < frames.len() { MORE_FLAG } else { 0 };
+            encode_frame(&mut buffer, frame.as_ref(), more);
+        }
+        self.stream.write_all(&buffer)
+    }
+
+    fn write_frame(&mut self, body: &[u8], flags: u8) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        encode_frame(&mut buffer, body, flags);
+        self.stream.write_all(&buffer)
+    }
+
+    fn read_frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut flags = [0];
+        self.stream.read_exact(&mut flags)?;
+        let flags = flags[0];
+        let len = if flags & LONG_FLAG != 0 {
+            let mut len = [0; 8];
+            self.stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        } else {
+            let mut len = [0];
+            self.stream.read_exact(&mut len)?;
+            u64::from(len[0])
+        };
+        if len > MAX_FRAME_SIZE {
+            return Err(invalid_data(format!(
+                "Frame of {} bytes is larger than the limit of {} bytes",
+                len, MAX_FRAME_SIZE
+            )));
+        }
+        let mut body = vec![0; len as usize];
+        self.stream.read_exact(&mut body)?;
+        Ok((flags, body))
+    }
+}
+
+fn encode_frame(buffer: &mut Vec<u8>, body: &[u8], flags: u8) {
+    if body.len() > 255 {
+        buffer.push(flags | LONG_FLAG);
+        buffer.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        buffer.push(flags);
+        buffer.push(body.len() as u8);
+    }
+    buffer.extend_from_slice(body);
+}
+
+fn ready_command(properties: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut body = b"\x05READY".to_vec();
+    for (name, value) in properties {
+        body.push(name.len() as u8);
+        body.extend_from_slice(name.as_bytes());
+        body.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        body.extend_from_slice(value);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn send_and_receive_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut connection = Connection::handshake(stream, SocketType::Dealer, false).unwrap();
+            connection
+                .send(&[&b"short"[..], &[b'x'; 300][..], b""])
+                .unwrap();
+            connection.recv().unwrap()
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection::handshake(stream, SocketType::Router, true).unwrap();
+        let frames = connection.recv().unwrap();
+        assert_eq!(frames, vec![b"short".to_vec(), vec![b'x'; 300], Vec::new()]);
+        connection.send(&frames[..1]).unwrap();
+
+        assert_eq!(client.join().unwrap(), vec![b"short".to_vec()]);
+    }
+
+    #[test]
+    fn reject_oversized_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut connection = Connection::handshake(stream, SocketType::Dealer, false).unwrap();
+            let mut header = vec![LONG_FLAG];
+            header.extend_from_slice(&u64::MAX.to_be_bytes());
+            connection.stream.write_all(&header).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection::handshake(stream, SocketType::Router, true).unwrap();
+        let err = connection.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        client.join().unwrap();
+    }
+}
@@ -61,3 +61,181 @@ fn issue_365_run_io_from_command_line() {
     }
     assert_eq!(String::from_utf8_lossy(&output.stdout), "123\n");
 }
+
+fn gluon_test(args: &[&str]) -> std::process::Output {
+    gluon_test_file(args, "tests/test_cases.glu")
+}
+
+fn gluon_test_file(args: &[&str], file: &str) -> std::process::Output {
+    if ::std::env::var("GLUON_PATH").is_err() {
+        ::std::env::set_var("GLUON_PATH", "..");
+    }
+
+    let path = env::args().next().unwrap();
+    let gluon_path = Path::new(&path[..])
+        .parent()
+        .and_then(|p| p.parent())
+        .expect("folder")
+        .join("gluon");
+    Command::new(&*gluon_path)
+        .arg("test")
+        .args(args)
+        .arg(file)
+        .output()
+        .unwrap_or_else(|err| panic!("{}\nWhen opening `{}`", err, gluon_path.display()))
+}
+
+#[test]
+fn test_command_json_report() {
+    let output = gluon_test(&["--format", "json"]);
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], 2);
+    assert_eq!(report["failed"], 1);
+    let tests = report["files"][0]["tests"].as_array().unwrap();
+    let names = tests
+        .iter()
+        .map(|test| test["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["cases/passes", "cases/fails", "cases/slow"]);
+    assert_eq!(tests[1]["message"], "Assertion failed: 1 != 2");
+    assert_eq!(tests[2]["tags"], serde_json::json!(["slow"]));
+}
+
+#[test]
+fn test_command_filters_by_name_and_tag() {
+    let output = gluon_test(&["--filter", "passes", "--format", "junit"]);
+    assert!(output.status.success());
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        report.contains(r#"<testcase name="cases/passes""#),
+        "{}",
+        report
+    );
+    assert!(!report.contains("cases/slow"), "{}", report);
+
+    let output = gluon_test(&["--tag", "slow", "--format", "junit"]);
+    assert!(output.status.success());
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.contains(r#"tests="1" failures="0""#), "{}", report);
+    assert!(
+        report.contains(r#"<testcase name="cases/slow""#),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn test_command_runs_doctests() {
+    let output = gluon_test_file(&["--doc", "--format", "json"], "tests/doctests.glu");
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], 1);
+    assert_eq!(report["failed"], 1);
+    let tests = report["files"][0]["tests"].as_array().unwrap();
+    let names = tests
+        .iter()
+        .map(|test| test["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["tests.doctests (line 3)", "add_one (line 10)"]);
+    assert_eq!(
+        tests[1]["message"],
+        "tests/doctests.glu:10: Assertion failed: 2 != 3"
+    );
+}
+
+#[test]
+fn test_command_writes_lcov() {
+    let lcov_path = env::temp_dir().join("gluon_test_coverage.info");
+    let output = gluon_test_file(
+        &[
+            "--filter",
+            "positive",
+            "--coverage",
+            "--coverage-output",
+            lcov_path.to_str().unwrap(),
+        ],
+        "tests/coverage_cases.glu",
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut lcov = String::new();
+    File::open(&lcov_path)
+        .unwrap()
+        .read_to_string(&mut lcov)
+        .unwrap();
+    // Only the tests themselves are reported, not the standard library
+    assert!(
+        lcov.starts_with("TN:\nSF:tests/coverage_cases.glu\n"),
+        "{}",
+        lcov
+    );
+    assert_eq!(lcov.matches("end_of_record").count(), 1, "{}", lcov);
+    assert!(lcov.contains("DA:6,1\n"), "{}", lcov);
+    assert!(lcov.contains("DA:8,0\n"), "{}", lcov);
+    // The test which the filter left out is never run
+    assert!(lcov.contains("DA:14,0\n"), "{}", lcov);
+}
+
+#[test]
+fn project_commands() {
+    if ::std::env::var("GLUON_PATH").is_err() {
+        ::std::env::set_var("GLUON_PATH", "..");
+    }
+
+    let path = env::args().next().unwrap();
+    let gluon_path = Path::new(&path[..])
+        .parent()
+        .and_then(|p| p.parent())
+        .expect("folder")
+        .join("gluon");
+    let dir = Path::new("../target/gluon_project_test");
+    if dir.exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+    // The standard library is found through `GLUON_PATH` which is relative to `repl`
+    let gluon_path_env = std::fs::canonicalize("..").unwrap();
+    let gluon = |dir: &Path, args: &[&str]| {
+        let output = Command::new(&*gluon_path)
+            .args(args)
+            .current_dir(dir)
+            .env("GLUON_PATH", &gluon_path_env)
+            .output()
+            .unwrap_or_else(|err| panic!("{}\nWhen opening `{}`", err, gluon_path.display()));
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    let (success, _, stderr) = gluon(dir, &["new", "hello"]);
+    assert!(success, "{}", stderr);
+    let project = dir.join("hello");
+
+    let (success, stdout, stderr) = gluon(&project, &["run"]);
+    assert!(success, "{}", stderr);
+    assert_eq!(stdout, "Hello, world!\n");
+
+    let (success, _, stderr) = gluon(&project, &["build", "--bundle"]);
+    assert!(success, "{}", stderr);
+    let (success, stdout, stderr) = gluon(dir, &["run", "--bundle", "hello/target/hello.bundle"]);
+    assert!(success, "{}", stderr);
+    assert_eq!(stdout, "Hello, world!\n");
+
+    std::fs::write(project.join("src/broken.glu"), "1 #Int+ \"\"").unwrap();
+    let (success, _, stderr) = gluon(&project, &["build"]);
+    assert!(!success);
+    assert!(
+        stderr.contains("Could not build `hello` due to 1 previous error"),
+        "{}",
+        stderr
+    );
+}
@@ -170,3 +170,114 @@ fn assert() {
     repl.test("let { assert } = import! std.test", None);
     repl.test("assert False", None);
 }
+
+#[test]
+fn multi_line_input() {
+    let mut repl = REPL::new();
+
+    (|| -> Result<()> {
+        // An incomplete binding continues on the next line
+        repl.session.send_line("let add x y =")?;
+        repl.session.send_line("    x + y")?;
+        repl.session.exp_string(repl.prompt)?;
+        // The prompt is redrawn while editing the continuation line so it may be matched before
+        // the binding has been evaluated, give the REPL time to show the next prompt
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        Ok(())
+    })()
+    .unwrap_or_else(|err| panic!("{}", err));
+
+    repl.test("add 1 2", Some("3"));
+
+    (|| -> Result<()> {
+        // An empty line submits the incomplete input
+        repl.session.send_line("1 +")?;
+        repl.session.send_line("")?;
+        repl.session.exp_string("Unexpected")?;
+        repl.session.exp_string(repl.prompt)?;
+        Ok(())
+    })()
+    .unwrap_or_else(|err| panic!("{}", err));
+}
+
+#[test]
+fn doc() {
+    let mut repl = REPL::new();
+
+    repl.test("let list = import! std.list", None);
+    repl.test(":doc std.list.of", Some("Constructs a list from an array"));
+    repl.test(":i std.list.of", Some("Defined at std.list:"));
+}
+
+#[test]
+fn save_and_load() {
+    let path = std::env::temp_dir().join("gluon_rexpect_save.glu");
+    let path = path.to_str().unwrap();
+
+    let mut repl = REPL::new();
+    repl.test("let x = 1", None);
+    repl.test("let add y = x + y", None);
+    repl.test(&format!(":save {}", path), Some("Saved 2 bindings"));
+
+    let mut repl = REPL::new();
+    repl.test(&format!(":load {}", path), Some("Loaded 2 bindings"));
+    repl.test("add 2", Some("3"));
+}
+
+#[test]
+fn time_and_memory() {
+    let mut repl = REPL::new();
+
+    repl.test(":time", Some("Timing enabled"));
+    repl.test("1 + 2", Some("instructions:"));
+    repl.test(":memory", Some("Memory reporting enabled"));
+    repl.test("[1, 2]", Some("Allocated:"));
+    repl.test(":time", Some("Timing disabled"));
+}
+
+#[test]
+fn set_options() {
+    let mut repl = REPL::new();
+
+    repl.test(":set types on", None);
+    repl.test("1 + 2", Some("3 : Int"));
+    repl.test(":set implicits on", None);
+    repl.test(
+        "show 1",
+        Some("Implicit argument: show : std.show.Show Int = std.int.show"),
+    );
+    repl.test(":set fuel 100", None);
+    repl.test(
+        "let f x = if x == 0 then 0 else f (x - 1)",
+        Some("<f> : Int -> Int"),
+    );
+    repl.test(
+        "f 1000",
+        Some("ran out of fuel after executing 100 instructions"),
+    );
+    repl.test(":set fuel off", None);
+    repl.test(":set", Some("fuel = off"));
+    repl.test(":set width wide", Some("Expected a width, found `wide`"));
+}
+
+#[test]
+fn debugger() {
+    let mut repl = REPL::new();
+
+    repl.test("let f x = x + 1", None);
+    repl.test(":break f", Some("Breakpoint set at `f`"));
+
+    (|| -> Result<()> {
+        repl.session.send_line("f 3")?;
+        repl.session.exp_string("Stopped at f")?;
+        repl.session.exp_string("debug> ")?;
+        repl.session.send_line(":locals")?;
+        repl.session.exp_string("x : Int = 3")?;
+        repl.session.exp_string("debug> ")?;
+        repl.session.send_line(":continue")?;
+        repl.session.exp_string("4")?;
+        repl.session.exp_string(repl.prompt)?;
+        Ok(())
+    })()
+    .unwrap_or_else(|err| panic!("{}", err));
+}
@@ -0,0 +1,6 @@
+#!/bin/bash
+# Regenerates the C header of the c-api crate, requires `cargo install cbindgen`
+set -ex
+
+cd "$(dirname "$0")/../c-api"
+cbindgen --config cbindgen.toml --output include/gluon.h
@@ -25,6 +25,9 @@ declare -a PROJECTS=(
     gluon_format
     gluon
     gluon_c-api
+    gluon_wasm
+    gluon_py
+    gluon_node
     gluon_doc
     gluon_repl
 )
@@ -15,15 +15,19 @@ declare -a PROJECTS=(
     gluon_format
     gluon
     gluon_c-api
+    gluon_wasm
     gluon_doc
     gluon_repl
 )
 
+# The bindings are not tested by cargo, see `py/test_gluon.py` and `node/test.js`
+BINDINGS="--exclude gluon_py --exclude gluon_node"
+
 if [ -z $NO_NORMAL_TEST ]; then
-    cargo test --features "test" --all "$@"
-    cargo test --features "test" --all --bins "$@"
-    cargo test --features "test" --all --examples "$@"
-    cargo test --features "test" --all --benches "$@"
+    cargo test --features "test" --all $BINDINGS "$@"
+    cargo test --features "test" --all $BINDINGS --bins "$@"
+    cargo test --features "test" --all $BINDINGS --examples "$@"
+    cargo test --features "test" --all $BINDINGS --benches "$@"
     cargo test --features "test" -p gluon_parser --benches "$@"
     echo "" | cargo run --features "test" --example 24
     cargo run --features "test" --example marshalling
@@ -36,4 +40,8 @@ if [ -z $NO_NORMAL_TEST ]; then
     do
         cargo check --package "${PROJECT}" --no-default-features "$@"
     done
+
+    # The bindings are not default members of the workspace
+    cargo check --package gluon_py "$@"
+    cargo check --package gluon_node "$@"
 fi
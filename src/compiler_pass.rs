@@ -0,0 +1,217 @@
+//! Passes which embedders add to the compiler to inspect or rewrite the AST of every module, for
+//! instance to implement lints, desugar a DSL or instrument code.
+//!
+//! ```
+//! use gluon::{
+//!     base::{ast::{self, Expr, SpannedExpr, Visitor}, symbol::Symbol},
+//!     compiler_pass::{CompilerPass, PassContext, Stage},
+//!     new_vm, ThreadExt,
+//! };
+//!
+//! /// Rejects any use of a variable named `todo`
+//! struct NoTodo;
+//!
+//! impl CompilerPass for NoTodo {
+//!     fn name(&self) -> &str {
+//!         "no_todo"
+//!     }
+//!
+//!     fn stage(&self) -> Stage {
+//!         Stage::Parsed
+//!     }
+//!
+//!     fn run<'ast>(&self, context: &mut PassContext<'_, 'ast>, expr: &mut SpannedExpr<'ast, Symbol>) {
+//!         struct Find<'a, 'b, 'ast>(&'a mut PassContext<'b, 'ast>);
+//!         impl<'a, 'ast> Visitor<'a, 'ast> for Find<'_, '_, 'ast> {
+//!             type Ident = Symbol;
+//!             fn visit_expr(&mut self, expr: &'a SpannedExpr<'ast, Symbol>) {
+//!                 match &expr.value {
+//!                     Expr::Ident(id) if id.name.declared_name() == "todo" => {
+//!                         self.0.error(expr.span, "`todo` must be implemented")
+//!                     }
+//!                     _ => ast::walk_expr(self, expr),
+//!                 }
+//!             }
+//!         }
+//!         Find(context).visit_expr(expr);
+//!     }
+//! }
+//!
+//! # if ::std::env::var("GLUON_PATH").is_err() {
+//! #     ::std::env::set_var("GLUON_PATH", "..");
+//! # }
+//! let vm = new_vm();
+//! vm.get_database_mut().add_pass(NoTodo);
+//! let err = vm.run_expr::<i32>("example", "let todo = 1 in todo").unwrap_err();
+//! assert!(err.to_string().contains("no_todo: `todo` must be implemented"));
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use crate::base::{
+    ast::{ArenaRef, SpannedExpr},
+    error::{AsDiagnostic, Errors},
+    pos::{self, BytePos, Span, Spanned},
+    source::FileId,
+    symbol::{Symbol, Symbols},
+};
+
+use crate::vm::thread::Thread;
+
+/// The point in the pipeline at which a `CompilerPass` runs
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Stage {
+    /// After macros have been expanded, before names are resolved and the module is typechecked.
+    /// Rewrites at this stage are typechecked like the code they replace.
+    Parsed,
+    /// After typechecking, before the module is translated to bytecode. Every expression is
+    /// annotated with its type so rewrites must keep the annotations correct.
+    Typechecked,
+}
+
+/// A pass over the AST of each module that is compiled, see the module documentation
+pub trait CompilerPass: Send + Sync {
+    /// Identifies the pass in the errors it reports
+    fn name(&self) -> &str;
+
+    fn stage(&self) -> Stage;
+
+    /// Passes of the same stage run in increasing `order`, passes with the same `order` run in
+    /// the order they were added (default: 0)
+    fn order(&self) -> i32 {
+        0
+    }
+
+    /// Inspects or rewrites `expr`, the whole module. Errors are reported through `context` and
+    /// stop the module from compiling once every pass of the stage has run.
+    fn run<'ast>(&self, context: &mut PassContext<'_, 'ast>, expr: &mut SpannedExpr<'ast, Symbol>);
+}
+
+/// Error reported by a `CompilerPass`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PassError {
+    pub pass: String,
+    pub message: String,
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.pass, self.message)
+    }
+}
+
+impl std::error::Error for PassError {}
+
+impl AsDiagnostic for PassError {
+    fn as_diagnostic(
+        &self,
+        _map: &crate::base::source::CodeMap,
+    ) -> codespan_reporting::diagnostic::Diagnostic<FileId> {
+        codespan_reporting::diagnostic::Diagnostic::error().with_message(self.to_string())
+    }
+}
+
+/// What a `CompilerPass` has access to while it runs
+pub struct PassContext<'a, 'ast> {
+    pub thread: &'a Thread,
+    /// The name of the module being compiled
+    pub module: &'a str,
+    /// Allocates the expressions of a rewritten AST
+    pub arena: ArenaRef<'a, 'ast, Symbol>,
+    symbols: &'a mut Symbols,
+    pass: &'a str,
+    errors: &'a mut Errors<Spanned<PassError, BytePos>>,
+}
+
+impl<'a, 'ast> PassContext<'a, 'ast> {
+    /// Reports an error at `span`
+    pub fn error(&mut self, span: Span<BytePos>, message: impl Into<String>) {
+        self.errors.push(pos::spanned(
+            span,
+            PassError {
+                pass: self.pass.to_string(),
+                message: message.into(),
+            },
+        ));
+    }
+
+    /// Creates a symbol for an identifier introduced by the pass. At the `Typechecked` stage the
+    /// symbol must be bound by the pass itself since names have already been resolved.
+    pub fn symbol(&mut self, name: &str) -> Symbol {
+        self.symbols.simple_symbol(name)
+    }
+}
+
+/// The passes added to a compiler. Two non-empty sets of passes are only equal if they are the
+/// same instance so adding a pass invalidates every module compiled without it.
+#[derive(Clone, Default)]
+pub struct Passes(Arc<Vec<Arc<dyn CompilerPass>>>);
+
+impl Passes {
+    pub(crate) fn add(&self, pass: Arc<dyn CompilerPass>) -> Passes {
+        let mut passes = (*self.0).clone();
+        let index = passes
+            .iter()
+            .position(|other| other.order() > pass.order())
+            .unwrap_or(passes.len());
+        passes.insert(index, pass);
+        Passes(Arc::new(passes))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn CompilerPass> {
+        self.0.iter().map(|pass| &**pass)
+    }
+
+    /// Runs the passes of `stage` over `expr`
+    pub(crate) fn run<'ast>(
+        &self,
+        stage: Stage,
+        thread: &Thread,
+        module: &str,
+        symbols: &mut Symbols,
+        arena: ArenaRef<'_, 'ast, Symbol>,
+        expr: &mut SpannedExpr<'ast, Symbol>,
+    ) -> Result<(), Errors<Spanned<PassError, BytePos>>> {
+        let mut errors = Errors::new();
+        for pass in self.iter().filter(|pass| pass.stage() == stage) {
+            let mut context = PassContext {
+                thread,
+                module,
+                arena,
+                symbols: &mut *symbols,
+                pass: pass.name(),
+                errors: &mut errors,
+            };
+            pass.run(&mut context, expr);
+        }
+        if errors.has_errors() {
+            Err(errors)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Eq for Passes {}
+
+impl PartialEq for Passes {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || (self.0.is_empty() && other.0.is_empty())
+    }
+}
+
+impl std::hash::Hash for Passes {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        if !self.0.is_empty() {
+            (&*self.0 as *const Vec<_>).hash(hasher)
+        }
+    }
+}
+
+impl fmt::Debug for Passes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|pass| pass.name()))
+            .finish()
+    }
+}
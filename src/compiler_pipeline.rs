@@ -9,7 +9,7 @@
 
 use std::{
     borrow::{Borrow, BorrowMut, Cow},
-    fmt,
+    fmt, mem,
     result::Result as StdResult,
     sync::Arc,
 };
@@ -26,11 +26,13 @@ use crate::{
         error::{Errors, InFile},
         fnv::FnvMap,
         metadata::Metadata,
+        origin::OriginMap,
         resolve,
         symbol::{Name, NameBuf, Symbol, SymbolModule},
         types::{ArcType, NullInterner, Type, TypeCache},
     },
-    check::{metadata, rename},
+    check::{effects, metadata, privacy, rename},
+    compiler_pass::Stage,
     query::{env, AsyncCompilation, Compilation},
     vm::{
         compiler::CompiledModule,
@@ -260,20 +262,49 @@ impl<'s> MacroExpandable for &'s mut OwnedExpr<Symbol> {
             let (arena, expr) = self.arena_expr();
             let mut macros = MacroExpander::new(thread, &mut forker, spawner);
             macros.run(&mut compiler.symbols, arena, expr).await;
+            compiler.origins.extend(mem::take(&mut macros.origins));
             macros.finish()
         };
-        let value = MacroValue { expr: self };
         if let Err(errors) = result {
-            Err(Salvage {
-                value: Some(value),
+            return Err(Salvage {
+                value: Some(MacroValue { expr: self }),
                 error: InFile::new(compiler.code_map().clone(), errors).into(),
-            })
-        } else {
-            Ok(value)
+            });
+        }
+
+        let result = run_passes(compiler, thread, file, Stage::Parsed, &mut *self);
+        let value = MacroValue { expr: self };
+        match result {
+            Ok(()) => Ok(value),
+            Err(error) => Err(Salvage {
+                value: Some(value),
+                error,
+            }),
         }
     }
 }
 
+fn run_passes(
+    compiler: &mut ModuleCompiler<'_, '_>,
+    thread: &Thread,
+    file: &str,
+    stage: Stage,
+    expr: &mut OwnedExpr<Symbol>,
+) -> Result<()> {
+    let passes = compiler.compiler_settings().passes;
+    let (arena, expr) = expr.arena_expr();
+    passes
+        .run(
+            stage,
+            thread,
+            file,
+            &mut compiler.symbols,
+            arena.borrow(),
+            expr,
+        )
+        .map_err(|errors| InFile::new(compiler.code_map().clone(), errors).into())
+}
+
 #[async_trait::async_trait]
 impl MacroExpandable for OwnedExpr<Symbol> {
     type Expr = OwnedExpr<Symbol>;
@@ -358,9 +389,10 @@ where
         let source = compiler.get_or_insert_filemap(file, expr_str);
         let mut symbols = SymbolModule::new(String::from(file), &mut compiler.symbols);
 
-        self.expr
-            .borrow_mut()
-            .with_arena(|arena, expr| rename::rename(&*source, &mut symbols, arena.borrow(), expr));
+        let origins = &mut compiler.origins;
+        self.expr.borrow_mut().with_arena(|arena, expr| {
+            rename::rename(&*source, &mut symbols, origins, arena.borrow(), expr)
+        });
         Ok(Renamed { expr: self.expr })
     }
 }
@@ -497,7 +529,13 @@ where
             metadata_map,
         } = self;
         match expr.borrow_mut().with_arena(|arena, expr| {
-            reparse_infix(arena.borrow(), &metadata_map, &compiler.symbols, expr)
+            reparse_infix(
+                arena.borrow(),
+                &metadata_map,
+                &compiler.symbols,
+                &mut compiler.origins,
+                expr,
+            )
         }) {
             Ok(()) => Ok(InfixReparsed {
                 expr,
@@ -523,6 +561,8 @@ pub struct TypecheckValue<E> {
     pub typ: ArcType,
     pub metadata_map: FnvMap<Symbol, Arc<Metadata>>,
     pub metadata: Arc<Metadata>,
+    /// Where the code produced by macros, derives, `do` expressions and infix reparsing came from
+    pub origins: Arc<OriginMap>,
 }
 
 impl<E> TypecheckValue<E> {
@@ -532,12 +572,14 @@ impl<E> TypecheckValue<E> {
             typ,
             metadata_map,
             metadata,
+            origins,
         } = self;
         TypecheckValue {
             expr: f(expr),
             typ,
             metadata_map,
             metadata,
+            origins,
         }
     }
 }
@@ -615,6 +657,37 @@ fn typecheck_expr(
         .map_err(|err| InFile::new(compiler.database.state().code_map.clone(), err).into())
 }
 
+/// Rejects uses of the effects forbidden by `Settings::forbidden_effects`. The standard library
+/// embedded in the binary is not checked as it is the modules which use its effectful functions
+/// that are rejected. `IO` refers to the `std.io.IO` type.
+fn check_effects(
+    compiler: &mut ModuleCompiler<'_, '_>,
+    thread: &Thread,
+    file: &str,
+    expr: &OwnedExpr<Symbol>,
+) -> Result<()> {
+    if crate::import::is_embedded_std_module(&*compiler.database, file) {
+        return Ok(());
+    }
+    let names = compiler.compiler_settings().forbidden_effects;
+    let forbidden = effects::Forbidden::new(&names, |name| {
+        let path = if name == "IO" { "std.io.IO" } else { name };
+        thread
+            .find_type_info(path)
+            .ok()
+            .map(|alias| alias.name.clone())
+    });
+    effects::check_effects(&forbidden, expr.expr())
+        .map_err(|err| InFile::new(compiler.database.state().code_map.clone(), err).into())
+}
+
+/// Rejects uses of values, fields and constructors which other modules marked as `#[private]`
+fn check_privacy(compiler: &mut ModuleCompiler<'_, '_>, expr: &OwnedExpr<Symbol>) -> Result<()> {
+    let env = env(&*compiler.database);
+    privacy::check_privacy(&env, expr.expr())
+        .map_err(|err| InFile::new(compiler.database.state().code_map.clone(), err).into())
+}
+
 #[async_trait::async_trait]
 impl<E> Typecheckable for InfixReparsed<E>
 where
@@ -635,10 +708,11 @@ where
         let InfixReparsed {
             mut expr,
             mut metadata_map,
-            metadata,
+            mut metadata,
         } = self;
+        let origins = Arc::new(mem::take(&mut compiler.origins));
 
-        let typ = match typecheck_expr(
+        let mut typ = match typecheck_expr(
             expr.borrow_mut(),
             compiler,
             thread,
@@ -658,12 +732,49 @@ where
                         expr,
                         metadata_map,
                         metadata,
+                        origins,
                     }),
                     error,
                 })
             }
         };
 
+        if let Err(error) = run_passes(
+            compiler,
+            thread,
+            file,
+            Stage::Typechecked,
+            expr.borrow_mut(),
+        ) {
+            return Err(Salvage {
+                value: Some(TypecheckValue {
+                    expr,
+                    typ,
+                    metadata_map,
+                    metadata,
+                    origins,
+                }),
+                error,
+            });
+        }
+
+        if let Err(error) = check_effects(compiler, thread, file, expr.borrow_mut())
+            .and_then(|()| check_privacy(compiler, expr.borrow_mut()))
+        {
+            return Err(Salvage {
+                value: Some(TypecheckValue {
+                    expr,
+                    typ,
+                    metadata_map,
+                    metadata,
+                    origins,
+                }),
+                error,
+            });
+        }
+
+        privacy::hide_private_bindings(expr.borrow_mut().expr_mut(), &mut typ, &mut metadata);
+
         // Some metadata requires typechecking so recompute it if full metadata is required
         let (metadata, metadata_map) = if compiler.compiler_settings().full_metadata {
             let env = env(&*compiler.database);
@@ -677,6 +788,7 @@ where
             typ,
             metadata_map,
             metadata,
+            origins,
         })
     }
 }
@@ -858,6 +970,8 @@ where
                 filename.to_string(),
                 settings.emit_debug_info,
             );
+            compiler.set_origins(&self.origins);
+            compiler.set_coverage(settings.coverage);
             compiler.compile_expr(core_expr.value.expr())?
         };
         module.function.id = Symbol::from(filename);
@@ -0,0 +1,78 @@
+//! Implementation of the `decimal!` macro.
+use gluon_codegen::Trace;
+
+use {
+    base::{
+        ast::{self, Expr, Literal, SpannedExpr, TypedIdent},
+        pos,
+        symbol::{Symbol, Symbols},
+        types::Type,
+    },
+    vm::macros::{self, Macro, MacroExpander, MacroFuture},
+};
+
+use crate::std_lib::decimal::Decimal;
+
+/// Expands `decimal! "19.99"` into a `std.decimal.Decimal`. The literal is checked while
+/// compiling so the conversion can not fail at runtime.
+#[derive(Trace)]
+#[gluon(crate_name = "vm")]
+pub(crate) struct DecimalLiteral;
+
+impl Macro for DecimalLiteral {
+    fn expand<'r, 'a: 'r, 'b: 'r, 'ast: 'r>(
+        &self,
+        env: &'b mut MacroExpander<'a>,
+        arena: &'b mut ast::OwnedArena<'ast, Symbol>,
+        args: &'b mut [SpannedExpr<'ast, Symbol>],
+    ) -> MacroFuture<'r, 'ast> {
+        Box::pin(async move {
+            if args.len() != 1 {
+                return Err(macros::Error::message(
+                    "`decimal!` expects 1 argument".to_string(),
+                ));
+            }
+
+            let span = args[0].span;
+            let literal = match &args[0].value {
+                Expr::Literal(Literal::String(literal)) => literal.clone(),
+                _ => {
+                    return Err(macros::Error::message(
+                        "`decimal!` expects a string literal".to_string(),
+                    ))
+                }
+            };
+            literal.parse::<Decimal>().map_err(macros::Error::message)?;
+
+            let sp = |e| pos::spanned(span, e);
+
+            let mut symbols = Symbols::new();
+            let mut import = sp(Expr::app(
+                arena.borrow(),
+                sp(Expr::Ident(TypedIdent::new(
+                    symbols.simple_symbol("import!"),
+                ))),
+                vec![sp(Expr::Projection(
+                    arena.alloc(sp(Expr::Ident(TypedIdent::new(
+                        symbols.simple_symbol("std"),
+                    )))),
+                    symbols.simple_symbol("decimal"),
+                    Type::hole(),
+                ))],
+            ));
+            env.run_once(&mut symbols, arena, &mut import).await;
+
+            let out = sp(Expr::app(
+                arena.borrow(),
+                sp(Expr::Projection(
+                    arena.alloc(import),
+                    symbols.simple_symbol("literal"),
+                    Type::hole(),
+                )),
+                vec![sp(Expr::Literal(Literal::String(literal)))],
+            ));
+
+            Ok(out.into())
+        })
+    }
+}
@@ -0,0 +1,134 @@
+//! Implementation of the `format!` macro.
+use gluon_codegen::Trace;
+
+use {
+    base::{
+        ast::{self, Expr, Literal, Pattern, PatternField, SpannedExpr, TypedIdent, ValueBinding},
+        pos,
+        symbol::{Symbol, Symbols},
+        types::Type,
+    },
+    vm::macros::{self, Macro, MacroExpander, MacroFuture},
+};
+
+use crate::std_lib::format::{parse_template, Piece};
+
+/// Expands `format! "user {name} has {count:03} items"` into a concatenation of the literal text
+/// and each variable formatted with the `std.format.Format` instance of its type.
+#[derive(Trace)]
+#[gluon(crate_name = "vm")]
+pub(crate) struct Format;
+
+impl Macro for Format {
+    fn expand<'r, 'a: 'r, 'b: 'r, 'ast: 'r>(
+        &self,
+        env: &'b mut MacroExpander<'a>,
+        arena: &'b mut ast::OwnedArena<'ast, Symbol>,
+        args: &'b mut [SpannedExpr<'ast, Symbol>],
+    ) -> MacroFuture<'r, 'ast> {
+        Box::pin(async move {
+            if args.len() != 1 {
+                return Err(macros::Error::message(
+                    "`format!` expects 1 argument".to_string(),
+                ));
+            }
+
+            let span = args[0].span;
+            let template = match &args[0].value {
+                Expr::Literal(Literal::String(template)) => template.clone(),
+                _ => {
+                    return Err(macros::Error::message(
+                        "`format!` expects a string literal".to_string(),
+                    ))
+                }
+            };
+            let pieces = parse_template(&template).map_err(macros::Error::message)?;
+
+            let sp = |e| pos::spanned(span, e);
+
+            // The names bound by the import must not shadow any of the variables in the template
+            let mut fresh = Symbols::new();
+            let format_arg = TypedIdent::new(fresh.simple_symbol("format_arg"));
+            let concat = TypedIdent::new(fresh.simple_symbol("concat"));
+
+            let parts = {
+                let symbols = env.symbols();
+                pieces
+                    .into_iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => sp(Expr::Literal(Literal::String(text))),
+                        Piece::Argument { path, spec } => {
+                            let mut path = path.into_iter();
+                            let root = sp(Expr::Ident(TypedIdent::new(
+                                symbols.simple_symbol(path.next().unwrap()),
+                            )));
+                            let value = path.fold(root, |expr, field| {
+                                sp(Expr::Projection(
+                                    arena.alloc(expr),
+                                    symbols.simple_symbol(field),
+                                    Type::hole(),
+                                ))
+                            });
+                            sp(Expr::app(
+                                arena.borrow(),
+                                sp(Expr::Ident(format_arg.clone())),
+                                vec![sp(Expr::Literal(Literal::String(spec.to_string()))), value],
+                            ))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let mut import = sp(Expr::app(
+                arena.borrow(),
+                sp(Expr::Ident(TypedIdent::new(fresh.simple_symbol("import!")))),
+                vec![sp(Expr::Projection(
+                    arena.alloc(sp(Expr::Ident(TypedIdent::new(fresh.simple_symbol("std"))))),
+                    fresh.simple_symbol("format"),
+                    Type::hole(),
+                ))],
+            ));
+            env.run_once(&mut fresh, arena, &mut import).await;
+
+            let arena = arena.borrow();
+            let field = |name: &str, bind: &TypedIdent<Symbol>| PatternField::Value {
+                name: pos::spanned(span, Symbol::from(name)),
+                value: Some(pos::spanned(span, Pattern::Ident(bind.clone()))),
+            };
+            let out = sp(Expr::let_binding(
+                arena,
+                ValueBinding {
+                    metadata: Default::default(),
+                    name: pos::spanned(
+                        span,
+                        Pattern::Record {
+                            implicit_import: Some(pos::spanned(
+                                span,
+                                Symbol::from("implicit_import"),
+                            )),
+                            typ: Type::hole(),
+                            fields: arena.alloc_extend(vec![
+                                field("format_arg", &format_arg),
+                                field("concat", &concat),
+                            ]),
+                        },
+                    ),
+                    typ: None,
+                    resolved_type: Default::default(),
+                    args: &mut [],
+                    expr: import,
+                },
+                sp(Expr::app(
+                    arena,
+                    sp(Expr::Ident(concat.clone())),
+                    vec![sp(Expr::Array(ast::Array {
+                        typ: Type::hole(),
+                        exprs: arena.alloc_extend(parts),
+                    }))],
+                )),
+            ));
+
+            Ok(out.into())
+        })
+    }
+}
@@ -8,7 +8,8 @@ use std::{
     mem,
     ops::{Deref, DerefMut},
     path::PathBuf,
-    sync::{Arc, Mutex, MutexGuard, RwLock},
+    result::Result as StdResult,
+    sync::{atomic, Arc, Mutex, MutexGuard, RwLock},
 };
 
 use {
@@ -22,8 +23,10 @@ use {
 };
 
 use crate::base::{
-    ast::{self, expr_to_path, Expr, Literal, SpannedExpr},
-    filename_to_module, pos,
+    ast::{self, expr_to_path, Expr, Literal, SpannedExpr, TypedIdent},
+    filename_to_module,
+    fnv::FnvSet,
+    pos,
     source::FileId,
     symbol::Symbol,
     types::ArcType,
@@ -31,6 +34,7 @@ use crate::base::{
 
 use crate::vm::{
     self,
+    api::{Userdata, UserdataMethods, VmType},
     gc::Trace,
     macros::{Error as MacroError, Macro, MacroExpander, MacroFuture},
     thread::{RootedThread, Thread},
@@ -79,6 +83,14 @@ impl base::error::AsDiagnostic for Error {
 
 include!(concat!(env!("OUT_DIR"), "/std_modules.rs"));
 
+/// Returns true if `module` is compiled from the standard library embedded in the binary, rather
+/// than from a file, a resolver or a module added with `load_script`
+pub(crate) fn is_embedded_std_module(db: &(dyn Compilation + '_), module: &str) -> bool {
+    db.compiler_settings().use_standard_lib
+        && !db.state().inline_modules.contains_key(module)
+        && STD_LIBS.iter().any(|tup| tup.0 == module)
+}
+
 #[async_trait]
 pub trait Importer: Any + Clone + Sync + Send {
     async fn import(
@@ -141,6 +153,7 @@ pub(crate) trait ImportApi: Send + Sync {
         module: &str,
         filename: &str,
     ) -> Result<Cow<'static, str>, Error>;
+    fn module_path(&self, use_standard_lib: bool, module: &str, filename: &str) -> Option<PathBuf>;
     async fn load_module(
         &self,
         compiler: &mut ModuleCompiler<'_, '_>,
@@ -168,6 +181,9 @@ where
     ) -> Result<Cow<'static, str>, Error> {
         Self::get_module_source(self, use_standard_lib, module, filename)
     }
+    fn module_path(&self, use_standard_lib: bool, module: &str, filename: &str) -> Option<PathBuf> {
+        Self::module_path(self, use_standard_lib, module, filename)
+    }
     async fn load_module(
         &self,
         compiler: &mut ModuleCompiler<'_, '_>,
@@ -191,6 +207,87 @@ where
     }
 }
 
+/// Provides the source code of modules loaded by `import!` from somewhere other than the
+/// filesystem, such as memory, an archive or a database.
+///
+/// Resolvers are asked, in the order they were added, before the import paths are searched.
+/// Diagnostics refer to the module by its name so they look the same whether or not the source
+/// came from a file.
+pub trait ImportResolver: Send + Sync {
+    /// Returns the source code of `module`, or `None` if this resolver does not provide it.
+    /// `filename` is the path which the module would have relative to an import path
+    /// (`std/io.glu` for `std.io`).
+    fn resolve(&self, module: &str, filename: &str) -> Result<Option<Cow<'static, str>>, Error>;
+
+    /// Lists the modules which this resolver provides
+    fn modules(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Module sources which are compiled into the binary, usually created with
+/// `gluon_codegen::embed_modules!`
+#[derive(Clone, Debug)]
+pub struct EmbeddedModules {
+    modules: &'static [(&'static str, &'static str)],
+}
+
+impl EmbeddedModules {
+    /// Creates a resolver from pairs of module names and sources
+    pub const fn new(modules: &'static [(&'static str, &'static str)]) -> Self {
+        EmbeddedModules { modules }
+    }
+}
+
+impl ImportResolver for EmbeddedModules {
+    fn resolve(&self, module: &str, _filename: &str) -> Result<Option<Cow<'static, str>>, Error> {
+        Ok(self
+            .modules
+            .iter()
+            .find(|(name, _)| *name == module)
+            .map(|(_, source)| Cow::Borrowed(*source)))
+    }
+
+    fn modules(&self) -> Vec<String> {
+        self.modules
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// A package whose modules are imported as `name.module`, usually a dependency declared in a
+/// `gluon.toml` manifest (see `crate::project`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub name: String,
+    /// The directories which the modules of the package are looked up in, in order
+    pub source_dirs: Vec<PathBuf>,
+    /// The file loaded by `import! name`, if any
+    pub entry: Option<PathBuf>,
+}
+
+impl Package {
+    /// Returns the files which `module` may be loaded from, or `None` if `module` is not part of
+    /// this package
+    fn candidates(&self, module: &str) -> Option<Vec<PathBuf>> {
+        if module == self.name {
+            return Some(self.entry.iter().cloned().collect());
+        }
+        let module = module
+            .strip_prefix(&self.name[..])
+            .and_then(|rest| rest.strip_prefix('.'))?;
+        let mut filename = module.replace(".", "/");
+        filename.push_str(".glu");
+        Some(
+            self.source_dirs
+                .iter()
+                .map(|dir| dir.join(&filename))
+                .collect(),
+        )
+    }
+}
+
 /// Macro which rewrites occurances of `import! "filename"` to a load of that file if it is not
 /// already loaded and then a global access to the loaded module
 pub struct Import<I = DefaultImporter> {
@@ -198,6 +295,11 @@ pub struct Import<I = DefaultImporter> {
     pub importer: I,
 
     pub compiler: Mutex<CompilerDatabase>,
+
+    resolvers: RwLock<Vec<Box<dyn ImportResolver>>>,
+    packages: RwLock<Vec<Package>>,
+    /// The modules of the standard library which may be imported, `None` if all of them can
+    std_modules: RwLock<Option<FnvSet<String>>>,
 }
 
 #[derive(Debug)]
@@ -237,9 +339,27 @@ impl<I> Import<I> {
             paths: RwLock::new(vec![PathBuf::from(".")]),
             compiler: CompilerDatabase::new_base(None).into(),
             importer: importer,
+            resolvers: RwLock::new(Vec::new()),
+            packages: RwLock::new(Vec::new()),
+            std_modules: RwLock::new(None),
         }
     }
 
+    /// Adds a package whose modules are looked up in its own directories instead of the import
+    /// paths. Packages are searched after the resolvers.
+    pub fn add_package(&self, package: Package) {
+        self.packages.write().unwrap().push(package);
+    }
+
+    /// Adds a resolver which is asked for the source of modules before the import paths are
+    /// searched
+    pub fn add_resolver<R>(&self, resolver: R)
+    where
+        R: ImportResolver + 'static,
+    {
+        self.resolvers.write().unwrap().push(Box::new(resolver));
+    }
+
     /// Adds a path to the list of paths which the importer uses to find files
     pub fn add_path<P: Into<PathBuf>>(&self, path: P) {
         self.paths.write().unwrap().push(path.into());
@@ -249,6 +369,54 @@ impl<I> Import<I> {
         *self.paths.write().unwrap() = paths;
     }
 
+    /// Only allows the modules of the standard library in `modules`, and the modules that they
+    /// import, to be imported. `None` allows every module of the standard library.
+    ///
+    /// The imports are found by reading the sources of the modules so this must be called after
+    /// the import paths are set.
+    pub fn set_std_modules(&self, use_standard_lib: bool, modules: Option<Vec<String>>) {
+        let modules = modules.map(|modules| self.std_module_closure(use_standard_lib, modules));
+        *self.std_modules.write().unwrap() = modules;
+    }
+
+    fn std_module_closure(&self, use_standard_lib: bool, modules: Vec<String>) -> FnvSet<String> {
+        let mut stack = modules;
+        // The implicit prelude is inserted into every module and `VmBuilder` imports `std.types`
+        // and `std.prim` while building the vm
+        stack.extend(imports_in_source(crate::PRELUDE));
+        stack.extend(vec!["std.types".to_string(), "std.prim".to_string()]);
+
+        let mut closure = FnvSet::default();
+        while let Some(module) = stack.pop() {
+            if closure.contains(&module) {
+                continue;
+            }
+            let mut filename = module.replace(".", "/");
+            filename.push_str(".glu");
+            // Extern modules do not have a source
+            if let Ok(source) = self.get_module_source(use_standard_lib, &module, &filename) {
+                stack.extend(imports_in_source(&source));
+            }
+            closure.insert(module);
+        }
+        closure
+    }
+
+    fn check_std_module(&self, module: &str) -> Result<(), Error> {
+        match &*self.std_modules.read().unwrap() {
+            Some(std_modules)
+                if (module == "std" || module.starts_with("std."))
+                    && !std_modules.contains(module) =>
+            {
+                Err(Error::String(format!(
+                    "Module '{}' is not one of the standard library modules that this vm loads",
+                    module
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn modules(&self, compiler: &mut ModuleCompiler<'_, '_>) -> Vec<Cow<'static, str>> {
         STD_LIBS
             .iter()
@@ -260,6 +428,14 @@ impl<I> Import<I> {
                     .into_iter()
                     .map(|entry| Cow::Owned(entry.key)),
             )
+            .chain(
+                self.resolvers
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .flat_map(|resolver| resolver.modules())
+                    .map(Cow::Owned),
+            )
             .collect()
     }
 
@@ -311,40 +487,91 @@ impl<I> Import<I> {
         } else {
             None
         };
-        Ok(match std_file {
-            Some(tup) => Cow::Borrowed(tup.1),
-            None => {
-                let paths = self.paths.read().unwrap();
-                let file = paths
+        if let Some(tup) = std_file {
+            return Ok(Cow::Borrowed(tup.1));
+        }
+
+        for resolver in self.resolvers.read().unwrap().iter() {
+            if let Some(source) = resolver.resolve(module, filename)? {
+                return Ok(source);
+            }
+        }
+
+        let (package, candidates) = self.candidates(module, filename);
+        let file = candidates
+            .iter()
+            .filter_map(|path| File::open(path).ok())
+            .next();
+        let mut file = file.ok_or_else(|| {
+            let format_paths = |paths: &[PathBuf]| {
+                paths
                     .iter()
-                    .filter_map(|p| {
-                        let base = p.join(filename);
-                        match File::open(&base) {
-                            Ok(file) => Some(file),
-                            Err(_) => None,
-                        }
-                    })
-                    .next();
-                let mut file = file.ok_or_else(|| {
-                    Error::String(format!(
-                        "Could not find module '{}'. Searched {}.",
-                        module,
-                        paths
-                            .iter()
-                            .map(|p| format!("`{}`", p.display()))
-                            .format(", ")
-                    ))
-                })?;
-                file.read_to_string(&mut buffer)
-                    .map_err(|err| Error::IO(err.into()))?;
-                Cow::Owned(buffer)
+                    .map(|p| format!("`{}`", p.display()))
+                    .join(", ")
+            };
+            Error::String(match package {
+                Some(package) if candidates.is_empty() => format!(
+                    "Could not find module '{}'. Package '{}' does not have an entry module.",
+                    module, package
+                ),
+                Some(package) => format!(
+                    "Could not find module '{}' in package '{}'. Searched {}.",
+                    module,
+                    package,
+                    format_paths(&candidates)
+                ),
+                None => format!(
+                    "Could not find module '{}'. Searched {}.",
+                    module,
+                    format_paths(&self.paths.read().unwrap())
+                ),
+            })
+        })?;
+        file.read_to_string(&mut buffer)
+            .map_err(|err| Error::IO(err.into()))?;
+        Ok(Cow::Owned(buffer))
+    }
+
+    /// Returns the path of the file that `get_module_source` reads `module` from, or `None` if
+    /// the source comes from the standard library embedded in the binary or from a resolver
+    pub(crate) fn module_path(
+        &self,
+        use_standard_lib: bool,
+        module: &str,
+        filename: &str,
+    ) -> Option<PathBuf> {
+        if use_standard_lib && STD_LIBS.iter().any(|tup| tup.0 == module) {
+            return None;
+        }
+        for resolver in self.resolvers.read().unwrap().iter() {
+            if let Ok(Some(_)) = resolver.resolve(module, filename) {
+                return None;
             }
-        })
+        }
+        self.candidates(module, filename)
+            .1
+            .into_iter()
+            .find(|path| path.is_file())
+    }
+
+    /// Returns the files which `module` may be loaded from along with the name of the package it
+    /// belongs to. Modules which are not in a package are looked for in the import paths.
+    fn candidates(&self, module: &str, filename: &str) -> (Option<String>, Vec<PathBuf>) {
+        for package in self.packages.read().unwrap().iter() {
+            if let Some(candidates) = package.candidates(module) {
+                return (Some(package.name.clone()), candidates);
+            }
+        }
+        let paths = self.paths.read().unwrap();
+        (None, paths.iter().map(|p| p.join(filename)).collect())
     }
 }
 
 /// Adds an extern module to `thread`, letting it be loaded with `import! name` from gluon code.
 ///
+/// `loader` is not called until the module is imported for the first time, see
+/// `add_extern_module_lazy`.
+///
 /// ```
 /// use gluon::vm::{self, ExternModule};
 /// use gluon::{primitive, record, Thread, ThreadExt};
@@ -409,6 +636,64 @@ pub fn add_extern_module_with_deps<F>(
     )
 }
 
+/// Adds the extern module `name` to `thread` without building it. `loader` builds the record of
+/// the module the first time that it is imported, so a host with a large API only pays for the
+/// modules which scripts use. The module is then cached like any other module.
+///
+/// `name` may be a dotted path so that the modules of a host API share a namespace instead of
+/// clashing with each other or with the standard library.
+///
+/// ```
+/// use gluon::vm::{self, ExternModule};
+/// use gluon::{primitive, record, Thread, ThreadExt};
+/// use gluon::import::add_extern_module_lazy;
+///
+/// fn graphics(thread: &Thread) -> vm::Result<ExternModule> {
+///     ExternModule::new(
+///         thread,
+///         record!{
+///             area => primitive!(2, |w: f64, h: f64| w * h)
+///         }
+///     )
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> gluon::Result<()> {
+///     let thread = gluon::new_vm_async().await;
+///     add_extern_module_lazy(&thread, "host.graphics", graphics);
+///     let script = r#"
+///         let graphics = import! host.graphics
+///         graphics.area 2.0 3.0
+///     "#;
+///     let (result, _) = thread.run_expr_async::<f64>("example", script).await?;
+///     assert_eq!(result, 6.0);
+///     Ok(())
+/// }
+/// ```
+pub fn add_extern_module_lazy<F>(thread: &Thread, name: &str, loader: F)
+where
+    F: Fn(&Thread) -> vm::Result<ExternModule> + Send + Sync + 'static,
+{
+    // Every extern module is only loaded on its first import, `loader` is just stored until then
+    add_extern_module(thread, name, loader)
+}
+
+/// Adds the module `name` which contains the type `T` together with the methods and instances
+/// that `T` exposes through `Userdata::methods`. `T` must be registered with
+/// `Thread::register_type` before the module is imported.
+pub fn add_userdata_module<T>(thread: &Thread, name: &str)
+where
+    T: Userdata + VmType,
+{
+    let dependencies = UserdataMethods::<T>::of().dependencies();
+    add_extern_module_with_deps(
+        thread,
+        name,
+        |thread| UserdataMethods::<T>::of().build(thread),
+        dependencies,
+    )
+}
+
 fn add_extern_module_(thread: &Thread, name: &str, loader: ExternLoader) {
     thread
         .get_database_mut()
@@ -513,6 +798,10 @@ where
 
         info!("import! {}", modulename);
 
+        if let Err(err) = self.check_std_module(&modulename) {
+            return Box::pin(future::err(MacroError::new(err)));
+        }
+
         let mut db = try_future!(macros
             .userdata
             .fork(macros.vm.root_thread())
@@ -530,7 +819,7 @@ where
             let (tx, rx) = tokio::sync::oneshot::channel();
             spawn
                 .spawn(Box::pin(async move {
-                    let result = std::panic::AssertUnwindSafe(db.import(modulename))
+                    let result = std::panic::AssertUnwindSafe(import_module(&mut db, modulename))
                         .catch_unwind()
                         .await
                         .map(|r| r.map_err(|err| MacroError::message(err.to_string())))
@@ -561,8 +850,7 @@ where
         Box::pin(async move {
             Ok(From::from(move || {
                 async move {
-                    let result = db
-                        .import(modulename)
+                    let result = import_module(&mut db, modulename)
                         .await
                         .map_err(|err| MacroError::message(err.to_string()))
                         .map(move |id| pos::spanned(span, Expr::Ident(id)));
@@ -575,6 +863,37 @@ where
     }
 }
 
+/// Returns the modules which are imported with `import! path.to.module` in `source`, ignoring the
+/// examples in comments
+fn imports_in_source(source: &str) -> impl Iterator<Item = String> + '_ {
+    source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .flat_map(|line| line.split("import!").skip(1))
+        .filter_map(|rest| {
+            let rest = rest.trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                None
+            } else {
+                Some(rest[..end].to_string())
+            }
+        })
+}
+
+async fn import_module(
+    db: &mut salsa::Snapshot<CompilerDatabase>,
+    modulename: String,
+) -> StdResult<TypedIdent<Symbol>, crate::Error> {
+    if db.typecheck_only.load(atomic::Ordering::SeqCst) {
+        crate::query::import_type(db, modulename).await
+    } else {
+        db.import(modulename).await
+    }
+}
+
 unsafe impl<I> Trace for Import<I> {
     impl_trace! { self, _gc, () }
 }
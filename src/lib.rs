@@ -45,19 +45,32 @@ macro_rules! try_future {
     };
 }
 
+pub mod compiler_pass;
 pub mod compiler_pipeline;
+mod decimal_macro;
+mod format_macro;
 #[macro_use]
 pub mod import;
 pub mod lift_io;
+pub mod native;
+pub mod project;
 #[doc(hidden)]
 pub mod query;
+#[cfg(feature = "serialization")]
+mod snapshot;
 pub mod std_lib;
+mod type_at;
 
 pub use crate::vm::{
     field_decl, primitive, record, record_p, record_type,
     thread::{RootedThread, Thread},
 };
 
+/// Marshalling of `serde` types, see `vm::api::json::Json` for types which do not implement
+/// `VmType`
+#[cfg(feature = "serialization")]
+pub use crate::vm::api::{de, ser};
+
 use either::Either;
 
 use std as real_std;
@@ -70,18 +83,20 @@ use crate::base::{
     error::{Errors, InFile},
     filename_to_module,
     metadata::Metadata,
-    pos::{BytePos, Span, Spanned},
-    source::FileId,
+    origin::OriginMap,
+    pos::{self, BytePos, Span, Spanned},
+    source::{self, FileId},
     symbol::{Symbol, Symbols},
     types::{ArcType, TypeCache},
 };
 
-use crate::format::Formatter;
+use crate::format::{Formatter, TextEdit};
 
 use crate::vm::{
-    api::{Getable, Hole, OpaqueValue, VmType},
+    api::{Getable, Hole, OpaqueValue, OwnedFunction, Pushable, VmType},
     compiler::CompiledModule,
     macros,
+    types::VmInt,
 };
 
 use crate::{
@@ -124,6 +139,11 @@ pub enum Error {
         display("{}", err)
         from()
     }
+    /// Error reported by a `CompilerPass`
+    Pass(err: InFile<compiler_pass::PassError>) {
+        display("{}", err)
+        from()
+    }
     /// Multiple errors where found
     Multiple(err: Errors<Error>) {
         display("{}", err)
@@ -269,6 +289,7 @@ impl Error {
             Error::VM(err) => write!(writer, "{}", err),
             Error::Macro(err) => err.emit(writer),
             Error::Other(err) => write!(writer, "{}", err),
+            Error::Pass(err) => err.emit(writer),
             Error::Multiple(errors) => {
                 for err in errors {
                     err.emit(writer)?;
@@ -290,6 +311,9 @@ pub struct Settings {
     pub use_standard_lib: bool,
     pub optimize: bool,
     pub run_io: bool,
+    pub coverage: bool,
+    pub passes: compiler_pass::Passes,
+    pub forbidden_effects: Vec<String>,
 }
 
 impl Default for Settings {
@@ -301,6 +325,9 @@ impl Default for Settings {
             use_standard_lib: true,
             optimize: true,
             run_io: false,
+            coverage: false,
+            passes: Default::default(),
+            forbidden_effects: Vec::new(),
         }
     }
 }
@@ -331,6 +358,8 @@ impl<'a, 'b> IntoDb<'a, 'b> for &'a mut salsa::Snapshot<CompilerDatabase> {
 pub struct ModuleCompiler<'a, 'b> {
     pub database: salsa::OwnedDb<'a, dyn Compilation + 'b>,
     symbols: Symbols,
+    /// The origins recorded by the stages run so far, moved into the `TypecheckValue`
+    origins: OriginMap,
 }
 
 impl<'a, 'b> ModuleCompiler<'a, 'b> {
@@ -338,6 +367,7 @@ impl<'a, 'b> ModuleCompiler<'a, 'b> {
         Self {
             database: database.into_db(),
             symbols: Symbols::default(),
+            origins: OriginMap::new(),
         }
     }
 }
@@ -369,6 +399,20 @@ macro_rules! option {
 };
 }
 
+macro_rules! capability_option {
+($(#[$attr:meta])* $name: ident $set_name: ident : $capability: ident) => {
+    $(#[$attr])*
+    pub fn $name(mut self, allow: bool) -> Self {
+        self.$set_name(allow);
+        self
+    }
+
+    pub fn $set_name(&mut self, allow: bool) {
+        self.capabilities.$capability = allow;
+    }
+};
+}
+
 macro_rules! runtime_option {
 ($(#[$attr:meta])* $name: ident $set_name: ident : $typ: ty) => {
     $(#[$attr])*
@@ -422,6 +466,31 @@ impl import::DatabaseMut {
         /// (default: false)
         run_io set_run_io: bool
     }
+
+    runtime_option! {
+        /// Sets whether the compiled code counts how often each of its lines is executed, see
+        /// `vm::coverage`. Code which the optimizer removes is not counted, see `set_optimize`.
+        /// (default: false)
+        coverage set_coverage: bool
+    }
+
+    runtime_option! {
+        /// Sets the effects which modules are not allowed to perform. Every module outside of the
+        /// embedded standard library which uses a value that performs one of the effects, either a
+        /// type such as `IO` or the label of an `Eff` row such as `io`, fails to typecheck.
+        /// See `check::effects`.
+        /// (default: [])
+        forbidden_effects set_forbidden_effects: Vec<String>
+    }
+
+    /// Adds a pass which runs over every module compiled after this call, see
+    /// `compiler_pass::CompilerPass`
+    pub fn add_pass(mut self, pass: impl compiler_pass::CompilerPass + 'static) -> Self {
+        let mut settings = self.compiler_settings();
+        settings.passes = settings.passes.add(Arc::new(pass));
+        self.set_compiler_settings(settings);
+        self
+    }
 }
 
 /// Extension trait which provides methods to load and execute gluon code
@@ -522,6 +591,94 @@ pub trait ThreadExt: Send + Sync {
         Ok((expr, typ))
     }
 
+    /// Parses and typechecks `expr_str`, returning its type, without running any gluon code.
+    /// Modules loaded by `import!` are typechecked but, unlike when the expression is run, not
+    /// evaluated, which makes this suitable for validating scripts while they are edited.
+    ///
+    /// ```
+    /// # use gluon::{new_vm, ThreadExt};
+    /// # use gluon::base::types::Type;
+    /// # fn main() {
+    /// # if ::std::env::var("GLUON_PATH").is_err() {
+    /// #     ::std::env::set_var("GLUON_PATH", "..")
+    /// # }
+    /// let vm = new_vm();
+    /// let typ = vm
+    ///     .typecheck_str_only("example", "let string = import! std.string in string.trim")
+    ///     .unwrap();
+    /// assert_eq!(typ, Type::function(vec![Type::string()], Type::string()));
+    /// # }
+    /// ```
+    fn typecheck_str_only(&self, file: &str, expr_str: &str) -> Result<ArcType> {
+        futures::executor::block_on(self.typecheck_str_only_async(file, expr_str))
+    }
+
+    /// Parses and typechecks `expr_str` without running any gluon code. See `typecheck_str_only`
+    async fn typecheck_str_only_async(&self, file: &str, expr_str: &str) -> Result<ArcType> {
+        let vm = self.thread();
+        {
+            let mut db = vm.get_database_mut();
+            db.add_module(file.into(), expr_str.into());
+        }
+        let mut db = vm.get_database();
+        db.typecheck_only
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+
+        let TypecheckValue { typ, .. } = db.typechecked_source_module(file.into(), None).await?;
+
+        // Ensure the type is stored in the database so we can collect typechecked_module later
+        db.module_type(file.into(), None).await?;
+        db.module_metadata(file.into(), None).await?;
+
+        Ok(typ)
+    }
+
+    /// Returns the type of the innermost expression, pattern or identifier at `byte_offset` in
+    /// the source of `module`. The module is typechecked if it has not already been but it is not
+    /// compiled or run, which makes this a lightweight alternative to the language server for
+    /// hover style queries.
+    ///
+    /// Returns `None` if the module fails to typecheck or if nothing is at `byte_offset`.
+    ///
+    /// ```
+    /// # use gluon::{new_vm, ThreadExt};
+    /// # use gluon::base::types::Type;
+    /// # fn main() {
+    /// # if ::std::env::var("GLUON_PATH").is_err() {
+    /// #     ::std::env::set_var("GLUON_PATH", "..")
+    /// # }
+    /// let vm = new_vm();
+    /// vm.typecheck_str_only("example", "let x = 1.0 in x + 2.0").unwrap();
+    /// assert_eq!(vm.type_at("example", 15), Some(Type::float()));
+    /// assert_eq!(vm.type_at("example", 100), None);
+    /// # }
+    /// ```
+    fn type_at(&self, module: &str, byte_offset: usize) -> Option<ArcType> {
+        futures::executor::block_on(self.type_at_async(module, byte_offset))
+    }
+
+    /// Returns the type at `byte_offset` in `module`. See `type_at`
+    async fn type_at_async(&self, module: &str, byte_offset: usize) -> Option<ArcType> {
+        let vm = self.thread();
+        let mut db = vm.get_database();
+        let value = match db.peek_typechecked_source_module(module) {
+            Some(value) => value,
+            None => {
+                db.typecheck_only
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+                db.typechecked_source_module(module.into(), None)
+                    .await
+                    .ok()?
+            }
+        };
+        let file_map = db.get_filemap(module)?;
+        let pos = file_map.span().start() + pos::ByteOffset::from(byte_offset as i64);
+        if !file_map.span().contains_pos(pos) {
+            return None;
+        }
+        type_at::type_at(&db.as_env(), &value.expr.expr(), pos)
+    }
+
     /// Compiles `expr` into a function which can be added and run by the `vm`
     async fn compile_script(
         &self,
@@ -535,6 +692,7 @@ pub trait ThreadExt: Send + Sync {
             typ: vm.global_env().type_cache().hole(),
             metadata: Default::default(),
             metadata_map: Default::default(),
+            origins: Default::default(),
         }
         .compile(
             &mut ModuleCompiler::new(&mut vm.get_database()),
@@ -593,6 +751,43 @@ pub trait ThreadExt: Send + Sync {
             .await
     }
 
+    /// Serializes the values of every module loaded into the vm so that they can be restored
+    /// later with `load_snapshot`.
+    ///
+    /// The vm should not be running any code while the snapshot is taken. Modules defined in Rust
+    /// are not included as they are loaded again by the vm which restores the snapshot, userdata
+    /// is only serialized if hooks have been registered for it with
+    /// `Thread::register_userdata_hooks`.
+    #[cfg(feature = "serialization")]
+    fn save_snapshot<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        snapshot::save(self.thread(), serializer)
+    }
+
+    /// Restores the modules from a snapshot created by `save_snapshot`. The modules are
+    /// typechecked from the source stored in the snapshot when they are imported but their values
+    /// are taken from the snapshot instead of running the module again.
+    ///
+    /// The snapshot should be loaded before any of its modules have been imported.
+    #[cfg(feature = "serialization")]
+    fn load_snapshot<'de, D>(&self, deserializer: D) -> Result<()>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        snapshot::load(self.thread(), deserializer)
+    }
+
+    /// Loads the native module in the dynamic library at `path` and returns the name it can be
+    /// imported as. See the `native` module for how to define a native module.
+    fn load_native_module<P>(&self, path: P) -> Result<String>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        native::load(self.thread(), path.as_ref())
+    }
+
     /// Parses and typechecks `expr_str` followed by extracting metadata from the created
     /// expression
     async fn extract_metadata(
@@ -654,6 +849,61 @@ pub trait ThreadExt: Send + Sync {
         db.import(module_name).await.map(|_| ())
     }
 
+    /// Recompiles the already loaded module `name` from `input` and replaces the old module with
+    /// the result.
+    ///
+    /// The new module is typechecked against the type of the module it replaces so code which
+    /// was written against the old module keeps working. If the new module fails to compile or
+    /// its type is incompatible the error is returned and the old module is left in place.
+    ///
+    /// Only code compiled after the reload sees the new module, closures which were created
+    /// before refer to the values of the old module.
+    fn reload_module(&self, name: &str, input: &str) -> Result<()> {
+        futures::executor::block_on(self.reload_module_async(name, input))
+    }
+
+    async fn reload_module_async(&self, name: &str, input: &str) -> Result<()> {
+        let module_name = filename_to_module(name);
+
+        let vm = self.thread();
+        let (old_type, old_input) = {
+            let db = vm.get_database();
+            let old_type = db
+                .peek_global(&module_name)
+                .map(|global| global.typ)
+                .ok_or_else(|| vm::Error::UndefinedBinding(module_name.clone()))?;
+            (old_type, db.module_text(module_name.clone())?)
+        };
+
+        // Check the new module before it is added to the database so that a failure does not
+        // affect the old module
+        let check_result = input
+            .typecheck_expected(
+                &mut ModuleCompiler::new(&mut vm.get_database()),
+                vm,
+                &module_name,
+                input,
+                Some(&old_type),
+            )
+            .await;
+        if let Err(err) = check_result {
+            vm.get_database().add_filemap(&module_name, &old_input[..]);
+            return Err(err.error);
+        }
+
+        {
+            let mut db = vm.get_database_mut();
+            db.replace_module(module_name.clone(), input);
+        }
+        let result = vm.get_database().import(module_name.clone()).await;
+        if let Err(err) = result {
+            let mut db = vm.get_database_mut();
+            db.replace_module(module_name, &old_input[..]);
+            return Err(err);
+        }
+        Ok(())
+    }
+
     /// Loads `filename` and compiles and runs its input by calling `load_script`
     fn load_file<'vm>(&'vm self, filename: &str) -> Result<()> {
         futures::executor::block_on(self.load_file_async(filename))
@@ -747,11 +997,186 @@ pub trait ThreadExt: Send + Sync {
             )
             .await?;
         Ok((
-            T::from_value(vm, execute_value.value.get_variant()),
+            crate::vm::api::try_from_value(vm, execute_value.value.get_variant())?,
             execute_value.typ,
         ))
     }
 
+    /// Compiles and runs `expr_str` with each of `bindings` in scope as a local variable. Useful
+    /// for evaluating small formulas over values from the host without defining a module or
+    /// adding globals.
+    ///
+    /// Each binding name must be an identifier, otherwise an error is returned without compiling
+    /// `expr_str`.
+    ///
+    /// ```
+    /// # use gluon::{new_vm, ThreadExt};
+    /// # fn main() {
+    /// # if ::std::env::var("GLUON_PATH").is_err() {
+    /// #     ::std::env::set_var("GLUON_PATH", "..")
+    /// # }
+    /// let vm = new_vm();
+    /// let (result, _) = vm
+    ///     .run_expr_with::<f64, f64>("cell", "a * b + 1.0", &[("a", 2.0), ("b", 3.0)])
+    ///     .unwrap();
+    /// assert_eq!(result, 7.0);
+    /// # }
+    /// ```
+    fn run_expr_with<'vm, T, V>(
+        &'vm self,
+        name: &str,
+        expr_str: &str,
+        bindings: &[(&str, V)],
+    ) -> Result<(T, ArcType)>
+    where
+        T: for<'value> Getable<'vm, 'value> + VmType + Send + 'vm,
+        V: for<'v> Pushable<'v> + VmType + Clone + Send + Sync,
+    {
+        futures::executor::block_on(self.run_expr_with_async(name, expr_str, bindings))
+    }
+
+    /// Compiles and runs `expr_str` with each of `bindings` in scope as a local variable. See
+    /// `run_expr_with`
+    async fn run_expr_with_async<'vm, T, V>(
+        &'vm self,
+        name: &str,
+        expr_str: &str,
+        bindings: &[(&str, V)],
+    ) -> Result<(T, ArcType)>
+    where
+        T: for<'value> Getable<'vm, 'value> + VmType + Send + 'vm,
+        V: for<'v> Pushable<'v> + VmType + Clone + Send + Sync,
+    {
+        if bindings.is_empty() {
+            return self.run_expr_async(name, expr_str).await;
+        }
+
+        if let Some((binding, _)) = bindings.iter().find(|(binding, _)| !is_identifier(binding)) {
+            return Err(Error::VM(crate::vm::Error::Message(format!(
+                "`{}` is not a valid name for a binding",
+                binding
+            ))));
+        }
+
+        let vm = self.thread();
+        let type_cache = vm.global_env().type_cache();
+        let mut db = vm.get_database();
+        let mut compiler = ModuleCompiler::new(&mut db);
+
+        // Compile the expression as a lambda taking the bindings as arguments by wrapping the
+        // parsed expression, so that the source of the expression is left as is
+        let mut expr =
+            parse_expr(&mut compiler, type_cache, name, expr_str).map_err(InFile::from)?;
+        {
+            let (arena, body) = expr.arena_expr();
+            let span = body.span;
+            let args = arena.alloc_extend(bindings.iter().map(|(binding, _)| {
+                ast::Argument::explicit(pos::spanned(
+                    span,
+                    ast::TypedIdent {
+                        name: compiler.symbols.simple_symbol(*binding),
+                        typ: type_cache.hole(),
+                    },
+                ))
+            }));
+            let original_body = std::mem::take(body);
+            *body = pos::spanned(
+                span,
+                ast::Expr::Lambda(ast::Lambda {
+                    id: ast::TypedIdent {
+                        name: compiler.symbols.simple_symbol(""),
+                        typ: type_cache.hole(),
+                    },
+                    args,
+                    body: arena.alloc(original_body),
+                }),
+            );
+        }
+
+        let expected =
+            type_cache.function(bindings.iter().map(|_| V::make_type(vm)), T::make_type(vm));
+        let execute_value = expr
+            .run_expr(&mut compiler, vm, name, expr_str, Some(&expected))
+            .await?;
+
+        let mut typ = execute_value.typ;
+        for _ in bindings {
+            typ = match typ.as_function() {
+                Some((_, ret)) => ret.clone(),
+                None => break,
+            };
+        }
+
+        // Apply the bindings one at a time so the partially applied function can be kept in an
+        // `OpaqueValue` between calls
+        let mut value = OpaqueValue::<RootedThread, Hole>::from_value(execute_value.value);
+        for (_, binding) in bindings {
+            let mut function =
+                OwnedFunction::<fn(V) -> OpaqueValue<RootedThread, Hole>>::from_value(
+                    vm,
+                    value.get_variant(),
+                );
+            value = function.call_async(binding.clone()).await?;
+        }
+        // `IO` actions are run by passing an extra argument
+        for _ in 0..T::EXTRA_ARGS {
+            let mut function =
+                OwnedFunction::<fn(VmInt) -> OpaqueValue<RootedThread, Hole>>::from_value(
+                    vm,
+                    value.get_variant(),
+                );
+            value = function.call_async(0).await?;
+        }
+        Ok((
+            crate::vm::api::try_from_value(vm, value.get_variant())?,
+            typ,
+        ))
+    }
+
+    /// Compiles `expr_str`, which must evaluate to a function, and returns the function so that it
+    /// can be called many times without being recompiled.
+    ///
+    /// ```
+    /// # use gluon::{new_vm, ThreadExt};
+    /// # fn main() {
+    /// # if ::std::env::var("GLUON_PATH").is_err() {
+    /// #     ::std::env::set_var("GLUON_PATH", "..")
+    /// # }
+    /// let vm = new_vm();
+    /// let mut is_adult = vm
+    ///     .compile_function::<i32, bool>("rule", r#"\age -> age >= 18"#)
+    ///     .unwrap();
+    /// assert!(is_adult.call(30).unwrap());
+    /// assert!(!is_adult.call(12).unwrap());
+    /// # }
+    /// ```
+    fn compile_function<A, R>(
+        &self,
+        name: &str,
+        expr_str: &str,
+    ) -> Result<OwnedFunction<fn(A) -> R>>
+    where
+        A: for<'vm> Pushable<'vm> + VmType,
+        R: for<'vm, 'value> Getable<'vm, 'value> + VmType + Send + Sync + 'static,
+    {
+        futures::executor::block_on(self.compile_function_async(name, expr_str))
+    }
+
+    /// Compiles `expr_str`, which must evaluate to a function, and returns the function so that it
+    /// can be called many times without being recompiled. See `compile_function`
+    async fn compile_function_async<A, R>(
+        &self,
+        name: &str,
+        expr_str: &str,
+    ) -> Result<OwnedFunction<fn(A) -> R>>
+    where
+        A: for<'vm> Pushable<'vm> + VmType,
+        R: for<'vm, 'value> Getable<'vm, 'value> + VmType + Send + Sync + 'static,
+    {
+        let (function, _) = self.run_expr_async(name, expr_str).await?;
+        Ok(function)
+    }
+
     fn format_expr(&self, formatter: &mut Formatter, file: &str, input: &str) -> Result<String> {
         futures::executor::block_on(self.format_expr_async(formatter, file, input))
     }
@@ -762,41 +1187,106 @@ pub trait ThreadExt: Send + Sync {
         file: &str,
         input: &str,
     ) -> Result<String> {
-        fn has_format_disabling_errors(file: &str, err: &Error) -> bool {
-            match *err {
-                Error::Multiple(ref errors) => errors
-                    .iter()
-                    .any(|err| has_format_disabling_errors(file, err)),
-                Error::Parse(ref err) => err.source_name() == file,
-                _ => false,
+        let (file_map, expr, formatter) =
+            parse_for_format(self.thread(), formatter, file, input).await?;
+        let expr = skip_implicit_prelude(file_map.span(), &expr.expr());
+        if formatter.sort_imports {
+            if let Some(sorted) = format::imports::sort_imports(&*file_map, expr) {
+                let (file_map, expr, formatter) =
+                    parse_for_format(self.thread(), &formatter, file, &sorted).await?;
+                let expr = skip_implicit_prelude(file_map.span(), &expr.expr());
+                return Ok(formatter.pretty_expr(&*file_map, expr));
             }
         }
+        Ok(formatter.pretty_expr(&*file_map, expr))
+    }
 
-        let thread = self.thread();
-        let mut db = thread.get_database();
-        let mut compiler = ModuleCompiler::new(&mut db);
-        let compiler = &mut compiler;
-
-        let expr = match input.reparse_infix(compiler, thread, file, input).await {
-            Ok(expr) => expr.expr,
-            Err(Salvage {
-                value: Some(expr),
-                error,
-            }) => {
-                if has_format_disabling_errors(file, &error) {
-                    return Err(error);
-                }
-                expr.expr
-            }
-            Err(Salvage { value: None, error }) => return Err(error),
-        };
+    /// Formats the smallest expression enclosing `range`, where `range` is given as byte offsets
+    /// into `input`. The span of the returned edit is also relative to `input`.
+    fn format_range(
+        &self,
+        formatter: &mut Formatter,
+        file: &str,
+        input: &str,
+        range: Span<BytePos>,
+    ) -> Result<TextEdit> {
+        futures::executor::block_on(self.format_range_async(formatter, file, input, range))
+    }
 
-        let file_map = db.get_filemap(file).unwrap();
+    async fn format_range_async(
+        &self,
+        formatter: &mut Formatter,
+        file: &str,
+        input: &str,
+        range: Span<BytePos>,
+    ) -> Result<TextEdit> {
+        let (file_map, expr, formatter) =
+            parse_for_format(self.thread(), formatter, file, input).await?;
         let expr = skip_implicit_prelude(file_map.span(), &expr.expr());
-        Ok(formatter.pretty_expr(&*file_map, expr))
+
+        let start = file_map.span().start();
+        let to_file = |pos: BytePos| start + (pos - BytePos::default());
+        let range = Span::new(to_file(range.start()), to_file(range.end()));
+        let edit = formatter.format_range(&*file_map, expr, range);
+
+        let to_input = |pos: BytePos| BytePos::default() + (pos - start);
+        Ok(TextEdit {
+            span: Span::new(to_input(edit.span.start()), to_input(edit.span.end())),
+            ..edit
+        })
     }
 }
 
+/// Parses `input` the way the formatter expects it, failing only on errors which would make the
+/// formatted code differ from the input. The returned formatter also knows the fixities of the
+/// operators used in `input`.
+async fn parse_for_format(
+    thread: &Thread,
+    formatter: &Formatter,
+    file: &str,
+    input: &str,
+) -> Result<(Arc<source::FileMap>, OwnedExpr<Symbol>, Formatter)> {
+    fn has_format_disabling_errors(file: &str, err: &Error) -> bool {
+        match *err {
+            Error::Multiple(ref errors) => errors
+                .iter()
+                .any(|err| has_format_disabling_errors(file, err)),
+            Error::Parse(ref err) => err.source_name() == file,
+            _ => false,
+        }
+    }
+
+    let mut db = thread.get_database();
+    let mut compiler = ModuleCompiler::new(&mut db);
+    let compiler = &mut compiler;
+
+    let reparsed = match input.reparse_infix(compiler, thread, file, input).await {
+        Ok(reparsed) => reparsed,
+        Err(Salvage {
+            value: Some(reparsed),
+            error,
+        }) => {
+            if has_format_disabling_errors(file, &error) {
+                return Err(error);
+            }
+            reparsed
+        }
+        Err(Salvage { value: None, error }) => return Err(error),
+    };
+
+    let mut formatter = formatter.clone();
+    let op_table = parser::op_table(&reparsed.metadata_map, reparsed.expr.expr());
+    formatter.operators.operators.extend(
+        op_table
+            .operators
+            .into_iter()
+            .map(|(op, meta)| (op.declared_name().to_string(), meta)),
+    );
+
+    let file_map = db.get_filemap(file).unwrap();
+    Ok((file_map, reparsed.expr, formatter))
+}
+
 fn skip_implicit_prelude<'a, 'ast>(
     span: Span<BytePos>,
     mut l: &'a SpannedExpr<'ast, Symbol>,
@@ -865,6 +1355,22 @@ impl ModuleCompiler<'_, '_> {
     }
 }
 
+/// Returns true if `name` is lexed as an identifier and can therefore be bound as a variable
+fn is_identifier(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    let is_start = |b: u8| b == b'_' || b.is_ascii_alphabetic();
+    match bytes.next() {
+        Some(b) if is_start(b) => (),
+        _ => return false,
+    }
+    bytes.all(|b| is_start(b) || b.is_ascii_digit() || b == b'\'')
+        && match name {
+            "rec" | "else" | "forall" | "if" | "in" | "let" | "do" | "seq" | "match" | "then"
+            | "type" | "with" => false,
+            _ => true,
+        }
+}
+
 pub const PRELUDE: &'static str = r#"
 let __implicit_prelude = import! std.prelude
 let { IO, Num, Eq, Ord, Show, Functor, Applicative, Monad, Option, Bool, ? } = __implicit_prelude
@@ -891,9 +1397,32 @@ let __string_eq: String -> String -> Bool = (==)
 in ()
 "#;
 
+/// Configures and creates a virtual machine. Every setting which affects how scripts are loaded
+/// and run has a method here so that an embedding can be set up in one place.
+///
+/// ```
+/// let vm = gluon::VmBuilder::new()
+///     .import_paths(Some(vec![".".into(), "scripts".into()]))
+///     .memory_limit(Some(64 * 1024 * 1024))
+///     .allow_net(false)
+///     .allow_process(false)
+///     .build();
+/// ```
 #[derive(Default)]
 pub struct VmBuilder {
     import_paths: Option<Vec<PathBuf>>,
+    deterministic: bool,
+    capabilities: crate::vm::vm::Capabilities,
+    args: Option<Vec<String>>,
+    output: Option<crate::vm::vm::Output>,
+    memory_limit: Option<usize>,
+    collect_limit: Option<usize>,
+    max_stack_size: Option<crate::vm::types::VmIndex>,
+    compiler_settings: Settings,
+    spawner: Option<Box<dyn futures::task::Spawn + Send + Sync>>,
+    shared_modules: Option<query::SharedModules>,
+    packages: Vec<import::Package>,
+    std_modules: Option<Vec<String>>,
 }
 
 impl VmBuilder {
@@ -906,16 +1435,122 @@ impl VmBuilder {
         import_paths set_import_paths: Option<Vec<PathBuf>>
     }
 
-    pub fn build(self) -> RootedThread {
-        futures::executor::block_on(self.build_inner(None))
+    option! {
+        /// Makes running the same program with the same inputs always produce the same result.
+        /// Unseeded random numbers, the clock and timeouts are unavailable and values are shown
+        /// without the addresses of closures, including the values inside userdata. Hash maps
+        /// are iterated in an order which only depends on their keys in every mode.
+        /// (default: false)
+        deterministic set_deterministic: bool
+    }
+
+    capability_option! {
+        /// Allows reading from stdin and writing to stdout and stderr (default: true)
+        allow_io set_allow_io: io
+    }
+
+    capability_option! {
+        /// Allows reading and writing files and directories (default: true)
+        allow_fs set_allow_fs: fs
+    }
+
+    capability_option! {
+        /// Allows listening for network connections (default: true)
+        allow_net set_allow_net: net
+    }
+
+    capability_option! {
+        /// Allows spawning processes (default: true)
+        allow_process set_allow_process: process
+    }
+
+    capability_option! {
+        /// Allows calling functions in C libraries through `std.ffi`. Foreign code can do anything
+        /// the process can so this must be enabled explicitly (default: false)
+        allow_ffi set_allow_ffi: ffi
+    }
+
+    capability_option! {
+        /// Allows reading the system and monotonic clocks through `std.time` (default: true)
+        allow_clock set_allow_clock: clock
+    }
+
+    capability_option! {
+        /// Allows reading and changing environment variables and the working directory through
+        /// `std.env` as well as reading the command line arguments (default: true)
+        allow_env set_allow_env: env
+    }
+
+    option! {
+        /// The command line arguments returned by `std.args.args`
+        /// (default: the arguments of the process without the name of the executable)
+        args set_args: Option<Vec<String>>
+    }
+
+    option! {
+        /// Receives what `std.io.print`, `std.io.eprint` and the functions built on them write,
+        /// instead of stdout and stderr (default: None)
+        output set_output: Option<crate::vm::vm::Output>
+    }
+
+    option! {
+        /// The maximum number of bytes the main thread may allocate (default: unlimited)
+        memory_limit set_memory_limit: Option<usize>
+    }
+
+    option! {
+        /// The number of bytes the main thread allocates before its first garbage collection. See
+        /// `Gc::set_collect_limit` (default: 100)
+        collect_limit set_collect_limit: Option<usize>
+    }
+
+    option! {
+        /// The maximum size of the stack of the main thread (default: unlimited)
+        max_stack_size set_max_stack_size: Option<crate::vm::types::VmIndex>
+    }
+
+    option! {
+        /// The settings used when compiling scripts, see `Settings` (default: `Settings::default()`)
+        compiler_settings set_compiler_settings: Settings
+    }
+
+    option! {
+        /// The executor which runs spawned gluon threads. `build_async` uses `tokio` if this is
+        /// not set and the `tokio` feature is enabled (default: None)
+        spawner set_spawner: Option<Box<dyn futures::task::Spawn + Send + Sync>>
+    }
+
+    option! {
+        /// Typechecked modules shared with other virtual machines built with the same
+        /// `SharedModules`. Only the types and typed AST are shared, each virtual machine still
+        /// compiles the modules to bytecode and has its own heap and globals (default: None)
+        shared_modules set_shared_modules: Option<query::SharedModules>
+    }
+
+    option! {
+        /// Packages whose modules are imported through the name of the package, see
+        /// `import::Package` and `project::Project::configure` (default: [])
+        packages set_packages: Vec<import::Package>
+    }
+
+    option! {
+        /// Only the modules of the standard library in this list, and the modules they import,
+        /// can be imported. Importing any other module of the standard library is an error
+        /// (default: None, every module can be imported)
+        std_modules set_std_modules: Option<Vec<String>>
     }
 
-    pub async fn build_async(self) -> RootedThread {
+    pub fn build(mut self) -> RootedThread {
+        let spawner = self.spawner.take();
+        futures::executor::block_on(self.build_inner(spawner))
+    }
+
+    pub async fn build_async(mut self) -> RootedThread {
         #[allow(unused_mut, unused_assignments)]
-        let mut spawner = None;
+        let mut spawner = self.spawner.take();
 
         #[cfg(feature = "tokio")]
-        {
+        if spawner.is_none() {
             struct TokioSpawn;
             impl futures::task::Spawn for TokioSpawn {
                 fn spawn_obj(
@@ -939,6 +1574,15 @@ impl VmBuilder {
         let vm = RootedThread::with_global_state(
             crate::vm::vm::GlobalVmStateBuilder::new()
                 .spawner(spawner)
+                .deterministic(self.deterministic)
+                .capabilities(self.capabilities)
+                .args(self.args)
+                .output(self.output)
+                .type_symbols(
+                    self.shared_modules
+                        .as_ref()
+                        .map(|shared| shared.type_symbols()),
+                )
                 .build(),
         );
 
@@ -950,16 +1594,25 @@ impl VmBuilder {
                 if let Some(import_paths) = self.import_paths {
                     import.set_paths(import_paths);
                 }
+                for package in self.packages {
+                    import.add_package(package);
+                }
 
                 if let Ok(gluon_path) = env::var("GLUON_PATH") {
                     import.add_path(gluon_path);
                 }
+                import.set_std_modules(self.compiler_settings.use_standard_lib, self.std_modules);
                 macros.insert(String::from("import"), import);
             }
 
             macros.insert(String::from("lift_io"), lift_io::LiftIo);
+            macros.insert(String::from("format"), format_macro::Format);
+            macros.insert(String::from("decimal"), decimal_macro::DecimalLiteral);
         }
 
+        vm.get_database_mut()
+            .set_shared_modules(self.shared_modules);
+
         add_extern_module_with_deps(
             &vm,
             "std.prim",
@@ -983,36 +1636,81 @@ impl VmBuilder {
             ("std.int.prim", crate::vm::primitives::load_int),
             ("std.float.prim", crate::vm::primitives::load_float),
             ("std.string.prim", crate::vm::primitives::load_string),
-            ("std.fs.prim", crate::vm::primitives::load_fs),
             ("std.char.prim", crate::vm::primitives::load_char),
             ("std.char.prim", crate::vm::primitives::load_char),
             ("std.thread.prim", crate::vm::channel::load_thread),
             ("std.io.prim", crate::std_lib::io::load),
+            ("std.duration.prim", crate::vm::duration::load),
+            ("std.decimal.prim", crate::std_lib::decimal::load),
+            ("std.encoding.prim", crate::std_lib::encoding::load),
+            ("std.format.prim", crate::std_lib::format::load),
         ];
         for (name, load_fn) in deps {
             add_extern_module_with_deps(&vm, name, load_fn, vec!["std.types".into()]);
         }
 
+        add_extern_module_with_deps(
+            &vm,
+            "std.fs.prim",
+            crate::vm::primitives::load_fs,
+            vec!["std.types".into(), "std.fs.types".into()],
+        );
+
         add_extern_module_with_deps(
             &vm,
             "std.path.prim",
             crate::vm::primitives::load_path,
-            vec!["std.path.types".into()],
+            vec!["std.path.types".into(), "std.fs.prim".into()],
+        );
+
+        add_extern_module_with_deps(
+            &vm,
+            "std.process.prim",
+            crate::std_lib::process::load,
+            vec![
+                "std.types".into(),
+                "std.process.types".into(),
+                "std.duration.prim".into(),
+            ],
+        );
+
+        add_extern_module_with_deps(
+            &vm,
+            "std.log.prim",
+            crate::std_lib::log::load,
+            vec!["std.types".into(), "std.log.types".into()],
+        );
+
+        add_extern_module_with_deps(
+            &vm,
+            "std.time.prim",
+            crate::std_lib::time::load,
+            vec!["std.types".into(), "std.duration.prim".into()],
         );
 
         let deps: &[(_, fn(&Thread) -> _)] = &[
             ("std.array.prim", crate::vm::primitives::load_array),
             ("std.lazy.prim", crate::vm::lazy::load),
             ("std.reference.prim", crate::vm::reference::load),
+            ("std.stm.prim", crate::vm::stm::load),
+            ("std.hash.prim", crate::vm::hash_map::load_hash),
+            ("std.collections.hashmap.prim", crate::vm::hash_map::load),
             ("std.channel.prim", crate::vm::channel::load_channel),
             ("std.debug.prim", crate::vm::debug::load),
-            ("std.process.prim", crate::std_lib::process::load),
             ("std.env.prim", crate::std_lib::env::load),
+            ("std.args.prim", crate::std_lib::env::load_args),
         ];
         for (name, load_fn) in deps {
             add_extern_module(&vm, name, load_fn);
         }
 
+        add_extern_module_with_deps(
+            &vm,
+            "std.collections.hashmap.mutable.prim",
+            crate::vm::hash_map::load_table,
+            vec!["std.collections.hashmap.prim".into()],
+        );
+
         add_extern_module(
             &vm,
             "std.effect.st.string.prim",
@@ -1033,6 +1731,53 @@ impl VmBuilder {
             args(&vm, "std.regex.prim", crate::std_lib::regex::load)
         );
 
+        add_extern_module_if!(
+            #[cfg(feature = "crypto")],
+            available_if = "gluon is compiled with the 'crypto' feature",
+            args(&vm, "std.crypto.prim", crate::std_lib::crypto::load)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "unicode")],
+            available_if = "gluon is compiled with the 'unicode' feature",
+            args(&vm, "std.unicode.prim", crate::std_lib::unicode::load)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "csv")],
+            available_if = "gluon is compiled with the 'csv' feature",
+            dependencies = ["std.json"],
+            args(&vm, "std.csv.prim", crate::std_lib::csv::load)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "serialization")],
+            available_if = "gluon is compiled with the 'serialization' feature",
+            dependencies = ["std.json"],
+            args(&vm, "std.toml.prim", crate::std_lib::toml::load)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "yaml")],
+            available_if = "gluon is compiled with the 'yaml' feature",
+            dependencies = ["std.json"],
+            args(&vm, "std.yaml.prim", crate::std_lib::yaml::load)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "net")],
+            available_if = "gluon is compiled with the 'net' feature",
+            dependencies = ["std.types", "std.duration.prim"],
+            args(&vm, "std.net.tcp.prim", crate::std_lib::net::load_tcp)
+        );
+
+        add_extern_module_if!(
+            #[cfg(feature = "net")],
+            available_if = "gluon is compiled with the 'net' feature",
+            dependencies = ["std.types", "std.duration.prim"],
+            args(&vm, "std.net.udp.prim", crate::std_lib::net::load_udp)
+        );
+
         add_extern_module_if!(
             #[cfg(feature = "web")],
             available_if = "gluon is compiled with the 'web' feature",
@@ -1046,12 +1791,32 @@ impl VmBuilder {
             args(&vm, "std.http.prim", crate::std_lib::http::load)
         );
 
+        add_extern_module_if!(
+            #[cfg(all(feature = "ffi", unix, target_endian = "little"))],
+            available_if = "gluon is compiled with the 'ffi' feature and is targeting a little endian unix",
+            dependencies = ["std.ffi.types"],
+            args(&vm, "std.ffi.prim", crate::std_lib::ffi::load)
+        );
+
         add_extern_module_if!(
             #[cfg(all(feature = "random", not(target_arch = "wasm32")))],
             available_if = "gluon is compiled with the 'random' feature and is not targeting WASM",
             args(&vm, "std.random.prim", crate::std_lib::random::load)
         );
 
+        if let Some(memory_limit) = self.memory_limit {
+            vm.set_memory_limit(memory_limit);
+        }
+        if let Some(collect_limit) = self.collect_limit {
+            vm.set_collect_limit(collect_limit);
+        }
+        if let Some(max_stack_size) = self.max_stack_size {
+            use crate::vm::thread::ThreadInternal;
+            vm.context().set_max_stack_size(max_stack_size);
+        }
+        vm.get_database_mut()
+            .set_compiler_settings(self.compiler_settings);
+
         vm
     }
 }
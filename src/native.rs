@@ -0,0 +1,181 @@
+//! Loading of extension modules which have been compiled into dynamic libraries.
+//!
+//! A native module is a Rust crate compiled with `crate-type = ["cdylib"]` which exports a single
+//! function named `gluon_native_module`. This function returns a pointer to a static
+//! `NativeModule` declaring the name of the module and the function which loads it. The
+//! `native_module!` macro generates this function.
+//!
+//! ```rust,ignore
+//! use gluon::{native_module, record, primitive, vm::{self, ExternModule}, Thread};
+//!
+//! fn load(vm: &Thread) -> vm::Result<ExternModule> {
+//!     ExternModule::new(vm, record! {
+//!         double => primitive!(1, "double", |x: i32| x * 2),
+//!     })
+//! }
+//!
+//! native_module!("my_module", load);
+//! ```
+//!
+//! As the declaration contains Rust types the library must be compiled with the same version of
+//! `rustc` and `gluon`, and with the same features of `gluon` enabled, as the host application.
+//! The declaration records all three so a library which was compiled differently is rejected when
+//! it is loaded instead of crashing the host.
+use std::{ffi::CStr, os::raw::c_char, path::Path};
+
+use crate::vm::{thread::Thread, ExternModule};
+
+use crate::{import::add_extern_module, Error, Result};
+
+/// Name of the function which every native module must export
+pub const ENTRY_POINT: &str = "gluon_native_module";
+
+/// Incremented whenever the layout of `NativeModule` changes
+pub const ABI_VERSION: u32 = 2;
+
+#[doc(hidden)]
+pub const GLUON_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+#[doc(hidden)]
+pub const RUSTC_VERSION: &str = concat!(env!("GLUON_RUSTC_VERSION"), "\0");
+
+/// The features `gluon` is compiled with, sorted and separated by `,`. Written in lowercase with
+/// `-` replaced by `_` as that is how cargo passes them to build scripts.
+pub const FEATURES: &str = concat!(env!("GLUON_FEATURES"), "\0");
+
+/// The declaration returned by the entry point of a native module
+#[repr(C)]
+pub struct NativeModule {
+    /// Must be `ABI_VERSION`. Kept as the first field so that it can be checked before any other
+    /// field is read.
+    pub abi_version: u32,
+    /// Nul terminated version of the `gluon` crate the module was compiled against
+    pub gluon_version: *const c_char,
+    /// Nul terminated output of `rustc --version` for the compiler which compiled `gluon`
+    pub rustc_version: *const c_char,
+    /// Nul terminated `FEATURES` of the `gluon` crate the module was compiled against
+    pub features: *const c_char,
+    /// Nul terminated name which the module is imported as
+    pub name: *const c_char,
+    pub load: fn(&Thread) -> crate::vm::Result<ExternModule>,
+}
+
+// The pointers only ever refer to static strings
+unsafe impl Sync for NativeModule {}
+
+/// Defines the entry point of a native module named `$name` which is loaded by `$load`.
+#[macro_export]
+macro_rules! native_module {
+    ($name: expr, $load: expr) => {
+        #[no_mangle]
+        pub extern "C" fn gluon_native_module() -> *const $crate::native::NativeModule {
+            static MODULE: $crate::native::NativeModule = $crate::native::NativeModule {
+                abi_version: $crate::native::ABI_VERSION,
+                gluon_version: $crate::native::GLUON_VERSION.as_ptr() as *const _,
+                rustc_version: $crate::native::RUSTC_VERSION.as_ptr() as *const _,
+                features: $crate::native::FEATURES.as_ptr() as *const _,
+                name: concat!($name, "\0").as_ptr() as *const _,
+                load: $load,
+            };
+            &MODULE
+        }
+    };
+}
+
+type EntryPoint = extern "C" fn() -> *const NativeModule;
+
+pub(crate) fn load(thread: &Thread, path: &Path) -> Result<String> {
+    let entry_point = open(path)?;
+
+    // SAFETY The library promises that the entry point follows the convention documented in this
+    // module and the ABI version is checked before any other field is read
+    let module = unsafe { &*entry_point() };
+    if module.abi_version != ABI_VERSION {
+        return Err(load_error(
+            path,
+            format!(
+                "The module uses version {} of the native module ABI but version {} is required",
+                module.abi_version, ABI_VERSION
+            ),
+        ));
+    }
+    let check = |what: &str, field: *const c_char, expected: &str| {
+        let found = unsafe { CStr::from_ptr(field) }.to_string_lossy();
+        let expected = &expected[..expected.len() - 1];
+        if found == expected {
+            Ok(())
+        } else {
+            Err(load_error(
+                path,
+                format!(
+                    "The module was compiled with {} `{}` but the host uses `{}`",
+                    what, found, expected
+                ),
+            ))
+        }
+    };
+    check("gluon", module.gluon_version, GLUON_VERSION)?;
+    check("rustc", module.rustc_version, RUSTC_VERSION)?;
+    check("the gluon features", module.features, FEATURES)?;
+
+    let name = unsafe { CStr::from_ptr(module.name) }
+        .to_str()
+        .map_err(|err| load_error(path, err.to_string()))?
+        .to_string();
+    add_extern_module(thread, &name, module.load);
+    Ok(name)
+}
+
+fn load_error(path: &Path, msg: impl std::fmt::Display) -> Error {
+    Error::VM(crate::vm::Error::Message(format!(
+        "Unable to load native module `{}`: {}",
+        path.display(),
+        msg
+    )))
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> Result<EntryPoint> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    fn last_error() -> String {
+        // SAFETY `dlerror` returns either null or a nul terminated string
+        unsafe {
+            let err = libc::dlerror();
+            if err.is_null() {
+                "Unknown error".to_string()
+            } else {
+                CStr::from_ptr(err).to_string_lossy().into_owned()
+            }
+        }
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| load_error(path, err.to_string()))?;
+    let entry_point = CString::new(ENTRY_POINT).unwrap();
+
+    // The library is never closed as the functions it defines may be referenced for as long as
+    // the process lives
+    unsafe {
+        let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return Err(load_error(path, last_error()));
+        }
+        let symbol = libc::dlsym(handle, entry_point.as_ptr());
+        if symbol.is_null() {
+            return Err(load_error(
+                path,
+                format!("The library does not export `{}`", ENTRY_POINT),
+            ));
+        }
+        Ok(std::mem::transmute::<*mut libc::c_void, EntryPoint>(symbol))
+    }
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> Result<EntryPoint> {
+    Err(load_error(
+        path,
+        "Native modules are not supported on this platform",
+    ))
+}
@@ -0,0 +1,571 @@
+//! Loading of `gluon.toml` project manifests.
+//!
+//! A manifest names the package, says where its modules are and lists the other gluon packages it
+//! depends on. The modules of a dependency are imported through the name it is declared with, so
+//! with the manifest below `import! json.parse` loads `src/parse.glu` from `../json` and
+//! `import! json` loads the entry module of `../json`.
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! version = "0.1.0"
+//! # The module which `gluon` runs when it is not given any files and which `import! <name>`
+//! # loads when the package is a dependency (default: "src/main.glu")
+//! entry = "src/main.glu"
+//! # The directories which the modules of the package are in (default: ["src"])
+//! source_dirs = ["src"]
+//!
+//! [dependencies]
+//! json = { path = "../json" }
+//! parser = { git = "https://github.com/user/parser", rev = "v1.0" }
+//! collections = "0.2"
+//!
+//! [settings]
+//! std_lib = true
+//! allow_net = false
+//! memory_limit = 67108864
+//! ```
+//!
+//! `git` dependencies are cloned into `.gluon/git/<name>` in the directory of the project the
+//! first time they are needed, remove the checkout to fetch them again. Dependencies which only
+//! give a version are read from `<registry>/<name>/<version>` where the registry is the directory
+//! in the `GLUON_REGISTRY` environment variable or `~/.gluon/registry`. Every dependency must
+//! contain a `gluon.toml` with a `[package]` table of its own. The names of dependencies may only
+//! contain ASCII letters, digits, `_` and `-`.
+//!
+//! Tables other than the ones above, such as the `[format]` table read by `gluon fmt`, are
+//! ignored.
+
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use toml::{value::Table, Value};
+
+use crate::{import::Package, VmBuilder};
+
+/// The name of the manifest file
+pub const MANIFEST_FILE: &str = "gluon.toml";
+
+quick_error! {
+    /// Error type for loading projects
+    #[derive(Debug)]
+    pub enum Error {
+        /// The manifest could not be read
+        Io(path: PathBuf, err: io::Error) {
+            display("{}: {}", path.display(), err)
+        }
+        /// The manifest is not valid
+        Parse(path: Option<PathBuf>, message: String) {
+            display(
+                "{}{}",
+                path.as_ref().map_or(String::new(), |path| format!("{}: ", path.display())),
+                message
+            )
+        }
+        /// A dependency could not be found or fetched
+        Dependency(name: String, message: String) {
+            display("Unable to load the dependency `{}`: {}", name, message)
+        }
+    }
+}
+
+/// Where a dependency is loaded from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dependency {
+    /// A package in a directory, relative to the manifest which declares it
+    Path(PathBuf),
+    /// A package in a git repository, checked out at `rev` (a branch, tag or commit) if it is set
+    Git { url: String, rev: Option<String> },
+    /// A package in the registry
+    Registry { version: String },
+}
+
+/// The `[package]` table of a manifest
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: Option<String>,
+    /// The entry module, relative to the manifest
+    pub entry: PathBuf,
+    /// The directories containing the modules of the package, relative to the manifest
+    pub source_dirs: Vec<PathBuf>,
+}
+
+/// The `[settings]` table of a manifest. Settings which are not set keep the defaults of
+/// `VmBuilder`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectSettings {
+    /// Whether the standard library embedded in the binary is used (default: true)
+    pub std_lib: bool,
+    pub deterministic: Option<bool>,
+    pub allow_io: Option<bool>,
+    pub allow_fs: Option<bool>,
+    pub allow_net: Option<bool>,
+    pub allow_process: Option<bool>,
+    pub allow_ffi: Option<bool>,
+    pub allow_clock: Option<bool>,
+    pub allow_env: Option<bool>,
+    pub memory_limit: Option<usize>,
+}
+
+impl Default for ProjectSettings {
+    fn default() -> Self {
+        ProjectSettings {
+            std_lib: true,
+            deterministic: None,
+            allow_io: None,
+            allow_fs: None,
+            allow_net: None,
+            allow_process: None,
+            allow_ffi: None,
+            allow_clock: None,
+            allow_env: None,
+            memory_limit: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub package: PackageInfo,
+    pub dependencies: BTreeMap<String, Dependency>,
+    pub settings: ProjectSettings,
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "a string",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        Value::Datetime(_) => "a datetime",
+        Value::Array(_) => "an array",
+        Value::Table(_) => "a table",
+    }
+}
+
+fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => Err(format!(
+            "Expected a string for `{}`, found {}",
+            key,
+            describe(&value)
+        )),
+    }
+}
+
+fn boolean(key: &str, value: Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(b) => Ok(b),
+        value => Err(format!(
+            "Expected a boolean for `{}`, found {}",
+            key,
+            describe(&value)
+        )),
+    }
+}
+
+/// Removes the table `key` from `root`, returning an empty table if it is not there
+fn table(root: &mut Table, key: &str) -> Result<Table, String> {
+    match root.remove(key) {
+        Some(Value::Table(table)) => Ok(table),
+        Some(value) => Err(format!(
+            "Expected a table for `{}`, found {}",
+            key,
+            describe(&value)
+        )),
+        None => Ok(Table::new()),
+    }
+}
+
+fn dependency(name: &str, value: Value) -> Result<Dependency, String> {
+    let fields = match value {
+        Value::String(version) => return Ok(Dependency::Registry { version }),
+        Value::Table(fields) => fields,
+        value => {
+            return Err(format!(
+                "Expected a version or a table for the dependency `{}`, found {}",
+                name,
+                describe(&value)
+            ))
+        }
+    };
+
+    let (mut path, mut git, mut rev, mut version) = (None, None, None, None);
+    for (key, value) in fields {
+        match &key[..] {
+            "path" => path = Some(PathBuf::from(string(&key, value)?)),
+            "git" => git = Some(string(&key, value)?),
+            "rev" => rev = Some(string(&key, value)?),
+            "version" => version = Some(string(&key, value)?),
+            _ => {
+                return Err(format!(
+                    "Unknown key `{}` in the dependency `{}`",
+                    key, name
+                ))
+            }
+        }
+    }
+    match (path, git, version) {
+        (Some(path), None, None) if rev.is_none() => Ok(Dependency::Path(path)),
+        (None, Some(url), None) => Ok(Dependency::Git { url, rev }),
+        (None, None, Some(version)) if rev.is_none() => Ok(Dependency::Registry { version }),
+        _ => Err(format!(
+            "The dependency `{}` must have exactly one of `path`, `git` or `version` and may \
+             only have a `rev` if it is a `git` dependency",
+            name
+        )),
+    }
+}
+
+impl Manifest {
+    /// Parses a manifest. Returns `None` if it does not have a `[package]` table, such as a
+    /// `gluon.toml` which only configures `gluon fmt`.
+    pub fn parse(manifest: &str) -> Result<Option<Manifest>, Error> {
+        Manifest::parse_toml(manifest).map_err(|message| Error::Parse(None, message))
+    }
+
+    fn parse_toml(manifest: &str) -> Result<Option<Manifest>, String> {
+        let mut root = toml::from_str::<Table>(manifest).map_err(|err| err.to_string())?;
+        if !root.contains_key("package") {
+            return Ok(None);
+        }
+
+        let mut name = None;
+        let mut package = PackageInfo {
+            name: String::new(),
+            version: None,
+            entry: PathBuf::from("src/main.glu"),
+            source_dirs: vec![PathBuf::from("src")],
+        };
+        for (key, value) in table(&mut root, "package")? {
+            match &key[..] {
+                "name" => name = Some(string(&key, value)?),
+                "version" => package.version = Some(string(&key, value)?),
+                "entry" => package.entry = PathBuf::from(string(&key, value)?),
+                "source_dirs" => {
+                    package.source_dirs = match value {
+                        Value::Array(values) => values
+                            .into_iter()
+                            .map(|value| string(&key, value).map(PathBuf::from))
+                            .collect::<Result<_, _>>()?,
+                        value => {
+                            return Err(format!(
+                                "Expected an array for `{}`, found {}",
+                                key,
+                                describe(&value)
+                            ))
+                        }
+                    }
+                }
+                _ => return Err(format!("Unknown key `{}` in `[package]`", key)),
+            }
+        }
+        package.name = name.ok_or("`[package]` does not have a `name`")?;
+
+        let mut dependencies = BTreeMap::new();
+        for (name, value) in table(&mut root, "dependencies")? {
+            let dependency = dependency(&name, value)?;
+            dependencies.insert(name, dependency);
+        }
+
+        let mut settings = ProjectSettings::default();
+        for (key, value) in table(&mut root, "settings")? {
+            match &key[..] {
+                "std_lib" => settings.std_lib = boolean(&key, value)?,
+                "deterministic" => settings.deterministic = Some(boolean(&key, value)?),
+                "allow_io" => settings.allow_io = Some(boolean(&key, value)?),
+                "allow_fs" => settings.allow_fs = Some(boolean(&key, value)?),
+                "allow_net" => settings.allow_net = Some(boolean(&key, value)?),
+                "allow_process" => settings.allow_process = Some(boolean(&key, value)?),
+                "allow_ffi" => settings.allow_ffi = Some(boolean(&key, value)?),
+                "allow_clock" => settings.allow_clock = Some(boolean(&key, value)?),
+                "allow_env" => settings.allow_env = Some(boolean(&key, value)?),
+                "memory_limit" => match value {
+                    Value::Integer(limit) if limit > 0 => {
+                        settings.memory_limit = Some(limit as usize)
+                    }
+                    value => {
+                        return Err(format!(
+                            "Expected a positive integer for `{}`, found {}",
+                            key,
+                            describe(&value)
+                        ))
+                    }
+                },
+                _ => return Err(format!("Unknown key `{}` in `[settings]`", key)),
+            }
+        }
+
+        Ok(Some(Manifest {
+            package,
+            dependencies,
+            settings,
+        }))
+    }
+
+    /// Reads the manifest at `path`, see `parse`
+    pub fn load(path: &Path) -> Result<Option<Manifest>, Error> {
+        let manifest = fs::read_to_string(path).map_err(|err| Error::Io(path.to_owned(), err))?;
+        Manifest::parse(&manifest).map_err(|err| match err {
+            Error::Parse(_, message) => Error::Parse(Some(path.to_owned()), message),
+            err => err,
+        })
+    }
+}
+
+/// A project and the packages it depends on
+#[derive(Clone, Debug)]
+pub struct Project {
+    /// The directory containing the manifest
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    /// The dependencies of the project, including the dependencies of its dependencies
+    pub packages: Vec<Package>,
+}
+
+impl Project {
+    /// Loads the project whose manifest is in `dir` or the closest of its ancestors. Returns
+    /// `None` if there is no manifest with a `[package]` table.
+    pub fn find(dir: &Path) -> Result<Option<Project>, Error> {
+        for dir in dir.ancestors() {
+            let path = dir.join(MANIFEST_FILE);
+            if path.is_file() {
+                if let Some(manifest) = Manifest::load(&path)? {
+                    return Project::new(dir.to_owned(), manifest).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Loads the project whose manifest is in `root`
+    pub fn load(root: &Path) -> Result<Project, Error> {
+        let path = root.join(MANIFEST_FILE);
+        let manifest = Manifest::load(&path)?.ok_or_else(|| {
+            Error::Parse(
+                Some(path.clone()),
+                "The manifest does not have a `[package]` table".into(),
+            )
+        })?;
+        Project::new(root.to_owned(), manifest)
+    }
+
+    fn new(root: PathBuf, manifest: Manifest) -> Result<Project, Error> {
+        let mut project = Project {
+            root,
+            manifest,
+            packages: Vec::new(),
+        };
+        let mut resolved = Vec::new();
+        project.resolve(&project.root, &project.manifest, &mut resolved)?;
+        project.packages = resolved.into_iter().map(|(package, _)| package).collect();
+        Ok(project)
+    }
+
+    /// Adds the dependencies of the package at `root` to `resolved`, along with the
+    /// directories they are in
+    fn resolve(
+        &self,
+        root: &Path,
+        manifest: &Manifest,
+        resolved: &mut Vec<(Package, PathBuf)>,
+    ) -> Result<(), Error> {
+        for (name, dependency) in &manifest.dependencies {
+            let dependency_error = |message: String| Error::Dependency(name.clone(), message);
+            check_dependency(name, dependency).map_err(dependency_error)?;
+
+            let dir = match dependency {
+                Dependency::Path(path) => root.join(path),
+                Dependency::Git { url, rev } => self.fetch_git(name, url, rev.as_deref())?,
+                Dependency::Registry { version } => {
+                    let registry = registry_dir().ok_or_else(|| {
+                        dependency_error("Unable to find the registry, set `GLUON_REGISTRY`".into())
+                    })?;
+                    registry.join(name).join(version)
+                }
+            };
+
+            let canonical = fs::canonicalize(&dir).map_err(|err| {
+                dependency_error(format!("Unable to open `{}`: {}", dir.display(), err))
+            })?;
+            if let Some((_, existing)) = resolved.iter().find(|(package, _)| package.name == *name)
+            {
+                if *existing != canonical {
+                    return Err(dependency_error(format!(
+                        "It refers to both `{}` and `{}`",
+                        existing.display(),
+                        canonical.display()
+                    )));
+                }
+                continue;
+            }
+
+            let dependency_manifest =
+                Manifest::load(&dir.join(MANIFEST_FILE))?.ok_or_else(|| {
+                    dependency_error(format!(
+                        "`{}` does not have a `[package]` table",
+                        dir.join(MANIFEST_FILE).display()
+                    ))
+                })?;
+            resolved.push((
+                Package {
+                    name: name.clone(),
+                    source_dirs: dependency_manifest
+                        .package
+                        .source_dirs
+                        .iter()
+                        .map(|source_dir| dir.join(source_dir))
+                        .collect(),
+                    entry: Some(dir.join(&dependency_manifest.package.entry)),
+                },
+                canonical,
+            ));
+            self.resolve(&dir, &dependency_manifest, resolved)?;
+        }
+        Ok(())
+    }
+
+    /// Clones the git repository of the dependency `name`, unless it has already been cloned,
+    /// returning the directory it is in
+    fn fetch_git(&self, name: &str, url: &str, rev: Option<&str>) -> Result<PathBuf, Error> {
+        let dir = self.root.join(".gluon").join("git").join(name);
+        if dir.exists() {
+            return Ok(dir);
+        }
+
+        info!("Cloning {} into {}", url, dir.display());
+        let run = |command: &mut Command| {
+            let output = command.output().map_err(|err| {
+                Error::Dependency(name.into(), format!("Unable to run git: {}", err))
+            })?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(Error::Dependency(
+                    name.into(),
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ))
+            }
+        };
+        run(Command::new("git")
+            .args(&["clone", "--quiet", "--", url])
+            .arg(&dir))?;
+        if let Some(rev) = rev {
+            // `check_dependency` has rejected revisions which could be read as an option, the
+            // `--` stops `rev` from being read as a path
+            if let Err(err) = run(Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(&["checkout", "--quiet", rev, "--"]))
+            {
+                // Clone again on the next attempt instead of using the wrong revision
+                let _ = fs::remove_dir_all(&dir);
+                return Err(err);
+            }
+        }
+        Ok(dir)
+    }
+
+    /// The path of the entry module
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.manifest.package.entry)
+    }
+
+    /// The directories containing the modules of the project
+    pub fn source_dirs(&self) -> Vec<PathBuf> {
+        self.manifest
+            .package
+            .source_dirs
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect()
+    }
+
+    /// Configures `builder` to load modules from the source directories of the project before
+    /// the import paths it already has, to load the modules of the dependencies and to use the
+    /// settings of the manifest. `ProjectSettings::std_lib` must be applied separately with
+    /// `CompilerDatabase::use_standard_lib`.
+    pub fn configure(&self, mut builder: VmBuilder) -> VmBuilder {
+        let mut import_paths = self.source_dirs();
+        import_paths.extend(
+            builder
+                .import_paths
+                .take()
+                .unwrap_or_else(|| vec![PathBuf::from(".")]),
+        );
+        builder.import_paths = Some(import_paths);
+        builder.packages.extend(self.packages.iter().cloned());
+
+        let settings = &self.manifest.settings;
+        let capabilities = &mut builder.capabilities;
+        for (setting, capability) in &mut [
+            (settings.allow_io, &mut capabilities.io),
+            (settings.allow_fs, &mut capabilities.fs),
+            (settings.allow_net, &mut capabilities.net),
+            (settings.allow_process, &mut capabilities.process),
+            (settings.allow_ffi, &mut capabilities.ffi),
+            (settings.allow_clock, &mut capabilities.clock),
+            (settings.allow_env, &mut capabilities.env),
+        ] {
+            if let Some(allow) = setting {
+                **capability = *allow;
+            }
+        }
+        if let Some(deterministic) = settings.deterministic {
+            builder.deterministic = deterministic;
+        }
+        if settings.memory_limit.is_some() {
+            builder.memory_limit = settings.memory_limit;
+        }
+        builder
+    }
+}
+
+/// Checks that the dependency `name` can't refer to files outside of the directories it is
+/// stored in or pass options to `git`
+fn check_dependency(name: &str, dependency: &Dependency) -> Result<(), String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        return Err(
+            "The names of dependencies may only contain ASCII letters, digits, `_` and `-`".into(),
+        );
+    }
+    match dependency {
+        Dependency::Path(_) => (),
+        Dependency::Git { url, rev } => {
+            if let Some(value) = Some(url)
+                .into_iter()
+                .chain(rev)
+                .find(|s| s.starts_with('-'))
+            {
+                return Err(format!("`{}` may not start with `-`", value));
+            }
+        }
+        Dependency::Registry { version } => {
+            if version.is_empty()
+                || version == "."
+                || version == ".."
+                || !version
+                    .chars()
+                    .all(|c| is_name_char(c) || c == '.' || c == '+')
+            {
+                return Err(format!("`{}` is not a valid version", version));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn registry_dir() -> Option<PathBuf> {
+    env::var_os("GLUON_REGISTRY")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".gluon").join("registry"))
+        })
+}
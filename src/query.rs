@@ -2,11 +2,15 @@ use std::{
     borrow::Cow,
     collections::hash_map,
     ops::Deref,
+    path::PathBuf,
     result::Result as StdResult,
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize},
+        Arc, Mutex, MutexGuard,
+    },
 };
 
-use salsa::{Database, OwnedDb};
+use salsa::{debug::DebugQueryTable, Database, OwnedDb};
 
 use {
     base::{
@@ -16,7 +20,7 @@ use {
         metadata::{Metadata, MetadataEnv},
         pos::BytePos,
         source::{CodeMap, FileMap, Source},
-        symbol::{Name, Symbol, SymbolModule, SymbolRef},
+        symbol::{Name, Symbol, SymbolModule, SymbolRef, Symbols},
         types::{Alias, ArcType, NullInterner, PrimitiveEnv, TypeEnv, TypeExt},
     },
     vm::{
@@ -81,6 +85,12 @@ pub struct State {
     pub(crate) inline_modules: FnvMap<String, Arc<Cow<'static, str>>>,
     pub(crate) index_map: FnvMap<String, BytePos>,
     extern_globals: FnvSet<String>,
+    extern_modules: FnvSet<String>,
+    restored_globals: FnvMap<String, UnrootedValue>,
+    pub(crate) shared_modules: Option<SharedModules>,
+    /// Modules which this database typechecked itself even though `shared_modules` has (or could
+    /// only have) a different version of them
+    unshared_modules: FnvSet<String>,
 }
 
 impl State {
@@ -132,6 +142,124 @@ impl State {
     }
 }
 
+/// Typechecked modules which several virtual machines in the same process can reuse, sparing each
+/// of them from parsing and typechecking the standard library (or any other module) again.
+///
+/// Only the types, metadata and typed AST of a module are shared. The bytecode is still generated
+/// by each virtual machine as it refers to strings interned in the heap of that machine, and each
+/// machine runs the modules itself so globals are never shared between them.
+///
+/// A module is only reused if its source and the compiler settings are the same as when it was
+/// typechecked, its source ends up at the same position in the `CodeMap` of the virtual machine
+/// and every module it imports were reused as well. Machines which are set up the same way load
+/// the standard library in the same order so this holds for them, otherwise the module is
+/// typechecked as usual.
+///
+/// Symbols are compared by identity, so the machines also create the symbols of the types they
+/// register (such as `std.io.IO`) from a table owned by the `SharedModules`. Otherwise the types
+/// of a reused module would refer to the extern types of the machine which typechecked it.
+#[derive(Clone, Default)]
+pub struct SharedModules {
+    modules: Arc<Mutex<FnvMap<String, SharedModule>>>,
+    type_symbols: Arc<Symbols>,
+    reused: Arc<AtomicUsize>,
+}
+
+struct SharedModule {
+    source: Arc<Cow<'static, str>>,
+    start: BytePos,
+    settings: Settings,
+    dependencies: Vec<String>,
+    value: TypecheckValue<Arc<OwnedExpr<Symbol>>>,
+}
+
+impl SharedModules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The names of the modules which have been typechecked so far
+    pub fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<_> = self.modules.lock().unwrap().keys().cloned().collect();
+        modules.sort();
+        modules
+    }
+
+    /// How many times a virtual machine reused a module instead of typechecking it
+    pub fn reused(&self) -> usize {
+        self.reused.load(atomic::Ordering::SeqCst)
+    }
+
+    pub(crate) fn type_symbols(&self) -> Arc<Symbols> {
+        self.type_symbols.clone()
+    }
+
+    fn get(
+        &self,
+        module: &str,
+        source: &str,
+        start: BytePos,
+        settings: &Settings,
+    ) -> Option<(Vec<String>, TypecheckValue<Arc<OwnedExpr<Symbol>>>)> {
+        let modules = self.modules.lock().unwrap();
+        let shared = modules.get(module)?;
+        if shared.start == start && &shared.settings == settings && &shared.source[..] == source {
+            Some((shared.dependencies.clone(), shared.value.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `false` if another version of the module has already been inserted
+    fn insert(
+        &self,
+        module: String,
+        source: Arc<Cow<'static, str>>,
+        start: BytePos,
+        settings: Settings,
+        dependencies: Vec<String>,
+        value: TypecheckValue<Arc<OwnedExpr<Symbol>>>,
+    ) -> bool {
+        match self.modules.lock().unwrap().entry(module) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(SharedModule {
+                    source,
+                    start,
+                    settings,
+                    dependencies,
+                    value,
+                });
+                true
+            }
+        }
+    }
+}
+
+/// Returns the modules which `expr` imports, in the order they are first imported
+fn imported_modules(expr: &ast::SpannedExpr<Symbol>) -> Vec<String> {
+    struct Imports(Vec<String>);
+    impl<'a> ast::Visitor<'a, '_> for Imports {
+        type Ident = Symbol;
+
+        fn visit_expr(&mut self, expr: &'a ast::SpannedExpr<Symbol>) {
+            match &expr.value {
+                ast::Expr::Ident(id) if id.name.is_global() => {
+                    let module = id.name.definition_name();
+                    if !self.0.iter().any(|m| m == module) {
+                        self.0.push(module.to_string());
+                    }
+                }
+                _ => ast::walk_expr(self, expr),
+            }
+        }
+    }
+
+    let mut imports = Imports(Vec::new());
+    ast::Visitor::visit_expr(&mut imports, expr);
+    imports.0
+}
+
 #[salsa::database(async CompileStorage)]
 pub struct CompilerDatabase {
     storage: salsa::Storage<CompilerDatabase>,
@@ -139,6 +267,8 @@ pub struct CompilerDatabase {
     // This is only set after calling snapshot on `Import`. `Import` itself can't contain a
     // `RootedThread` as that would create a cycle
     pub(crate) thread: Option<RootedThread>,
+    // Set when only the types of imported modules are needed so that `import!` must not run them
+    pub(crate) typecheck_only: AtomicBool,
 }
 
 impl CompilerDatabase {
@@ -147,6 +277,7 @@ impl CompilerDatabase {
             storage: self.storage.snapshot(),
             state: self.state.clone(),
             thread: Some(thread),
+            typecheck_only: AtomicBool::new(false),
         })
     }
 
@@ -155,6 +286,7 @@ impl CompilerDatabase {
             storage: self.storage.fork(state),
             state: self.state.clone(),
             thread: Some(thread),
+            typecheck_only: AtomicBool::new(self.typecheck_only.load(atomic::Ordering::SeqCst)),
         })
     }
 }
@@ -273,11 +405,17 @@ impl CompilerDatabase {
             state: Default::default(),
             storage: Default::default(),
             thread,
+            typecheck_only: AtomicBool::new(false),
         };
         compiler.set_compiler_settings(Default::default());
         compiler
     }
 
+    /// Sets the typechecked modules which are shared with other virtual machines
+    pub fn set_shared_modules(&mut self, shared_modules: Option<SharedModules>) {
+        self.state().shared_modules = shared_modules;
+    }
+
     pub(crate) fn state(&self) -> MutexGuard<'_, State> {
         self.state.lock().unwrap()
     }
@@ -297,6 +435,38 @@ impl CompilerDatabase {
         self.state().get_filemap(file)
     }
 
+    /// Returns the path of the file that `module` was loaded from. Modules which were not loaded
+    /// from a file, such as the standard library modules embedded in the binary or extern
+    /// modules, return `None` but may still have their source in `get_filemap`.
+    pub fn module_path(&self, module: &str) -> Option<PathBuf> {
+        {
+            let state = self.state();
+            if state.inline_modules.contains_key(module) || state.extern_modules.contains(module) {
+                return None;
+            }
+        }
+        let mut filename = module.replace(".", "/");
+        filename.push_str(".glu");
+        crate::get_import(self.thread()).module_path(
+            self.compiler_settings().use_standard_lib,
+            module,
+            &filename,
+        )
+    }
+
+    /// Returns the names of the modules which have been typechecked without errors
+    pub fn typechecked_modules(&self) -> Vec<String> {
+        TypecheckedSourceModuleQuery
+            .in_db(self)
+            .entries::<Vec<_>>()
+            .into_iter()
+            .filter(|entry| {
+                entry.key.1.is_none() && entry.value.as_ref().map_or(false, |value| value.is_ok())
+            })
+            .map(|entry| entry.key.0)
+            .collect()
+    }
+
     pub(crate) fn get_or_insert_filemap<S>(&self, file: &str, source: S) -> Arc<FileMap>
     where
         S: AsRef<str> + Into<String>,
@@ -333,6 +503,59 @@ impl CompilerDatabase {
         )
     }
 
+    /// Uses `value` as the value of `module` instead of running the module when it is imported.
+    /// The type and metadata of the module are still taken from its source.
+    ///
+    /// Returns an error if the global heap can't fit a copy of `value`.
+    pub fn restore_global(&mut self, module: &str, value: &Value) -> Result<()> {
+        let thread = self.thread().root_thread();
+        let mut gc = thread.global_env().gc.lock().unwrap();
+        let mut cloner = vm::internal::Cloner::new(&thread, &mut gc);
+        let mut value: RootedValue<RootedThread> = thread.root_value(cloner.deep_clone(&value)?);
+        // SAFETY The database is owned by the thread so rooting the thread would keep it alive
+        // forever. The value itself stays rooted and is rooted to the thread again when it is
+        // retrieved, see `UnrootedValue::root_with`.
+        unsafe { value.vm_mut().unroot() };
+        self.state()
+            .restored_globals
+            .insert(module.into(), UnrootedValue(value));
+        Ok(())
+    }
+
+    /// Returns the name, source and value of every module that has been loaded from gluon code
+    pub fn loaded_modules(
+        &self,
+    ) -> Vec<(String, Arc<Cow<'static, str>>, RootedValue<RootedThread>)> {
+        let mut names: Vec<String> = self.state().index_map.keys().cloned().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter(|name| ExternLoaderQuery.in_db(self).peek(name).is_none())
+            .filter_map(|name| {
+                let global = self.peek_global(&name)?;
+                let text = self.module_text(name.clone()).ok()?;
+                Some((name, text, global.value))
+            })
+            .collect()
+    }
+
+    /// Returns the names of the modules implemented in Rust that have been loaded
+    pub fn loaded_extern_modules(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.state().extern_modules.iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Replaces the source of `module`, ensuring that it is recompiled even if it was previously
+    /// loaded from a file
+    pub(crate) fn replace_module(&mut self, module: String, contents: &str) {
+        self.state().restored_globals.remove(&module);
+        self.add_module(module.clone(), contents);
+        ModuleTextQuery
+            .in_db_mut(self as &mut dyn Compilation)
+            .invalidate(&module);
+    }
+
     pub(crate) fn collect_garbage(&self) {
         let strategy = salsa::SweepStrategy::default()
             .discard_values()
@@ -518,8 +741,37 @@ async fn typechecked_source_module(
 
     let text = db.module_text(module.clone())?;
 
+    let shared = if expected_type.is_none() {
+        db.compiler().state().shared_modules.clone()
+    } else {
+        None
+    };
+    let shared = shared.map(|shared| {
+        let start = db.add_filemap(&module, &text[..]).span().start();
+        (shared, start, db.compiler_settings())
+    });
+
+    if let Some((shared, start, settings)) = &shared {
+        if let Some((dependencies, value)) = shared.get(&module, &text, *start, settings) {
+            // The imports are normally loaded while the module is typechecked
+            for dependency in &dependencies {
+                db.import(dependency.clone()).await?;
+            }
+            // Symbols are compared by identity so the module can only be reused if the modules
+            // it imports were reused as well
+            let state = db.compiler().state();
+            if !dependencies
+                .iter()
+                .any(|dependency| state.unshared_modules.contains(dependency))
+            {
+                shared.reused.fetch_add(1, atomic::Ordering::SeqCst);
+                return Ok(value);
+            }
+        }
+    }
+
     let thread = db.thread().root_thread();
-    let mut compiler = ModuleCompiler::new(db);
+    let mut compiler = ModuleCompiler::new(&mut *db);
     let value = text
         .typecheck_expected(
             &mut compiler,
@@ -531,7 +783,27 @@ async fn typechecked_source_module(
         .await
         .map_err(|err| err.map(|value| value.map(Arc::new)))?;
 
-    Ok(value.map(Arc::new))
+    let value = value.map(Arc::new);
+    if let Some((shared, start, settings)) = shared {
+        let dependencies = imported_modules(value.expr.expr());
+        let mut state = db.compiler().state();
+        let unshared = dependencies
+            .iter()
+            .any(|dependency| state.unshared_modules.contains(dependency));
+        if unshared
+            || !shared.insert(
+                module.clone(),
+                text,
+                start,
+                settings,
+                dependencies,
+                value.clone(),
+            )
+        {
+            state.unshared_modules.insert(module);
+        }
+    }
+    Ok(value)
 }
 
 async fn module_type(
@@ -607,6 +879,10 @@ async fn compiled_module(
     module: String,
     expected_type: Option<ArcType>,
 ) -> StdResult<OpaqueValue<RootedThread, GcPtr<ClosureData>>, Error> {
+    let origins = db
+        .typechecked_source_module(module.clone(), expected_type.clone())
+        .await?
+        .origins;
     let core_expr = db.core_expr(module.clone(), expected_type).await?;
     let settings = db.compiler_settings();
 
@@ -632,6 +908,8 @@ async fn compiled_module(
         module.clone(),
         settings.emit_debug_info,
     );
+    compiler.set_origins(&origins);
+    compiler.set_coverage(settings.coverage);
 
     let mut compiled_module = compiler.compile_expr(core_expr.value.expr())?;
     let module_id = Symbol::from(format!("@{}", name));
@@ -663,6 +941,17 @@ async fn import(
     Ok(TypedIdent { name, typ })
 }
 
+/// Returns the type of the module `module` without running it or anything it imports
+pub(crate) async fn import_type(
+    db: &mut salsa::Snapshot<CompilerDatabase>,
+    module: String,
+) -> StdResult<TypedIdent<Symbol>, Error> {
+    let name = Symbol::from(format!("@{}", module));
+    let typ = db.module_type(module.clone(), None).await?;
+    db.module_metadata(module, None).await?;
+    Ok(TypedIdent { name, typ })
+}
+
 async fn global_inner(
     db: &mut OwnedDb<'_, dyn Compilation + '_>,
     name: String,
@@ -677,13 +966,32 @@ async fn global_inner(
         return Ok(global);
     }
 
-    let TypecheckValue { metadata, typ, .. } =
-        db.typechecked_source_module(name.clone(), None).await?;
+    let TypecheckValue {
+        expr,
+        metadata,
+        typ,
+        ..
+    } = db.typechecked_source_module(name.clone(), None).await?;
+
+    // The module may have been typechecked without running its imports, see `import_type`
+    for dependency in imported_modules(expr.expr()) {
+        db.import(dependency).await?;
+    }
 
     // Ensure the type is stored in the database so we can collect typechecked_source_module later
     db.module_type(name.clone(), None).await?;
     db.module_metadata(name.clone(), None).await?;
 
+    let restored = db.compiler().state().restored_globals.get(&name).cloned();
+    if let Some(value) = restored {
+        return Ok(UnrootedGlobal {
+            id: Symbol::from(format!("@{}", name)),
+            typ,
+            metadata,
+            value,
+        });
+    }
+
     let closure = db.compiled_module(name.clone(), None).await?;
 
     let module_id = closure.function.name.clone();
@@ -734,7 +1042,7 @@ async fn extern_module(
     name: String,
 ) -> Result<UnrootedGlobal> {
     let id = Symbol::from(format!("@{}", name));
-    let loader = db.extern_loader(name);
+    let loader = db.extern_loader(name.clone());
 
     for dep in &loader.dependencies {
         db.import(dep.clone()).await?;
@@ -743,6 +1051,7 @@ async fn extern_module(
     let vm = db.thread();
 
     let module = (loader.load_fn)(vm)?;
+    db.compiler().state().extern_modules.insert(name);
     let mut value = module.value.clone();
     unsafe { value.vm_mut().unroot() }; // FIXME
 
@@ -0,0 +1,129 @@
+//! Saving and restoring the modules loaded into a vm.
+//!
+//! A snapshot is serialized as a tuple of the names of the Rust modules which were loaded, the
+//! name and source of every module loaded from gluon code and the values of those modules. The
+//! Rust modules are loaded first when restoring as the extern functions in the values are looked
+//! up by name while deserializing. The sources are used to recover the types of the modules so
+//! that new code can be compiled against them.
+use std::{fmt, result::Result as StdResult};
+
+use crate::serde::{
+    de::{DeserializeSeed, Error as _, SeqAccess, Visitor},
+    ser::{Seeded, SerializeTuple},
+    Deserializer, Serializer,
+};
+
+use crate::vm::{
+    serialization::{DeSeed, SeSeed},
+    thread::{RootedThread, RootedValue, Thread},
+};
+
+use crate::{
+    query::{AsyncCompilation, CompilationBase},
+    Result, ThreadExt,
+};
+
+pub(crate) fn save<S>(thread: &Thread, serializer: S) -> StdResult<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let db = thread.get_database();
+    let extern_modules = db.loaded_extern_modules();
+    let (sources, values): (Vec<_>, Vec<_>) = db
+        .loaded_modules()
+        .into_iter()
+        .map(|(name, source, value)| ((name, String::from(&source[..])), value))
+        .unzip();
+
+    let seed = SeSeed::new().with_userdata_hooks(thread.global_env().userdata_hooks());
+    let mut tuple = serializer.serialize_tuple(3)?;
+    tuple.serialize_element(&extern_modules)?;
+    tuple.serialize_element(&sources)?;
+    tuple.serialize_element(&Seeded::new(&seed, &values))?;
+    tuple.end()
+}
+
+pub(crate) fn load<'de, D>(thread: &Thread, deserializer: D) -> Result<()>
+where
+    D: Deserializer<'de>,
+{
+    type Modules = Vec<(String, RootedValue<RootedThread>)>;
+
+    struct SnapshotVisitor<'t>(&'t Thread);
+
+    impl<'de> Visitor<'de> for SnapshotVisitor<'_> {
+        type Value = Modules;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a snapshot")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> StdResult<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let thread = self.0;
+            let extern_modules: Vec<String> = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+            {
+                let mut db = thread.get_database();
+                for module in &extern_modules {
+                    futures::executor::block_on(db.import(module.clone()))
+                        .map_err(A::Error::custom)?;
+                }
+            }
+
+            let sources: Vec<(String, String)> = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+
+            let mut context = thread.current_context();
+            let mut seed = DeSeed::new(thread, &mut context)
+                .with_userdata_hooks(thread.global_env().userdata_hooks())
+                .with_extern_modules(extern_modules.iter().map(|module| &module[..]))
+                .map_err(A::Error::custom)?;
+            let values: Vec<RootedValue<RootedThread>> = seq
+                .next_element_seed(crate::serde::de::Seed::new(&mut seed))?
+                .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+
+            if sources.len() != values.len() {
+                return Err(A::Error::custom(
+                    "The number of module sources and values do not match",
+                ));
+            }
+            let mut db = thread.get_database_mut();
+            Ok(sources
+                .into_iter()
+                .zip(values)
+                .map(|((name, source), value)| {
+                    db.add_module(name.clone(), &source);
+                    (name, value)
+                })
+                .collect())
+        }
+    }
+
+    struct SnapshotSeed<'t>(&'t Thread);
+
+    impl<'de> DeserializeSeed<'de> for SnapshotSeed<'_> {
+        type Value = Modules;
+
+        fn deserialize<D>(self, deserializer: D) -> StdResult<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_tuple(3, SnapshotVisitor(self.0))
+        }
+    }
+
+    let modules = SnapshotSeed(thread)
+        .deserialize(deserializer)
+        .map_err(|err| err.to_string())?;
+
+    let mut db = thread.get_database_mut();
+    for (name, value) in modules {
+        db.restore_global(&name, value.get_value())?;
+    }
+    Ok(())
+}
@@ -1,9 +1,28 @@
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod decimal;
+pub mod encoding;
 pub mod env;
+#[cfg(all(feature = "ffi", unix, target_endian = "little"))]
+pub mod ffi;
+pub mod format;
 #[cfg(feature = "http")]
 pub mod http;
 pub mod io;
+pub mod log;
+#[cfg(feature = "net")]
+pub mod net;
 pub mod process;
 #[cfg(all(feature = "random", not(target_arch = "wasm32")))]
 pub mod random;
 #[cfg(feature = "regex")]
 pub mod regex;
+pub mod time;
+#[cfg(feature = "serialization")]
+pub mod toml;
+#[cfg(feature = "unicode")]
+pub mod unicode;
+#[cfg(feature = "yaml")]
+pub mod yaml;
@@ -0,0 +1,72 @@
+//! Module containing bindings to hashing and message authentication functions.
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+use crate::vm::{self, thread::Thread, ExternModule};
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+fn sha512(data: &[u8]) -> Vec<u8> {
+    Sha512::digest(data).to_vec()
+}
+
+fn blake3(data: &[u8]) -> Vec<u8> {
+    blake3::hash(data).as_bytes().to_vec()
+}
+
+fn hmac<M>(key: &[u8], data: &[u8]) -> Vec<u8>
+where
+    M: Mac + NewMac,
+{
+    let mut mac = M::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::<Hmac<Sha256>>(key, data)
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::<Hmac<Sha512>>(key, data)
+}
+
+/// Compares `l` and `r` in time which only depends on their lengths
+fn constant_time_eq(l: &[u8], r: &[u8]) -> bool {
+    l.ct_eq(r).into()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    use crate::real_std::fmt::Write;
+
+    let mut hex = String::with_capacity(data.len() * 2);
+    for byte in data {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+mod std {
+    pub mod crypto {
+        pub use crate::std_lib::crypto as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            sha256 => primitive!(1, std::crypto::prim::sha256),
+            sha512 => primitive!(1, std::crypto::prim::sha512),
+            blake3 => primitive!(1, std::crypto::prim::blake3),
+            hmac_sha256 => primitive!(2, std::crypto::prim::hmac_sha256),
+            hmac_sha512 => primitive!(2, std::crypto::prim::hmac_sha512),
+            constant_time_eq => primitive!(2, std::crypto::prim::constant_time_eq),
+            to_hex => primitive!(1, std::crypto::prim::to_hex),
+        },
+    )
+}
@@ -0,0 +1,175 @@
+//! Module containing bindings to the `csv` library.
+
+extern crate csv;
+extern crate serde_json;
+
+use crate::real_std::{io::Cursor, sync::Arc};
+
+use crate::vm::{self, thread::Thread, ExternModule};
+
+/// A position in a CSV input. Reading a row returns the position of the next row, leaving the
+/// reader itself untouched, so rows can be read lazily without any mutable state.
+#[derive(Debug, Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.csv.Reader")]
+#[gluon(crate_name = "vm")]
+#[gluon_trace(skip)]
+struct Reader {
+    input: Arc<str>,
+    position: csv::Position,
+}
+
+fn builder() -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.has_headers(false);
+    builder
+}
+
+fn reader(input: &str) -> Reader {
+    Reader {
+        input: Arc::from(input),
+        position: csv::Position::new(),
+    }
+}
+
+fn read_row(reader: &Reader) -> Result<Option<(Vec<String>, Reader)>, String> {
+    // The rows of a stream are read one at a time so the number of fields can't be checked
+    // against the first row
+    let mut csv_reader = builder()
+        .flexible(true)
+        .from_reader(Cursor::new(reader.input.as_bytes()));
+    csv_reader
+        .seek(reader.position.clone())
+        .map_err(|err| err.to_string())?;
+
+    let mut record = csv::StringRecord::new();
+    if !csv_reader
+        .read_record(&mut record)
+        .map_err(|err| err.to_string())?
+    {
+        return Ok(None);
+    }
+    let next = Reader {
+        input: reader.input.clone(),
+        position: csv_reader.position().clone(),
+    };
+    Ok(Some((record.iter().map(String::from).collect(), next)))
+}
+
+fn parse(input: &str) -> Result<Vec<Vec<String>>, String> {
+    builder()
+        .from_reader(input.as_bytes())
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(String::from).collect())
+                .map_err(|err| err.to_string())
+        })
+        .collect()
+}
+
+fn cell_value(cell: &str) -> serde_json::Value {
+    use serde_json::Value;
+
+    if cell.is_empty() {
+        Value::Null
+    } else if let Ok(b) = cell.parse::<bool>() {
+        Value::Bool(b)
+    } else if let Ok(i) = cell.parse::<i64>() {
+        Value::from(i)
+    } else if let Some(f) = cell
+        .parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+    {
+        Value::Number(f)
+    } else {
+        Value::String(cell.into())
+    }
+}
+
+fn to_value(headers: Vec<String>, row: Vec<String>) -> Result<serde_json::Value, String> {
+    if headers.len() != row.len() {
+        return Err(format!(
+            "Expected a row with {} fields but found {}",
+            headers.len(),
+            row.len()
+        ));
+    }
+    Ok(serde_json::Value::Object(
+        headers
+            .into_iter()
+            .zip(row.iter().map(|cell| cell_value(cell)))
+            .collect(),
+    ))
+}
+
+fn cell_string(value: &serde_json::Value) -> Result<String, String> {
+    use serde_json::Value;
+
+    Ok(match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(_) | Value::Object(_) => {
+            return Err("Arrays and objects can't be written as CSV fields".into())
+        }
+    })
+}
+
+fn write(rows: Vec<Vec<String>>) -> Result<String, String> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for row in rows {
+        writer.write_record(&row).map_err(|err| err.to_string())?;
+    }
+    let output = writer.into_inner().map_err(|err| err.to_string())?;
+    String::from_utf8(output).map_err(|err| err.to_string())
+}
+
+fn write_values(values: Vec<serde_json::Value>) -> Result<String, String> {
+    let mut rows: Vec<Vec<String>> = Vec::with_capacity(values.len() + 1);
+    for value in &values {
+        let fields = match value {
+            serde_json::Value::Object(fields) => fields,
+            _ => return Err("Only objects can be written as CSV rows".into()),
+        };
+        if rows.is_empty() {
+            rows.push(fields.keys().cloned().collect());
+        }
+        let row = rows[0]
+            .iter()
+            .map(|header| match fields.get(header) {
+                Some(value) => cell_string(value),
+                None => Err(format!("Missing field `{}`", header)),
+            })
+            .collect::<Result<_, _>>()?;
+        rows.push(row);
+    }
+    write(rows)
+}
+
+mod std {
+    pub mod csv {
+        pub use crate::std_lib::csv as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Reader>("std.csv.Reader", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Reader => Reader,
+
+            reader => primitive!(1, std::csv::prim::reader),
+            read_row => primitive!(1, std::csv::prim::read_row),
+            parse => primitive!(1, std::csv::prim::parse),
+            to_value => primitive!(2, std::csv::prim::to_value),
+            write => primitive!(1, std::csv::prim::write),
+            write_values => primitive!(1, std::csv::prim::write_values)
+        },
+    )
+}
@@ -0,0 +1,412 @@
+//! Module containing fixed-point decimal numbers.
+
+use crate::real_std::{cmp::Ordering, convert::TryFrom, fmt, str::FromStr};
+
+use crate::vm::{self, api::RuntimeResult, thread::Thread, types::VmInt, ExternModule};
+
+/// The largest number of digits a `Decimal` may have after the decimal point
+pub(crate) const MAX_SCALE: u32 = 28;
+
+/// A decimal number `mantissa * 10^-scale`. The scale is kept through addition and
+/// multiplication so that `1.50 + 1.00` shows as `2.50`.
+#[derive(Clone, Copy, Debug, Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.decimal.Decimal")]
+#[gluon_userdata(clone)]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+pub(crate) struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+fn pow10(exponent: u32) -> Option<i128> {
+    10i128.checked_pow(exponent)
+}
+
+fn overflow(operation: &str) -> String {
+    format!("Decimal overflow in `{}`", operation)
+}
+
+fn division_by_zero() -> String {
+    "Decimal division by zero".to_string()
+}
+
+/// Divides `n` by `d` rounding the result to the nearest integer. Ties are rounded away from
+/// zero or to the even neighbour if `half_even` is set.
+fn div_round(n: i128, d: i128, half_even: bool) -> i128 {
+    let quotient = n / d;
+    let remainder = n % d;
+    if remainder == 0 {
+        return quotient;
+    }
+    let away = if n.signum() == d.signum() { 1 } else { -1 };
+    // `remainder * 2` could overflow so compare against the distance to `d` instead
+    match remainder
+        .unsigned_abs()
+        .cmp(&(d.unsigned_abs() - remainder.unsigned_abs()))
+    {
+        Ordering::Less => quotient,
+        Ordering::Greater => quotient + away,
+        Ordering::Equal if half_even && quotient % 2 == 0 => quotient,
+        Ordering::Equal => quotient + away,
+    }
+}
+
+impl Decimal {
+    fn new(mantissa: i128, scale: u32) -> Decimal {
+        Decimal { mantissa, scale }
+    }
+
+    /// Returns the mantissa of `self` at `scale` or `None` if it does not fit
+    fn mantissa_at(&self, scale: u32) -> Option<i128> {
+        if scale >= self.scale {
+            self.mantissa.checked_mul(pow10(scale - self.scale)?)
+        } else {
+            Some(div_round(self.mantissa, pow10(self.scale - scale)?, false))
+        }
+    }
+
+    fn round_to(&self, scale: u32, half_even: bool) -> Decimal {
+        if scale >= self.scale {
+            return *self;
+        }
+        let divisor = pow10(self.scale - scale).expect("scale is at most MAX_SCALE");
+        Decimal::new(div_round(self.mantissa, divisor, half_even), scale)
+    }
+
+    fn truncate_to(&self, scale: u32) -> Decimal {
+        if scale >= self.scale {
+            return *self;
+        }
+        let divisor = pow10(self.scale - scale).expect("scale is at most MAX_SCALE");
+        Decimal::new(self.mantissa / divisor, scale)
+    }
+
+    /// Removes trailing zeroes after the decimal point
+    fn normalize(&self) -> Decimal {
+        let mut result = *self;
+        while result.scale > 0 && result.mantissa % 10 == 0 {
+            result.mantissa /= 10;
+            result.scale -= 1;
+        }
+        result
+    }
+
+    /// Brings `self` and `other` to the same scale, rounding the one with the most decimals if
+    /// the other can not be scaled up.
+    fn align(&self, other: &Decimal, operation: &str) -> Result<(i128, i128, u32), String> {
+        let scale = self.scale.max(other.scale);
+        match (self.mantissa_at(scale), other.mantissa_at(scale)) {
+            (Some(l), Some(r)) => Ok((l, r, scale)),
+            _ => Err(overflow(operation)),
+        }
+    }
+
+    fn checked_add(&self, other: &Decimal) -> Result<Decimal, String> {
+        let (l, r, scale) = self.align(other, "add")?;
+        match l.checked_add(r) {
+            Some(mantissa) => Ok(Decimal::new(mantissa, scale)),
+            None => Err(overflow("add")),
+        }
+    }
+
+    fn checked_neg(&self) -> Result<Decimal, String> {
+        match self.mantissa.checked_neg() {
+            Some(mantissa) => Ok(Decimal::new(mantissa, self.scale)),
+            None => Err(overflow("negate")),
+        }
+    }
+
+    fn checked_mul(&self, other: &Decimal) -> Result<Decimal, String> {
+        let mut l = *self;
+        let mut r = *other;
+        loop {
+            if let Some(mantissa) = l.mantissa.checked_mul(r.mantissa) {
+                let product = Decimal::new(mantissa, l.scale + r.scale);
+                return Ok(product.round_to(MAX_SCALE.min(product.scale), false));
+            }
+            // Decimals past `MAX_SCALE` are rounded away in the result anyway so give them up
+            // from the most precise operand until the product fits
+            if l.scale + r.scale <= MAX_SCALE {
+                return Err(overflow("mul"));
+            }
+            let most_precise = if l.scale >= r.scale { &mut l } else { &mut r };
+            *most_precise = most_precise.round_to(most_precise.scale - 1, false);
+        }
+    }
+
+    fn checked_div(&self, other: &Decimal) -> Result<Decimal, String> {
+        if other.mantissa == 0 {
+            return Err(division_by_zero());
+        }
+        // Scale the dividend up as far as possible so that the quotient gets as many decimals as
+        // fit, up to `MAX_SCALE`
+        let mut numerator = self.mantissa;
+        let mut scale = i64::from(self.scale) - i64::from(other.scale);
+        while scale < i64::from(MAX_SCALE) {
+            match numerator.checked_mul(10) {
+                Some(n) => {
+                    numerator = n;
+                    scale += 1;
+                }
+                None => break,
+            }
+        }
+        let mut quotient = div_round(numerator, other.mantissa, true);
+        while scale < 0 {
+            quotient = quotient.checked_mul(10).ok_or_else(|| overflow("div"))?;
+            scale += 1;
+        }
+        let minimum_scale = self.scale.max(other.scale);
+        let mut result = Decimal::new(quotient, scale as u32).normalize();
+        if result.scale < minimum_scale {
+            if let Some(mantissa) = result.mantissa_at(minimum_scale) {
+                result = Decimal::new(mantissa, minimum_scale);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.mantissa_at(scale), other.mantissa_at(scale)) {
+            (Some(l), Some(r)) => l.cmp(&r),
+            // Only the operand with fewer decimals can fail to scale up and if it does it must
+            // have the larger magnitude
+            (None, _) => 0.cmp(&self.mantissa.signum()).reverse(),
+            (_, None) => 0.cmp(&other.mantissa.signum()),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        if scale == 0 {
+            write!(f, "{}", digits)
+        } else if digits.len() > scale {
+            let (integer, fraction) = digits.split_at(digits.len() - scale);
+            write!(f, "{}.{}", integer, fraction)
+        } else {
+            write!(f, "0.{:0>width$}", digits, width = scale)
+        }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Decimal, String> {
+        let invalid = || format!("Invalid decimal `{}`", s);
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (integer, fraction) = match unsigned.find('.') {
+            Some(i) => (&unsigned[..i], &unsigned[i + 1..]),
+            None => (unsigned, ""),
+        };
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if fraction.len() > MAX_SCALE as usize {
+            return Err(format!(
+                "Decimal `{}` has more than {} decimals",
+                s, MAX_SCALE
+            ));
+        }
+        let mantissa = integer
+            .bytes()
+            .chain(fraction.bytes())
+            .try_fold(0i128, |acc, b| {
+                acc.checked_mul(10)?.checked_add(i128::from(b - b'0'))
+            })
+            .ok_or_else(|| format!("Decimal `{}` does not fit in 128 bits", s))?;
+        Ok(Decimal::new(
+            if negative { -mantissa } else { mantissa },
+            fraction.len() as u32,
+        ))
+    }
+}
+
+fn parse(s: &str) -> Result<Decimal, String> {
+    s.parse()
+}
+
+fn literal(s: &str) -> RuntimeResult<Decimal, String> {
+    s.parse().into()
+}
+
+fn from_int(i: VmInt) -> Decimal {
+    Decimal::new(i128::from(i), 0)
+}
+
+fn from_parts(mantissa: VmInt, scale: VmInt) -> RuntimeResult<Decimal, String> {
+    if scale < 0 || scale > VmInt::from(MAX_SCALE) {
+        return RuntimeResult::Panic(format!("Decimal scale must be between 0 and {}", MAX_SCALE));
+    }
+    RuntimeResult::Return(Decimal::new(i128::from(mantissa), scale as u32))
+}
+
+fn to_int(d: &Decimal) -> RuntimeResult<VmInt, String> {
+    let integer = d.truncate_to(0).mantissa;
+    VmInt::try_from(integer)
+        .map_err(|_| format!("Decimal `{}` does not fit in an Int", d))
+        .into()
+}
+
+fn to_float(d: &Decimal) -> f64 {
+    // Going through the string representation is the simplest way to get a correctly rounded
+    // float
+    d.to_string().parse().unwrap_or(f64::NAN)
+}
+
+fn scale(d: &Decimal) -> VmInt {
+    VmInt::from(d.scale)
+}
+
+fn clamp_scale(scale: VmInt) -> u32 {
+    scale.max(0).min(VmInt::from(MAX_SCALE)) as u32
+}
+
+fn round(scale: VmInt, d: &Decimal) -> Decimal {
+    d.round_to(clamp_scale(scale), false)
+}
+
+fn round_half_even(scale: VmInt, d: &Decimal) -> Decimal {
+    d.round_to(clamp_scale(scale), true)
+}
+
+fn truncate(scale: VmInt, d: &Decimal) -> Decimal {
+    d.truncate_to(clamp_scale(scale))
+}
+
+fn rescale(scale: VmInt, d: &Decimal) -> RuntimeResult<Decimal, String> {
+    let scale = clamp_scale(scale);
+    match d.mantissa_at(scale) {
+        Some(mantissa) => RuntimeResult::Return(Decimal::new(mantissa, scale)),
+        None => RuntimeResult::Panic(overflow("rescale")),
+    }
+}
+
+fn normalize(d: &Decimal) -> Decimal {
+    d.normalize()
+}
+
+fn add(l: &Decimal, r: &Decimal) -> RuntimeResult<Decimal, String> {
+    l.checked_add(r).into()
+}
+
+fn sub(l: &Decimal, r: &Decimal) -> RuntimeResult<Decimal, String> {
+    r.checked_neg().and_then(|r| l.checked_add(&r)).into()
+}
+
+fn mul(l: &Decimal, r: &Decimal) -> RuntimeResult<Decimal, String> {
+    l.checked_mul(r).into()
+}
+
+fn div(l: &Decimal, r: &Decimal) -> RuntimeResult<Decimal, String> {
+    l.checked_div(r).into()
+}
+
+fn rem(l: &Decimal, r: &Decimal) -> RuntimeResult<Decimal, String> {
+    if r.mantissa == 0 {
+        return RuntimeResult::Panic(division_by_zero());
+    }
+    l.align(r, "rem")
+        .map(|(l, r, scale)| Decimal::new(l % r, scale))
+        .into()
+}
+
+fn negate(d: &Decimal) -> RuntimeResult<Decimal, String> {
+    d.checked_neg().into()
+}
+
+fn abs(d: &Decimal) -> RuntimeResult<Decimal, String> {
+    if d.mantissa < 0 {
+        negate(d)
+    } else {
+        RuntimeResult::Return(*d)
+    }
+}
+
+fn eq(l: &Decimal, r: &Decimal) -> bool {
+    l == r
+}
+
+fn compare(l: &Decimal, r: &Decimal) -> Ordering {
+    l.cmp(r)
+}
+
+fn show(d: &Decimal) -> String {
+    d.to_string()
+}
+
+mod std {
+    pub mod decimal {
+        pub use crate::std_lib::decimal as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Decimal>("std.decimal.Decimal", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Decimal => Decimal,
+
+            zero => Decimal::new(0, 0),
+            one => Decimal::new(1, 0),
+            max_scale => VmInt::from(MAX_SCALE),
+            parse => primitive!(1, std::decimal::prim::parse),
+            literal => primitive!(1, std::decimal::prim::literal),
+            from_int => primitive!(1, std::decimal::prim::from_int),
+            from_parts => primitive!(2, std::decimal::prim::from_parts),
+            to_int => primitive!(1, std::decimal::prim::to_int),
+            to_float => primitive!(1, std::decimal::prim::to_float),
+            scale => primitive!(1, std::decimal::prim::scale),
+            round => primitive!(2, std::decimal::prim::round),
+            round_half_even => primitive!(2, std::decimal::prim::round_half_even),
+            truncate => primitive!(2, std::decimal::prim::truncate),
+            rescale => primitive!(2, std::decimal::prim::rescale),
+            normalize => primitive!(1, std::decimal::prim::normalize),
+            add => primitive!(2, std::decimal::prim::add),
+            sub => primitive!(2, std::decimal::prim::sub),
+            mul => primitive!(2, std::decimal::prim::mul),
+            div => primitive!(2, std::decimal::prim::div),
+            rem => primitive!(2, std::decimal::prim::rem),
+            negate => primitive!(1, std::decimal::prim::negate),
+            abs => primitive!(1, std::decimal::prim::abs),
+            eq => primitive!(2, std::decimal::prim::eq),
+            compare => primitive!(2, std::decimal::prim::compare),
+            show => primitive!(1, std::decimal::prim::show)
+        },
+    )
+}
@@ -0,0 +1,193 @@
+//! Module containing functions for encoding and decoding binary data as text.
+
+use crate::real_std::{fmt::Write, str};
+
+use crate::vm::{self, thread::Thread, ExternModule};
+
+type DecodeResult<T> = Result<T, String>;
+
+const BASE64_STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL_SAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64_encode_with(alphabet: &[u8; 64], pad: bool, data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = (u32::from(chunk[0]) << 16)
+            | (u32::from(*chunk.get(1).unwrap_or(&0)) << 8)
+            | u32::from(*chunk.get(2).unwrap_or(&0));
+        let sextets = [(n >> 18) & 63, (n >> 12) & 63, (n >> 6) & 63, n & 63];
+        for &sextet in &sextets[..chunk.len() + 1] {
+            encoded.push(alphabet[sextet as usize] as char);
+        }
+        if pad {
+            for _ in chunk.len()..3 {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode_with(
+    alphabet: &[u8; 64],
+    require_padding: bool,
+    text: &str,
+) -> DecodeResult<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let unpadded = bytes.iter().rposition(|&b| b != b'=').map_or(0, |i| i + 1);
+    let padding = bytes.len() - unpadded;
+    if padding > 2 || (padding != 0 && bytes.len() % 4 != 0) {
+        return Err(format!("invalid base64 padding at position {}", unpadded));
+    }
+    if require_padding && bytes.len() % 4 != 0 {
+        return Err("base64 input length must be a multiple of 4".to_string());
+    }
+    if unpadded % 4 == 1 {
+        return Err("base64 input has an invalid length".to_string());
+    }
+
+    let mut decoded = Vec::with_capacity(unpadded / 4 * 3 + 2);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for (i, &b) in bytes[..unpadded].iter().enumerate() {
+        let value = alphabet.iter().position(|&c| c == b).ok_or_else(|| {
+            format!(
+                "invalid base64 character `{}` at position {}",
+                text[i..].chars().next().unwrap_or('?'),
+                i
+            )
+        })?;
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if buffer != 0 {
+        return Err("base64 input has non-zero trailing bits".to_string());
+    }
+    Ok(decoded)
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    base64_encode_with(BASE64_STANDARD, true, data)
+}
+
+fn base64_decode(text: &str) -> DecodeResult<Vec<u8>> {
+    base64_decode_with(BASE64_STANDARD, true, text)
+}
+
+fn base64_encode_url_safe(data: &[u8]) -> String {
+    base64_encode_with(BASE64_URL_SAFE, false, data)
+}
+
+fn base64_decode_url_safe(text: &str) -> DecodeResult<Vec<u8>> {
+    base64_decode_with(BASE64_URL_SAFE, false, text)
+}
+
+fn hex_digit(text: &str, i: usize) -> DecodeResult<u8> {
+    let b = text.as_bytes()[i];
+    (b as char).to_digit(16).map(|d| d as u8).ok_or_else(|| {
+        format!(
+            "invalid hex character `{}` at position {}",
+            text[i..].chars().next().unwrap_or('?'),
+            i
+        )
+    })
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len() * 2);
+    for byte in data {
+        let _ = write!(encoded, "{:02x}", byte);
+    }
+    encoded
+}
+
+fn hex_decode(text: &str) -> DecodeResult<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return Err("hex input must have an even number of digits".to_string());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| Ok(hex_digit(text, i)? << 4 | hex_digit(text, i + 1)?))
+        .collect()
+}
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-_.~".contains(&b)
+}
+
+fn percent_encode_bytes(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len());
+    for &b in data {
+        if is_unreserved(b) {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", b);
+        }
+    }
+    encoded
+}
+
+fn percent_decode_bytes(text: &str) -> DecodeResult<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if i + 2 >= bytes.len() {
+                return Err(format!("incomplete percent escape at position {}", i));
+            }
+            decoded.push(hex_digit(text, i + 1)? << 4 | hex_digit(text, i + 2)?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+fn percent_encode(text: &str) -> String {
+    percent_encode_bytes(text.as_bytes())
+}
+
+fn percent_decode(text: &str) -> DecodeResult<String> {
+    String::from_utf8(percent_decode_bytes(text)?)
+        .map_err(|err| format!("percent decoded input is not valid UTF-8: {}", err))
+}
+
+mod std {
+    pub mod encoding {
+        pub use crate::std_lib::encoding as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            base64 => record! {
+                encode => primitive!(1, std::encoding::prim::base64_encode),
+                decode => primitive!(1, std::encoding::prim::base64_decode),
+                encode_url_safe => primitive!(1, std::encoding::prim::base64_encode_url_safe),
+                decode_url_safe => primitive!(1, std::encoding::prim::base64_decode_url_safe),
+            },
+            hex => record! {
+                encode => primitive!(1, std::encoding::prim::hex_encode),
+                decode => primitive!(1, std::encoding::prim::hex_decode),
+            },
+            percent => record! {
+                encode => primitive!(1, std::encoding::prim::percent_encode),
+                decode => primitive!(1, std::encoding::prim::percent_decode),
+                encode_bytes => primitive!(1, std::encoding::prim::percent_encode_bytes),
+                decode_bytes => primitive!(1, std::encoding::prim::percent_decode_bytes),
+            },
+        },
+    )
+}
@@ -4,7 +4,13 @@ use crate::real_std::{
     path::{Path, PathBuf},
 };
 
-use crate::vm::{self, api::IO, thread::Thread, ExternModule};
+use crate::vm::{
+    self,
+    api::{WithVM, IO},
+    thread::Thread,
+    vm::Capabilities,
+    ExternModule,
+};
 
 fn args() -> IO<Vec<String>> {
     IO::Value(env::args().collect())
@@ -61,6 +67,17 @@ fn vars() -> IO<Vec<Entry>> {
     )
 }
 
+/// The arguments of the program itself, which differ from `args` when the program is run by
+/// `gluon` or embedded in another application
+fn program_args(vm: WithVM<()>) -> IO<Vec<String>> {
+    let global_env = vm.vm.global_env();
+    if global_env.capabilities().env {
+        IO::Value(global_env.args().to_vec())
+    } else {
+        IO::Exception(Capabilities::not_granted("env"))
+    }
+}
+
 mod std {
     pub mod env {
         pub use crate::std_lib::env as prim;
@@ -68,6 +85,10 @@ mod std {
 }
 
 pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    // Primitives which inspect or change the environment are replaced by ones which throw an
+    // exception if the `env` capability has not been granted
+    let capabilities = vm.global_env().capabilities();
+
     ExternModule::new(
         vm,
         record! {
@@ -80,17 +101,26 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
                 family => crate::real_std::env::consts::FAMILY,
                 os => crate::real_std::env::consts::OS,
             },
-            args => primitive!(0, std::env::prim::args),
-            current_dir => primitive!(0, std::env::prim::current_dir),
-            current_exe => primitive!(0, std::env::prim::current_exe),
+            args => gated_primitive!(capabilities, env, 0, "std.env.prim.args", std::env::prim::args, () -> IO<Vec<String>>),
+            current_dir => gated_primitive!(capabilities, env, 0, "std.env.prim.current_dir", std::env::prim::current_dir, () -> IO<PathBuf>),
+            current_exe => gated_primitive!(capabilities, env, 0, "std.env.prim.current_exe", std::env::prim::current_exe, () -> IO<PathBuf>),
             join_paths => primitive!(1, std::env::prim::join_paths),
-            remove_var => primitive!(1, std::env::prim::remove_var),
-            set_current_dir => primitive!(1, std::env::prim::set_current_dir),
-            set_var => primitive!(2, std::env::prim::set_var),
+            remove_var => gated_primitive!(capabilities, env, 1, "std.env.prim.remove_var", std::env::prim::remove_var, (&str) -> IO<()>),
+            set_current_dir => gated_primitive!(capabilities, env, 1, "std.env.prim.set_current_dir", std::env::prim::set_current_dir, (&str) -> IO<()>),
+            set_var => gated_primitive!(capabilities, env, 2, "std.env.prim.set_var", std::env::prim::set_var, (&str, &str) -> IO<()>),
             split_paths => primitive!(1, std::env::prim::split_paths),
-            temp_dir => primitive!(0, std::env::prim::temp_dir),
-            var => primitive!(1, std::env::prim::var),
-            vars => primitive!(0, std::env::prim::vars),
+            temp_dir => gated_primitive!(capabilities, env, 0, "std.env.prim.temp_dir", std::env::prim::temp_dir, () -> IO<PathBuf>),
+            var => gated_primitive!(capabilities, env, 1, "std.env.prim.var", std::env::prim::var, (&str) -> IO<String>),
+            vars => gated_primitive!(capabilities, env, 0, "std.env.prim.vars", std::env::prim::vars, () -> IO<Vec<Entry>>),
+        },
+    )
+}
+
+pub fn load_args(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            args => primitive!(1, std::env::prim::program_args),
         },
     )
 }
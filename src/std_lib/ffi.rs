@@ -0,0 +1,385 @@
+//! Module containing a foreign function interface to C libraries, implemented on top of `libffi`.
+//!
+//! The signature of a function is described with `CType` values at runtime so the arguments are
+//! marshalled into buffers laid out as C would lay them out before `ffi_call` does the call.
+use crate::real_std::{
+    ffi::{CStr, CString},
+    fmt,
+    mem::{align_of, size_of},
+    os::raw::{c_char, c_void},
+    ptr,
+    sync::Arc,
+};
+
+use libffi::middle::{Cif, CodePtr, Type};
+
+use crate::vm::{self, api::IO, thread::Thread, vm::Capabilities, ExternModule};
+
+#[derive(Clone, Debug, Getable, VmType)]
+#[gluon(vm_type = "std.ffi.types.CType")]
+#[gluon(crate_name = "::vm")]
+enum CType {
+    Void,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Pointer,
+    CString,
+    Struct(Vec<CType>),
+}
+
+#[derive(Debug, Getable, Pushable, VmType)]
+#[gluon(vm_type = "std.ffi.types.CValue")]
+#[gluon(crate_name = "::vm")]
+enum CValue {
+    Unit,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Record(Vec<CValue>),
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+impl CType {
+    /// Returns the size and alignment of the type as laid out by C
+    fn layout(&self) -> (usize, usize) {
+        match self {
+            CType::Void => (0, 1),
+            CType::I8 | CType::U8 => (1, 1),
+            CType::I16 | CType::U16 => (2, 2),
+            CType::I32 | CType::U32 | CType::F32 => (4, 4),
+            CType::I64 | CType::U64 | CType::F64 => (8, 8),
+            CType::Pointer | CType::CString => {
+                (size_of::<*const c_void>(), align_of::<*const c_void>())
+            }
+            CType::Struct(fields) => {
+                let (size, align) = fields.iter().fold((0, 1), |(offset, align), field| {
+                    let (field_size, field_align) = field.layout();
+                    (
+                        align_up(offset, field_align) + field_size,
+                        align.max(field_align),
+                    )
+                });
+                (align_up(size, align), align)
+            }
+        }
+    }
+
+    fn field_offsets(fields: &[CType]) -> impl Iterator<Item = (&CType, usize)> {
+        let mut offset = 0;
+        fields.iter().map(move |field| {
+            let (size, align) = field.layout();
+            let field_offset = align_up(offset, align);
+            offset = field_offset + size;
+            (field, field_offset)
+        })
+    }
+
+    /// Returns true if `self` is, or contains, a struct without fields which libffi can't describe
+    fn has_empty_struct(&self) -> bool {
+        match self {
+            CType::Struct(fields) => {
+                fields.is_empty() || fields.iter().any(|f| f.has_empty_struct())
+            }
+            _ => false,
+        }
+    }
+
+    fn ffi_type(&self) -> Type {
+        match self {
+            CType::Void => Type::void(),
+            CType::I8 => Type::i8(),
+            CType::I16 => Type::i16(),
+            CType::I32 => Type::i32(),
+            CType::I64 => Type::i64(),
+            CType::U8 => Type::u8(),
+            CType::U16 => Type::u16(),
+            CType::U32 => Type::u32(),
+            CType::U64 => Type::u64(),
+            CType::F32 => Type::f32(),
+            CType::F64 => Type::f64(),
+            CType::Pointer | CType::CString => Type::pointer(),
+            CType::Struct(fields) => Type::structure(fields.iter().map(CType::ffi_type)),
+        }
+    }
+}
+
+struct LibraryHandle(*mut c_void);
+
+// SAFETY The handle is only used to look up symbols, which `dlsym` allows from any thread
+unsafe impl Send for LibraryHandle {}
+unsafe impl Sync for LibraryHandle {}
+
+impl Drop for LibraryHandle {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dlclose(self.0);
+        }
+    }
+}
+
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.ffi.Library")]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct Library {
+    name: String,
+    handle: Arc<LibraryHandle>,
+}
+
+impl fmt::Debug for Library {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Library({})", self.name)
+    }
+}
+
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.ffi.Function")]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct Function {
+    name: String,
+    code: CodePtr,
+    cif: Cif,
+    args: Vec<CType>,
+    ret: CType,
+    // Keeps the library loaded for as long as the function can be called
+    _library: Arc<LibraryHandle>,
+}
+
+// SAFETY The cif is not mutated after it has been prepared and the code pointer is only read
+unsafe impl Send for Function {}
+unsafe impl Sync for Function {}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Function({})", self.name)
+    }
+}
+
+fn dl_error() -> String {
+    // SAFETY `dlerror` returns either null or a nul terminated string
+    unsafe {
+        let err = libc::dlerror();
+        if err.is_null() {
+            "Unknown error".to_string()
+        } else {
+            CStr::from_ptr(err).to_string_lossy().into_owned()
+        }
+    }
+}
+
+fn open(name: &str) -> IO<Library> {
+    let c_name = match CString::new(name) {
+        Ok(c_name) => c_name,
+        Err(err) => return IO::Exception(err.to_string()),
+    };
+    let handle = unsafe { libc::dlopen(c_name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return IO::Exception(dl_error());
+    }
+    IO::Value(Library {
+        name: name.to_string(),
+        handle: Arc::new(LibraryHandle(handle)),
+    })
+}
+
+fn function(library: &Library, name: &str, args: Vec<CType>, ret: CType) -> IO<Function> {
+    if let Some(arg) = args.iter().find(|arg| matches!(arg, CType::Void)) {
+        return IO::Exception(format!("`{:?}` can't be used as an argument type", arg));
+    }
+    if let Some(typ) = args
+        .iter()
+        .chain(Some(&ret))
+        .find(|typ| typ.has_empty_struct())
+    {
+        return IO::Exception(format!("`{:?}` contains a struct without fields", typ));
+    }
+
+    let c_name = match CString::new(name) {
+        Ok(c_name) => c_name,
+        Err(err) => return IO::Exception(err.to_string()),
+    };
+    let symbol = unsafe { libc::dlsym(library.handle.0, c_name.as_ptr()) };
+    if symbol.is_null() {
+        return IO::Exception(format!("`{}` is not defined in `{}`", name, library.name));
+    }
+
+    let cif = Cif::new(args.iter().map(CType::ffi_type), ret.ffi_type());
+
+    IO::Value(Function {
+        name: name.to_string(),
+        // The caller declares that the symbol is a function with this signature
+        code: CodePtr::from_ptr(symbol),
+        cif,
+        args,
+        ret,
+        _library: library.handle.clone(),
+    })
+}
+
+/// Writes `value` to `dest` as a C value of type `typ`. `dest` must have room for `typ`.
+unsafe fn write_value(
+    typ: &CType,
+    value: &CValue,
+    dest: *mut u8,
+    strings: &mut Vec<CString>,
+) -> Result<(), String> {
+    macro_rules! write_as {
+        ($t: ty, $value: expr) => {
+            ptr::write_unaligned(dest as *mut $t, $value as $t)
+        };
+    }
+    match (typ, value) {
+        (CType::I8, CValue::Int(i)) => write_as!(i8, *i),
+        (CType::I16, CValue::Int(i)) => write_as!(i16, *i),
+        (CType::I32, CValue::Int(i)) => write_as!(i32, *i),
+        (CType::I64, CValue::Int(i)) => write_as!(i64, *i),
+        (CType::U8, CValue::Int(i)) => write_as!(u8, *i),
+        (CType::U16, CValue::Int(i)) => write_as!(u16, *i),
+        (CType::U32, CValue::Int(i)) => write_as!(u32, *i),
+        (CType::U64, CValue::Int(i)) => write_as!(u64, *i),
+        (CType::Pointer, CValue::Int(i)) => write_as!(usize, *i),
+        (CType::F32, CValue::Float(f)) => write_as!(f32, *f),
+        (CType::F64, CValue::Float(f)) => write_as!(f64, *f),
+        (CType::CString, CValue::String(s)) => {
+            let s = CString::new(&s[..]).map_err(|err| err.to_string())?;
+            write_as!(*const c_char, s.as_ptr());
+            // Kept alive until the call has returned
+            strings.push(s);
+        }
+        (CType::Struct(fields), CValue::Record(values)) if fields.len() == values.len() => {
+            for ((field, offset), value) in CType::field_offsets(fields).zip(values) {
+                write_value(field, value, dest.add(offset), strings)?;
+            }
+        }
+        _ => {
+            return Err(format!(
+                "Expected a value of type `{:?}`, got `{:?}`",
+                typ, value
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Reads a C value of type `typ` from `src`.
+///
+/// Integer return values which are smaller than a register are widened by libffi, reading them
+/// through their first bytes relies on the supported targets being little endian.
+unsafe fn read_value(typ: &CType, src: *const u8) -> Result<CValue, String> {
+    macro_rules! read_as {
+        ($t: ty) => {
+            ptr::read_unaligned(src as *const $t)
+        };
+    }
+    Ok(match typ {
+        CType::Void => CValue::Unit,
+        CType::I8 => CValue::Int(read_as!(i8) as i64),
+        CType::I16 => CValue::Int(read_as!(i16) as i64),
+        CType::I32 => CValue::Int(read_as!(i32) as i64),
+        CType::I64 => CValue::Int(read_as!(i64)),
+        CType::U8 => CValue::Int(read_as!(u8) as i64),
+        CType::U16 => CValue::Int(read_as!(u16) as i64),
+        CType::U32 => CValue::Int(read_as!(u32) as i64),
+        CType::U64 => CValue::Int(read_as!(u64) as i64),
+        CType::Pointer => CValue::Int(read_as!(usize) as i64),
+        CType::F32 => CValue::Float(read_as!(f32) as f64),
+        CType::F64 => CValue::Float(read_as!(f64)),
+        CType::CString => {
+            let s = read_as!(*const c_char);
+            if s.is_null() {
+                return Err("Expected a string, got a null pointer".to_string());
+            }
+            CValue::String(CStr::from_ptr(s).to_string_lossy().into_owned())
+        }
+        CType::Struct(fields) => CValue::Record(
+            CType::field_offsets(fields)
+                .map(|(field, offset)| read_value(field, src.add(offset)))
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+/// Allocates a zeroed buffer which can hold `size` bytes with any alignment up to 8
+fn buffer(size: usize) -> Vec<u64> {
+    vec![0; align_up(size.max(1), 8) / 8]
+}
+
+fn call(function: &Function, args: Vec<CValue>) -> IO<CValue> {
+    if args.len() != function.args.len() {
+        return IO::Exception(format!(
+            "`{}` expects {} arguments but {} were given",
+            function.name,
+            function.args.len(),
+            args.len()
+        ));
+    }
+
+    let mut strings = Vec::new();
+    let mut buffers = Vec::with_capacity(args.len());
+    for (typ, value) in function.args.iter().zip(&args) {
+        let mut buffer = buffer(typ.layout().0);
+        if let Err(err) =
+            unsafe { write_value(typ, value, buffer.as_mut_ptr() as *mut u8, &mut strings) }
+        {
+            return IO::Exception(err);
+        }
+        buffers.push(buffer);
+    }
+    let mut arg_ptrs: Vec<_> = buffers
+        .iter_mut()
+        .map(|buffer| buffer.as_mut_ptr() as *mut c_void)
+        .collect();
+
+    // libffi requires the return value to have room for at least a register
+    let mut ret = buffer(function.ret.layout().0.max(size_of::<u64>()));
+    // SAFETY The buffers match the signature the cif was prepared with
+    unsafe {
+        libffi::raw::ffi_call(
+            function.cif.as_raw_ptr(),
+            Some(*function.code.as_fun()),
+            ret.as_mut_ptr() as *mut c_void,
+            arg_ptrs.as_mut_ptr(),
+        );
+        IO::from(read_value(&function.ret, ret.as_ptr() as *const u8))
+    }
+}
+
+mod std {
+    pub mod ffi {
+        pub use crate::std_lib::ffi as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Library>("std.ffi.Library", &[])?;
+    vm.register_type::<Function>("std.ffi.Function", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Library => Library,
+            type Function => Function,
+            open => if vm.global_env().capabilities().ffi {
+                primitive!(1, std::ffi::prim::open)
+            } else {
+                primitive!(1, "std.ffi.prim.open", |_: &str| -> IO<Library> {
+                    IO::Exception(Capabilities::not_granted("ffi"))
+                })
+            },
+            function => primitive!(4, std::ffi::prim::function),
+            call => primitive!(2, std::ffi::prim::call),
+        },
+    )
+}
@@ -0,0 +1,320 @@
+//! Module containing the format specifications used by `std.format` and the `format!` macro.
+
+use crate::real_std::{mem, str::FromStr};
+
+use crate::vm::{self, api::RuntimeResult, thread::Thread, ExternModule};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Default,
+    LowerHex,
+    UpperHex,
+    Octal,
+    Binary,
+    Exponent,
+}
+
+/// A parsed format specification, `[[fill]align][+][#][0][width][.precision][kind]`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Spec {
+    fill: char,
+    align: Option<Align>,
+    sign: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: Kind,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Spec {
+            fill: ' ',
+            align: None,
+            sign: false,
+            alternate: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            kind: Kind::Default,
+        }
+    }
+}
+
+fn parse_align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+fn parse_number(s: &str) -> (Option<usize>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    if end == 0 {
+        (None, s)
+    } else {
+        (s[..end].parse().ok(), &s[end..])
+    }
+}
+
+impl FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Spec, String> {
+        let mut spec = Spec::default();
+        let mut rest = s;
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(c)) if parse_align(c).is_some() => {
+                spec.fill = fill;
+                spec.align = parse_align(c);
+                rest = &rest[fill.len_utf8() + 1..];
+            }
+            (Some(c), _) if parse_align(c).is_some() => {
+                spec.align = parse_align(c);
+                rest = &rest[1..];
+            }
+            _ => (),
+        }
+
+        if rest.starts_with('+') {
+            spec.sign = true;
+            rest = &rest[1..];
+        }
+        if rest.starts_with('#') {
+            spec.alternate = true;
+            rest = &rest[1..];
+        }
+        if rest.starts_with('0') {
+            spec.zero = true;
+            rest = &rest[1..];
+        }
+
+        let (width, after_width) = parse_number(rest);
+        spec.width = width.unwrap_or(0);
+        rest = after_width;
+
+        if rest.starts_with('.') {
+            let (precision, after_precision) = parse_number(&rest[1..]);
+            spec.precision = Some(
+                precision.ok_or_else(|| format!("Expected a precision after `.` in `{}`", s))?,
+            );
+            rest = after_precision;
+        }
+
+        spec.kind = match rest {
+            "" => Kind::Default,
+            "x" => Kind::LowerHex,
+            "X" => Kind::UpperHex,
+            "o" => Kind::Octal,
+            "b" => Kind::Binary,
+            "e" => Kind::Exponent,
+            _ => return Err(format!("Invalid format specification `{}`", s)),
+        };
+
+        Ok(spec)
+    }
+}
+
+impl Spec {
+    fn pad(&self, body: &str, default_align: Align) -> String {
+        let len = body.chars().count();
+        if len >= self.width {
+            return body.to_string();
+        }
+        let padding = self.width - len;
+        let (before, after) = match self.align.unwrap_or(default_align) {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let mut padded = String::with_capacity(body.len() + padding);
+        padded.extend((0..before).map(|_| self.fill));
+        padded.push_str(body);
+        padded.extend((0..after).map(|_| self.fill));
+        padded
+    }
+
+    /// Pads a number where `sign` and `prefix` must stay in front of any zero padding
+    fn pad_number(&self, negative: bool, prefix: &str, digits: &str) -> String {
+        let sign = if negative {
+            "-"
+        } else if self.sign {
+            "+"
+        } else {
+            ""
+        };
+        if self.zero && self.align.is_none() {
+            let len = sign.len() + prefix.len() + digits.chars().count();
+            let zeros = self.width.saturating_sub(len);
+            let mut padded = String::with_capacity(len + zeros);
+            padded.push_str(sign);
+            padded.push_str(prefix);
+            padded.extend((0..zeros).map(|_| '0'));
+            padded.push_str(digits);
+            padded
+        } else {
+            self.pad(&format!("{}{}{}", sign, prefix, digits), Align::Right)
+        }
+    }
+
+    fn format_int(&self, value: i64) -> String {
+        let magnitude = value.unsigned_abs();
+        let (prefix, digits) = match self.kind {
+            Kind::LowerHex => ("0x", format!("{:x}", magnitude)),
+            Kind::UpperHex => ("0x", format!("{:X}", magnitude)),
+            Kind::Octal => ("0o", format!("{:o}", magnitude)),
+            Kind::Binary => ("0b", format!("{:b}", magnitude)),
+            Kind::Exponent => ("", format!("{:e}", magnitude)),
+            Kind::Default => ("", magnitude.to_string()),
+        };
+        let prefix = if self.alternate { prefix } else { "" };
+        self.pad_number(value < 0, prefix, &digits)
+    }
+
+    fn format_float(&self, value: f64) -> String {
+        let magnitude = value.abs();
+        let digits = match (self.kind, self.precision) {
+            (Kind::Exponent, Some(precision)) => format!("{:.*e}", precision, magnitude),
+            (Kind::Exponent, None) => format!("{:e}", magnitude),
+            (_, Some(precision)) => format!("{:.*}", precision, magnitude),
+            (_, None) => format!("{}", magnitude),
+        };
+        self.pad_number(value.is_sign_negative() && !value.is_nan(), "", &digits)
+    }
+
+    fn format_str(&self, value: &str) -> String {
+        match self.precision {
+            Some(precision) => {
+                let truncated: String = value.chars().take(precision).collect();
+                self.pad(&truncated, Align::Left)
+            }
+            None => self.pad(value, Align::Left),
+        }
+    }
+}
+
+/// Runs `f` with the parsed `spec`. Since `format!` validates its specifications while
+/// compiling, an invalid specification can only come from a direct call to `std.format`
+fn with_spec(spec: &str, f: impl FnOnce(&Spec) -> String) -> RuntimeResult<String, String> {
+    spec.parse::<Spec>().map(|spec| f(&spec)).into()
+}
+
+fn format_int(spec: &str, value: vm::types::VmInt) -> RuntimeResult<String, String> {
+    with_spec(spec, |spec| spec.format_int(value))
+}
+
+fn format_float(spec: &str, value: f64) -> RuntimeResult<String, String> {
+    with_spec(spec, |spec| spec.format_float(value))
+}
+
+fn format_byte(spec: &str, value: u8) -> RuntimeResult<String, String> {
+    with_spec(spec, |spec| spec.format_int(i64::from(value)))
+}
+
+fn format_string(spec: &str, value: &str) -> RuntimeResult<String, String> {
+    with_spec(spec, |spec| spec.format_str(value))
+}
+
+fn format_char(spec: &str, value: char) -> RuntimeResult<String, String> {
+    with_spec(spec, |spec| spec.format_str(value.encode_utf8(&mut [0; 4])))
+}
+
+fn concat(parts: Vec<String>) -> String {
+    parts.concat()
+}
+
+/// A piece of a `format!` template
+#[derive(Debug, PartialEq)]
+pub(crate) enum Piece<'a> {
+    Text(String),
+    Argument { path: Vec<&'a str>, spec: &'a str },
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Splits a `format!` template into text and `{path:spec}` arguments. `{{` and `}}` are used to
+/// write literal braces.
+pub(crate) fn parse_template(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(|c| c == '{' || c == '}') {
+        text.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        if rest[i + 1..].starts_with(brace) {
+            text.push_str(brace);
+            rest = &rest[i + 2..];
+            continue;
+        }
+        if brace == "}" {
+            return Err("Unmatched `}` in format string, use `}}` to write a literal `}`".into());
+        }
+
+        let end = rest[i..]
+            .find('}')
+            .map(|end| i + end)
+            .ok_or_else(|| "Unclosed `{` in format string, use `{{` to write a literal `{`")?;
+        let argument = &rest[i + 1..end];
+        let (path, spec) = match argument.find(':') {
+            Some(colon) => (&argument[..colon], &argument[colon + 1..]),
+            None => (argument, ""),
+        };
+        let path: Vec<_> = path.trim().split('.').collect();
+        if !path.iter().all(|name| is_identifier(name)) {
+            return Err(format!(
+                "Expected a variable or field access in `{{{}}}`",
+                argument
+            ));
+        }
+        spec.parse::<Spec>()?;
+
+        if !text.is_empty() {
+            pieces.push(Piece::Text(mem::take(&mut text)));
+        }
+        pieces.push(Piece::Argument { path, spec });
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+mod std {
+    pub mod format {
+        pub use crate::std_lib::format as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            format_int => primitive!(2, std::format::prim::format_int),
+            format_float => primitive!(2, std::format::prim::format_float),
+            format_byte => primitive!(2, std::format::prim::format_byte),
+            format_string => primitive!(2, std::format::prim::format_string),
+            format_char => primitive!(2, std::format::prim::format_char),
+            concat => primitive!(1, std::format::prim::concat),
+        },
+    )
+}
@@ -204,6 +204,7 @@ field_decl! { http, method, uri, status, body, request, response, headers }
 type Request = record_type! {
     method => String,
     uri => Uri,
+    headers => Headers,
     body => Body
 };
 
@@ -237,6 +238,10 @@ async fn listen_(
     thread: RootedThread,
     handler: OpaqueValue<RootedThread, Handler<Response>>,
 ) -> vm::Result<()> {
+    if !thread.global_env().capabilities().net {
+        return Err(vm::Error::Message(vm::vm::Capabilities::not_granted("net")));
+    }
+
     let thread = match thread.new_thread() {
         Ok(thread) => thread,
         Err(err) => return Err(err),
@@ -268,6 +273,7 @@ async fn listen_(
             let gluon_request = record_no_decl! {
                 method => parts.method.as_str().to_owned(),
                 uri => Uri(parts.uri),
+                headers => Headers(parts.headers),
                 // Since `Body` implements `Userdata` it can be directly pushed to gluon
                 body => Body(Arc::new(Mutex::new(Box::pin(
                     body
@@ -437,6 +443,81 @@ macro_rules! uri_binds {
     }
 }
 
+/// Matches `path` against `pattern`, where a segment of the form `:name` in `pattern` matches any
+/// single segment and a final `*` segment matches the rest of the path. Returns the segments
+/// which were matched by name.
+fn match_path(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut path_segments = path.trim_start_matches('/').split('/');
+    for segment in pattern.trim_start_matches('/').split('/') {
+        if segment == "*" {
+            return Some(params);
+        }
+        let path_segment = path_segments.next()?;
+        if let Some(name) = segment.strip_prefix(':') {
+            if path_segment.is_empty() {
+                return None;
+            }
+            params.push((name.to_owned(), percent_decode(path_segment)));
+        } else if segment != path_segment {
+            return None;
+        }
+    }
+    if path_segments.next().is_some() {
+        None
+    } else {
+        Some(params)
+    }
+}
+
+/// Decodes `%XX` escapes, replacing sequences which are not valid UTF-8 by `U+FFFD`
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' => crate::real_std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Splits the query string of `uri` into its decoded `key=value` pairs
+fn query_pairs(uri: &Uri) -> Vec<(String, String)> {
+    uri.0
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let pair = pair.replace('+', " ");
+            let mut iter = pair.splitn(2, '=');
+            let key = percent_decode(iter.next().unwrap_or(""));
+            let value = percent_decode(iter.next().unwrap_or(""));
+            (key, value)
+        })
+        .collect()
+}
+
+/// Returns the value of the header `name`, ignoring case
+fn find_header(name: &str, headers: Headers) -> Option<Vec<u8>> {
+    headers.0.get(name).map(|value| value.as_bytes().to_owned())
+}
+
 mod std {
     pub(crate) mod http {
         pub(crate) use crate::std_lib::http as prim;
@@ -450,6 +531,9 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
             listen => primitive!(2, async fn std::http::prim::listen),
             read_chunk => primitive!(1, async fn std::http::prim::read_chunk),
             write_response => primitive!(2, async fn std::http::prim::write_response),
+            match_path => primitive!(2, std::http::prim::match_path),
+            find_header => primitive!(2, std::http::prim::find_header),
+            query_pairs => primitive!(1, "std.http.prim.query_pairs", std::http::prim::query_pairs),
             port => primitive!(1, "std.http.prim.uri.port", |u: &Uri| (u.0).port().map(|p| p.as_u16())),
             uri => uri_binds!(path host query to_string)
         },
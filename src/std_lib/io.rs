@@ -19,18 +19,30 @@ use crate::vm::{
     stack::{self, StackFrame},
     thread::{RootedThread, Thread, ThreadInternal},
     types::*,
+    vm::OutputStream,
     ExternModule, Result,
 };
 
 use crate::{compiler_pipeline::*, Error, ModuleCompiler, ThreadExt};
 
-fn print(s: &str) -> IO<()> {
-    print!("{}", s);
+/// Writes `s` to `stream`, or to the output the vm was built with
+fn write_output(vm: &Thread, stream: OutputStream, s: &str) {
+    match vm.global_env().output() {
+        Some(output) => output(stream, s),
+        None => match stream {
+            OutputStream::Stdout => print!("{}", s),
+            OutputStream::Stderr => eprint!("{}", s),
+        },
+    }
+}
+
+fn print(WithVM { vm, value: s }: WithVM<&str>) -> IO<()> {
+    write_output(vm, OutputStream::Stdout, s);
     IO::Value(())
 }
 
-fn println(s: &str) -> IO<()> {
-    println!("{}", s);
+fn println(WithVM { vm, value: s }: WithVM<&str>) -> IO<()> {
+    write_output(vm, OutputStream::Stdout, &format!("{}\n", s));
     IO::Value(())
 }
 
@@ -41,13 +53,13 @@ fn flush_stdout() -> IO<()> {
     }
 }
 
-fn eprint(s: &str) -> IO<()> {
-    eprint!("{}", s);
+fn eprint(WithVM { vm, value: s }: WithVM<&str>) -> IO<()> {
+    write_output(vm, OutputStream::Stderr, s);
     IO::Value(())
 }
 
-fn eprintln(s: &str) -> IO<()> {
-    eprintln!("{}", s);
+fn eprintln(WithVM { vm, value: s }: WithVM<&str>) -> IO<()> {
+    write_output(vm, OutputStream::Stderr, &format!("{}\n", s));
     IO::Value(())
 }
 
@@ -367,6 +379,10 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
 
     let wrap = vec![Pop(1), Return];
 
+    // Primitives which need a capability which has not been granted are replaced by ones which
+    // throw an exception
+    let capabilities = vm.global_env().capabilities();
+
     // IO functions
     ExternModule::new(
         vm,
@@ -376,22 +392,22 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
             type std::io::IO a => IO<A>,
             flat_map => TypedBytecode::<FlatMap>::new("std.io.prim.flat_map", 3, flat_map),
             wrap => TypedBytecode::<Wrap>::new("std.io.prim.wrap", 2, wrap),
-            open_file_with => primitive!(2, std::io::prim::open_file_with),
-            read_file_to_string => primitive!(1, std::io::prim::read_file_to_string),
-            read_file_to_array => primitive!(1, std::io::prim::read_file_to_array),
+            open_file_with => gated_primitive!(capabilities, fs, 2, "std.io.prim.open_file_with", std::io::prim::open_file_with, (&str, Vec<OpenOptions>) -> IO<GluonFile>),
+            read_file_to_string => gated_primitive!(capabilities, fs, 1, "std.io.prim.read_file_to_string", std::io::prim::read_file_to_string, (&str) -> IO<String>),
+            read_file_to_array => gated_primitive!(capabilities, fs, 1, "std.io.prim.read_file_to_array", std::io::prim::read_file_to_array, (&str) -> IO<Vec<u8>>),
             read_file => primitive!(2, std::io::prim::read_file),
             read_file_to_end => primitive!(1, std::io::prim::read_file_to_end),
             write_slice_file => primitive!(4, std::io::prim::write_slice_file),
             flush_file => primitive!(1, std::io::prim::flush_file),
             close_file => primitive!(1, std::io::prim::close_file),
             is_file_closed => primitive!(1, std::io::prim::is_file_closed),
-            read_char => primitive!(0, std::io::prim::read_char),
-            read_line => primitive!(0, std::io::prim::read_line),
-            print => primitive!(1, std::io::prim::print),
-            println => primitive!(1, std::io::prim::println),
-            flush_stdout => primitive!(0, std::io::prim::flush_stdout),
-            eprint => primitive!(1, std::io::prim::eprint),
-            eprintln => primitive!(1, std::io::prim::eprintln),
+            read_char => gated_primitive!(capabilities, io, 0, "std.io.prim.read_char", std::io::prim::read_char, () -> IO<char>),
+            read_line => gated_primitive!(capabilities, io, 0, "std.io.prim.read_line", std::io::prim::read_line, () -> IO<String>),
+            print => gated_primitive!(capabilities, io, 1, "std.io.prim.print", std::io::prim::print, (WithVM<&str>) -> IO<()>),
+            println => gated_primitive!(capabilities, io, 1, "std.io.prim.println", std::io::prim::println, (WithVM<&str>) -> IO<()>),
+            flush_stdout => gated_primitive!(capabilities, io, 0, "std.io.prim.flush_stdout", std::io::prim::flush_stdout, () -> IO<()>),
+            eprint => gated_primitive!(capabilities, io, 1, "std.io.prim.eprint", std::io::prim::eprint, (WithVM<&str>) -> IO<()>),
+            eprintln => gated_primitive!(capabilities, io, 1, "std.io.prim.eprintln", std::io::prim::eprintln, (WithVM<&str>) -> IO<()>),
             catch => primitive!(2, async fn std::io::prim::catch),
             throw => primitive!(1, std::io::prim::throw),
             run_expr => primitive!(1, async fn std::io::prim::run_expr),
@@ -0,0 +1,113 @@
+//! Module containing bindings to the `log` crate, so that records written by gluon programs are
+//! handled by the logger of the host application.
+
+use log::kv::{self, ToValue};
+
+use crate::vm::{self, api::IO, thread::Thread, types::VmInt, ExternModule};
+
+#[derive(Clone, Copy, Getable, Pushable, VmType)]
+#[gluon(vm_type = "std.log.types.Level")]
+#[gluon(crate_name = "::vm")]
+enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => log::Level::Error,
+            Level::Warn => log::Level::Warn,
+            Level::Info => log::Level::Info,
+            Level::Debug => log::Level::Debug,
+            Level::Trace => log::Level::Trace,
+        }
+    }
+}
+
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Trace,
+        }
+    }
+}
+
+#[derive(Getable, VmType)]
+#[gluon(vm_type = "std.log.types.Value")]
+#[gluon(crate_name = "::vm")]
+enum Value<'a> {
+    String(&'a str),
+    Int(VmInt),
+    Float(f64),
+    Bool(bool),
+}
+
+impl ToValue for Value<'_> {
+    fn to_value(&self) -> kv::Value<'_> {
+        match self {
+            Value::String(s) => s.to_value(),
+            Value::Int(i) => i.to_value(),
+            Value::Float(f) => f.to_value(),
+            Value::Bool(b) => b.to_value(),
+        }
+    }
+}
+
+fn log(level: Level, target: &str, message: &str, fields: Vec<(&str, Value)>) -> IO<()> {
+    let level = log::Level::from(level);
+    if level <= log::STATIC_MAX_LEVEL && level <= log::max_level() {
+        log::logger().log(
+            &log::Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .key_values(&&fields[..])
+                .build(),
+        );
+    }
+    IO::Value(())
+}
+
+fn enabled(level: Level, target: &str) -> IO<bool> {
+    let level = log::Level::from(level);
+    IO::Value(
+        level <= log::STATIC_MAX_LEVEL
+            && level <= log::max_level()
+            && log::logger().enabled(&log::Metadata::builder().level(level).target(target).build()),
+    )
+}
+
+fn max_level() -> IO<Option<Level>> {
+    IO::Value(log::max_level().to_level().map(Level::from))
+}
+
+fn flush() -> IO<()> {
+    log::logger().flush();
+    IO::Value(())
+}
+
+mod std {
+    pub mod log {
+        pub use crate::std_lib::log as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            log => primitive!(4, std::log::prim::log),
+            enabled => primitive!(2, std::log::prim::enabled),
+            max_level => primitive!(0, std::log::prim::max_level),
+            flush => primitive!(0, std::log::prim::flush),
+        },
+    )
+}
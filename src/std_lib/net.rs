@@ -0,0 +1,480 @@
+//! TCP and UDP sockets which run on the host's tokio executor.
+
+use crate::real_std::{
+    convert::TryFrom,
+    fmt, io,
+    sync::{Arc, Mutex},
+    time,
+};
+
+use futures::prelude::*;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        self,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        udp::{RecvHalf, SendHalf},
+    },
+    sync::Mutex as AsyncMutex,
+};
+
+use crate::vm::{
+    api::{WithVM, IO},
+    duration::Duration,
+    thread::Thread,
+    vm::Capabilities,
+    ExternModule, Result,
+};
+
+/// The timeout of an operation on a socket. `None` waits forever.
+#[derive(Default)]
+struct Timeouts {
+    read: Mutex<Option<time::Duration>>,
+    write: Mutex<Option<time::Duration>>,
+}
+
+fn to_std_duration(duration: &Duration) -> time::Duration {
+    time::Duration::from_nanos(u64::try_from(duration.0.max(0)).unwrap_or(u64::MAX))
+}
+
+fn set_timeout(timeout: &Mutex<Option<time::Duration>>, duration: Option<&Duration>) -> IO<()> {
+    *timeout.lock().unwrap() = duration.map(to_std_duration);
+    IO::Value(())
+}
+
+/// Runs `future`, failing with a `TimedOut` error if it does not complete within `timeout`
+async fn with_timeout<T>(
+    timeout: Option<time::Duration>,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"))),
+        None => future.await,
+    }
+}
+
+/// Sockets may only be opened if the `net` capability has been granted
+fn check_capability(vm: &Thread) -> io::Result<()> {
+    if vm.global_env().capabilities().net {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            Capabilities::not_granted("net"),
+        ))
+    }
+}
+
+fn check_slice(buf: &[u8], start: usize, end: usize) -> io::Result<&[u8]> {
+    buf.get(start..end).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "slice {}..{} is out of range for array of length {}",
+                start,
+                end,
+                buf.len()
+            ),
+        )
+    })
+}
+
+pub mod tcp {
+    use super::*;
+
+    /// A TCP connection. Reads and writes use separate halves of the connection so that one task
+    /// can read while another writes.
+    #[derive(Userdata, Trace, VmType)]
+    #[gluon(vm_type = "std.net.tcp.TcpStream")]
+    #[gluon(crate_name = "::vm")]
+    #[gluon_trace(skip)]
+    pub struct TcpStream {
+        reader: Arc<AsyncMutex<OwnedReadHalf>>,
+        writer: Arc<AsyncMutex<Option<OwnedWriteHalf>>>,
+        local_address: String,
+        peer_address: String,
+        timeouts: Arc<Timeouts>,
+    }
+
+    impl fmt::Debug for TcpStream {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "TcpStream({} -> {})",
+                self.local_address, self.peer_address
+            )
+        }
+    }
+
+    impl TcpStream {
+        fn new(stream: net::TcpStream) -> io::Result<Self> {
+            let local_address = stream.local_addr()?.to_string();
+            let peer_address = stream.peer_addr()?.to_string();
+            let (reader, writer) = stream.into_split();
+            Ok(TcpStream {
+                reader: Arc::new(AsyncMutex::new(reader)),
+                writer: Arc::new(AsyncMutex::new(Some(writer))),
+                local_address,
+                peer_address,
+                timeouts: Default::default(),
+            })
+        }
+    }
+
+    #[derive(Userdata, Trace, VmType)]
+    #[gluon(vm_type = "std.net.tcp.TcpListener")]
+    #[gluon(crate_name = "::vm")]
+    #[gluon_trace(skip)]
+    pub struct TcpListener {
+        listener: Arc<AsyncMutex<net::TcpListener>>,
+        local_address: String,
+    }
+
+    impl fmt::Debug for TcpListener {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "TcpListener({})", self.local_address)
+        }
+    }
+
+    pub(crate) fn connect(
+        WithVM { vm, value: address }: WithVM<&str>,
+    ) -> impl Future<Output = IO<TcpStream>> + Send + 'static {
+        connect_timeout_(check_capability(vm), address.to_owned(), None)
+    }
+
+    pub(crate) fn connect_timeout(
+        WithVM { vm, value: address }: WithVM<&str>,
+        timeout: &Duration,
+    ) -> impl Future<Output = IO<TcpStream>> + Send + 'static {
+        connect_timeout_(
+            check_capability(vm),
+            address.to_owned(),
+            Some(to_std_duration(timeout)),
+        )
+    }
+
+    async fn connect_timeout_(
+        granted: io::Result<()>,
+        address: String,
+        timeout: Option<time::Duration>,
+    ) -> IO<TcpStream> {
+        let result = async {
+            granted?;
+            let stream = with_timeout(timeout, net::TcpStream::connect(&address[..])).await?;
+            TcpStream::new(stream)
+        };
+        result.await.into()
+    }
+
+    pub(crate) fn listen(
+        WithVM { vm, value: address }: WithVM<&str>,
+    ) -> impl Future<Output = IO<TcpListener>> + Send + 'static {
+        let granted = check_capability(vm);
+        let address = address.to_owned();
+        async move {
+            if let Err(err) = granted {
+                return IO::from(Err(err));
+            }
+            let result = net::TcpListener::bind(&address[..])
+                .await
+                .and_then(|listener| {
+                    Ok(TcpListener {
+                        local_address: listener.local_addr()?.to_string(),
+                        listener: Arc::new(AsyncMutex::new(listener)),
+                    })
+                });
+            result.into()
+        }
+    }
+
+    pub(crate) fn accept(
+        listener: &TcpListener,
+    ) -> impl Future<Output = IO<TcpStream>> + Send + 'static {
+        let listener = listener.listener.clone();
+        async move {
+            let result = listener.lock().await.accept().await;
+            result.and_then(|(stream, _)| TcpStream::new(stream)).into()
+        }
+    }
+
+    pub(crate) fn listener_address(listener: &TcpListener) -> String {
+        listener.local_address.clone()
+    }
+
+    pub(crate) fn local_address(stream: &TcpStream) -> String {
+        stream.local_address.clone()
+    }
+
+    pub(crate) fn peer_address(stream: &TcpStream) -> String {
+        stream.peer_address.clone()
+    }
+
+    pub(crate) fn set_read_timeout(stream: &TcpStream, timeout: Option<&Duration>) -> IO<()> {
+        set_timeout(&stream.timeouts.read, timeout)
+    }
+
+    pub(crate) fn set_write_timeout(stream: &TcpStream, timeout: Option<&Duration>) -> IO<()> {
+        set_timeout(&stream.timeouts.write, timeout)
+    }
+
+    pub(crate) fn read(
+        stream: &TcpStream,
+        count: usize,
+    ) -> impl Future<Output = IO<Option<Vec<u8>>>> + Send + 'static {
+        let reader = stream.reader.clone();
+        let timeout = *stream.timeouts.read.lock().unwrap();
+        async move {
+            let mut reader = reader.lock().await;
+            let mut buf = vec![0; count];
+            let result = with_timeout(timeout, reader.read(&mut buf)).await;
+            result
+                .map(|bytes_read| {
+                    if bytes_read == 0 {
+                        None
+                    } else {
+                        buf.truncate(bytes_read);
+                        Some(buf)
+                    }
+                })
+                .into()
+        }
+    }
+
+    pub(crate) fn read_to_end(
+        stream: &TcpStream,
+    ) -> impl Future<Output = IO<Vec<u8>>> + Send + 'static {
+        let reader = stream.reader.clone();
+        let timeout = *stream.timeouts.read.lock().unwrap();
+        async move {
+            let mut reader = reader.lock().await;
+            let mut buf = Vec::new();
+            let result = with_timeout(timeout, reader.read_to_end(&mut buf)).await;
+            result.map(|_| buf).into()
+        }
+    }
+
+    pub(crate) fn write_slice(
+        stream: &TcpStream,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+    ) -> impl Future<Output = IO<usize>> + Send + 'static {
+        let writer = stream.writer.clone();
+        let timeout = *stream.timeouts.write.lock().unwrap();
+        let buf = check_slice(buf, start, end).map(|buf| buf.to_owned());
+        async move {
+            let buf = buf?;
+            let mut writer = writer.lock().await;
+            match *writer {
+                Some(ref mut writer) => with_timeout(timeout, writer.write(&buf)).await,
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "the stream has been shut down",
+                )),
+            }
+        }
+        .map(IO::from)
+    }
+
+    pub(crate) fn flush(stream: &TcpStream) -> impl Future<Output = IO<()>> + Send + 'static {
+        let writer = stream.writer.clone();
+        let timeout = *stream.timeouts.write.lock().unwrap();
+        async move {
+            let mut writer = writer.lock().await;
+            match *writer {
+                Some(ref mut writer) => with_timeout(timeout, writer.flush()).await,
+                None => Ok(()),
+            }
+        }
+        .map(IO::from)
+    }
+
+    /// Shuts down the writing half of the connection, signaling the end of the data to the peer
+    pub(crate) fn shutdown(stream: &TcpStream) -> impl Future<Output = IO<()>> + Send + 'static {
+        let writer = stream.writer.clone();
+        async move {
+            match writer.lock().await.take() {
+                Some(mut writer) => writer.shutdown().await,
+                None => Ok(()),
+            }
+        }
+        .map(IO::from)
+    }
+
+    pub(crate) fn is_shutdown(stream: &TcpStream) -> bool {
+        stream
+            .writer
+            .try_lock()
+            .map_or(false, |writer| writer.is_none())
+    }
+}
+
+pub mod udp {
+    use super::*;
+
+    /// A UDP socket. Like `TcpStream` the receiving and sending halves are separate so that
+    /// receiving does not block sending.
+    #[derive(Userdata, Trace, VmType)]
+    #[gluon(vm_type = "std.net.udp.UdpSocket")]
+    #[gluon(crate_name = "::vm")]
+    #[gluon_trace(skip)]
+    pub struct UdpSocket {
+        receiver: Arc<AsyncMutex<RecvHalf>>,
+        sender: Arc<AsyncMutex<SendHalf>>,
+        local_address: String,
+        timeouts: Arc<Timeouts>,
+    }
+
+    impl fmt::Debug for UdpSocket {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "UdpSocket({})", self.local_address)
+        }
+    }
+
+    #[derive(Pushable, VmType)]
+    #[gluon(crate_name = "::vm")]
+    pub struct Datagram {
+        data: Vec<u8>,
+        address: String,
+    }
+
+    pub(crate) fn bind(
+        WithVM { vm, value: address }: WithVM<&str>,
+    ) -> impl Future<Output = IO<UdpSocket>> + Send + 'static {
+        let granted = check_capability(vm);
+        let address = address.to_owned();
+        async move {
+            if let Err(err) = granted {
+                return IO::from(Err(err));
+            }
+            let result = net::UdpSocket::bind(&address[..]).await.and_then(|socket| {
+                let local_address = socket.local_addr()?.to_string();
+                let (receiver, sender) = socket.split();
+                Ok(UdpSocket {
+                    receiver: Arc::new(AsyncMutex::new(receiver)),
+                    sender: Arc::new(AsyncMutex::new(sender)),
+                    local_address,
+                    timeouts: Default::default(),
+                })
+            });
+            result.into()
+        }
+    }
+
+    pub(crate) fn local_address(socket: &UdpSocket) -> String {
+        socket.local_address.clone()
+    }
+
+    pub(crate) fn set_read_timeout(socket: &UdpSocket, timeout: Option<&Duration>) -> IO<()> {
+        set_timeout(&socket.timeouts.read, timeout)
+    }
+
+    pub(crate) fn set_write_timeout(socket: &UdpSocket, timeout: Option<&Duration>) -> IO<()> {
+        set_timeout(&socket.timeouts.write, timeout)
+    }
+
+    pub(crate) fn send_to(
+        socket: &UdpSocket,
+        data: &[u8],
+        address: &str,
+    ) -> impl Future<Output = IO<usize>> + Send + 'static {
+        let sender = socket.sender.clone();
+        let timeout = *socket.timeouts.write.lock().unwrap();
+        let data = data.to_owned();
+        let address = address.to_owned();
+        async move {
+            let target = net::lookup_host(&address[..])
+                .await?
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("`{}` did not resolve to an address", address),
+                    )
+                })?;
+            let mut sender = sender.lock().await;
+            with_timeout(timeout, sender.send_to(&data, &target)).await
+        }
+        .map(IO::from)
+    }
+
+    pub(crate) fn receive_from(
+        socket: &UdpSocket,
+        count: usize,
+    ) -> impl Future<Output = IO<Datagram>> + Send + 'static {
+        let receiver = socket.receiver.clone();
+        let timeout = *socket.timeouts.read.lock().unwrap();
+        async move {
+            let mut receiver = receiver.lock().await;
+            let mut data = vec![0; count];
+            let (bytes_read, address) =
+                with_timeout(timeout, receiver.recv_from(&mut data)).await?;
+            data.truncate(bytes_read);
+            Ok::<_, io::Error>(Datagram {
+                data,
+                address: address.to_string(),
+            })
+        }
+        .map(IO::from)
+    }
+}
+
+mod std {
+    pub mod net {
+        pub use crate::std_lib::net::{tcp, udp};
+    }
+}
+
+pub fn load_tcp(vm: &Thread) -> Result<ExternModule> {
+    use self::tcp::{TcpListener, TcpStream};
+
+    vm.register_type::<TcpStream>("std.net.tcp.TcpStream", &[])?;
+    vm.register_type::<TcpListener>("std.net.tcp.TcpListener", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type TcpStream => TcpStream,
+            type TcpListener => TcpListener,
+
+            connect => primitive!(1, "std.net.tcp.prim.connect", async fn std::net::tcp::connect),
+            connect_timeout => primitive!(2, "std.net.tcp.prim.connect_timeout", async fn std::net::tcp::connect_timeout),
+            listen => primitive!(1, "std.net.tcp.prim.listen", async fn std::net::tcp::listen),
+            accept => primitive!(1, "std.net.tcp.prim.accept", async fn std::net::tcp::accept),
+            listener_address => primitive!(1, "std.net.tcp.prim.listener_address", std::net::tcp::listener_address),
+            local_address => primitive!(1, "std.net.tcp.prim.local_address", std::net::tcp::local_address),
+            peer_address => primitive!(1, "std.net.tcp.prim.peer_address", std::net::tcp::peer_address),
+            set_read_timeout => primitive!(2, "std.net.tcp.prim.set_read_timeout", std::net::tcp::set_read_timeout),
+            set_write_timeout => primitive!(2, "std.net.tcp.prim.set_write_timeout", std::net::tcp::set_write_timeout),
+            read => primitive!(2, "std.net.tcp.prim.read", async fn std::net::tcp::read),
+            read_to_end => primitive!(1, "std.net.tcp.prim.read_to_end", async fn std::net::tcp::read_to_end),
+            write_slice => primitive!(4, "std.net.tcp.prim.write_slice", async fn std::net::tcp::write_slice),
+            flush => primitive!(1, "std.net.tcp.prim.flush", async fn std::net::tcp::flush),
+            shutdown => primitive!(1, "std.net.tcp.prim.shutdown", async fn std::net::tcp::shutdown),
+            is_shutdown => primitive!(1, "std.net.tcp.prim.is_shutdown", std::net::tcp::is_shutdown),
+        },
+    )
+}
+
+pub fn load_udp(vm: &Thread) -> Result<ExternModule> {
+    use self::udp::UdpSocket;
+
+    vm.register_type::<UdpSocket>("std.net.udp.UdpSocket", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type UdpSocket => UdpSocket,
+
+            bind => primitive!(1, "std.net.udp.prim.bind", async fn std::net::udp::bind),
+            local_address => primitive!(1, "std.net.udp.prim.local_address", std::net::udp::local_address),
+            set_read_timeout => primitive!(2, "std.net.udp.prim.set_read_timeout", std::net::udp::set_read_timeout),
+            set_write_timeout => primitive!(2, "std.net.udp.prim.set_write_timeout", std::net::udp::set_write_timeout),
+            send_to => primitive!(3, "std.net.udp.prim.send_to", async fn std::net::udp::send_to),
+            receive_from => primitive!(2, "std.net.udp.prim.receive_from", async fn std::net::udp::receive_from),
+        },
+    )
+}
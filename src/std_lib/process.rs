@@ -1,5 +1,19 @@
-use crate::real_std::process::Command;
-use crate::vm::{api::IO, thread::Thread, ExternModule, Result};
+use crate::real_std::{
+    convert::TryFrom,
+    fmt,
+    io::{self, Read, Write},
+    process::{self, Command, Stdio},
+    sync::Mutex,
+    thread,
+    time::{self, Instant},
+};
+
+use crate::vm::{
+    api::{RuntimeResult, IO},
+    duration::Duration,
+    thread::Thread,
+    ExternModule, Result,
+};
 
 #[derive(Getable, VmType)]
 #[gluon(crate_name = "::vm")]
@@ -10,24 +24,333 @@ struct CreateProcess<'a> {
     current_dir: Option<&'a str>,
 }
 
-fn execute(create: CreateProcess) -> IO<Option<i32>> {
-    let mut command = Command::new(create.command);
-    for arg in &create.args {
-        command.arg(arg);
-    }
-    match create.env {
-        Some(env) => {
-            command.env_clear();
-            for (key, value) in &env {
-                command.env(key, value);
+impl<'a> CreateProcess<'a> {
+    fn command(&self) -> Command {
+        let mut command = Command::new(self.command);
+        for arg in &self.args {
+            command.arg(arg);
+        }
+        match self.env {
+            Some(ref env) => {
+                command.env_clear();
+                for (key, value) in env {
+                    command.env(key, value);
+                }
             }
+            None => (),
+        }
+        if let Some(current_dir) = self.current_dir {
+            command.current_dir(current_dir);
+        }
+        command
+    }
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(vm_type = "std.process.types.ExitStatus")]
+#[gluon(crate_name = "::vm")]
+struct ExitStatus {
+    code: Option<i32>,
+    success: bool,
+}
+
+impl From<process::ExitStatus> for ExitStatus {
+    fn from(status: process::ExitStatus) -> Self {
+        ExitStatus {
+            code: status.code(),
+            success: status.success(),
+        }
+    }
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(vm_type = "std.process.types.Output")]
+#[gluon(crate_name = "::vm")]
+struct Output {
+    status: ExitStatus,
+    stdout: String,
+    stderr: String,
+}
+
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.process.Child")]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct Child(Mutex<process::Child>);
+
+impl fmt::Debug for Child {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Child")
+    }
+}
+
+/// The standard input of a child process
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.process.ChildStdin")]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct ChildStdin(Mutex<Option<process::ChildStdin>>);
+
+impl fmt::Debug for ChildStdin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChildStdin")
+    }
+}
+
+/// The standard output or standard error of a child process
+#[derive(Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.process.ChildOutput")]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct ChildOutput(Mutex<Option<Box<dyn Read + Send>>>);
+
+impl fmt::Debug for ChildOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ChildOutput")
+    }
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(crate_name = "::vm")]
+struct Spawned {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildOutput,
+    stderr: ChildOutput,
+}
+
+macro_rules! unwrap_pipe {
+    ($pipe: expr) => {{
+        match *$pipe {
+            Some(ref mut pipe) => pipe,
+            None => return IO::Value(RuntimeResult::Panic("the pipe has been closed".to_owned())),
+        }
+    }};
+}
+
+/// How often a process is polled while waiting for it to exit with a timeout
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(5);
+
+/// Converts a gluon `Duration` into the instant it elapses at, treating negative durations as
+/// zero
+fn deadline(timeout: &Duration) -> Instant {
+    let nanos = u64::try_from(timeout.0.max(0)).unwrap_or(u64::MAX);
+    Instant::now() + time::Duration::from_nanos(nanos)
+}
+
+fn execute(create: CreateProcess) -> IO<Option<i32>> {
+    IO::from(create.command().status().map(|status| status.code()))
+}
+
+fn spawn(create: CreateProcess) -> IO<Spawned> {
+    let child = create
+        .command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    match child {
+        Ok(mut child) => {
+            let stdin = child.stdin.take();
+            let stdout = child
+                .stdout
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>);
+            let stderr = child
+                .stderr
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>);
+            IO::Value(Spawned {
+                child: Child(Mutex::new(child)),
+                stdin: ChildStdin(Mutex::new(stdin)),
+                stdout: ChildOutput(Mutex::new(stdout)),
+                stderr: ChildOutput(Mutex::new(stderr)),
+            })
+        }
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+fn output(create: CreateProcess) -> IO<Output> {
+    IO::from(
+        create
+            .command()
+            .stdin(Stdio::null())
+            .output()
+            .map(|output| Output {
+                status: output.status.into(),
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }),
+    )
+}
+
+fn read_all(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
+fn join_output(handle: Option<thread::JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<String> {
+    match handle {
+        Some(handle) => {
+            let buf = handle
+                .join()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "reader thread panicked"))??;
+            Ok(String::from_utf8_lossy(&buf).into_owned())
         }
-        None => (),
+        None => Ok(String::new()),
     }
-    if let Some(current_dir) = create.current_dir {
-        command.current_dir(current_dir);
+}
+
+fn output_until(create: &CreateProcess, deadline: Instant) -> io::Result<Option<Output>> {
+    let mut child = create
+        .command()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // The pipes are drained on separate threads so that the child can't block on a full pipe
+    // while we wait for it to exit
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+    let status = match wait_until(&mut child, deadline)? {
+        Some(status) => status,
+        None => {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+    };
+    Ok(Some(Output {
+        status: status.into(),
+        stdout: join_output(stdout)?,
+        stderr: join_output(stderr)?,
+    }))
+}
+
+/// Like `output` but kills the process and returns `None` if it has not exited before `timeout`
+fn output_timeout(create: CreateProcess, timeout: &Duration) -> IO<Option<Output>> {
+    IO::from(output_until(&create, deadline(timeout)))
+}
+
+fn wait_until(
+    child: &mut process::Child,
+    deadline: Instant,
+) -> io::Result<Option<process::ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
     }
-    IO::from(command.status().map(|status| status.code()))
+}
+
+fn wait(child: &Child) -> IO<ExitStatus> {
+    IO::from(child.0.lock().unwrap().wait().map(ExitStatus::from))
+}
+
+fn wait_timeout(child: &Child, timeout: &Duration) -> IO<Option<ExitStatus>> {
+    let mut child = child.0.lock().unwrap();
+    IO::from(wait_until(&mut child, deadline(timeout)).map(|status| status.map(ExitStatus::from)))
+}
+
+fn try_wait(child: &Child) -> IO<Option<ExitStatus>> {
+    IO::from(
+        child
+            .0
+            .lock()
+            .unwrap()
+            .try_wait()
+            .map(|status| status.map(ExitStatus::from)),
+    )
+}
+
+fn kill(child: &Child) -> IO<()> {
+    IO::from(child.0.lock().unwrap().kill())
+}
+
+fn id(child: &Child) -> u32 {
+    child.0.lock().unwrap().id()
+}
+
+fn write_slice_stdin(
+    stdin: &ChildStdin,
+    buf: &[u8],
+    start: usize,
+    end: usize,
+) -> IO<RuntimeResult<usize, String>> {
+    if start > end || end > buf.len() {
+        return IO::Value(RuntimeResult::Panic(format!(
+            "slice {}..{} is out of range for array of length {}",
+            start,
+            end,
+            buf.len()
+        )));
+    }
+
+    let mut stdin = stdin.0.lock().unwrap();
+    match unwrap_pipe!(stdin).write(&buf[start..end]) {
+        Ok(bytes_written) => IO::Value(RuntimeResult::Return(bytes_written)),
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+fn flush_stdin(stdin: &ChildStdin) -> IO<RuntimeResult<(), String>> {
+    let mut stdin = stdin.0.lock().unwrap();
+    match unwrap_pipe!(stdin).flush() {
+        Ok(()) => IO::Value(RuntimeResult::Return(())),
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+fn close_stdin(stdin: &ChildStdin) -> IO<()> {
+    match stdin.0.lock().unwrap().take() {
+        Some(mut stdin) => stdin.flush().into(),
+        None => IO::Value(()),
+    }
+}
+
+fn is_stdin_closed(stdin: &ChildStdin) -> bool {
+    stdin.0.lock().unwrap().is_none()
+}
+
+fn read_output(output: &ChildOutput, count: usize) -> IO<RuntimeResult<Option<Vec<u8>>, String>> {
+    let mut output = output.0.lock().unwrap();
+    let output = unwrap_pipe!(output);
+    let mut buf = vec![0; count];
+    match output.read(&mut buf) {
+        Ok(0) => IO::Value(RuntimeResult::Return(None)),
+        Ok(bytes_read) => {
+            buf.truncate(bytes_read);
+            IO::Value(RuntimeResult::Return(Some(buf)))
+        }
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+fn read_output_to_end(output: &ChildOutput) -> IO<RuntimeResult<Vec<u8>, String>> {
+    let mut output = output.0.lock().unwrap();
+    let mut buf = Vec::new();
+    match unwrap_pipe!(output).read_to_end(&mut buf) {
+        Ok(_) => IO::Value(RuntimeResult::Return(buf)),
+        Err(err) => IO::Exception(err.to_string()),
+    }
+}
+
+fn close_output(output: &ChildOutput) -> IO<()> {
+    output.0.lock().unwrap().take();
+    IO::Value(())
+}
+
+fn is_output_closed(output: &ChildOutput) -> bool {
+    output.0.lock().unwrap().is_none()
 }
 
 mod std {
@@ -37,10 +360,64 @@ mod std {
 }
 
 pub fn load(vm: &Thread) -> Result<ExternModule> {
+    vm.register_type::<Child>("std.process.Child", &[])?;
+    vm.register_type::<ChildStdin>("std.process.ChildStdin", &[])?;
+    vm.register_type::<ChildOutput>("std.process.ChildOutput", &[])?;
+
+    // Primitives which start processes are replaced by ones which throw an exception if the
+    // `process` capability has not been granted
+    // Timeouts depend on the clock so, like `std.time`, they are unavailable in deterministic mode
+    let deterministic = vm.global_env().is_deterministic();
+    macro_rules! timeout {
+        ($arg_count: tt, $name: expr, $primitive: expr, ($($arg: ty),*) -> $ret: ty) => {
+            if deterministic {
+                primitive!($arg_count, $name, |$(_: $arg),*| -> $ret {
+                    IO::Exception("Timeouts are not available in deterministic mode".to_string())
+                })
+            } else {
+                $primitive
+            }
+        };
+    }
+
+    let capabilities = vm.global_env().capabilities();
+
     ExternModule::new(
         vm,
         record! {
-            execute => primitive!(1, std::process::prim::execute)
+            type Child => Child,
+            type ChildStdin => ChildStdin,
+            type ChildOutput => ChildOutput,
+
+            execute => gated_primitive!(capabilities, process, 1, "std.process.prim.execute", std::process::prim::execute, (CreateProcess) -> IO<Option<i32>>),
+            spawn => gated_primitive!(capabilities, process, 1, "std.process.prim.spawn", std::process::prim::spawn, (CreateProcess) -> IO<Spawned>),
+            output => gated_primitive!(capabilities, process, 1, "std.process.prim.output", std::process::prim::output, (CreateProcess) -> IO<Output>),
+            output_timeout => timeout!(
+                2,
+                "std.process.prim.output_timeout",
+                gated_primitive!(capabilities, process, 2, "std.process.prim.output_timeout", std::process::prim::output_timeout, (CreateProcess, &Duration) -> IO<Option<Output>>),
+                (CreateProcess, &Duration) -> IO<Option<Output>>
+            ),
+
+            wait => primitive!(1, std::process::prim::wait),
+            wait_timeout => timeout!(
+                2,
+                "std.process.prim.wait_timeout",
+                primitive!(2, std::process::prim::wait_timeout),
+                (&Child, &Duration) -> IO<Option<ExitStatus>>
+            ),
+            try_wait => primitive!(1, std::process::prim::try_wait),
+            kill => primitive!(1, std::process::prim::kill),
+            id => primitive!(1, std::process::prim::id),
+
+            write_slice_stdin => primitive!(4, std::process::prim::write_slice_stdin),
+            flush_stdin => primitive!(1, std::process::prim::flush_stdin),
+            close_stdin => primitive!(1, std::process::prim::close_stdin),
+            is_stdin_closed => primitive!(1, std::process::prim::is_stdin_closed),
+            read_output => primitive!(2, std::process::prim::read_output),
+            read_output_to_end => primitive!(1, std::process::prim::read_output_to_end),
+            close_output => primitive!(1, std::process::prim::close_output),
+            is_output_closed => primitive!(1, std::process::prim::is_output_closed),
         },
     )
 }
@@ -7,7 +7,7 @@ use self::rand::{Rng, SeedableRng};
 
 use crate::vm::{
     self,
-    api::{RuntimeResult, IO},
+    api::{RuntimeResult, WithVM, IO},
     thread::Thread,
     types::VmInt,
     ExternModule,
@@ -22,16 +22,26 @@ struct XorShiftRng(self::rand_xorshift::XorShiftRng);
 
 field_decl! { value, gen }
 
-fn next_int(_: ()) -> IO<VmInt> {
-    IO::Value(rand::thread_rng().gen())
+fn thread_rng(vm: &Thread) -> Result<rand::rngs::ThreadRng, String> {
+    if vm.global_env().is_deterministic() {
+        Err("Unseeded random numbers are not available in deterministic mode".to_string())
+    } else {
+        Ok(rand::thread_rng())
+    }
+}
+
+fn next_int(vm: WithVM<()>) -> IO<VmInt> {
+    thread_rng(vm.vm).map(|mut rng| rng.gen()).into()
 }
 
-fn next_float(_: ()) -> IO<f64> {
-    IO::Value(rand::thread_rng().gen())
+fn next_float(vm: WithVM<()>) -> IO<f64> {
+    thread_rng(vm.vm).map(|mut rng| rng.gen()).into()
 }
 
-fn gen_int_range(low: VmInt, high: VmInt) -> IO<VmInt> {
-    IO::Value(rand::thread_rng().gen_range(low, high))
+fn gen_int_range(low: WithVM<VmInt>, high: VmInt) -> IO<VmInt> {
+    thread_rng(low.vm)
+        .map(|mut rng| rng.gen_range(low.value, high))
+        .into()
 }
 
 type RngNext<G> = record_type! {
@@ -58,6 +68,74 @@ fn xor_shift_next(gen: &XorShiftRng) -> RngNext<XorShiftRng> {
     }
 }
 
+type RngNextFloat<G> = record_type! {
+    value => f64,
+    gen => G
+};
+
+/// Expands `seed` into a xorshift seed with SplitMix64, so that similar seeds still produce
+/// unrelated sequences
+fn expand_seed(seed: u64) -> self::rand_xorshift::XorShiftRng {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let mut seed = [0; 16];
+    seed[..8].copy_from_slice(&next().to_le_bytes());
+    seed[8..].copy_from_slice(&next().to_le_bytes());
+    self::rand_xorshift::XorShiftRng::from_seed(seed)
+}
+
+fn xor_shift_from_int(seed: VmInt) -> XorShiftRng {
+    XorShiftRng(expand_seed(seed as u64))
+}
+
+fn xor_shift_next_float(gen: &XorShiftRng) -> RngNextFloat<XorShiftRng> {
+    let mut gen = gen.clone();
+    record_no_decl! {
+        value => gen.0.gen(),
+        gen => gen
+    }
+}
+
+fn xor_shift_gen_int_range(
+    low: VmInt,
+    high: VmInt,
+    gen: &XorShiftRng,
+) -> RuntimeResult<RngNext<XorShiftRng>, String> {
+    if low >= high {
+        return RuntimeResult::Panic(format!("Empty range {}..{}", low, high));
+    }
+    let mut gen = gen.clone();
+    RuntimeResult::Return(record_no_decl! {
+        value => gen.0.gen_range(low, high),
+        gen => gen
+    })
+}
+
+/// Splits `gen` into two generators which produce independent sequences
+fn xor_shift_split(gen: &XorShiftRng) -> (XorShiftRng, XorShiftRng) {
+    // The state of a xorshift generator consists of its last outputs so seeding the new
+    // generator directly from `gen` would just produce the same sequence
+    let mut left = gen.clone();
+    let right = expand_seed(left.0.gen());
+    (left, XorShiftRng(right))
+}
+
+/// Seeds a new generator from the thread local generator
+fn xor_shift_from_thread_rng(vm: WithVM<()>) -> IO<XorShiftRng> {
+    thread_rng(vm.vm)
+        .and_then(|rng| {
+            self::rand_xorshift::XorShiftRng::from_rng(rng).map_err(|err| err.to_string())
+        })
+        .map(XorShiftRng)
+        .into()
+}
+
 mod std {
     pub mod random {
         pub use crate::std_lib::random as prim;
@@ -75,7 +153,12 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
             next_float => primitive!(1, std::random::prim::next_float),
             gen_int_range => primitive!(2, std::random::prim::gen_int_range),
             xor_shift_new => primitive!(1, std::random::prim::xor_shift_new),
-            xor_shift_next => primitive!(1, std::random::prim::xor_shift_next)
+            xor_shift_next => primitive!(1, std::random::prim::xor_shift_next),
+            xor_shift_from_int => primitive!(1, std::random::prim::xor_shift_from_int),
+            xor_shift_next_float => primitive!(1, std::random::prim::xor_shift_next_float),
+            xor_shift_gen_int_range => primitive!(3, std::random::prim::xor_shift_gen_int_range),
+            xor_shift_split => primitive!(1, std::random::prim::xor_shift_split),
+            xor_shift_from_thread_rng => primitive!(1, std::random::prim::xor_shift_from_thread_rng)
         },
     )
 }
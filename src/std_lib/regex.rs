@@ -52,6 +52,11 @@ fn find<'a>(re: &Regex, text: &'a str) -> Option<Match<'a>> {
     re.find(text).map(Match::new)
 }
 
+fn find_all<'a>(re: &Regex, text: &'a str) -> Vec<Match<'a>> {
+    let &Regex(ref re) = re;
+    re.find_iter(text).map(Match::new).collect()
+}
+
 fn captures<'a>(re: &Regex, text: &'a str) -> Option<Vec<Option<Match<'a>>>> {
     let &Regex(ref re) = re;
     re.captures(text)
@@ -60,6 +65,16 @@ fn captures<'a>(re: &Regex, text: &'a str) -> Option<Vec<Option<Match<'a>>>> {
         .map(|i| i.collect())
 }
 
+fn replace(re: &Regex, text: &str, replacement: &str) -> String {
+    let &Regex(ref re) = re;
+    re.replace(text, replacement).into_owned()
+}
+
+fn replace_all(re: &Regex, text: &str, replacement: &str) -> String {
+    let &Regex(ref re) = re;
+    re.replace_all(text, replacement).into_owned()
+}
+
 fn error_to_string(err: &Error) -> String {
     let &Error(ref err) = err;
     err.to_string()
@@ -85,8 +100,11 @@ pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
             new => primitive!(1, std::regex::prim::new),
             is_match => primitive!(2, std::regex::prim::is_match),
             find => primitive!(2, std::regex::prim::find),
+            find_all => primitive!(2, std::regex::prim::find_all),
             // Workaround MIR bug in rustc
             captures => primitive!(2, "std.regex.prim.captures", |x, y| std::regex::prim::captures(x, y)),
+            replace => primitive!(3, std::regex::prim::replace),
+            replace_all => primitive!(3, std::regex::prim::replace_all),
             error_to_string => primitive!(1, std::regex::prim::error_to_string)
         },
     )
@@ -0,0 +1,332 @@
+//! Module containing points in time and access to the system clock.
+
+use crate::real_std::{cmp::Ordering, fmt, time};
+
+use crate::vm::{
+    self,
+    api::IO,
+    duration::{saturate, Duration, NANOS_PER_SEC},
+    thread::Thread,
+    types::VmInt,
+    ExternModule,
+};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// A point in time in UTC, stored as the number of nanoseconds since the UNIX epoch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.time.Time")]
+#[gluon_userdata(clone)]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct Time(i128);
+
+/// A reading of the monotonic clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.time.Instant")]
+#[gluon_userdata(clone)]
+#[gluon(crate_name = "::vm")]
+#[gluon_trace(skip)]
+struct Instant(time::Instant);
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = (self.0.div_euclid(NANOS_PER_SEC)) as i64;
+        let subsec_nanos = self.0.rem_euclid(NANOS_PER_SEC) as u32;
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let secs_of_day = secs.rem_euclid(SECS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )?;
+        // Like other RFC 3339 formatters, only as many digits as are needed to show the
+        // precision of the fraction are written
+        if subsec_nanos == 0 {
+        } else if subsec_nanos % 1_000_000 == 0 {
+            write!(f, ".{:03}", subsec_nanos / 1_000_000)?;
+        } else if subsec_nanos % 1_000 == 0 {
+            write!(f, ".{:06}", subsec_nanos / 1_000)?;
+        } else {
+            write!(f, ".{:09}", subsec_nanos)?;
+        }
+        write!(f, "Z")
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Conversions between the proleptic Gregorian calendar and days since the UNIX epoch, from
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: &[u8]) -> Option<()> {
+        if expected.contains(&self.next()?) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn digits(&mut self, count: usize) -> Option<i64> {
+        let mut value = 0;
+        for _ in 0..count {
+            match self.next()? {
+                c @ b'0'..=b'9' => value = value * 10 + i64::from(c - b'0'),
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    fn timestamp(&mut self) -> Option<i128> {
+        let year = self.digits(4)?;
+        self.expect(b"-")?;
+        let month = self.digits(2)?;
+        self.expect(b"-")?;
+        let day = self.digits(2)?;
+        self.expect(b"Tt ")?;
+        let hour = self.digits(2)?;
+        self.expect(b":")?;
+        let minute = self.digits(2)?;
+        self.expect(b":")?;
+        let second = self.digits(2)?;
+
+        let mut subsec_nanos = 0;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            let mut scale = NANOS_PER_SEC;
+            while let Some(c @ b'0'..=b'9') = self.peek() {
+                self.pos += 1;
+                // Digits beyond nanosecond precision are ignored
+                scale /= 10;
+                subsec_nanos += i128::from(c - b'0') * scale;
+            }
+            if scale == NANOS_PER_SEC {
+                return None;
+            }
+        }
+
+        let offset = match self.next()? {
+            b'Z' | b'z' => 0,
+            sign @ b'+' | sign @ b'-' => {
+                let hours = self.digits(2)?;
+                self.expect(b":")?;
+                let minutes = self.digits(2)?;
+                if hours >= 24 || minutes >= 60 {
+                    return None;
+                }
+                let offset = (hours * 60 + minutes) * 60;
+                if sign == b'-' {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+            _ => return None,
+        };
+
+        // A leap second (60) is accepted and is treated as the first second of the next minute
+        if self.pos != self.input.len()
+            || !(1..=12).contains(&month)
+            || day < 1
+            || day > days_in_month(year, month)
+            || hour >= 24
+            || minute >= 60
+            || second > 60
+        {
+            return None;
+        }
+
+        let secs =
+            days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second
+                - offset;
+        Some(i128::from(secs) * NANOS_PER_SEC + subsec_nanos)
+    }
+}
+
+fn parse_rfc3339(input: &str) -> Result<Time, String> {
+    Parser {
+        input: input.as_bytes(),
+        pos: 0,
+    }
+    .timestamp()
+    .map(Time)
+    .ok_or_else(|| format!("Invalid RFC 3339 timestamp `{}`", input))
+}
+
+fn format_rfc3339(time: &Time) -> String {
+    time.to_string()
+}
+
+fn now() -> IO<Time> {
+    match time::SystemTime::now().duration_since(time::UNIX_EPOCH) {
+        Ok(since_epoch) => IO::Value(Time(since_epoch.as_nanos() as i128)),
+        Err(err) => IO::Value(Time(-(err.duration().as_nanos() as i128))),
+    }
+}
+
+fn instant() -> IO<Instant> {
+    IO::Value(Instant(time::Instant::now()))
+}
+
+fn elapsed(instant: &Instant) -> IO<Duration> {
+    IO::Value(Duration(instant.0.elapsed().as_nanos() as i128))
+}
+
+fn duration_since(later: &Instant, earlier: &Instant) -> Duration {
+    match later.0.checked_duration_since(earlier.0) {
+        Some(duration) => Duration(duration.as_nanos() as i128),
+        None => Duration(-(earlier.0.duration_since(later.0).as_nanos() as i128)),
+    }
+}
+
+fn from_unix_seconds(seconds: VmInt) -> Time {
+    Time(i128::from(seconds) * NANOS_PER_SEC)
+}
+
+fn unix_seconds(time: &Time) -> VmInt {
+    saturate(time.0.div_euclid(NANOS_PER_SEC))
+}
+
+fn since_epoch(time: &Time) -> Duration {
+    Duration(time.0)
+}
+
+fn add(time: &Time, duration: &Duration) -> Time {
+    Time(time.0.saturating_add(duration.0))
+}
+
+fn sub(time: &Time, duration: &Duration) -> Time {
+    Time(time.0.saturating_sub(duration.0))
+}
+
+fn diff(later: &Time, earlier: &Time) -> Duration {
+    Duration(later.0.saturating_sub(earlier.0))
+}
+
+fn eq(l: &Time, r: &Time) -> bool {
+    l == r
+}
+
+fn compare(l: &Time, r: &Time) -> Ordering {
+    l.cmp(r)
+}
+
+fn instant_eq(l: &Instant, r: &Instant) -> bool {
+    l == r
+}
+
+fn instant_compare(l: &Instant, r: &Instant) -> Ordering {
+    l.cmp(r)
+}
+
+mod std {
+    pub mod time {
+        pub use crate::std_lib::time as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    vm.register_type::<Time>("std.time.Time", &[])?;
+    vm.register_type::<Instant>("std.time.Instant", &[])?;
+
+    // Reading the clock makes programs give different results each time they are run so it is
+    // unavailable in deterministic mode as well as when the capability has not been granted
+    let deterministic = vm.global_env().is_deterministic();
+    let capabilities = vm.global_env().capabilities();
+    macro_rules! clock {
+        ($arg_count: tt, $name: expr, $func: expr, ($($arg: ty),*) -> $ret: ty) => {
+            if deterministic {
+                primitive!($arg_count, $name, |$(_: $arg),*| -> $ret {
+                    IO::Exception("The clock is not available in deterministic mode".to_string())
+                })
+            } else {
+                gated_primitive!(capabilities, clock, $arg_count, $name, $func, ($($arg),*) -> $ret)
+            }
+        };
+    }
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Time => Time,
+            type Instant => Instant,
+
+            now => clock!(0, "std.time.prim.now", std::time::prim::now, () -> IO<Time>),
+            instant => clock!(0, "std.time.prim.instant", std::time::prim::instant, () -> IO<Instant>),
+            elapsed => clock!(1, "std.time.prim.elapsed", std::time::prim::elapsed, (&Instant) -> IO<Duration>),
+            duration_since => primitive!(2, std::time::prim::duration_since),
+            unix_epoch => Time(0),
+            from_unix_seconds => primitive!(1, std::time::prim::from_unix_seconds),
+            unix_seconds => primitive!(1, std::time::prim::unix_seconds),
+            since_epoch => primitive!(1, std::time::prim::since_epoch),
+            add => primitive!(2, std::time::prim::add),
+            sub => primitive!(2, std::time::prim::sub),
+            diff => primitive!(2, std::time::prim::diff),
+            format_rfc3339 => primitive!(1, std::time::prim::format_rfc3339),
+            parse_rfc3339 => primitive!(1, std::time::prim::parse_rfc3339),
+            eq => primitive!(2, std::time::prim::eq),
+            compare => primitive!(2, std::time::prim::compare),
+            instant_eq => primitive!(2, std::time::prim::instant_eq),
+            instant_compare => primitive!(2, std::time::prim::instant_compare)
+        },
+    )
+}
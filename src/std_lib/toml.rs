@@ -0,0 +1,59 @@
+//! Module containing bindings to the `toml` library.
+
+extern crate serde_json;
+
+use serde_json::Value as Json;
+use toml::Value as Toml;
+
+use crate::vm::{self, thread::Thread, ExternModule};
+
+/// Converts a TOML value to the JSON value which `std.json` uses. Dates and times are converted to
+/// strings as JSON does not have a type for them.
+fn to_json(value: Toml) -> Result<Json, String> {
+    Ok(match value {
+        Toml::String(s) => Json::String(s),
+        Toml::Integer(i) => Json::from(i),
+        Toml::Float(f) => serde_json::Number::from_f64(f)
+            .map(Json::Number)
+            .ok_or_else(|| format!("`{}` can't be represented as a `std.json.Value`", f))?,
+        Toml::Boolean(b) => Json::Bool(b),
+        Toml::Datetime(datetime) => Json::String(datetime.to_string()),
+        Toml::Array(values) => {
+            Json::Array(values.into_iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        Toml::Table(table) => Json::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| Ok((key, to_json(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+fn deserialize(input: &str) -> Result<Json, String> {
+    let value = input.parse::<Toml>().map_err(|err| err.to_string())?;
+    to_json(value)
+}
+
+fn serialize(value: Json) -> Result<String, String> {
+    // Going through `toml::Value` writes the values of a table before its subtables, which TOML
+    // requires
+    let value = Toml::try_from(value).map_err(|err| err.to_string())?;
+    toml::to_string(&value).map_err(|err| err.to_string())
+}
+
+mod std {
+    pub mod toml {
+        pub use crate::std_lib::toml as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            deserialize => primitive!(1, std::toml::prim::deserialize),
+            serialize => primitive!(1, std::toml::prim::serialize)
+        },
+    )
+}
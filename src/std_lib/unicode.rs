@@ -0,0 +1,128 @@
+//! Module containing Unicode text segmentation, case folding and normalization.
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::vm::{self, thread::Thread, types::VmInt, ExternModule};
+
+fn graphemes(s: &str) -> Vec<&str> {
+    s.graphemes(true).collect()
+}
+
+fn grapheme_len(s: &str) -> VmInt {
+    s.graphemes(true).count() as VmInt
+}
+
+/// Returns the byte offset of each grapheme cluster in `s` together with the cluster
+fn grapheme_indices(s: &str) -> Vec<(VmInt, &str)> {
+    s.grapheme_indices(true)
+        .map(|(i, g)| (i as VmInt, g))
+        .collect()
+}
+
+fn words(s: &str) -> Vec<&str> {
+    s.unicode_words().collect()
+}
+
+fn word_bounds(s: &str) -> Vec<&str> {
+    s.split_word_bounds().collect()
+}
+
+fn sentences(s: &str) -> Vec<&str> {
+    s.unicode_sentences().collect()
+}
+
+/// Case foldings which differ from `char::to_lowercase`
+fn fold_special(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' | 'ẞ' => "ss",
+        'ſ' => "s",
+        'µ' => "μ",
+        'ς' => "σ",
+        'ϐ' => "β",
+        'ϑ' => "θ",
+        'ϕ' => "φ",
+        'ϖ' => "π",
+        'ϰ' => "κ",
+        'ϱ' => "ρ",
+        'ϵ' => "ε",
+        '\u{345}' | '\u{1fbe}' => "ι",
+        'ŉ' => "ʼn",
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        'ﬅ' | 'ﬆ' => "st",
+        _ => return None,
+    })
+}
+
+fn casefold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match fold_special(c) {
+            Some(special) => folded.push_str(special),
+            None => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Compares `l` and `r` as described by "canonical caseless matching" in the Unicode standard
+fn caseless_eq(l: &str, r: &str) -> bool {
+    let fold = |s: &str| -> String { casefold(&s.nfd().collect::<String>()).nfd().collect() };
+    fold(l) == fold(r)
+}
+
+fn nfc(s: &str) -> String {
+    s.nfc().collect()
+}
+
+fn nfd(s: &str) -> String {
+    s.nfd().collect()
+}
+
+fn nfkc(s: &str) -> String {
+    s.nfkc().collect()
+}
+
+fn nfkd(s: &str) -> String {
+    s.nfkd().collect()
+}
+
+fn is_nfc(s: &str) -> bool {
+    unicode_normalization::is_nfc(s)
+}
+
+fn is_nfd(s: &str) -> bool {
+    unicode_normalization::is_nfd(s)
+}
+
+mod std {
+    pub mod unicode {
+        pub use crate::std_lib::unicode as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            graphemes => primitive!(1, std::unicode::prim::graphemes),
+            grapheme_len => primitive!(1, std::unicode::prim::grapheme_len),
+            grapheme_indices => primitive!(1, std::unicode::prim::grapheme_indices),
+            words => primitive!(1, std::unicode::prim::words),
+            word_bounds => primitive!(1, std::unicode::prim::word_bounds),
+            sentences => primitive!(1, std::unicode::prim::sentences),
+            casefold => primitive!(1, std::unicode::prim::casefold),
+            caseless_eq => primitive!(2, std::unicode::prim::caseless_eq),
+            nfc => primitive!(1, std::unicode::prim::nfc),
+            nfd => primitive!(1, std::unicode::prim::nfd),
+            nfkc => primitive!(1, std::unicode::prim::nfkc),
+            nfkd => primitive!(1, std::unicode::prim::nfkd),
+            is_nfc => primitive!(1, std::unicode::prim::is_nfc),
+            is_nfd => primitive!(1, std::unicode::prim::is_nfd),
+        },
+    )
+}
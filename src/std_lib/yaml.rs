@@ -0,0 +1,30 @@
+//! Module containing bindings to the `serde_yaml` library.
+
+extern crate serde_json;
+extern crate serde_yaml;
+
+use crate::vm::{self, thread::Thread, ExternModule};
+
+fn deserialize(input: &str) -> Result<serde_json::Value, String> {
+    serde_yaml::from_str(input).map_err(|err| err.to_string())
+}
+
+fn serialize(value: serde_json::Value) -> Result<String, String> {
+    serde_yaml::to_string(&value).map_err(|err| err.to_string())
+}
+
+mod std {
+    pub mod yaml {
+        pub use crate::std_lib::yaml as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            deserialize => primitive!(1, std::yaml::prim::deserialize),
+            serialize => primitive!(1, std::yaml::prim::serialize)
+        },
+    )
+}
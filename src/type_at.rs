@@ -0,0 +1,65 @@
+//! Finds the type of the innermost expression, pattern or identifier at a position, see
+//! `ThreadExt::type_at`
+
+use crate::base::{
+    ast::{self, SpannedExpr, SpannedIdent, SpannedPattern, Typed, Visitor},
+    pos::{BytePos, Span},
+    symbol::Symbol,
+    types::{ArcType, TypeEnv},
+};
+
+struct FindType<'e> {
+    env: &'e dyn TypeEnv<Type = ArcType>,
+    pos: BytePos,
+    typ: Option<ArcType>,
+}
+
+impl FindType<'_> {
+    fn found(&mut self, span: Span<BytePos>, typ: impl FnOnce() -> Option<ArcType>) -> bool {
+        if span.contains_pos(self.pos) {
+            if let Some(typ) = typ() {
+                self.typ = Some(typ);
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'a, 'ast> for FindType<'_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, e: &'a SpannedExpr<'ast, Symbol>) {
+        let env = self.env;
+        if self.found(e.span, || e.try_type_of(env).ok()) {
+            ast::walk_expr(self, e);
+        }
+    }
+
+    fn visit_pattern(&mut self, p: &'a SpannedPattern<'ast, Symbol>) {
+        let env = self.env;
+        if self.found(p.span, || p.try_type_of(env).ok()) {
+            ast::walk_pattern(self, &p.value);
+        }
+    }
+
+    fn visit_spanned_typed_ident(&mut self, id: &'a SpannedIdent<Symbol>) {
+        self.found(id.span, || Some(id.value.typ.clone()));
+    }
+}
+
+/// Returns the type of the innermost node in `expr` which contains `pos`
+pub(crate) fn type_at(
+    env: &dyn TypeEnv<Type = ArcType>,
+    expr: &SpannedExpr<'_, Symbol>,
+    pos: BytePos,
+) -> Option<ArcType> {
+    let mut visitor = FindType {
+        env,
+        pos,
+        typ: None,
+    };
+    visitor.visit_expr(expr);
+    visitor.typ
+}
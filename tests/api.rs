@@ -6,19 +6,29 @@ extern crate gluon_vm;
 #[macro_use]
 extern crate gluon_codegen;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 
 use futures::prelude::*;
 
 use gluon::{
     base::types::{Alias, ArcType, Type},
-    import::{add_extern_module, add_extern_module_with_deps, Import},
-    query::Compilation,
+    import::{
+        add_extern_module, add_extern_module_lazy, add_extern_module_with_deps,
+        add_userdata_module, Import, ImportResolver,
+    },
+    query::{Compilation, CompilationBase},
     vm::{
         api::{
+            async_closure, closure, closure_mut, closure_with_state,
             de::De,
             scoped::{Ref, RefMut},
-            FunctionRef, FutureResult, Hole, OpaqueValue, OwnedFunction, RuntimeResult, VmType, IO,
+            ErrorInfo, FunctionRef, FutureResult, Hole, OpaqueValue, OwnedFunction, RuntimeResult,
+            VmType, IO,
         },
         gc,
         thread::{RootedThread, Thread},
@@ -403,6 +413,159 @@ fn return_btreemap() {
     );
 }
 
+#[test]
+fn return_hashmap() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    add_extern_module_with_deps(
+        &vm,
+        "test",
+        |vm| {
+            ExternModule::new(
+                vm,
+                primitive!(1, "test", |()| {
+                    vec![("a".to_string(), 1), ("b".to_string(), 2)]
+                        .into_iter()
+                        .collect::<HashMap<_, _>>()
+                }),
+            )
+        },
+        vec!["std.map".into()],
+    );
+
+    vm.run_expr::<()>("", "let _ = import! test in ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (result, _) = vm
+        .run_expr::<HashMap<String, VmInt>>("", "(import! test) ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        result,
+        vec![("a".to_string(), 1), ("b".to_string(), 2)]
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+    );
+}
+
+#[test]
+fn marshal_hashmap_value() {
+    use gluon::vm::hash_map::HashMapValue;
+
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    add_extern_module_with_deps(
+        &vm,
+        "test",
+        |vm| {
+            ExternModule::new(
+                vm,
+                primitive!(1, "test", |map: HashMapValue<String, VmInt>| {
+                    HashMapValue(
+                        map.0
+                            .into_iter()
+                            .map(|(key, value)| (key.to_uppercase(), value * 10))
+                            .collect::<HashMap<_, _>>(),
+                    )
+                }),
+            )
+        },
+        vec!["std.collections.hashmap".into()],
+    );
+
+    vm.run_expr::<()>("", "let _ = import! test in ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (result, _) = vm
+        .run_expr::<HashMapValue<String, VmInt>>(
+            "",
+            r#"
+            let hashmap = import! std.collections.hashmap
+            let { ? } = import! std.hash
+            let { ? } = import! std.string
+            let m = (import! test) (hashmap.insert "a" 1 (hashmap.singleton "b" 2))
+            hashmap.insert "A" 11 m
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    // The map from Rust is hashed like the `Hashable String` instance so "A" is replaced
+    assert_eq!(
+        result.0,
+        vec![("A".to_string(), 11), ("B".to_string(), 20)]
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+    );
+}
+
+#[test]
+fn marshal_std_types() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    add_extern_module_with_deps(
+        &vm,
+        "test",
+        |vm| {
+            ExternModule::new(
+                vm,
+                record! {
+                    deque => primitive!(1, |()| (1..4).collect::<VecDeque<VmInt>>()),
+                    set => primitive!(1, |()| vec![1, 2].into_iter().collect::<HashSet<VmInt>>()),
+                    arc => primitive!(1, |()| Arc::new("shared".to_string())),
+                    cow => primitive!(1, |s: Cow<str>| Cow::<str>::Owned(s.to_uppercase())),
+                    duration => primitive!(1, |d: Duration| d * 2)
+                },
+            )
+        },
+        vec!["std.duration.prim".into()],
+    );
+
+    let (result, _) = vm
+        .run_expr::<VecDeque<VmInt>>("", "(import! test).deque ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, (1..4).collect::<VecDeque<_>>());
+
+    let (result, _) = vm
+        .run_expr::<HashSet<VmInt>>("", "(import! test).set ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, vec![1, 2].into_iter().collect::<HashSet<_>>());
+
+    let (result, _) = vm
+        .run_expr::<Arc<String>>("", "(import! test).arc ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(*result, "shared");
+
+    let (result, _) = vm
+        .run_expr::<String>("", r#"(import! test).cow "abc""#)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "ABC");
+
+    let (result, _) = vm
+        .run_expr::<Duration>(
+            "",
+            r#"
+            let duration = import! std.duration
+            (import! test).duration (duration.add (duration.from_seconds 1) (duration.from_millis 600))
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, Duration::new(3, 200_000_000));
+
+    let result = vm.run_expr::<Duration>(
+        "",
+        "let duration = import! std.duration in duration.from_seconds (-1)",
+    );
+    assert!(result.is_err(), "Negative durations must be rejected");
+
+    let result = vm.run_expr::<Duration>(
+        "",
+        "let duration = import! std.duration in duration.mul (duration.from_seconds 9223372036854775807) 4",
+    );
+    assert!(result.is_err(), "Durations which overflow must be rejected");
+}
+
 #[test]
 fn get_value_boxed_or_unboxed() {
     let _ = ::env_logger::try_init();
@@ -679,6 +842,184 @@ fn cyclic_userdata_mutable() {
     );
 }
 
+#[test]
+fn userdata_mutex_roots_unrooted_value_when_locked() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug, Default, Userdata, Trace)]
+    struct Cell(gc::mutex::Mutex<Option<OpaqueValue<RootedThread, NoisyDrop>>>);
+    impl VmType for Cell {
+        type Type = Cell;
+    }
+
+    let vm = make_vm();
+
+    vm.register_type::<NoisyDrop>("NoisyDrop", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    vm.register_type::<Cell>("Cell", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+
+    add_extern_module(&vm, "function", |thread| {
+        ExternModule::new(
+            thread,
+            record! {
+                new => primitive!(1, |()| Cell::default()),
+                set => primitive!(2, |cell: &Cell, noisy| *cell.0.lock().unwrap() = Some(noisy))
+            },
+        )
+    });
+
+    // The second `set` locks the mutex while it holds a value which was unrooted when the first
+    // lock was released, so the value must be rooted again
+    let expr = r#"
+        let f = import! function
+        \noisy ->
+            let cell = f.new ()
+            let _ = f.set cell noisy
+            f.set cell noisy
+    "#;
+    vm.load_script("test", expr).unwrap();
+
+    let mut f: FunctionRef<fn(NoisyDrop)> = vm
+        .get_global("test")
+        .unwrap_or_else(|err| panic!("{}", err));
+    f.call(NoisyDrop::default()).unwrap();
+}
+
+#[test]
+fn cyclic_userdata_collected_while_vm_is_alive() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug, Default, Userdata, Trace)]
+    struct Registry(gc::mutex::Mutex<Vec<OwnedFunction<fn(()) -> ()>>>);
+    impl VmType for Registry {
+        type Type = Registry;
+    }
+
+    let mut noisy_drop = NoisyDrop::default();
+
+    let vm = make_vm();
+
+    vm.register_type::<NoisyDrop>("NoisyDrop", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    vm.register_type::<Registry>("Registry", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+
+    add_extern_module(&vm, "registry", |thread| {
+        ExternModule::new(
+            thread,
+            record! {
+                new => primitive!(1, |()| Registry::default()),
+                register => primitive!(2, |registry: &Registry, callback| {
+                    registry.0.lock().unwrap().push(callback);
+                    IO::Value(())
+                }),
+                keep_alive => primitive!(2, |_: &NoisyDrop, _: &Registry| ()),
+                call_all => primitive!(1, |registry: &Registry| {
+                    for callback in registry.0.lock().unwrap().iter_mut() {
+                        callback.call(()).unwrap();
+                    }
+                    IO::Value(())
+                })
+            },
+        )
+    });
+
+    // The callback refers to the registry which stores it, creating a cycle which passes through
+    // Rust
+    let expr = r#"
+        let { ? } = import! std.io
+        let r = import! registry
+        \noisy ->
+            let registry = r.new ()
+            let callback _ = r.keep_alive noisy registry
+            seq r.register registry callback
+            r.call_all registry
+    "#;
+    vm.load_script("test", expr).unwrap();
+
+    {
+        let mut f: FunctionRef<fn(NoisyDrop) -> IO<()>> = vm
+            .get_global("test")
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(f.call(noisy_drop.clone()).unwrap(), IO::Value(()));
+    }
+
+    vm.collect();
+
+    assert!(
+        Arc::get_mut(&mut noisy_drop.0).is_some(),
+        "The cycle through the registry was not collected"
+    );
+}
+
+#[test]
+fn cyclic_userdata_rw_lock_collected_while_vm_is_alive() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug, Default, Userdata, Trace)]
+    struct Registry(gc::rw_lock::RwLock<Vec<OwnedFunction<fn(()) -> ()>>>);
+    impl VmType for Registry {
+        type Type = Registry;
+    }
+
+    let mut noisy_drop = NoisyDrop::default();
+
+    let vm = make_vm();
+
+    vm.register_type::<NoisyDrop>("NoisyDrop", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    vm.register_type::<Registry>("Registry", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+
+    add_extern_module(&vm, "registry", |thread| {
+        ExternModule::new(
+            thread,
+            record! {
+                new => primitive!(1, |()| Registry::default()),
+                register => primitive!(2, |registry: &Registry, callback| {
+                    registry.0.write().unwrap().push(callback);
+                    IO::Value(())
+                }),
+                keep_alive => primitive!(2, |_: &NoisyDrop, _: &Registry| ()),
+                call_all => primitive!(1, |registry: &Registry| {
+                    let callbacks = registry.0.read().unwrap().clone();
+                    for mut callback in callbacks {
+                        callback.call(()).unwrap();
+                    }
+                    IO::Value(())
+                })
+            },
+        )
+    });
+
+    let expr = r#"
+        let { ? } = import! std.io
+        let r = import! registry
+        \noisy ->
+            let registry = r.new ()
+            let callback _ = r.keep_alive noisy registry
+            seq r.register registry callback
+            seq r.register registry callback
+            r.call_all registry
+    "#;
+    vm.load_script("test", expr).unwrap();
+
+    {
+        let mut f: FunctionRef<fn(NoisyDrop) -> IO<()>> = vm
+            .get_global("test")
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(f.call(noisy_drop.clone()).unwrap(), IO::Value(()));
+    }
+
+    vm.collect();
+
+    assert!(
+        Arc::get_mut(&mut noisy_drop.0).is_some(),
+        "The cycle through the registry was not collected"
+    );
+}
+
 #[test]
 fn child_vm_do_not_cause_undroppable_cycle_normal_drop_order() {
     let _ = ::env_logger::try_init();
@@ -777,3 +1118,800 @@ fn clone_userdata() {
 
     assert_eq!(*result, Test(123));
 }
+
+#[test]
+fn userdata_methods() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug, Trace, VmType, PartialEq, Eq, PartialOrd, Ord)]
+    #[gluon(vm_type = "counter.Counter")]
+    struct Counter(VmInt);
+
+    impl std::fmt::Display for Counter {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "Counter({})", self.0)
+        }
+    }
+
+    impl gluon::vm::api::Userdata for Counter {
+        fn methods(methods: &mut gluon::vm::api::UserdataMethods<Self>) {
+            methods
+                .method("new", primitive!(1, "counter.new", |x: VmInt| Counter(x)))
+                .method("get", primitive!(1, "counter.get", |c: &Counter| c.0))
+                .eq()
+                .ord()
+                .show();
+        }
+    }
+
+    let expr = r#"
+        let { Counter, new, get, ? } = import! counter
+        let { (==), (<) } = import! std.cmp
+        let { show } = import! std.show
+
+        let a : Counter = new 1
+        let b = new 2
+        if a == new 1 && a < b && not (b == a) then show b ++ " " ++ show (get b)
+        else "wrong"
+    "#;
+
+    let vm = make_vm();
+    vm.register_type::<Counter>("counter.Counter", &[])
+        .unwrap_or_else(|_| panic!("Could not add type"));
+    add_userdata_module::<Counter>(&vm, "counter");
+
+    let (result, _) = vm
+        .run_expr::<String>("<top>", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "Counter(2) 2");
+}
+
+#[test]
+fn reload_module() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    load_script(&vm, "reloaded", "let f x : Int -> Int = x + 1 in { f }")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (old_f, _) = vm
+        .run_expr::<OwnedFunction<fn(VmInt) -> VmInt>>("f1", "let { f } = import! reloaded in f")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    vm.reload_module("reloaded", "let f x : Int -> Int = x + 10 in { f }")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (result, _) = vm
+        .run_expr::<VmInt>("f2", "let { f } = import! reloaded in f 1")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 11);
+
+    let mut old_f = old_f;
+    assert_eq!(old_f.call(1), Ok(2));
+}
+
+#[test]
+fn reload_module_with_incompatible_type() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    load_script(&vm, "reloaded", "let f x : Int -> Int = x + 1 in { f }")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    assert!(vm
+        .reload_module("reloaded", r#"let f x : Int -> String = "" in { f }"#)
+        .is_err());
+
+    let (result, _) = vm
+        .run_expr::<VmInt>("f", "let { f } = import! reloaded in f 1")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 2);
+}
+
+#[test]
+fn rooted_values_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<RootedThread>();
+    assert_send_sync::<gluon::vm::thread::RootedValue<RootedThread>>();
+    assert_send_sync::<OpaqueValue<RootedThread, Hole>>();
+    assert_send_sync::<OwnedFunction<fn(VmInt) -> VmInt>>();
+}
+
+#[test]
+fn call_re_rooted_function_from_other_threads() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let (f, _) = vm
+        .run_expr::<OwnedFunction<fn(VmInt) -> VmInt>>("f", r"\x -> x + 1")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let handles: Vec<_> = (0..4)
+        .map(|i: VmInt| {
+            let thread = vm.new_thread().unwrap();
+            let mut f = f.re_root(thread).unwrap_or_else(|err| panic!("{}", err));
+            std::thread::spawn(move || {
+                let mut sum = 0;
+                for j in 0..100 {
+                    sum += f.call(i * 100 + j).unwrap();
+                }
+                sum
+            })
+        })
+        .collect();
+
+    let mut total = 0;
+    for handle in handles {
+        total += handle.join().unwrap();
+    }
+    assert_eq!(total, (1..=400).sum::<VmInt>());
+}
+
+#[test]
+fn call_any_checks_arity() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    let (f, _) = vm
+        .run_expr::<OwnedFunction<fn(VmInt, VmInt) -> VmInt>>("f", r"\x y -> x + y")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (args, _) = vm
+        .run_expr::<OpaqueValue<RootedThread, Hole>>("args", "(1, 2)")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let args: Vec<_> = (0..2)
+        .map(|i| match args.get_variant().as_ref() {
+            gluon::vm::api::ValueRef::Data(data) => data.get_variant(i).unwrap(),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    assert_eq!(f.call_any::<VmInt>(&args), Ok(3));
+    assert!(f.call_any::<VmInt>(&args[..1]).is_err());
+}
+
+#[test]
+fn borrow_strings_and_bytes_without_copying() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    add_extern_module(&vm, "test", |vm| {
+        ExternModule::new(
+            vm,
+            record! {
+                str_ptr => primitive!(1, |s: &str| s.as_ptr() as usize),
+                bytes_ptr => primitive!(1, |b: Cow<[u8]>| b.as_ptr() as usize),
+                bytes => primitive!(1, |s: &str| Cow::Borrowed(s.as_bytes()))
+            },
+        )
+    });
+    vm.run_expr::<()>("", "let _ = import! test in ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (s, _) = vm
+        .run_expr::<OpaqueValue<RootedThread, str>>("", r#""hello""#)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let mut str_ptr: FunctionRef<fn(OpaqueValue<RootedThread, str>) -> usize> =
+        vm.get_global("test.str_ptr").unwrap();
+    let ptr = s.as_ptr() as usize;
+    assert_eq!(str_ptr.call(s), Ok(ptr));
+
+    let (bytes, _) = vm
+        .run_expr::<OpaqueValue<RootedThread, [u8]>>("", r#"(import! test).bytes "hello""#)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(&*bytes, b"hello");
+    let mut bytes_ptr: FunctionRef<fn(OpaqueValue<RootedThread, [u8]>) -> usize> =
+        vm.get_global("test.bytes_ptr").unwrap();
+    let ptr = bytes.as_ptr() as usize;
+    assert_eq!(bytes_ptr.call(bytes), Ok(ptr));
+}
+
+#[test]
+fn closures_with_captured_state() {
+    let _ = ::env_logger::try_init();
+
+    let captured = Arc::new(());
+    {
+        let vm = make_vm();
+
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let offset = 10;
+        let keep_alive = captured.clone();
+        let counter2 = counter.clone();
+        add_extern_module(&vm, "test", move |vm| {
+            let keep_alive = keep_alive.clone();
+            let counter2 = counter2.clone();
+            let mut calls = 0;
+            ExternModule::new(
+                vm,
+                record! {
+                    add_offset => closure::<fn(VmInt) -> VmInt, _>(
+                        "test.add_offset",
+                        move |x: VmInt| {
+                            let _ = &keep_alive;
+                            x + offset
+                        },
+                    ),
+                    count => closure_mut::<fn(()) -> VmInt, _>("test.count", move |()| {
+                        calls += 1;
+                        counter2.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        calls
+                    }),
+                    scale => closure_with_state::<fn(VmInt) -> VmInt, _, _>(
+                        "test.scale",
+                        3,
+                        |factor: &VmInt, x: VmInt| x * factor,
+                    )
+                },
+            )
+        });
+
+        let (result, _) = vm
+            .run_expr::<VmInt>(
+                "test",
+                r#"
+                let { add_offset, count, scale } = import! test
+                let _ = count ()
+                scale (add_offset (count ()))
+                "#,
+            )
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(result, 36);
+        assert_eq!(counter.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    assert_eq!(
+        Arc::strong_count(&captured),
+        1,
+        "The captured state was not dropped along with the vm"
+    );
+}
+
+#[tokio::test]
+async fn async_closure_suspends_until_the_future_completes() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+
+    let prefix = "fetched ".to_string();
+    add_extern_module(&vm, "test", move |vm| {
+        let prefix = prefix.clone();
+        ExternModule::new(
+            vm,
+            record! {
+                fetch => async_closure::<fn(String) -> String, _>("test.fetch", move |url: String| {
+                    let (sender, receiver) = futures::channel::oneshot::channel();
+                    let response = format!("{}{}", prefix, url);
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_millis(10));
+                        let _ = sender.send(response);
+                    });
+                    async move { receiver.await.unwrap_or_default() }
+                })
+            },
+        )
+    });
+
+    let (result, _) = vm
+        .run_expr_async::<String>(
+            "test",
+            r#"
+            let { fetch } = import! test
+            fetch "a" ++ ", " ++ fetch "b"
+            "#,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "fetched a, fetched b");
+}
+
+#[test]
+fn structured_errors_from_primitives() {
+    let _ = ::env_logger::try_init();
+
+    #[derive(Debug)]
+    struct ConfigError(std::num::ParseIntError);
+
+    impl std::fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "Invalid port")
+        }
+    }
+
+    impl std::error::Error for ConfigError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    fn parse_port(s: &str) -> Result<VmInt, ConfigError> {
+        s.parse().map_err(ConfigError)
+    }
+
+    fn port(s: &str) -> Result<VmInt, ErrorInfo> {
+        Ok(parse_port(s)?)
+    }
+
+    fn port_or_panic(s: &str) -> RuntimeResult<VmInt, ErrorInfo> {
+        port(s).into()
+    }
+
+    let vm = make_vm();
+    add_extern_module(&vm, "test", |vm| {
+        ExternModule::new(
+            vm,
+            record! {
+                port => primitive!(1, port),
+                port_or_panic => primitive!(1, port_or_panic)
+            },
+        )
+    });
+
+    let (result, _) = vm
+        .run_expr::<String>(
+            "test",
+            r#"
+            let { port } = import! test
+            let { Result } = import! std.result
+            let array = import! std.array
+            match port "http" with
+            | Ok _ -> ""
+            | Err e -> e.message ++ " / " ++ array.index e.causes 0
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "Invalid port / invalid digit found in string");
+
+    let err = vm
+        .run_expr::<VmInt>("test", r#"(import! test).port_or_panic "http""#)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("Invalid port: invalid digit found in string"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn error_info_causes_start_with_the_direct_cause() {
+    #[derive(Debug)]
+    struct Chained(&'static str, Option<Box<Chained>>);
+
+    impl std::fmt::Display for Chained {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl std::error::Error for Chained {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.1.as_ref().map(|err| &**err as _)
+        }
+    }
+
+    let err = Chained(
+        "Could not load config",
+        Some(Box::new(Chained(
+            "Invalid port",
+            Some(Box::new(Chained("invalid digit found in string", None))),
+        ))),
+    );
+    let info = ErrorInfo::from(err);
+    assert_eq!(info.message, "Could not load config");
+    assert_eq!(
+        info.causes,
+        ["Invalid port", "invalid digit found in string"]
+    );
+    assert_eq!(
+        info.to_string(),
+        "Could not load config: Invalid port: invalid digit found in string"
+    );
+
+    let info = ErrorInfo {
+        backtrace: Some("0: load_config".into()),
+        ..info
+    };
+    assert_eq!(
+        format!("{:#}", info),
+        "Could not load config: Invalid port: invalid digit found in string\n\
+         Backtrace:\n0: load_config"
+    );
+}
+
+#[test]
+fn extern_modules_are_loaded_on_first_import() {
+    let _ = ::env_logger::try_init();
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let vm = make_vm();
+    let loads = Arc::new(AtomicUsize::new(0));
+    for name in &["host.graphics", "host.audio"] {
+        let loads = loads.clone();
+        let module = name.to_string();
+        add_extern_module_lazy(&vm, name, move |vm| {
+            loads.fetch_add(1, Ordering::SeqCst);
+            ExternModule::new(vm, record! { name => module.clone() })
+        });
+    }
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+    let (result, _) = vm
+        .run_expr::<String>(
+            "test",
+            r#"
+            let graphics = import! host.graphics
+            let graphics2 = import! host.graphics
+            graphics.name ++ graphics2.name
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "host.graphicshost.graphics");
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn import_modules_from_a_resolver() {
+    let _ = ::env_logger::try_init();
+
+    struct InMemory(HashMap<&'static str, &'static str>);
+
+    impl ImportResolver for InMemory {
+        fn resolve(
+            &self,
+            module: &str,
+            _filename: &str,
+        ) -> Result<Option<Cow<'static, str>>, gluon::import::Error> {
+            Ok(self.0.get(module).map(|source| Cow::Borrowed(*source)))
+        }
+
+        fn modules(&self) -> Vec<String> {
+            self.0.keys().map(|name| name.to_string()).collect()
+        }
+    }
+
+    let vm = make_vm();
+    let mut modules = HashMap::new();
+    modules.insert(
+        "virtual.greeting",
+        r#"let { name } = import! virtual.name in "Hello " ++ name"#,
+    );
+    modules.insert("virtual.name", r#"{ name = "world" }"#);
+    modules.insert("virtual.broken", "1 +");
+    let import = vm.get_macros().get("import");
+    import
+        .as_ref()
+        .and_then(|import| import.downcast_ref::<Import>())
+        .expect("Import macro")
+        .add_resolver(InMemory(modules));
+
+    let (result, _) = vm
+        .run_expr::<String>("test", "import! virtual.greeting")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "Hello world");
+
+    let err = vm
+        .run_expr::<VmInt>("test", "import! virtual.broken")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("virtual.broken"), "{}", err);
+}
+
+#[test]
+fn vms_sharing_typechecked_modules() {
+    let _ = ::env_logger::try_init();
+
+    let shared = gluon::query::SharedModules::new();
+    let new_vm = || {
+        gluon::VmBuilder::new()
+            .import_paths(Some(vec![".".into(), "..".into()]))
+            .shared_modules(Some(shared.clone()))
+            .build()
+    };
+
+    let expr = r#"
+        let list @ { ? } = import! std.list
+        let { foldl } = import! std.foldable
+        foldl (+) 0 (list.of [1, 2, 3])
+    "#;
+
+    let vm1 = new_vm();
+    let (value, _) = vm1
+        .run_expr::<VmInt>("counter", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 6);
+    assert!(shared.modules().contains(&"std.list".to_string()));
+
+    // The second vm reuses the typechecked modules but runs them, and the script, itself
+    let reused = shared.reused();
+    let vm2 = new_vm();
+    let (value, _) = vm2
+        .run_expr::<VmInt>("counter", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 6);
+    assert!(
+        shared.reused() > reused,
+        "The second vm typechecked every module again"
+    );
+}
+
+#[test]
+fn vms_sharing_modules_which_use_extern_types() {
+    let _ = ::env_logger::try_init();
+
+    let shared = gluon::query::SharedModules::new();
+    let new_vm = || {
+        gluon::VmBuilder::new()
+            .import_paths(Some(vec![".".into(), "..".into()]))
+            .shared_modules(Some(shared.clone()))
+            .build()
+    };
+
+    // `IO` and `File` are registered by each vm, so the second vm must agree with the types that
+    // the first vm inferred for `std.io`
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = import! std.applicative
+        do _ = io.println "shared"
+        wrap 1
+    "#;
+
+    for _ in 0..2 {
+        let vm = new_vm();
+        let (value, _) = vm
+            .run_expr::<IO<VmInt>>("shared_io", expr)
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(value, IO::Value(1));
+    }
+    assert!(shared.modules().contains(&"std.io".to_string()));
+    assert!(shared.reused() > 0);
+}
+
+#[test]
+fn run_expr_with_bindings() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let (value, _) = vm
+        .run_expr_with::<VmInt, VmInt>("cell", "let c = a * b in c + a", &[("a", 3), ("b", 4)])
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 15);
+
+    let (value, _) = vm
+        .run_expr_with::<String, &str>(
+            "cell",
+            r#"let string = import! std.string in string.trim name"#,
+            &[("name", "  gluon ")],
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, "gluon");
+
+    // The bindings are locals so they are not visible to later expressions
+    assert!(vm.run_expr::<VmInt>("cell", "a").is_err());
+    assert!(vm
+        .run_expr_with::<VmInt, VmInt>("cell", "a + \"\"", &[("a", 1)])
+        .is_err());
+}
+
+#[test]
+fn run_multi_line_expr_with_bindings() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let expr = r#"
+let c =
+    a * b
+let d = c + a
+d * 2
+"#;
+    let (value, _) = vm
+        .run_expr_with::<VmInt, VmInt>("cell", expr, &[("a", 3), ("b", 4)])
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 30);
+}
+
+#[test]
+fn run_expr_with_invalid_binding_name() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    for name in &["", "a -> 1 ; b", "1a", "let", "a b"] {
+        let result = vm.run_expr_with::<VmInt, VmInt>("cell", "1", &[(*name, 1)]);
+        assert!(result.is_err(), "`{}` was accepted as a binding name", name);
+    }
+}
+
+#[test]
+fn compile_function_once_and_call_it_repeatedly() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let mut discount = vm
+        .compile_function::<f64, f64>(
+            "rule",
+            r#"\price -> if price > 100.0 then price * 0.9 else price"#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let mut total = 0.0;
+    for price in &[50.0, 200.0, 100.0] {
+        total += discount.call(*price).unwrap();
+    }
+    assert_eq!(total, 330.0);
+
+    assert!(vm
+        .compile_function::<f64, f64>("rule", r#"\price -> "free""#)
+        .is_err());
+}
+
+#[test]
+fn typecheck_without_running_imports() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    {
+        let mut db = vm.get_database_mut();
+        db.add_module(
+            "test_effect".into(),
+            r#"let { error } = import! std.prim in let x : Int = error "ran" in { x }"#.into(),
+        );
+        db.add_module("test_dep".into(), "{ x = 1 }".into());
+        db.add_module(
+            "test_lazy".into(),
+            "let dep = import! test_dep in { y = dep.x + 1 }".into(),
+        );
+    }
+
+    let expr = "let m = import! test_effect in m.x + 1";
+    let typ = vm
+        .typecheck_str_only("check", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(typ, Type::int());
+    assert!(vm.run_expr::<VmInt>("run", expr).is_err());
+
+    assert!(vm.typecheck_str_only("check", r#"1 + """#).is_err());
+
+    // Modules which were only typechecked still run their imports once they are run
+    let expr = "let m = import! test_lazy in m.y";
+    let typ = vm
+        .typecheck_str_only("check", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(typ, Type::int());
+    let (value, _) = vm
+        .run_expr::<VmInt>("run", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 2);
+}
+
+#[test]
+fn type_at_position() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let expr = r#"
+let f x = if x == "" then 1 else 2
+let r = { name = "a", count = f "b" }
+r.count
+"#;
+    vm.typecheck_str_only("type_at", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let offset = |needle: &str| expr.find(needle).unwrap();
+    assert_eq!(vm.type_at("type_at", offset("x =")), Some(Type::string()));
+    assert_eq!(vm.type_at("type_at", offset("\"a\"")), Some(Type::string()));
+    assert_eq!(
+        vm.type_at("type_at", offset("f \"b\""))
+            .map(|typ| typ.to_string()),
+        Some("String -> Int".to_string())
+    );
+    assert_eq!(vm.type_at("type_at", offset("count\n")), Some(Type::int()));
+    assert_eq!(vm.type_at("type_at", expr.len() + 10), None);
+    assert_eq!(vm.type_at("missing", 0), None);
+}
+
+#[test]
+fn forbidden_effects_reject_scripts() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+    vm.get_database_mut().set_forbidden_effects(vec!["IO".to_string()]);
+
+    // Importing a module with effectful values is fine as long as they are not used
+    let typ = vm
+        .typecheck_str_only("pure", "let io = import! std.io in 1 + 2")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(typ, Type::int());
+
+    // Only effects in the result of a function are performed by calling it
+    vm.typecheck_str_only(
+        "argument",
+        "let { IO } = import! std.io in let apply f : (() -> IO ()) -> Int = 1 in apply",
+    )
+    .unwrap_or_else(|err| panic!("{}", err));
+
+    // A type of the same name is not the `IO` effect
+    let typ = vm
+        .typecheck_str_only("shadowed", "type IO = Int in let x : IO = 1 in x")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(typ.to_string(), "IO");
+
+    let err = vm
+        .typecheck_str_only("effect", r#"let io = import! std.io in io.println "x""#)
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("`println` performs the `IO` effect which is not allowed"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn private_items_are_hidden_from_other_modules() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let counter = r#"
+#[private]
+type Counter = { count : Int }
+
+#[private]
+let start = 0
+
+let new : Counter = { count = start }
+let get counter : Counter -> Int = counter.count
+
+{ Counter, new, get, start }
+"#;
+    load_script(&vm, "counter", counter).unwrap_or_else(|err| panic!("{}", err));
+
+    let typ = vm
+        .typecheck_str_only(
+            "public",
+            "let counter = import! counter in counter.get counter.new",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(typ, Type::int());
+
+    // Private bindings are not part of the module's type, however the module is accessed
+    for (name, expr) in &[
+        (
+            "private_value",
+            "let counter = import! counter in counter.start",
+        ),
+        (
+            "private_pattern",
+            "let { start } = import! counter in start",
+        ),
+        (
+            "private_value_through_function",
+            "let id x = x in (id (import! counter)).start",
+        ),
+    ] {
+        let err = vm.typecheck_str_only(name, expr).unwrap_err();
+        assert!(err.to_string().contains("start"), "{}", err);
+    }
+
+    for (name, expr) in &[
+        (
+            "private_field_through_function",
+            "let id x = x in (id (import! counter)).new.count",
+        ),
+        (
+            "private_field",
+            "let counter = import! counter in counter.new.count",
+        ),
+        (
+            "private_construction",
+            "let { Counter } = import! counter in let c : Counter = { count = 1 } in c",
+        ),
+    ] {
+        let err = vm.typecheck_str_only(name, expr).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("is private to the module which defines it"),
+            "{}",
+            err
+        );
+    }
+}
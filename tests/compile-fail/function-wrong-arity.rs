@@ -0,0 +1,13 @@
+extern crate gluon;
+use gluon::vm::api::OwnedFunction;
+use gluon::{new_vm, ThreadExt};
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn main() {
+    let vm = new_vm();
+    let (mut f, _) = vm
+        .run_expr::<OwnedFunction<fn(i32) -> i32>>("f", r"\x -> x")
+        .unwrap();
+    f.call(1, 2);
+    //~^ Error this method takes 1 argument but 2 arguments were supplied
+}
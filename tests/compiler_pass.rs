@@ -0,0 +1,183 @@
+mod support;
+
+use std::sync::{Arc, Mutex};
+
+use gluon::{
+    base::{
+        ast::{self, EmptyEnv, Expr, Literal, MutVisitor, SpannedExpr, Typed, Visitor},
+        symbol::Symbol,
+    },
+    compiler_pass::{CompilerPass, PassContext, Stage},
+    vm::api::{Hole, OpaqueValue},
+    Error, Thread, ThreadExt,
+};
+
+use crate::support::make_vm;
+
+/// Reports every use of the identifier `self.0`
+struct Forbid(&'static str);
+
+impl CompilerPass for Forbid {
+    fn name(&self) -> &str {
+        "forbid"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Parsed
+    }
+
+    fn run<'ast>(&self, context: &mut PassContext<'_, 'ast>, expr: &mut SpannedExpr<'ast, Symbol>) {
+        struct Find<'a, 'b, 'ast>(&'static str, &'a mut PassContext<'b, 'ast>);
+        impl<'a, 'ast> Visitor<'a, 'ast> for Find<'_, '_, 'ast> {
+            type Ident = Symbol;
+            fn visit_expr(&mut self, expr: &'a SpannedExpr<'ast, Symbol>) {
+                match &expr.value {
+                    Expr::Ident(id) if id.name.declared_name() == self.0 => self
+                        .1
+                        .error(expr.span, format!("`{}` is not allowed", self.0)),
+                    _ => ast::walk_expr(self, expr),
+                }
+            }
+        }
+        Find(self.0, context).visit_expr(expr);
+    }
+}
+
+/// Replaces `__module` with the name of the module it appears in
+struct ModuleName;
+
+impl CompilerPass for ModuleName {
+    fn name(&self) -> &str {
+        "module_name"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Parsed
+    }
+
+    fn run<'ast>(&self, context: &mut PassContext<'_, 'ast>, expr: &mut SpannedExpr<'ast, Symbol>) {
+        struct Replace<'a>(&'a str);
+        impl<'a, 'ast> MutVisitor<'a, 'ast> for Replace<'_> {
+            type Ident = Symbol;
+            fn visit_expr(&mut self, expr: &'a mut SpannedExpr<'ast, Symbol>) {
+                match &expr.value {
+                    Expr::Ident(id) if id.name.declared_name() == "__module" => {
+                        expr.value = Expr::Literal(Literal::String(self.0.to_string()))
+                    }
+                    _ => ast::walk_mut_expr(self, expr),
+                }
+            }
+        }
+        Replace(context.module).visit_expr(expr);
+    }
+}
+
+struct Record {
+    name: &'static str,
+    stage: Stage,
+    order: i32,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl CompilerPass for Record {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    fn order(&self) -> i32 {
+        self.order
+    }
+
+    fn run<'ast>(&self, context: &mut PassContext<'_, 'ast>, expr: &mut SpannedExpr<'ast, Symbol>) {
+        if context.module == "test" {
+            let typ = match self.stage {
+                Stage::Parsed => String::new(),
+                Stage::Typechecked => format!(": {}", expr.env_type_of(&EmptyEnv::default())),
+            };
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{}{}", self.name, typ));
+        }
+    }
+}
+
+#[test]
+fn pass_errors() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.get_database_mut()
+        .add_pass(Forbid("unsafe_op"))
+        .add_pass(Forbid("other_op"));
+
+    let result = vm.run_expr::<OpaqueValue<&Thread, Hole>>(
+        "test",
+        "let unsafe_op x = x\nlet other_op x = x\nunsafe_op (other_op 1)",
+    );
+    match result {
+        Err(Error::Pass(err)) => {
+            let message = err.to_string();
+            assert!(
+                message.contains("forbid: `unsafe_op` is not allowed"),
+                "{}",
+                message
+            );
+            assert!(
+                message.contains("forbid: `other_op` is not allowed"),
+                "{}",
+                message
+            );
+            assert!(message.contains("test:3:1"), "{}", message);
+        }
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    // Modules which do not trigger the pass are unaffected
+    assert_eq!(vm.run_expr::<i32>("test2", "1 + 2").map(|t| t.0), Ok(3));
+}
+
+#[test]
+fn desugar_before_typechecking() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.get_database_mut().add_pass(ModuleName);
+
+    let (name, _) = vm
+        .run_expr::<String>("my_module", "__module")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(name, "my_module");
+}
+
+#[test]
+fn passes_run_in_order() {
+    let _ = ::env_logger::try_init();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let vm = make_vm();
+    let record = |name, stage, order| Record {
+        name,
+        stage,
+        order,
+        log: log.clone(),
+    };
+    vm.get_database_mut()
+        .add_pass(record("check", Stage::Typechecked, 0))
+        .add_pass(record("late", Stage::Parsed, 1))
+        .add_pass(record("first", Stage::Parsed, 0))
+        .add_pass(record("early", Stage::Parsed, -1))
+        .add_pass(record("second", Stage::Parsed, 0));
+
+    vm.run_expr::<i32>("test", "1 + 2")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["early", "first", "second", "late", "check: Int"]
+    );
+}
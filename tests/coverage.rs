@@ -0,0 +1,69 @@
+mod support;
+
+use std::collections::BTreeMap;
+
+use gluon::{base::pos::Line, ThreadExt};
+
+use crate::support::make_vm;
+
+fn lines(counts: &[(u32, usize)]) -> BTreeMap<Line, usize> {
+    counts
+        .iter()
+        .map(|&(line, hits)| (Line::from(line), hits))
+        .collect()
+}
+
+#[test]
+fn counts_executed_lines() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.get_database_mut()
+        .implicit_prelude(false)
+        .coverage(true);
+
+    let text = r#"
+let sign x =
+    if x #Int< 0 then
+        "negative"
+    else
+        "positive"
+let _ = sign 1
+sign 2
+"#;
+    let (sign, _) = vm
+        .run_expr::<String>("test", text)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(sign, "positive");
+
+    let report = vm.global_env().coverage().report();
+    assert_eq!(
+        report.modules.get("test"),
+        Some(&lines(&[(1, 1), (2, 2), (3, 0), (5, 2), (6, 1), (7, 1)]))
+    );
+
+    let mut lcov = Vec::new();
+    report
+        .write_lcov(&mut lcov, |module| {
+            if module == "test" {
+                Some("test.glu".to_string())
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    assert!(lcov.starts_with("TN:\nSF:test.glu\nDA:2,1\n"), "{}", lcov);
+    assert!(lcov.ends_with("LF:6\nLH:5\nend_of_record\n"), "{}", lcov);
+}
+
+#[test]
+fn coverage_is_disabled_by_default() {
+    let _ = ::env_logger::try_init();
+
+    let vm = make_vm();
+    vm.get_database_mut().implicit_prelude(false);
+    vm.run_expr::<i32>("test", "1 #Int+ 2")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert!(vm.global_env().coverage().report().modules.is_empty());
+}
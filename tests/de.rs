@@ -189,3 +189,168 @@ fn enum_() {
         .unwrap_or_else(|err| panic!("{}", err));
     assert_eq!(enum_, Enum::C(0, 1));
 }
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Untyped {
+    name: String,
+    tags: Vec<String>,
+    size: Option<i32>,
+}
+
+#[test]
+fn json_without_vm_type() {
+    use gluon::vm::api::{json::Json, FunctionRef};
+
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    thread
+        .run_expr::<()>("test", "let _ = import! std.json in ()")
+        .unwrap_or_else(|err| panic!("{}", err));
+    let (mut size, _) = thread
+        .run_expr::<FunctionRef<fn(Json<Untyped>) -> Option<i64>>>(
+            "test",
+            r#"
+            let { Value } = import! std.json
+            let { Map, find, ? } = import! std.map
+            \v ->
+                match v with
+                | Object m ->
+                    match find "size" m with
+                    | Some (Int i) -> Some i
+                    | _ -> None
+                | _ -> None
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let value = Untyped {
+        name: "test".to_string(),
+        tags: vec!["a".to_string()],
+        size: Some(3),
+    };
+    assert_eq!(size.call(Json(value)), Ok(Some(3)));
+
+    let (Json(value), _) = thread
+        .run_expr::<Json<Untyped>>(
+            "test",
+            r#"
+            let { Value } = import! std.json
+            let de = import! std.json.de
+            let { Result } = import! std.result
+            let input = "{ \"name\": \"test\", \"tags\": [\"a\", \"b\"], \"size\": null }"
+            match de.deserialize_with de.value input with
+            | Ok v -> v
+            | Err _ -> Null
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        value,
+        Untyped {
+            name: "test".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+            size: None,
+        }
+    );
+}
+
+#[test]
+fn json_with_the_wrong_shape_is_an_error() {
+    use gluon::vm::api::json::Json;
+
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let err = thread
+        .run_expr::<Json<Untyped>>(
+            "test",
+            r#"
+            let { Value } = import! std.json
+            Int 1
+            "#,
+        )
+        .map(|_| ())
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid type"), "{}", err);
+}
+
+#[test]
+fn typed_json() {
+    use gluon::vm::api::json::{call_json, push_json, to_json};
+    use serde_json::json;
+
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    thread
+        .load_script(
+            "test",
+            r#"
+            type Shape = | Circle Float | Rect Float Float | Empty
+            let area shape : Shape -> Float =
+                match shape with
+                | Circle r -> 3.0 * r * r
+                | Rect w h -> w * h
+                | Empty -> 0.0
+            let describe x : { name : String, tags : Array String, size : Option Int } -> String =
+                x.name
+            { Shape, area, describe, shapes = [Circle 1.0, Rect 2.0 3.0, Empty] }
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (value, typ) = thread
+        .run_expr::<OpaqueValue<&Thread, Hole>>(
+            "test",
+            r#"
+            let { shapes } = import! test
+            { shapes, enabled = True, missing = None, answer = Some 'a', unit = () }
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    let value = to_json(&thread, value.get_variant(), &typ).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "shapes": [{ "Circle": [1.0] }, { "Rect": [2.0, 3.0] }, "Empty"],
+            "enabled": true,
+            "missing": null,
+            "answer": "a",
+            "unit": null,
+        })
+    );
+
+    {
+        let mut context = thread.current_context();
+        push_json(&mut context, &value, &typ).unwrap();
+        let pushed = context.pop();
+        assert_eq!(to_json(&thread, pushed.clone(), &typ), Ok(value));
+    }
+
+    let (area, typ) = thread.get_env().get_binding("test.area").unwrap();
+    assert_eq!(
+        call_json(&area, &typ, &[json!({ "Rect": [2, 3.5] })]),
+        Ok(json!(7.0))
+    );
+    assert_eq!(
+        call_json(&area, &typ, &[json!({ "Square": [2] })]).map_err(|err| err.to_string()),
+        Err(
+            "`Square` is not a constructor of `| Circle Float\n| Rect Float Float\n| Empty`".into()
+        )
+    );
+    assert!(call_json(&area, &typ, &[json!(1), json!(2)]).is_err());
+
+    let (describe, typ) = thread.get_env().get_binding("test.describe").unwrap();
+    assert_eq!(
+        call_json(
+            &describe,
+            &typ,
+            &[json!({ "name": "test", "tags": ["a"], "size": 3 })]
+        ),
+        Ok(json!("test"))
+    );
+    assert_eq!(
+        call_json(&describe, &typ, &[json!({ "name": "test" })]).map_err(|err| err.to_string()),
+        Err("Missing the field `tags` of `{ name : String, tags : Array String, size : std.types.Option Int }`".into())
+    );
+}
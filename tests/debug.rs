@@ -8,16 +8,26 @@ use std::{
 
 use futures::{prelude::*, task::Poll};
 
+use gluon_codegen::Trace;
+
 use gluon::{
     base::{
-        pos::Line,
+        ast::{self, Expr, Literal, SpannedExpr, TypedIdent},
+        origin::Desugaring,
+        pos::{self, ByteOffset, BytePos, Line, Span},
+        source::Source,
+        symbol::Symbol,
         types::{ArcType, Type, TypeExt},
     },
+    compiler_pipeline::Typecheckable,
     vm::{
+        self,
+        api::ValueRef,
         compiler::UpvarInfo,
+        macros::{Macro, MacroExpander, MacroFuture},
         thread::{HookFlags, ThreadInternal},
     },
-    RootedThread, ThreadExt,
+    Error, RootedThread, ThreadExt,
 };
 
 fn new_vm() -> RootedThread {
@@ -67,6 +77,39 @@ fn function_hook() {
     );
 }
 
+#[test]
+fn instruction_hook() {
+    let _ = env_logger::try_init();
+
+    let count_instructions = |source: &str| {
+        let thread = new_vm();
+        let instructions = Arc::new(Mutex::new(0));
+        {
+            let instructions = instructions.clone();
+            let mut context = thread.context();
+            context.set_hook(Some(Box::new(move |_, debug_info| {
+                assert_eq!(debug_info.state(), HookFlags::INSTRUCTION_FLAG);
+                *instructions.lock().unwrap() += 1;
+                Poll::Ready(Ok(()))
+            })));
+            context.set_hook_mask(HookFlags::INSTRUCTION_FLAG);
+        }
+        thread.run_expr::<i32>("test", source).unwrap();
+        let count = *instructions.lock().unwrap();
+        count
+    };
+
+    let simple = count_instructions(SIMPLE_EXPR);
+    assert!(simple > 0);
+    let recursive = count_instructions(
+        r#"
+        let count n = if n == 0 then 0 else count (n - 1)
+        count 10
+        "#,
+    );
+    assert!(recursive > simple, "{} > {}", recursive, simple);
+}
+
 fn run_line_hook_test(source: &str) -> Vec<Line> {
     let thread = new_vm();
     {
@@ -217,6 +260,48 @@ fn implicit_prelude_lines_not_counted() {
     assert_eq!(lines, vec![Line::from(0)]);
 }
 
+#[test]
+fn read_local_values() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    let result = Arc::new(Mutex::new(Vec::new()));
+    {
+        let result = result.clone();
+        let mut context = thread.context();
+        context.set_hook(Some(Box::new(move |_, debug_context| {
+            let stack_info = debug_context.stack_info(0).unwrap();
+            let mut result = result.lock().unwrap();
+            result.clear();
+            for local in stack_info.locals() {
+                let value = match stack_info.local_value(local).unwrap().as_ref() {
+                    ValueRef::Int(i) => i,
+                    _ => panic!("Expected an integer"),
+                };
+                result.push((local.name.declared_name().to_string(), value));
+            }
+            Poll::Ready(Ok(()))
+        })));
+        context.set_hook_mask(HookFlags::LINE_FLAG);
+    }
+    let expr = r#"
+    let x = 1
+    let y = 2
+    x
+    "#;
+
+    thread.get_database_mut().implicit_prelude(false);
+
+    thread
+        .run_expr::<i32>("test", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    assert_eq!(
+        *result.lock().unwrap(),
+        vec![("x".to_string(), 1), ("y".to_string(), 2)]
+    );
+}
+
 #[test]
 fn read_variables() {
     let _ = env_logger::try_init();
@@ -479,3 +564,145 @@ fn implicit_prelude_variable_names() {
         _ => panic!("{:#?}", f[0]),
     }
 }
+
+#[test]
+fn origins_of_desugared_code() {
+    let _ = env_logger::try_init();
+
+    let expr = r#"
+#[derive(Show)]
+type T = | A Int
+
+let flat_map f x : (a -> Option b) -> Option a -> Option b =
+    match x with
+    | Some y -> f y
+    | None -> None
+
+let x : Option Int =
+    do y = Some 1
+    Some (y + 2 * 3)
+
+let s = show (A 1)
+format! "{s}"
+"#;
+
+    let thread = new_vm();
+    let value = futures::executor::block_on(expr.typecheck(
+        &mut thread.module_compiler(&mut thread.get_database()),
+        &thread,
+        "test",
+        expr,
+    ))
+    .unwrap_or_else(|err| panic!("{}", err));
+
+    let map = thread.get_database().get_filemap("test").unwrap();
+    // Origins are also recorded for the implicit prelude
+    let origins = value
+        .origins
+        .iter()
+        .filter(|(span, _)| map.span().contains(*span))
+        .map(|(span, origin)| {
+            (
+                map.src_slice(span),
+                origin.desugaring.clone(),
+                map.src_slice(origin.span),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        origins.contains(&("T", Desugaring::Derive("Show".into()), "T")),
+        "{:#?}",
+        origins
+    );
+    assert!(
+        origins.contains(&(
+            " y = Some 1\n    Some (y + 2 * 3)",
+            Desugaring::Do,
+            "do y = Some 1"
+        )),
+        "{:#?}",
+        origins
+    );
+    assert!(
+        origins.contains(&("y + 2 * 3", Desugaring::Infix, "y + 2 * 3")),
+        "{:#?}",
+        origins
+    );
+    assert!(
+        origins.contains(&(
+            "format! \"{s}\"",
+            Desugaring::Macro("format".into()),
+            "format! \"{s}\""
+        )),
+        "{:#?}",
+        origins
+    );
+}
+
+/// Expands `elsewhere! arg` to `arg; error "elsewhere"; 1` where the code around `arg` has spans
+/// which lie outside of the module, like code parsed from another file
+#[derive(Trace)]
+#[gluon_trace(skip)]
+struct Elsewhere;
+
+impl Macro for Elsewhere {
+    fn expand<'r, 'a: 'r, 'b: 'r, 'ast: 'r>(
+        &self,
+        env: &'b mut MacroExpander<'a>,
+        arena: &'b mut ast::OwnedArena<'ast, Symbol>,
+        args: &'b mut [SpannedExpr<'ast, Symbol>],
+    ) -> MacroFuture<'r, 'ast> {
+        Box::pin(async move {
+            let start = BytePos::from(u32::max_value() - 100);
+            let span = Span::new(start, start + ByteOffset::from(10));
+            let arg = std::mem::take(&mut args[0]);
+            let error = env.symbols().simple_symbol("error");
+            let call = pos::spanned(
+                span,
+                Expr::app(
+                    arena.borrow(),
+                    pos::spanned(span, Expr::Ident(TypedIdent::new(error))),
+                    vec![pos::spanned(
+                        span,
+                        Expr::Literal(Literal::String("elsewhere".into())),
+                    )],
+                ),
+            );
+            let one = pos::spanned(span, Expr::Literal(Literal::Int(1)));
+            Ok(pos::spanned(span, Expr::Block(arena.alloc_extend(vec![arg, call, one]))).into())
+        })
+    }
+}
+
+#[test]
+fn stacktrace_of_macro_expanded_code() {
+    let _ = env_logger::try_init();
+
+    let expr = r#"
+let { error } = import! std.prim
+let f x =
+    let g a b = a + b
+    g
+        x
+        (elsewhere!
+            x)
+f 1
+"#;
+
+    let thread = new_vm();
+    thread.get_macros().insert("elsewhere".into(), Elsewhere);
+    match thread.run_expr::<i32>("test", expr) {
+        Err(Error::VM(vm::Error::Panic(_, Some(stacktrace)))) => {
+            let frame = stacktrace
+                .frames
+                .iter()
+                .flatten()
+                .find(|frame| frame.name.declared_name() == "f")
+                .unwrap_or_else(|| panic!("{}", stacktrace));
+            // The line of the invocation, not the line of its argument
+            assert_eq!(frame.line, Some(Line::from(6)), "{}", stacktrace);
+        }
+        Err(err) => panic!("Unexpected error `{}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
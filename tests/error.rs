@@ -95,3 +95,72 @@ fn undefined_infix() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn invalid_format_specification() {
+    let _ = ::env_logger::try_init();
+
+    let vm = support::make_vm();
+    let text = r#"
+        let x = 1
+        format! "x = {x:?}"
+    "#;
+    let error = vm.load_script("test", text).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Invalid format specification `?`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn invalid_format_specification_at_runtime() {
+    let _ = ::env_logger::try_init();
+
+    let vm = support::make_vm();
+    let text = r#"
+        let format = import! std.format
+        format.int.format "?" 1
+    "#;
+    let error = vm.run_expr::<String>("test", text).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("Invalid format specification `?`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn invalid_decimal_literal() {
+    let _ = ::env_logger::try_init();
+
+    let vm = support::make_vm();
+    let error = vm.load_script("test", r#"decimal! "1.2.3""#).unwrap_err();
+    assert!(
+        error.to_string().contains("Invalid decimal `1.2.3`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn decimal_division_by_zero() {
+    let _ = ::env_logger::try_init();
+
+    let vm = support::make_vm();
+    let text = r#"
+        let decimal @ { ? } = import! std.decimal
+        let { (/) } = import! std.num
+        decimal.show.show (decimal.from_int 1 / decimal.zero)
+    "#;
+    let error = vm.run_expr::<String>("test", text).unwrap_err();
+    assert!(
+        error.to_string().contains("Decimal division by zero"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,86 @@
+#![cfg(all(feature = "ffi", target_os = "linux"))]
+
+use gluon::{vm::api::IO, RootedThread, ThreadExt, VmBuilder};
+
+fn run_io_f64(vm: &RootedThread, expr: &str) -> f64 {
+    match vm.run_expr::<IO<f64>>("test", expr) {
+        Ok((IO::Value(value), _)) => value,
+        Ok((IO::Exception(err), _)) => panic!("{}", err),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn call_double_function() {
+    let _ = ::env_logger::try_init();
+    let vm = VmBuilder::new().allow_ffi(true).build();
+    vm.get_database_mut().run_io(true);
+
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+        let ffi @ { CType, CValue } = import! std.ffi
+
+        do libm = ffi.open "libm.so.6"
+        do pow = ffi.function libm "pow" [F64, F64] F64
+        do result = ffi.call pow [Float 2.0, Float 10.0]
+        match result with
+        | Float f -> wrap f
+        | _ -> error "Expected a float"
+    "#;
+    assert_eq!(run_io_f64(&vm, expr), 1024.0);
+}
+
+#[test]
+fn call_with_strings_and_structs() {
+    let _ = ::env_logger::try_init();
+    let vm = VmBuilder::new().allow_ffi(true).build();
+    vm.get_database_mut().run_io(true);
+
+    let expr = r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+        let ffi @ { CType, CValue } = import! std.ffi
+
+        do libc = ffi.open "libc.so.6"
+        do strlen = ffi.function libc "strlen" [CString] U64
+        do len = ffi.call strlen [String "hello"]
+        do div = ffi.function libc "div" [I32, I32] (Struct [I32, I32])
+        do quot_rem = ffi.call div [Int 17, Int 5]
+        let array = import! std.array
+        match (len, quot_rem) with
+        | (Int len, Record fields) ->
+            match (array.index fields 0, array.index fields 1) with
+            | (Int quot, Int rem) -> wrap (len * 100 + quot * 10 + rem)
+            | _ -> error "Unexpected fields"
+        | _ -> error "Unexpected result"
+    "#;
+    match vm.run_expr::<IO<i32>>("test", expr) {
+        Ok((IO::Value(value), _)) => assert_eq!(value, 532),
+        Ok((IO::Exception(err), _)) => panic!("{}", err),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn ffi_capability_is_not_granted_by_default() {
+    let _ = ::env_logger::try_init();
+    let vm = VmBuilder::new().build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<()>>(
+        "test",
+        r#"
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+        let ffi = import! std.ffi
+        do _ = ffi.open "libm.so.6"
+        wrap ()
+        "#,
+    );
+    match result {
+        Ok((IO::Exception(err), _)) => assert!(err.contains("`ffi` capability"), "{}", err),
+        Err(err) => assert!(err.to_string().contains("`ffi` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
@@ -47,3 +47,61 @@ fn stack_overflow() {
         Ok(_) => panic!("Expected an error"),
     }
 }
+
+#[test]
+fn limits_from_builder() {
+    let _ = ::env_logger::try_init();
+
+    let vm = gluon::VmBuilder::new()
+        .max_stack_size(Some(3))
+        .compiler_settings(gluon::Settings {
+            implicit_prelude: false,
+            ..gluon::Settings::default()
+        })
+        .build();
+
+    let expr = " [1, 2, 3, 4] ";
+    let result = vm.run_expr::<OpaqueValue<&Thread, Hole>>("example", expr);
+
+    match result {
+        Err(Error::VM(VMError::StackOverflow(3))) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+
+    let vm = gluon::VmBuilder::new().memory_limit(Some(10)).build();
+    vm.get_database_mut().implicit_prelude(false);
+    let result = vm.run_expr::<OpaqueValue<&Thread, Hole>>("example", expr);
+
+    match result {
+        Err(Error::VM(VMError::OutOfMemory { limit: 10, .. })) => (),
+        Err(err) => panic!("Unexpected error `{:?}`", err),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn std_modules_from_builder() {
+    let _ = ::env_logger::try_init();
+
+    let vm = gluon::VmBuilder::new()
+        .import_paths(Some(vec![".".into(), "..".into()]))
+        .std_modules(Some(vec!["std.list".into()]))
+        .build();
+
+    let expr = r#"
+        let list @ { ? } = import! std.list
+        let { foldl } = import! std.foldable
+        foldl (+) 0 (list.of [1, 2, 3])
+    "#;
+    let (value, _) = vm
+        .run_expr::<i32>("list", expr)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 6);
+
+    let err = vm
+        .run_expr::<OpaqueValue<&Thread, Hole>>("fs", "import! std.fs")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("std.fs"), "{}", err);
+}
@@ -0,0 +1,103 @@
+use std::sync::Mutex;
+
+use log::kv;
+
+use gluon::{new_vm, vm::api::IO, ThreadExt};
+
+#[derive(Debug, PartialEq)]
+struct Captured {
+    level: log::Level,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+struct CaptureLogger(Mutex<Vec<Captured>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("script")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        struct Fields(Vec<(String, String)>);
+        impl<'kvs> kv::Visitor<'kvs> for Fields {
+            fn visit_pair(
+                &mut self,
+                key: kv::Key<'kvs>,
+                value: kv::Value<'kvs>,
+            ) -> Result<(), kv::Error> {
+                self.0.push((key.to_string(), value.to_string()));
+                Ok(())
+            }
+        }
+        let mut fields = Fields(Vec::new());
+        record.key_values().visit(&mut fields).unwrap();
+
+        self.0.lock().unwrap().push(Captured {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            fields: fields.0,
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn records_are_passed_to_the_host_logger() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let thread = new_vm();
+    thread.get_database_mut().run_io(true);
+    let text = r#"
+        let log @ { Level, Value, ? } = import! std.log
+        let io @ { ? } = import! std.io
+        let { wrap } = io.applicative
+
+        let logger = log.logger "script" [("user", String "ada")]
+        seq logger.info "started"
+        seq logger.debug "not written"
+        seq log.log Warn "script.db" "slow query" [("ms", Int 120), ("cached", Bool False)]
+        seq log.info "not a script target"
+        do debug_enabled = logger.enabled Debug
+        do warn_enabled = log.enabled Warn "script.db"
+        wrap (debug_enabled, warn_enabled)
+        "#;
+    let result = thread.run_expr::<IO<(bool, bool)>>("<top>", text);
+    match result {
+        Ok((IO::Value(value), _)) => assert_eq!(value, (false, true)),
+        Ok((IO::Exception(err), _)) => panic!("{}", err),
+        Err(err) => panic!("{}", err),
+    }
+
+    let records = LOGGER.0.lock().unwrap();
+    assert_eq!(
+        *records,
+        [
+            Captured {
+                level: log::Level::Info,
+                target: "script".into(),
+                message: "started".into(),
+                fields: vec![("user".into(), "ada".into())],
+            },
+            Captured {
+                level: log::Level::Warn,
+                target: "script.db".into(),
+                message: "slow query".into(),
+                fields: vec![
+                    ("ms".into(), "120".into()),
+                    ("cached".into(), "false".into())
+                ],
+            },
+        ]
+    );
+}
@@ -145,6 +145,7 @@ type TestFn = OwnedFunction<fn(()) -> TestEff>;
 enum TestCase {
     Test(String, TestFn),
     Group(String, Vec<TestCase>),
+    Tagged(Vec<String>, Box<TestCase>),
 }
 
 define_test_type! { TestCase Hole }
@@ -179,6 +180,7 @@ impl TestCase {
                 name,
                 tests: tests.into_iter().map(TestCase::into_tensile_test).collect(),
             },
+            TestCase::Tagged(_, test) => test.into_tensile_test(),
         }
     }
 }
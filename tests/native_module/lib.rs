@@ -0,0 +1,18 @@
+//! A native module which `load_native_module_fixture` in `tests/vm.rs` compiles into a dynamic
+//! library and loads
+use gluon::{
+    native_module,
+    vm::{self, primitive, record, ExternModule},
+    Thread,
+};
+
+fn load(vm: &Thread) -> vm::Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            double => primitive!(1, "native_fixture.double", |x: i32| x * 2),
+        },
+    )
+}
+
+native_module!("native_fixture", load);
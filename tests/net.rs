@@ -0,0 +1,125 @@
+#![cfg(feature = "net")]
+
+use gluon::{vm::api::IO, ThreadExt, VmBuilder};
+
+#[macro_use]
+mod support;
+
+use crate::support::*;
+
+#[tokio::test]
+async fn tcp_echo() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr_async::<IO<(String, String)>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let { dispose } = import! std.disposable
+            let { write_all } = import! std.io.write
+            let { read_to_string } = import! std.io.read
+            let { unwrap } = import! std.option
+            let string = import! std.string
+            let tcp @ { ? } = import! std.net.tcp
+
+            do listener = tcp.listen "127.0.0.1:0"
+            do client = tcp.connect (tcp.listener_address listener)
+            do server = tcp.accept listener
+            seq write_all client (string.as_bytes "ping")
+            seq dispose client
+            do request = read_to_string server
+            seq write_all server (string.as_bytes "pong")
+            seq dispose server
+            do response = read_to_string client
+            wrap (unwrap request, unwrap response)
+            "#,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value(("ping".to_string(), "pong".to_string())));
+}
+
+#[tokio::test]
+async fn tcp_read_timeout() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr_async::<IO<String>>(
+            "test",
+            r#"
+            let io @ { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let { (*>) } = import! std.applicative
+            let { Option } = import! std.option
+            let { read } = import! std.io.read
+            let duration = import! std.duration
+            let tcp @ { ? } = import! std.net.tcp
+
+            do listener = tcp.listen "127.0.0.1:0"
+            do client = tcp.connect (tcp.listener_address listener)
+            seq tcp.set_read_timeout client (Some (duration.from_millis 20))
+            io.catch (read client 10 *> wrap "read") wrap
+            "#,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    match result {
+        IO::Value(err) => assert!(err.contains("timed out"), "{}", err),
+        IO::Exception(err) => panic!("{}", err),
+    }
+}
+
+#[tokio::test]
+async fn udp_send_receive() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr_async::<IO<(Vec<u8>, bool)>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let string = import! std.string
+            let udp = import! std.net.udp
+
+            do a = udp.bind "127.0.0.1:0"
+            do b = udp.bind "127.0.0.1:0"
+            seq udp.send_to a (string.as_bytes "hello") (udp.local_address b)
+            do datagram = udp.receive_from b 3
+            wrap (datagram.data, datagram.address == udp.local_address a)
+            "#,
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value((b"hel".to_vec(), true)));
+}
+
+#[tokio::test]
+async fn capability_net_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = VmBuilder::new().allow_net(false).build_async().await;
+    vm.get_database_mut().run_io(true);
+
+    let result = vm
+        .run_expr_async::<IO<String>>(
+            "test",
+            r#"
+            let io = import! std.io
+            let tcp = import! std.net.tcp
+            io.functor.map tcp.listener_address (tcp.listen "127.0.0.1:0")
+            "#,
+        )
+        .await;
+    match result {
+        Err(err) => assert!(err.to_string().contains("`net` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
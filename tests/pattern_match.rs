@@ -199,3 +199,103 @@ getName ("abc", 123)
 "#,
 "abc".to_string()
 }
+
+test_expr! { pattern_synonym,
+r#"
+type List a = | Nil | Cons a (List a)
+#[pattern]
+let Singleton x = Cons x Nil
+#[pattern]
+let Pair x y = Cons x (Singleton y)
+match Pair 1 2 with
+| Singleton x -> x
+| Pair x y -> x #Int+ y
+| _ -> 0
+"#,
+3i32
+}
+
+test_expr! { pattern_synonym_without_arguments,
+r#"
+type Option a = | None | Some a
+#[pattern]
+let Zero = Some 0
+match Some 0 with
+| Zero -> 1
+| _ -> 0
+"#,
+1i32
+}
+
+#[test]
+fn pattern_synonym_arity_mismatch() {
+    let _ = ::env_logger::try_init();
+    let text = r"
+type Option a = | None | Some a
+#[pattern]
+let Just x = Some x
+match Some 1 with
+| Just -> 1
+| _ -> 0
+";
+    let vm = make_vm();
+    let result = vm.run_expr::<i32>("<top>", text);
+    assert!(result.is_err());
+}
+
+test_expr! { view_pattern,
+r#"
+type Option a = | None | Some a
+let positive x = if x #Int< 0 then None else Some x
+let abs x =
+    match x with
+    | (positive -> Some y) -> y
+    | y -> 0 #Int- y
+abs 3 #Int+ abs (0 #Int- 4)
+"#,
+7i32
+}
+
+test_expr! { view_pattern_projection,
+r#"
+type Option a = | None | Some a
+let int = { parse = \x -> if x #Int< 0 then None else Some x }
+match 3 with
+| (int.parse -> Some n) -> n
+| _ -> 0
+"#,
+3i32
+}
+
+test_expr! { view_patterns_fall_through,
+r#"
+type Option a = | None | Some a
+let below y x = if x #Int< y then Some x else None
+let negative = below 0
+let small = below 10
+let medium = below 100
+let classify x =
+    match x with
+    | (negative -> Some _) -> 1
+    | (small -> Some _) -> 2
+    | (medium -> Some _) -> 3
+    | _ -> 4
+classify (0 #Int- 1) #Int+ classify 5 #Int* 10 #Int+ classify 50 #Int* 100 #Int+ classify 500 #Int* 1000
+"#,
+4321i32
+}
+
+test_expr! { nested_view_patterns,
+r#"
+type Option a = | None | Some a
+type List a = | Nil | Cons a (List a)
+let positive x = if x #Int< 0 then None else Some x
+let first_positive xs =
+    match xs with
+    | Cons (positive -> Some x) _ -> x
+    | Cons _ (Cons (positive -> Some y) _) -> y
+    | _ -> 0
+first_positive (Cons (0 #Int- 1) (Cons 5 Nil))
+"#,
+5i32
+}
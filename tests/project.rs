@@ -0,0 +1,172 @@
+use std::{fs, path::PathBuf};
+
+use gluon::{
+    project::{Dependency, Manifest, Project},
+    ThreadExt, VmBuilder,
+};
+
+#[test]
+fn parse_manifest() {
+    let manifest = Manifest::parse(
+        r#"
+# The application
+[package]
+name = "app"
+version = "0.1.0"
+source_dirs = ["src", "lib"] # Searched in order
+
+[dependencies]
+json = { path = "../json" }
+collections = "0.2"
+
+[dependencies.parser]
+git = "https://example.com/parser#1"
+rev = "v1.0"
+
+[settings]
+std_lib = false
+allow_net = false
+memory_limit = 1_000_000
+
+[format]
+max_width = 80
+"#,
+    )
+    .unwrap_or_else(|err| panic!("{}", err))
+    .expect("Manifest");
+
+    assert_eq!(manifest.package.name, "app");
+    assert_eq!(manifest.package.version.as_deref(), Some("0.1.0"));
+    assert_eq!(manifest.package.entry, PathBuf::from("src/main.glu"));
+    assert_eq!(
+        manifest.package.source_dirs,
+        [PathBuf::from("src"), PathBuf::from("lib")]
+    );
+    assert_eq!(
+        manifest.dependencies.into_iter().collect::<Vec<_>>(),
+        [
+            (
+                "collections".to_string(),
+                Dependency::Registry {
+                    version: "0.2".into()
+                }
+            ),
+            ("json".to_string(), Dependency::Path("../json".into())),
+            (
+                "parser".to_string(),
+                Dependency::Git {
+                    url: "https://example.com/parser#1".into(),
+                    rev: Some("v1.0".into())
+                }
+            ),
+        ]
+    );
+    assert!(!manifest.settings.std_lib);
+    assert_eq!(manifest.settings.allow_net, Some(false));
+    assert_eq!(manifest.settings.allow_fs, None);
+    assert_eq!(manifest.settings.memory_limit, Some(1_000_000));
+}
+
+#[test]
+fn manifest_errors() {
+    let error = |manifest: &str| {
+        Manifest::parse(manifest)
+            .map(|_| ())
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error("[package]\nname = \"app\"\nsource_dirs = \"src\""),
+        "Expected an array for `source_dirs`, found a string"
+    );
+    assert_eq!(
+        error("[package]\nname = \"app\"\n[dependencies]\njson = { path = \"a\", git = \"b\" }"),
+        "The dependency `json` must have exactly one of `path`, `git` or `version` and may \
+         only have a `rev` if it is a `git` dependency"
+    );
+    assert_eq!(
+        error("[package]\nversion = \"1\""),
+        "`[package]` does not have a `name`"
+    );
+
+    // Only configures `gluon fmt`
+    assert_eq!(Manifest::parse("[format]\nmax_width = 80").unwrap(), None);
+}
+
+#[test]
+fn reject_unsafe_dependencies() {
+    let error = |dependency: &str| {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("gluon.toml"),
+            format!("[package]\nname = \"app\"\n[dependencies]\n{}", dependency),
+        )
+        .unwrap();
+        Project::load(dir.path()).unwrap_err().to_string()
+    };
+
+    let err = error(r#""../escape" = { git = "https://example.com/dep" }"#);
+    assert!(err.contains("may only contain ASCII letters"), "{}", err);
+    let err = error(r#"dep = { git = "--upload-pack=touch /tmp/pwned" }"#);
+    assert!(err.contains("may not start with `-`"), "{}", err);
+    let err = error(r#"dep = { git = "https://example.com/dep", rev = "-b" }"#);
+    assert!(err.contains("may not start with `-`"), "{}", err);
+    let err = error(r#"dep = "../../etc""#);
+    assert!(err.contains("is not a valid version"), "{}", err);
+}
+
+#[test]
+fn import_from_dependency() {
+    let _ = env_logger::try_init();
+
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        (
+            "app/gluon.toml",
+            "[package]\nname = \"app\"\n[dependencies]\ndep = { path = \"../dep\" }",
+        ),
+        ("app/src/helper.glu", "3"),
+        (
+            "dep/gluon.toml",
+            "[package]\nname = \"dep\"\nentry = \"src/lib.glu\"",
+        ),
+        ("dep/src/lib.glu", "{ y = 2 }"),
+        ("dep/src/util.glu", "{ x = 1 }"),
+    ];
+    for (file, contents) in &files {
+        let path = dir.path().join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    let project =
+        Project::find(&dir.path().join("app/src")).unwrap_or_else(|err| panic!("{}", err));
+    let project = project.expect("Project");
+    assert_eq!(project.root, dir.path().join("app"));
+    assert_eq!(project.entry(), dir.path().join("app/src/main.glu"));
+
+    let vm = project.configure(VmBuilder::new()).build();
+    vm.get_database_mut().set_implicit_prelude(false);
+    let (value, _) = vm
+        .run_expr::<i32>(
+            "test",
+            r#"
+let { x } = import! dep.util
+let { y } = import! dep
+let z : Int = import! helper
+x #Int+ y #Int+ z
+"#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 6);
+
+    let err = vm
+        .run_expr::<i32>("missing", "import! dep.missing")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("Could not find module 'dep.missing' in package 'dep'"),
+        "{}",
+        err
+    );
+}
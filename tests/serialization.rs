@@ -282,3 +282,100 @@ fn issue_805_no_deadlock_in_deserialize() {
         .to_string()
         .contains("is not defined"));
 }
+
+#[test]
+fn snapshot_roundtrip() {
+    let _ = env_logger::try_init();
+
+    let thread = new_vm();
+    thread
+        .load_script(
+            "snapshot_test",
+            r#"
+            let x = 1 + 2
+            { x, add_x = \y -> y + x }
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut buffer = Vec::new();
+    thread
+        .save_snapshot(&mut bincode::Serializer::new(
+            &mut buffer,
+            bincode::DefaultOptions::new(),
+        ))
+        .unwrap();
+
+    let thread2 = new_vm();
+    thread2
+        .load_snapshot(&mut bincode::Deserializer::from_slice(
+            &buffer,
+            bincode::DefaultOptions::new(),
+        ))
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (result, _) = thread2
+        .run_expr::<i32>("test", "let m = import! snapshot_test in m.add_x 10")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, 13);
+}
+
+#[test]
+fn snapshot_userdata() {
+    use gluon::{
+        import::add_extern_module,
+        vm::{api::UserdataValue, ExternModule},
+    };
+
+    #[derive(Debug, Clone, PartialEq, gluon_codegen::Userdata, gluon_codegen::Trace)]
+    #[gluon_trace(skip)]
+    #[gluon_userdata(clone)]
+    struct Position(i32, i32);
+
+    impl gluon::vm::api::VmType for Position {
+        type Type = Self;
+    }
+
+    fn make_vm() -> gluon::RootedThread {
+        let thread = new_vm();
+        thread.register_type::<Position>("Position", &[]).unwrap();
+        thread.register_userdata_hooks(
+            "Position",
+            |pos: &Position| bincode::serialize(&(pos.0, pos.1)).unwrap(),
+            |bytes| {
+                let (x, y) = bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+                Ok(Position(x, y))
+            },
+        );
+        add_extern_module(&thread, "position", |thread| {
+            ExternModule::new(thread, Position(1, 2))
+        });
+        thread
+    }
+
+    let thread = make_vm();
+    thread
+        .load_script("snapshot_test", "let pos = import! position in { pos }")
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut buffer = Vec::new();
+    thread
+        .save_snapshot(&mut bincode::Serializer::new(
+            &mut buffer,
+            bincode::DefaultOptions::new(),
+        ))
+        .unwrap();
+
+    let thread2 = make_vm();
+    thread2
+        .load_snapshot(&mut bincode::Deserializer::from_slice(
+            &buffer,
+            bincode::DefaultOptions::new(),
+        ))
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let (UserdataValue(result), _) = thread2
+        .run_expr::<UserdataValue<Position>>("test", "(import! snapshot_test).pos")
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, Position(1, 2));
+}
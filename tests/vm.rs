@@ -9,7 +9,12 @@ mod support;
 use crate::support::*;
 
 use gluon::{
-    base::{pos::BytePos, source::Source, types::Type},
+    base::{
+        pos::{BytePos, Span},
+        source::Source,
+        types::Type,
+    },
+    query::CompilationBase,
     vm,
     vm::{
         api::{FunctionRef, Hole, OpaqueValue, ValueRef, IO},
@@ -829,6 +834,230 @@ let from f : (Int -> Option a) -> Stream a =
     assert_eq!(result, Ok(Type::int()));
 }
 
+#[tokio::test]
+async fn definition_in_imported_module() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+
+    let source = r#"
+let list = import! std.list
+let xs = list.of [1, 2]
+xs
+"#;
+
+    let (expr, _) = vm
+        .typecheck_str_async("example", source, None)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let db = vm.get_database();
+    let file_map = db.get_filemap("example").expect("file_map");
+    let metadata_map = &db
+        .peek_typechecked_source_module("example")
+        .expect("example")
+        .metadata_map;
+
+    let definition = completion::definition(
+        metadata_map,
+        file_map.span(),
+        &expr.expr(),
+        file_map.byte_index(3.into(), 0.into()).unwrap(),
+    );
+    match definition {
+        Some(completion::Definition::Local(span)) => {
+            assert_eq!(file_map.src_slice(span), "xs");
+            assert_eq!(file_map.location(span.start()).unwrap().line, 2.into());
+        }
+        _ => panic!("Expected a local definition: {:?}", definition),
+    }
+
+    let definition = completion::definition(
+        metadata_map,
+        file_map.span(),
+        &expr.expr(),
+        file_map.byte_index(2.into(), 15.into()).unwrap(),
+    );
+    let symbol = match definition {
+        Some(completion::Definition::Imported(symbol)) => symbol,
+        _ => panic!("Expected an imported definition: {:?}", definition),
+    };
+    let list = db
+        .peek_typechecked_source_module("std.list")
+        .expect("std.list");
+    let list_map = db.get_filemap("std.list").expect("std.list file_map");
+    let span = completion::find_definition(list_map.span(), &list.expr.expr(), symbol)
+        .expect("definition of `of`");
+    assert!(
+        list_map.src()[(span.start() - list_map.span().start()).to_usize()..].starts_with("of"),
+        "{}",
+        list_map.src_slice(span)
+    );
+
+    // The standard library is embedded in the binary unless the `test` feature is enabled, in
+    // which case it is read from `std/list.glu`
+    match db.module_path("std.list") {
+        Some(path) => assert!(path.ends_with("std/list.glu"), "{}", path.display()),
+        None => assert!(!cfg!(feature = "test")),
+    }
+}
+
+#[tokio::test]
+async fn semantic_tokens_skip_generated_names() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+
+    let source = r#"
+let list = import! std.list
+list.of [1]
+"#;
+    let (expr, _) = vm
+        .typecheck_str_async("example", source, None)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let db = vm.get_database();
+    let file_map = db.get_filemap("example").expect("file_map");
+    let metadata_map = &db
+        .peek_typechecked_source_module("example")
+        .expect("example")
+        .metadata_map;
+    let tokens: Vec<_> = completion::semantic_tokens(metadata_map, file_map.span(), &expr.expr())
+        .into_iter()
+        .map(|token| (file_map.src_slice(token.span), token.kind))
+        .collect();
+    assert_eq!(
+        tokens,
+        [
+            ("list", completion::TokenKind::Variable),
+            ("list", completion::TokenKind::Variable),
+            ("of", completion::TokenKind::Function),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn inlay_hints_for_implicit_arguments() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+
+    let source = r#"
+let { (==) } = import! std.prelude
+let { ? } = import! std.int
+let list = import! std.list
+let xs = list.of [1]
+1 == 2
+"#;
+    let (expr, _) = vm
+        .typecheck_str_async("example", source, None)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let db = vm.get_database();
+    let file_map = db.get_filemap("example").expect("file_map");
+    let hints: Vec<_> = completion::inlay_hints(
+        &completion::InlayHintOptions::default(),
+        file_map.span(),
+        &expr.expr(),
+    )
+    .into_iter()
+    .map(|hint| {
+        let line = file_map.location(hint.position).unwrap().line.to_usize();
+        (line, hint.label, hint.kind)
+    })
+    .collect();
+    assert_eq!(
+        hints,
+        [
+            (
+                4,
+                ": std.list.List Int".to_string(),
+                completion::InlayHintKind::Type
+            ),
+            (
+                5,
+                "?eq".to_string(),
+                completion::InlayHintKind::ImplicitArgument
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn references_in_imported_modules() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+
+    vm.load_script_async(
+        "references_lib",
+        r#"
+let value = 1
+let other = 2
+{ value, other }
+"#,
+    )
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+    vm.load_script_async(
+        "references_user",
+        r#"
+let lib = import! references_lib
+let { value } = import! references_lib
+value + lib.value
+"#,
+    )
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+
+    let db = vm.get_database();
+    let index = |module: &str| {
+        let file_map = db.get_filemap(module).expect("file_map");
+        let typechecked = db
+            .peek_typechecked_source_module(module)
+            .expect("typechecked module");
+        let index = completion::SymbolIndex::new(
+            &typechecked.metadata_map,
+            file_map.span(),
+            &typechecked.expr.expr(),
+        );
+        (file_map, index)
+    };
+    let (lib_map, lib) = index("references_lib");
+    let (user_map, user) = index("references_user");
+
+    let value = user
+        .definition_at(user_map.byte_index(3.into(), 0.into()).unwrap())
+        .expect("definition")
+        .clone();
+    assert_eq!(
+        lib.references(&value)
+            .into_iter()
+            .map(|span| lib_map.location(span.start()).unwrap().line.to_usize())
+            .collect::<Vec<_>>(),
+        [1, 3]
+    );
+    let user_references = user.references(&value);
+    assert_eq!(user_references.len(), 3);
+    for span in user_references {
+        assert_eq!(user_map.src_slice(span), "value");
+    }
+
+    let indexes = [("references_lib", &lib), ("references_user", &user)];
+    assert_eq!(
+        completion::rename(&indexes, &value, "renamed")
+            .unwrap()
+            .iter()
+            .map(|(_, spans)| spans.len())
+            .collect::<Vec<_>>(),
+        [2, 3]
+    );
+    match completion::rename(&indexes, &value, "other") {
+        Err(completion::RenameError::Conflict { module, .. }) => {
+            assert_eq!(module, "references_lib")
+        }
+        result => panic!("Expected a conflict: {:?}", result),
+    }
+}
+
 #[tokio::test]
 async fn completion_with_prelude_at_0() {
     let _ = ::env_logger::try_init();
@@ -852,6 +1081,55 @@ async fn completion_with_prelude_at_0() {
     assert_eq!(result, Ok(Type::int()));
 }
 
+#[tokio::test]
+async fn qualify_name_with_module() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm_async().await;
+
+    let modules = ["std.int".to_string(), "std.list".to_string()];
+    let cases = [
+        (
+            "let list = import! std.list\n1",
+            ("Change to `list.of`", "list.of"),
+        ),
+        (
+            "let { List } = import! std.list\n1",
+            (
+                "Import `of` from `std.list`",
+                "let { of } = import! std.list\n",
+            ),
+        ),
+    ];
+    for &(source, (title, new_text)) in &cases {
+        let (expr, _) = vm
+            .typecheck_str_async("example", source, None)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        let file_map = vm.get_database().get_filemap("example").expect("file_map");
+        let end = file_map.span().end();
+        let actions: Vec<_> = completion::qualify_name(
+            &vm.get_env(),
+            &*file_map,
+            &expr.expr(),
+            Span::new(end, end),
+            "of",
+            &modules,
+        )
+        .into_iter()
+        .map(|action| {
+            let edits: Vec<_> = action.edits.into_iter().map(|edit| edit.new_text).collect();
+            (action.title, edits)
+        })
+        .collect();
+        assert_eq!(
+            actions,
+            [(title.to_string(), vec![new_text.to_string()])],
+            "{}",
+            source
+        );
+    }
+}
+
 #[tokio::test]
 async fn suggestion_from_implicit_prelude() {
     let _ = ::env_logger::try_init();
@@ -1103,3 +1381,415 @@ let f a =
 { f }
 "
 }
+
+#[test]
+fn deterministic_mode_disables_thread_rng() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().deterministic(true).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<i32>>(
+        "test",
+        "let random = import! std.random in random.thread_rng.next_int ()",
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("deterministic"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn deterministic_mode_hides_closure_addresses() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().deterministic(true).build();
+
+    let (result, _) = vm
+        .run_expr::<String>(
+            "test",
+            r#"
+            let debug = import! std.debug
+            let f x = x
+            debug.show f
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "<f>");
+
+    let (result, _) = vm
+        .run_expr::<String>(
+            "test",
+            r#"
+            let debug = import! std.debug
+            let { ref } = import! std.reference
+            let f x = x
+            debug.show (ref f)
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, "<Userdata Ref(<f>)>");
+}
+
+#[test]
+fn deterministic_mode_disables_the_clock() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().deterministic(true).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<String>>(
+        "test",
+        r#"
+        let io = import! std.io
+        let time = import! std.time
+        io.functor.map time.format_rfc3339 time.now
+        "#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("deterministic"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn capability_io_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_io(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<()>>("test", r#"let io = import! std.io in io.println "hello""#);
+    match result {
+        Err(err) => assert!(err.to_string().contains("`io` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn read_clock() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr::<IO<bool>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let time = import! std.time
+            let duration = import! std.duration
+            do now = time.now
+            do instant = time.instant.now
+            do elapsed = time.instant.elapsed instant
+            wrap (time.unix_seconds now > 1600000000 && duration.nanos elapsed >= 0)
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value(true));
+}
+
+#[test]
+fn capability_clock_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_clock(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<String>>(
+        "test",
+        r#"
+        let io = import! std.io
+        let time = import! std.time
+        io.functor.map time.format_rfc3339 time.now
+        "#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("`clock` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn capability_fs_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_fs(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<String>>(
+        "test",
+        r#"let io = import! std.io in io.read_file_to_string "Cargo.toml""#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("`fs` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn capability_env_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_env(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result =
+        vm.run_expr::<IO<String>>("test", r#"let env = import! std.env in env.var "PATH""#);
+    match result {
+        Err(err) => assert!(err.to_string().contains("`env` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+
+    let result =
+        vm.run_expr::<IO<Vec<String>>>("test", r#"let args = import! std.args in args.args"#);
+    match result {
+        Err(err) => assert!(err.to_string().contains("`env` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn program_args() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new()
+        .args(Some(vec!["--verbose".into(), "input.txt".into()]))
+        .build();
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr::<IO<(bool, Option<String>)>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let { Result } = import! std.result
+            let args = import! std.args
+            let cmd =
+                args.command "test" "" [args.flag "verbose" "", args.positional "input" ""]
+            do result = args.parse_args cmd
+            match result with
+            | Ok matches -> wrap (args.is_present "verbose" matches, args.value "input" matches)
+            | Err _ -> wrap (False, None)
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value((true, Some("input.txt".to_string()))));
+}
+
+#[test]
+fn capability_fs_not_granted_std_fs() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_fs(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<bool>>(
+        "test",
+        r#"let fs = import! std.fs in fs.exists "Cargo.toml""#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("`fs` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn capability_fs_not_granted_std_path() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_fs(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<bool>>(
+        "test",
+        r#"let path = import! std.path in path.is_file "Cargo.toml""#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("`fs` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn spawn_process_with_piped_stdio() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr::<IO<(String, Option<i32>)>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let { dispose } = import! std.disposable
+            let { write_all } = import! std.io.write
+            let { read_to_string } = import! std.io.read
+            let { unwrap } = import! std.option
+            let string = import! std.string
+            let process @ { ? } = import! std.process
+            do spawned = process.spawn (process.proc "cat" [])
+            seq write_all spawned.stdin (string.as_bytes "hello")
+            seq dispose spawned.stdin
+            do stdout = read_to_string spawned.stdout
+            do status = process.wait spawned.child
+            wrap (unwrap stdout, status.code)
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(result, IO::Value(("hello".to_string(), Some(0))));
+}
+
+#[cfg(unix)]
+#[test]
+fn process_output_and_timeout() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+    vm.get_database_mut().run_io(true);
+
+    let (result, _) = vm
+        .run_expr::<IO<(String, String, Option<i32>, bool)>>(
+            "test",
+            r#"
+            let { ? } = import! std.io
+            let { wrap } = import! std.applicative
+            let { Option } = import! std.option
+            let duration = import! std.duration
+            let process = import! std.process
+            do output = process.output (process.proc "sh" ["-c", "echo out; echo err >&2; exit 3"])
+            do timed_out = process.output_timeout (process.proc "sleep" ["5"]) (duration.from_millis 50)
+            let timed_out =
+                match timed_out with
+                | Some _ -> False
+                | None -> True
+            wrap (output.stdout, output.stderr, output.status.code, timed_out)
+            "#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        result,
+        IO::Value(("out\n".to_string(), "err\n".to_string(), Some(3), true))
+    );
+}
+
+#[test]
+fn capability_process_not_granted() {
+    let _ = ::env_logger::try_init();
+    let vm = gluon::VmBuilder::new().allow_process(false).build();
+    vm.get_database_mut().run_io(true);
+
+    let result = vm.run_expr::<IO<String>>(
+        "test",
+        r#"
+        let io = import! std.io
+        let process = import! std.process
+        io.functor.map (\output -> output.stdout) (process.output (process.proc "echo" []))
+        "#,
+    );
+    match result {
+        Err(err) => assert!(err.to_string().contains("`process` capability"), "{}", err),
+        Ok((value, _)) => panic!("Expected an error, got {:?}", value),
+    }
+}
+
+#[test]
+fn load_native_module_missing_library() {
+    let _ = ::env_logger::try_init();
+    let vm = make_vm();
+
+    let err = vm
+        .load_native_module("this/library/does/not/exist.so")
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Unable to load native module"),
+        "{}",
+        err
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn load_native_module_fixture() {
+    use std::{env, fs, path::Path, process::Command};
+
+    let _ = ::env_logger::try_init();
+
+    // Compile `tests/native_module` the same way as this test, with the dependency versions of
+    // `Cargo.lock` and the same features of gluon, otherwise the module is rejected
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let fixture = Path::new(env!("CARGO_TARGET_TMPDIR")).join("native_module");
+    fs::create_dir_all(&fixture).unwrap();
+    fs::write(
+        fixture.join("Cargo.toml"),
+        format!(
+            r#"
+[package]
+name = "native_module"
+version = "0.0.0"
+edition = "2018"
+
+[lib]
+path = "{}"
+crate-type = ["cdylib"]
+
+[dependencies]
+gluon = {{ path = "{}", default-features = false }}
+
+[workspace]
+"#,
+            manifest_dir.join("tests/native_module/lib.rs").display(),
+            manifest_dir.display(),
+        ),
+    )
+    .unwrap();
+    fs::copy(manifest_dir.join("Cargo.lock"), fixture.join("Cargo.lock")).unwrap();
+
+    let enabled_features = gluon::native::FEATURES.trim_end_matches('\0');
+    let cargo_toml = fs::read_to_string(manifest_dir.join("Cargo.toml")).unwrap();
+    let features: Vec<_> = cargo_toml
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split('=').next())
+        .map(|feature| feature.trim())
+        .filter(|feature| {
+            enabled_features
+                .split(',')
+                .any(|enabled| enabled == feature.replace('-', "_"))
+        })
+        .map(|feature| format!("gluon/{}", feature))
+        .collect();
+
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(fixture.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(fixture.join("target"));
+    if !features.is_empty() {
+        cargo.arg("--features").arg(features.join(","));
+    }
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        cargo.arg("--release");
+        "release"
+    };
+    assert!(cargo.status().unwrap().success());
+
+    let library = fixture.join("target").join(profile).join(format!(
+        "{}native_module{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ));
+
+    let vm = make_vm();
+    let name = vm
+        .load_native_module(&library)
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(name, "native_fixture");
+
+    let (value, _) = vm
+        .run_expr::<i32>(
+            "native",
+            "let native = import! native_fixture in native.double 21",
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(value, 42);
+}
@@ -1,5 +1,7 @@
 use std::any::Any;
+use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Mutex;
 
 #[cfg(feature = "serde")]
 use crate::serde::{Deserialize, Deserializer};
@@ -11,15 +13,17 @@ use futures::{
 };
 
 use crate::base::symbol::Symbol;
-use crate::base::types::ArcType;
+use crate::base::types::{ArcType, Type, TypeExt};
 
-use crate::api::{ActiveThread, AsyncPushable, Getable, Pushable, RootedValue, VmType};
+use crate::api::{
+    ActiveThread, AsyncPushable, FutureResult, Getable, Pushable, RootedValue, VmType,
+};
 use crate::compiler::{CompiledFunction, CompiledModule};
 use crate::gc::{Move, Trace};
 use crate::stack::{ExternState, StackFrame};
 use crate::thread::{RootedThread, Status, Thread, ThreadInternal, VmRoot, VmRootInternal};
 use crate::types::{Instruction, VmIndex};
-use crate::value::ExternFunction;
+use crate::value::{Callable, ExternFunction, PartialApplicationDataDef, Userdata, ValueRepr};
 use crate::{Error, Result, Variants};
 
 pub type GluonFunction = extern "C" fn(&Thread) -> Status;
@@ -121,6 +125,179 @@ impl<'vm> Pushable<'vm> for CPrimitive {
     }
 }
 
+/// A Rust closure which is pushed to gluon as a function of type `S`.
+///
+/// Unlike `Primitive` the closure may capture state. The closure is stored as userdata which the
+/// gluon function refers to, so the state is dropped once the function has been collected.
+///
+/// The garbage collector can not look inside a Rust closure so gluon values captured by the
+/// closures of `closure`, `closure_mut` and `async_closure` (such as `OwnedFunction`) stay rooted
+/// for as long as the closure lives. State passed to `closure_with_state` is traced instead.
+///
+/// ```rust,ignore
+/// let offset = 10;
+/// let add = closure::<fn(i32) -> i32, _>("add_offset", move |x: i32| x + offset);
+/// ```
+pub struct ClosurePrimitive<S, F> {
+    name: &'static str,
+    function: F,
+    _typ: PhantomData<S>,
+}
+
+fn closure_primitive<S, F>(name: &'static str, function: F) -> ClosurePrimitive<S, F> {
+    ClosurePrimitive {
+        name,
+        function,
+        _typ: PhantomData,
+    }
+}
+
+/// Creates a gluon function of type `S` from a `Fn` closure
+pub fn closure<S, F>(name: &'static str, function: F) -> ClosurePrimitive<S, FnClosure<F>> {
+    closure_primitive(name, FnClosure(function))
+}
+
+/// Creates a gluon function of type `S` from `state` and a `Fn` closure which is called with a
+/// reference to `state` followed by the arguments of the gluon function. `state` is traced by the
+/// garbage collector together with the function.
+///
+/// ```rust,ignore
+/// let add = closure_with_state::<fn(i32) -> i32, _, _>("add_offset", 10, |offset, x: i32| {
+///     x + offset
+/// });
+/// ```
+pub fn closure_with_state<S, T, F>(
+    name: &'static str,
+    state: T,
+    function: F,
+) -> ClosurePrimitive<S, WithState<T, F>> {
+    closure_primitive(name, WithState { state, function })
+}
+
+/// Creates a gluon function of type `S` from a `FnMut` closure.
+///
+/// The closure is locked while it runs so it must not call itself recursively through gluon.
+pub fn closure_mut<S, F>(name: &'static str, function: F) -> ClosurePrimitive<S, MutClosure<F>> {
+    closure_primitive(name, MutClosure(Mutex::new(function)))
+}
+
+/// Creates a gluon function of type `S` from a closure returning a `Future`. The future is run
+/// by the virtual machine, suspending the gluon thread which called the function until it
+/// completes.
+///
+/// ```rust,ignore
+/// let client = Client::new();
+/// let send = async_closure::<fn(String) -> IO<String>, _>("http.send", move |url: String| {
+///     let client = client.clone();
+///     async move { client.get(&url).await.into() }
+/// });
+/// ```
+pub fn async_closure<S, F>(
+    name: &'static str,
+    function: F,
+) -> ClosurePrimitive<S, AsyncClosure<F>> {
+    closure_primitive(name, AsyncClosure(function))
+}
+
+/// Wraps a `Fn` closure
+pub struct FnClosure<F>(F);
+
+/// Wraps a `FnMut` closure so that it can be called through a shared reference
+pub struct MutClosure<F>(Mutex<F>);
+
+/// Wraps a closure returning a `Future` so that the future is run by the virtual machine
+pub struct AsyncClosure<F>(F);
+
+/// Wraps a `Fn` closure together with the traced state it is called with
+pub struct WithState<T, F> {
+    state: T,
+    function: F,
+}
+
+// The captures of a closure can't be traced, any gluon values they hold are roots
+unsafe impl<F> Trace for FnClosure<F> {
+    impl_trace! { self, _gc, { } }
+}
+
+unsafe impl<F> Trace for MutClosure<F> {
+    impl_trace! { self, _gc, { } }
+}
+
+unsafe impl<F> Trace for AsyncClosure<F> {
+    impl_trace! { self, _gc, { } }
+}
+
+unsafe impl<T: Trace, F> Trace for WithState<T, F> {
+    impl_trace_fields! { self, gc; state }
+}
+
+/// Abstracts over the `Fn`, `FnMut` and async closures which can be called by a
+/// `ClosurePrimitive`. `Args` is a tuple of the arguments of the closure.
+pub trait CallClosure<Args> {
+    /// The value which is pushed to gluon after calling the closure
+    type Output;
+
+    fn call_closure(&self, args: Args) -> Self::Output;
+}
+
+impl<S: VmType, F> VmType for ClosurePrimitive<S, F> {
+    type Type = S::Type;
+    fn make_type(vm: &Thread) -> ArcType {
+        S::make_type(vm)
+    }
+}
+
+/// Userdata holding the closure of a `ClosurePrimitive`
+struct CapturedClosure<F>(F);
+
+impl<F> fmt::Debug for CapturedClosure<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<closure>")
+    }
+}
+
+impl<F> Userdata for CapturedClosure<F> where F: Trace + Send + Sync + 'static {}
+
+unsafe impl<F: Trace> Trace for CapturedClosure<F> {
+    impl_trace! { self, gc, {
+        let CapturedClosure(function) = self;
+        mark(function, gc)
+    } }
+}
+
+/// Pushes `wrapper` partially applied to the userdata holding `function`
+fn push_closure<'vm, F>(
+    context: &mut ActiveThread<'vm>,
+    name: &str,
+    function: F,
+    args: VmIndex,
+    wrapper: GluonFunction,
+) -> Result<()>
+where
+    F: Trace + Send + Sync + 'static,
+{
+    let id = Symbol::from(name.replace("::", "."));
+    context.context().push_new_alloc(Move(ExternFunction {
+        id,
+        args: args + 1,
+        function: wrapper,
+    }))?;
+    CapturedClosure(function).vm_push(context)?;
+
+    let mut context = context.context();
+    let callable = match context.stack[context.stack.len() - 2].get_repr() {
+        ValueRepr::Function(ext) => construct_gc!(Callable::Extern(@ ext)),
+        _ => unreachable!(),
+    };
+    let fields = std::slice::from_ref(context.stack.last().unwrap());
+    let def = construct_gc!(PartialApplicationDataDef(@callable, fields));
+    let value = Variants::from(context.gc.alloc(def)?);
+
+    context.stack.pop_many(2);
+    context.stack.push(value);
+    Ok(())
+}
+
 fn make_type<T: ?Sized + VmType>(vm: &Thread) -> ArcType {
     <T as VmType>::make_type(vm)
 }
@@ -169,6 +346,7 @@ where
         self.value.vm()
     }
 
+    /// Copies the function into `vm`. See `RootedValue::re_root`
     pub fn re_root<'vm, U>(&self, vm: U) -> Result<Function<U, F>>
     where
         U: VmRoot<'vm>,
@@ -316,7 +494,7 @@ where $($args: Getable<'vm, 'vm> + 'vm,)*
     }
 }
 
-fn block_on_sync<F, T>(f: F) -> F::Output
+pub(crate) fn block_on_sync<F, T>(f: F) -> F::Output
 where
     F: Future<Output = Result<T>>,
 {
@@ -415,7 +593,7 @@ impl<T, $($args,)* R> Function<T, fn($($args),*) -> R>
     }
 
     fn return_value(vm: &Thread, value: Variants) -> Result<R> {
-        Ok(R::from_value(vm, value))
+        crate::api::try_from_value(vm, value)
     }
 }
 
@@ -453,6 +631,105 @@ make_vm_function!(A, B, C, D, E);
 make_vm_function!(A, B, C, D, E, F);
 make_vm_function!(A, B, C, D, E, F, G);
 
+macro_rules! make_closure {
+    ($($args:ident),*) => (
+impl<Func, $($args,)* R> CallClosure<($($args,)*)> for FnClosure<Func>
+where
+    Func: Fn($($args),*) -> R,
+{
+    type Output = R;
+
+    #[allow(non_snake_case)]
+    fn call_closure(&self, ($($args,)*): ($($args,)*)) -> R {
+        (self.0)($($args),*)
+    }
+}
+
+impl<T, Func, $($args,)* R> CallClosure<($($args,)*)> for WithState<T, Func>
+where
+    Func: Fn(&T, $($args),*) -> R,
+{
+    type Output = R;
+
+    #[allow(non_snake_case)]
+    fn call_closure(&self, ($($args,)*): ($($args,)*)) -> R {
+        (self.function)(&self.state, $($args),*)
+    }
+}
+
+impl<Func, $($args,)* R> CallClosure<($($args,)*)> for MutClosure<Func>
+where
+    Func: FnMut($($args),*) -> R,
+{
+    type Output = R;
+
+    #[allow(non_snake_case)]
+    fn call_closure(&self, ($($args,)*): ($($args,)*)) -> R {
+        let mut function = self.0.lock().unwrap();
+        (&mut *function)($($args),*)
+    }
+}
+
+impl<Func, $($args,)* R> CallClosure<($($args,)*)> for AsyncClosure<Func>
+where
+    Func: Fn($($args),*) -> R,
+    R: Future,
+{
+    type Output = FutureResult<R>;
+
+    #[allow(non_snake_case)]
+    fn call_closure(&self, ($($args,)*): ($($args,)*)) -> FutureResult<R> {
+        FutureResult((self.0)($($args),*))
+    }
+}
+
+impl<'vm, Func, $($args,)* R> Pushable<'vm> for ClosurePrimitive<fn($($args),*) -> R, Func>
+where
+    Func: CallClosure<($($args,)*)> + Trace + Send + Sync + 'static,
+    Func::Output: for<'x> AsyncPushable<'x> + VmType<Type = R::Type> + 'static,
+    $($args: for<'x> Getable<'x, 'x> + 'static,)*
+    R: VmType,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        #[allow(non_snake_case)]
+        fn invoke<Func, $($args),*>(
+            closure: &CapturedClosure<Func>,
+            $($args: $args),*
+        ) -> Func::Output
+        where
+            Func: CallClosure<($($args,)*)>,
+        {
+            closure.0.call_closure(($($args,)*))
+        }
+
+        extern "C" fn wrapper<'thread, Func, $($args),*>(thread: &'thread Thread) -> Status
+        where
+            Func: CallClosure<($($args,)*)> + Trace + Send + Sync + 'static,
+            Func::Output: for<'x> AsyncPushable<'x> + VmType + 'static,
+            $($args: for<'x> Getable<'x, 'x> + 'static,)*
+        {
+            VmFunction::unpack_and_call(
+                &(invoke::<Func, $($args),*>
+                    as fn(&'thread CapturedClosure<Func>, $($args),*) -> Func::Output),
+                thread,
+            )
+        }
+
+        let args = <fn($($args),*) -> R as FunctionType>::arguments();
+        push_closure(context, self.name, self.function, args, wrapper::<Func, $($args),*>)
+    }
+}
+    )
+}
+
+make_closure!();
+make_closure!(A);
+make_closure!(A, B);
+make_closure!(A, B, C);
+make_closure!(A, B, C, D);
+make_closure!(A, B, C, D, E);
+make_closure!(A, B, C, D, E, F);
+
 impl<'vm, T, F> Function<T, F>
 where
     T: VmRootInternal + 'vm,
@@ -475,51 +752,66 @@ where
 
     /// Calls `self` with a number of arguments that is only known at runtime.
     ///
-    /// WARNING: No check is done that the number of arguments is correct. The VM may return an
-    /// error or panic if an incorrect number of arguments is passed.
-    pub fn call_any<A, R>(&'vm self, args: impl IntoIterator<Item = A>) -> Result<R>
+    /// Returns an error if the number of arguments does not match the type of the function. The
+    /// types of the arguments themselves are not checked so they must be values of the types the
+    /// function expects.
+    pub fn call_any<R>(&'vm self, args: &[Variants<'_>]) -> Result<R>
     where
-        A: Pushable<'vm>,
         R: for<'value> Getable<'vm, 'value> + VmType,
     {
         block_on_sync(self.call_any_async(args))
     }
 
-    async fn call_any_async<A, R>(&'vm self, args: impl IntoIterator<Item = A>) -> Result<R>
+    async fn call_any_async<R>(&'vm self, args: &[Variants<'_>]) -> Result<R>
     where
-        A: Pushable<'vm>,
         R: for<'value> Getable<'vm, 'value> + VmType,
     {
-        let mut args = Some(args);
-        future::poll_fn(move |cx| self.call_any_first(cx, args.take().unwrap())).await
+        self.check_arity(args.len())?;
+        future::poll_fn(move |cx| self.call_any_first(cx, args)).await
+    }
+
+    fn check_arity(&self, given: usize) -> Result<()> {
+        let typ = F::make_type(self.value.vm());
+        let typ = typ.remove_forall();
+        if let Type::Hole = **typ {
+            // The type of the function is not known
+            return Ok(());
+        }
+        let expected = typ.arg_iter().count();
+        if given == expected {
+            Ok(())
+        } else {
+            Err(Error::Message(format!(
+                "Expected {} arguments to function of type `{}` but {} were given",
+                expected, typ, given
+            )))
+        }
     }
 
-    fn call_any_first<A, R>(
+    fn call_any_first<R>(
         &'vm self,
         cx: &mut task::Context<'_>,
-        args: impl IntoIterator<Item = A>,
+        args: &[Variants<'_>],
     ) -> Poll<Result<R>>
     where
-        A: Pushable<'vm>,
         R: for<'value> Getable<'vm, 'value> + VmType,
     {
         let vm = self.value.vm();
         let mut context = vm.current_context();
         context.push(self.value.get_variant());
 
-        let mut arg_count = R::EXTRA_ARGS;
         for arg in args {
-            arg_count += 1;
-            arg.vm_push(&mut context)?;
+            context.push(arg.clone());
         }
         for _ in 0..R::EXTRA_ARGS {
             0.vm_push(&mut context).unwrap();
         }
+        let arg_count = args.len() as VmIndex + R::EXTRA_ARGS;
         let context = ready!(vm.call_function(cx, context.into_owned(), arg_count))?;
         let mut context = context.unwrap();
         let result = {
             let value = context.stack.last().unwrap();
-            Ok(R::from_value(vm, value))
+            crate::api::try_from_value(vm, value)
         };
         context.stack.pop();
         result.into()
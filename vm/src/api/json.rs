@@ -1,12 +1,16 @@
 extern crate serde_json;
 
-use std::{borrow::Borrow, fmt, result::Result as StdResult};
+use std::{borrow::Borrow, fmt, result::Result as StdResult, task::Poll};
 
-use crate::base::types::ArcType;
+use crate::base::{
+    resolve,
+    types::{ctor_args, ArcType, BuiltinType, NullInterner, Type, TypeExt},
+};
 
 use crate::{
-    api::{Getable, OpaqueValue, ValueRef, VmInt, VmType},
-    thread::{ActiveThread, RootedThread, Thread, ThreadInternal},
+    api::{Getable, OpaqueValue, Pushable, ValueRef, VmInt, VmType},
+    thread::{ActiveThread, RootedThread, RootedValue, Thread, ThreadInternal},
+    types::{VmIndex, VmTag},
     ExternModule, Result, Variants,
 };
 
@@ -38,6 +42,17 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
         unsafe { Ok(String::from_utf8_unchecked(output)) }
     }
 
+    fn deserialize_stream(input: &str) -> StdResult<Vec<serde_json::Value>, String> {
+        serde_json::Deserializer::from_str(input)
+            .into_iter()
+            .collect::<StdResult<_, _>>()
+            .map_err(|err| err.to_string())
+    }
+
+    fn pointer(pointer: &str, mut value: serde_json::Value) -> Option<serde_json::Value> {
+        value.pointer_mut(pointer).map(serde_json::Value::take)
+    }
+
     ExternModule::new(
         vm,
         record! {
@@ -46,6 +61,12 @@ pub fn load(vm: &Thread) -> Result<ExternModule> {
                 "std.json.prim.deserialize",
                 deserialize
             ),
+            deserialize_stream => primitive!(
+                1,
+                "std.json.prim.deserialize_stream",
+                deserialize_stream
+            ),
+            pointer => primitive!(2, "std.json.prim.pointer", pointer),
             serialize => primitive!(
                 1,
                 "std.json.prim.serialize",
@@ -143,6 +164,85 @@ impl<'vm, 'value> crate::api::Getable<'vm, 'value> for serde_json::Value {
     }
 }
 
+/// Marshals any `Serialize` or `Deserialize` type by converting it through a `std.json.Value`.
+///
+/// Unlike `Ser` and `De` the type does not need to implement `VmType` so `Json` can be used to
+/// quickly expose a Rust type to gluon before writing `Pushable` and `Getable` implementations
+/// for it. On the gluon side the value has the type `std.json.Value`.
+///
+/// ```
+/// use serde_derive::{Deserialize, Serialize};
+///
+/// use gluon::{new_vm, vm::api::{json::Json, FunctionRef}, ThreadExt};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// # if ::std::env::var("GLUON_PATH").is_err() {
+/// #     ::std::env::set_var("GLUON_PATH", "..");
+/// # }
+///
+/// let vm = new_vm();
+/// vm.run_expr::<()>("", "let _ = import! std.json in ()").unwrap();
+/// let (mut id, _) = vm
+///     .run_expr::<FunctionRef<fn(Json<Point>) -> Json<Point>>>(
+///         "",
+///         "let { Value } = import! std.json in let f x : Value -> Value = x in f",
+///     )
+///     .unwrap();
+/// assert_eq!(id.call(Json(Point { x: 1, y: 2 })).unwrap().0, Point { x: 1, y: 2 });
+/// ```
+pub struct Json<T>(pub T);
+
+impl<T> VmType for Json<T> {
+    type Type = serde_json::Value;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        serde_json::Value::make_type(vm)
+    }
+}
+
+impl<'vm, T> crate::api::Pushable<'vm> for Json<T>
+where
+    T: crate::serde::Serialize,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        serde_json::to_value(self.0)
+            .map_err(|err| crate::Error::Message(err.to_string()))?
+            .vm_push(context)
+    }
+}
+
+impl<'vm, 'value, T> Getable<'vm, 'value> for Json<T>
+where
+    T: crate::serde::de::DeserializeOwned + 'value,
+{
+    type Proxy = Option<T>;
+
+    fn to_proxy(vm: &'vm Thread, value: Variants<'value>) -> Result<Self::Proxy> {
+        serde_json::from_value(serde_json::Value::from_value(vm, value))
+            .map(Some)
+            .map_err(|err| crate::Error::Message(err.to_string()))
+    }
+
+    fn from_proxy(_vm: &'vm Thread, proxy: &'value mut Self::Proxy) -> Self {
+        Json(proxy.take().expect("Json proxies are only used once"))
+    }
+
+    /// Panics if the value can't be deserialized into `T`. Arguments of rust functions and the
+    /// values returned by `run_expr`, `get_global` and `FunctionRef::call` are converted with
+    /// `to_proxy` instead so they return an error.
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        match Self::to_proxy(vm, value) {
+            Ok(proxy) => Json(proxy.expect("Proxy")),
+            Err(err) => panic!("Unable to deserialize a `Json` value: {}", err),
+        }
+    }
+}
+
 #[derive(Pushable, Getable, SerializeState)]
 #[serde(serialize_state = "Thread")]
 #[gluon(gluon_vm)]
@@ -368,3 +468,229 @@ impl<'de> de::DeserializeState<'de, ActiveThread<'de>> for JsonValue {
         deserializer.deserialize_any(ValueVisitor(thread))
     }
 }
+
+/// Converts `value` of the type `typ` to JSON, for hosts which exchange dynamically typed values
+/// with gluon.
+///
+/// Records become objects, `Bool` becomes a boolean, `None` and the empty record become `null`
+/// and `Some x` becomes `x`. Other variants become the name of the constructor or, if the
+/// constructor has arguments, an object mapping the name to an array of the arguments.
+pub fn to_json(thread: &Thread, value: Variants, typ: &ArcType) -> Result<serde_json::Value> {
+    use serde_json::Value::*;
+
+    let env = thread.get_env();
+    let typ = resolve::remove_aliases_cow(&env, &mut NullInterner, typ);
+    Ok(match (value.as_ref(), &**typ) {
+        (_, Type::Forall(_, typ)) => return to_json(thread, value, typ),
+        (ValueRef::Byte(b), _) => Number(b.into()),
+        (ValueRef::Int(i), Type::Builtin(BuiltinType::Char)) => String(
+            std::char::from_u32(i as u32)
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        (ValueRef::Int(i), _) => Number(i.into()),
+        (ValueRef::Float(f), _) => serde_json::Number::from_f64(f).map_or(Null, Number),
+        (ValueRef::String(s), _) => String(s.to_string()),
+        (ValueRef::Array(array), Type::App(_, args)) if args.len() == 1 => Array(
+            array
+                .iter()
+                .map(|elem| to_json(thread, elem, &args[0]))
+                .collect::<Result<_>>()?,
+        ),
+        (ValueRef::Data(data), Type::Record(row)) => {
+            if data.len() == 0 {
+                Null
+            } else {
+                Object(
+                    row.row_iter()
+                        .enumerate()
+                        .map(|(i, field)| {
+                            let value = data.get_variant(i).expect("Record field");
+                            Ok((
+                                field.name.declared_name().to_string(),
+                                to_json(thread, value, &field.typ)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )
+            }
+        }
+        (ValueRef::Data(data), Type::Variant(row)) => {
+            let ctor = row
+                .row_iter()
+                .nth(data.tag() as usize)
+                .expect("Variant tag is out of bounds");
+            let mut args = ctor_args(&ctor.typ)
+                .enumerate()
+                .map(|(i, typ)| to_json(thread, data.get_variant(i).expect("Variant field"), typ))
+                .collect::<Result<Vec<_>>>()?;
+            match (variant_kind(row), ctor.name.declared_name()) {
+                (VariantKind::Bool, name) => Bool(name == "True"),
+                (VariantKind::Option, "None") => Null,
+                (VariantKind::Option, _) => args.pop().expect("Some argument"),
+                (VariantKind::Other, name) if args.is_empty() => String(name.to_string()),
+                (VariantKind::Other, name) => {
+                    Object(Some((name.to_string(), Array(args))).into_iter().collect())
+                }
+            }
+        }
+        _ => return Err(format!("Values of type `{}` can't be converted to JSON", typ).into()),
+    })
+}
+
+/// Pushes `json` as a value of the type `typ`, using the representation described in `to_json`.
+/// Integers are accepted in place of floats.
+pub fn push_json<'vm>(
+    context: &mut ActiveThread<'vm>,
+    json: &serde_json::Value,
+    typ: &ArcType,
+) -> Result<()> {
+    use serde_json::Value::*;
+
+    let thread = context.thread();
+    let mismatch = || -> crate::Error {
+        format!("Expected a value of type `{}` but found `{}`", typ, json).into()
+    };
+
+    let env = thread.get_env();
+    let typ = resolve::remove_aliases_cow(&env, &mut NullInterner, typ);
+    match (json, &**typ) {
+        (_, Type::Forall(_, typ)) => push_json(context, json, typ),
+        (Number(n), Type::Builtin(BuiltinType::Int)) => {
+            n.as_i64().ok_or_else(mismatch)?.vm_push(context)
+        }
+        (Number(n), Type::Builtin(BuiltinType::Byte)) => {
+            let byte = n.as_u64().filter(|&b| b <= u8::MAX as u64);
+            (byte.ok_or_else(mismatch)? as u8).vm_push(context)
+        }
+        (Number(n), Type::Builtin(BuiltinType::Float)) => {
+            n.as_f64().ok_or_else(mismatch)?.vm_push(context)
+        }
+        (String(s), Type::Builtin(BuiltinType::String)) => s.vm_push(context),
+        (String(s), Type::Builtin(BuiltinType::Char)) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c.vm_push(context),
+                _ => Err(mismatch()),
+            }
+        }
+        (Array(elems), Type::App(f, args))
+            if args.len() == 1 && **f == Type::Builtin(BuiltinType::Array) =>
+        {
+            for elem in elems {
+                push_json(context, elem, &args[0])?;
+            }
+            context.context().push_new_array(elems.len())?;
+            Ok(())
+        }
+        (Null, Type::Record(row)) if row.row_iter().next().is_none() => {
+            context.context().push_new_record(0, &[])?;
+            Ok(())
+        }
+        (Object(object), Type::Record(row)) => {
+            let mut names = Vec::new();
+            for field in row.row_iter() {
+                let name = field.name.declared_name();
+                let value = object
+                    .get(name)
+                    .ok_or_else(|| format!("Missing the field `{}` of `{}`", name, typ))?;
+                push_json(context, value, &field.typ)?;
+                names.push(thread.global_env().intern(name)?);
+            }
+            context.context().push_new_record(names.len(), &names)?;
+            Ok(())
+        }
+        (_, Type::Variant(row)) => {
+            let (name, args) = match (variant_kind(row), json) {
+                (VariantKind::Bool, Bool(b)) => (if *b { "True" } else { "False" }, &[][..]),
+                (VariantKind::Option, Null) => ("None", &[][..]),
+                (VariantKind::Option, value) => ("Some", std::slice::from_ref(value)),
+                (VariantKind::Other, String(name)) => (&name[..], &[][..]),
+                (VariantKind::Other, Object(object)) if object.len() == 1 => {
+                    match object.iter().next() {
+                        Some((name, Array(args))) => (&name[..], &args[..]),
+                        _ => return Err(mismatch()),
+                    }
+                }
+                _ => return Err(mismatch()),
+            };
+            let (tag, ctor) = row
+                .row_iter()
+                .enumerate()
+                .find(|(_, ctor)| ctor.name.declared_name() == name)
+                .ok_or_else(|| format!("`{}` is not a constructor of `{}`", name, typ))?;
+            let mut arity = 0;
+            for (arg, arg_type) in args.iter().zip(ctor_args(&ctor.typ)) {
+                push_json(context, arg, arg_type)?;
+                arity += 1;
+            }
+            if arity != args.len() || ctor_args(&ctor.typ).count() != args.len() {
+                return Err(mismatch());
+            }
+            context.context().push_new_data(tag as VmTag, arity)?;
+            Ok(())
+        }
+        _ => Err(mismatch()),
+    }
+}
+
+/// Calls the function `function` of the type `typ` with `args` converted by `push_json`,
+/// returning the result converted by `to_json`
+pub fn call_json(
+    function: &RootedValue<RootedThread>,
+    typ: &ArcType,
+    args: &[serde_json::Value],
+) -> Result<serde_json::Value> {
+    let vm = function.vm();
+    let env = vm.get_env();
+    let mut arg_types = Vec::with_capacity(args.len());
+    let mut return_type = typ.clone();
+    for _ in args {
+        let function_type = resolve::remove_aliases(&env, &mut NullInterner, return_type.clone());
+        match function_type.as_function() {
+            Some((arg, ret)) => {
+                arg_types.push(arg.clone());
+                return_type = ret.clone();
+            }
+            None => {
+                return Err(
+                    format!("`{}` can't be called with {} arguments", typ, args.len()).into(),
+                )
+            }
+        }
+    }
+
+    crate::api::function::block_on_sync(futures::future::lazy(|cx| {
+        let mut context = vm.current_context();
+        context.push(function.get_variant());
+        for (arg, arg_type) in args.iter().zip(&arg_types) {
+            push_json(&mut context, arg, arg_type)?;
+        }
+        let context = match vm.call_function(cx, context.into_owned(), args.len() as VmIndex) {
+            Poll::Ready(context) => context?,
+            Poll::Pending => return Err(crate::Error::Message("Unexpected async".into())),
+        };
+        let mut context = context.expect("Context");
+        let result = to_json(vm, context.stack.last().unwrap(), &return_type);
+        context.stack.pop();
+        result
+    }))
+}
+
+enum VariantKind {
+    Bool,
+    Option,
+    Other,
+}
+
+fn variant_kind(row: &ArcType) -> VariantKind {
+    let names = row
+        .row_iter()
+        .map(|ctor| ctor.name.declared_name())
+        .collect::<Vec<_>>();
+    match &names[..] {
+        ["False", "True"] => VariantKind::Bool,
+        ["None", "Some"] => VariantKind::Option,
+        _ => VariantKind::Other,
+    }
+}
@@ -60,6 +60,10 @@ macro_rules! closure_wrapper {
 
 /// Creates a `GluonFunction` from a function implementing `VMFunction`
 ///
+/// Prefixing the function with `async fn` (`primitive!(1, async fn fetch)`) lets it return a
+/// `Future` which the virtual machine runs before resuming the gluon code which called it. For
+/// functions which capture state see `closure` and `async_closure`.
+///
 /// ```rust
 /// #[macro_use]
 /// extern crate gluon_vm;
@@ -1,16 +1,22 @@
 //! The marshalling api
 use std::{
     any::Any,
-    borrow::Borrow,
+    backtrace::{Backtrace, BacktraceStatus},
+    borrow::{Borrow, Cow},
     cell::Ref,
     cmp::Ordering,
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    error::Error as StdError,
     ffi::{OsStr, OsString},
     fmt,
+    hash::{BuildHasher, Hash},
     marker::PhantomData,
     ops::Deref,
     path::{Path, PathBuf},
     result::Result as StdResult,
+    sync::Arc,
+    time::Duration,
 };
 
 use crate::base::{
@@ -34,6 +40,7 @@ pub use self::{
     function::*,
     opaque::{Opaque, OpaqueRef, OpaqueValue},
     record::Record,
+    userdata::UserdataMethods,
 };
 pub use crate::{thread::ActiveThread, value::Cloner, value::Userdata};
 
@@ -77,6 +84,7 @@ pub mod json;
 pub mod ser;
 #[cfg(feature = "serde")]
 pub mod typ;
+pub mod userdata;
 
 #[derive(Clone, Debug)]
 pub enum ValueRef<'a> {
@@ -584,7 +592,17 @@ where
     t.vm_push(context)?;
     let thread = context.thread();
     let value = context.pop();
-    Ok(U::from_value(thread, (*value).clone()))
+    try_from_value(thread, (*value).clone())
+}
+
+/// Converts `value` through the proxy of `T`. Unlike `Getable::from_value` this returns an error
+/// for conversions which can fail on some values, such as `json::Json`.
+pub fn try_from_value<'vm, T>(vm: &'vm Thread, value: Variants) -> Result<T>
+where
+    T: for<'value> Getable<'vm, 'value>,
+{
+    let mut proxy = T::to_proxy(vm, value)?;
+    Ok(T::from_proxy(vm, &mut proxy))
 }
 
 impl<'vm, T: vm::Userdata> Pushable<'vm> for T {
@@ -740,6 +758,7 @@ where
     }
 }
 
+/// Borrows the string directly from the gc heap without copying it
 impl<'vm, 'value> Getable<'vm, 'value> for &'value str {
     impl_getable_simple!();
 
@@ -1026,6 +1045,22 @@ impl<'vm> Pushable<'vm> for String {
     }
 }
 
+impl<'s> VmType for Cow<'s, str> {
+    type Type = String;
+}
+impl<'vm, 's> Pushable<'vm> for Cow<'s, str> {
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        <&str as Pushable>::vm_push(&self, context)
+    }
+}
+impl<'vm, 'value> Getable<'vm, 'value> for Cow<'value, str> {
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        Cow::Borrowed(<&'value str>::from_value(vm, value))
+    }
+}
+
 impl VmType for char {
     type Type = Self;
 }
@@ -1128,6 +1163,41 @@ impl<'vm> Pushable<'vm> for OsString {
     }
 }
 
+/// `Duration` is marshalled as `std.duration.Duration`
+impl VmType for Duration {
+    type Type = crate::duration::Duration;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        crate::duration::Duration::make_type(vm)
+    }
+}
+impl<'vm> Pushable<'vm> for Duration {
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        crate::duration::Duration::from(self).vm_push(context)
+    }
+}
+impl<'vm, 'value> Getable<'vm, 'value> for Duration {
+    type Proxy = Option<Duration>;
+
+    fn to_proxy(vm: &'vm Thread, value: Variants<'value>) -> Result<Self::Proxy> {
+        Duration::try_from(crate::duration::Duration::from_value(vm, value)).map(Some)
+    }
+
+    fn from_proxy(_vm: &'vm Thread, proxy: &'value mut Self::Proxy) -> Self {
+        proxy.take().expect("Duration proxies are only used once")
+    }
+
+    /// Panics if the duration is negative or too large for `Duration`. Arguments of rust
+    /// functions and the values returned by `run_expr`, `get_global` and `FunctionRef::call` are
+    /// converted with `to_proxy` instead so they return an error.
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        match Self::to_proxy(vm, value) {
+            Ok(proxy) => proxy.expect("Proxy"),
+            Err(err) => panic!("Unable to convert a `Duration`: {}", err),
+        }
+    }
+}
+
 impl<'s, T: VmType> VmType for Ref<'s, T> {
     type Type = T::Type;
     fn make_type(vm: &Thread) -> ArcType {
@@ -1157,6 +1227,7 @@ where
         Ok(())
     }
 }
+/// Borrows the array directly from the gc heap without copying it
 impl<'vm, 'value, T: Copy + ArrayRepr> Getable<'vm, 'value> for &'value [T] {
     impl_getable_simple!();
 
@@ -1168,6 +1239,26 @@ impl<'vm, 'value, T: Copy + ArrayRepr> Getable<'vm, 'value> for &'value [T] {
     }
 }
 
+impl<'s> VmType for Cow<'s, [u8]> {
+    type Type = Vec<u8>;
+
+    fn make_type(thread: &Thread) -> ArcType {
+        <Vec<u8> as VmType>::make_type(thread)
+    }
+}
+impl<'vm, 's> Pushable<'vm> for Cow<'s, [u8]> {
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        <&[u8] as Pushable>::vm_push(&self, context)
+    }
+}
+impl<'vm, 'value> Getable<'vm, 'value> for Cow<'value, [u8]> {
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        Cow::Borrowed(<&'value [u8]>::from_value(vm, value))
+    }
+}
+
 impl<T> VmType for [T]
 where
     T: VmType,
@@ -1212,6 +1303,73 @@ where
     }
 }
 
+impl<T> VmType for VecDeque<T>
+where
+    T: VmType,
+    T::Type: Sized,
+{
+    type Type = VecDeque<T::Type>;
+
+    fn make_type(thread: &Thread) -> ArcType {
+        <Vec<T> as VmType>::make_type(thread)
+    }
+}
+
+impl<'vm, T> Pushable<'vm> for VecDeque<T>
+where
+    T: Pushable<'vm>,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        Collect::new(self).vm_push(context)
+    }
+}
+
+impl<'vm, 'value, T> Getable<'vm, 'value> for VecDeque<T>
+where
+    T: Getable<'vm, 'value>,
+{
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        Collect::<GetableIter<T>>::from_value(vm, value).collect()
+    }
+}
+
+/// Sets are represented as arrays
+impl<T, S> VmType for HashSet<T, S>
+where
+    T: VmType,
+    T::Type: Sized,
+    S: 'static,
+{
+    type Type = HashSet<T::Type, S>;
+
+    fn make_type(thread: &Thread) -> ArcType {
+        <Vec<T> as VmType>::make_type(thread)
+    }
+}
+
+impl<'vm, T, S> Pushable<'vm> for HashSet<T, S>
+where
+    T: Pushable<'vm>,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        Collect::new(self).vm_push(context)
+    }
+}
+
+impl<'vm, 'value, T, S> Getable<'vm, 'value> for HashSet<T, S>
+where
+    T: Getable<'vm, 'value> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        Collect::<GetableIter<T>>::from_value(vm, value).collect()
+    }
+}
+
 impl<'s, T: VmType> VmType for *const T {
     type Type = T::Type;
     fn make_type(vm: &Thread) -> ArcType {
@@ -1248,6 +1406,33 @@ impl<'vm, 'value, T: Getable<'vm, 'value>> Getable<'vm, 'value> for Box<T> {
     }
 }
 
+impl<T: ?Sized + VmType> VmType for Arc<T> {
+    type Type = T::Type;
+    fn make_type(vm: &Thread) -> ArcType {
+        T::make_type(vm)
+    }
+}
+
+/// Pushes the value inside the `Arc`, cloning it if it is shared
+impl<'vm, T> Pushable<'vm> for Arc<T>
+where
+    T: Pushable<'vm> + Clone,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        Arc::try_unwrap(self)
+            .unwrap_or_else(|arc| (*arc).clone())
+            .vm_push(context)
+    }
+}
+
+impl<'vm, 'value, T: Getable<'vm, 'value>> Getable<'vm, 'value> for Arc<T> {
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Arc<T> {
+        Arc::new(T::from_value(vm, value))
+    }
+}
+
 impl<K, V> VmType for BTreeMap<K, V>
 where
     K: VmType,
@@ -1293,6 +1478,49 @@ where
     }
 }
 
+impl<K, V, S> VmType for HashMap<K, V, S>
+where
+    K: VmType,
+    K::Type: Sized,
+    V: VmType,
+    V::Type: Sized,
+    S: 'static,
+{
+    type Type = HashMap<K::Type, V::Type, S>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        <BTreeMap<K, V> as VmType>::make_type(vm)
+    }
+}
+
+impl<'vm, K, V, S> Pushable<'vm> for HashMap<K, V, S>
+where
+    K: Borrow<str> + VmType,
+    K::Type: Sized,
+    V: for<'vm2> Pushable<'vm2> + VmType,
+    V::Type: Sized,
+    S: 'static,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        to_gluon_map(self, context)
+    }
+}
+
+impl<'vm, 'value, K, V, S> Getable<'vm, 'value> for HashMap<K, V, S>
+where
+    K: Getable<'vm, 'value> + Eq + Hash,
+    V: Getable<'vm, 'value>,
+    S: BuildHasher + Default,
+{
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        let mut map = HashMap::default();
+        from_gluon_map(&mut map, vm, value);
+        map
+    }
+}
+
 fn to_gluon_map<'vm, K, V>(
     map_iter: impl IntoIterator<Item = (K, V)>,
     context: &mut ActiveThread<'vm>,
@@ -1322,7 +1550,7 @@ where
 fn from_gluon_map<'vm2, 'value2, M, K2, V2>(map: &mut M, vm: &'vm2 Thread, value: Variants<'value2>)
 where
     M: Extend<(K2, V2)>,
-    K2: Getable<'vm2, 'value2> + Ord,
+    K2: Getable<'vm2, 'value2>,
     V2: Getable<'vm2, 'value2>,
 {
     match value.as_ref() {
@@ -1527,6 +1755,77 @@ impl<T, E> RuntimeResult<T, E> {
     }
 }
 
+/// An error returned from Rust along with the chain of errors which caused it. Marshalled as the
+/// record `{ message : String, causes : Array String, backtrace : Option String }` so that gluon
+/// code can inspect it.
+///
+/// Any `std::error::Error` converts into `ErrorInfo`, so a primitive which uses `?` can return
+/// `Result<T, ErrorInfo>` to hand the error to gluon, or `RuntimeResult<T, ErrorInfo>` to panic
+/// with a message which includes every cause.
+#[derive(Clone, Debug, PartialEq, Eq, Pushable, Getable, VmType)]
+#[gluon(gluon_vm)]
+pub struct ErrorInfo {
+    pub message: String,
+    /// The messages of `Error::source`, starting with the direct cause of `message` and ending
+    /// with the innermost cause
+    pub causes: Vec<String>,
+    /// The backtrace of the conversion into `ErrorInfo` if backtraces are enabled through
+    /// `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+    pub backtrace: Option<String>,
+}
+
+impl ErrorInfo {
+    pub fn new(err: &(dyn StdError + 'static)) -> Self {
+        let mut causes = Vec::new();
+        let mut source = err.source();
+        while let Some(err) = source {
+            causes.push(err.to_string());
+            source = err.source();
+        }
+        let backtrace = Backtrace::capture();
+        ErrorInfo {
+            message: err.to_string(),
+            causes,
+            backtrace: match backtrace.status() {
+                BacktraceStatus::Captured => Some(backtrace.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl<E> From<E> for ErrorInfo
+where
+    E: StdError + 'static,
+{
+    fn from(err: E) -> Self {
+        ErrorInfo::new(&err)
+    }
+}
+
+impl From<ErrorInfo> for Error {
+    fn from(err: ErrorInfo) -> Self {
+        Error::Message(format!("{:#}", err))
+    }
+}
+
+/// Writes the message followed by each cause. The alternate form (`{:#}`) also writes the
+/// backtrace if one was captured.
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for cause in &self.causes {
+            write!(f, ": {}", cause)?;
+        }
+        if f.alternate() {
+            if let Some(backtrace) = &self.backtrace {
+                write!(f, "\nBacktrace:\n{}", backtrace)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<T> VmType for IO<T>
 where
     T: VmType,
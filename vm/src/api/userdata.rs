@@ -0,0 +1,239 @@
+//! Exposing the methods of a `Userdata` type to gluon code.
+use std::{cmp::Ordering, fmt, marker::PhantomData, sync::Arc};
+
+use crate::base::{
+    metadata::Metadata,
+    symbol::Symbol,
+    types::{Alias, ArcType, Field, Type},
+};
+use crate::{
+    api::{Pushable, VmType},
+    thread::{RootedThread, RootedValue, Thread, ThreadInternal},
+    value::Userdata,
+    ExternModule, Result,
+};
+
+type Marshal = Box<dyn FnOnce(&Thread) -> Result<(ArcType, RootedValue<RootedThread>, Metadata)>>;
+
+/// The methods and instances of a `Userdata` type which are exposed as a module to gluon code.
+/// Filled in by `Userdata::methods`.
+///
+/// ```rust,ignore
+/// impl Userdata for Counter {
+///     fn methods(methods: &mut UserdataMethods<Self>) {
+///         methods
+///             .method("get", primitive!(1, "counter.get", |c: &Counter| c.0))
+///             .eq()
+///             .show();
+///     }
+/// }
+/// ```
+pub struct UserdataMethods<T> {
+    fields: Vec<(String, Marshal)>,
+    dependencies: Vec<&'static str>,
+    /// Marks the `Eq` instances of `eq` and `ord.eq` as the same so that they do not cause an
+    /// ambiguity when resolving implicits
+    eq_definition: Symbol,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T> UserdataMethods<T>
+where
+    T: Userdata + VmType,
+{
+    /// Collects the methods which `T` exposes
+    pub fn of() -> Self {
+        let mut methods = UserdataMethods {
+            fields: Vec::new(),
+            dependencies: Vec::new(),
+            eq_definition: Symbol::from("eq"),
+            _marker: PhantomData,
+        };
+        T::methods(&mut methods);
+        methods
+    }
+
+    /// Adds `function` as the field `name` of the module
+    pub fn method<F>(&mut self, name: &str, function: F) -> &mut Self
+    where
+        F: VmType + for<'vm> Pushable<'vm> + 'static,
+    {
+        self.fields.push((
+            name.into(),
+            Box::new(move |vm| {
+                Ok((
+                    F::make_forall_type(vm),
+                    function.marshal(vm)?,
+                    Metadata::default(),
+                ))
+            }),
+        ));
+        self
+    }
+
+    /// Adds an `Eq` instance, named `eq`, which uses the `PartialEq` implementation of `T`
+    pub fn eq(&mut self) -> &mut Self
+    where
+        T: PartialEq,
+    {
+        self.dependencies.push("std.cmp");
+        let definition = self.eq_definition.clone();
+        self.fields.push((
+            "eq".into(),
+            Box::new(move |vm| {
+                let (typ, value) = eq_instance::<T>(vm)?;
+                Ok((typ, value, eq_metadata(definition)))
+            }),
+        ));
+        self
+    }
+
+    /// Adds an `Ord` instance, named `ord`, which uses the `Ord` implementation of `T`
+    pub fn ord(&mut self) -> &mut Self
+    where
+        T: Ord,
+    {
+        self.dependencies.push("std.cmp");
+        let definition = self.eq_definition.clone();
+        self.fields.push((
+            "ord".into(),
+            Box::new(move |vm| {
+                let (_, eq) = eq_instance::<T>(vm)?;
+                let compare = primitive!(impl fn(_, _) -> _, "userdata.compare", userdata_compare::<T>,
+                    [T] [T: Userdata + VmType + Ord]
+                );
+                let typ = instance_type::<T>(vm, "std.cmp.Ord")?;
+                let value = new_record(
+                    vm,
+                    vec![("eq", eq), ("compare", compare.marshal(vm)?)],
+                )?;
+                let mut metadata = Metadata::default();
+                metadata
+                    .module
+                    .insert("eq".into(), Arc::new(eq_metadata(definition)));
+                Ok((typ, value, metadata))
+            }),
+        ));
+        self
+    }
+
+    /// Adds a `Show` instance, named `show`, which uses the `Display` implementation of `T`
+    pub fn show(&mut self) -> &mut Self
+    where
+        T: fmt::Display,
+    {
+        self.dependencies.push("std.show");
+        self.fields.push((
+            "show".into(),
+            Box::new(|vm| {
+                let show = primitive!(impl fn(_) -> _, "userdata.show", userdata_show::<T>,
+                    [T] [T: Userdata + VmType + fmt::Display]
+                );
+                let typ = instance_type::<T>(vm, "std.show.Show")?;
+                let value = new_record(vm, vec![("show", show.marshal(vm)?)])?;
+                Ok((typ, value, Metadata::default()))
+            }),
+        ));
+        self
+    }
+
+    /// The modules which must be loaded before the module can be created
+    pub fn dependencies(&self) -> Vec<String> {
+        let mut dependencies: Vec<_> = self.dependencies.iter().map(|s| s.to_string()).collect();
+        dependencies.sort();
+        dependencies.dedup();
+        dependencies
+    }
+
+    /// Creates a module which contains the type of `T` along with every method and instance.
+    /// `T` must have been registered with `Thread::register_type` beforehand.
+    pub fn build(self, vm: &Thread) -> Result<ExternModule> {
+        let mut type_fields = Vec::new();
+        if let Type::Alias(alias) = &*T::make_type(vm) {
+            type_fields.push(Field::new(
+                Symbol::from(alias.name.declared_name()),
+                Alias::from(alias.clone()),
+            ));
+        }
+
+        let mut fields = Vec::with_capacity(self.fields.len());
+        let mut values = Vec::with_capacity(self.fields.len());
+        let mut metadata = Metadata::default();
+        for (name, marshal) in self.fields {
+            let (typ, value, field_metadata) = marshal(vm)?;
+            fields.push(Field::new(Symbol::from(&name[..]), typ));
+            if field_metadata.has_data() {
+                metadata
+                    .module
+                    .insert(name.clone(), Arc::new(field_metadata));
+            }
+            values.push((name, value));
+        }
+
+        let value = new_record(
+            vm,
+            values
+                .iter()
+                .map(|(name, value)| (&name[..], value.clone()))
+                .collect(),
+        )?;
+        Ok(ExternModule {
+            metadata,
+            value,
+            typ: vm.global_env().type_cache().record(type_fields, fields),
+        })
+    }
+}
+
+fn userdata_eq<T: PartialEq>(l: &T, r: &T) -> bool {
+    l == r
+}
+
+fn userdata_compare<T: Ord>(l: &T, r: &T) -> Ordering {
+    l.cmp(r)
+}
+
+fn userdata_show<T: fmt::Display>(x: &T) -> String {
+    x.to_string()
+}
+
+fn eq_instance<T>(vm: &Thread) -> Result<(ArcType, RootedValue<RootedThread>)>
+where
+    T: Userdata + VmType + PartialEq,
+{
+    let eq = primitive!(impl fn(_, _) -> _, "userdata.==", userdata_eq::<T>,
+        [T] [T: Userdata + VmType + PartialEq]
+    );
+    let typ = instance_type::<T>(vm, "std.cmp.Eq")?;
+    Ok((typ, new_record(vm, vec![("==", eq.marshal(vm)?)])?))
+}
+
+fn eq_metadata(definition: Symbol) -> Metadata {
+    Metadata {
+        definition: Some(definition),
+        ..Metadata::default()
+    }
+}
+
+/// Returns the type `$class T`
+fn instance_type<T: VmType>(vm: &Thread, class: &str) -> Result<ArcType> {
+    let class = vm.find_type_info(class)?.into_type();
+    Ok(Type::app(class, collect![T::make_type(vm)]))
+}
+
+fn new_record(
+    vm: &Thread,
+    fields: Vec<(&str, RootedValue<RootedThread>)>,
+) -> Result<RootedValue<RootedThread>> {
+    let mut context = vm.current_context();
+    let mut field_names = Vec::with_capacity(fields.len());
+    for (name, value) in &fields {
+        field_names.push(vm.global_env().intern(name)?);
+        value.clone().vm_push(&mut context)?;
+    }
+    context
+        .context()
+        .push_new_record(fields.len(), &field_names)?;
+    let record = context.pop();
+    Ok(vm.root_value(record.clone()))
+}
@@ -150,6 +150,15 @@ pub type ChannelRecord<S, R> = record_type!(sender => S, receiver => R);
 /// FIXME The dummy `a` argument should not be needed to ensure that the channel can only be used
 /// with a single type
 fn channel(WithVM { vm, .. }: WithVM<Generic<A>>) -> ChannelRecord<Sender<A>, Receiver<A>> {
+    make_channel(vm)
+}
+
+/// Like `channel` but without a value of the element type, for code that is generic over it
+fn new_channel(WithVM { vm, .. }: WithVM<()>) -> ChannelRecord<Sender<A>, Receiver<A>> {
+    make_channel(vm)
+}
+
+fn make_channel(vm: &Thread) -> ChannelRecord<Sender<A>, Receiver<A>> {
     let sender = Sender {
         thread: unsafe { GcPtr::from_raw(vm) },
         queue: Arc::new(Mutex::new(VecDeque::new())),
@@ -396,6 +405,7 @@ pub fn load_channel<'vm>(vm: &'vm Thread) -> VmResult<ExternModule> {
             type Sender a => Sender<A>,
             type Receiver a => Sender<A>,
             channel => primitive!(1, std::channel::channel),
+            new_channel => primitive!(1, std::channel::new_channel),
             recv => primitive!(1, std::channel::recv),
             send => primitive!(2, std::channel::send),
         },
@@ -3,7 +3,8 @@ use std::ops::{Deref, DerefMut};
 use crate::base::{
     ast::{DisplayEnv, Typed, TypedIdent},
     kind::{ArcKind, KindEnv},
-    pos::Line,
+    origin::OriginMap,
+    pos::{BytePos, Line},
     resolve,
     scoped_map::ScopedMap,
     source::{FileMap, Source},
@@ -195,6 +196,8 @@ struct FunctionEnv {
     free_vars: Vec<(Symbol, ArcType)>,
     /// The line where instructions are currently being emitted
     current_line: Line,
+    /// The line counted by the last `Hit` instruction, reset where the code can be jumped to
+    covered_line: Option<Line>,
     emit_debug_info: bool,
     function: CompiledFunction,
 }
@@ -280,6 +283,7 @@ impl FunctionEnv {
             stack_size: 0,
             function: CompiledFunction::new(args, id, typ, source_name),
             current_line: Line::from(0),
+            covered_line: None,
             emit_debug_info,
         }
     }
@@ -451,7 +455,9 @@ pub struct Compiler<'a> {
     stack_types: ScopedMap<Symbol, Alias<Symbol, ArcType>>,
     source: &'a FileMap,
     source_name: String,
+    origins: Option<&'a OriginMap>,
     emit_debug_info: bool,
+    coverage: bool,
     empty_symbol: Symbol,
     hole: ArcType,
 }
@@ -497,11 +503,31 @@ impl<'a> Compiler<'a> {
             stack_types: ScopedMap::new(),
             source: source,
             source_name: source_name,
+            origins: None,
             emit_debug_info,
+            coverage: false,
             hole: Type::hole(),
         }
     }
 
+    /// Uses `origins` to attribute desugared code to the lines the user wrote in the debug info
+    pub fn set_origins(&mut self, origins: &'a OriginMap) {
+        self.origins = Some(origins);
+    }
+
+    /// Instruments the compiled code to count how often each line is executed, see
+    /// `GlobalVmState::coverage`
+    pub fn set_coverage(&mut self, coverage: bool) {
+        self.coverage = coverage;
+    }
+
+    fn line_number_at_byte(&self, pos: BytePos) -> Option<Line> {
+        let pos = self
+            .origins
+            .map_or(pos, |origins| origins.user_position(pos));
+        self.source.line_number_at_byte(pos)
+    }
+
     fn intern(&mut self, s: &str) -> Result<InternedStr> {
         self.vm.intern(s)
     }
@@ -588,7 +614,7 @@ impl<'a> Compiler<'a> {
         env.start_function(self, 0, id, typ);
         info!("COMPILING: {}", expr);
         self.compile(&expr, &mut env, true)?;
-        let current_line = self.source.line_number_at_byte(expr.span().end());
+        let current_line = self.line_number_at_byte(expr.span().end());
         let FunctionEnv {
             function,
             free_vars,
@@ -621,10 +647,15 @@ impl<'a> Compiler<'a> {
     }
 
     fn update_line(&mut self, function: &mut FunctionEnvs, expr: CExpr) {
-        // Don't update the current_line for macro expanded code as the lines in that code do not
-        // come from this module
-        if let Some(current_line) = self.source.line_number_at_byte(expr.span().start()) {
+        // Don't update the current_line for macro expanded code which does not come from this
+        // module and which `origins` does not know the origin of
+        if let Some(current_line) = self.line_number_at_byte(expr.span().start()) {
             function.current_line = current_line;
+            if self.coverage && function.covered_line != Some(current_line) {
+                function.covered_line = Some(current_line);
+                let probe = self.vm.coverage().probe(&self.source_name, current_line);
+                function.emit(Hit(probe));
+            }
         }
     }
 
@@ -697,8 +728,7 @@ impl<'a> Compiler<'a> {
                         }
 
                         for (i, closure) in closures.iter().enumerate() {
-                            if let Some(current_line) = self.source.line_number_at_byte(closure.pos)
-                            {
+                            if let Some(current_line) = self.line_number_at_byte(closure.pos) {
                                 function.current_line = current_line;
                             }
                             function.stack.enter_scope();
@@ -898,6 +928,7 @@ impl<'a> Compiler<'a> {
                             function.new_stack_var(self, self.empty_symbol.clone(), Type::hole());
                         }
                     }
+                    function.covered_line = None;
                     self.compile(&alt.expr, function, tail_position)?;
                     let count = function.exit_scope(self);
                     function.emit(Slide(count));
@@ -908,6 +939,7 @@ impl<'a> Compiler<'a> {
                     function.function.instructions[index] =
                         Jump(function.function.instructions.len() as VmIndex);
                 }
+                function.covered_line = None;
             }
             Expr::Data(ref id, exprs, _) => {
                 for expr in exprs {
@@ -1124,7 +1156,7 @@ impl<'a> Compiler<'a> {
 
         // Insert all free variables into the above globals free variables
         // if they arent in that lambdas scope
-        let current_line = self.source.line_number_at_byte(body.span().end());
+        let current_line = self.line_number_at_byte(body.span().end());
         let f = function.end_function(self, current_line);
         for &(ref var, _) in f.free_vars.iter() {
             match self
@@ -0,0 +1,149 @@
+//! Line coverage of gluon code. Code compiled with `Compiler::set_coverage` contains `Hit`
+//! instructions which count how often each line is executed in the `Coverage` of the vm.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::base::{fnv::FnvMap, pos::Line};
+
+use crate::types::VmIndex;
+
+#[derive(Default)]
+struct Probes {
+    indexes: FnvMap<(String, Line), VmIndex>,
+    hits: Vec<(String, Line, AtomicUsize)>,
+}
+
+/// The execution counts of the lines of every module compiled with coverage instrumentation
+#[derive(Default)]
+pub struct Coverage {
+    probes: parking_lot::RwLock<Probes>,
+}
+
+impl Coverage {
+    /// Returns the index of the counter of `line` in `module`, registering it if it did not
+    /// exist. Recompiling a module reuses its counters.
+    pub(crate) fn probe(&self, module: &str, line: Line) -> VmIndex {
+        let key = (module.to_string(), line);
+        if let Some(&index) = self.probes.read().indexes.get(&key) {
+            return index;
+        }
+        let mut probes = self.probes.write();
+        let Probes { indexes, hits } = &mut *probes;
+        *indexes.entry(key).or_insert_with(|| {
+            hits.push((module.to_string(), line, AtomicUsize::new(0)));
+            (hits.len() - 1) as VmIndex
+        })
+    }
+
+    pub(crate) fn hit(&self, probe: VmIndex) {
+        if let Some((_, _, hits)) = self.probes.read().hits.get(probe as usize) {
+            hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns how often each instrumented line has been executed so far
+    pub fn report(&self) -> CoverageReport {
+        let mut report = CoverageReport::default();
+        for (module, line, hits) in &self.probes.read().hits {
+            report
+                .modules
+                .entry(module.clone())
+                .or_default()
+                .insert(*line, hits.load(Ordering::Relaxed));
+        }
+        report
+    }
+
+    /// Sets the execution count of every line to 0
+    pub fn reset(&self) {
+        for (_, _, hits) in &self.probes.read().hits {
+            hits.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The number of times each instrumented line of each module was executed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CoverageReport {
+    pub modules: BTreeMap<String, BTreeMap<Line, usize>>,
+}
+
+impl CoverageReport {
+    /// Adds the execution counts of `other`, for instance from a different vm, to `self`
+    pub fn merge(&mut self, other: CoverageReport) {
+        for (module, lines) in other.modules {
+            let merged = self.modules.entry(module).or_default();
+            for (line, hits) in lines {
+                *merged.entry(line).or_insert(0) += hits;
+            }
+        }
+    }
+
+    /// Writes the report in the lcov tracefile format. `path` returns the path of the source file
+    /// of a module, modules for which it returns `None` are left out of the report.
+    pub fn write_lcov(
+        &self,
+        out: &mut dyn Write,
+        mut path: impl FnMut(&str) -> Option<String>,
+    ) -> io::Result<()> {
+        for (module, lines) in &self.modules {
+            let path = match path(module) {
+                Some(path) => path,
+                None => continue,
+            };
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", path)?;
+            for (line, hits) in lines {
+                writeln!(out, "DA:{},{}", line.number(), hits)?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(
+                out,
+                "LH:{}",
+                lines.values().filter(|&&hits| hits != 0).count()
+            )?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcov_report() {
+        let coverage = Coverage::default();
+        let first = coverage.probe("test", Line::from(0));
+        let second = coverage.probe("test", Line::from(2));
+        assert_eq!(coverage.probe("test", Line::from(0)), first);
+        coverage.probe("std.prelude", Line::from(0));
+        coverage.hit(first);
+        coverage.hit(first);
+
+        let mut report = coverage.report();
+        coverage.reset();
+        coverage.hit(second);
+        report.merge(coverage.report());
+
+        let mut lcov = Vec::new();
+        report
+            .write_lcov(&mut lcov, |module| {
+                if module.starts_with("std.") {
+                    None
+                } else {
+                    Some(format!("{}.glu", module))
+                }
+            })
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(lcov).unwrap(),
+            "TN:\nSF:test.glu\nDA:1,2\nDA:3,1\nLF:2\nLH:2\nend_of_record\n"
+        );
+    }
+}
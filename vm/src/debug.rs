@@ -1,16 +1,21 @@
 use crate::{
-    api::{generic::A, Generic, OpaqueRef},
+    api::{generic::A, Generic, OpaqueRef, WithVM},
     thread::Thread,
-    value::ValueRepr,
+    value::{DebugValue, ValueRepr},
     ExternModule, Result,
 };
 
-fn trace(a: Generic<A>) {
-    println!("{:?}", a);
+fn trace(a: WithVM<Generic<A>>) {
+    println!("{}", show(a));
 }
 
-fn show(a: Generic<A>) -> String {
-    format!("{:?}", a)
+fn show(a: WithVM<Generic<A>>) -> String {
+    // The addresses of closures differ between runs so they are not shown in deterministic mode
+    let show_addresses = !a.vm.global_env().is_deterministic();
+    format!(
+        "{:?}",
+        DebugValue::new(a.value.get_value().get_repr(), show_addresses)
+    )
 }
 
 fn tag(a: OpaqueRef<A>) -> Option<String> {
@@ -0,0 +1,338 @@
+use crate::base::{
+    ast::{
+        self, Alternative, Argument, Expr, ExprField, Lambda, Pattern, SpannedExpr, SpannedPattern,
+        TypeBinding, TypedIdent, ValueBinding,
+    },
+    pos::{self, BytePos, Span},
+    symbol::{Symbol, Symbols},
+    types::{ctor_args, remove_forall, row_iter, KindedIdent, Type, TypeContext},
+};
+
+use crate::macros::Error;
+
+use crate::derive::*;
+
+pub fn generate<'ast>(
+    mut arena: ast::ArenaRef<'_, 'ast, Symbol>,
+    symbols: &mut Symbols,
+    bind: &TypeBinding<'ast, Symbol>,
+) -> Result<ValueBinding<'ast, Symbol>, Error> {
+    let span = bind.name.span;
+
+    let x = Symbol::from("x");
+    let value = Symbol::from("value");
+
+    let mut self_type = {
+        let mut arena = arena;
+        move || bind.alias.value.self_type(&mut arena)
+    };
+
+    // Each optic is bound to a local with the type `Lens Self _` or `Prism Self _` so that the
+    // record projections and constructor patterns are checked against the derived type
+    let (imports, optic_type, optics) = match **remove_forall(bind.alias.value.unresolved_type()) {
+        Type::Record(ref row) => {
+            let import = arena.generate_import(span, symbols, &["Lens"], &["lens"], "std.lens");
+
+            let field_names: Vec<_> = row_iter(row)
+                .map(|field| field.name.declared_name().to_string())
+                .collect();
+            let project = |symbols: &mut Symbols, name: &str| {
+                pos::spanned(
+                    span,
+                    Expr::Projection(
+                        arena.alloc(ident(span, x.clone())),
+                        symbols.simple_symbol(name),
+                        Type::hole(),
+                    ),
+                )
+            };
+
+            let optics = field_names
+                .iter()
+                .map(|name| {
+                    let get = project(symbols, name);
+                    let get = lambda(arena, symbols, span, &[x.clone()], get);
+
+                    // Every field is copied explicitly instead of updating `x` with `..`, the type
+                    // of `x` is only known once the binding has been checked against its
+                    // annotation
+                    let fields: Vec<_> = field_names
+                        .iter()
+                        .map(|other| ExprField {
+                            metadata: Default::default(),
+                            name: pos::spanned(span, symbols.simple_symbol(&other[..])),
+                            value: Some(if other == name {
+                                ident(span, value.clone())
+                            } else {
+                                project(symbols, other)
+                            }),
+                        })
+                        .collect();
+                    let set = lambda(
+                        arena,
+                        symbols,
+                        span,
+                        &[x.clone(), value.clone()],
+                        pos::spanned(
+                            span,
+                            Expr::Record {
+                                typ: Type::hole(),
+                                types: &mut [],
+                                exprs: arena.alloc_extend(fields),
+                                base: None,
+                            },
+                        ),
+                    );
+
+                    let expr = arena.app(span, symbols.simple_symbol("lens"), vec![get, set]);
+                    (name.clone(), expr)
+                })
+                .collect::<Vec<_>>();
+            (vec![import], "Lens", optics)
+        }
+        Type::Variant(ref row) => {
+            let imports = vec![
+                arena.generate_import(span, symbols, &["Prism"], &["prism"], "std.lens.prism"),
+                arena.generate_import(span, symbols, &["Option"], &[], "std.option"),
+            ];
+
+            let optics = row_iter(row)
+                .map(|variant| {
+                    let args: Vec<_> = ctor_args(&variant.typ)
+                        .enumerate()
+                        .map(|(i, _)| TypedIdent::new(Symbol::from(format!("arg_{}", i))))
+                        .collect();
+
+                    let constructor = || {
+                        arena.app(
+                            span,
+                            variant.name.value.clone(),
+                            args.iter().map(|arg| ident(span, arg.name.clone())),
+                        )
+                    };
+                    let constructor_pattern = || {
+                        pos::spanned(
+                            span,
+                            Pattern::Constructor(
+                                TypedIdent::new(variant.name.value.clone()),
+                                arena.alloc_extend(
+                                    args.iter()
+                                        .cloned()
+                                        .map(|arg| pos::spanned(span, Pattern::Ident(arg))),
+                                ),
+                            ),
+                        )
+                    };
+
+                    // A constructor with a single argument focuses on that argument, any other
+                    // number of arguments is focused on as a tuple
+                    let (args_pattern, args_expr) = match &args[..] {
+                        [arg] => (
+                            pos::spanned(span, Pattern::Ident(arg.clone())),
+                            ident(span, arg.name.clone()),
+                        ),
+                        _ => (
+                            pos::spanned(
+                                span,
+                                Pattern::Tuple {
+                                    typ: Type::hole(),
+                                    elems: arena.alloc_extend(
+                                        args.iter()
+                                            .cloned()
+                                            .map(|arg| pos::spanned(span, Pattern::Ident(arg))),
+                                    ),
+                                },
+                            ),
+                            pos::spanned(
+                                span,
+                                Expr::Tuple {
+                                    typ: Type::hole(),
+                                    elems: arena.alloc_extend(
+                                        args.iter().map(|arg| ident(span, arg.name.clone())),
+                                    ),
+                                },
+                            ),
+                        ),
+                    };
+
+                    let review = lambda(
+                        arena,
+                        symbols,
+                        span,
+                        &[x.clone()],
+                        match_expr(arena, span, x.clone(), vec![(args_pattern, constructor())]),
+                    );
+
+                    let some = symbols.simple_symbol("Some");
+                    let none = symbols.simple_symbol("None");
+                    let mut alternatives = vec![(
+                        constructor_pattern(),
+                        arena.app(span, some, Some(args_expr)),
+                    )];
+                    if row_iter(row).count() > 1 {
+                        alternatives.push((
+                            pos::spanned(span, Pattern::Ident(TypedIdent::new(Symbol::from("_")))),
+                            ident(span, none),
+                        ));
+                    }
+                    let preview = lambda(
+                        arena,
+                        symbols,
+                        span,
+                        &[x.clone()],
+                        match_expr(arena, span, x.clone(), alternatives),
+                    );
+
+                    let expr =
+                        arena.app(span, symbols.simple_symbol("prism"), vec![review, preview]);
+                    (snake_case(variant.name.declared_name()), expr)
+                })
+                .collect::<Vec<_>>();
+            (imports, "Prism", optics)
+        }
+        _ => return Err(Error::message("Unable to derive Lens for this type")),
+    };
+
+    let optic_type_symbol = symbols.simple_symbol(optic_type);
+    let optic_bindings: Vec<_> = optics
+        .into_iter()
+        .map(|(name, expr)| {
+            let local = TypedIdent::new(Symbol::from(format!("{}_{}", name, optic_type)));
+            let typ = TypeContext::app(
+                &mut arena.clone(),
+                arena
+                    .clone()
+                    .ident(KindedIdent::new(optic_type_symbol.clone())),
+                arena
+                    .clone()
+                    .alloc_extend(vec![self_type(), arena.clone().hole()]),
+            );
+            let binding = ValueBinding {
+                name: pos::spanned(span, Pattern::Ident(local.clone())),
+                args: &mut [],
+                expr,
+                metadata: Default::default(),
+                typ: Some(typ),
+                resolved_type: Type::hole(),
+            };
+            (name, local, binding)
+        })
+        .collect();
+
+    let export_expr = pos::spanned(
+        span,
+        Expr::Record {
+            typ: Type::hole(),
+            types: &mut [],
+            exprs: arena.alloc_extend(optic_bindings.iter().map(|(name, local, _)| ExprField {
+                metadata: Default::default(),
+                name: pos::spanned(span, symbols.simple_symbol(&name[..])),
+                value: Some(ident(span, local.name.clone())),
+            })),
+            base: None,
+        },
+    );
+
+    let lens_record_expr = imports
+        .into_iter()
+        .chain(optic_bindings.into_iter().map(|(_, _, binding)| binding))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .fold(export_expr, |expr, bind| {
+            pos::spanned(span, Expr::let_binding(arena, bind, expr))
+        });
+
+    Ok(ValueBinding {
+        name: pos::spanned(
+            span,
+            Pattern::Ident(TypedIdent::new(symbols.simple_symbol(format!(
+                "lens_{}",
+                bind.alias.value.name.declared_name()
+            )))),
+        ),
+        args: &mut [],
+        expr: lens_record_expr,
+        metadata: Default::default(),
+        typ: None,
+        resolved_type: Type::hole(),
+    })
+}
+
+fn lambda<'ast>(
+    arena: ast::ArenaRef<'_, 'ast, Symbol>,
+    symbols: &mut Symbols,
+    span: Span<BytePos>,
+    args: &[Symbol],
+    body: SpannedExpr<'ast, Symbol>,
+) -> SpannedExpr<'ast, Symbol> {
+    arena.paren(
+        span,
+        pos::spanned(
+            span,
+            Expr::Lambda(Lambda {
+                args: arena.alloc_extend(args.iter().map(|arg| {
+                    Argument::explicit(pos::spanned(span, TypedIdent::new(arg.clone())))
+                })),
+                body: arena.alloc(body),
+                id: TypedIdent::new(symbols.simple_symbol("optic")),
+            }),
+        ),
+    )
+}
+
+fn match_expr<'ast>(
+    arena: ast::ArenaRef<'_, 'ast, Symbol>,
+    span: Span<BytePos>,
+    scrutinee: Symbol,
+    alternatives: Vec<(SpannedPattern<'ast, Symbol>, SpannedExpr<'ast, Symbol>)>,
+) -> SpannedExpr<'ast, Symbol> {
+    pos::spanned(
+        span,
+        Expr::Match(
+            arena.alloc(ident(span, scrutinee)),
+            arena.alloc_extend(
+                alternatives
+                    .into_iter()
+                    .map(|(pattern, expr)| Alternative { pattern, expr }),
+            ),
+        ),
+    )
+}
+
+/// Converts a constructor name such as `HttpError` to the field name `http_error`
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_numeric());
+            let next_lower = chars.get(i + 1).map_or(false, |c| c.is_lowercase());
+            if i > 0 && (prev_lower || (next_lower && chars[i - 1].is_uppercase())) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    match &snake[..] {
+        "do" | "else" | "forall" | "if" | "in" | "let" | "match" | "rec" | "seq" | "then"
+        | "type" | "with" => snake + "_",
+        _ => snake,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snake_case;
+
+    #[test]
+    fn snake_case_constructor_names() {
+        assert_eq!(snake_case("Circle"), "circle");
+        assert_eq!(snake_case("HttpError"), "http_error");
+        assert_eq!(snake_case("IOError"), "io_error");
+        assert_eq!(snake_case("V2"), "v2");
+        assert_eq!(snake_case("Type"), "type_");
+    }
+}
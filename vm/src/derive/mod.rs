@@ -13,6 +13,7 @@ use crate::macros::Error;
 
 mod deserialize;
 mod eq;
+mod lens;
 mod serialize;
 mod show;
 
@@ -30,6 +31,7 @@ pub fn generate<'ast>(
                 Ok(match arg {
                     "Eq" => eq::generate(arena, symbols, bind),
                     "Show" => show::generate(arena, symbols, bind),
+                    "Lens" => lens::generate(arena, symbols, bind),
                     "Deserialize" => deserialize::generate(arena, symbols, bind),
                     "Serialize" => serialize::generate(arena, symbols, bind),
                     _ => {
@@ -0,0 +1,177 @@
+//! Module containing signed spans of time, `std.duration`.
+//!
+//! Rust's `std::time::Duration` is marshalled as `std.duration.Duration` as well.
+
+use crate::real_std::{cmp::Ordering, convert::TryFrom, fmt, time};
+
+use crate::{thread::Thread, types::VmInt, Error, ExternModule, Result};
+
+const NANOS_PER_MICRO: i128 = 1_000;
+const NANOS_PER_MILLI: i128 = 1_000_000;
+pub const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// A span of time with nanosecond precision. Unlike `std::time::Duration` it may be negative so
+/// that the difference between any two points in time can be represented. The field is the
+/// number of nanoseconds in the span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Userdata, Trace, VmType)]
+#[gluon(vm_type = "std.duration.Duration")]
+#[gluon_userdata(clone)]
+#[gluon(gluon_vm)]
+#[gluon_trace(skip)]
+pub struct Duration(pub i128);
+
+impl From<time::Duration> for Duration {
+    fn from(duration: time::Duration) -> Self {
+        // `as_nanos` is at most `u64::MAX * NANOS_PER_SEC` which always fits in an `i128`
+        Duration(duration.as_nanos() as i128)
+    }
+}
+
+impl TryFrom<Duration> for time::Duration {
+    type Error = Error;
+
+    fn try_from(duration: Duration) -> Result<Self> {
+        if duration.0 < 0 {
+            return Err(Error::Message(format!(
+                "The negative duration `{}` can't be converted to `std::time::Duration`",
+                duration
+            )));
+        }
+        let secs = u64::try_from(duration.0 / NANOS_PER_SEC).map_err(|_| {
+            Error::Message(format!(
+                "The duration `{}` is too large for `std::time::Duration`",
+                duration
+            ))
+        })?;
+        Ok(time::Duration::new(
+            secs,
+            (duration.0 % NANOS_PER_SEC) as u32,
+        ))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nanos = self.0.unsigned_abs();
+        let secs = u64::try_from(nanos / NANOS_PER_SEC as u128).unwrap_or(u64::MAX);
+        let duration = time::Duration::new(secs, (nanos % NANOS_PER_SEC as u128) as u32);
+        if self.0 < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{:?}", duration)
+    }
+}
+
+/// Converts `nanos` to an `Int`, saturating at the bounds of `Int`
+pub fn saturate(nanos: i128) -> VmInt {
+    VmInt::try_from(nanos).unwrap_or(if nanos < 0 { VmInt::MIN } else { VmInt::MAX })
+}
+
+fn from_seconds(seconds: VmInt) -> Duration {
+    Duration(i128::from(seconds) * NANOS_PER_SEC)
+}
+
+fn from_millis(millis: VmInt) -> Duration {
+    Duration(i128::from(millis) * NANOS_PER_MILLI)
+}
+
+fn from_micros(micros: VmInt) -> Duration {
+    Duration(i128::from(micros) * NANOS_PER_MICRO)
+}
+
+fn from_nanos(nanos: VmInt) -> Duration {
+    Duration(i128::from(nanos))
+}
+
+fn from_seconds_float(seconds: f64) -> Duration {
+    Duration((seconds * NANOS_PER_SEC as f64) as i128)
+}
+
+fn seconds(duration: &Duration) -> VmInt {
+    saturate(duration.0 / NANOS_PER_SEC)
+}
+
+fn millis(duration: &Duration) -> VmInt {
+    saturate(duration.0 / NANOS_PER_MILLI)
+}
+
+fn micros(duration: &Duration) -> VmInt {
+    saturate(duration.0 / NANOS_PER_MICRO)
+}
+
+fn nanos(duration: &Duration) -> VmInt {
+    saturate(duration.0)
+}
+
+fn seconds_float(duration: &Duration) -> f64 {
+    duration.0 as f64 / NANOS_PER_SEC as f64
+}
+
+fn add(l: &Duration, r: &Duration) -> Duration {
+    Duration(l.0.saturating_add(r.0))
+}
+
+fn sub(l: &Duration, r: &Duration) -> Duration {
+    Duration(l.0.saturating_sub(r.0))
+}
+
+fn mul(duration: &Duration, factor: VmInt) -> Duration {
+    Duration(duration.0.saturating_mul(i128::from(factor)))
+}
+
+fn negate(duration: &Duration) -> Duration {
+    Duration(duration.0.saturating_neg())
+}
+
+fn abs(duration: &Duration) -> Duration {
+    Duration(duration.0.saturating_abs())
+}
+
+fn eq(l: &Duration, r: &Duration) -> bool {
+    l == r
+}
+
+fn compare(l: &Duration, r: &Duration) -> Ordering {
+    l.cmp(r)
+}
+
+fn show(duration: &Duration) -> String {
+    duration.to_string()
+}
+
+mod std {
+    pub mod duration {
+        pub use crate::duration as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    vm.register_type::<Duration>("std.duration.Duration", &[])?;
+
+    ExternModule::new(
+        vm,
+        record! {
+            type Duration => Duration,
+
+            zero => Duration(0),
+            from_seconds => primitive!(1, std::duration::prim::from_seconds),
+            from_millis => primitive!(1, std::duration::prim::from_millis),
+            from_micros => primitive!(1, std::duration::prim::from_micros),
+            from_nanos => primitive!(1, std::duration::prim::from_nanos),
+            from_seconds_float => primitive!(1, std::duration::prim::from_seconds_float),
+            seconds => primitive!(1, std::duration::prim::seconds),
+            millis => primitive!(1, std::duration::prim::millis),
+            micros => primitive!(1, std::duration::prim::micros),
+            nanos => primitive!(1, std::duration::prim::nanos),
+            seconds_float => primitive!(1, std::duration::prim::seconds_float),
+            add => primitive!(2, std::duration::prim::add),
+            sub => primitive!(2, std::duration::prim::sub),
+            mul => primitive!(2, std::duration::prim::mul),
+            negate => primitive!(1, std::duration::prim::negate),
+            abs => primitive!(1, std::duration::prim::abs),
+            eq => primitive!(2, std::duration::prim::eq),
+            compare => primitive!(2, std::duration::prim::compare),
+            show => primitive!(1, std::duration::prim::show)
+        },
+    )
+}
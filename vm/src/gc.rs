@@ -19,6 +19,7 @@ use crate::{
 };
 
 pub mod mutex;
+pub mod rw_lock;
 
 #[doc(hidden)]
 #[macro_export]
@@ -225,6 +226,9 @@ pub struct Gc {
     values: Option<AllocPtr>,
     /// How many bytes which is currently allocated
     allocated_memory: usize,
+    /// How many bytes that have been allocated in total, including those which have since been
+    /// collected
+    total_allocated_memory: usize,
     /// How many bytes this garbage collector can allocate before a collection is run
     collect_limit: usize,
     /// The maximum number of bytes this garbage collector may contain
@@ -1053,6 +1057,7 @@ impl Gc {
         Gc {
             values: None,
             allocated_memory: 0,
+            total_allocated_memory: 0,
             collect_limit: 100,
             memory_limit: memory_limit,
             type_infos: FnvMap::default(),
@@ -1066,10 +1071,22 @@ impl Gc {
         self.allocated_memory
     }
 
+    /// Returns the number of bytes allocated since this garbage collector was created, including
+    /// memory which has already been collected
+    pub fn total_allocated_memory(&self) -> usize {
+        self.total_allocated_memory
+    }
+
     pub fn set_memory_limit(&mut self, memory_limit: usize) {
         self.memory_limit = memory_limit;
     }
 
+    /// Sets how many bytes may be allocated before the next collection. After each collection the
+    /// limit becomes twice the amount of memory which survived it.
+    pub fn set_collect_limit(&mut self, collect_limit: usize) {
+        self.collect_limit = collect_limit;
+    }
+
     pub fn generation(&self) -> Generation {
         self.generation
     }
@@ -1228,6 +1245,7 @@ impl Gc {
         let mut ptr = AllocPtr::new::<D::Value>(type_info, size);
         ptr.next = self.values.take();
         self.allocated_memory += ptr.size();
+        self.total_allocated_memory += ptr.size();
         unsafe {
             let p: *mut D::Value = D::Value::make_ptr(&def, ptr.value());
             let ret: *const D::Value = &*def.initialize(WriteOnly::new(p));
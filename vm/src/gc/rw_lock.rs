@@ -0,0 +1,224 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync,
+};
+
+use crate::gc::{Gc, Trace};
+
+pub use std::sync::{PoisonError, RwLockReadGuard, TryLockError};
+
+pub type LockResult<Guard> = Result<Guard, PoisonError<Guard>>;
+
+/// A reader-writer lock which can be stored in userdata that is moved into the gc, such as a
+/// registry of gluon functions which are called more often than they are registered.
+///
+/// Like `gc::mutex::Mutex`, the values in the lock are unrooted while the lock is stored in the gc
+/// and are instead traced through it, so that cycles which pass through the lock can be collected.
+/// Values can't be moved out through a shared reference so the contents are only rooted while the
+/// lock is held for writing.
+pub struct RwLock<T>
+where
+    T: ?Sized,
+{
+    // Only changed through `Trace::root` and `Trace::unroot` which take `&mut self`, so it can't
+    // change while the lock is held
+    rooted: bool,
+    lock: sync::RwLock<T>,
+}
+
+impl<T> Default for RwLock<T>
+where
+    T: Default,
+{
+    fn default() -> Self {
+        RwLock::new(Default::default())
+    }
+}
+
+impl<T> fmt::Debug for RwLock<T>
+where
+    T: ?Sized + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.lock, f)
+    }
+}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        RwLock {
+            rooted: true,
+            lock: sync::RwLock::new(value),
+        }
+    }
+}
+
+impl<T> RwLock<T>
+where
+    T: ?Sized + Trace,
+{
+    pub fn read(&self) -> LockResult<RwLockReadGuard<T>> {
+        self.lock.read()
+    }
+
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<T>> {
+        match self.lock.write() {
+            Ok(lock) => Ok(self.new_guard(lock)),
+            Err(err) => Err(PoisonError::new(self.new_guard(err.into_inner()))),
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.lock.is_poisoned()
+    }
+
+    pub fn into_inner(self) -> LockResult<T>
+    where
+        T: Sized,
+    {
+        self.lock.into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.lock.get_mut()
+    }
+
+    fn new_guard<'a>(
+        &'a self,
+        mut value: sync::RwLockWriteGuard<'a, T>,
+    ) -> RwLockWriteGuard<'a, T> {
+        if !self.rooted {
+            unsafe {
+                value.root();
+            }
+        }
+        RwLockWriteGuard {
+            rooted: self.rooted,
+            value,
+        }
+    }
+}
+
+unsafe impl<T> Trace for RwLock<T>
+where
+    T: ?Sized + Trace,
+{
+    unsafe fn root(&mut self) {
+        assert!(!self.rooted, "RwLock can't be rooted twice!");
+        self.rooted = true;
+        self.lock
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .root();
+    }
+    unsafe fn unroot(&mut self) {
+        assert!(self.rooted, "RwLock can't be unrooted twice!");
+        self.rooted = false;
+        self.lock
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .unroot();
+    }
+    fn trace(&self, gc: &mut Gc) {
+        match self.lock.try_read() {
+            Ok(lock) => lock.trace(gc),
+            Err(TryLockError::WouldBlock) => (), // The value is rooted while it is written to
+            Err(TryLockError::Poisoned(err)) => err.into_inner().trace(gc),
+        }
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T>
+where
+    T: ?Sized + Trace,
+{
+    rooted: bool,
+    value: sync::RwLockWriteGuard<'a, T>,
+}
+
+impl<'a, T> Drop for RwLockWriteGuard<'a, T>
+where
+    T: ?Sized + Trace,
+{
+    fn drop(&mut self) {
+        if !self.rooted {
+            unsafe {
+                self.value.unroot();
+            }
+        }
+    }
+}
+
+impl<'a, T> Deref for RwLockWriteGuard<'a, T>
+where
+    T: ?Sized + Trace,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<'a, T> DerefMut for RwLockWriteGuard<'a, T>
+where
+    T: ?Sized + Trace,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    struct Rooted<'a>(&'a Cell<bool>);
+
+    unsafe impl<'a> Trace for Rooted<'a> {
+        unsafe fn root(&mut self) {
+            assert!(!self.0.get());
+            self.0.set(true);
+        }
+        unsafe fn unroot(&mut self) {
+            assert!(self.0.get());
+            self.0.set(false);
+        }
+        fn trace(&self, _gc: &mut Gc) {}
+    }
+
+    #[test]
+    fn rooted() {
+        let rooted = Cell::new(true);
+        let lock = RwLock::new(Rooted(&rooted));
+
+        {
+            let _lock = lock.write().unwrap();
+            assert!(rooted.get());
+        }
+        assert!(rooted.get());
+    }
+
+    #[test]
+    fn unrooted() {
+        let rooted = Cell::new(true);
+        let mut lock = RwLock::new(Rooted(&rooted));
+        // Emulate this `RwLock` being unrooted (stored in another root)
+        unsafe {
+            lock.unroot();
+        }
+
+        assert!(!rooted.get());
+        {
+            let _lock = lock.read().unwrap();
+            assert!(!rooted.get());
+        }
+        {
+            let _lock = lock.write().unwrap();
+            assert!(rooted.get());
+        }
+        assert!(!rooted.get());
+    }
+}
@@ -0,0 +1,657 @@
+//! Primitives for `std.collections.hashmap` and `std.hash`.
+//!
+//! The maps do not hash or compare keys themselves. Gluon computes the hash of a key with its
+//! `Hashable` instance and the map stores the entries whose keys have that hash together in a
+//! bucket, which gluon then searches with the `Eq` instance of the key. The buckets are found
+//! through fixed hash functions so the order of iteration only depends on the keys and on the
+//! order in which colliding keys were inserted, never on the process which runs the program.
+use crate::real_std::{
+    any::Any,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    marker::PhantomData,
+    sync::Mutex,
+};
+
+use rpds::RedBlackTreeMapSync;
+
+use crate::{
+    api::{
+        generic::{A, B, C},
+        ActiveThread, Getable, OpaqueRef, Pushable, RuntimeResult, Unrooted, Userdata, ValueRef,
+        VmType, WithVM, IO,
+    },
+    base::{
+        fnv::{FnvHasher, FnvMap},
+        types::{ArcType, Type},
+    },
+    gc::{CloneUnrooted, Gc, GcPtr, GcRef, Move, Trace},
+    thread::ThreadInternal,
+    types::{VmIndex, VmInt, VmTag},
+    value::{Cloner, Value},
+    vm::Thread,
+    Error, ExternModule, Result, Variants,
+};
+
+/// An owned, hashable copy of the structure of a value
+#[derive(Hash)]
+enum HashKey {
+    Byte(u8),
+    Int(VmInt),
+    Float(u64),
+    String(Box<str>),
+    Data(VmTag, Box<[HashKey]>),
+    Array(Box<[HashKey]>),
+}
+
+impl HashKey {
+    fn new(value: Variants) -> StdResult<HashKey, String> {
+        Ok(match value.as_ref() {
+            ValueRef::Byte(b) => HashKey::Byte(b),
+            ValueRef::Int(i) => HashKey::Int(i),
+            // `0.0` and `-0.0` are equal so they must have the same hash
+            ValueRef::Float(f) if f == 0.0 => HashKey::Float(0),
+            ValueRef::Float(f) => HashKey::Float(f.to_bits()),
+            ValueRef::String(s) => HashKey::String(s.into()),
+            ValueRef::Data(data) => HashKey::Data(
+                data.tag(),
+                data.iter().map(HashKey::new).collect::<StdResult<_, _>>()?,
+            ),
+            ValueRef::Array(array) => HashKey::Array(
+                array
+                    .iter()
+                    .map(HashKey::new)
+                    .collect::<StdResult<_, _>>()?,
+            ),
+            ValueRef::Userdata(_)
+            | ValueRef::Thread(_)
+            | ValueRef::Closure(_)
+            | ValueRef::Internal => {
+                return Err("Functions, userdata and threads can't be hashed structurally".into())
+            }
+        })
+    }
+}
+
+type StdResult<T, E> = crate::real_std::result::Result<T, E>;
+
+/// Hashes `value` by its structure, with a hasher which does not depend on the process
+fn structural_hash(value: Variants) -> StdResult<VmInt, String> {
+    let key = HashKey::new(value)?;
+    let mut hasher = FnvHasher::default();
+    key.hash(&mut hasher);
+    Ok(hasher.finish() as VmInt)
+}
+
+/// The key and value as they were inserted. The key is kept so that it can be returned when
+/// iterating over the map.
+struct Entry {
+    key: Value,
+    value: Value,
+}
+
+/// The entries whose keys have the same hash
+type Bucket = Vec<Entry>;
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {:?}", self.key, self.value)
+    }
+}
+
+impl Entry {
+    // SAFETY The entry must be rooted by being stored in a traced map before the next collection
+    unsafe fn new(key: &Value, value: &Value) -> Entry {
+        Entry {
+            key: key.clone_unrooted(),
+            value: value.clone_unrooted(),
+        }
+    }
+
+    fn deep_clone(&self, deep_cloner: &mut Cloner) -> Result<Entry> {
+        // SAFETY The cloned values are stored in the map that is allocated by the caller
+        unsafe {
+            Ok(Entry {
+                key: deep_cloner.deep_clone(&self.key)?.unrooted(),
+                value: deep_cloner.deep_clone(&self.value)?.unrooted(),
+            })
+        }
+    }
+
+    fn trace(&self, gc: &mut Gc) {
+        self.key.trace(gc);
+        self.value.trace(gc);
+    }
+}
+
+fn deep_clone_bucket(bucket: &Bucket, deep_cloner: &mut Cloner) -> Result<Bucket> {
+    bucket
+        .iter()
+        .map(|entry| entry.deep_clone(deep_cloner))
+        .collect()
+}
+
+// SAFETY The entries must be rooted by being stored in a traced map before the next collection
+unsafe fn clone_bucket(bucket: &Bucket) -> Bucket {
+    bucket
+        .iter()
+        .map(|entry| Entry::new(&entry.key, &entry.value))
+        .collect()
+}
+
+field_decl! { key, value }
+
+pub type EntryRecord<K, V> = record_type!(key => K, value => V);
+
+fn entry_record(entry: &Entry) -> EntryRecord<Unrooted<A>, Unrooted<B>> {
+    // SAFETY The values are rooted by the map and pushed immediately to the stack
+    unsafe {
+        record_no_decl!(
+            key => Unrooted::from(entry.key.clone_unrooted()),
+            value => Unrooted::from(entry.value.clone_unrooted())
+        )
+    }
+}
+
+// SAFETY The values must be rooted until the bucket is stored in a traced map
+unsafe fn new_bucket(entries: &[EntryRecord<OpaqueRef<A>, OpaqueRef<B>>]) -> Bucket {
+    entries
+        .iter()
+        .map(|record_p! { key, value }| Entry::new(key.get_value(), value.get_value()))
+        .collect()
+}
+
+/// A persistent hash map, implemented as a balanced tree of buckets ordered by their hash.
+/// Inserting into or removing from the map returns a new map which shares most of its structure
+/// with the old one.
+#[derive(VmType)]
+#[gluon(gluon_vm)]
+#[gluon(vm_type = "std.collections.hashmap.HashMap")]
+pub struct PersistentHashMap<K, V> {
+    buckets: RedBlackTreeMapSync<VmInt, Bucket>,
+    len: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> PersistentHashMap<K, V> {
+    fn new(buckets: RedBlackTreeMapSync<VmInt, Bucket>, len: usize) -> Self {
+        PersistentHashMap {
+            buckets,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.buckets.values().flatten()
+    }
+}
+
+impl<K, V> Userdata for PersistentHashMap<K, V>
+where
+    K: Any + Send + Sync,
+    V: Any + Send + Sync,
+{
+    fn deep_clone<'gc>(
+        &self,
+        deep_cloner: &'gc mut Cloner,
+    ) -> Result<GcRef<'gc, Box<dyn Userdata>>> {
+        let mut buckets = RedBlackTreeMapSync::new_sync();
+        for (&hash, bucket) in self.buckets.iter() {
+            buckets.insert_mut(hash, deep_clone_bucket(bucket, deep_cloner)?);
+        }
+        let data: Box<dyn Userdata> = Box::new(PersistentHashMap::<A, B>::new(buckets, self.len));
+        // During the `alloc` call the unrooted values are scanned through the `DataDef`
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl<K, V> fmt::Debug for PersistentHashMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.entries()).finish()
+    }
+}
+
+// Don't root/unroot the contents as an unrooted value could be moved out of the map
+unsafe impl<K, V> Trace for PersistentHashMap<K, V> {
+    fn trace(&self, gc: &mut Gc) {
+        for entry in self.entries() {
+            entry.trace(gc);
+        }
+    }
+}
+
+/// The buckets of a `HashTable`
+#[derive(Default)]
+struct Table {
+    buckets: FnvMap<VmInt, Bucket>,
+    len: usize,
+    /// Incremented on every modification so that a bucket is only replaced if it is unchanged
+    /// since it was read
+    version: VmInt,
+}
+
+impl Table {
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.buckets.values().flatten()
+    }
+}
+
+/// A mutable hash map
+#[derive(VmType)]
+#[gluon(gluon_vm)]
+#[gluon(vm_type = "std.collections.hashmap.mutable.HashTable")]
+pub struct HashTable<K, V> {
+    table: Mutex<Table>,
+    thread: GcPtr<Thread>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V> Userdata for HashTable<K, V>
+where
+    K: Any + Send + Sync,
+    V: Any + Send + Sync,
+{
+    fn deep_clone<'gc>(
+        &self,
+        deep_cloner: &'gc mut Cloner,
+    ) -> Result<GcRef<'gc, Box<dyn Userdata>>> {
+        let table = self.table.lock().unwrap();
+        let mut buckets = FnvMap::with_capacity_and_hasher(table.buckets.len(), Default::default());
+        for (&hash, bucket) in table.buckets.iter() {
+            buckets.insert(hash, deep_clone_bucket(bucket, deep_cloner)?);
+        }
+        // SAFETY During the `alloc` call the unrooted values are scanned through the `DataDef`
+        unsafe {
+            let data: Box<dyn Userdata> = Box::new(HashTable::<A, B> {
+                table: Mutex::new(Table {
+                    buckets,
+                    len: table.len,
+                    version: table.version,
+                }),
+                thread: GcPtr::from_raw(deep_cloner.thread()),
+                _marker: PhantomData,
+            });
+            deep_cloner.gc().alloc(Move(data))
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for HashTable<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.table.lock().unwrap().entries())
+            .finish()
+    }
+}
+
+unsafe impl<K, V> Trace for HashTable<K, V> {
+    fn trace(&self, gc: &mut Gc) {
+        for entry in self.table.lock().unwrap().entries() {
+            entry.trace(gc);
+        }
+    }
+}
+
+/// Marshals a Rust `HashMap` to and from gluon's `std.collections.hashmap.HashMap`.
+///
+/// The keys are hashed with `std.hash.structural` when the map is pushed so the map may only be
+/// used from gluon with `Hashable` instances which agree with it, such as the instances for
+/// numbers, strings, characters and booleans in `std.hash`. Rust's `HashMap` itself is marshalled
+/// as the ordered `std.map.Map`.
+#[derive(Clone, Debug)]
+pub struct HashMapValue<K, V, S = RandomState>(pub HashMap<K, V, S>);
+
+impl<K, V, S> VmType for HashMapValue<K, V, S>
+where
+    K: VmType,
+    K::Type: Sized,
+    V: VmType,
+    V::Type: Sized,
+    S: 'static,
+{
+    type Type = HashMapValue<K::Type, V::Type, S>;
+
+    fn make_type(vm: &Thread) -> ArcType {
+        let alias = vm
+            .find_type_info("std.collections.hashmap.HashMap")
+            .unwrap()
+            .clone()
+            .into_type();
+        Type::app(alias, collect![K::make_type(vm), V::make_type(vm)])
+    }
+}
+
+impl<'vm, K, V, S> Pushable<'vm> for HashMapValue<K, V, S>
+where
+    K: Pushable<'vm>,
+    V: Pushable<'vm>,
+{
+    fn vm_push(self, context: &mut ActiveThread<'vm>) -> Result<()> {
+        let len = self.0.len();
+        let stack_len = (len * 2) as VmIndex;
+        for (key, value) in self.0 {
+            key.vm_push(context)?;
+            value.vm_push(context)?;
+        }
+        let mut context = context.context();
+        let mut buckets = RedBlackTreeMapSync::<_, Bucket>::new_sync();
+        for pair in context.stack[context.stack.len() - stack_len..].chunks(2) {
+            let hash = structural_hash(Variants::new(&pair[0])).map_err(Error::Message)?;
+            let mut bucket = buckets.get(&hash).map_or_else(Vec::new, |bucket| {
+                // SAFETY The entries are rooted by the stack until the map has been allocated
+                unsafe { clone_bucket(bucket) }
+            });
+            // SAFETY The entries are rooted by the stack until the map has been allocated
+            bucket.push(unsafe { Entry::new(&pair[0], &pair[1]) });
+            buckets.insert_mut(hash, bucket);
+        }
+        let data: Box<dyn Userdata> = Box::new(PersistentHashMap::<A, B>::new(buckets, len));
+        let userdata = alloc!(context, Move(data))?;
+        context.stack.pop_many(stack_len);
+        context.stack.push(Variants::from(userdata));
+        Ok(())
+    }
+}
+
+impl<'vm, 'value, K, V, S> Getable<'vm, 'value> for HashMapValue<K, V, S>
+where
+    K: Getable<'vm, 'value> + Eq + Hash,
+    V: Getable<'vm, 'value>,
+    S: BuildHasher + Default,
+{
+    impl_getable_simple!();
+
+    fn from_value(vm: &'vm Thread, value: Variants<'value>) -> Self {
+        match value.as_ref() {
+            ValueRef::Userdata(data) => {
+                let map = data
+                    .downcast_ref::<PersistentHashMap<A, B>>()
+                    .unwrap_or_else(|| ice!("Expected a HashMap"));
+                HashMapValue(
+                    map.entries()
+                        .map(|entry| {
+                            (
+                                K::from_value(vm, Variants::new(&entry.key)),
+                                V::from_value(vm, Variants::new(&entry.value)),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            _ => ice!("ValueRef is not an Userdata"),
+        }
+    }
+}
+
+fn structural(value: OpaqueRef<A>) -> RuntimeResult<VmInt, String> {
+    match structural_hash(value.get_variant()) {
+        Ok(hash) => RuntimeResult::Return(hash),
+        Err(err) => RuntimeResult::Panic(err),
+    }
+}
+
+fn combine(l: VmInt, r: VmInt) -> VmInt {
+    let mut hasher = FnvHasher::default();
+    hasher.write_i64(l);
+    hasher.write_i64(r);
+    hasher.finish() as VmInt
+}
+
+fn empty(_: ()) -> PersistentHashMap<A, B> {
+    PersistentHashMap::new(RedBlackTreeMapSync::new_sync(), 0)
+}
+
+fn bucket(
+    hash: VmInt,
+    map: &PersistentHashMap<A, B>,
+) -> Vec<EntryRecord<Unrooted<A>, Unrooted<B>>> {
+    map.buckets
+        .get(&hash)
+        .map_or_else(Vec::new, |bucket| bucket.iter().map(entry_record).collect())
+}
+
+fn set_bucket(
+    hash: VmInt,
+    entries: Vec<EntryRecord<OpaqueRef<A>, OpaqueRef<B>>>,
+    map: &PersistentHashMap<A, B>,
+) -> PersistentHashMap<A, B> {
+    let old_len = map.buckets.get(&hash).map_or(0, |bucket| bucket.len());
+    let len = map.len - old_len + entries.len();
+    let buckets = if entries.is_empty() {
+        map.buckets.remove(&hash)
+    } else {
+        // SAFETY The entries are rooted through the returned map which is immediately pushed to
+        // the stack
+        map.buckets.insert(hash, unsafe { new_bucket(&entries) })
+    };
+    PersistentHashMap::new(buckets, len)
+}
+
+fn len(map: &PersistentHashMap<A, B>) -> VmInt {
+    map.len as VmInt
+}
+
+fn to_array(map: &PersistentHashMap<A, B>) -> Vec<EntryRecord<Unrooted<A>, Unrooted<B>>> {
+    map.entries().map(entry_record).collect()
+}
+
+fn keys(map: &PersistentHashMap<A, B>) -> Vec<Unrooted<A>> {
+    // SAFETY The keys are rooted by the map and pushed immediately to the stack
+    map.entries()
+        .map(|entry| unsafe { Unrooted::from(entry.key.clone_unrooted()) })
+        .collect()
+}
+
+fn values(map: &PersistentHashMap<A, B>) -> Vec<Unrooted<B>> {
+    // SAFETY The values are rooted by the map and pushed immediately to the stack
+    map.entries()
+        .map(|entry| unsafe { Unrooted::from(entry.value.clone_unrooted()) })
+        .collect()
+}
+
+/// Returns a map with the keys of `map` and `values`, which are in the same order as the entries
+/// of `map`
+fn replace_values(
+    values: Vec<OpaqueRef<C>>,
+    map: &PersistentHashMap<A, B>,
+) -> RuntimeResult<PersistentHashMap<A, C>, String> {
+    if values.len() != map.len {
+        return RuntimeResult::Panic(format!(
+            "Expected {} values but got {}",
+            map.len,
+            values.len()
+        ));
+    }
+    let mut values = values.iter();
+    let mut buckets = RedBlackTreeMapSync::new_sync();
+    for (&hash, bucket) in map.buckets.iter() {
+        let bucket = bucket
+            .iter()
+            // SAFETY The entries are rooted through the returned map which is immediately pushed
+            // to the stack
+            .map(|entry| unsafe { Entry::new(&entry.key, values.next().unwrap().get_value()) })
+            .collect();
+        buckets.insert_mut(hash, bucket);
+    }
+    RuntimeResult::Return(PersistentHashMap::new(buckets, map.len))
+}
+
+#[doc(hidden)]
+pub mod table {
+    use super::*;
+
+    fn new_table(vm: &Thread, capacity: usize) -> HashTable<A, B> {
+        HashTable {
+            table: Mutex::new(Table {
+                buckets: FnvMap::with_capacity_and_hasher(capacity, Default::default()),
+                ..Table::default()
+            }),
+            // SAFETY Any thread with a reference to the table also owns a reference to `vm`
+            thread: unsafe { GcPtr::from_raw(vm) },
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn new(WithVM { vm, .. }: WithVM<()>) -> IO<HashTable<A, B>> {
+        IO::Value(new_table(vm, 0))
+    }
+
+    pub(crate) fn with_capacity(WithVM { vm, value }: WithVM<VmInt>) -> IO<HashTable<A, B>> {
+        IO::Value(new_table(vm, value.max(0) as usize))
+    }
+
+    /// Returns the current version of the table together with the entries at `hash`
+    pub(crate) fn bucket(
+        hash: VmInt,
+        table: &HashTable<A, B>,
+    ) -> IO<(VmInt, Vec<EntryRecord<Unrooted<A>, Unrooted<B>>>)> {
+        let table = table.table.lock().unwrap();
+        let entries = table
+            .buckets
+            .get(&hash)
+            .map_or_else(Vec::new, |bucket| bucket.iter().map(entry_record).collect());
+        IO::Value((table.version, entries))
+    }
+
+    /// Replaces the entries at `hash` unless the table was modified after `version` was returned
+    /// by `bucket`. Returns `False` if the bucket was not replaced.
+    pub(crate) fn replace_bucket(
+        hash: VmInt,
+        version: VmInt,
+        entries: Vec<EntryRecord<OpaqueRef<A>, OpaqueRef<B>>>,
+        table: &HashTable<A, B>,
+    ) -> RuntimeResult<IO<bool>, String> {
+        let thread = &table.thread;
+        let cloned = entries
+            .iter()
+            .map(|record_p! { key, value }| {
+                let key = thread.deep_clone_value(thread, key.get_value())?;
+                Ok((key, thread.deep_clone_value(thread, value.get_value())?))
+            })
+            .collect::<Result<Vec<_>>>();
+        let cloned = match cloned {
+            Ok(cloned) => cloned,
+            Err(err) => return RuntimeResult::Panic(format!("{}", err)),
+        };
+
+        let mut table = table.table.lock().unwrap();
+        if table.version != version {
+            return RuntimeResult::Return(IO::Value(false));
+        }
+        // SAFETY Rooted when stored in the table
+        let bucket = cloned
+            .iter()
+            .map(|(key, value)| unsafe { Entry::new(key.get_value(), value.get_value()) })
+            .collect::<Bucket>();
+        let new_len = bucket.len();
+        let old_bucket = if bucket.is_empty() {
+            table.buckets.remove(&hash)
+        } else {
+            table.buckets.insert(hash, bucket)
+        };
+        table.len = table.len - old_bucket.map_or(0, |bucket| bucket.len()) + new_len;
+        table.version = table.version.wrapping_add(1);
+        RuntimeResult::Return(IO::Value(true))
+    }
+
+    pub(crate) fn len(table: &HashTable<A, B>) -> IO<VmInt> {
+        IO::Value(table.table.lock().unwrap().len as VmInt)
+    }
+
+    pub(crate) fn clear(table: &HashTable<A, B>) -> IO<()> {
+        let mut table = table.table.lock().unwrap();
+        table.buckets.clear();
+        table.len = 0;
+        table.version = table.version.wrapping_add(1);
+        IO::Value(())
+    }
+
+    pub(crate) fn to_array(
+        table: &HashTable<A, B>,
+    ) -> IO<Vec<EntryRecord<Unrooted<A>, Unrooted<B>>>> {
+        IO::Value(
+            table
+                .table
+                .lock()
+                .unwrap()
+                .entries()
+                .map(entry_record)
+                .collect(),
+        )
+    }
+
+    /// Copies the contents of the table into a persistent map
+    pub(crate) fn freeze(table: &HashTable<A, B>) -> IO<PersistentHashMap<A, B>> {
+        let table = table.table.lock().unwrap();
+        let mut buckets = RedBlackTreeMapSync::new_sync();
+        for (&hash, bucket) in table.buckets.iter() {
+            // SAFETY The entries are rooted by the table until the map is pushed to the stack
+            buckets.insert_mut(hash, unsafe { clone_bucket(bucket) });
+        }
+        IO::Value(PersistentHashMap::new(buckets, table.len))
+    }
+}
+
+mod std {
+    pub mod hash {
+        pub use crate::hash_map as prim;
+    }
+    pub mod collections {
+        pub mod hashmap {
+            pub use crate::hash_map as prim;
+            pub mod mutable {
+                pub use crate::hash_map::table as prim;
+            }
+        }
+    }
+}
+
+pub fn load_hash(vm: &Thread) -> Result<ExternModule> {
+    ExternModule::new(
+        vm,
+        record! {
+            structural => primitive!(1, std::hash::prim::structural),
+            combine => primitive!(2, std::hash::prim::combine),
+        },
+    )
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    let _ =
+        vm.register_type::<PersistentHashMap<A, B>>("std.collections.hashmap.HashMap", &["a", "b"]);
+    ExternModule::new(
+        vm,
+        record! {
+            type HashMap a b => PersistentHashMap<A, B>,
+            empty => primitive!(1, std::collections::hashmap::prim::empty),
+            bucket => primitive!(2, std::collections::hashmap::prim::bucket),
+            set_bucket => primitive!(3, std::collections::hashmap::prim::set_bucket),
+            len => primitive!(1, std::collections::hashmap::prim::len),
+            to_array => primitive!(1, std::collections::hashmap::prim::to_array),
+            keys => primitive!(1, std::collections::hashmap::prim::keys),
+            values => primitive!(1, std::collections::hashmap::prim::values),
+            replace_values => primitive!(2, std::collections::hashmap::prim::replace_values),
+        },
+    )
+}
+
+pub fn load_table(vm: &Thread) -> Result<ExternModule> {
+    let _ = vm
+        .register_type::<HashTable<A, B>>("std.collections.hashmap.mutable.HashTable", &["a", "b"]);
+    ExternModule::new(
+        vm,
+        record! {
+            type HashTable a b => HashTable<A, B>,
+            new => primitive!(1, std::collections::hashmap::mutable::prim::new),
+            with_capacity => primitive!(1, std::collections::hashmap::mutable::prim::with_capacity),
+            bucket => primitive!(2, std::collections::hashmap::mutable::prim::bucket),
+            replace_bucket => primitive!(4, std::collections::hashmap::mutable::prim::replace_bucket),
+            len => primitive!(1, std::collections::hashmap::mutable::prim::len),
+            clear => primitive!(1, std::collections::hashmap::mutable::prim::clear),
+            to_array => primitive!(1, std::collections::hashmap::mutable::prim::to_array),
+            freeze => primitive!(1, std::collections::hashmap::mutable::prim::freeze),
+        },
+    )
+}
@@ -52,13 +52,17 @@ pub mod api;
 pub mod channel;
 pub mod compiler;
 pub mod core;
+pub mod coverage;
 pub mod debug;
+pub mod duration;
 pub mod dynamic;
+pub mod hash_map;
 pub mod lazy;
 pub mod macros;
 pub mod primitives;
 pub mod reference;
 pub mod stack;
+pub mod stm;
 pub mod thread;
 pub mod types;
 pub mod vm;
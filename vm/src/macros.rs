@@ -16,11 +16,12 @@ use {
 use gluon_codegen::Trace;
 
 use crate::base::{
-    ast::{self, Expr, MutVisitor, SpannedExpr},
+    ast::{self, Expr, MutVisitor, SpannedExpr, Visitor},
     error::{AsDiagnostic, Errors as BaseErrors},
     fnv::FnvMap,
+    origin::{Desugaring, OriginMap},
     pos,
-    pos::{BytePos, Spanned},
+    pos::{BytePos, Span, Spanned},
     source::FileId,
     symbol::{Symbol, Symbols},
 };
@@ -397,7 +398,10 @@ pub struct MacroExpander<'a> {
     pub errors: Errors,
     pub userdata: &'a mut (dyn MacroUserdata + 'a),
     pub spawn: Option<&'a (dyn Spawn + Send + Sync + 'a)>,
+    /// The origins of the code produced by the expanded macros and derives
+    pub origins: OriginMap,
     macros: &'a MacroEnv,
+    symbols: Symbols,
 }
 
 impl<'a> MacroExpander<'a> {
@@ -413,6 +417,8 @@ impl<'a> MacroExpander<'a> {
             userdata,
             spawn,
             errors: Errors::new(),
+            origins: OriginMap::new(),
+            symbols: Symbols::default(),
         }
     }
 
@@ -424,9 +430,17 @@ impl<'a> MacroExpander<'a> {
             userdata,
             spawn: self.spawn,
             errors: Errors::new(),
+            origins: OriginMap::new(),
+            symbols: Symbols::default(),
         }
     }
 
+    /// The symbols of the module whose macros are currently being expanded. Identifiers created
+    /// from these refer to the same variables as identifiers written in the module itself.
+    pub fn symbols(&mut self) -> &mut Symbols {
+        &mut self.symbols
+    }
+
     pub fn finish(self) -> Result<(), Errors> {
         if self.errors.has_errors() {
             Err(self.errors)
@@ -458,36 +472,57 @@ impl<'a> MacroExpander<'a> {
         };
         visitor.visit_expr(expr);
         let MacroVisitor { exprs, .. } = visitor;
-        self.expand(arena, exprs).await
+        self.expand(symbols, arena, exprs).await
     }
 
     async fn expand<'ast>(
         &mut self,
+        symbols: &mut Symbols,
         arena: &mut ast::OwnedArena<'ast, Symbol>,
         mut exprs: Vec<(&'_ mut SpannedExpr<'ast, Symbol>, Arc<dyn Macro>)>,
     ) {
         let mut futures = Vec::with_capacity(exprs.len());
+        mem::swap(&mut self.symbols, symbols);
         for (expr, mac) in exprs.drain(..) {
-            let result = match &mut expr.value {
-                Expr::App { args, .. } => mac.expand(self, arena, args).await,
+            let (name, result) = match &mut expr.value {
+                Expr::App { func, args, .. } => {
+                    let name = match &func.value {
+                        Expr::Ident(id) => id.name.as_str().trim_end_matches('!').to_string(),
+                        _ => unreachable!("{:?}", func),
+                    };
+                    (name, mac.expand(self, arena, args).await)
+                }
                 _ => unreachable!("{:?}", expr),
             };
             match result {
-                Ok(result) => futures.push(result.compute().map(move |result| (expr, result))),
+                Ok(result) => {
+                    futures.push(result.compute().map(move |result| (expr, name, result)))
+                }
                 Err(err) => {
                     self.errors.push(pos::spanned(expr.span, err));
                     replace_expr(arena, expr, Expr::Error(None));
                 }
             }
         }
+        mem::swap(&mut self.symbols, symbols);
 
         let mut stream = futures
             .into_iter()
             .collect::<futures::stream::FuturesUnordered<_>>();
-        while let Some((expr, result)) = stream.next().await {
+        while let Some((expr, name, result)) = stream.next().await {
             let expr = { expr };
             let new_expr = match result {
-                Ok(replacement) => replacement.value,
+                Ok(replacement) => {
+                    self.origins
+                        .record(expr.span, Desugaring::Macro(name.clone()), expr.span);
+                    RecordOrigins {
+                        origins: &mut self.origins,
+                        name: &name,
+                        call: expr.span,
+                    }
+                    .visit_expr(&replacement);
+                    replacement.value
+                }
                 Err(err) => {
                     self.errors.push(pos::spanned(expr.span, err));
                     Expr::Error(None)
@@ -499,6 +534,29 @@ impl<'a> MacroExpander<'a> {
     }
 }
 
+/// Records the code produced by the macro `name` which is not part of its invocation, such as
+/// code parsed from another file, as originating from the invocation
+struct RecordOrigins<'a> {
+    origins: &'a mut OriginMap,
+    name: &'a str,
+    call: Span<BytePos>,
+}
+
+impl<'a, 'ast> Visitor<'a, 'ast> for RecordOrigins<'_> {
+    type Ident = Symbol;
+
+    fn visit_expr(&mut self, expr: &'a SpannedExpr<'ast, Symbol>) {
+        if !self.call.contains(expr.span) {
+            self.origins.record(
+                expr.span,
+                Desugaring::Macro(self.name.to_string()),
+                self.call,
+            );
+        }
+        ast::walk_expr(self, expr);
+    }
+}
+
 fn replace_expr<'ast>(
     arena: &ast::OwnedArena<'ast, Symbol>,
     expr: &mut SpannedExpr<'ast, Symbol>,
@@ -565,7 +623,17 @@ impl<'a, 'b, 'c, 'e, 'ast> MutVisitor<'e, 'ast> for MacroVisitor<'a, 'b, 'c, '_,
                             .map(|derive| {
                                 match crate::derive::generate(arena.borrow(), symbols, derive, bind)
                                 {
-                                    Ok(x) => x,
+                                    Ok(x) => {
+                                        // Derived bindings use the span of the type's name
+                                        expander.origins.record(
+                                            bind.name.span,
+                                            Desugaring::Derive(
+                                                derive.arguments.clone().unwrap_or_default(),
+                                            ),
+                                            bind.name.span,
+                                        );
+                                        x
+                                    }
                                     Err(err) => {
                                         expander.errors.push(pos::spanned(bind.name.span, err));
                                         Vec::new()
@@ -26,6 +26,8 @@ use crate::{
     Error, ExternModule, Result, Variants,
 };
 
+use crate::gated_primitive;
+
 #[doc(hidden)]
 pub mod array {
     use super::*;
@@ -465,6 +467,9 @@ mod std {
     pub mod path {
         pub type prim = ::std::path::Path;
     }
+    pub mod fs {
+        pub use crate::primitives::fs_prim as prim;
+    }
     pub mod effect {
         pub mod st {
             pub mod string {
@@ -736,23 +741,251 @@ unsafe impl Trace for DirEntry {
     impl_trace! { self, _gc, { } }
 }
 
+#[derive(Pushable, VmType)]
+#[gluon(vm_type = "std.fs.types.ErrorKind")]
+#[gluon(gluon_vm)]
+pub enum FsErrorKind {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    InvalidInput,
+    InvalidData,
+    Other,
+}
+
+#[derive(Pushable, VmType)]
+#[gluon(vm_type = "std.fs.types.Error")]
+#[gluon(gluon_vm)]
+pub struct FsError {
+    kind: FsErrorKind,
+    path: StdString,
+    message: StdString,
+}
+
+impl FsError {
+    fn new(path: &Path, err: io::Error) -> Self {
+        let kind = match err.kind() {
+            io::ErrorKind::NotFound => FsErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => FsErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => FsErrorKind::AlreadyExists,
+            io::ErrorKind::InvalidInput => FsErrorKind::InvalidInput,
+            io::ErrorKind::InvalidData => FsErrorKind::InvalidData,
+            _ => FsErrorKind::Other,
+        };
+        FsError {
+            kind,
+            path: path.display().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub mod fs_prim {
+    use super::*;
+
+    use crate::real_std::{
+        env,
+        fs::OpenOptions,
+        io::Write,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    pub type FsResult<T> = IO<StdResult<T, FsError>>;
+
+    fn fs_result<T>(path: &Path, result: io::Result<T>) -> FsResult<T> {
+        IO::Value(result.map_err(|err| FsError::new(path, err)))
+    }
+
+    pub(crate) fn read_file(path: &Path) -> FsResult<Vec<u8>> {
+        fs_result(path, fs::read(path))
+    }
+
+    pub(crate) fn read_file_to_string(path: &Path) -> FsResult<StdString> {
+        fs_result(path, fs::read_to_string(path))
+    }
+
+    pub(crate) fn write_file(path: &Path, contents: &str) -> FsResult<()> {
+        fs_result(path, fs::write(path, contents))
+    }
+
+    pub(crate) fn write_file_bytes(path: &Path, contents: &[u8]) -> FsResult<()> {
+        fs_result(path, fs::write(path, contents))
+    }
+
+    pub(crate) fn append_file(path: &Path, contents: &str) -> FsResult<()> {
+        fs_result(
+            path,
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(contents.as_bytes())),
+        )
+    }
+
+    pub(crate) fn file_metadata(path: &Path) -> FsResult<Metadata> {
+        fs_result(path, fs::metadata(path).map(Metadata))
+    }
+
+    pub(crate) fn exists(path: &Path) -> IO<bool> {
+        IO::Value(path.exists())
+    }
+
+    pub(crate) fn create_dir(path: &Path) -> FsResult<()> {
+        fs_result(path, fs::create_dir(path))
+    }
+
+    pub(crate) fn create_dir_all(path: &Path) -> FsResult<()> {
+        fs_result(path, fs::create_dir_all(path))
+    }
+
+    pub(crate) fn remove_file(path: &Path) -> FsResult<()> {
+        fs_result(path, fs::remove_file(path))
+    }
+
+    pub(crate) fn remove_dir(path: &Path) -> FsResult<()> {
+        fs_result(path, fs::remove_dir(path))
+    }
+
+    pub(crate) fn remove_dir_all(path: &Path) -> FsResult<()> {
+        fs_result(path, fs::remove_dir_all(path))
+    }
+
+    pub(crate) fn rename(from: &Path, to: &Path) -> FsResult<()> {
+        fs_result(from, fs::rename(from, to))
+    }
+
+    pub(crate) fn copy(from: &Path, to: &Path) -> FsResult<()> {
+        fs_result(from, fs::copy(from, to).map(|_| ()))
+    }
+
+    pub(crate) fn list_dir(path: &Path) -> FsResult<Vec<PathBuf>> {
+        fs_result(
+            path,
+            fs::read_dir(path).and_then(|entries| {
+                let mut paths = entries
+                    .map(|entry| Ok(entry?.path()))
+                    .collect::<io::Result<Vec<_>>>()?;
+                paths.sort();
+                Ok(paths)
+            }),
+        )
+    }
+
+    fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> StdResult<(), FsError> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|err| FsError::new(dir, err))?;
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            let path = entry.path();
+            // Symlinks are not followed so that a link to a parent directory can't cause the
+            // walk to loop forever
+            let file_type = entry.file_type().map_err(|err| FsError::new(&path, err))?;
+            paths.push(path.clone());
+            if file_type.is_dir() {
+                walk(&path, paths)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn walk_dir(path: &Path) -> FsResult<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        IO::Value(walk(path, &mut paths).map(|()| paths))
+    }
+
+    pub(crate) fn temp_dir() -> IO<PathBuf> {
+        IO::Value(env::temp_dir())
+    }
+
+    /// Creates a new file or directory, using `create`, in the temporary directory. The name
+    /// starts with `prefix` followed by a suffix which is unique to this process.
+    fn create_temp(prefix: &str, create: fn(&Path) -> io::Result<()>) -> FsResult<PathBuf> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        loop {
+            let path = dir.join(format!(
+                "{}{}-{}-{}",
+                prefix,
+                process::id(),
+                nanos,
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match create(&path) {
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                result => return fs_result(&path, result.map(|()| path.clone())),
+            }
+        }
+    }
+
+    pub(crate) fn create_temp_file(prefix: &str) -> FsResult<PathBuf> {
+        create_temp(prefix, |path| {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map(|_| ())
+        })
+    }
+
+    pub(crate) fn create_temp_dir(prefix: &str) -> FsResult<PathBuf> {
+        create_temp(prefix, |path| fs::create_dir(path))
+    }
+}
+
 pub fn load_fs(vm: &Thread) -> Result<ExternModule> {
     vm.register_type::<Metadata>("std.fs.Metadata", &[])?;
     vm.register_type::<DirEntry>("std.fs.DirEntry", &[])?;
 
+    use self::fs_prim::FsResult;
+
+    // Primitives which need the `fs` capability are replaced by ones which throw an exception if
+    // it has not been granted
+    let capabilities = vm.global_env().capabilities();
+
     ExternModule::new(
         vm,
         record! {
             type Metadata => Metadata,
             type DirEntry => DirEntry,
 
-            read_dir => primitive!(1, "std.fs.prim.read_dir", |p: &Path| {
+            read_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.read_dir", |p: &Path| {
                 IO::from(fs::read_dir(p).and_then(|iter| iter.map(|result| result.map(DirEntry)).collect::<io::Result<Vec<_>>>()))
-            }),
+            }, (&Path) -> IO<Vec<DirEntry>>),
+
+            read_file => gated_primitive!(capabilities, fs, 1, "std.fs.prim.read_file", std::fs::prim::read_file, (&Path) -> FsResult<Vec<u8>>),
+            read_file_to_string => gated_primitive!(capabilities, fs, 1, "std.fs.prim.read_file_to_string", std::fs::prim::read_file_to_string, (&Path) -> FsResult<StdString>),
+            write_file => gated_primitive!(capabilities, fs, 2, "std.fs.prim.write_file", std::fs::prim::write_file, (&Path, &str) -> FsResult<()>),
+            write_file_bytes => gated_primitive!(capabilities, fs, 2, "std.fs.prim.write_file_bytes", std::fs::prim::write_file_bytes, (&Path, &[u8]) -> FsResult<()>),
+            append_file => gated_primitive!(capabilities, fs, 2, "std.fs.prim.append_file", std::fs::prim::append_file, (&Path, &str) -> FsResult<()>),
+            file_metadata => gated_primitive!(capabilities, fs, 1, "std.fs.prim.file_metadata", std::fs::prim::file_metadata, (&Path) -> FsResult<Metadata>),
+            exists => gated_primitive!(capabilities, fs, 1, "std.fs.prim.exists", std::fs::prim::exists, (&Path) -> IO<bool>),
+            create_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.create_dir", std::fs::prim::create_dir, (&Path) -> FsResult<()>),
+            create_dir_all => gated_primitive!(capabilities, fs, 1, "std.fs.prim.create_dir_all", std::fs::prim::create_dir_all, (&Path) -> FsResult<()>),
+            remove_file => gated_primitive!(capabilities, fs, 1, "std.fs.prim.remove_file", std::fs::prim::remove_file, (&Path) -> FsResult<()>),
+            remove_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.remove_dir", std::fs::prim::remove_dir, (&Path) -> FsResult<()>),
+            remove_dir_all => gated_primitive!(capabilities, fs, 1, "std.fs.prim.remove_dir_all", std::fs::prim::remove_dir_all, (&Path) -> FsResult<()>),
+            rename => gated_primitive!(capabilities, fs, 2, "std.fs.prim.rename", std::fs::prim::rename, (&Path, &Path) -> FsResult<()>),
+            copy => gated_primitive!(capabilities, fs, 2, "std.fs.prim.copy", std::fs::prim::copy, (&Path, &Path) -> FsResult<()>),
+            list_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.list_dir", std::fs::prim::list_dir, (&Path) -> FsResult<Vec<path::PathBuf>>),
+            walk_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.walk_dir", std::fs::prim::walk_dir, (&Path) -> FsResult<Vec<path::PathBuf>>),
+            temp_dir => gated_primitive!(capabilities, fs, 0, "std.fs.prim.temp_dir", std::fs::prim::temp_dir, () -> IO<path::PathBuf>),
+            create_temp_file => gated_primitive!(capabilities, fs, 1, "std.fs.prim.create_temp_file", std::fs::prim::create_temp_file, (&str) -> FsResult<path::PathBuf>),
+            create_temp_dir => gated_primitive!(capabilities, fs, 1, "std.fs.prim.create_temp_dir", std::fs::prim::create_temp_dir, (&str) -> FsResult<path::PathBuf>),
 
             dir_entry => record! {
                 path => primitive!(1, "std.fs.prim.dir_entry.path", |m: &DirEntry| m.0.path()),
-                metadata => primitive!(1, "std.fs.prim.dir_entry.metadata", |m: &DirEntry| IO::from(m.0.metadata().map(Metadata))),
+                metadata => gated_primitive!(capabilities, fs, 1, "std.fs.prim.dir_entry.metadata", |m: &DirEntry| IO::from(m.0.metadata().map(Metadata)), (&DirEntry) -> IO<Metadata>),
                 file_name => primitive!(1, "std.fs.prim.dir_entry.file_name", |m: &DirEntry| m.0.file_name()),
             },
 
@@ -760,12 +993,17 @@ pub fn load_fs(vm: &Thread) -> Result<ExternModule> {
                 is_dir => primitive!(1, "std.fs.prim.metadata.is_dir", |m: &Metadata| m.0.is_dir()),
                 is_file => primitive!(1, "std.fs.prim.metadata.is_file", |m: &Metadata| m.0.is_file()),
                 len => primitive!(1, "std.fs.prim.metadata.len", |m: &Metadata| m.0.len()),
+                readonly => primitive!(1, "std.fs.prim.metadata.readonly", |m: &Metadata| m.0.permissions().readonly()),
             },
         },
     )
 }
 
 pub fn load_path(vm: &Thread) -> Result<ExternModule> {
+    // Primitives which query the filesystem need the `fs` capability, the others only look at the
+    // path itself
+    let capabilities = vm.global_env().capabilities();
+
     ExternModule::new(
         vm,
         record! {
@@ -794,22 +1032,25 @@ pub fn load_path(vm: &Thread) -> Result<ExternModule> {
                     })
                     .collect::<Vec<_>>()
             }),
-            metadata => primitive!(1, "std.path.prim.metadata", |p: &Path| IO::from(p.metadata().map(Metadata))),
-            symlink_metadata => primitive!(1, "std.path.prim.symlink_metadata", |p: &Path| IO::from(p.symlink_metadata().map(Metadata))),
-            canonicalize => primitive!(1, "std.path.prim.canonicalize", |p: &Path| IO::from(p.canonicalize())),
-            read_link => primitive!(1, "std.path.prim.read_link", |p: &Path| IO::from(p.read_link())),
-            read_dir => primitive!(
+            metadata => gated_primitive!(capabilities, fs, 1, "std.path.prim.metadata", |p: &Path| IO::from(p.metadata().map(Metadata)), (&Path) -> IO<Metadata>),
+            symlink_metadata => gated_primitive!(capabilities, fs, 1, "std.path.prim.symlink_metadata", |p: &Path| IO::from(p.symlink_metadata().map(Metadata)), (&Path) -> IO<Metadata>),
+            canonicalize => gated_primitive!(capabilities, fs, 1, "std.path.prim.canonicalize", |p: &Path| IO::from(p.canonicalize()), (&Path) -> IO<path::PathBuf>),
+            read_link => gated_primitive!(capabilities, fs, 1, "std.path.prim.read_link", |p: &Path| IO::from(p.read_link()), (&Path) -> IO<path::PathBuf>),
+            read_dir => gated_primitive!(
+                capabilities,
+                fs,
                 1,
                 "std.path.prim.read_dir",
                 |p: &Path| IO::from(
                         p.read_dir()
                             .and_then(|iter| iter.map(|result| Ok(result?.path())).collect::<StdResult<Vec<_>, _>>())
                             .map_err(|err| Error::Message(err.to_string()))
-                    )
+                    ),
+                (&Path) -> IO<Vec<path::PathBuf>>
             ),
-            exists => primitive!(1, "std.path.prim.exists", |p: &Path| IO::Value(p.exists())),
-            is_file => primitive!(1, "std.path.prim.is_file", |p: &Path| IO::Value(p.is_file())),
-            is_dir => primitive!(1, "std.path.prim.is_file", |p: &Path| IO::Value(p.is_dir())),
+            exists => gated_primitive!(capabilities, fs, 1, "std.path.prim.exists", |p: &Path| IO::Value(p.exists()), (&Path) -> IO<bool>),
+            is_file => gated_primitive!(capabilities, fs, 1, "std.path.prim.is_file", |p: &Path| IO::Value(p.is_file()), (&Path) -> IO<bool>),
+            is_dir => gated_primitive!(capabilities, fs, 1, "std.path.prim.is_dir", |p: &Path| IO::Value(p.is_dir()), (&Path) -> IO<bool>),
         },
     )
 }
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, cell::RefCell, marker::PhantomData, rc::Rc, sync::Arc};
+use std::{any::TypeId, borrow::Cow, cell::RefCell, marker::PhantomData, mem, rc::Rc, sync::Arc};
 
 use itertools::Itertools;
 
@@ -9,6 +9,7 @@ use crate::serde::{
 };
 
 use crate::base::{
+    fnv::FnvMap,
     serialization::{NodeMap, NodeToId, SharedSeed},
     symbol::{Symbol, Symbols},
     types::ArcType,
@@ -16,23 +17,68 @@ use crate::base::{
 
 use crate::{
     array::Array,
-    gc::{CloneUnrooted, DataDef, GcPtr, GcRef, OwnedGcRef, WriteOnly},
+    gc::{CloneUnrooted, DataDef, GcPtr, GcRef, Move, OwnedGcRef, WriteOnly},
     stack::State,
-    thread::{ActiveThread, ExecuteContext, RootedThread, RootedValue, Thread, ThreadInternal},
+    thread::{
+        ActiveThread, ExecuteContext, RootedThread, RootedValue, Thread, ThreadInternal,
+        VmRootInternal,
+    },
     types::VmIndex,
     value::{
         BytecodeFunction, Callable, ClosureData, ExternFunction, PartialApplicationData,
-        PartialApplicationDataDef, Value, ValueArray, ValueRepr,
+        PartialApplicationDataDef, Userdata, Value, ValueArray, ValueRepr,
     },
     Variants,
 };
 
+struct UserdataHook {
+    name: String,
+    serialize: Box<dyn Fn(&dyn Userdata) -> Vec<u8> + Send + Sync>,
+    deserialize: Box<dyn Fn(&[u8]) -> Result<Box<dyn Userdata>, String> + Send + Sync>,
+}
+
+/// Conversion functions which lets userdata be serialized. As userdata is opaque to the virtual
+/// machine, only types which have a hook registered can be serialized.
+#[derive(Clone, Default)]
+pub struct UserdataHooks {
+    by_type: FnvMap<TypeId, Arc<UserdataHook>>,
+    by_name: FnvMap<String, Arc<UserdataHook>>,
+}
+
+impl UserdataHooks {
+    /// Registers `serialize` and `deserialize` as the functions which converts `T` to and from
+    /// bytes. `name` is stored with the bytes to identify which `deserialize` function to use.
+    pub fn register<T, S, D>(&mut self, name: &str, serialize: S, deserialize: D)
+    where
+        T: Userdata,
+        S: Fn(&T) -> Vec<u8> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Result<T, String> + Send + Sync + 'static,
+    {
+        let hook = Arc::new(UserdataHook {
+            name: name.into(),
+            serialize: Box::new(move |data| {
+                serialize(
+                    data.downcast_ref::<T>()
+                        .expect("Userdata hook called on wrong type"),
+                )
+            }),
+            deserialize: Box::new(move |bytes| {
+                deserialize(bytes).map(|data| Box::new(data) as Box<dyn Userdata>)
+            }),
+        });
+        self.by_type.insert(TypeId::of::<T>(), hook.clone());
+        self.by_name.insert(name.into(), hook);
+    }
+}
+
 pub struct DeSeed<'gc> {
     pub thread: RootedThread,
     context: ExecuteContext<'gc, 'gc, State>,
     symbols: Rc<RefCell<Symbols>>,
     gc_map: NodeMap,
     base_seed: crate::base::serialization::Seed<Symbol, ArcType<Symbol>>,
+    userdata_hooks: UserdataHooks,
+    extern_functions: FnvMap<String, ExternFunction>,
 }
 
 impl<'de, 'gc> DeSeed<'gc> {
@@ -43,7 +89,46 @@ impl<'de, 'gc> DeSeed<'gc> {
             gc_map: NodeMap::default(),
             base_seed: Default::default(),
             context: context.context(),
+            userdata_hooks: Default::default(),
+            extern_functions: Default::default(),
+        }
+    }
+
+    /// Lets userdata be deserialized using `hooks`
+    pub fn with_userdata_hooks(mut self, hooks: UserdataHooks) -> Self {
+        self.userdata_hooks = hooks;
+        self
+    }
+
+    /// Lets extern functions be resolved from the records of the loaded extern modules in
+    /// `modules`. The id of an extern function does not always match the path it is exported
+    /// under (`std.int.shl` is exported as `std.int.prim.shl`) so looking it up as a global may
+    /// fail.
+    pub fn with_extern_modules<'a>(
+        mut self,
+        modules: impl IntoIterator<Item = &'a str>,
+    ) -> crate::Result<Self> {
+        fn collect(value: &ValueRepr, extern_functions: &mut FnvMap<String, ExternFunction>) {
+            match value {
+                ValueRepr::Function(function) => {
+                    extern_functions.insert(function.id.to_string(), (**function).clone());
+                }
+                ValueRepr::Data(data) => {
+                    for field in &data.fields {
+                        collect(field.get_repr(), extern_functions);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for module in modules {
+            let value = self
+                .thread
+                .get_global::<crate::api::OpaqueValue<RootedThread, crate::api::Hole>>(module)?;
+            collect(value.get_value().get_repr(), &mut self.extern_functions);
         }
+        Ok(self)
     }
 
     pub fn deserialize<D, T>(mut self, deserializer: D) -> Result<T, D::Error>
@@ -63,6 +148,7 @@ impl AsMut<NodeMap> for DeSeed<'_> {
 
 pub struct SeSeed {
     node_to_id: crate::base::serialization::SeSeed,
+    userdata_hooks: UserdataHooks,
 }
 
 impl AsRef<NodeToId> for SeSeed {
@@ -75,8 +161,15 @@ impl SeSeed {
     pub fn new() -> SeSeed {
         SeSeed {
             node_to_id: Default::default(),
+            userdata_hooks: Default::default(),
         }
     }
+
+    /// Lets userdata be serialized using `hooks`
+    pub fn with_userdata_hooks(mut self, hooks: UserdataHooks) -> Self {
+        self.userdata_hooks = hooks;
+        self
+    }
 }
 
 fn gc_seed<S, T>(seed: &mut S) -> SharedSeed<GcPtr<T>, S> {
@@ -698,35 +791,56 @@ where
 }
 
 pub trait PostDeserialize {
-    fn init<'gc>(parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self>;
+    fn init<'gc>(parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> crate::Result<GcRef<'gc, Self>>;
 }
 
 impl PostDeserialize for PartialApplicationData {
-    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self> {
-        ptr.into()
+    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> crate::Result<GcRef<'gc, Self>> {
+        Ok(ptr.into())
     }
 }
 
 impl PostDeserialize for ValueArray {
-    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self> {
-        ptr.into()
+    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> crate::Result<GcRef<'gc, Self>> {
+        Ok(ptr.into())
     }
 }
 
 impl PostDeserialize for ExternFunction {
-    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self> {
-        ptr.into()
+    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> crate::Result<GcRef<'gc, Self>> {
+        Ok(ptr.into())
     }
 }
 
 impl PostDeserialize for BytecodeFunction {
-    fn init<'gc>(_parent: &Thread, ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self> {
-        ptr.into()
+    fn init<'gc>(
+        parent: &Thread,
+        mut ptr: OwnedGcRef<'gc, Self>,
+    ) -> crate::Result<GcRef<'gc, Self>> {
+        // Functions are shared between all threads so they must be moved into the global heap,
+        // same as the functions created by the compiler
+        let function = BytecodeFunction {
+            name: ptr.name.clone(),
+            args: ptr.args,
+            max_stack_size: ptr.max_stack_size,
+            instructions: mem::take(&mut ptr.instructions),
+            inner_functions: mem::take(&mut ptr.inner_functions),
+            strings: mem::take(&mut ptr.strings),
+            records: mem::take(&mut ptr.records),
+            debug_info: mem::take(&mut ptr.debug_info),
+        };
+        let mut gc = parent.global_env().gc.lock().unwrap();
+        let function = gc.alloc(Move(function))?;
+        // SAFETY The global heap outlives the heap of the deserializing thread
+        unsafe { Ok(GcRef::with_root(function.unrooted(), ptr.as_lifetime())) }
     }
 }
 
 impl PostDeserialize for Thread {
-    fn init<'gc>(parent: &Thread, mut ptr: OwnedGcRef<'gc, Self>) -> GcRef<'gc, Self> {
+    fn init<'gc>(
+        parent: &Thread,
+        mut ptr: OwnedGcRef<'gc, Self>,
+    ) -> crate::Result<GcRef<'gc, Self>> {
         let mut parent_threads = parent.child_threads.write().unwrap();
         let entry = parent_threads.vacant_entry();
         ptr.thread_index = entry.key();
@@ -734,7 +848,7 @@ impl PostDeserialize for Thread {
         unsafe {
             entry.insert(ptr.clone().unrooted());
         }
-        ptr
+        Ok(ptr)
     }
 }
 
@@ -782,7 +896,8 @@ where
                     .gc
                     .alloc_owned(def)
                     .map_err(D::Error::custom)?;
-                unsafe { Ok(T::Value::init(&seed.state.thread, ptr).unrooted()) }
+                let ptr = T::Value::init(&seed.state.thread, ptr).map_err(D::Error::custom)?;
+                unsafe { Ok(ptr.unrooted()) }
             }
         }
 
@@ -808,6 +923,13 @@ impl<'de, 'gc> DeserializeState<'de, DeSeed<'gc>> for ExternFunction {
         }
 
         let partial = ExternFunction_::deserialize(deserializer)?;
+        if let Some(function) = seed.extern_functions.get(&partial.id[..]) {
+            return if partial.args == function.args {
+                Ok(function.clone())
+            } else {
+                Err(D::Error::custom("Invalid type for extern function"))
+            };
+        }
         // Wrap any operators with parens so that they are acceptable for `get_global`
         let mut escaped_id = Cow::Borrowed("");
         let iter = partial
@@ -854,12 +976,52 @@ where
     }
 }
 
-pub fn serialize_userdata<S, T>(_: &T, _: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    use crate::serde::ser::Error;
-    Err(S::Error::custom("Userdata cannot be serialized"))
+pub mod userdata {
+    use super::*;
+    use crate::gc::Move;
+    use crate::serde::ser::Error as _;
+
+    pub fn deserialize<'de, 'gc, D>(
+        seed: &mut DeSeed<'gc>,
+        deserializer: D,
+    ) -> Result<GcPtr<Box<dyn Userdata>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (name, bytes) = <(String, Vec<u8>)>::deserialize(deserializer)?;
+        let hook = seed.userdata_hooks.by_name.get(&name).ok_or_else(|| {
+            D::Error::custom(format!(
+                "No deserializer is registered for userdata `{}`",
+                name
+            ))
+        })?;
+        let data = (hook.deserialize)(&bytes).map_err(D::Error::custom)?;
+        let ptr = seed
+            .context
+            .gc
+            .alloc_owned(Move(data))
+            .map_err(D::Error::custom)?;
+        unsafe { Ok(GcRef::from(ptr).unrooted()) }
+    }
+
+    pub fn serialize<S>(
+        self_: &GcPtr<Box<dyn Userdata>>,
+        serializer: S,
+        seed: &SeSeed,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let data: &dyn Userdata = &***self_;
+        let hook = seed
+            .userdata_hooks
+            .by_type
+            .get(&data.as_any().type_id())
+            .ok_or_else(|| {
+                S::Error::custom(format!("Userdata cannot be serialized: {:?}", data))
+            })?;
+        (&hook.name, (hook.serialize)(data)).serialize(serializer)
+    }
 }
 
 impl<'a> crate::serde::ser::SerializeState<crate::serialization::SeSeed> for Variants<'a> {
@@ -876,6 +1038,19 @@ impl<'a> crate::serde::ser::SerializeState<crate::serialization::SeSeed> for Var
     }
 }
 
+impl<T> SerializeState<SeSeed> for RootedValue<T>
+where
+    T: VmRootInternal,
+{
+    #[inline]
+    fn serialize_state<S>(&self, serializer: S, seed: &SeSeed) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.get_variant().serialize_state(serializer, seed)
+    }
+}
+
 impl<'de, 'gc> DeserializeState<'de, DeSeed<'gc>> for RootedValue<RootedThread> {
     fn deserialize_state<D>(seed: &mut DeSeed<'gc>, deserializer: D) -> Result<Self, D::Error>
     where
@@ -0,0 +1,373 @@
+//! Primitives for software transactional memory (`std.stm`).
+//!
+//! A `TVar` stores its value together with a version which is incremented on every commit that
+//! writes to it. Transactions record the version of every `TVar` they read and buffer their
+//! writes, on commit the read versions are validated (with all involved `TVar`s locked) and the
+//! writes are published only if no other transaction has committed a conflicting write.
+use crate::real_std::{
+    any::Any,
+    fmt,
+    marker::PhantomData,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use futures::Future;
+
+use crate::{
+    api::{generic::A, Generic, Unrooted, Userdata, WithVM, IO},
+    gc::{CloneUnrooted, Gc, GcPtr, GcRef, Generation, Move, Trace},
+    thread::ThreadInternal,
+    types::VmInt,
+    value::{Cloner, Value},
+    vm::Thread,
+    Error, ExternModule, Result,
+};
+
+struct TVarState {
+    value: Value,
+    version: usize,
+    /// Transactions which called `retry` after reading this `TVar`, identified by the id of
+    /// their `Wait`
+    waiters: Vec<(usize, Waker)>,
+}
+
+struct TVarCell {
+    state: Mutex<TVarState>,
+    // No need to traverse this thread reference as any thread having a reference to this `TVar`
+    // would also directly own a reference to the `Thread`
+    thread: GcPtr<Thread>,
+    /// The generation of the heap of `thread`, which the value is stored in
+    generation: Generation,
+}
+
+impl TVarCell {
+    fn lock(&self) -> MutexGuard<TVarState> {
+        self.state.lock().unwrap()
+    }
+}
+
+#[derive(VmType)]
+#[gluon(gluon_vm)]
+#[gluon(vm_type = "std.stm.TVar")]
+pub struct TVar<T> {
+    cell: Arc<TVarCell>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Userdata for TVar<T>
+where
+    T: Any + Send + Sync,
+{
+    // The clone shares the cell so that both refer to the same variable
+    fn deep_clone<'gc>(
+        &self,
+        deep_cloner: &'gc mut Cloner,
+    ) -> Result<GcRef<'gc, Box<dyn Userdata>>> {
+        if !deep_cloner.can_contain_values_from(self.cell.generation) {
+            return Err(Error::Message(
+                "A `TVar` can only be shared with the thread which created it and its children"
+                    .into(),
+            ));
+        }
+        let data: Box<dyn Userdata> = Box::new(TVar::<T> {
+            cell: self.cell.clone(),
+            _marker: PhantomData,
+        });
+        deep_cloner.gc().alloc(Move(data))
+    }
+}
+
+impl<T> fmt::Debug for TVar<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TVar({:?})", self.cell.lock().value)
+    }
+}
+
+// Don't root/unroot the contents as an unrooted value could be moved out of the TVar
+unsafe impl<T> Trace for TVar<T> {
+    fn trace(&self, gc: &mut Gc) {
+        self.cell.lock().value.trace(gc)
+    }
+}
+
+#[derive(Default)]
+struct Log {
+    reads: Vec<(Arc<TVarCell>, usize)>,
+    writes: Vec<(Arc<TVarCell>, Value)>,
+    /// Set if a `TVar` was observed with two different versions during the transaction
+    conflict: bool,
+}
+
+#[derive(VmType)]
+#[gluon(gluon_vm)]
+#[gluon(vm_type = "std.stm.Transaction")]
+pub struct Transaction {
+    log: Mutex<Log>,
+}
+
+impl Userdata for Transaction {}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let log = self.log.lock().unwrap();
+        f.debug_struct("Transaction")
+            .field("reads", &log.reads.len())
+            .field("writes", &log.writes.len())
+            .field("conflict", &log.conflict)
+            .finish()
+    }
+}
+
+unsafe impl Trace for Transaction {
+    fn trace(&self, gc: &mut Gc) {
+        for (_, value) in &self.log.lock().unwrap().writes {
+            value.trace(gc);
+        }
+    }
+}
+
+fn new_tvar(a: WithVM<Generic<A>>) -> TVar<A> {
+    let generation = a.vm.context().gc.generation();
+    // SAFETY The value is rooted through the returned `TVar` which is immediately pushed to the
+    // stack
+    unsafe {
+        TVar {
+            cell: Arc::new(TVarCell {
+                state: Mutex::new(TVarState {
+                    value: a.value.get_value().clone_unrooted(),
+                    version: 0,
+                    waiters: Vec::new(),
+                }),
+                thread: GcPtr::from_raw(a.vm),
+                generation,
+            }),
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn begin(_: ()) -> IO<Transaction> {
+    IO::Value(Transaction {
+        log: Mutex::new(Log::default()),
+    })
+}
+
+fn read(tx: &Transaction, tvar: &TVar<A>) -> Unrooted<A> {
+    let mut log = tx.log.lock().unwrap();
+    if let Some((_, value)) = log
+        .writes
+        .iter()
+        .rev()
+        .find(|(cell, _)| Arc::ptr_eq(cell, &tvar.cell))
+    {
+        // SAFETY The value is rooted by the transaction and gets pushed immediately to the stack
+        return unsafe { Unrooted::from(value.clone_unrooted()) };
+    }
+
+    let state = tvar.cell.lock();
+    match log
+        .reads
+        .iter()
+        .find(|(cell, _)| Arc::ptr_eq(cell, &tvar.cell))
+    {
+        Some(&(_, version)) => {
+            if version != state.version {
+                log.conflict = true;
+            }
+        }
+        None => log.reads.push((tvar.cell.clone(), state.version)),
+    }
+    // SAFETY The returned, unrooted value gets pushed immediately to the stack
+    unsafe { Unrooted::from(state.value.clone_unrooted()) }
+}
+
+// `write` and `rollback` return `Some ()` so that their result can be returned from the `Stm`
+// action, a call whose result is unused would be removed by the optimizer.
+fn write(tx: &Transaction, tvar: &TVar<A>, value: Generic<A>) -> Option<()> {
+    // SAFETY Rooted when stored in the transaction
+    unsafe {
+        tx.log
+            .lock()
+            .unwrap()
+            .writes
+            .push((tvar.cell.clone(), value.get_value().clone_unrooted()));
+    }
+    Some(())
+}
+
+fn checkpoint(tx: &Transaction) -> VmInt {
+    tx.log.lock().unwrap().writes.len() as VmInt
+}
+
+fn rollback(tx: &Transaction, checkpoint: VmInt) -> Option<()> {
+    // The reads are kept so that a `retry` in both branches of an `or_else` waits on all `TVar`s
+    tx.log.lock().unwrap().writes.truncate(checkpoint as usize);
+    Some(())
+}
+
+fn commit(tx: &Transaction) -> IO<bool> {
+    // The transaction keeps the written values alive for the duration of the commit. The log is
+    // not kept locked as cloning the values may trigger a collection which traces `tx`.
+    let (reads, writes) = {
+        let log = tx.log.lock().unwrap();
+        if log.conflict {
+            return IO::Value(false);
+        }
+        // SAFETY The values are rooted by `tx`
+        let writes: Vec<_> = unsafe {
+            log.writes
+                .iter()
+                .map(|(cell, value)| (cell.clone(), value.clone_unrooted()))
+                .collect()
+        };
+        (log.reads.clone(), writes)
+    };
+
+    // Move the written values into the heap of each `TVar` before taking any locks
+    let mut cloned_writes = Vec::with_capacity(writes.len());
+    for (cell, value) in &writes {
+        match cell.thread.deep_clone_value(&cell.thread, value) {
+            Ok(value) => cloned_writes.push((cell, value)),
+            Err(err) => return IO::Exception(err.to_string()),
+        }
+    }
+
+    // Lock every involved `TVar` in a consistent order so that concurrent commits can't deadlock
+    let mut cells: Vec<&Arc<TVarCell>> = reads
+        .iter()
+        .map(|(cell, _)| cell)
+        .chain(writes.iter().map(|(cell, _)| cell))
+        .collect();
+    cells.sort_by_key(|cell| Arc::as_ptr(cell));
+    cells.dedup_by(|l, r| Arc::ptr_eq(l, r));
+    let mut guards: Vec<_> = cells.iter().map(|cell| cell.lock()).collect();
+    let index_of = |cell: &Arc<TVarCell>| {
+        cells
+            .binary_search_by_key(&Arc::as_ptr(cell), |cell| Arc::as_ptr(cell))
+            .unwrap()
+    };
+
+    for (cell, version) in &reads {
+        if guards[index_of(cell)].version != *version {
+            return IO::Value(false);
+        }
+    }
+
+    let mut written = vec![false; guards.len()];
+    let mut waiters = Vec::new();
+    for (cell, value) in &cloned_writes {
+        let index = index_of(cell);
+        let state = &mut guards[index];
+        // SAFETY Rooted when stored in the `TVar`
+        state.value = unsafe { value.get_value().clone_unrooted() };
+        if !written[index] {
+            written[index] = true;
+            state.version += 1;
+            waiters.extend(
+                mem::replace(&mut state.waiters, Vec::new())
+                    .into_iter()
+                    .map(|(_, waker)| waker),
+            );
+        }
+    }
+    drop(guards);
+
+    for waker in waiters {
+        waker.wake();
+    }
+
+    IO::Value(true)
+}
+
+/// Waits for a commit to one of the `TVar`s that a transaction read before calling `retry`
+struct Wait {
+    /// Identifies the wakers that this `Wait` registered
+    id: usize,
+    reads: Vec<(Arc<TVarCell>, usize)>,
+    conflict: bool,
+}
+
+impl Future for Wait {
+    type Output = IO<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IO<()>> {
+        if self.conflict {
+            return Poll::Ready(IO::Value(()));
+        }
+        if self.reads.is_empty() {
+            return Poll::Ready(IO::Exception(
+                "Transaction retried without reading any `TVar`".to_string(),
+            ));
+        }
+        for (cell, version) in &self.reads {
+            // The waker must be registered while the state is locked, otherwise a commit could
+            // happen between the version check and the registration
+            let mut state = cell.lock();
+            if state.version != *version {
+                return Poll::Ready(IO::Value(()));
+            }
+            match state.waiters.iter_mut().find(|(id, _)| *id == self.id) {
+                Some((_, waker)) => {
+                    if !waker.will_wake(cx.waker()) {
+                        *waker = cx.waker().clone();
+                    }
+                }
+                None => state.waiters.push((self.id, cx.waker().clone())),
+            }
+        }
+        Poll::Pending
+    }
+}
+
+// Remove the wakers once the transaction is woken or cancelled so that `TVar`s which are not
+// written to don't accumulate them
+impl Drop for Wait {
+    fn drop(&mut self) {
+        for (cell, _) in &self.reads {
+            cell.lock().waiters.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+fn wait(tx: &Transaction) -> impl Future<Output = IO<()>> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let log = tx.log.lock().unwrap();
+    Wait {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        reads: log.reads.clone(),
+        conflict: log.conflict,
+    }
+}
+
+mod std {
+    pub mod stm {
+        pub use crate::stm as prim;
+    }
+}
+
+pub fn load(vm: &Thread) -> Result<ExternModule> {
+    let _ = vm.register_type::<TVar<A>>("std.stm.TVar", &["a"]);
+    let _ = vm.register_type::<Transaction>("std.stm.Transaction", &[]);
+    ExternModule::new(
+        vm,
+        record! {
+            type TVar a => TVar<A>,
+            type Transaction => Transaction,
+            new_tvar => primitive!(1, std::stm::prim::new_tvar),
+            begin => primitive!(1, std::stm::prim::begin),
+            read => primitive!(2, std::stm::prim::read),
+            write => primitive!(3, std::stm::prim::write),
+            checkpoint => primitive!(1, std::stm::prim::checkpoint),
+            rollback => primitive!(2, std::stm::prim::rollback),
+            commit => primitive!(1, std::stm::prim::commit),
+            wait => primitive!(1, async fn std::stm::prim::wait),
+        },
+    )
+}
@@ -39,7 +39,7 @@ use crate::{
     gc::{self, CloneUnrooted, DataDef, Gc, GcPtr, GcRef, Generation, Move},
     interner::InternedStr,
     macros::MacroEnv,
-    source_map::LocalIter,
+    source_map::{Local, LocalIter},
     stack::{
         ClosureState, ExternCallState, ExternState, Frame, Lock, Stack, StackFrame, StackState,
         State,
@@ -225,6 +225,9 @@ impl<T> RootedValue<T>
 where
     T: VmRootInternal,
 {
+    /// Copies the value into `vm` and roots it there. As a gluon thread only runs one call at a
+    /// time, a value which is called concurrently from several Rust threads should be re-rooted
+    /// into a separate thread (created with `Thread::new_thread`) for each of them.
     pub fn re_root<'vm, U>(&self, vm: U) -> Result<RootedValue<U>>
     where
         U: VmRoot<'vm>,
@@ -307,7 +310,9 @@ where
     unsafe fn root_(&mut self) {
         self.vm.root_vm();
         let mut rooted_values = self.vm.rooted_values.write().unwrap();
-        assert!(self.rooted);
+        // Only values which were unrooted, by being moved into the gc inside userdata, are rooted
+        // again, such as when `gc::mutex::Mutex` is locked
+        assert!(!self.rooted, "Value is already rooted");
         self.rooted = true;
         rooted_values.push(self.value.clone_unrooted());
     }
@@ -853,7 +858,7 @@ impl Thread {
 
         // Finally check that type of the returned value is correct
         if check_signature(&env, &expected, &actual) {
-            Ok(T::from_value(self, Variants::new(&value)))
+            crate::api::try_from_value(self, Variants::new(&value))
         } else {
             Err(Error::WrongType(expected, actual))
         }
@@ -881,6 +886,20 @@ impl Thread {
     pub fn register_type<T: ?Sized + Any>(&self, name: &str, args: &[&str]) -> Result<ArcType> {
         self.global_env().register_type::<T>(name, args)
     }
+
+    /// Registers `serialize` and `deserialize` as the functions used to serialize userdata of
+    /// type `T`
+    #[cfg(feature = "serde")]
+    pub fn register_userdata_hooks<T, S, D>(&self, name: &str, serialize: S, deserialize: D)
+    where
+        T: Userdata,
+        S: Fn(&T) -> Vec<u8> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> StdResult<T, crate::real_std::string::String> + Send + Sync + 'static,
+    {
+        self.global_env()
+            .register_userdata_hooks(name, serialize, deserialize)
+    }
+
     pub fn register_type_as(
         &self,
         name: Symbol,
@@ -944,10 +963,20 @@ impl Thread {
         self.owned_context().gc.allocated_memory()
     }
 
+    /// See `Gc::total_allocated_memory`
+    pub fn total_allocated_memory(&self) -> usize {
+        self.owned_context().gc.total_allocated_memory()
+    }
+
     pub fn set_memory_limit(&self, memory_limit: usize) {
         self.owned_context().gc.set_memory_limit(memory_limit)
     }
 
+    /// See `Gc::set_collect_limit`
+    pub fn set_collect_limit(&self, collect_limit: usize) {
+        self.owned_context().gc.set_collect_limit(collect_limit)
+    }
+
     pub fn interrupt(&self) {
         self.interrupt.store(true, atomic::Ordering::Relaxed)
     }
@@ -1439,6 +1468,12 @@ impl<'a> StackInfo<'a> {
         }
     }
 
+    /// Returns the value of `local`, which should be one of the locals returned by `locals`
+    pub fn local_value(&self, local: &Local) -> Option<Variants<'a>> {
+        let stack: &'a Stack = self.info.stack;
+        stack.get_variant(self.frame().offset + local.index)
+    }
+
     /// Returns a slice with information about the values bound to this closure
     pub fn upvars(&self) -> &[UpvarInfo] {
         match self.frame().state {
@@ -1455,6 +1490,8 @@ bitflags::bitflags! {
         const LINE_FLAG = 0b01;
         /// Call the hook when a function is called
         const CALL_FLAG = 0b10;
+        /// Call the hook before each bytecode instruction is executed
+        const INSTRUCTION_FLAG = 0b100;
     }
 }
 
@@ -2064,6 +2101,23 @@ where
         Ok(value)
     }
 
+    /// Replaces the `elems` topmost values of the stack with an array containing them. The values
+    /// must all have the same type.
+    pub fn push_new_array(&mut self, elems: usize) -> Result<Variants> {
+        let value = {
+            let elems = &self.stack[self.stack.len() - elems as VmIndex..];
+            Variants::from(alloc(
+                &mut self.gc,
+                self.thread,
+                &self.stack.stack(),
+                crate::value::ArrayDef(elems),
+            )?)
+        };
+        self.stack.pop_many(elems as u32);
+        self.stack.push(value.clone());
+        Ok(value)
+    }
+
     pub fn push_new_alloc<D>(&mut self, def: D) -> Result<Variants>
     where
         D: DataDef + Trace,
@@ -2100,8 +2154,13 @@ impl<'b, 'gc> ExecuteContext<'b, 'gc> {
 
             debug_instruction(&self.stack, instruction_index, instr);
 
-            if !self.hook.flags.is_empty() && self.hook.flags.contains(HookFlags::LINE_FLAG) {
-                ready!(self.run_hook(&function, instruction_index))?;
+            if !self.hook.flags.is_empty() {
+                if self.hook.flags.contains(HookFlags::LINE_FLAG) {
+                    ready!(self.run_hook(&function, instruction_index))?;
+                }
+                if self.hook.flags.contains(HookFlags::INSTRUCTION_FLAG) {
+                    ready!(self.run_instruction_hook(instruction_index))?;
+                }
             }
 
             match instr {
@@ -2468,6 +2527,8 @@ impl<'b, 'gc> ExecuteContext<'b, 'gc> {
                 FloatLT => binop_bool(self.thread, &mut self.stack, |l: f64, r| l < r)?,
                 FloatEQ => binop_bool(self.thread, &mut self.stack, |l: f64, r| l == r)?,
 
+                Hit(probe) => self.thread.global_env().coverage().hit(probe),
+
                 Return => {
                     drop(program_counter);
                     break;
@@ -2534,6 +2595,19 @@ impl<'b, 'gc> ExecuteContext<'b, 'gc> {
         }
         Ok(()).into()
     }
+
+    fn run_instruction_hook(&mut self, index: usize) -> Poll<Result<()>> {
+        if let Some(ref mut hook) = self.hook.function {
+            // Resume at this instruction if the hook returns `Pending`
+            self.stack.frame_mut().state.instruction_index = index;
+            let info = DebugInfo {
+                stack: &self.stack.stack(),
+                state: HookFlags::INSTRUCTION_FLAG,
+            };
+            ready!(hook(self.thread, info))?
+        }
+        Ok(()).into()
+    }
 }
 
 impl<'b, 'gc> ExecuteContext<'b, 'gc, State> {
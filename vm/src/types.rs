@@ -180,6 +180,9 @@ pub enum Instruction {
     FloatLT,
     FloatEQ,
 
+    /// Increments the coverage counter at `index`, see `coverage::Coverage`
+    Hit(VmIndex),
+
     Return,
 }
 
@@ -213,6 +216,7 @@ impl Instruction {
             AddInt | SubtractInt | MultiplyInt | DivideInt | IntLT | IntEQ | AddFloat | AddByte
             | SubtractByte | MultiplyByte | DivideByte | ByteLT | ByteEQ | SubtractFloat
             | MultiplyFloat | DivideFloat | FloatLT | FloatEQ => -1,
+            Hit(_) => 0,
             Return => 0,
         }
     }
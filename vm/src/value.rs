@@ -1,8 +1,10 @@
 use std::{
+    cell::Cell,
     collections::hash_map::Entry,
     fmt, iter,
     marker::PhantomData,
     mem::{self, size_of},
+    ptr,
     result::Result as StdResult,
 };
 
@@ -43,11 +45,24 @@ pub trait Userdata: Downcast + Trace + fmt::Debug + Send + Sync {
         let _ = deep_cloner;
         Err(Error::Message("Userdata cannot be cloned".into()))
     }
+    /// Adds the methods and instances which are exposed to gluon code by modules created with
+    /// `UserdataMethods::build`
+    fn methods(methods: &mut crate::api::UserdataMethods<Self>)
+    where
+        Self: Sized,
+    {
+        let _ = methods;
+    }
 }
 
 impl PartialEq for dyn Userdata {
+    /// Compares the addresses of the data only. The vtables of the same type may differ between
+    /// codegen units so comparing the whole pointers could give different results between builds.
     fn eq(&self, other: &dyn Userdata) -> bool {
-        self as *const _ == other as *const _
+        ptr::eq(
+            self as *const dyn Userdata as *const u8,
+            other as *const dyn Userdata as *const u8,
+        )
     }
 }
 
@@ -430,12 +445,10 @@ pub(crate) enum ValueRepr {
         #[cfg_attr(feature = "serde_derive", serde(serialize_state))]
         GcPtr<PartialApplicationData>,
     ),
-    // TODO Implement serializing of userdata
-    #[cfg_attr(feature = "serde_derive", serde(skip_deserializing))]
     Userdata(
         #[cfg_attr(
             feature = "serde_derive",
-            serde(serialize_with = "crate::serialization::serialize_userdata")
+            serde(state_with = "crate::serialization::userdata")
         )]
         GcPtr<Box<dyn Userdata>>,
     ),
@@ -1007,23 +1020,52 @@ impl fmt::Debug for Value {
 }
 impl fmt::Debug for ValueRepr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        struct Level<'b>(i32, Variants<'b>);
-        struct LevelSlice<I>(i32, I);
+        let show_addresses = !HIDE_ADDRESSES.with(|hide| hide.get());
+        fmt::Debug::fmt(&DebugValue::new(self, show_addresses), f)
+    }
+}
+
+thread_local! {
+    /// Set while a `DebugValue` which omits addresses is formatted so that the values inside
+    /// userdata, which are formatted through their own `Debug` implementations, omit them as well
+    static HIDE_ADDRESSES: Cell<bool> = Cell::new(false);
+}
+
+/// Formats a value the same way as its `Debug` implementation but can omit the addresses of
+/// closures so that the output is the same every time a program is run
+pub(crate) struct DebugValue<'a> {
+    value: &'a ValueRepr,
+    show_addresses: bool,
+}
+
+impl<'a> DebugValue<'a> {
+    pub(crate) fn new(value: &'a ValueRepr, show_addresses: bool) -> Self {
+        DebugValue {
+            value,
+            show_addresses,
+        }
+    }
+}
+
+impl fmt::Debug for DebugValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Level<'b>(i32, bool, Variants<'b>);
+        struct LevelSlice<I>(i32, bool, I);
 
         impl<'b, I> fmt::Debug for LevelSlice<I>
         where
             I: Iterator<Item = Variants<'b>> + Clone,
         {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                let level = self.0;
-                let mut iter = self.1.clone();
+                let (level, show_addresses) = (self.0, self.1);
+                let mut iter = self.2.clone();
                 let first = iter.next();
                 if level <= 0 || first.is_none() {
                     return Ok(());
                 }
-                write!(f, "{:?}", Level(level - 1, first.unwrap()))?;
+                write!(f, "{:?}", Level(level - 1, show_addresses, first.unwrap()))?;
                 for v in iter {
-                    write!(f, ", {:?}", Level(level - 1, v))?;
+                    write!(f, ", {:?}", Level(level - 1, show_addresses, v))?;
                 }
                 Ok(())
             }
@@ -1031,11 +1073,11 @@ impl fmt::Debug for ValueRepr {
 
         impl<'b> fmt::Debug for Level<'b> {
             fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-                let level = self.0;
+                let (level, show_addresses) = (self.0, self.1);
                 if level <= 0 {
                     return Ok(());
                 }
-                match &(self.1).0 {
+                match &(self.2).0 {
                     ValueRepr::Byte(i) => write!(f, "{:?}b", i),
                     ValueRepr::Int(i) => write!(f, "{:?}", i),
                     ValueRepr::Float(x) => write!(f, "{:?}f", x),
@@ -1046,13 +1088,13 @@ impl fmt::Debug for ValueRepr {
                             f,
                             "{{{}: {:?}}}",
                             tag,
-                            LevelSlice(level - 1, variant_iter(&data.fields))
+                            LevelSlice(level - 1, show_addresses, variant_iter(&data.fields))
                         ),
                         None => write!(
                             f,
                             "{{{:?}: {:?}}}",
                             data.tag,
-                            LevelSlice(level - 1, variant_iter(&data.fields))
+                            LevelSlice(level - 1, show_addresses, variant_iter(&data.fields))
                         ),
                     },
                     ValueRepr::Array(array) => {
@@ -1063,33 +1105,46 @@ impl fmt::Debug for ValueRepr {
                                 write!(f, ", ")?;
                             }
                             first = false;
-                            write!(f, "{:?}", Level(level - 1, value))?;
+                            write!(f, "{:?}", Level(level - 1, show_addresses, value))?;
                         }
                         write!(f, "]")
                     }
-                    ValueRepr::Function(func) => write!(f, "<EXTERN {:?}>", &**func),
-                    ValueRepr::Closure(closure) => {
+                    // Symbols are only shown by their name as their debug representation
+                    // includes their address
+                    ValueRepr::Function(func) if show_addresses => {
+                        write!(f, "<EXTERN {:?}>", &**func)
+                    }
+                    ValueRepr::Function(func) => write!(f, "<EXTERN {}>", func.id),
+                    ValueRepr::Closure(closure) if show_addresses => {
                         let p: *const _ = &*closure.function;
                         write!(f, "<{:?} {:?}>", closure.function.name, p)
                     }
+                    ValueRepr::Closure(closure) => write!(f, "<{}>", closure.function.name),
                     ValueRepr::PartialApplication(app) => {
                         let name = match &app.function {
                             Callable::Closure(c) => &c.function.name,
                             Callable::Extern(e) => &e.id,
                         };
-                        write!(
-                            f,
-                            "<App {:?}, {:?}>",
-                            name,
-                            LevelSlice(level - 1, variant_iter(&app.args))
-                        )
+                        let args = LevelSlice(level - 1, show_addresses, variant_iter(&app.args));
+                        if show_addresses {
+                            write!(f, "<App {:?}, {:?}>", name, args)
+                        } else {
+                            write!(f, "<App {}, {:?}>", name, args)
+                        }
                     }
                     ValueRepr::Userdata(data) => write!(f, "<Userdata {:?}>", &**data),
                     ValueRepr::Thread(_) => write!(f, "<thread>"),
                 }
             }
         }
-        write!(f, "{:?}", Level(7, self.get_variants()))
+        let hidden = HIDE_ADDRESSES.with(|hide| hide.replace(!self.show_addresses));
+        let result = write!(
+            f,
+            "{:?}",
+            Level(7, self.show_addresses, self.value.get_variants())
+        );
+        HIDE_ADDRESSES.with(|hide| hide.set(hidden));
+        result
     }
 }
 
@@ -1542,6 +1597,12 @@ impl<'t> Cloner<'t> {
         self.gc
     }
 
+    /// Returns true if the cloned value may refer to values of generation `generation` instead
+    /// of cloning them
+    pub fn can_contain_values_from(&self, generation: Generation) -> bool {
+        self.receiver_generation.can_contain_values_from(generation)
+    }
+
     /// Deep clones the entire value doing no sharing
     pub fn force_full_clone(&mut self) -> &mut Self {
         self.receiver_generation = Generation::disjoint();
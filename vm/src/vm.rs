@@ -14,7 +14,7 @@ use crate::base::{
     fnv::FnvMap,
     kind::{ArcKind, Kind, KindEnv},
     metadata::{Metadata, MetadataEnv},
-    symbol::{Name, Symbol, SymbolRef},
+    symbol::{Name, Symbol, SymbolRef, Symbols},
     types::{
         Alias, AliasData, AppVec, ArcType, Generic, NullInterner, PrimitiveEnv, Type, TypeCache,
         TypeEnv, TypeExt,
@@ -26,6 +26,7 @@ use crate::{
     api::{OpaqueValue, ValueRef, IO},
     compiler::{CompiledFunction, CompiledModule, CompilerEnv, Variable},
     core::{interpreter, optimize::OptimizeEnv, CoreExpr},
+    coverage::Coverage,
     gc::{Gc, GcPtr, GcRef, Generation, Move, Trace},
     interner::{InternedStr, Interner},
     lazy::Lazy,
@@ -214,6 +215,11 @@ pub struct GlobalVmState {
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     typeids: RwLock<FnvMap<TypeId, ArcType>>,
 
+    /// The symbols of registered types and of generics are created from this table, so that the
+    /// types are equal in every vm which was built with the same table
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    type_symbols: Arc<Symbols>,
+
     #[cfg_attr(feature = "serde_derive", serde(state))]
     interner: RwLock<Interner>,
 
@@ -245,6 +251,25 @@ pub struct GlobalVmState {
 
     #[cfg_attr(feature = "serde_derive", serde(skip))]
     spawner: Option<Box<dyn futures::task::Spawn + Send + Sync>>,
+
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    deterministic: bool,
+
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    capabilities: Capabilities,
+
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    args: Vec<StdString>,
+
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    output: Option<Output>,
+
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    coverage: Coverage,
+
+    #[cfg(feature = "serde")]
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    userdata_hooks: RwLock<crate::serialization::UserdataHooks>,
 }
 
 unsafe impl Trace for GlobalVmState {
@@ -506,9 +531,87 @@ impl<'a> VmEnvInstance<'a> {
     }
 }
 
+/// The effects which the primitives of the standard library are allowed to perform. A primitive
+/// which needs a capability that has not been granted throws an exception instead of running.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Reading from stdin and writing to stdout and stderr
+    pub io: bool,
+    /// Reading and writing files and directories
+    pub fs: bool,
+    /// Listening for or making network connections
+    pub net: bool,
+    /// Spawning processes
+    pub process: bool,
+    /// Calling functions in C libraries through `std.ffi`. Unlike the other capabilities this is
+    /// not granted by default.
+    pub ffi: bool,
+    /// Reading the system and monotonic clocks through `std.time`
+    pub clock: bool,
+    /// Reading and changing environment variables and the working directory as well as reading
+    /// the command line arguments
+    pub env: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            io: true,
+            fs: true,
+            net: true,
+            process: true,
+            ffi: false,
+            clock: true,
+            env: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// The message of the exception thrown by primitives which need `capability`
+    pub fn not_granted(capability: &str) -> StdString {
+        format!(
+            "The `{}` capability has not been granted to this vm",
+            capability
+        )
+    }
+}
+
+/// Creates the primitive `$func` if `$capabilities` grants `$capability`. Otherwise the primitive
+/// has the same type but throws the exception from `Capabilities::not_granted`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! gated_primitive {
+    ($capabilities: expr, $capability: ident, $arg_count: tt, $name: expr, $func: expr, ($($arg: ty),*) -> $ret: ty) => {
+        if $capabilities.$capability {
+            $crate::primitive!($arg_count, $name, $func)
+        } else {
+            $crate::primitive!($arg_count, $name, |$(_: $arg),*| -> $ret {
+                $crate::api::IO::Exception($crate::vm::Capabilities::not_granted(stringify!($capability)))
+            })
+        }
+    };
+}
+
+/// The stream which a gluon program writes to through `std.io`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives the text which `std.io` writes to stdout and stderr, for embedders which do not have
+/// (or do not want the program to use) the standard streams of the process
+pub type Output = Arc<dyn Fn(OutputStream, &str) + Send + Sync>;
+
 #[derive(Default)]
 pub struct GlobalVmStateBuilder {
     spawner: Option<Box<dyn futures::task::Spawn + Send + Sync>>,
+    deterministic: bool,
+    capabilities: Capabilities,
+    args: Option<Vec<StdString>>,
+    output: Option<Output>,
+    type_symbols: Option<Arc<Symbols>>,
 }
 
 impl GlobalVmStateBuilder {
@@ -521,11 +624,49 @@ impl GlobalVmStateBuilder {
         self
     }
 
+    /// Makes the vm produce the same results every time the same program is run with the same
+    /// inputs. Primitives which depend on the environment, such as random number generators
+    /// which are not seeded, the clock and timeouts, return an error instead.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets which effects the primitives of the standard library are allowed to perform
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Sets the command line arguments of the program, as returned by `std.args.args`. If this is
+    /// not set the arguments of the process, without the name of the executable, are used.
+    pub fn args(mut self, args: Option<Vec<StdString>>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Sends the output of `std.io.print`, `std.io.eprint` and the functions built on them to
+    /// `output` instead of stdout and stderr
+    pub fn output(mut self, output: Option<Output>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Creates the symbols of registered types and generics from `type_symbols` instead of a
+    /// table owned by this vm. Types which are registered under the same name are then equal in
+    /// every vm built with the same table, so that types inferred by one vm can be used by the
+    /// others
+    pub fn type_symbols(mut self, type_symbols: Option<Arc<Symbols>>) -> Self {
+        self.type_symbols = type_symbols;
+        self
+    }
+
     pub fn build(self) -> GlobalVmState {
         let mut vm = GlobalVmState {
             env: Default::default(),
             generics: RwLock::new(FnvMap::default()),
             typeids: RwLock::new(FnvMap::default()),
+            type_symbols: self.type_symbols.unwrap_or_default(),
             interner: RwLock::new(Interner::new()),
             gc: Mutex::new(Gc::new(Generation::default(), usize::MAX)),
             macros: MacroEnv::new(),
@@ -534,6 +675,15 @@ impl GlobalVmStateBuilder {
             debug_level: RwLock::new(DebugLevel::default()),
             thread_reference_count: Default::default(),
             spawner: self.spawner,
+            deterministic: self.deterministic,
+            capabilities: self.capabilities,
+            args: self
+                .args
+                .unwrap_or_else(|| std::env::args().skip(1).collect()),
+            output: self.output,
+            coverage: Coverage::default(),
+            #[cfg(feature = "serde")]
+            userdata_hooks: Default::default(),
         };
         vm.add_types().unwrap();
         vm
@@ -618,11 +768,60 @@ impl GlobalVmState {
         if let Some(g) = generics.get(name) {
             return g.clone();
         }
-        let g: ArcType = Type::generic(Generic::new(Symbol::from(name), Kind::typ()));
+        let g: ArcType = Type::generic(Generic::new(
+            self.type_symbols.simple_symbol(name),
+            Kind::typ(),
+        ));
         generics.insert(name.into(), g.clone());
         g
     }
 
+    /// Registers `serialize` and `deserialize` as the functions used to serialize userdata of
+    /// type `T`
+    #[cfg(feature = "serde")]
+    pub fn register_userdata_hooks<T, S, D>(&self, name: &str, serialize: S, deserialize: D)
+    where
+        T: Userdata,
+        S: Fn(&T) -> Vec<u8> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> StdResult<T, StdString> + Send + Sync + 'static,
+    {
+        self.userdata_hooks
+            .write()
+            .unwrap()
+            .register(name, serialize, deserialize)
+    }
+
+    /// Returns `true` if the vm was built with `GlobalVmStateBuilder::deterministic`
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Returns the effects which the primitives of the standard library are allowed to perform
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Returns the command line arguments of the program, see `GlobalVmStateBuilder::args`
+    pub fn args(&self) -> &[StdString] {
+        &self.args
+    }
+
+    /// Returns where `std.io` writes its output, see `GlobalVmStateBuilder::output`. `None` means
+    /// stdout and stderr.
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+
+    /// Returns the execution counts of the code compiled with coverage instrumentation
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn userdata_hooks(&self) -> crate::serialization::UserdataHooks {
+        self.userdata_hooks.read().unwrap().clone()
+    }
+
     /// Registers a new type called `name`
     pub fn register_type<T: ?Sized + Any>(&self, name: &str, args: &[&str]) -> Result<ArcType> {
         self.register_type_(name, args, TypeId::of::<T>())
@@ -637,7 +836,7 @@ impl GlobalVmState {
                 _ => unreachable!(),
             })
             .collect();
-        let n = Symbol::from(name);
+        let n = self.type_symbols.simple_symbol(name);
         let alias = Alias::from(AliasData::new(n.clone(), args, self.type_cache.opaque()));
         self.register_type_as(n, alias, id)
     }
@@ -664,8 +863,7 @@ impl GlobalVmState {
     #[doc(hidden)]
     pub fn get_cache_alias(&self, name: &str) -> Option<ArcType> {
         let env = self.env.read();
-        env
-            .type_infos
+        env.type_infos
             .id_to_type
             .get(name)
             .map(|alias| alias.clone().into_type())
@@ -0,0 +1,104 @@
+//! The JavaScript API, exported through `wasm-bindgen` as the class `Gluon`.
+//!
+//! ```js
+//! const gluon = new Gluon((stream, text) => console.log(stream, text));
+//! gluon.addModule("greeting", `{ name = "world" }`);
+//! gluon.registerFunction("upper", (s) => s.toUpperCase());
+//! gluon.run(`
+//!     let io = import! std.io
+//!     let { ? } = import! std.io
+//!     let js = import! js
+//!     let { name } = import! greeting
+//!     do name = js.call "upper" name
+//!     io.println ("Hello " ++ name)
+//! `);
+//! ```
+
+use std::sync::Arc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+
+use crate::OutputStream;
+
+/// Lets JavaScript values be captured by the `Send + Sync` callbacks of the vm.
+///
+/// `wasm32-unknown-unknown` runs everything on the thread which created the module, so the
+/// values are never actually shared between threads.
+struct JsFunction(js_sys::Function);
+
+unsafe impl Send for JsFunction {}
+unsafe impl Sync for JsFunction {}
+
+fn error_message(err: JsValue) -> String {
+    match err.dyn_ref::<js_sys::Error>() {
+        Some(err) => String::from(err.message()),
+        None => err.as_string().unwrap_or_else(|| format!("{:?}", err)),
+    }
+}
+
+#[wasm_bindgen]
+pub struct Gluon(crate::Gluon);
+
+#[wasm_bindgen]
+impl Gluon {
+    /// Creates a vm which calls `output(stream, text)` with what `std.io` prints, where `stream`
+    /// is either `"stdout"` or `"stderr"`
+    #[wasm_bindgen(constructor)]
+    pub fn new(output: js_sys::Function) -> Gluon {
+        let output = JsFunction(output);
+        Gluon(crate::Gluon::new(Arc::new(move |stream, text| {
+            let stream = match stream {
+                OutputStream::Stdout => "stdout",
+                OutputStream::Stderr => "stderr",
+            };
+            // The program can not do anything about a failing output callback
+            let _ = output.0.call2(
+                &JsValue::NULL,
+                &JsValue::from_str(stream),
+                &JsValue::from_str(text),
+            );
+        })))
+    }
+
+    /// Adds a module which can be imported as `import! name`
+    #[wasm_bindgen(js_name = addModule)]
+    pub fn add_module(&self, name: &str, source: &str) {
+        self.0.add_module(name, source)
+    }
+
+    /// Makes `function` callable from gluon as `js.call name argument`. The argument is passed as
+    /// a string and the result is converted to a string, using `JSON.stringify` if it is not
+    /// already one. A thrown exception is rethrown in gluon.
+    #[wasm_bindgen(js_name = registerFunction)]
+    pub fn register_function(&self, name: &str, function: js_sys::Function) {
+        let function = JsFunction(function);
+        self.0.register_function(
+            name,
+            Arc::new(move |argument| {
+                let result = function
+                    .0
+                    .call1(&JsValue::NULL, &JsValue::from_str(argument))
+                    .map_err(error_message)?;
+                match result.as_string() {
+                    Some(result) => Ok(result),
+                    None => js_sys::JSON::stringify(&result)
+                        .map(String::from)
+                        .map_err(error_message),
+                }
+            }),
+        );
+    }
+
+    /// Compiles and runs `source`, returning the resulting value as it would be shown in the
+    /// repl. Throws the compilation or runtime error as a string if it fails.
+    pub fn run(&self, source: &str) -> Result<String, JsValue> {
+        self.0.run(source).map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// Returns the type of `source`, or throws the error as a string if it does not typecheck
+    pub fn typecheck(&self, source: &str) -> Result<String, JsValue> {
+        self.0
+            .typecheck(source)
+            .map_err(|err| JsValue::from_str(&err))
+    }
+}
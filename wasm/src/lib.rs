@@ -0,0 +1,224 @@
+//! Runs gluon inside a JavaScript host, such as a browser, by compiling it to
+//! `wasm32-unknown-unknown`.
+//!
+//! A vm created through this crate never touches the filesystem, the network, processes or the
+//! environment of the host. Instead the host provides everything the program may use:
+//!
+//! * Modules added with `Gluon::add_module` (`addModule` in JavaScript) can be imported by name.
+//!   They are looked up after the standard library, which is compiled into the binary.
+//! * Functions registered with `Gluon::register_function` (`registerFunction`) are called from
+//!   gluon through the `js` module, `js.call "name" argument`.
+//! * What `std.io` prints is passed to the `Output` the vm was created with.
+//!
+//! The JavaScript API in the `js` module is only compiled for `wasm32`, the rest of the crate is
+//! available on every target so that embedders can share its behaviour with other hosts.
+#![doc(html_root_url = "https://docs.rs/gluon_wasm/0.17.1")] // # GLUON
+
+#[macro_use]
+extern crate gluon_codegen;
+#[macro_use]
+extern crate gluon_vm;
+
+#[cfg(target_arch = "wasm32")]
+pub mod js;
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use gluon::{
+    base::filename_to_module,
+    import::{self, add_extern_module, Import, ImportResolver},
+    vm::{
+        self,
+        api::{Hole, OpaqueValue, IO},
+        internal::ValuePrinter,
+        vm::Output,
+        ExternModule,
+    },
+    RootedThread, Thread, ThreadExt, VmBuilder,
+};
+
+pub use gluon::vm::vm::OutputStream;
+
+/// A function provided by the host. It takes and returns strings so that structured values are
+/// passed as JSON or another format which both sides agree on. An `Err` is thrown as an exception
+/// in the `IO` action which called it.
+pub type HostFunction = Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+/// The source of the `js` module, which wraps the primitives in `js.prim`
+const JS_MODULE: &str = include_str!("js.glu");
+
+/// Module sources added by the host, served to `import!` in place of files
+#[derive(Clone, Default)]
+pub struct VirtualModules(Arc<RwLock<HashMap<String, String>>>);
+
+impl VirtualModules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds (or replaces) the module `name`
+    pub fn insert(&self, name: &str, source: &str) {
+        self.0
+            .write()
+            .unwrap()
+            .insert(name.to_string(), source.to_string());
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.0.write().unwrap().remove(name).is_some()
+    }
+}
+
+impl ImportResolver for VirtualModules {
+    fn resolve(
+        &self,
+        module: &str,
+        _filename: &str,
+    ) -> Result<Option<Cow<'static, str>>, import::Error> {
+        Ok(self.0.read().unwrap().get(module).cloned().map(Cow::Owned))
+    }
+
+    fn modules(&self) -> Vec<String> {
+        let mut modules: Vec<_> = self.0.read().unwrap().keys().cloned().collect();
+        modules.sort();
+        modules
+    }
+}
+
+/// The functions which `js.call` can call
+#[derive(Clone, Default, Userdata, Trace, VmType)]
+#[gluon(vm_type = "js.Functions")]
+#[gluon_trace(skip)]
+#[gluon_userdata(clone)]
+struct Functions(Arc<RwLock<HashMap<String, HostFunction>>>);
+
+impl std::fmt::Debug for Functions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_set()
+            .entries(self.0.read().unwrap().keys())
+            .finish()
+    }
+}
+
+fn call(functions: &Functions, name: &str, argument: &str) -> IO<String> {
+    // The lock must not be held while calling the function as it may register other functions
+    let function = functions.0.read().unwrap().get(name).cloned();
+    match function {
+        Some(function) => match function(argument) {
+            Ok(result) => IO::Value(result),
+            Err(err) => IO::Exception(err),
+        },
+        None => IO::Exception(format!(
+            "No function named `{}` has been registered with the host",
+            name
+        )),
+    }
+}
+
+fn load_prim(vm: &Thread, functions: Functions) -> vm::Result<ExternModule> {
+    vm.register_type::<Functions>("js.Functions", &[])?;
+    ExternModule::new(
+        vm,
+        record! {
+            type Functions => Functions,
+            functions => functions,
+            call => primitive!(3, call),
+        },
+    )
+}
+
+/// A gluon vm which only has access to what its host gives it
+pub struct Gluon {
+    vm: RootedThread,
+    modules: VirtualModules,
+    functions: Functions,
+}
+
+impl Gluon {
+    /// Creates a vm which sends what `std.io` prints to `output`
+    pub fn new(output: Output) -> Self {
+        let vm = VmBuilder::new()
+            .import_paths(Some(Vec::new()))
+            .args(Some(Vec::new()))
+            .output(Some(output))
+            .allow_fs(false)
+            .allow_net(false)
+            .allow_process(false)
+            .allow_ffi(false)
+            // `std::time` panics on `wasm32-unknown-unknown`
+            .allow_clock(false)
+            .allow_env(false)
+            .build();
+        vm.get_database_mut().run_io(true);
+
+        let modules = VirtualModules::new();
+        modules.insert("js", JS_MODULE);
+        vm.get_macros()
+            .get("import")
+            .as_ref()
+            .and_then(|import| import.downcast_ref::<Import>())
+            .expect("Import macro")
+            .add_resolver(modules.clone());
+
+        let functions = Functions::default();
+        {
+            let functions = functions.clone();
+            add_extern_module(&vm, "js.prim", move |vm| load_prim(vm, functions.clone()));
+        }
+
+        Gluon {
+            vm,
+            modules,
+            functions,
+        }
+    }
+
+    pub fn vm(&self) -> &Thread {
+        &self.vm
+    }
+
+    /// Adds a module which can be imported as `import! name`. A module is compiled the first
+    /// time it is imported so replacing it afterwards does not affect the modules which already
+    /// imported it.
+    pub fn add_module(&self, name: &str, source: &str) {
+        self.modules.insert(&filename_to_module(name), source);
+    }
+
+    /// Makes `function` callable from gluon as `js.call name`
+    pub fn register_function(&self, name: &str, function: HostFunction) {
+        self.functions
+            .0
+            .write()
+            .unwrap()
+            .insert(name.to_string(), function);
+    }
+
+    /// Compiles and runs `source`, running the resulting value if it is an `IO` action. Returns the
+    /// value formatted as it would be shown in the repl.
+    pub fn run(&self, source: &str) -> Result<String, String> {
+        let vm = &self.vm;
+        let (value, typ) = vm
+            .run_expr::<OpaqueValue<RootedThread, Hole>>("main", source)
+            .map_err(|err| err.to_string())?;
+
+        let env = vm.get_env();
+        let debug_level = vm.global_env().get_debug_level();
+        Ok(
+            ValuePrinter::new(&env, &typ, value.get_variant(), &debug_level)
+                .width(80)
+                .to_string(),
+        )
+    }
+
+    /// Typechecks `source` without running it, returning its type
+    pub fn typecheck(&self, source: &str) -> Result<String, String> {
+        self.vm
+            .typecheck_str("main", source, None)
+            .map(|(_, typ)| typ.to_string())
+            .map_err(|err| err.to_string())
+    }
+}
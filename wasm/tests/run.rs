@@ -0,0 +1,82 @@
+use std::sync::{Arc, Mutex};
+
+use gluon_wasm::{Gluon, OutputStream};
+
+fn new_gluon() -> (Gluon, Arc<Mutex<Vec<(OutputStream, String)>>>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let gluon = {
+        let output = output.clone();
+        Gluon::new(Arc::new(move |stream, text| {
+            output.lock().unwrap().push((stream, text.to_string()))
+        }))
+    };
+    (gluon, output)
+}
+
+#[test]
+fn run_with_virtual_modules_and_host_functions() {
+    let (gluon, output) = new_gluon();
+    gluon.add_module("greeting", r#"{ name = "world" }"#);
+    gluon.register_function("upper", Arc::new(|s| Ok(s.to_uppercase())));
+
+    gluon
+        .run(
+            r#"
+let io = import! std.io
+let { ? } = import! std.io
+let js = import! js
+let { name } = import! greeting
+do name = js.call "upper" name
+seq io.eprint "!"
+io.println ("Hello " ++ name)
+"#,
+        )
+        .unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(
+        *output.lock().unwrap(),
+        [
+            (OutputStream::Stderr, "!".to_string()),
+            (OutputStream::Stdout, "Hello WORLD\n".to_string())
+        ]
+    );
+
+    assert_eq!(gluon.run("1 + 2").as_deref(), Ok("3"));
+    assert_eq!(
+        gluon.typecheck("import! greeting").as_deref(),
+        Ok("{ name : String }")
+    );
+}
+
+#[test]
+fn host_errors() {
+    let (gluon, _) = new_gluon();
+    gluon.register_function("fail", Arc::new(|s| Err(format!("failed with {}", s))));
+
+    let err = gluon.run(r#"(import! js).call "fail" "x""#).unwrap_err();
+    assert!(err.contains("failed with x"), "{}", err);
+
+    let err = gluon.run(r#"(import! js).call "missing" "x""#).unwrap_err();
+    assert!(
+        err.contains("No function named `missing` has been registered with the host"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn no_access_to_the_host_system() {
+    let (gluon, _) = new_gluon();
+
+    // Modules are never loaded from the filesystem
+    let err = gluon.run("import! src.lib").unwrap_err();
+    assert!(err.contains("Could not find module 'src.lib'"), "{}", err);
+
+    let err = gluon
+        .run(r#"(import! std.io).read_file_to_string "Cargo.toml""#)
+        .unwrap_err();
+    assert!(
+        err.contains("The `fs` capability has not been granted to this vm"),
+        "{}",
+        err
+    );
+}